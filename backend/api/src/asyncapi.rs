@@ -0,0 +1,223 @@
+use axum::response::Json;
+use serde_json::{json, Value};
+use utoipa::OpenApi;
+
+use crate::openapi::ApiDoc;
+
+/// Serves an AsyncAPI 2.6 description of the `/ws` protocol. Payload schemas
+/// that are also REST models (orders, feed messages) are taken from the
+/// OpenAPI components so the two documents cannot drift apart.
+pub async fn asyncapi_handler() -> Json<Value> {
+    Json(asyncapi_document())
+}
+
+pub fn asyncapi_document() -> Value {
+    let mut schemas = ApiDoc::openapi()
+        .components
+        .and_then(|components| serde_json::to_value(components.schemas).ok())
+        .unwrap_or_else(|| json!({}));
+
+    if let Some(schemas) = schemas.as_object_mut() {
+        for (name, schema) in ws_schemas() {
+            schemas.insert(name.to_string(), schema);
+        }
+    }
+
+    json!({
+        "asyncapi": "2.6.0",
+        "info": {
+            "title": "CryptoTrade Exchange WebSocket API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Order entry and sequenced market data feeds over a single authenticated WebSocket. \
+                Send a bearer token on the upgrade request. Client messages are JSON text frames tagged by `op`; \
+                server messages are tagged by `type`. The server pings periodically and closes connections that stay \
+                silent past the idle timeout. Feed channels are `trades:<pair id>`, `book:<pair id>`, \
+                `ticker:<pair id>` and `candles_<interval>:<pair id>` for intervals 1m, 5m, 15m, 1h, 4h and 1d, whose \
+                last update for each bucket has `closed: true`; every update carries a per-channel `sequence`, and a jump means messages were \
+                missed and should be recovered with `resync`. To keep a local book, subscribe to `book:<pair id>`, \
+                fetch `GET /api/v1/order-book/<pair id>/snapshot`, then drop buffered updates whose `engine_sequence` \
+                is at or below the snapshot's `last_update_id` and apply the rest. Server messages are JSON unless \
+                `subscribe` switched the connection to CBOR or MessagePack (`application/cbor`, \
+                `application/msgpack`), optionally deflated."
+        },
+        "servers": {
+            "api": {
+                "url": "localhost:8080",
+                "protocol": "ws",
+                "security": [{ "bearer_auth": [] }]
+            }
+        },
+        "channels": {
+            "/ws": {
+                "publish": {
+                    "summary": "Messages sent by the client",
+                    "message": {
+                        "oneOf": [
+                            message_ref("Subscribe"),
+                            message_ref("Unsubscribe"),
+                            message_ref("Resync"),
+                            message_ref("PlaceOrder"),
+                            message_ref("CancelOrder")
+                        ]
+                    }
+                },
+                "subscribe": {
+                    "summary": "Messages sent by the server",
+                    "message": {
+                        "oneOf": [
+                            message_ref("Subscribed"),
+                            message_ref("Unsubscribed"),
+                            message_ref("Update"),
+                            message_ref("ResyncResult"),
+                            message_ref("Ack"),
+                            message_ref("Reject")
+                        ]
+                    }
+                }
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "bearer_auth": { "type": "httpBearerToken", "scheme": "bearer", "bearerFormat": "JWT" }
+            },
+            "messages": {
+                "Subscribe": message("Subscribe to feed channels and optionally switch the frame encoding", "WsSubscribe"),
+                "Unsubscribe": message("Stop receiving updates for channels", "WsUnsubscribe"),
+                "Resync": message("Replay a channel from a sequence after a gap", "WsResync"),
+                "PlaceOrder": message("Place an order", "WsPlaceOrder"),
+                "CancelOrder": message("Cancel an order", "WsCancelOrder"),
+                "Subscribed": message("Subscription confirmed", "WsSubscribed"),
+                "Unsubscribed": message("Unsubscription confirmed", "WsUnsubscribed"),
+                "Update": message("A sequenced feed message", "WsUpdate"),
+                "ResyncResult": message("Buffered messages from the requested sequence on", "WsResyncResult"),
+                "Ack": message("An order request succeeded", "WsAck"),
+                "Reject": message("A request failed", "WsReject")
+            },
+            "schemas": schemas
+        }
+    })
+}
+
+fn message_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/messages/{}", name) })
+}
+
+fn message(summary: &str, schema: &str) -> Value {
+    json!({
+        "summary": summary,
+        "contentType": "application/json",
+        "payload": { "$ref": format!("#/components/schemas/{}", schema) }
+    })
+}
+
+fn tagged(tag: &str, value: &str, properties: Value, required: &[&str]) -> Value {
+    let mut properties = properties;
+    properties[tag] = json!({ "type": "string", "const": value });
+
+    let mut required: Vec<&str> = required.to_vec();
+    required.insert(0, tag);
+
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+fn ws_schemas() -> Vec<(&'static str, Value)> {
+    let channels = json!({ "type": "array", "items": { "type": "string" } });
+    let request_id = json!({ "type": "string", "description": "Client-chosen id echoed on the ack or reject" });
+
+    vec![
+        (
+            "WsSubscribe",
+            tagged(
+                "op",
+                "subscribe",
+                json!({
+                    "channels": channels,
+                    "encoding": { "type": "string", "enum": ["json", "cbor", "msgpack"], "description": "Frame encoding from now on, starting with the ack; CBOR and MessagePack are sent as binary frames" },
+                    "compress": { "type": "boolean", "description": "Deflate every frame on one stream per connection and send it as binary. Each frame is a permessage-deflate (RFC 7692) payload with context takeover: inflate the frames in order with one raw inflater, appending `00 00 ff ff` to each. Turning compression on starts a new stream with the ack" }
+                }),
+                &["channels"],
+            ),
+        ),
+        ("WsUnsubscribe", tagged("op", "unsubscribe", json!({ "channels": channels }), &["channels"])),
+        (
+            "WsResync",
+            tagged(
+                "op",
+                "resync",
+                json!({
+                    "channel": { "type": "string" },
+                    "from_sequence": { "type": "integer", "format": "int64", "description": "First sequence to replay, inclusive" }
+                }),
+                &["channel", "from_sequence"],
+            ),
+        ),
+        (
+            "WsPlaceOrder",
+            tagged(
+                "op",
+                "place_order",
+                json!({ "request_id": request_id, "order": { "$ref": "#/components/schemas/CreateOrderRequest" } }),
+                &["request_id", "order"],
+            ),
+        ),
+        (
+            "WsCancelOrder",
+            tagged(
+                "op",
+                "cancel_order",
+                json!({ "request_id": request_id, "order_id": { "type": "string", "format": "uuid" } }),
+                &["request_id", "order_id"],
+            ),
+        ),
+        ("WsSubscribed", tagged("type", "subscribed", json!({ "channels": channels }), &["channels"])),
+        ("WsUnsubscribed", tagged("type", "unsubscribed", json!({ "channels": channels }), &["channels"])),
+        (
+            "WsUpdate",
+            json!({
+                "allOf": [
+                    { "$ref": "#/components/schemas/StreamMessage" },
+                    tagged("type", "update", json!({}), &[])
+                ],
+                "description": "`data` is a PublicTrade (or a PublicTradeBust when a trade is reversed), BookUpdate, TickerUpdate or CandleUpdate depending on the channel"
+            }),
+        ),
+        (
+            "WsResyncResult",
+            tagged(
+                "type",
+                "resync",
+                json!({
+                    "channel": { "type": "string" },
+                    "messages": { "type": "array", "items": { "$ref": "#/components/schemas/StreamMessage" } }
+                }),
+                &["channel", "messages"],
+            ),
+        ),
+        (
+            "WsAck",
+            tagged(
+                "type",
+                "ack",
+                json!({ "request_id": request_id, "order": { "$ref": "#/components/schemas/Order" } }),
+                &["request_id", "order"],
+            ),
+        ),
+        (
+            "WsReject",
+            tagged(
+                "type",
+                "reject",
+                json!({
+                    "request_id": { "type": ["string", "null"] },
+                    "code": {
+                        "type": "string",
+                        "description": "An `ErrorCode`, or one of the protocol codes INVALID_MESSAGE, INVALID_CHANNEL, \
+                            SUBSCRIPTION_LIMIT, RATE_LIMITED, RESYNC_UNAVAILABLE, INVALID_USER_ID"
+                    },
+                    "message": { "type": "string" }
+                }),
+                &["code", "message"],
+            ),
+        ),
+    ]
+}
@@ -0,0 +1,544 @@
+//! GraphQL over the same services as the REST API, for clients that want
+//! several resources in one round trip and only the fields they render:
+//! the caller's portfolio, orders with their fills and pair, trades, and
+//! market data. Queries only; orders are still placed over REST.
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, ErrorExtensions, Object, Request, Response, Schema,
+};
+use axum::{response::Json, Extension};
+use chrono::{DateTime, Utc};
+use cryptotrade_core::{i18n::current_locale, models, Claims, CryptoTradeError, ErrorResponse, GraphqlConfig, Result};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::handlers::parse_user_id;
+use crate::AppState;
+
+pub type ExchangeSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// The schema over `state`, with the configured depth and complexity limits.
+pub fn schema(state: AppState, config: &GraphqlConfig) -> ExchangeSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(state)
+        .limit_depth(config.max_depth)
+        .limit_complexity(config.max_complexity)
+        .finish()
+}
+
+/// The user a query runs as.
+struct Viewer(Uuid);
+
+/// Runs a GraphQL query as the caller. Errors come back in the response's
+/// `errors` with the REST error code under `extensions.code`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/graphql",
+    tag = "Trading",
+    request_body(content = serde_json::Value, description = "A GraphQL request: `query`, and optionally `variables` and `operationName`"),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "A GraphQL response with `data` and any `errors`", body = serde_json::Value),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn graphql_handler(
+    Extension(claims): Extension<Claims>,
+    Extension(schema): Extension<ExchangeSchema>,
+    Json(request): Json<Request>,
+) -> Result<Json<Response>> {
+    let user_id = parse_user_id(&claims)?;
+
+    Ok(Json(schema.execute(request.data(Viewer(user_id))).await))
+}
+
+fn gql_error(e: CryptoTradeError) -> async_graphql::Error {
+    async_graphql::Error::new(e.localized_message(current_locale())).extend_with(|_, extensions| {
+        extensions.set("code", e.error_code());
+    })
+}
+
+fn services<'c>(ctx: &Context<'c>) -> (&'c AppState, Uuid) {
+    let state = ctx.data_unchecked::<AppState>();
+    let Viewer(user_id) = ctx.data_unchecked::<Viewer>();
+    (state, *user_id)
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The caller's balances and their value.
+    async fn portfolio(&self, ctx: &Context<'_>) -> async_graphql::Result<Portfolio> {
+        let (state, user_id) = services(ctx);
+        state.portfolio_service.get_portfolio(user_id).await.map(Portfolio).map_err(gql_error)
+    }
+
+    /// The caller's orders, newest first.
+    async fn orders(
+        &self,
+        ctx: &Context<'_>,
+        status: Option<OrderStatus>,
+        #[graphql(default = 100)] limit: i64,
+    ) -> async_graphql::Result<Vec<Order>> {
+        let (state, user_id) = services(ctx);
+        let orders = state
+            .order_service
+            .get_user_orders(user_id, status.map(Into::into), Some(limit))
+            .await
+            .map_err(gql_error)?;
+        Ok(orders.into_iter().map(Order).collect())
+    }
+
+    async fn order(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Order> {
+        let (state, user_id) = services(ctx);
+        state.order_service.get_user_order(user_id, id).await.map(Order).map_err(gql_error)
+    }
+
+    /// The caller's trades, newest first.
+    async fn trades(&self, ctx: &Context<'_>, #[graphql(default = 100)] limit: i64) -> async_graphql::Result<Vec<Trade>> {
+        let (state, user_id) = services(ctx);
+        let trades = state.trading_service.get_user_trades(user_id, Some(limit)).await.map_err(gql_error)?;
+        Ok(trades.into_iter().map(Trade).collect())
+    }
+
+    /// 24h statistics of every active pair, or of the pairs given.
+    async fn market_data(&self, ctx: &Context<'_>, pair_ids: Option<Vec<Uuid>>) -> async_graphql::Result<Vec<MarketData>> {
+        let (state, user_id) = services(ctx);
+        let tenant_id = state.tenant_service.user_tenant_id(user_id).await.map_err(gql_error)?;
+        let market_data = match pair_ids {
+            Some(pair_ids) => state.market_data_service.get_market_data_for(&pair_ids, tenant_id).await.map_err(gql_error)?,
+            None => state.market_data_service.get_all_market_data(tenant_id).await.map_err(gql_error)?,
+        };
+        Ok(market_data.into_iter().map(MarketData).collect())
+    }
+
+    async fn trading_pair(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<TradingPair> {
+        let (state, user_id) = services(ctx);
+        let tenant_id = state.tenant_service.user_tenant_id(user_id).await.map_err(gql_error)?;
+        state.tenant_service.ensure_pair_visible_to(id, tenant_id).await.map_err(gql_error)?;
+        state.order_service.get_trading_pair(id).await.map(TradingPair).map_err(gql_error)
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "models::OrderType")]
+pub enum OrderType {
+    Market,
+    Limit,
+    StopLoss,
+    TakeProfit,
+    StopLossLimit,
+    TakeProfitLimit,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "models::OrderSide")]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "models::OrderStatus")]
+pub enum OrderStatus {
+    Scheduled,
+    Pending,
+    Open,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected,
+    Expired,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "models::TimeInForce")]
+pub enum TimeInForce {
+    #[graphql(name = "GTC")]
+    GTC,
+    #[graphql(name = "IOC")]
+    IOC,
+    #[graphql(name = "FOK")]
+    FOK,
+    #[graphql(name = "GTD")]
+    GTD,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "models::Liquidity")]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "models::TradingPairStatus")]
+pub enum TradingPairStatus {
+    Announced,
+    CallAuction,
+    Trading,
+    Halt,
+    Delisting,
+    Delisted,
+}
+
+pub struct Order(models::Order);
+
+#[Object]
+impl Order {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn order_type(&self) -> Option<OrderType> {
+        self.0.order_type.map(Into::into)
+    }
+
+    async fn side(&self) -> Option<OrderSide> {
+        self.0.side.map(Into::into)
+    }
+
+    async fn status(&self) -> Option<OrderStatus> {
+        self.0.status.map(Into::into)
+    }
+
+    async fn time_in_force(&self) -> Option<TimeInForce> {
+        self.0.time_in_force.map(Into::into)
+    }
+
+    async fn quantity(&self) -> Option<Decimal> {
+        self.0.quantity
+    }
+
+    async fn price(&self) -> Option<Decimal> {
+        self.0.price
+    }
+
+    async fn stop_price(&self) -> Option<Decimal> {
+        self.0.stop_price
+    }
+
+    async fn filled_quantity(&self) -> Option<Decimal> {
+        self.0.filled_quantity
+    }
+
+    async fn remaining_quantity(&self) -> Option<Decimal> {
+        self.0.remaining_quantity
+    }
+
+    async fn average_fill_price(&self) -> Option<Decimal> {
+        self.0.average_fill_price
+    }
+
+    async fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.0.updated_at
+    }
+
+    async fn pair(&self, ctx: &Context<'_>) -> async_graphql::Result<TradingPair> {
+        let (state, _) = services(ctx);
+        state
+            .order_service
+            .get_trading_pair(self.0.trading_pair_id)
+            .await
+            .map(TradingPair)
+            .map_err(gql_error)
+    }
+
+    /// Trades that filled the order, oldest first.
+    async fn fills(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Fill>> {
+        let (state, user_id) = services(ctx);
+        let fills = state.order_service.get_order_fills(user_id, self.0.id).await.map_err(gql_error)?;
+        Ok(fills
+            .into_iter()
+            .map(|fill| Fill {
+                fill,
+                trading_pair_id: self.0.trading_pair_id,
+            })
+            .collect())
+    }
+}
+
+pub struct Fill {
+    fill: models::Fill,
+    trading_pair_id: Uuid,
+}
+
+#[Object]
+impl Fill {
+    async fn trade_id(&self) -> Uuid {
+        self.fill.trade_id
+    }
+
+    async fn price(&self) -> Decimal {
+        self.fill.price
+    }
+
+    async fn quantity(&self) -> Decimal {
+        self.fill.quantity
+    }
+
+    async fn quote_quantity(&self) -> Decimal {
+        self.fill.quote_quantity
+    }
+
+    async fn fee(&self) -> Decimal {
+        self.fill.fee
+    }
+
+    async fn fee_currency(&self) -> &str {
+        &self.fill.fee_currency
+    }
+
+    async fn liquidity(&self) -> Liquidity {
+        self.fill.liquidity.into()
+    }
+
+    async fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.fill.created_at
+    }
+
+    async fn pair(&self, ctx: &Context<'_>) -> async_graphql::Result<TradingPair> {
+        let (state, _) = services(ctx);
+        state
+            .order_service
+            .get_trading_pair(self.trading_pair_id)
+            .await
+            .map(TradingPair)
+            .map_err(gql_error)
+    }
+}
+
+pub struct Trade(models::Trade);
+
+#[Object]
+impl Trade {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn price(&self) -> Option<Decimal> {
+        self.0.price
+    }
+
+    async fn quantity(&self) -> Option<Decimal> {
+        self.0.quantity
+    }
+
+    /// The caller's side of the trade
+    async fn side(&self, ctx: &Context<'_>) -> OrderSide {
+        let (_, user_id) = services(ctx);
+        if self.0.buyer_user_id == user_id {
+            OrderSide::Buy
+        } else {
+            OrderSide::Sell
+        }
+    }
+
+    /// The fee the caller paid
+    async fn fee(&self, ctx: &Context<'_>) -> Option<Decimal> {
+        let (_, user_id) = services(ctx);
+        if self.0.buyer_user_id == user_id {
+            self.0.buyer_fee
+        } else {
+            self.0.seller_fee
+        }
+    }
+
+    async fn taker_side(&self) -> OrderSide {
+        self.0.taker_side.into()
+    }
+
+    async fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.0.created_at
+    }
+
+    async fn busted_at(&self) -> Option<DateTime<Utc>> {
+        self.0.busted_at
+    }
+
+    async fn pair(&self, ctx: &Context<'_>) -> async_graphql::Result<TradingPair> {
+        let (state, _) = services(ctx);
+        state
+            .order_service
+            .get_trading_pair(self.0.trading_pair_id)
+            .await
+            .map(TradingPair)
+            .map_err(gql_error)
+    }
+}
+
+pub struct TradingPair(models::TradingPair);
+
+#[Object]
+impl TradingPair {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn symbol(&self) -> &str {
+        &self.0.symbol
+    }
+
+    async fn base_currency(&self) -> &str {
+        &self.0.base_currency
+    }
+
+    async fn quote_currency(&self) -> &str {
+        &self.0.quote_currency
+    }
+
+    async fn status(&self) -> TradingPairStatus {
+        self.0.status.into()
+    }
+
+    async fn min_order_size(&self) -> Option<Decimal> {
+        self.0.min_order_size
+    }
+
+    async fn max_order_size(&self) -> Option<Decimal> {
+        self.0.max_order_size
+    }
+
+    async fn price_precision(&self) -> Option<i32> {
+        self.0.price_precision
+    }
+
+    async fn quantity_precision(&self) -> Option<i32> {
+        self.0.quantity_precision
+    }
+
+    async fn maker_fee(&self) -> Option<Decimal> {
+        self.0.maker_fee
+    }
+
+    async fn taker_fee(&self) -> Option<Decimal> {
+        self.0.taker_fee
+    }
+
+    async fn market_data(&self, ctx: &Context<'_>) -> async_graphql::Result<MarketData> {
+        let (state, user_id) = services(ctx);
+        let tenant_id = state.tenant_service.user_tenant_id(user_id).await.map_err(gql_error)?;
+        state.market_data_service.get_market_data(self.0.id, tenant_id).await.map(MarketData).map_err(gql_error)
+    }
+}
+
+pub struct MarketData(models::MarketData);
+
+#[Object]
+impl MarketData {
+    async fn trading_pair_id(&self) -> Uuid {
+        self.0.trading_pair_id
+    }
+
+    async fn symbol(&self) -> &str {
+        &self.0.symbol
+    }
+
+    async fn last_price(&self) -> Decimal {
+        self.0.last_price
+    }
+
+    #[graphql(name = "volume24h")]
+    async fn volume_24h(&self) -> Decimal {
+        self.0.volume_24h
+    }
+
+    #[graphql(name = "high24h")]
+    async fn high_24h(&self) -> Decimal {
+        self.0.high_24h
+    }
+
+    #[graphql(name = "low24h")]
+    async fn low_24h(&self) -> Decimal {
+        self.0.low_24h
+    }
+
+    #[graphql(name = "priceChange24h")]
+    async fn price_change_24h(&self) -> Decimal {
+        self.0.price_change_24h
+    }
+
+    #[graphql(name = "priceChangePercent24h")]
+    async fn price_change_percent_24h(&self) -> Decimal {
+        self.0.price_change_percent_24h
+    }
+
+    async fn bid_price(&self) -> Option<Decimal> {
+        self.0.bid_price
+    }
+
+    async fn ask_price(&self) -> Option<Decimal> {
+        self.0.ask_price
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.0.updated_at
+    }
+}
+
+pub struct Portfolio(models::Portfolio);
+
+#[Object]
+impl Portfolio {
+    async fn total_value_usd(&self) -> Decimal {
+        self.0.total_value_usd
+    }
+
+    async fn accounts(&self) -> Vec<AccountBalance<'_>> {
+        self.0.accounts.iter().map(AccountBalance).collect()
+    }
+
+    #[graphql(name = "pnl24h")]
+    async fn pnl_24h(&self) -> Decimal {
+        self.0.performance_24h.pnl_24h
+    }
+
+    #[graphql(name = "pnlPercentage24h")]
+    async fn pnl_percentage_24h(&self) -> Decimal {
+        self.0.performance_24h.pnl_percentage_24h
+    }
+
+    async fn open_orders_count(&self) -> i64 {
+        self.0.open_orders_count
+    }
+
+    async fn total_trades(&self) -> i64 {
+        self.0.total_trades
+    }
+}
+
+pub struct AccountBalance<'a>(&'a models::AccountBalance);
+
+#[Object]
+impl AccountBalance<'_> {
+    async fn currency(&self) -> &str {
+        &self.0.currency
+    }
+
+    async fn balance(&self) -> Decimal {
+        self.0.balance
+    }
+
+    async fn available_balance(&self) -> Decimal {
+        self.0.available_balance
+    }
+
+    async fn locked_balance(&self) -> Decimal {
+        self.0.locked_balance
+    }
+
+    async fn usd_value(&self) -> Decimal {
+        self.0.usd_value
+    }
+
+    async fn percentage(&self) -> Decimal {
+        self.0.percentage
+    }
+}
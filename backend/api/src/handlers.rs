@@ -1,22 +1,20 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    extract::{multipart::MultipartError, ConnectInfo, Multipart, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
     Extension,
 };
 use cryptotrade_core::{Claims, *};
 use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
 use uuid::Uuid;
-use chrono;
 
 // Import AppState from the parent module (main.rs)
 use super::AppState;
 
-#[derive(Serialize, Deserialize, utoipa::ToSchema)]
-pub struct ErrorResponse {
-    pub error: String,
-    pub code: String,
-}
+/// Names the white-label broker a public request comes through
+const TENANT_HEADER: &str = "x-tenant";
 
 // Auth handlers
 #[utoipa::path(
@@ -26,17 +24,29 @@ pub struct ErrorResponse {
     request_body = RegisterRequest,
     responses(
         (status = 200, description = "User registered successfully", body = AuthResponse),
-        (status = 400, description = "Registration failed", body = ErrorResponse)
+        (status = 400, description = "Registration failed", body = ErrorResponse),
+        (status = 403, description = "A solved CAPTCHA is required", body = ErrorResponse),
+        (status = 451, description = "Registration from or residence in a restricted country", body = ErrorResponse)
     )
 )]
 pub async fn register_handler(
     State(state): State<AppState>,
-    Json(payload): Json<RegisterRequest>,
-) -> std::result::Result<Json<AuthResponse>, (StatusCode, Json<ErrorResponse>)> {
-    match state.user_service.register(payload).await {
-        Ok(response) => Ok(Json(response)),
-        Err(e) => Err(handle_error(e)),
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(mut payload): Json<RegisterRequest>,
+) -> Result<Json<AuthResponse>> {
+    let ip = request_ip(&state, &headers, peer);
+    state.captcha_service.check_registration(payload.captcha_token.as_deref(), ip).await?;
+    state.compliance_service.check_registration(&headers, &mut payload)?;
+    let device = request_device(&state, &headers, ip);
+    let mut response = state.user_service.register(payload, &device).await?;
+    if !state.compliance_service.screen_user(response.user.id).await?.is_empty() {
+        response.user.account_status = AccountStatus::Restricted;
+    }
+    if let Some(sandbox) = &state.sandbox_service {
+        sandbox.fund_user(response.user.id).await?;
     }
+    Ok(Json(response))
 }
 
 #[utoipa::path(
@@ -46,17 +56,109 @@ pub async fn register_handler(
     request_body = LoginRequest,
     responses(
         (status = 200, description = "User logged in successfully", body = AuthResponse),
-        (status = 401, description = "Invalid credentials", body = ErrorResponse)
+        (status = 401, description = "Invalid credentials", body = ErrorResponse),
+        (status = 403, description = "New device or unusual location, to be confirmed through the emailed link; or a solved CAPTCHA is required after repeated failures", body = ErrorResponse),
+        (status = 451, description = "Login from a restricted country", body = ErrorResponse)
     )
 )]
 pub async fn login_handler(
     State(state): State<AppState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
-) -> std::result::Result<Json<AuthResponse>, (StatusCode, Json<ErrorResponse>)> {
-    match state.user_service.login(payload).await {
-        Ok(response) => Ok(Json(response)),
-        Err(e) => Err(handle_error(e)),
-    }
+) -> Result<Json<AuthResponse>> {
+    state.compliance_service.check_login(&headers)?;
+    let ip = request_ip(&state, &headers, peer);
+    state
+        .captcha_service
+        .check_login(&payload.email, payload.captcha_token.as_deref(), ip)
+        .await?;
+    let device = request_device(&state, &headers, ip);
+    state.user_service.login(payload, &device).await.map(Json)
+}
+
+/// The device a login or registration comes from, and where.
+fn request_device(state: &AppState, headers: &HeaderMap, ip: Option<IpAddr>) -> DeviceInfo {
+    DeviceInfo::from_headers(headers, ip).located(
+        state.compliance_service.request_country(headers),
+        state.login_risk_service.coordinates(headers),
+    )
+}
+
+/// The address a login or registration comes from.
+fn request_ip(state: &AppState, headers: &HeaderMap, peer: Option<ConnectInfo<SocketAddr>>) -> Option<IpAddr> {
+    crate::middleware::caller_ip(
+        headers,
+        peer.map(|ConnectInfo(addr)| addr),
+        state.server_config.trust_forwarded_for,
+    )
+}
+
+/// Confirms a new device with the token from the emailed link; logging in
+/// from it again then issues tokens.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/devices/confirm",
+    tag = "Authentication",
+    request_body = ConfirmDeviceRequest,
+    responses(
+        (status = 200, description = "The confirmed device", body = UserDevice),
+        (status = 400, description = "Invalid, used or expired link", body = ErrorResponse)
+    )
+)]
+pub async fn confirm_device_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<ConfirmDeviceRequest>,
+) -> Result<Json<UserDevice>> {
+    state.device_service.confirm(&payload.token).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/sessions",
+    tag = "Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Devices the user has logged in from, most recent first", body = Vec<UserDevice>),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_sessions_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<UserDevice>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.device_service.list_devices(user_id).await.map(Json)
+}
+
+/// Forgets a device: its next login must be confirmed again.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/user/sessions/{device_id}",
+    tag = "Authentication",
+    params(
+        ("device_id" = Uuid, Path, description = "Device ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The revoked device", body = UserDevice),
+        (status = 404, description = "Device not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn revoke_session_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(device_id): Path<Uuid>,
+) -> Result<Json<UserDevice>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.device_service.revoke_device(user_id, device_id).await.map(Json)
 }
 
 #[utoipa::path(
@@ -72,34 +174,32 @@ pub async fn login_handler(
 pub async fn refresh_token_handler(
     State(state): State<AppState>,
     Json(payload): Json<RefreshTokenRequest>,
-) -> std::result::Result<Json<TokenResponse>, (StatusCode, Json<ErrorResponse>)> {
-    match state.auth_service.verify_refresh_token(&payload.refresh_token) {
-        Ok(token_data) => {
-            match token_data.claims.sub.parse::<Uuid>() {
-                Ok(user_id) => {
-                    match state.user_service.get_user_by_id(user_id).await {
-                        Ok(user) => {
-                            match state.auth_service.generate_jwt(&user) {
-                                Ok(new_access_token) => {
-                                    Ok(Json(TokenResponse {
-                                        access_token: new_access_token,
-                                        expires_in: 3600,
-                                    }))
-                                }
-                                Err(e) => Err(handle_error(e))
-                            }
-                        }
-                        Err(e) => Err(handle_error(e))
-                    }
-                }
-                Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
-                    error: "Invalid user ID in token".to_string(),
-                    code: "INVALID_TOKEN".to_string(),
-                })))
-            }
-        }
-        Err(e) => Err(handle_error(e)),
-    }
+) -> Result<Json<TokenResponse>> {
+    let token_data = state.auth_service.verify_refresh_token(&payload.refresh_token)?;
+    let user_id = token_data.claims.sub.parse::<Uuid>()
+        .map_err(|_| CryptoTradeError::InvalidToken)?;
+
+    let user = state.user_service.get_user_by_id(user_id).await?;
+    let new_access_token = state.auth_service.generate_jwt(&user)?;
+
+    Ok(Json(TokenResponse {
+        access_token: new_access_token,
+        expires_in: 3600,
+    }))
+}
+
+/// Public keys of the asymmetric token signing keys in use, so other
+/// services can verify access tokens without the shared secret.
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/keys",
+    tag = "Authentication",
+    responses(
+        (status = 200, description = "Token verification keys", body = Vec<JwtPublicKey>)
+    )
+)]
+pub async fn jwt_keys_handler(State(state): State<AppState>) -> Json<Vec<JwtPublicKey>> {
+    Json(state.auth_service.public_keys())
 }
 
 // User handlers
@@ -118,489 +218,4636 @@ pub async fn refresh_token_handler(
 pub async fn get_user_profile_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-) -> std::result::Result<Json<UserProfile>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
-
-    match state.user_service.get_user_by_id(user_id).await {
-        Ok(user) => {
-            let profile = UserProfile {
-                id: user.id,
-                email: user.email,
-                username: user.username,
-                first_name: user.first_name,
-                last_name: user.last_name,
-                is_verified: user.is_verified.unwrap_or(false),
-                two_fa_enabled: user.two_fa_enabled.unwrap_or(false),
-                kyc_status: user.kyc_status.unwrap_or(KycStatus::Pending),
-            };
-            Ok(Json(profile))
-        }
-        Err(e) => Err(handle_error(e)),
-    }
+) -> Result<Json<UserProfile>> {
+    let user_id = parse_user_id(&claims)?;
+
+    let user = state.user_service.get_user_by_id(user_id).await?;
+
+    Ok(Json(state.user_service.profile(user)))
 }
 
 #[utoipa::path(
     get,
-    path = "/api/v1/user/accounts",
+    path = "/api/v1/user/watchlist",
     tag = "User Management",
     security(
         ("bearer_auth" = [])
     ),
     responses(
-        (status = 200, description = "User accounts retrieved", body = [Account]),
+        (status = 200, description = "Watched pairs in the user's order", body = [WatchlistEntry]),
         (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn get_user_accounts_handler(
+pub async fn get_watchlist_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-) -> std::result::Result<Json<Vec<Account>>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
+) -> Result<Json<Vec<WatchlistEntry>>> {
+    let user_id = parse_user_id(&claims)?;
 
-    match state.user_service.get_user_accounts(user_id).await {
-        Ok(accounts) => Ok(Json(accounts)),
-        Err(e) => Err(handle_error(e)),
-    }
+    state.watchlist_service.get_watchlist(user_id).await.map(Json)
 }
 
-// 2FA handlers
+/// Adds a pair to the end of the watchlist.
 #[utoipa::path(
     post,
-    path = "/api/v1/user/2fa/enable",
-    tag = "Two-Factor Authentication",
+    path = "/api/v1/user/watchlist",
+    tag = "User Management",
+    request_body = AddWatchlistEntryRequest,
     security(
         ("bearer_auth" = [])
     ),
     responses(
-        (status = 200, description = "2FA enabled successfully", body = TwoFactorResponse),
-        (status = 401, description = "Unauthorized", body = ErrorResponse)
+        (status = 200, description = "The watchlist after the addition", body = [WatchlistEntry]),
+        (status = 400, description = "Watchlist full", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse)
     )
 )]
-pub async fn enable_2fa_handler(
+pub async fn add_watchlist_entry_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-) -> std::result::Result<Json<TwoFactorResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
+    Json(payload): Json<AddWatchlistEntryRequest>,
+) -> Result<Json<Vec<WatchlistEntry>>> {
+    let user_id = parse_user_id(&claims)?;
 
-    match state.user_service.enable_2fa(user_id).await {
-        Ok(response) => {
-            Ok(Json(response))
-        }
-        Err(e) => Err(handle_error(e)),
-    }
+    state.watchlist_service.add_entry(user_id, payload).await.map(Json)
 }
 
+/// Replaces the watchlist, which is how its pairs are reordered.
 #[utoipa::path(
-    post,
-    path = "/api/v1/user/2fa/confirm",
-    tag = "Two-Factor Authentication",
+    put,
+    path = "/api/v1/user/watchlist",
+    tag = "User Management",
+    request_body = SetWatchlistRequest,
     security(
         ("bearer_auth" = [])
     ),
-    request_body = ConfirmTwoFactorRequest,
     responses(
-        (status = 200, description = "2FA confirmed successfully", body = SuccessResponse),
-        (status = 400, description = "Invalid 2FA code", body = ErrorResponse),
-        (status = 401, description = "Unauthorized", body = ErrorResponse)
+        (status = 200, description = "The watchlist as set", body = [WatchlistEntry]),
+        (status = 400, description = "Duplicate pairs or too many pairs", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse)
     )
 )]
-pub async fn confirm_2fa_handler(
+pub async fn set_watchlist_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Json(payload): Json<ConfirmTwoFactorRequest>,
-) -> std::result::Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
-
-    match state.user_service.confirm_2fa(user_id, payload).await {
-        Ok(response) => Ok(Json(response)),
-        Err(e) => Err(handle_error(e)),
-    }
+    Json(payload): Json<SetWatchlistRequest>,
+) -> Result<Json<Vec<WatchlistEntry>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.watchlist_service.set_watchlist(user_id, payload).await.map(Json)
 }
 
 #[utoipa::path(
-    post,
-    path = "/api/v1/user/2fa/disable",
-    tag = "Two-Factor Authentication",
+    delete,
+    path = "/api/v1/user/watchlist/{pair_id}",
+    tag = "User Management",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    ),
     security(
         ("bearer_auth" = [])
     ),
     responses(
-        (status = 200, description = "2FA disabled successfully", body = SuccessResponse),
-        (status = 401, description = "Unauthorized", body = ErrorResponse)
+        (status = 200, description = "The watchlist after the removal", body = [WatchlistEntry]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Pair not on the watchlist", body = ErrorResponse)
     )
 )]
-pub async fn disable_2fa_handler(
+pub async fn remove_watchlist_entry_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-) -> std::result::Result<Json<SuccessResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
+    Path(pair_id): Path<Uuid>,
+) -> Result<Json<Vec<WatchlistEntry>>> {
+    let user_id = parse_user_id(&claims)?;
 
-    match state.user_service.disable_2fa(user_id).await {
-        Ok(response) => Ok(Json(response)),
-        Err(e) => Err(handle_error(e)),
-    }
+    state.watchlist_service.remove_entry(user_id, pair_id).await.map(Json)
 }
 
-// Order handlers
+/// Sets the language error messages are returned in. The profile setting
+/// wins over `Accept-Language`; clearing it follows the header again.
 #[utoipa::path(
-    post,
-    path = "/api/v1/orders",
-    tag = "Trading",
+    put,
+    path = "/api/v1/user/locale",
+    tag = "User Management",
+    request_body = UpdateLocaleRequest,
     security(
         ("bearer_auth" = [])
     ),
-    request_body = CreateOrderRequest,
     responses(
-        (status = 200, description = "Order created successfully", body = Order),
-        (status = 400, description = "Invalid order request", body = ErrorResponse),
+        (status = 200, description = "Locale updated", body = UserProfile),
+        (status = 400, description = "Unsupported locale", body = ErrorResponse),
         (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn create_order_handler(
+pub async fn update_locale_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Json(payload): Json<CreateOrderRequest>,
-) -> std::result::Result<Json<Order>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
-
-    match state.order_service.create_order(user_id, payload).await {
-        Ok(order) => Ok(Json(order)),
-        Err(e) => Err(handle_error(e)),
-    }
+    Json(request): Json<UpdateLocaleRequest>,
+) -> Result<Json<UserProfile>> {
+    let user_id = parse_user_id(&claims)?;
+
+    let user = state.user_service.set_locale(user_id, request.locale).await?;
+
+    Ok(Json(state.user_service.profile(user)))
 }
 
+/// Closes the caller's account for good. It must hold no funds and have no
+/// open orders; afterwards the history stays readable but nothing else is
+/// allowed, and API keys are revoked.
 #[utoipa::path(
-    get,
-    path = "/api/v1/orders",
-    tag = "Trading",
+    post,
+    path = "/api/v1/user/close",
+    tag = "User Management",
+    request_body = CloseAccountRequest,
     security(
         ("bearer_auth" = [])
     ),
-    params(
-        ("status" = Option<String>, Query, description = "Filter by order status"),
-        ("limit" = Option<i64>, Query, description = "Limit number of results")
-    ),
     responses(
-        (status = 200, description = "Orders retrieved successfully", body = [Order]),
-        (status = 401, description = "Unauthorized", body = ErrorResponse)
+        (status = 200, description = "Account closed", body = AccountStatusChange),
+        (status = 400, description = "Account still holds funds or open orders, or is already closed", body = ErrorResponse),
+        (status = 401, description = "Wrong password", body = ErrorResponse)
     )
 )]
-pub async fn get_user_orders_handler(
+pub async fn close_account_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Query(params): Query<OrdersQuery>,
-) -> std::result::Result<Json<Vec<Order>>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
-
-    match state.order_service.get_user_orders(user_id, params.status, params.limit).await {
-        Ok(orders) => Ok(Json(orders)),
-        Err(e) => Err(handle_error(e)),
-    }
+    Json(payload): Json<CloseAccountRequest>,
+) -> Result<Json<AccountStatusChange>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.user_service.close_account(user_id, payload).await.map(Json)
 }
 
 #[utoipa::path(
-    delete,
-    path = "/api/v1/orders/{order_id}",
-    tag = "Trading",
+    get,
+    path = "/api/v1/user/accounts",
+    tag = "User Management",
     security(
         ("bearer_auth" = [])
     ),
-    params(
-        ("order_id" = Uuid, Path, description = "Order ID to cancel")
-    ),
     responses(
-        (status = 200, description = "Order cancelled successfully", body = Order),
-        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 200, description = "User accounts retrieved", body = [Account]),
         (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn cancel_order_handler(
+pub async fn get_user_accounts_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Path(order_id): Path<Uuid>,
-) -> std::result::Result<Json<Order>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
-
-    match state.order_service.cancel_order(user_id, order_id).await {
-        Ok(order) => Ok(Json(order)),
-        Err(e) => Err(handle_error(e)),
-    }
+) -> Result<Json<Vec<Account>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.user_service.get_user_accounts(user_id).await.map(Json)
 }
 
-// Portfolio handlers
+/// Converts the caller's dust, balances too small to trade, into one
+/// currency at oracle prices, less a fee.
 #[utoipa::path(
-    get,
-    path = "/api/v1/portfolio",
-    tag = "Portfolio",
+    post,
+    path = "/api/v1/user/dust-convert",
+    tag = "User Management",
+    request_body = DustConvertRequest,
     security(
         ("bearer_auth" = [])
     ),
     responses(
-        (status = 200, description = "Portfolio retrieved successfully", body = Portfolio),
-        (status = 401, description = "Unauthorized", body = ErrorResponse)
+        (status = 200, description = "The balances converted and the amount credited", body = DustConversion),
+        (status = 400, description = "No balance qualifies as convertible dust", body = ErrorResponse),
+        (status = 403, description = "Account may not trade", body = ErrorResponse)
     )
 )]
-pub async fn get_portfolio_handler(
+pub async fn dust_convert_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-) -> std::result::Result<Json<Portfolio>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
+    Json(payload): Json<DustConvertRequest>,
+) -> Result<Json<DustConversion>> {
+    let user_id = parse_user_id(&claims)?;
 
-    match state.portfolio_service.get_portfolio(user_id).await {
-        Ok(portfolio) => Ok(Json(portfolio)),
-        Err(e) => Err(handle_error(e)),
-    }
+    state.dust_service.convert(user_id, payload).await.map(Json)
 }
 
 #[utoipa::path(
     get,
-    path = "/api/v1/portfolio/history",
-    tag = "Portfolio",
+    path = "/api/v1/user/accounts/{currency}/holds",
+    tag = "User Management",
     security(
         ("bearer_auth" = [])
     ),
     params(
-        ("days" = Option<i32>, Query, description = "Number of days to retrieve history for (default: 30)")
+        ("currency" = String, Path, description = "Account currency, e.g. BTC")
     ),
     responses(
-        (status = 200, description = "Portfolio history retrieved successfully", body = [PortfolioSnapshot]),
+        (status = 200, description = "Open orders and pending withdrawals holding the locked balance", body = AccountHolds),
+        (status = 404, description = "No account in this currency", body = ErrorResponse),
         (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn get_portfolio_history_handler(
+pub async fn get_account_holds_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Query(params): Query<HistoryQuery>,
-) -> std::result::Result<Json<Vec<PortfolioSnapshot>>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
-
-    match state.portfolio_service.get_portfolio_history(user_id, params.days.unwrap_or(30)).await {
-        Ok(history) => Ok(Json(history)),
-        Err(e) => Err(handle_error(e)),
-    }
+    Path(currency): Path<String>,
+) -> Result<Json<AccountHolds>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.portfolio_service.get_account_holds(user_id, &currency.to_uppercase()).await.map(Json)
 }
 
-// Trading handlers
 #[utoipa::path(
     get,
-    path = "/api/v1/trades",
-    tag = "Trading",
+    path = "/api/v1/user/limits",
+    tag = "User Management",
     security(
         ("bearer_auth" = [])
     ),
-    params(
-        ("limit" = Option<i64>, Query, description = "Limit number of results")
-    ),
     responses(
-        (status = 200, description = "Trades retrieved successfully", body = [Trade]),
+        (status = 200, description = "Open order and notional limits with current usage", body = UserLimits),
         (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn get_user_trades_handler(
+pub async fn get_user_limits_handler(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Query(params): Query<TradesQuery>,
-) -> std::result::Result<Json<Vec<Trade>>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = claims.sub.parse::<Uuid>()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "Invalid user ID".to_string(),
-            code: "INVALID_USER_ID".to_string(),
-        })))?;
-
-    match state.trading_service.get_user_trades(user_id, params.limit).await {
-        Ok(trades) => Ok(Json(trades)),
-        Err(e) => Err(handle_error(e)),
-    }
+) -> Result<Json<UserLimits>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.risk_limit_service.get_user_limits(user_id).await.map(Json)
 }
 
-// Market data handlers
+/// Lists the feature flags on for the caller, so clients can hide features
+/// that are still being rolled out.
 #[utoipa::path(
     get,
-    path = "/api/v1/market-data",
-    tag = "Market Data",
+    path = "/api/v1/user/features",
+    tag = "User",
+    security(
+        ("bearer_auth" = [])
+    ),
     responses(
-        (status = 200, description = "Market data retrieved successfully", body = [MarketData])
+        (status = 200, description = "Keys of the feature flags on for the caller", body = [String]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn get_all_market_data_handler(
+pub async fn get_user_features_handler(
+    Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-) -> std::result::Result<Json<Vec<MarketData>>, (StatusCode, Json<ErrorResponse>)> {
-    match state.market_data_service.get_all_market_data().await {
-        Ok(data) => Ok(Json(data)),
-        Err(e) => Err(handle_error(e)),
-    }
+) -> Result<Json<Vec<String>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    Ok(Json(state.feature_flag_service.enabled_for(user_id)))
 }
 
+// API key handlers
+/// Creates an API key. The secret is in this response only and is never
+/// sent back: requests carry `X-API-Key`, `X-API-Timestamp` (Unix ms),
+/// `X-API-Nonce` and `X-API-Signature`, the hex HMAC-SHA256 with the secret
+/// of timestamp + nonce + method + path and query + body.
 #[utoipa::path(
-    get,
-    path = "/api/v1/market-data/{pair_id}",
-    tag = "Market Data",
-    params(
-        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    post,
+    path = "/api/v1/user/api-keys",
+    tag = "API Keys",
+    request_body = CreateApiKeyRequest,
+    security(
+        ("bearer_auth" = [])
     ),
     responses(
-        (status = 200, description = "Market data retrieved successfully", body = MarketData),
-        (status = 404, description = "Trading pair not found", body = ErrorResponse)
+        (status = 200, description = "The new key and its secret", body = CreatedApiKey),
+        (status = 400, description = "Invalid label or no permissions", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn get_market_data_handler(
+pub async fn create_api_key_handler(
+    Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Path(pair_id): Path<Uuid>,
-) -> std::result::Result<Json<MarketData>, (StatusCode, Json<ErrorResponse>)> {
-    match state.market_data_service.get_market_data(pair_id).await {
-        Ok(data) => Ok(Json(data)),
-        Err(e) => Err(handle_error(e)),
-    }
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreatedApiKey>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.api_key_service.create_key(user_id, payload).await.map(Json)
 }
 
 #[utoipa::path(
     get,
-    path = "/api/v1/order-book/{pair_id}",
-    tag = "Market Data",
-    params(
-        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    path = "/api/v1/user/api-keys",
+    tag = "API Keys",
+    security(
+        ("bearer_auth" = [])
     ),
     responses(
-        (status = 200, description = "Order book retrieved successfully", body = OrderBook),
-        (status = 404, description = "Trading pair not found", body = ErrorResponse)
+        (status = 200, description = "The user's API keys, without secrets", body = Vec<ApiKey>),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn get_order_book_handler(
+pub async fn list_api_keys_handler(
+    Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Path(pair_id): Path<Uuid>,
-) -> std::result::Result<Json<OrderBook>, (StatusCode, Json<ErrorResponse>)> {
-    match state.order_service.get_order_book(pair_id, Some(20)).await {
-        Ok(order_book) => Ok(Json(order_book)),
-        Err(e) => Err(handle_error(e)),
-    }
+) -> Result<Json<Vec<ApiKey>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.api_key_service.list_keys(user_id).await.map(Json)
 }
 
 #[utoipa::path(
     get,
-    path = "/api/v1/trades/{pair_id}",
-    tag = "Market Data",
-    params(
-        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    path = "/api/v1/user/api-usage",
+    tag = "API Keys",
+    security(
+        ("bearer_auth" = [])
     ),
     responses(
-        (status = 200, description = "Recent trades retrieved successfully", body = [Trade]),
-        (status = 404, description = "Trading pair not found", body = ErrorResponse)
+        (status = 200, description = "Market data requests made with each active key against its limits", body = Vec<ApiKeyUsage>),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn get_recent_trades_handler(
+pub async fn get_api_usage_handler(
+    Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Path(pair_id): Path<Uuid>,
-) -> std::result::Result<Json<Vec<Trade>>, (StatusCode, Json<ErrorResponse>)> {
-    match state.trading_service.get_recent_trades(pair_id, Some(100)).await {
-        Ok(trades) => Ok(Json(trades)),
-        Err(e) => Err(handle_error(e)),
-    }
+) -> Result<Json<Vec<ApiKeyUsage>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.rate_limit_service.api_usage(user_id).await.map(Json)
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/v1/candlesticks/{pair_id}",
-    tag = "Market Data",
+    delete,
+    path = "/api/v1/user/api-keys/{key_id}",
+    tag = "API Keys",
     params(
-        ("pair_id" = Uuid, Path, description = "Trading pair ID"),
-        ("interval" = Option<String>, Query, description = "Candlestick interval (e.g., 1m, 5m, 1h, 1d)"),
-        ("start_time" = Option<String>, Query, description = "Start time (ISO 8601)"),
-        ("end_time" = Option<String>, Query, description = "End time (ISO 8601)"),
-        ("limit" = Option<i32>, Query, description = "Limit number of results")
+        ("key_id" = Uuid, Path, description = "API key ID")
+    ),
+    security(
+        ("bearer_auth" = [])
     ),
     responses(
-        (status = 200, description = "Candlestick data retrieved successfully", body = [Candlestick]),
-        (status = 404, description = "Trading pair not found", body = ErrorResponse)
+        (status = 200, description = "The revoked key", body = ApiKey),
+        (status = 404, description = "API key not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
     )
 )]
-pub async fn get_candlestick_data_handler(
+pub async fn revoke_api_key_handler(
+    Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
-    Path(pair_id): Path<Uuid>,
-    Query(params): Query<CandlestickQuery>,
-) -> std::result::Result<Json<Vec<Candlestick>>, (StatusCode, Json<ErrorResponse>)> {
-    match state.market_data_service.get_candlestick_data(
-        pair_id,
-        params.interval.unwrap_or_else(|| "1h".to_string()),
-        params.start_time,
-        params.end_time,
-        params.limit,
-    ).await {
-        Ok(candlesticks) => Ok(Json(candlesticks)),
-        Err(e) => Err(handle_error(e)),
+    Path(key_id): Path<Uuid>,
+) -> Result<Json<ApiKey>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.api_key_service.revoke_key(user_id, key_id).await.map(Json)
+}
+
+/// Replaces the networks a key may be used from. Requires 2FA to be enabled
+/// and a current code.
+#[utoipa::path(
+    put,
+    path = "/api/v1/user/api-keys/{key_id}/ip-allowlist",
+    tag = "API Keys",
+    params(
+        ("key_id" = Uuid, Path, description = "API key ID")
+    ),
+    request_body = UpdateIpAllowlistRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The updated key", body = ApiKey),
+        (status = 400, description = "Malformed address or CIDR block", body = ErrorResponse),
+        (status = 401, description = "Invalid 2FA code", body = ErrorResponse),
+        (status = 403, description = "2FA is not enabled", body = ErrorResponse),
+        (status = 404, description = "API key not found", body = ErrorResponse)
+    )
+)]
+pub async fn update_api_key_ip_allowlist_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(key_id): Path<Uuid>,
+    Json(payload): Json<UpdateIpAllowlistRequest>,
+) -> Result<Json<ApiKey>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.api_key_service.update_ip_allowlist(user_id, key_id, payload).await.map(Json)
+}
+
+// 2FA handlers
+#[utoipa::path(
+    post,
+    path = "/api/v1/user/2fa/enable",
+    tag = "Two-Factor Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "2FA enabled successfully", body = TwoFactorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn enable_2fa_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<TwoFactorResponse>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.user_service.enable_2fa(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/user/2fa/confirm",
+    tag = "Two-Factor Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = ConfirmTwoFactorRequest,
+    responses(
+        (status = 200, description = "2FA confirmed successfully", body = SuccessResponse),
+        (status = 400, description = "Invalid 2FA code", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn confirm_2fa_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<ConfirmTwoFactorRequest>,
+) -> Result<Json<SuccessResponse>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.user_service.confirm_2fa(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/user/2fa/disable",
+    tag = "Two-Factor Authentication",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "2FA disabled successfully", body = SuccessResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn disable_2fa_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<SuccessResponse>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.user_service.disable_2fa(user_id).await.map(Json)
+}
+
+// Order handlers
+#[utoipa::path(
+    post,
+    path = "/api/v1/orders",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = CreateOrderRequest,
+    responses(
+        (status = 200, description = "Order created successfully", body = Order),
+        (status = 400, description = "Invalid order request", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 451, description = "Trading from or residence in a restricted country", body = ErrorResponse)
+    )
+)]
+pub async fn create_order_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateOrderRequest>,
+) -> Result<Json<Order>> {
+    let user_id = parse_user_id(&claims)?;
+    let origin = state.compliance_service.request_country(&headers);
+    state.compliance_service.check_trading(user_id, origin.as_deref()).await?;
+
+    state.order_service.create_order(user_id, payload).await.map(Json)
+}
+
+/// Places the limit orders of a CSV with the columns
+/// `symbol,side,quantity,price` and optionally `time_in_force`, or with
+/// `dry_run` only validates them. Small files are processed before this
+/// returns; larger ones are accepted and processed in the background.
+#[utoipa::path(
+    post,
+    path = "/api/v1/orders/import",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("dry_run" = Option<bool>, Query, description = "Validate the rows without placing them")
+    ),
+    request_body(content = String, content_type = "text/csv", description = "The orders, one per row after a header"),
+    responses(
+        (status = 200, description = "Every row processed", body = OrderImportReport),
+        (status = 202, description = "Rows recorded and queued; poll the import for progress", body = OrderImportReport),
+        (status = 400, description = "Unreadable CSV, missing columns or too many rows", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 413, description = "File larger than the upload limit", body = ErrorResponse),
+        (status = 451, description = "Trading from or residence in a restricted country", body = ErrorResponse)
+    )
+)]
+pub async fn import_orders_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<OrderImportQuery>,
+    body: axum::body::Bytes,
+) -> Result<(StatusCode, Json<OrderImportReport>)> {
+    let user_id = parse_user_id(&claims)?;
+    let dry_run = params.dry_run.unwrap_or(false);
+    if !dry_run {
+        let origin = state.compliance_service.request_country(&headers);
+        state.compliance_service.check_trading(user_id, origin.as_deref()).await?;
     }
+
+    let report = state.order_import_service.upload(user_id, &body, dry_run).await?;
+    let status = match report.import.status {
+        OrderImportStatus::Completed => StatusCode::OK,
+        OrderImportStatus::Pending => StatusCode::ACCEPTED,
+    };
+    Ok((status, Json(report)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/orders/import/{import_id}",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("import_id" = Uuid, Path, description = "Order import ID")
+    ),
+    responses(
+        (status = 200, description = "The import's status and every row's outcome so far", body = OrderImportReport),
+        (status = 404, description = "Order import not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_import_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(import_id): Path<Uuid>,
+) -> Result<Json<OrderImportReport>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.order_import_service.report(user_id, import_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/orders/preview",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("trading_pair_id" = Uuid, Query, description = "Trading pair ID"),
+        ("order_type" = OrderType, Query, description = "Order type"),
+        ("side" = OrderSide, Query, description = "Order side"),
+        ("quantity" = f64, Query, description = "Order quantity"),
+        ("price" = Option<String>, Query, description = "Limit price"),
+        ("time_in_force" = Option<TimeInForce>, Query, description = "Time in force"),
+        ("stop_price" = Option<String>, Query, description = "Stop price")
+    ),
+    responses(
+        (status = 200, description = "Estimated lock and fees for the order", body = OrderPreview),
+        (status = 400, description = "Invalid order", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn preview_order_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<CreateOrderRequest>,
+) -> Result<Json<OrderPreview>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.order_service.preview_order(user_id, params).await.map(Json)
+}
+
+/// Runs an order through the matching engine against the live book without
+/// placing it, returning the fills it would get, their average price and
+/// slippage, and the fee on them.
+#[utoipa::path(
+    post,
+    path = "/api/v1/orders/simulate",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = CreateOrderRequest,
+    responses(
+        (status = 200, description = "Expected fills and fees for the order", body = OrderSimulation),
+        (status = 400, description = "Invalid order", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn simulate_order_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateOrderRequest>,
+) -> Result<Json<OrderSimulation>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.order_service.simulate_order(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/orders",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("status" = Option<String>, Query, description = "Filter by order status"),
+        ("limit" = Option<i64>, Query, description = "Limit number of results")
+    ),
+    responses(
+        (status = 200, description = "Orders retrieved successfully", body = [Order]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_user_orders_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<OrdersQuery>,
+) -> Result<Json<Vec<Order>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.order_service.get_user_orders(user_id, params.status, params.limit).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/orders/{order_id}",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("order_id" = Uuid, Path, description = "Order ID")
+    ),
+    responses(
+        (status = 200, description = "Order retrieved successfully", body = Order),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> Result<Json<Order>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.order_service.get_user_order(user_id, order_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/orders/{order_id}/fills",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("order_id" = Uuid, Path, description = "Order ID")
+    ),
+    responses(
+        (status = 200, description = "Trades that filled the order, oldest first", body = [Fill]),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_fills_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> Result<Json<Vec<Fill>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.order_service.get_order_fills(user_id, order_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/orders/{order_id}/history",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("order_id" = Uuid, Path, description = "Order ID")
+    ),
+    responses(
+        (status = 200, description = "Status transitions of the order, oldest first", body = [OrderEvent]),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_history_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> Result<Json<Vec<OrderEvent>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.order_service.get_order_history(user_id, order_id).await.map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/orders/{order_id}",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("order_id" = Uuid, Path, description = "Order ID to cancel")
+    ),
+    responses(
+        (status = 200, description = "Order cancelled successfully", body = Order),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn cancel_order_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> Result<Json<Order>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.order_service.cancel_order(user_id, order_id).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/orders/cancel-replace",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = CancelReplaceBatchRequest,
+    responses(
+        (status = 200, description = "Per-entry cancel and replace outcomes", body = [CancelReplaceResult]),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 403, description = "Cancel-replace is switched off for the caller", body = ErrorResponse)
+    )
+)]
+pub async fn cancel_replace_orders_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<CancelReplaceBatchRequest>,
+) -> Result<Json<Vec<CancelReplaceResult>>> {
+    let user_id = parse_user_id(&claims)?;
+    let origin = state.compliance_service.request_country(&headers);
+    state.compliance_service.check_trading(user_id, origin.as_deref()).await?;
+    state
+        .feature_flag_service
+        .ensure_enabled(feature_flag_service::CANCEL_REPLACE, user_id)?;
+
+    state.order_service.cancel_replace_batch(user_id, payload).await.map(Json)
+}
+
+// Algo order handlers
+/// Places a TWAP or POV parent order, worked in child order slices by the
+/// algo order service.
+#[utoipa::path(
+    post,
+    path = "/api/v1/algo-orders",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = CreateAlgoOrderRequest,
+    responses(
+        (status = 200, description = "Algo order accepted", body = AlgoOrder),
+        (status = 400, description = "Invalid algo order", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 451, description = "Trading from or residence in a restricted country", body = ErrorResponse)
+    )
+)]
+pub async fn create_algo_order_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateAlgoOrderRequest>,
+) -> Result<Json<AlgoOrder>> {
+    let user_id = parse_user_id(&claims)?;
+    let origin = state.compliance_service.request_country(&headers);
+    state.compliance_service.check_trading(user_id, origin.as_deref()).await?;
+
+    state.algo_order_service.create(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/algo-orders",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's latest algo orders", body = Vec<AlgoOrder>),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_algo_orders_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AlgoOrder>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.algo_order_service.list(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/algo-orders/{algo_order_id}",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("algo_order_id" = Uuid, Path, description = "Algo order ID")
+    ),
+    responses(
+        (status = 200, description = "Progress of the algo order and its child orders", body = AlgoOrderProgress),
+        (status = 404, description = "Algo order not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_algo_order_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(algo_order_id): Path<Uuid>,
+) -> Result<Json<AlgoOrderProgress>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.algo_order_service.progress(user_id, algo_order_id).await.map(Json)
+}
+
+/// Stops the algo order and cancels its resting child orders.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/algo-orders/{algo_order_id}",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("algo_order_id" = Uuid, Path, description = "Algo order ID to cancel")
+    ),
+    responses(
+        (status = 200, description = "Algo order cancelled", body = AlgoOrderProgress),
+        (status = 400, description = "Algo order is no longer active", body = ErrorResponse),
+        (status = 404, description = "Algo order not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn cancel_algo_order_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(algo_order_id): Path<Uuid>,
+) -> Result<Json<AlgoOrderProgress>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.algo_order_service.cancel(user_id, algo_order_id).await.map(Json)
+}
+
+// Grid bot handlers
+/// Creates a grid bot and places its ladder of limit orders around the
+/// last price.
+#[utoipa::path(
+    post,
+    path = "/api/v1/grid-bots",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = CreateGridBotRequest,
+    responses(
+        (status = 200, description = "Grid bot started", body = GridBotReport),
+        (status = 400, description = "Invalid grid or the ladder could not be placed", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 451, description = "Trading from or residence in a restricted country", body = ErrorResponse)
+    )
+)]
+pub async fn create_grid_bot_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateGridBotRequest>,
+) -> Result<Json<GridBotReport>> {
+    let user_id = parse_user_id(&claims)?;
+    let origin = state.compliance_service.request_country(&headers);
+    state.compliance_service.check_trading(user_id, origin.as_deref()).await?;
+
+    state.grid_bot_service.create(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/grid-bots",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's latest grid bots", body = Vec<GridBot>),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_grid_bots_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<GridBot>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.grid_bot_service.list(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/grid-bots/{bot_id}",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("bot_id" = Uuid, Path, description = "Grid bot ID")
+    ),
+    responses(
+        (status = 200, description = "The bot with its open orders and PnL", body = GridBotReport),
+        (status = 404, description = "Grid bot not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_grid_bot_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(bot_id): Path<Uuid>,
+) -> Result<Json<GridBotReport>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.grid_bot_service.report(user_id, bot_id).await.map(Json)
+}
+
+/// Restarts a stopped or failed bot with a new ladder around the last price.
+#[utoipa::path(
+    post,
+    path = "/api/v1/grid-bots/{bot_id}/start",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("bot_id" = Uuid, Path, description = "Grid bot ID")
+    ),
+    responses(
+        (status = 200, description = "Grid bot restarted", body = GridBotReport),
+        (status = 400, description = "Bot already running or the ladder could not be placed", body = ErrorResponse),
+        (status = 404, description = "Grid bot not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 451, description = "Trading from or residence in a restricted country", body = ErrorResponse)
+    )
+)]
+pub async fn start_grid_bot_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(bot_id): Path<Uuid>,
+) -> Result<Json<GridBotReport>> {
+    let user_id = parse_user_id(&claims)?;
+    let origin = state.compliance_service.request_country(&headers);
+    state.compliance_service.check_trading(user_id, origin.as_deref()).await?;
+
+    state.grid_bot_service.start(user_id, bot_id).await.map(Json)
+}
+
+/// Stops the bot and cancels its open orders.
+#[utoipa::path(
+    post,
+    path = "/api/v1/grid-bots/{bot_id}/stop",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("bot_id" = Uuid, Path, description = "Grid bot ID")
+    ),
+    responses(
+        (status = 200, description = "Grid bot stopped", body = GridBotReport),
+        (status = 400, description = "Grid bot is already stopped", body = ErrorResponse),
+        (status = 404, description = "Grid bot not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn stop_grid_bot_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(bot_id): Path<Uuid>,
+) -> Result<Json<GridBotReport>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.grid_bot_service.stop(user_id, bot_id).await.map(Json)
+}
+
+// Alert webhook handlers
+/// Creates an order template for alerts. The secret is returned only here:
+/// alerts carry it as `passphrase` or sign their body with it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/alert-templates",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = CreateAlertTemplateRequest,
+    responses(
+        (status = 200, description = "Template created, with its secret and webhook path", body = CreatedAlertTemplate),
+        (status = 400, description = "Invalid template", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 409, description = "A template with this name already exists", body = ErrorResponse)
+    )
+)]
+pub async fn create_alert_template_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateAlertTemplateRequest>,
+) -> Result<Json<CreatedAlertTemplate>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.alert_service.create_template(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/alert-templates",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's templates in use", body = Vec<AlertTemplate>),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_alert_templates_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AlertTemplate>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.alert_service.list_templates(user_id).await.map(Json)
+}
+
+/// Stops the template taking alerts.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/alert-templates/{template_id}",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("template_id" = Uuid, Path, description = "Alert template ID")
+    ),
+    responses(
+        (status = 200, description = "Template disabled", body = AlertTemplate),
+        (status = 404, description = "Alert template not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn disable_alert_template_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(template_id): Path<Uuid>,
+) -> Result<Json<AlertTemplate>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.alert_service.disable_template(user_id, template_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/alert-templates/{template_id}/executions",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("template_id" = Uuid, Path, description = "Alert template ID")
+    ),
+    responses(
+        (status = 200, description = "The template's latest alerts, with the order each placed or why not", body = Vec<AlertExecution>),
+        (status = 404, description = "Alert template not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_alert_executions_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(template_id): Path<Uuid>,
+) -> Result<Json<Vec<AlertExecution>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.alert_service.executions(user_id, template_id).await.map(Json)
+}
+
+/// Receives a TradingView-style alert for a template and places its order.
+/// The alert carries the template's secret as `passphrase`, or signs the
+/// exact body with it in `X-Alert-Signature`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/alerts/{template_id}",
+    tag = "Trading",
+    params(
+        ("template_id" = Uuid, Path, description = "Alert template ID")
+    ),
+    request_body = TradingViewAlert,
+    responses(
+        (status = 200, description = "Order placed", body = AlertExecution),
+        (status = 400, description = "Alert rejected, see the template's executions", body = ErrorResponse),
+        (status = 401, description = "Passphrase or signature missing or invalid", body = ErrorResponse),
+        (status = 404, description = "Alert template not found", body = ErrorResponse),
+        (status = 409, description = "The same alert body was received in the past day", body = ErrorResponse)
+    )
+)]
+pub async fn alert_webhook_handler(
+    State(state): State<AppState>,
+    Path(template_id): Path<Uuid>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Json<AlertExecution>> {
+    let signature = headers.get(ALERT_SIGNATURE_HEADER).and_then(|value| value.to_str().ok());
+
+    state.alert_service.handle_alert(template_id, &body, signature).await.map(Json)
+}
+
+// Copy trading handlers
+/// Publishes the caller as a lead trader others can copy, or updates the
+/// listing.
+#[utoipa::path(
+    post,
+    path = "/api/v1/copy-trading/leads",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = PublishLeadTraderRequest,
+    responses(
+        (status = 200, description = "Listing published", body = LeadTrader),
+        (status = 400, description = "Invalid name or fee rate", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn publish_lead_trader_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<PublishLeadTraderRequest>,
+) -> Result<Json<LeadTrader>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.copy_trading_service.publish(user_id, payload).await.map(Json)
+}
+
+/// Withdraws the caller's listing; subscriptions to it stop mirroring.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/copy-trading/leads",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Listing withdrawn", body = LeadTrader),
+        (status = 404, description = "Caller is not a published lead trader", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn unpublish_lead_trader_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<LeadTrader>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.copy_trading_service.unpublish(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/copy-trading/leads",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Published lead traders, most followed first", body = Vec<LeadTrader>),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_lead_traders_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LeadTrader>>> {
+    state.copy_trading_service.leads().await.map(Json)
+}
+
+/// Starts copying a lead trader's fills with a budget.
+#[utoipa::path(
+    post,
+    path = "/api/v1/copy-trading/subscriptions",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = CreateCopySubscriptionRequest,
+    responses(
+        (status = 200, description = "Subscription started", body = CopySubscription),
+        (status = 400, description = "Invalid budget or limits, or already following", body = ErrorResponse),
+        (status = 404, description = "Lead trader not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 451, description = "Trading from or residence in a restricted country", body = ErrorResponse)
+    )
+)]
+pub async fn create_copy_subscription_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateCopySubscriptionRequest>,
+) -> Result<Json<CopySubscription>> {
+    let user_id = parse_user_id(&claims)?;
+    let origin = state.compliance_service.request_country(&headers);
+    state.compliance_service.check_trading(user_id, origin.as_deref()).await?;
+
+    state.copy_trading_service.subscribe(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/copy-trading/subscriptions",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's subscriptions with their PnL and fees", body = Vec<CopySubscription>),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_copy_subscriptions_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CopySubscription>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.copy_trading_service.subscriptions(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/copy-trading/subscriptions/{subscription_id}/trades",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("subscription_id" = Uuid, Path, description = "Copy subscription ID")
+    ),
+    responses(
+        (status = 200, description = "Lead fills the subscription mirrored or skipped, newest first", body = Vec<CopyTrade>),
+        (status = 404, description = "Subscription not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_copy_trades_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(subscription_id): Path<Uuid>,
+) -> Result<Json<Vec<CopyTrade>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.copy_trading_service.copy_trades(user_id, subscription_id).await.map(Json)
+}
+
+/// Stops copying; what was bought stays in the caller's balances.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/copy-trading/subscriptions/{subscription_id}",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("subscription_id" = Uuid, Path, description = "Copy subscription ID")
+    ),
+    responses(
+        (status = 200, description = "Subscription closed", body = CopySubscription),
+        (status = 400, description = "Subscription is already closed", body = ErrorResponse),
+        (status = 404, description = "Subscription not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn close_copy_subscription_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(subscription_id): Path<Uuid>,
+) -> Result<Json<CopySubscription>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.copy_trading_service.close(user_id, subscription_id).await.map(Json)
+}
+
+// Payment handlers
+/// Opens a fiat deposit. Card payments are completed with the returned
+/// client secret; bank transfers must quote the reference in the returned
+/// instructions. The balance is credited once the provider reports the
+/// money arrived.
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments",
+    tag = "Payments",
+    request_body = CreatePaymentRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The pending deposit and how to pay it", body = PaymentIntent),
+        (status = 400, description = "Amount out of range", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Payment method not available", body = ErrorResponse),
+        (status = 502, description = "The payment provider refused the payment", body = ErrorResponse)
+    )
+)]
+pub async fn create_payment_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<CreatePaymentRequest>,
+) -> Result<Json<PaymentIntent>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.payment_service.create_payment(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments",
+    tag = "Payments",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's fiat deposits, newest first", body = [Payment]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_payments_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Payment>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.payment_service.list_payments(user_id).await.map(Json)
+}
+
+/// Receives a payment provider's notification. The body must be exactly as
+/// signed by the provider.
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/webhooks/{method}",
+    tag = "Payments",
+    params(
+        ("method" = PaymentMethod, Path, description = "Provider sending the notification")
+    ),
+    request_body(content = Object, description = "The provider's notification"),
+    responses(
+        (status = 204, description = "Notification applied"),
+        (status = 401, description = "Signature missing, invalid or expired", body = ErrorResponse),
+        (status = 404, description = "Payment method not available", body = ErrorResponse)
+    )
+)]
+pub async fn payment_webhook_handler(
+    State(state): State<AppState>,
+    Path(method): Path<PaymentMethod>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<StatusCode> {
+    state.payment_service.handle_webhook(method, &headers, &body).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Bank account and fiat withdrawal handlers
+/// Adds a bank account to withdraw to. Two small deposits are sent to it;
+/// the account can be used once their amounts are confirmed.
+#[utoipa::path(
+    post,
+    path = "/api/v1/user/bank-accounts",
+    tag = "Fiat Withdrawals",
+    request_body = AddBankAccountRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The account, awaiting verification", body = BankAccount),
+        (status = 400, description = "Invalid account or routing details", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn add_bank_account_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<AddBankAccountRequest>,
+) -> Result<Json<BankAccount>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.fiat_withdrawal_service.add_bank_account(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/bank-accounts",
+    tag = "Fiat Withdrawals",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's bank accounts", body = [BankAccount]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_bank_accounts_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<BankAccount>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.fiat_withdrawal_service.list_bank_accounts(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/user/bank-accounts/{bank_account_id}/verify",
+    tag = "Fiat Withdrawals",
+    params(
+        ("bank_account_id" = Uuid, Path, description = "Bank account ID")
+    ),
+    request_body = VerifyBankAccountRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The verified account", body = BankAccount),
+        (status = 400, description = "Amounts do not match or the account is not awaiting verification", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Bank account not found", body = ErrorResponse)
+    )
+)]
+pub async fn verify_bank_account_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(bank_account_id): Path<Uuid>,
+    Json(payload): Json<VerifyBankAccountRequest>,
+) -> Result<Json<BankAccount>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state
+        .fiat_withdrawal_service
+        .verify_bank_account(user_id, bank_account_id, payload.amounts)
+        .await
+        .map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/user/bank-accounts/{bank_account_id}",
+    tag = "Fiat Withdrawals",
+    params(
+        ("bank_account_id" = Uuid, Path, description = "Bank account ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Bank account removed", body = SuccessResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Bank account not found", body = ErrorResponse)
+    )
+)]
+pub async fn remove_bank_account_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(bank_account_id): Path<Uuid>,
+) -> Result<Json<SuccessResponse>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.fiat_withdrawal_service.remove_bank_account(user_id, bank_account_id).await?;
+    Ok(Json(SuccessResponse {
+        message: "Bank account removed".to_string(),
+    }))
+}
+
+/// Requests a withdrawal to a verified bank account. The amount and the
+/// rail's fee are held until an admin reviews it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/withdrawals/fiat",
+    tag = "Fiat Withdrawals",
+    request_body = CreateFiatWithdrawalRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The pending withdrawal", body = FiatWithdrawal),
+        (status = 400, description = "Invalid amount, unverified account or insufficient balance", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Bank account not found", body = ErrorResponse)
+    )
+)]
+pub async fn create_fiat_withdrawal_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateFiatWithdrawalRequest>,
+) -> Result<Json<FiatWithdrawal>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.fiat_withdrawal_service.create_withdrawal(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/withdrawals/fiat",
+    tag = "Fiat Withdrawals",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's fiat withdrawals, newest first", body = [FiatWithdrawal]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_fiat_withdrawals_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<FiatWithdrawal>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.fiat_withdrawal_service.list_withdrawals(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/withdrawals/fiat/{withdrawal_id}",
+    tag = "Fiat Withdrawals",
+    params(
+        ("withdrawal_id" = Uuid, Path, description = "Withdrawal ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The cancelled withdrawal", body = FiatWithdrawal),
+        (status = 400, description = "Withdrawal is no longer pending", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Withdrawal not found", body = ErrorResponse)
+    )
+)]
+pub async fn cancel_fiat_withdrawal_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(withdrawal_id): Path<Uuid>,
+) -> Result<Json<FiatWithdrawal>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.fiat_withdrawal_service.cancel_withdrawal(user_id, withdrawal_id).await.map(Json)
+}
+
+/// Submits the originator and beneficiary details of a withdrawal that
+/// reached the travel rule threshold, or retries a failed transmission.
+#[utoipa::path(
+    post,
+    path = "/api/v1/withdrawals/fiat/{withdrawal_id}/travel-rule",
+    tag = "Fiat Withdrawals",
+    params(
+        ("withdrawal_id" = Uuid, Path, description = "Withdrawal ID")
+    ),
+    request_body = TravelRuleInfo,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The transmitted travel rule data", body = TravelRuleRecord),
+        (status = 400, description = "Invalid details, or the withdrawal is no longer pending", body = ErrorResponse),
+        (status = 404, description = "Withdrawal not found", body = ErrorResponse),
+        (status = 502, description = "Saved but not transmitted; submit again to retry", body = ErrorResponse)
+    )
+)]
+pub async fn submit_travel_rule_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(withdrawal_id): Path<Uuid>,
+    Json(payload): Json<TravelRuleInfo>,
+) -> Result<Json<TravelRuleRecord>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state
+        .fiat_withdrawal_service
+        .submit_travel_rule(user_id, withdrawal_id, payload)
+        .await
+        .map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/withdrawals/fiat/{withdrawal_id}/travel-rule",
+    tag = "Fiat Withdrawals",
+    params(
+        ("withdrawal_id" = Uuid, Path, description = "Withdrawal ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Travel rule data on file for the withdrawal", body = TravelRuleRecord),
+        (status = 404, description = "None submitted", body = ErrorResponse)
+    )
+)]
+pub async fn get_travel_rule_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(withdrawal_id): Path<Uuid>,
+) -> Result<Json<TravelRuleRecord>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.fiat_withdrawal_service.travel_rule_record(user_id, withdrawal_id).await.map(Json)
+}
+
+// Crypto deposit and withdrawal handlers
+/// The caller's deposit address for a currency. On chains paying every
+/// user to one shared address it carries the caller's memo, which deposits
+/// must include to be credited.
+#[utoipa::path(
+    get,
+    path = "/api/v1/deposits/crypto/{currency}/address",
+    tag = "Crypto Transfers",
+    params(
+        ("currency" = String, Path, description = "Currency code, e.g. XRP")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Where to send deposits", body = DepositAddress),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Currency not listed or without deposits", body = ErrorResponse)
+    )
+)]
+pub async fn get_deposit_address_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(currency): Path<String>,
+) -> Result<Json<DepositAddress>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.crypto_transfer_service.deposit_address(user_id, &currency).await.map(Json)
+}
+
+/// Receives the deposit watcher's report of a transfer to a deposit
+/// address, signed with the watcher secret in `X-Watcher-Signature`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/deposits",
+    tag = "Crypto Transfers",
+    request_body = ChainDepositReport,
+    responses(
+        (status = 204, description = "Report applied"),
+        (status = 400, description = "Invalid report", body = ErrorResponse),
+        (status = 401, description = "Signature missing or invalid", body = ErrorResponse),
+        (status = 404, description = "Reports not accepted, or currency not listed", body = ErrorResponse)
+    )
+)]
+pub async fn deposit_report_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<StatusCode> {
+    state.crypto_transfer_service.handle_deposit_report(&headers, &body).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Requests a withdrawal to an address, with the recipient's memo or
+/// destination tag on chains that use them. The amount is held until the
+/// withdrawal is sent.
+#[utoipa::path(
+    post,
+    path = "/api/v1/withdrawals/crypto",
+    tag = "Crypto Transfers",
+    request_body = CreateCryptoWithdrawalRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The pending withdrawal", body = CryptoWithdrawal),
+        (status = 400, description = "Invalid amount or memo, or insufficient balance", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Currency not listed", body = ErrorResponse)
+    )
+)]
+pub async fn create_crypto_withdrawal_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateCryptoWithdrawalRequest>,
+) -> Result<Json<CryptoWithdrawal>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.crypto_transfer_service.create_withdrawal(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/withdrawals/crypto",
+    tag = "Crypto Transfers",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's crypto withdrawals, newest first", body = [CryptoWithdrawal]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_crypto_withdrawals_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CryptoWithdrawal>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.crypto_transfer_service.list_withdrawals(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/withdrawals/crypto/{withdrawal_id}",
+    tag = "Crypto Transfers",
+    params(
+        ("withdrawal_id" = Uuid, Path, description = "Withdrawal ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The cancelled withdrawal", body = CryptoWithdrawal),
+        (status = 400, description = "Withdrawal is no longer pending", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Withdrawal not found", body = ErrorResponse)
+    )
+)]
+pub async fn cancel_crypto_withdrawal_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(withdrawal_id): Path<Uuid>,
+) -> Result<Json<CryptoWithdrawal>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.crypto_transfer_service.cancel_withdrawal(user_id, withdrawal_id).await.map(Json)
+}
+
+// Portfolio handlers
+#[utoipa::path(
+    get,
+    path = "/api/v1/portfolio",
+    tag = "Portfolio",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("fields" = Option<String>, Query, description = "Comma separated fields to return, e.g. accounts.currency; all by default")
+    ),
+    responses(
+        (status = 200, description = "Portfolio retrieved successfully", body = Portfolio),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_portfolio_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Portfolio>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.portfolio_service.get_portfolio(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/portfolio/history",
+    tag = "Portfolio",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("days" = Option<i32>, Query, description = "Number of days to retrieve history for (default: 30)")
+    ),
+    responses(
+        (status = 200, description = "Portfolio history retrieved successfully", body = [PortfolioSnapshot]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_portfolio_history_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<HistoryQuery>,
+) -> Result<Json<Vec<PortfolioSnapshot>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.portfolio_service.get_portfolio_history(user_id, params.days.unwrap_or(30)).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/portfolio/allocation",
+    tag = "Portfolio",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's target allocation, largest first", body = [AllocationTarget]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_allocation_targets_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AllocationTarget>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.rebalance_service.targets(user_id).await.map(Json)
+}
+
+/// Replaces the caller's target allocation; the targets must sum to 100%.
+#[utoipa::path(
+    put,
+    path = "/api/v1/portfolio/allocation",
+    tag = "Portfolio",
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body = SetAllocationTargetsRequest,
+    responses(
+        (status = 200, description = "The new target allocation", body = [AllocationTarget]),
+        (status = 400, description = "Invalid targets, or they do not sum to 100", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn set_allocation_targets_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<SetAllocationTargetsRequest>,
+) -> Result<Json<Vec<AllocationTarget>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.rebalance_service.set_targets(user_id, payload).await.map(Json)
+}
+
+/// Measures the portfolio's drift from its targets at oracle prices and
+/// proposes the market orders that would close it. Nothing is placed until
+/// the plan is confirmed.
+#[utoipa::path(
+    post,
+    path = "/api/v1/portfolio/rebalance",
+    tag = "Portfolio",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Drift per asset and the proposed trades", body = RebalancePlan),
+        (status = 400, description = "No allocation targets set", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn plan_rebalance_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<RebalancePlan>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.rebalance_service.plan(user_id).await.map(Json)
+}
+
+/// Confirms a plan: places its trades as proposed, once, before it expires.
+#[utoipa::path(
+    post,
+    path = "/api/v1/portfolio/rebalance/{plan_id}/execute",
+    tag = "Portfolio",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("plan_id" = Uuid, Path, description = "Rebalance plan ID")
+    ),
+    responses(
+        (status = 200, description = "The order placed for each trade, or why not", body = RebalanceExecution),
+        (status = 404, description = "Rebalance plan not found", body = ErrorResponse),
+        (status = 409, description = "Plan expired or already placed", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 451, description = "Trading from or residence in a restricted country", body = ErrorResponse)
+    )
+)]
+pub async fn execute_rebalance_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(plan_id): Path<Uuid>,
+) -> Result<Json<RebalanceExecution>> {
+    let user_id = parse_user_id(&claims)?;
+    let origin = state.compliance_service.request_country(&headers);
+    state.compliance_service.check_trading(user_id, origin.as_deref()).await?;
+
+    state.rebalance_service.execute(user_id, plan_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/stats",
+    tag = "User Management",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "30-day volume, fee rates, open orders per pair and locked funds by order", body = UserStats),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_user_stats_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<UserStats>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.portfolio_service.get_user_stats(user_id).await.map(Json)
+}
+
+/// Balances at a past moment, rebuilt from the ledger, for audits and tax
+/// reports. Without `at` they are the balances now.
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/balances",
+    tag = "User Management",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("at" = Option<String>, Query, description = "Point in time, inclusive (RFC 3339); now by default")
+    ),
+    responses(
+        (status = 200, description = "Balance per currency at the time", body = HistoricalBalances),
+        (status = 400, description = "Time in the future", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_balances_at_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<BalancesQuery>,
+) -> Result<Json<HistoricalBalances>> {
+    let user_id = parse_user_id(&claims)?;
+
+    let at = params.at.unwrap_or_else(chrono::Utc::now);
+    state.ledger_service.balances_at(user_id, at).await.map(Json)
+}
+
+/// Ledger entries in one currency with their running balance. `format=csv`
+/// returns the same page as a CSV attachment.
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/statement",
+    tag = "User Management",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("currency" = String, Query, description = "Account currency, e.g. BTC"),
+        ("start" = Option<String>, Query, description = "Earliest entry time, inclusive (RFC 3339)"),
+        ("end" = Option<String>, Query, description = "Latest entry time, exclusive (RFC 3339)"),
+        ("limit" = Option<i64>, Query, description = "Page size (default 100, max 1000)"),
+        ("offset" = Option<i64>, Query, description = "Entries to skip"),
+        ("format" = Option<String>, Query, description = "`json` (default) or `csv`")
+    ),
+    responses(
+        (status = 200, description = "Statement page, as JSON or CSV depending on `format`", content(
+            (Statement = "application/json"),
+            (String = "text/csv")
+        )),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_statement_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<StatementQuery>,
+) -> Result<Response> {
+    let user_id = parse_user_id(&claims)?;
+    let currency = params.currency.to_uppercase();
+
+    let statement = state
+        .ledger_service
+        .get_statement(user_id, &currency, params.start, params.end, params.limit, params.offset)
+        .await?;
+
+    match params.format.unwrap_or_default() {
+        StatementFormat::Json => Ok(Json(statement).into_response()),
+        StatementFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for entry in &statement.entries {
+                writer.serialize(entry).map_err(std::io::Error::from)?;
+            }
+            let body = writer.into_inner().map_err(|e| e.into_error())?;
+
+            let disposition = format!("attachment; filename=\"statement-{}.csv\"", currency);
+            Ok((
+                [
+                    (header::CONTENT_TYPE, HeaderValue::from_static("text/csv")),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        HeaderValue::from_str(&disposition).map_err(|_| CryptoTradeError::Validation {
+                            message: "Invalid currency".to_string(),
+                        })?,
+                    ),
+                ],
+                body,
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Every trade of the caller, oldest first, as newline-delimited JSON
+/// streamed straight from the database. Pass the id of the last trade
+/// received as `from_id` to resume an interrupted download.
+#[utoipa::path(
+    get,
+    path = "/api/v1/exports/trades",
+    tag = "User Management",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("from_id" = Option<Uuid>, Query, description = "Resume after this trade")
+    ),
+    responses(
+        (status = 200, description = "One trade per line", content_type = "application/x-ndjson", body = Trade),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "from_id is not one of the caller's trades", body = ErrorResponse),
+        (status = 503, description = "Too many exports running; retry shortly", body = ErrorResponse)
+    )
+)]
+pub async fn export_trades_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<ExportQuery>,
+) -> Result<Response> {
+    let user_id = parse_user_id(&claims)?;
+
+    let rows = state.export_service.trades(user_id, params.from_id).await?;
+    Ok(ndjson_response(rows, "trades.ndjson"))
+}
+
+/// Every order of the caller, oldest first, as newline-delimited JSON;
+/// resumable like the trade export.
+#[utoipa::path(
+    get,
+    path = "/api/v1/exports/orders",
+    tag = "User Management",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("from_id" = Option<Uuid>, Query, description = "Resume after this order")
+    ),
+    responses(
+        (status = 200, description = "One order per line", content_type = "application/x-ndjson", body = Order),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "from_id is not one of the caller's orders", body = ErrorResponse),
+        (status = 503, description = "Too many exports running; retry shortly", body = ErrorResponse)
+    )
+)]
+pub async fn export_orders_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<ExportQuery>,
+) -> Result<Response> {
+    let user_id = parse_user_id(&claims)?;
+
+    let rows = state.export_service.orders(user_id, params.from_id).await?;
+    Ok(ndjson_response(rows, "orders.ndjson"))
+}
+
+/// Writes every trade of the caller to object storage as one NDJSON file
+/// and returns a presigned link to it, for clients that would rather fetch
+/// a file than hold a stream open.
+#[utoipa::path(
+    post,
+    path = "/api/v1/exports/trades/file",
+    tag = "User Management",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The export file", body = ExportFile),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 503, description = "Too many exports running; retry shortly", body = ErrorResponse)
+    )
+)]
+pub async fn export_trades_file_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<ExportFile>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.export_service.trades_file(user_id).await.map(Json)
+}
+
+/// Like the trade export file, for orders.
+#[utoipa::path(
+    post,
+    path = "/api/v1/exports/orders/file",
+    tag = "User Management",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The export file", body = ExportFile),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 503, description = "Too many exports running; retry shortly", body = ErrorResponse)
+    )
+)]
+pub async fn export_orders_file_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<ExportFile>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.export_service.orders_file(user_id).await.map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PresignedQuery {
+    pub expires: i64,
+    pub signature: String,
+}
+
+/// Serves an object of the local storage backend through a presigned URL
+/// the API issued; no token needed. With the S3 backend, presigned URLs
+/// point at the bucket and this route finds nothing.
+#[utoipa::path(
+    get,
+    path = "/api/v1/storage/{key}",
+    tag = "Documents",
+    params(
+        ("key" = String, Path, description = "Object key"),
+        ("expires" = i64, Query, description = "When the URL expires, in Unix seconds"),
+        ("signature" = String, Query, description = "Signature of the key and expiry")
+    ),
+    responses(
+        (status = 200, description = "The object", body = Vec<u8>),
+        (status = 403, description = "Expired or invalid signature", body = ErrorResponse),
+        (status = 404, description = "Object not found", body = ErrorResponse)
+    )
+)]
+pub async fn download_object_handler(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<PresignedQuery>,
+) -> Result<Response> {
+    if params.expires < chrono::Utc::now().timestamp() || !state.storage.verify_signature(&key, params.expires, &params.signature) {
+        return Err(CryptoTradeError::Authorization {
+            message: "Download link is invalid or has expired".to_string(),
+        });
+    }
+
+    let body = state.storage.get(&key).await?.ok_or_else(|| CryptoTradeError::NotFound {
+        message: "Object not found".to_string(),
+    })?;
+    let file_name = key.rsplit('/').next().unwrap_or(&key);
+    let disposition = format!("attachment; filename=\"{}\"", file_name);
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(storage::content_type(&key))),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&disposition).map_err(|_| CryptoTradeError::Internal)?,
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Requests a PDF receipt, trade confirmation or monthly statement. It is
+/// generated in the background; poll the document list for its download link.
+#[utoipa::path(
+    post,
+    path = "/api/v1/user/documents",
+    tag = "Documents",
+    request_body = RequestDocumentRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The queued document, or the one already requested for the same subject", body = Document),
+        (status = 400, description = "Missing reference, invalid month, or nothing to issue a receipt for", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Transaction or trade not found", body = ErrorResponse)
+    )
+)]
+pub async fn request_document_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<RequestDocumentRequest>,
+) -> Result<Json<Document>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.document_service.request(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/documents",
+    tag = "Documents",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's documents, newest first", body = [Document]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_documents_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Document>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.document_service.list(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/documents/{document_id}/download",
+    tag = "Documents",
+    params(
+        ("document_id" = Uuid, Path, description = "Document ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The PDF, for documents rendered before object storage", content_type = "application/pdf", body = Vec<u8>),
+        (status = 307, description = "Redirect to a presigned URL of the PDF"),
+        (status = 400, description = "Document is not ready", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Document not found", body = ErrorResponse)
+    )
+)]
+pub async fn download_document_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+) -> Result<Response> {
+    let user_id = parse_user_id(&claims)?;
+
+    let (document, body) = state.document_service.download(user_id, document_id).await?;
+    let content = match body {
+        DocumentBody::Presigned(url) => {
+            let location = HeaderValue::from_str(&url.url).map_err(|_| CryptoTradeError::Internal)?;
+            return Ok((StatusCode::TEMPORARY_REDIRECT, [(header::LOCATION, location)]).into_response());
+        }
+        DocumentBody::Inline(content) => content,
+    };
+    let disposition = format!("attachment; filename=\"{}\"", document.file_name());
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/pdf")),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&disposition).map_err(|_| CryptoTradeError::Internal)?,
+            ),
+        ],
+        content,
+    )
+        .into_response())
+}
+
+/// Opens a support ticket with its first message. Files are attached
+/// separately, once the ticket exists.
+#[utoipa::path(
+    post,
+    path = "/api/v1/support/tickets",
+    tag = "Support",
+    request_body = CreateTicketRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The opened ticket", body = SupportTicketDetail),
+        (status = 400, description = "Invalid subject or message", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn create_ticket_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateTicketRequest>,
+) -> Result<Json<SupportTicketDetail>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.support_service.open_ticket(user_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/support/tickets",
+    tag = "Support",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The caller's tickets, newest first", body = [SupportTicket]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn list_tickets_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SupportTicket>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.support_service.list_tickets(user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/support/tickets/{ticket_id}",
+    tag = "Support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The ticket with its messages and attachments", body = SupportTicketDetail),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Ticket not found", body = ErrorResponse)
+    )
+)]
+pub async fn get_ticket_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(ticket_id): Path<Uuid>,
+) -> Result<Json<SupportTicketDetail>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.support_service.ticket(TicketActor::User(user_id), ticket_id).await.map(Json)
+}
+
+/// Replies on a ticket, reopening it for support if it was pending or
+/// resolved.
+#[utoipa::path(
+    post,
+    path = "/api/v1/support/tickets/{ticket_id}/messages",
+    tag = "Support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID")
+    ),
+    request_body = TicketReplyRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The added message", body = TicketMessage),
+        (status = 400, description = "Empty or overlong message", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Ticket not found", body = ErrorResponse)
+    )
+)]
+pub async fn reply_ticket_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(ticket_id): Path<Uuid>,
+    Json(payload): Json<TicketReplyRequest>,
+) -> Result<Json<TicketMessage>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.support_service.reply(TicketActor::User(user_id), ticket_id, payload).await.map(Json)
+}
+
+/// Attaches a PDF, PNG or JPEG sent as the `file` field of a multipart form.
+#[utoipa::path(
+    post,
+    path = "/api/v1/support/tickets/{ticket_id}/attachments",
+    tag = "Support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID")
+    ),
+    request_body(content = Vec<u8>, content_type = "multipart/form-data", description = "The file, as the `file` field"),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The stored attachment", body = TicketAttachment),
+        (status = 400, description = "Missing file, unsupported type, too large, or too many attachments", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Ticket not found", body = ErrorResponse),
+        (status = 413, description = "File larger than the upload limit", body = ErrorResponse)
+    )
+)]
+pub async fn upload_ticket_attachment_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(ticket_id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<Json<TicketAttachment>> {
+    let user_id = parse_user_id(&claims)?;
+    let (file_name, body) = multipart_file(multipart, state.limits_config.upload_limit_bytes).await?;
+
+    state
+        .support_service
+        .attach(TicketActor::User(user_id), ticket_id, &file_name, body)
+        .await
+        .map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/support/tickets/{ticket_id}/attachments/{attachment_id}",
+    tag = "Support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID"),
+        ("attachment_id" = Uuid, Path, description = "Attachment ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 307, description = "Redirect to a presigned URL of the file"),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Ticket or attachment not found", body = ErrorResponse)
+    )
+)]
+pub async fn download_ticket_attachment_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path((ticket_id, attachment_id)): Path<(Uuid, Uuid)>,
+) -> Result<Response> {
+    let user_id = parse_user_id(&claims)?;
+
+    let url = state
+        .support_service
+        .attachment_url(TicketActor::User(user_id), ticket_id, attachment_id)
+        .await?;
+    redirect_to(&url.url)
+}
+
+/// Reads the `file` field of a multipart upload.
+async fn multipart_file(mut multipart: Multipart, limit_bytes: usize) -> Result<(String, Vec<u8>)> {
+    let invalid = |e: MultipartError| match e.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => CryptoTradeError::PayloadTooLarge { limit_bytes },
+        _ => CryptoTradeError::Validation { message: e.body_text() },
+    };
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let file_name = field.file_name().unwrap_or_default().to_string();
+        let body = field.bytes().await.map_err(invalid)?;
+        return Ok((file_name, body.to_vec()));
+    }
+    Err(CryptoTradeError::Validation {
+        message: "Missing multipart field `file`".to_string(),
+    })
+}
+
+fn redirect_to(url: &str) -> Result<Response> {
+    let location = HeaderValue::from_str(url).map_err(|_| CryptoTradeError::Internal)?;
+    Ok((StatusCode::TEMPORARY_REDIRECT, [(header::LOCATION, location)]).into_response())
+}
+
+// Trading handlers
+#[utoipa::path(
+    get,
+    path = "/api/v1/trades",
+    tag = "Trading",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("limit" = Option<i64>, Query, description = "Limit number of results")
+    ),
+    responses(
+        (status = 200, description = "Trades retrieved successfully", body = [Trade]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse)
+    )
+)]
+pub async fn get_user_trades_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<TradesQuery>,
+) -> Result<Json<Vec<Trade>>> {
+    let user_id = parse_user_id(&claims)?;
+
+    state.trading_service.get_user_trades(user_id, params.limit).await.map(Json)
+}
+
+// Market data handlers
+/// The server clock, to sync with before signing requests.
+#[utoipa::path(
+    get,
+    path = "/api/v1/time",
+    tag = "Market Data",
+    responses(
+        (status = 200, description = "Server time in Unix milliseconds", body = ServerTime),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn server_time_handler(State(state): State<AppState>) -> Json<ServerTime> {
+    Json(state.exchange_info_service.server_time())
+}
+
+/// Pairs with their order filters, rate limits and supported features in
+/// one document.
+#[utoipa::path(
+    get,
+    path = "/api/v1/exchange-info",
+    tag = "Market Data",
+    params(
+        ("X-Tenant" = Option<String>, Header, description = "Slug of the white-label broker asking; adds its private pairs"),
+        ("fields" = Option<String>, Query, description = "Comma separated fields to return, e.g. accounts.currency; all by default")
+    ),
+    responses(
+        (status = 200, description = "Exchange rules and listed pairs", body = ExchangeInfo),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn exchange_info_handler(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<ExchangeInfo>> {
+    let tenant_id = request_tenant(&state, &headers).await?;
+    state.exchange_info_service.exchange_info(tenant_id).await.map(Json)
+}
+
+/// Which optional subsystems this deployment runs, the API version and the
+/// API changelog, for SDKs to feature-detect against. No token needed.
+#[utoipa::path(
+    get,
+    path = "/api/v1/capabilities",
+    tag = "Market Data",
+    responses(
+        (status = 200, description = "Enabled subsystems, API version and changelog", body = Capabilities)
+    )
+)]
+pub async fn capabilities_handler(State(state): State<AppState>) -> Json<Capabilities> {
+    Json(state.exchange_info_service.capabilities())
+}
+
+/// Every active pair, or with `watchlist=true` only the caller's watched
+/// pairs in their order, which takes a bearer token.
+#[utoipa::path(
+    get,
+    path = "/api/v1/market-data",
+    tag = "Market Data",
+    params(
+        ("watchlist" = Option<bool>, Query, description = "Only the caller's watchlist, in its order"),
+        ("X-Tenant" = Option<String>, Header, description = "Slug of the white-label broker asking, without a token; adds its private pairs")
+    ),
+    responses(
+        (status = 200, description = "Market data retrieved successfully", body = [MarketData]),
+        (status = 304, description = "Unchanged since the ETag sent in If-None-Match"),
+        (status = 401, description = "Watchlist requested without a token", body = ErrorResponse)
+    )
+)]
+pub async fn get_all_market_data_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<MarketDataQuery>,
+) -> Result<Response> {
+    let tenant_id = viewer_tenant(&state, claims.as_deref(), &headers).await?;
+    if !params.watchlist {
+        return state.market_data_service.get_all_market_data(tenant_id).await.map(|data| conditional_json(&headers, &data, Some("updated_at")));
+    }
+
+    let Some(Extension(claims)) = claims else {
+        return Err(CryptoTradeError::Authentication {
+            message: "The watchlist needs a bearer token".to_string(),
+        });
+    };
+    let user_id = parse_user_id(&claims)?;
+    let watched = state.watchlist_service.watched_pair_ids(user_id).await?;
+    let data = state.market_data_service.get_market_data_for(&watched, tenant_id).await?;
+    Ok(conditional_json(&headers, &data, Some("updated_at")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/market-data/{pair_id}",
+    tag = "Market Data",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    ),
+    responses(
+        (status = 200, description = "Market data retrieved successfully", body = MarketData),
+        (status = 304, description = "Unchanged since the ETag sent in If-None-Match"),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse)
+    )
+)]
+pub async fn get_market_data_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(pair_id): Path<Uuid>,
+) -> Result<Response> {
+    let tenant_id = viewer_tenant(&state, claims.as_deref(), &headers).await?;
+    state.market_data_service.get_market_data(pair_id, tenant_id).await.map(|data| conditional_json(&headers, &data, Some("updated_at")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/currencies",
+    tag = "Market Data",
+    responses(
+        (status = 200, description = "Listed currencies, by code", body = [Currency]),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn list_currencies_handler(State(state): State<AppState>) -> Result<Json<Vec<Currency>>> {
+    state.currency_service.list_currencies().await.map(Json)
+}
+
+/// Every pair not delisted, with its lifecycle status and trading hours.
+/// Pairs private to a white-label broker are only listed for its slug.
+#[utoipa::path(
+    get,
+    path = "/api/v1/trading-pairs",
+    tag = "Market Data",
+    params(
+        ("X-Tenant" = Option<String>, Header, description = "Slug of the white-label broker asking; adds its private pairs")
+    ),
+    responses(
+        (status = 200, description = "Trading pairs, by symbol", body = [TradingPairListing]),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn list_trading_pairs_handler(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<Vec<TradingPairListing>>> {
+    let tenant_id = request_tenant(&state, &headers).await?;
+    state.trading_pair_service.list_trading_pairs(tenant_id).await.map(Json)
+}
+
+/// Every active pair's 24-hour ticker in one array, for aggregators that
+/// poll the whole exchange. The array is cached briefly, and clients may
+/// cache it as long.
+#[utoipa::path(
+    get,
+    path = "/api/v1/tickers",
+    tag = "Market Data",
+    params(
+        ("fields" = Option<String>, Query, description = "Comma separated fields to return, e.g. accounts.currency; all by default"),
+        ("X-Tenant" = Option<String>, Header, description = "Slug of the white-label broker asking, without a token; adds its private pairs")
+    ),
+    responses(
+        (status = 200, description = "24-hour tickers of all active pairs, by symbol", body = [Ticker]),
+        (status = 304, description = "Unchanged since the ETag sent in If-None-Match"),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_tickers_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let tenant_id = viewer_tenant(&state, claims.as_deref(), &headers).await?;
+    let tickers = state.market_data_service.get_tickers(tenant_id).await?;
+    let mut response = conditional_json(&headers, &*tickers, None);
+    let cache_control = format!("public, max-age={}", state.market_data_service.tickers_cache_ttl().as_secs());
+    if let Ok(value) = HeaderValue::from_str(&cache_control) {
+        response.headers_mut().insert(header::CACHE_CONTROL, value);
+    }
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/order-book/{pair_id}",
+    tag = "Market Data",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID"),
+        ("depth" = Option<usize>, Query, description = "Price levels per side; 20 by default, up to 20 without an API key and 100 with one")
+    ),
+    responses(
+        (status = 200, description = "Order book retrieved successfully", body = OrderBook),
+        (status = 304, description = "Unchanged since the ETag sent in If-None-Match"),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_book_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    Extension(tier): Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(pair_id): Path<Uuid>,
+    Query(params): Query<OrderBookQuery>,
+) -> Result<Response> {
+    ensure_pair_visible(&state, claims.as_deref(), &headers, pair_id).await?;
+    let depth = params.depth.unwrap_or(20).min(tier.order_book_depth);
+    state.order_service.get_order_book(pair_id, Some(depth)).await.map(|order_book| conditional_json(&headers, &order_book, Some("timestamp")))
+}
+
+/// The engine's live book with the engine sequence it reflects, to start a
+/// local book kept current by the `book` WebSocket feed: buffer the feed,
+/// fetch this, skip buffered updates whose `engine_sequence` is at or below
+/// `last_update_id` and apply the rest.
+#[utoipa::path(
+    get,
+    path = "/api/v1/order-book/{pair_id}/snapshot",
+    tag = "Market Data",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID"),
+        ("depth" = Option<usize>, Query, description = "Price levels per side; 20 by default, up to 20 without an API key and 100 with one")
+    ),
+    responses(
+        (status = 200, description = "Order book snapshot", body = OrderBookSnapshot),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_book_snapshot_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    Extension(tier): Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(pair_id): Path<Uuid>,
+    Query(params): Query<OrderBookQuery>,
+) -> Result<Json<OrderBookSnapshot>> {
+    ensure_pair_visible(&state, claims.as_deref(), &headers, pair_id).await?;
+    let depth = params.depth.unwrap_or(20).min(tier.order_book_depth);
+    state.matching_service.book_snapshot(pair_id, depth).await.map(Json)
+}
+
+/// Spread, mid price and the liquidity within 0.5%, 1% and 2% of mid,
+/// taken from the matching engine's live book.
+#[utoipa::path(
+    get,
+    path = "/api/v1/order-book/{pair_id}/stats",
+    tag = "Market Data",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    ),
+    responses(
+        (status = 200, description = "Depth-of-market statistics", body = OrderBookStats),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_book_stats_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(pair_id): Path<Uuid>,
+) -> Result<Json<OrderBookStats>> {
+    ensure_pair_visible(&state, claims.as_deref(), &headers, pair_id).await?;
+    state.matching_service.book_stats(pair_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/trades/{pair_id}",
+    tag = "Market Data",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID"),
+        ("limit" = Option<i64>, Query, description = "Most recent trades to return; up to 100 without an API key and 1000 with one")
+    ),
+    responses(
+        (status = 200, description = "Recent trades retrieved successfully", body = [Trade]),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_recent_trades_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    Extension(tier): Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(pair_id): Path<Uuid>,
+    Query(params): Query<TradesQuery>,
+) -> Result<Json<Vec<Trade>>> {
+    ensure_pair_visible(&state, claims.as_deref(), &headers, pair_id).await?;
+    let limit = params.limit.unwrap_or(tier.max_trades).min(tier.max_trades);
+    state.trading_service.get_recent_trades(pair_id, Some(limit)).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/candlesticks/{pair_id}",
+    tag = "Market Data",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID"),
+        ("interval" = Option<String>, Query, description = "Candlestick interval: 1m, 3m, 5m, 15m, 30m, 1h, 2h, 4h, 6h, 12h, 1d, 1w or 1M (months); 1h by default"),
+        ("start_time" = Option<String>, Query, description = "Start time (ISO 8601); at most 30 days back without an API key"),
+        ("end_time" = Option<String>, Query, description = "End time (ISO 8601)"),
+        ("limit" = Option<i32>, Query, description = "Limit number of results; up to 500 without an API key and 5000 with one"),
+        ("fill" = Option<CandleFill>, Query, description = "`previous` to return flat zero-volume candles at the previous close for buckets without trades; `none` by default")
+    ),
+    responses(
+        (status = 200, description = "Candlestick data retrieved successfully", body = [Candlestick]),
+        (status = 304, description = "Unchanged since the ETag sent in If-None-Match"),
+        (status = 400, description = "Unsupported interval, or a start time further back than the caller's history allows", body = ErrorResponse),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_candlestick_data_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    Extension(tier): Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(pair_id): Path<Uuid>,
+    Query(params): Query<CandlestickQuery>,
+) -> Result<Response> {
+    ensure_pair_visible(&state, claims.as_deref(), &headers, pair_id).await?;
+    let earliest = chrono::Utc::now() - chrono::Duration::days(tier.history_days);
+    if params.start_time.is_some_and(|start| start < earliest) {
+        return Err(CryptoTradeError::Validation {
+            message: format!("start_time may be at most {} days back; API keys can go further", tier.history_days),
+        });
+    }
+    let limit = params.limit.unwrap_or(tier.max_candles).min(tier.max_candles);
+    let interval = params.interval.as_deref().map_or(Ok(Interval::OneHour), str::parse)?;
+
+    state.market_data_service.get_candlestick_data(
+        pair_id,
+        interval,
+        params.start_time,
+        params.end_time,
+        Some(limit),
+        params.fill.unwrap_or_default(),
+    ).await.map(|candlesticks| conditional_json(&headers, &candlesticks, None))
+}
+
+/// Indicators computed server-side from the candle store, so clients can
+/// chart them without downloading the history behind them.
+#[utoipa::path(
+    get,
+    path = "/api/v1/indicators/{pair_id}",
+    tag = "Market Data",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID"),
+        ("interval" = Option<String>, Query, description = "Candle interval, as for candlesticks; 1h by default"),
+        ("window" = Option<u32>, Query, description = "Candles each indicator covers, 2 to 200; 14 by default"),
+        ("end_time" = Option<String>, Query, description = "Time of the last point (ISO 8601); now by default"),
+        ("limit" = Option<i32>, Query, description = "Points to return; 100 by default, capped like candlesticks")
+    ),
+    responses(
+        (status = 200, description = "VWAP, SMA, EMA and ATR per candle", body = Indicators),
+        (status = 400, description = "Unsupported interval or window, or a range further back than the caller's history allows", body = ErrorResponse),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_indicators_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    Extension(tier): Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(pair_id): Path<Uuid>,
+    Query(params): Query<IndicatorQuery>,
+) -> Result<Response> {
+    ensure_pair_visible(&state, claims.as_deref(), &headers, pair_id).await?;
+    let interval = params.interval.as_deref().map_or(Ok(Interval::OneHour), str::parse)?;
+    let limit = params.limit.unwrap_or(100).min(tier.max_candles);
+    let earliest = chrono::Utc::now() - chrono::Duration::days(tier.history_days);
+
+    state
+        .market_data_service
+        .get_indicators(pair_id, interval, params.window.unwrap_or(14), params.end_time, limit, earliest)
+        .await
+        .map(|indicators| conditional_json(&headers, &indicators, None))
+}
+
+// Market data by symbol. The `/api/v1` routes taking a pair id are
+// deprecated in favour of these; each resolves the symbol and answers as
+// the route it replaces.
+#[utoipa::path(
+    get,
+    path = "/api/v2/market-data/{symbol}",
+    tag = "Market Data",
+    params(
+        ("symbol" = String, Path, description = "Trading pair symbol, e.g. BTC-USD")
+    ),
+    responses(
+        (status = 200, description = "Market data retrieved successfully", body = MarketData),
+        (status = 304, description = "Unchanged since the ETag sent in If-None-Match"),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse)
+    )
+)]
+pub async fn get_market_data_by_symbol_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(symbol): Path<String>,
+) -> Result<Response> {
+    let pair_id = state.trading_pair_service.pair_id(&symbol).await?;
+    get_market_data_handler(claims, State(state), headers, Path(pair_id)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/order-book/{symbol}",
+    tag = "Market Data",
+    params(
+        ("symbol" = String, Path, description = "Trading pair symbol, e.g. BTC-USD"),
+        ("depth" = Option<usize>, Query, description = "Price levels per side; 20 by default, up to 20 without an API key and 100 with one")
+    ),
+    responses(
+        (status = 200, description = "Order book retrieved successfully", body = OrderBook),
+        (status = 304, description = "Unchanged since the ETag sent in If-None-Match"),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_book_by_symbol_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    tier: Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(symbol): Path<String>,
+    params: Query<OrderBookQuery>,
+) -> Result<Response> {
+    let pair_id = state.trading_pair_service.pair_id(&symbol).await?;
+    get_order_book_handler(claims, State(state), tier, headers, Path(pair_id), params).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/order-book/{symbol}/snapshot",
+    tag = "Market Data",
+    params(
+        ("symbol" = String, Path, description = "Trading pair symbol, e.g. BTC-USD"),
+        ("depth" = Option<usize>, Query, description = "Price levels per side; 20 by default, up to 20 without an API key and 100 with one")
+    ),
+    responses(
+        (status = 200, description = "Order book snapshot", body = OrderBookSnapshot),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_book_snapshot_by_symbol_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    tier: Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(symbol): Path<String>,
+    params: Query<OrderBookQuery>,
+) -> Result<Json<OrderBookSnapshot>> {
+    let pair_id = state.trading_pair_service.pair_id(&symbol).await?;
+    get_order_book_snapshot_handler(claims, State(state), tier, headers, Path(pair_id), params).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/order-book/{symbol}/stats",
+    tag = "Market Data",
+    params(
+        ("symbol" = String, Path, description = "Trading pair symbol, e.g. BTC-USD")
+    ),
+    responses(
+        (status = 200, description = "Depth-of-market statistics", body = OrderBookStats),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_order_book_stats_by_symbol_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(symbol): Path<String>,
+) -> Result<Json<OrderBookStats>> {
+    let pair_id = state.trading_pair_service.pair_id(&symbol).await?;
+    get_order_book_stats_handler(claims, State(state), headers, Path(pair_id)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/trades/{symbol}",
+    tag = "Market Data",
+    params(
+        ("symbol" = String, Path, description = "Trading pair symbol, e.g. BTC-USD"),
+        ("limit" = Option<i64>, Query, description = "Most recent trades to return; up to 100 without an API key and 1000 with one")
+    ),
+    responses(
+        (status = 200, description = "Recent trades retrieved successfully", body = [Trade]),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_recent_trades_by_symbol_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    tier: Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(symbol): Path<String>,
+    params: Query<TradesQuery>,
+) -> Result<Json<Vec<Trade>>> {
+    let pair_id = state.trading_pair_service.pair_id(&symbol).await?;
+    get_recent_trades_handler(claims, State(state), tier, headers, Path(pair_id), params).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/candlesticks/{symbol}",
+    tag = "Market Data",
+    params(
+        ("symbol" = String, Path, description = "Trading pair symbol, e.g. BTC-USD"),
+        ("interval" = Option<String>, Query, description = "Candlestick interval: 1m, 3m, 5m, 15m, 30m, 1h, 2h, 4h, 6h, 12h, 1d, 1w or 1M (months); 1h by default"),
+        ("start_time" = Option<String>, Query, description = "Start time (ISO 8601); at most 30 days back without an API key"),
+        ("end_time" = Option<String>, Query, description = "End time (ISO 8601)"),
+        ("limit" = Option<i32>, Query, description = "Limit number of results; up to 500 without an API key and 5000 with one"),
+        ("fill" = Option<CandleFill>, Query, description = "`previous` to return flat zero-volume candles at the previous close for buckets without trades; `none` by default")
+    ),
+    responses(
+        (status = 200, description = "Candlestick data retrieved successfully", body = [Candlestick]),
+        (status = 304, description = "Unchanged since the ETag sent in If-None-Match"),
+        (status = 400, description = "Unsupported interval, or a start time further back than the caller's history allows", body = ErrorResponse),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_candlestick_data_by_symbol_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    tier: Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(symbol): Path<String>,
+    params: Query<CandlestickQuery>,
+) -> Result<Response> {
+    let pair_id = state.trading_pair_service.pair_id(&symbol).await?;
+    get_candlestick_data_handler(claims, State(state), tier, headers, Path(pair_id), params).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/indicators/{symbol}",
+    tag = "Market Data",
+    params(
+        ("symbol" = String, Path, description = "Trading pair symbol, e.g. BTC-USD"),
+        ("interval" = Option<String>, Query, description = "Candle interval, as for candlesticks; 1h by default"),
+        ("window" = Option<u32>, Query, description = "Candles each indicator covers, 2 to 200; 14 by default"),
+        ("end_time" = Option<String>, Query, description = "Time of the last point (ISO 8601); now by default"),
+        ("limit" = Option<i32>, Query, description = "Points to return; 100 by default, capped like candlesticks")
+    ),
+    responses(
+        (status = 200, description = "VWAP, SMA, EMA and ATR per candle", body = Indicators),
+        (status = 400, description = "Unsupported interval or window, or a range further back than the caller's history allows", body = ErrorResponse),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+pub async fn get_indicators_by_symbol_handler(
+    claims: Option<Extension<Claims>>,
+    State(state): State<AppState>,
+    tier: Extension<MarketDataTier>,
+    headers: HeaderMap,
+    Path(symbol): Path<String>,
+    params: Query<IndicatorQuery>,
+) -> Result<Response> {
+    let pair_id = state.trading_pair_service.pair_id(&symbol).await?;
+    get_indicators_handler(claims, State(state), tier, headers, Path(pair_id), params).await
+}
+
+// Matching engine handlers
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/engine/replay/{pair_id}",
+    tag = "Matching Engine",
+    security(
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID"),
+        ("up_to_sequence" = Option<i64>, Query, description = "Stop replay after the command containing this sequence number")
+    ),
+    responses(
+        (status = 200, description = "Book rebuilt from the event log", body = ReplayReport),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn replay_order_book_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(pair_id): Path<Uuid>,
+    Query(params): Query<ReplayQuery>,
+) -> Result<Json<ReplayReport>> {
+    require_admin(&claims)?;
+
+    state.matching_service.replay(pair_id, params.up_to_sequence).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/health/engine",
+    tag = "Matching Engine",
+    params(
+        ("X-Tenant" = Option<String>, Header, description = "Slug of the white-label broker asking; adds its private pairs")
+    ),
+    responses(
+        (status = 200, description = "Last applied sequence number per pair", body = [EngineStatus])
+    )
+)]
+pub async fn engine_health_handler(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<Vec<EngineStatus>>> {
+    let tenant_id = request_tenant(&state, &headers).await?;
+    let hidden = state.tenant_service.hidden_pair_ids(tenant_id).await?;
+    let mut status = state.matching_service.status().await;
+    status.retain(|engine| !hidden.contains(&engine.trading_pair_id));
+    Ok(Json(status))
+}
+
+// Query parameter structs
+#[derive(Deserialize)]
+pub struct OrdersQuery {
+    pub status: Option<OrderStatus>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct OrderImportQuery {
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct WithdrawalQueueQuery {
+    pub status: Option<TransactionStatus>,
+}
+
+#[derive(Deserialize)]
+pub struct SupportQueueQuery {
+    pub status: Option<TicketStatus>,
+}
+
+#[derive(Deserialize)]
+pub struct AnalyticsQuery {
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+}
+
+#[derive(Deserialize)]
+pub struct TreasurySweepsQuery {
+    pub currency: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ComplianceReviewQuery {
+    pub status: Option<ComplianceReviewStatus>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub days: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct OrderBookQuery {
+    pub depth: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct BalancesQuery {
+    pub at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    pub from_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct MarketDataQuery {
+    #[serde(default)]
+    pub watchlist: bool,
+}
+
+#[derive(Deserialize)]
+pub struct TradesQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct StatementQuery {
+    pub currency: String,
+    pub start: Option<chrono::DateTime<chrono::Utc>>,
+    pub end: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub format: Option<StatementFormat>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+pub struct ReplayQuery {
+    pub up_to_sequence: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct CandlestickQuery {
+    pub interval: Option<String>,
+    pub start_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub end_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: Option<i32>,
+    pub fill: Option<CandleFill>,
+}
+
+#[derive(Deserialize)]
+pub struct IndicatorQuery {
+    pub interval: Option<String>,
+    pub window: Option<u32>,
+    pub end_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: Option<i32>,
+}
+
+// Response helpers
+/// Serializes `body` with a weak ETag and answers `304 Not Modified` when the
+/// client's `If-None-Match` already names it. The tag hashes the data with
+/// the `generated_at` field left out, so an unchanged book or ticker keeps
+/// its tag between polls; timestamps that are data, such as candle
+/// buckets, are always hashed.
+fn conditional_json<T: Serialize>(headers: &HeaderMap, body: &T, generated_at: Option<&str>) -> Response {
+    let value = match serde_json::to_value(body) {
+        Ok(value) => value,
+        Err(e) => return CryptoTradeError::from(e).into_response(),
+    };
+
+    let etag = format!("W/\"{:016x}\"", data_version(&value, generated_at));
+    let etag_header = HeaderValue::from_str(&etag).expect("ETag is ASCII");
+
+    let matches = headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/"));
+
+    if matches {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag_header)]).into_response();
+    }
+
+    ([(header::ETAG, etag_header)], Json(value)).into_response()
+}
+
+/// Streams export rows as newline-delimited JSON, one row per line, as
+/// they are read. A failure after the first row can no longer change the
+/// status, so it aborts the body and the client resumes with `from_id`.
+fn ndjson_response<T: Serialize + Send + 'static>(rows: ExportRows<T>, filename: &str) -> Response {
+    let lines = futures::stream::unfold(rows, |mut rows| async move {
+        let row = rows.recv().await?;
+        let line = row.and_then(|row| {
+            let mut line = serde_json::to_vec(&row)?;
+            line.push(b'\n');
+            Ok(line)
+        });
+        if let Err(e) = &line {
+            tracing::error!("Export aborted: {}", e);
+        }
+        Some((line, rows))
+    });
+
+    let disposition = format!("attachment; filename=\"{}\"", filename);
+    (
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson")),
+            (header::CONTENT_DISPOSITION, HeaderValue::from_str(&disposition).expect("filename is ASCII")),
+        ],
+        axum::body::Body::from_stream(lines),
+    )
+        .into_response()
+}
+
+// Sandbox handlers
+/// Cancels the caller's open orders and restores the sandbox starting
+/// balances. Only routed when the server runs in sandbox mode.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sandbox/reset",
+    tag = "Sandbox",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Balances after the reset", body = [Account]),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Sandbox mode is not enabled", body = ErrorResponse)
+    )
+)]
+pub async fn reset_sandbox_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Account>>> {
+    let user_id = parse_user_id(&claims)?;
+    let sandbox = state.sandbox_service.as_ref().ok_or(CryptoTradeError::NotFound {
+        message: "Sandbox mode is not enabled".to_string(),
+    })?;
+
+    sandbox.reset_user(user_id).await.map(Json)
+}
+
+// Admin handlers
+/// Sets or removes the band limit prices on a pair must fall within, as a
+/// percentage either side of its last traded price.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/trading-pairs/{pair_id}/price-band",
+    tag = "Admin",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    ),
+    request_body = PriceBandRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The updated trading pair", body = TradingPair),
+        (status = 400, description = "Band out of range", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse)
+    )
+)]
+pub async fn set_price_band_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(pair_id): Path<Uuid>,
+    Json(payload): Json<PriceBandRequest>,
+) -> Result<Json<TradingPair>> {
+    require_admin(&claims)?;
+
+    state
+        .trading_pair_service
+        .set_price_band(pair_id, payload.price_band_percent)
+        .await
+        .map(Json)
+}
+
+/// Moves a trading pair through its lifecycle: into or out of a call
+/// auction, halted, or delisted with its open orders cancelled.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/trading-pairs/{pair_id}/status",
+    tag = "Admin",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    ),
+    request_body = UpdateTradingPairStatusRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The pair after the move, with its opening price or cancelled orders", body = TradingPairTransition),
+        (status = 400, description = "The pair cannot move to that status", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse),
+        (status = 409, description = "The pair's status changed concurrently", body = ErrorResponse)
+    )
+)]
+pub async fn set_trading_pair_status_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(pair_id): Path<Uuid>,
+    Json(payload): Json<UpdateTradingPairStatusRequest>,
+) -> Result<Json<TradingPairTransition>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state
+        .trading_pair_service
+        .set_status(admin_id, pair_id, payload.status)
+        .await
+        .map(Json)
+}
+
+/// Replaces a pair's weekly trading sessions. Outside them the pair takes
+/// no orders and is halted, then reopened through a call auction when the
+/// next session starts; an empty list lets it trade around the clock.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/trading-pairs/{pair_id}/sessions",
+    tag = "Admin",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    ),
+    request_body = SetTradingSessionsRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The pair with its new sessions", body = TradingPairListing),
+        (status = 400, description = "Malformed or overlapping sessions", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse)
+    )
+)]
+pub async fn set_trading_sessions_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(pair_id): Path<Uuid>,
+    Json(payload): Json<SetTradingSessionsRequest>,
+) -> Result<Json<TradingPairListing>> {
+    require_admin(&claims)?;
+
+    state
+        .trading_pair_service
+        .set_sessions(pair_id, payload.sessions)
+        .await
+        .map(Json)
+}
+
+/// Overrides a user's open order count and notional limits; `null` fields
+/// restore the configured defaults.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/users/{user_id}/limits",
+    tag = "Admin",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID")
+    ),
+    request_body = UpdateUserLimitsRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The user's limits and usage", body = UserLimits),
+        (status = 400, description = "Negative limit", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse)
+    )
+)]
+pub async fn set_user_limits_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<UpdateUserLimitsRequest>,
+) -> Result<Json<UserLimits>> {
+    require_admin(&claims)?;
+
+    state.risk_limit_service.set_user_limits(user_id, payload).await.map(Json)
+}
+
+/// Restricts what a user may do from any session or API key; `null` lifts
+/// the restriction on permissions or pairs.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/users/{user_id}/permissions",
+    tag = "Admin",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID")
+    ),
+    request_body = PermissionScope,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The user's permissions", body = PermissionScope),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse)
+    )
+)]
+pub async fn set_user_permissions_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<PermissionScope>,
+) -> Result<Json<PermissionScope>> {
+    require_admin(&claims)?;
+
+    state.user_service.set_permissions(user_id, payload).await.map(Json)
+}
+
+/// The user's recent logins with their geo-velocity risk scores.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/users/{user_id}/login-risk",
+    tag = "Admin",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The account's risk score and the logins in the risk window, newest first", body = AccountLoginRisk),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn get_login_risk_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<AccountLoginRisk>> {
+    require_admin(&claims)?;
+
+    state.login_risk_service.account_risk(user_id).await.map(Json)
+}
+
+/// Moves a user to another KYC tier, which sets their deposit, withdrawal
+/// and open notional limits.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/users/{user_id}/kyc-tier",
+    tag = "Admin",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID")
+    ),
+    request_body = UpdateKycTierRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The user's profile with the new tier and its limits", body = UserProfile),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse)
+    )
+)]
+pub async fn set_kyc_tier_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<UpdateKycTierRequest>,
+) -> Result<Json<UserProfile>> {
+    require_admin(&claims)?;
+
+    state.user_service.set_kyc_tier(user_id, payload.kyc_tier).await.map(Json)
+}
+
+/// Restricts an account to winding down, reinstates it, or closes it.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/users/{user_id}/status",
+    tag = "Admin",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID")
+    ),
+    request_body = UpdateAccountStatusRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The recorded change", body = AccountStatusChange),
+        (status = 400, description = "Change not allowed from the current status", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse)
+    )
+)]
+pub async fn set_account_status_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<UpdateAccountStatusRequest>,
+) -> Result<Json<AccountStatusChange>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state.user_service.set_account_status(admin_id, user_id, payload).await.map(Json)
+}
+
+/// Reverses an erroneous trade: its settlement is undone in the ledger,
+/// both orders lose the fill, feed subscribers are told, and the bust is
+/// recorded in the audit log with its reason.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/trades/{trade_id}/bust",
+    tag = "Admin",
+    params(
+        ("trade_id" = Uuid, Path, description = "Trade ID")
+    ),
+    request_body = BustTradeRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The busted trade and the journal reversing it", body = TradeBust),
+        (status = 400, description = "No reason given, or the trade is already busted", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Trade not found", body = ErrorResponse)
+    )
+)]
+pub async fn bust_trade_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(trade_id): Path<Uuid>,
+    Json(payload): Json<BustTradeRequest>,
+) -> Result<Json<TradeBust>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state.trade_bust_service.bust(admin_id, trade_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/runtime-config",
+    tag = "Admin",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Runtime settings in force", body = RuntimeConfig),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn get_runtime_config_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<RuntimeConfig>> {
+    require_admin(&claims)?;
+
+    Ok(Json(state.runtime_config_service.config()))
+}
+
+/// Changes runtime settings on every instance without a restart. The body
+/// holds only the settings to change; `null` restores a setting's default.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/runtime-config",
+    tag = "Admin",
+    request_body(content = Object, description = "Settings to change, named as in RuntimeSettings"),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Runtime settings in force", body = RuntimeConfig),
+        (status = 400, description = "Unknown setting or invalid value", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn update_runtime_config_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<serde_json::Map<String, serde_json::Value>>,
+) -> Result<Json<RuntimeConfig>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state.runtime_config_service.update(admin_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/feature-flags",
+    tag = "Admin",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Every feature flag and its targeting", body = [FeatureFlag]),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn list_feature_flags_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<FeatureFlag>>> {
+    require_admin(&claims)?;
+
+    Ok(Json(state.feature_flag_service.list()))
+}
+
+/// Replaces a flag's targeting on every instance.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/feature-flags/{key}",
+    tag = "Admin",
+    params(
+        ("key" = String, Path, description = "Feature flag key")
+    ),
+    request_body = UpdateFeatureFlagRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The flag as now in force", body = FeatureFlag),
+        (status = 400, description = "Invalid targeting", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "No such feature flag", body = ErrorResponse)
+    )
+)]
+pub async fn update_feature_flag_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Json(payload): Json<UpdateFeatureFlagRequest>,
+) -> Result<Json<FeatureFlag>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state.feature_flag_service.update(admin_id, &key, payload).await.map(Json)
+}
+
+/// Returns a flag to its built-in default.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/feature-flags/{key}",
+    tag = "Admin",
+    params(
+        ("key" = String, Path, description = "Feature flag key")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The flag at its default", body = FeatureFlag),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "No such feature flag", body = ErrorResponse)
+    )
+)]
+pub async fn reset_feature_flag_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<FeatureFlag>> {
+    require_admin(&claims)?;
+
+    state.feature_flag_service.reset(&key).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/withdrawals",
+    tag = "Admin",
+    params(
+        ("status" = Option<TransactionStatus>, Query, description = "Only withdrawals in this status; pending and approved by default")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Fiat withdrawals, oldest first", body = [FiatWithdrawal]),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn list_withdrawal_queue_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<WithdrawalQueueQuery>,
+) -> Result<Json<Vec<FiatWithdrawal>>> {
+    require_admin(&claims)?;
+
+    state.fiat_withdrawal_service.review_queue(params.status).await.map(Json)
+}
+
+/// Approves or rejects a pending withdrawal, or records whether the
+/// transfer of an approved one went through. Above the dual approval
+/// threshold the first approval only initiates it; a different admin's
+/// approval confirms it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/withdrawals/{withdrawal_id}/review",
+    tag = "Admin",
+    params(
+        ("withdrawal_id" = Uuid, Path, description = "Withdrawal ID")
+    ),
+    request_body = ReviewWithdrawalRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The withdrawal after review", body = FiatWithdrawal),
+        (status = 400, description = "Action not allowed in the withdrawal's status", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin, or initiated the approval they are confirming", body = ErrorResponse),
+        (status = 404, description = "Withdrawal not found", body = ErrorResponse)
+    )
+)]
+pub async fn review_withdrawal_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(withdrawal_id): Path<Uuid>,
+    Json(payload): Json<ReviewWithdrawalRequest>,
+) -> Result<Json<FiatWithdrawal>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state.fiat_withdrawal_service.review(admin_id, withdrawal_id, payload).await.map(Json)
+}
+
+/// Every admin action taken on a withdrawal, oldest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/withdrawals/{withdrawal_id}/reviews",
+    tag = "Admin",
+    params(
+        ("withdrawal_id" = Uuid, Path, description = "Withdrawal ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The withdrawal's audit trail", body = Vec<WithdrawalReview>),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn withdrawal_reviews_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(withdrawal_id): Path<Uuid>,
+) -> Result<Json<Vec<WithdrawalReview>>> {
+    require_admin(&claims)?;
+
+    state.fiat_withdrawal_service.review_history(withdrawal_id).await.map(Json)
+}
+
+/// Deposits to a shared address whose memo was missing or matched no
+/// user, oldest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/deposits/unmatched",
+    tag = "Admin",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Deposits awaiting an admin", body = Vec<UnmatchedDeposit>),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn list_unmatched_deposits_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<UnmatchedDeposit>>> {
+    require_admin(&claims)?;
+
+    state.crypto_transfer_service.unmatched_deposits().await.map(Json)
+}
+
+/// Credits an unmatched deposit to the user who sent it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/deposits/unmatched/{deposit_id}/credit",
+    tag = "Admin",
+    params(
+        ("deposit_id" = Uuid, Path, description = "Unmatched deposit ID")
+    ),
+    request_body = CreditUnmatchedDepositRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The credited deposit", body = UnmatchedDeposit),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Deposit already credited or not found, or user not found", body = ErrorResponse)
+    )
+)]
+pub async fn credit_unmatched_deposit_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(deposit_id): Path<Uuid>,
+    Json(payload): Json<CreditUnmatchedDepositRequest>,
+) -> Result<Json<UnmatchedDeposit>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state
+        .crypto_transfer_service
+        .credit_unmatched(admin_id, deposit_id, payload.user_id)
+        .await
+        .map(Json)
+}
+
+/// Tickets in one status, longest waiting first; by default the open ones,
+/// which are waiting on support.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/support/tickets",
+    tag = "Support",
+    params(
+        ("status" = Option<TicketStatus>, Query, description = "Ticket status (default open)")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The support queue", body = [SupportTicket]),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn support_queue_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(query): Query<SupportQueueQuery>,
+) -> Result<Json<Vec<SupportTicket>>> {
+    require_admin(&claims)?;
+
+    state.support_service.queue(query.status).await.map(Json)
+}
+
+/// A ticket with the account it is about.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/support/tickets/{ticket_id}",
+    tag = "Support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The ticket, its conversation and the account context", body = SupportTicketDetail),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Ticket not found", body = ErrorResponse)
+    )
+)]
+pub async fn admin_get_ticket_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(ticket_id): Path<Uuid>,
+) -> Result<Json<SupportTicketDetail>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state.support_service.ticket(TicketActor::Staff(admin_id), ticket_id).await.map(Json)
+}
+
+/// Answers a ticket, leaving it pending on the user.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/support/tickets/{ticket_id}/messages",
+    tag = "Support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID")
+    ),
+    request_body = TicketReplyRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The added message", body = TicketMessage),
+        (status = 400, description = "Empty or overlong message", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Ticket not found", body = ErrorResponse)
+    )
+)]
+pub async fn admin_reply_ticket_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(ticket_id): Path<Uuid>,
+    Json(payload): Json<TicketReplyRequest>,
+) -> Result<Json<TicketMessage>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state.support_service.reply(TicketActor::Staff(admin_id), ticket_id, payload).await.map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/support/tickets/{ticket_id}/status",
+    tag = "Support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID")
+    ),
+    request_body = TicketStatusRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The updated ticket", body = SupportTicket),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Ticket not found", body = ErrorResponse)
+    )
+)]
+pub async fn set_ticket_status_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(ticket_id): Path<Uuid>,
+    Json(payload): Json<TicketStatusRequest>,
+) -> Result<Json<SupportTicket>> {
+    require_admin(&claims)?;
+
+    state.support_service.set_status(ticket_id, payload.status).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/support/tickets/{ticket_id}/attachments",
+    tag = "Support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID")
+    ),
+    request_body(content = Vec<u8>, content_type = "multipart/form-data", description = "The file, as the `file` field"),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The stored attachment", body = TicketAttachment),
+        (status = 400, description = "Missing file, unsupported type, too large, or too many attachments", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Ticket not found", body = ErrorResponse),
+        (status = 413, description = "File larger than the upload limit", body = ErrorResponse)
+    )
+)]
+pub async fn admin_upload_ticket_attachment_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(ticket_id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<Json<TicketAttachment>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+    let (file_name, body) = multipart_file(multipart, state.limits_config.upload_limit_bytes).await?;
+
+    state
+        .support_service
+        .attach(TicketActor::Staff(admin_id), ticket_id, &file_name, body)
+        .await
+        .map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/support/tickets/{ticket_id}/attachments/{attachment_id}",
+    tag = "Support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID"),
+        ("attachment_id" = Uuid, Path, description = "Attachment ID")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 307, description = "Redirect to a presigned URL of the file"),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Ticket or attachment not found", body = ErrorResponse)
+    )
+)]
+pub async fn admin_download_ticket_attachment_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path((ticket_id, attachment_id)): Path<(Uuid, Uuid)>,
+) -> Result<Response> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    let url = state
+        .support_service
+        .attachment_url(TicketActor::Staff(admin_id), ticket_id, attachment_id)
+        .await?;
+    redirect_to(&url.url)
+}
+
+/// Bank accounts whose micro-deposits still have to be sent.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/bank-accounts/micro-deposits",
+    tag = "Admin",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Micro-deposits to send, oldest first", body = [PendingMicroDeposits]),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn list_pending_micro_deposits_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<PendingMicroDeposits>>> {
+    require_admin(&claims)?;
+
+    state.fiat_withdrawal_service.pending_micro_deposits().await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/compliance/reviews",
+    tag = "Admin",
+    params(
+        ("status" = Option<ComplianceReviewStatus>, Query, description = "Only reviews in this status; all by default")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Possible sanctions list matches, oldest first", body = [ComplianceReview]),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn list_compliance_reviews_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<ComplianceReviewQuery>,
+) -> Result<Json<Vec<ComplianceReview>>> {
+    require_admin(&claims)?;
+
+    state.compliance_service.list_reviews(params.status).await.map(Json)
 }
 
-// Query parameter structs
-#[derive(Deserialize)]
-pub struct OrdersQuery {
-    pub status: Option<OrderStatus>,
-    pub limit: Option<i64>,
+/// Clears a possible sanctions list match as a false positive or confirms
+/// it. Clearing a user's last open match lifts the restriction screening
+/// put on the account.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/compliance/reviews/{review_id}/resolve",
+    tag = "Admin",
+    params(
+        ("review_id" = Uuid, Path, description = "Compliance review ID")
+    ),
+    request_body = ResolveComplianceReviewRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The resolved review", body = ComplianceReview),
+        (status = 400, description = "Review already resolved or decision missing", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Review not found", body = ErrorResponse)
+    )
+)]
+pub async fn resolve_compliance_review_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(review_id): Path<Uuid>,
+    Json(payload): Json<ResolveComplianceReviewRequest>,
+) -> Result<Json<ComplianceReview>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state.compliance_service.resolve_review(admin_id, review_id, payload).await.map(Json)
 }
 
-#[derive(Deserialize)]
-pub struct HistoryQuery {
-    pub days: Option<i32>,
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/analytics/activity",
+    tag = "Admin",
+    params(
+        ("from" = Option<String>, Query, description = "First day, YYYY-MM-DD; 30 days before `to` by default"),
+        ("to" = Option<String>, Query, description = "Last day, inclusive; today by default")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Daily active traders and new registrations, oldest first", body = [DailyActivity]),
+        (status = 400, description = "Invalid or too long a range", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn get_activity_analytics_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<DailyActivity>>> {
+    require_admin(&claims)?;
+
+    state.analytics_service.activity(params.from, params.to).await.map(Json)
 }
 
-#[derive(Deserialize)]
-pub struct TradesQuery {
-    pub limit: Option<i64>,
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/analytics/volume",
+    tag = "Admin",
+    params(
+        ("from" = Option<String>, Query, description = "First day, YYYY-MM-DD; 30 days before `to` by default"),
+        ("to" = Option<String>, Query, description = "Last day, inclusive; today by default")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Traded volume per pair and day", body = [DailyPairVolume]),
+        (status = 400, description = "Invalid or too long a range", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn get_volume_analytics_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<DailyPairVolume>>> {
+    require_admin(&claims)?;
+
+    state.analytics_service.pair_volumes(params.from, params.to).await.map(Json)
 }
 
-#[derive(Deserialize)]
-pub struct CandlestickQuery {
-    pub interval: Option<String>,
-    pub start_time: Option<chrono::DateTime<chrono::Utc>>,
-    pub end_time: Option<chrono::DateTime<chrono::Utc>>,
-    pub limit: Option<i32>,
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/analytics/fees",
+    tag = "Admin",
+    params(
+        ("from" = Option<String>, Query, description = "First day, YYYY-MM-DD; 30 days before `to` by default"),
+        ("to" = Option<String>, Query, description = "Last day, inclusive; today by default")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Fees collected per currency and day", body = [DailyFeeRevenue]),
+        (status = 400, description = "Invalid or too long a range", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn get_fee_revenue_analytics_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<DailyFeeRevenue>>> {
+    require_admin(&claims)?;
+
+    state.analytics_service.fee_revenue(params.from, params.to).await.map(Json)
 }
 
-// Error handling
-fn handle_error(error: CryptoTradeError) -> (StatusCode, Json<ErrorResponse>) {
-    let status_code = StatusCode::from_u16(error.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-    let error_response = ErrorResponse {
-        error: error.to_string(),
-        code: error.error_code().to_string(),
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/analytics/flows",
+    tag = "Admin",
+    params(
+        ("from" = Option<String>, Query, description = "First day, YYYY-MM-DD; 30 days before `to` by default"),
+        ("to" = Option<String>, Query, description = "Last day, inclusive; today by default")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Deposits and withdrawals per currency and day", body = [DailyFlow]),
+        (status = 400, description = "Invalid or too long a range", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn get_flows_analytics_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<DailyFlow>>> {
+    require_admin(&claims)?;
+
+    state.analytics_service.flows(params.from, params.to).await.map(Json)
+}
+
+/// Recomputes the dashboard rollups for a range of days, e.g. after
+/// correcting trades or ledger entries. The background task only keeps the
+/// last two days current.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/analytics/rollup",
+    tag = "Admin",
+    request_body = RollupRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The days were rolled up", body = SuccessResponse),
+        (status = 400, description = "Invalid or too long a range", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn rollup_analytics_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<RollupRequest>,
+) -> Result<Json<SuccessResponse>> {
+    require_admin(&claims)?;
+
+    let days = state
+        .analytics_service
+        .rollup(payload.from, payload.to.unwrap_or(payload.from))
+        .await?;
+    Ok(Json(SuccessResponse {
+        message: format!("Rolled up {} day(s)", days),
+    }))
+}
+
+/// Fees collected into the treasury, per currency.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/treasury",
+    tag = "Admin",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Treasury accounts with their balances and totals", body = [TreasuryAccount]),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn list_treasury_accounts_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TreasuryAccount>>> {
+    require_admin(&claims)?;
+
+    state.treasury_service.accounts().await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/treasury/sweeps",
+    tag = "Admin",
+    params(
+        ("currency" = Option<String>, Query, description = "Only sweeps of this currency")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Sweeps, newest first", body = [TreasurySweep]),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn list_treasury_sweeps_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<TreasurySweepsQuery>,
+) -> Result<Json<Vec<TreasurySweep>>> {
+    require_admin(&claims)?;
+
+    let currency = params.currency.map(|currency| currency.to_uppercase());
+    state.treasury_service.sweeps(currency.as_deref()).await.map(Json)
+}
+
+/// Pays collected fees out of the treasury to the exchange's operating funds.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/treasury/{currency}/sweep",
+    tag = "Admin",
+    params(
+        ("currency" = String, Path, description = "Currency to sweep")
+    ),
+    request_body = SweepTreasuryRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The recorded sweep", body = TreasurySweep),
+        (status = 400, description = "Invalid amount or more than the treasury holds", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "No fees have been collected in the currency", body = ErrorResponse)
+    )
+)]
+pub async fn sweep_treasury_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(currency): Path<String>,
+    Json(payload): Json<SweepTreasuryRequest>,
+) -> Result<Json<TreasurySweep>> {
+    require_admin(&claims)?;
+    let admin_id = parse_user_id(&claims)?;
+
+    state.treasury_service.sweep(admin_id, &currency, payload).await.map(Json)
+}
+
+/// Lists a new currency. Trading pairs, deposits and trades can use it at
+/// once; with `open_by_default`, users registering from now on get an
+/// account in it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/currencies",
+    tag = "Admin",
+    request_body = CreateCurrencyRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The listed currency", body = Currency),
+        (status = 400, description = "Invalid or already listed", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn create_currency_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateCurrencyRequest>,
+) -> Result<Json<Currency>> {
+    require_admin(&claims)?;
+
+    state.currency_service.create_currency(payload).await.map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/currencies/{code}",
+    tag = "Admin",
+    params(
+        ("code" = String, Path, description = "Currency code, e.g. BTC")
+    ),
+    request_body = UpdateCurrencyRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The updated currency", body = Currency),
+        (status = 400, description = "Invalid change", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Currency not listed", body = ErrorResponse)
+    )
+)]
+pub async fn update_currency_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Json(payload): Json<UpdateCurrencyRequest>,
+) -> Result<Json<Currency>> {
+    require_admin(&claims)?;
+
+    state.currency_service.update_currency(&code, payload).await.map(Json)
+}
+
+/// A white-label broker's name, fee schedule and branding, for its front end.
+#[utoipa::path(
+    get,
+    path = "/api/v1/tenants/{slug}",
+    tag = "Tenants",
+    params(
+        ("slug" = String, Path, description = "Tenant slug")
+    ),
+    responses(
+        (status = 200, description = "The tenant", body = Tenant),
+        (status = 404, description = "Tenant not found", body = ErrorResponse)
+    )
+)]
+pub async fn get_tenant_handler(State(state): State<AppState>, Path(slug): Path<String>) -> Result<Json<Tenant>> {
+    state.tenant_service.get_by_slug(&slug).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/tenants",
+    tag = "Admin",
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "Every tenant, by slug", body = [Tenant]),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn list_tenants_handler(Extension(claims): Extension<Claims>, State(state): State<AppState>) -> Result<Json<Vec<Tenant>>> {
+    require_admin(&claims)?;
+
+    state.tenant_service.list_tenants().await.map(Json)
+}
+
+/// Onboards a white-label broker. Users registering with its slug belong
+/// to it from then on.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/tenants",
+    tag = "Admin",
+    request_body = CreateTenantRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The new tenant", body = Tenant),
+        (status = 400, description = "Invalid, or the slug or API key prefix is taken", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse)
+    )
+)]
+pub async fn create_tenant_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateTenantRequest>,
+) -> Result<Json<Tenant>> {
+    require_admin(&claims)?;
+
+    state.tenant_service.create_tenant(payload).await.map(Json)
+}
+
+/// Replaces a tenant's name, fee schedule and branding. New fees apply to
+/// orders placed from then on.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/tenants/{tenant_id}",
+    tag = "Admin",
+    params(
+        ("tenant_id" = Uuid, Path, description = "Tenant ID")
+    ),
+    request_body = UpdateTenantRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The updated tenant", body = Tenant),
+        (status = 400, description = "Invalid fees or branding", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Tenant not found", body = ErrorResponse)
+    )
+)]
+pub async fn update_tenant_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(tenant_id): Path<Uuid>,
+    Json(payload): Json<UpdateTenantRequest>,
+) -> Result<Json<Tenant>> {
+    require_admin(&claims)?;
+
+    state.tenant_service.update_tenant(tenant_id, payload).await.map(Json)
+}
+
+/// Makes a pair private to one tenant, or shares it with everyone again.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/trading-pairs/{pair_id}/tenant",
+    tag = "Admin",
+    params(
+        ("pair_id" = Uuid, Path, description = "Trading pair ID")
+    ),
+    request_body = PairTenantRequest,
+    security(
+        ("bearer_auth" = [])
+    ),
+    responses(
+        (status = 200, description = "The updated trading pair", body = TradingPair),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "Trading pair or tenant not found", body = ErrorResponse)
+    )
+)]
+pub async fn set_pair_tenant_handler(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(pair_id): Path<Uuid>,
+    Json(payload): Json<PairTenantRequest>,
+) -> Result<Json<TradingPair>> {
+    require_admin(&claims)?;
+
+    state.tenant_service.set_pair_tenant(pair_id, payload.tenant_id).await.map(Json)
+}
+
+/// The tenant a public request names in `X-Tenant`, if any.
+pub(crate) async fn request_tenant(state: &AppState, headers: &HeaderMap) -> Result<Option<Uuid>> {
+    let Some(slug) = headers.get(TENANT_HEADER).and_then(|value| value.to_str().ok()) else {
+        return Ok(None);
     };
-    (status_code, Json(error_response))
+    Ok(Some(state.tenant_service.get_by_slug(slug).await?.id))
+}
+
+/// The tenant whose private pairs the caller sees: a signed-in user's own,
+/// otherwise the broker named in `X-Tenant`, as for the pair listing.
+async fn viewer_tenant(state: &AppState, claims: Option<&Claims>, headers: &HeaderMap) -> Result<Option<Uuid>> {
+    match claims {
+        Some(claims) => state.tenant_service.user_tenant_id(parse_user_id(claims)?).await,
+        None => request_tenant(state, headers).await,
+    }
+}
+
+/// Answers as if the pair did not exist when it is private to a tenant the
+/// caller does not see.
+async fn ensure_pair_visible(state: &AppState, claims: Option<&Claims>, headers: &HeaderMap, pair_id: Uuid) -> Result<()> {
+    let tenant_id = viewer_tenant(state, claims, headers).await?;
+    state.tenant_service.ensure_pair_visible_to(pair_id, tenant_id).await
+}
+
+/// Hashes `value` without its `generated_at` field, which is looked for on
+/// the body itself or on each item of a list body and nowhere deeper.
+fn data_version(value: &serde_json::Value, generated_at: Option<&str>) -> u64 {
+    fn visit(item: &serde_json::Value, generated_at: Option<&str>, hasher: &mut std::collections::hash_map::DefaultHasher) {
+        match item {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter().filter(|(key, _)| Some(key.as_str()) != generated_at) {
+                    key.hash(hasher);
+                    value.to_string().hash(hasher);
+                }
+            }
+            other => other.to_string().hash(hasher),
+        }
+    }
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    match value {
+        serde_json::Value::Array(items) => {
+            items.len().hash(&mut hasher);
+            items.iter().for_each(|item| visit(item, generated_at, &mut hasher));
+        }
+        other => visit(other, generated_at, &mut hasher),
+    }
+    hasher.finish()
+}
+
+// Error handling
+pub(crate) fn parse_user_id(claims: &Claims) -> Result<Uuid> {
+    claims.sub.parse::<Uuid>().map_err(|_| CryptoTradeError::InvalidUserId)
+}
+
+fn require_admin(claims: &Claims) -> Result<()> {
+    if claims.role != ADMIN_ROLE {
+        return Err(CryptoTradeError::Authorization {
+            message: "Admin role required".to_string(),
+        });
+    }
+    Ok(())
 }
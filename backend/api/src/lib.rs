@@ -1,20 +1,269 @@
+pub mod asyncapi;
 pub mod auth;
+pub mod graphql;
 pub mod handlers;
 pub mod middleware;
+pub mod router;
+pub mod sse;
 pub mod websocket;
 pub mod openapi;
 
 use cryptotrade_core::{
     UserService, OrderService, TradingService,
-    MarketDataService, PortfolioService, AuthService,
+    MarketDataService, PortfolioService, AuthService, LedgerService,
+    LimitsConfig, MatchingService, PaymentService, PoolMetrics, ServerConfig, StreamService, WebSocketConfig,
+    AlertService, AlgoOrderService, AnalyticsService, CaptchaService, CircuitBreaker, ComplianceService, CopyTradingService, Config, CryptoTransferService, CurrencyService, Database, DeprecatedRouteUsage, DeviceService, LoginRiskService, ApiKeyService, ExchangeInfoService, ExportService, DocumentService, DustService, EventLogService, FeatureFlagService, FiatWithdrawalService, GridBotService, OrderImportService, RateLimitService, RebalanceService, RiskLimitService, RiskService, RuntimeConfigService, RuntimeSettings,
+    SandboxService, SupportService, TenantService, TradeBustService, TradingPairService, TreasuryService, WatchlistService,
 };
+use cryptotrade_core::compliance::SanctionsList;
+use cryptotrade_core::email;
+use cryptotrade_core::signer::{self, TransactionSigner};
+use cryptotrade_core::storage::{self, ObjectStore};
+use redis::aio::ConnectionManager;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+
+pub use router::create_router;
 
 #[derive(Clone)]
 pub struct AppState {
     pub user_service: UserService,
+    pub device_service: DeviceService,
+    pub login_risk_service: LoginRiskService,
+    pub captcha_service: CaptchaService,
+    pub compliance_service: ComplianceService,
+    pub api_key_service: ApiKeyService,
+    pub rate_limit_service: RateLimitService,
     pub order_service: OrderService,
     pub trading_service: TradingService,
+    pub trade_bust_service: TradeBustService,
+    pub trading_pair_service: TradingPairService,
+    pub tenant_service: TenantService,
+    pub exchange_info_service: ExchangeInfoService,
+    pub currency_service: CurrencyService,
+    pub watchlist_service: WatchlistService,
+    pub risk_limit_service: RiskLimitService,
+    pub risk_service: RiskService,
+    pub runtime_config_service: RuntimeConfigService,
+    pub feature_flag_service: FeatureFlagService,
     pub market_data_service: MarketDataService,
     pub portfolio_service: PortfolioService,
+    pub ledger_service: LedgerService,
+    pub export_service: ExportService,
+    pub payment_service: PaymentService,
+    pub fiat_withdrawal_service: FiatWithdrawalService,
+    pub crypto_transfer_service: CryptoTransferService,
+    pub document_service: DocumentService,
+    pub support_service: SupportService,
+    /// Generated documents, export files and the ledger archive
+    pub storage: Arc<dyn ObjectStore>,
+    /// Signs hot wallet withdrawals
+    pub signer: Arc<dyn TransactionSigner>,
+    pub analytics_service: AnalyticsService,
+    pub treasury_service: TreasuryService,
+    pub dust_service: DustService,
+    pub algo_order_service: AlgoOrderService,
+    pub grid_bot_service: GridBotService,
+    pub alert_service: AlertService,
+    pub order_import_service: OrderImportService,
+    pub rebalance_service: RebalanceService,
+    pub copy_trading_service: CopyTradingService,
+    pub matching_service: MatchingService,
+    pub stream_service: StreamService,
     pub auth_service: AuthService,
+    pub websocket_config: WebSocketConfig,
+    pub server_config: ServerConfig,
+    pub limits_config: LimitsConfig,
+    /// One permit per export allowed to run at once
+    pub export_permits: Arc<Semaphore>,
+    pub pool_metrics: PoolMetrics,
+    pub deprecated_routes: DeprecatedRouteUsage,
+    /// Shared by every service calling Redis
+    pub redis_breaker: CircuitBreaker,
+    /// Guards the outbox relay's NATS publisher, which the server builds
+    /// itself when the relay is enabled
+    pub nats_breaker: CircuitBreaker,
+    /// Present only when the server runs in sandbox mode
+    pub sandbox_service: Option<SandboxService>,
+}
+
+impl AppState {
+    /// Wires up every service over an already migrated database and recovers
+    /// the matching engine's books. Background tasks (snapshots, partition
+    /// maintenance, the pool probe, the runtime settings and feature flag
+    /// listeners) are left to the caller.
+    pub async fn new(config: &Config, db: Database, redis: ConnectionManager) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !(config.sandbox.enabled && config.app.environment == "production"),
+            "sandbox mode funds accounts with play money and must not run in production"
+        );
+
+        let auth_service = AuthService::from_config(&config.jwt)?;
+
+        let storage = storage::object_store(&config.storage, &config.jwt.secret)?;
+        let presign_ttl = Duration::from_secs(config.storage.presign_ttl_seconds);
+        let trading_service = TradingService::new(db.clone());
+        let redis_breaker = CircuitBreaker::new("redis", &config.resilience);
+        let stream_service = StreamService::new(redis.clone(), redis_breaker.clone(), &config.websocket);
+        let matching_service = MatchingService::new(
+            db.clone(),
+            EventLogService::new(db.clone()),
+            stream_service.clone(),
+            &config.engine,
+        );
+        matching_service.recover().await?;
+        tracing::info!("Recovered matching engine state");
+
+        let runtime_config_service =
+            RuntimeConfigService::load(db.clone(), RuntimeSettings::from_config(config)).await?;
+        let feature_flag_service = FeatureFlagService::load(db.clone(), config.app.environment.clone()).await?;
+        let risk_limit_service = RiskLimitService::new(db.clone(), config.trading.clone(), config.kyc_tiers.clone());
+        let risk_service = RiskService::new(
+            db.clone(),
+            risk_limit_service.clone(),
+            runtime_config_service.clone(),
+            config.risk.clone(),
+        );
+        let order_service = OrderService::new(
+            db.clone(),
+            matching_service.clone(),
+            trading_service.clone(),
+            risk_service.clone(),
+            runtime_config_service.clone(),
+            config.trading.max_schedule_days,
+        );
+        let ledger_service = LedgerService::new(db.clone());
+        let sandbox_service = config.sandbox.enabled.then(|| {
+            SandboxService::new(db.clone(), ledger_service.clone(), order_service.clone(), config.sandbox.clone())
+        });
+
+        let payment_service = PaymentService::new(
+            db.clone(),
+            ledger_service.clone(),
+            risk_limit_service.clone(),
+            config.payments.clone(),
+        );
+        let fiat_withdrawal_service =
+            FiatWithdrawalService::new(
+                db.clone(),
+                ledger_service.clone(),
+                risk_limit_service.clone(),
+                config.payments.clone(),
+                config.compliance.travel_rule.clone(),
+            );
+        let crypto_transfer_service = CryptoTransferService::new(
+            db.clone(),
+            ledger_service.clone(),
+            risk_limit_service.clone(),
+            &config.blockchain,
+        );
+        let treasury_service = TreasuryService::new(db.clone(), ledger_service.clone());
+        let dust_service = DustService::new(db.clone(), ledger_service.clone(), config.trading.dust.clone());
+        let algo_order_service = AlgoOrderService::new(db.clone(), order_service.clone(), config.trading.algo.clone());
+        let grid_bot_service = GridBotService::new(db.clone(), order_service.clone(), config.trading.grid.clone());
+        let alert_service = AlertService::new(db.clone(), order_service.clone());
+        let order_import_service = OrderImportService::new(db.clone(), order_service.clone(), config.trading.order_import.clone());
+        let rebalance_service = RebalanceService::new(db.clone(), order_service.clone(), config.trading.rebalance.clone());
+        let copy_trading_service = CopyTradingService::new(
+            db.clone(),
+            ledger_service.clone(),
+            order_service.clone(),
+            config.trading.copy.clone(),
+        );
+
+        let trading_pair_service = TradingPairService::new(db.clone(), order_service.clone());
+        let exchange_info_service = ExchangeInfoService::new(
+            trading_pair_service.clone(),
+            runtime_config_service.clone(),
+            feature_flag_service.clone(),
+            config,
+        );
+
+        let device_service = DeviceService::new(db.clone(), email::sender(&config.email), config.devices.clone());
+        let login_risk_service =
+            LoginRiskService::new(db.clone(), email::sender(&config.email), config.devices.login_risk.clone());
+        let user_service = UserService::new(
+            db.clone(),
+            auth_service.clone(),
+            device_service.clone(),
+            login_risk_service.clone(),
+            config.kyc_tiers.clone(),
+        );
+        let mut compliance_service = ComplianceService::new(db.clone(), user_service.clone(), config.compliance.clone());
+        if config.compliance.sanctions.enabled {
+            let list = SanctionsList::load(&config.compliance.sanctions).map_err(|e| {
+                anyhow::anyhow!("cannot load sanctions list {}: {}", config.compliance.sanctions.list_path, e)
+            })?;
+            tracing::info!("Screening against {} sanctioned names", list.len());
+            compliance_service = compliance_service.with_screener(Arc::new(list));
+        }
+
+        let signer = signer::signer(&config.blockchain).await?;
+
+        Ok(Self {
+            user_service,
+            device_service,
+            login_risk_service,
+            captcha_service: CaptchaService::new(db.clone(), config.captcha.clone()),
+            compliance_service,
+            rate_limit_service: RateLimitService::new(
+                db.clone(),
+                redis.clone(),
+                redis_breaker.clone(),
+                config.market_data.clone(),
+            ),
+            api_key_service: ApiKeyService::new(
+                db.clone(),
+                redis,
+                redis_breaker.clone(),
+                auth_service.clone(),
+                config.api_keys.clone(),
+            ),
+            order_service,
+            trading_service,
+            trade_bust_service: TradeBustService::new(db.clone(), stream_service.clone()),
+            trading_pair_service,
+            tenant_service: TenantService::new(db.clone()),
+            exchange_info_service,
+            currency_service: CurrencyService::new(db.clone()),
+            watchlist_service: WatchlistService::new(db.clone()),
+            risk_limit_service,
+            risk_service,
+            runtime_config_service,
+            feature_flag_service,
+            market_data_service: MarketDataService::new(db.clone())
+                .with_tickers_cache(Duration::from_secs(config.market_data.tickers_cache_seconds)),
+            portfolio_service: PortfolioService::new(db.clone()),
+            ledger_service,
+            export_service: ExportService::new(db.clone(), storage.clone(), presign_ttl),
+            payment_service,
+            fiat_withdrawal_service,
+            crypto_transfer_service,
+            document_service: DocumentService::new(db.clone(), config.app.name.clone(), storage.clone(), presign_ttl),
+            support_service: SupportService::new(db.clone(), storage.clone(), presign_ttl, config.support.clone()),
+            storage,
+            signer,
+            analytics_service: AnalyticsService::new(db.clone()),
+            treasury_service,
+            dust_service,
+            algo_order_service,
+            grid_bot_service,
+            alert_service,
+            order_import_service,
+            rebalance_service,
+            copy_trading_service,
+            matching_service,
+            stream_service,
+            auth_service,
+            websocket_config: config.websocket.clone(),
+            server_config: config.server.clone(),
+            limits_config: config.limits.clone(),
+            export_permits: Arc::new(Semaphore::new(config.limits.export_concurrency)),
+            pool_metrics: PoolMetrics::new(db),
+            deprecated_routes: DeprecatedRouteUsage::new(),
+            redis_breaker,
+            nats_breaker: CircuitBreaker::new("nats", &config.resilience),
+            sandbox_service,
+        })
+    }
 }
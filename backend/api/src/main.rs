@@ -1,113 +1,328 @@
-use axum::{
-    response::Json,
-    routing::{delete, get, post},
-    Router,
-};
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
-
-use cryptotrade_api::handlers::*;
-use cryptotrade_api::middleware::auth_middleware;
-use cryptotrade_api::websocket;
-use cryptotrade_api::AppState;
-use cryptotrade_core::{
-    database, AuthService, Config, MarketDataService, OrderService,
-    PortfolioService, TradingService, UserService,
-};
-
-use utoipa::OpenApi;
-use utoipa_swagger_ui::SwaggerUi;
-
-mod openapi;
-mod handlers;
-use openapi::ApiDoc;
-
+use cryptotrade_api::{create_router, AppState};
+use cryptotrade_core::{database, AlgoOrderService, AnalyticsService, Config, CopyTradingService, DocumentService, GridBotService, LedgerCompactionService, MatchingService, NatsPublisher, OutboxService, OrderImportService, OrderService, PartitionService, SandboxService, TradingPairService};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
 
     tracing_subscriber::fmt()
-        .with_env_filter("cryptotrade_api=debug,tower_http=debug")
+        .with_env_filter("cryptotrade_api=debug,tower_http=debug,sqlx::query=warn")
         .init();
 
-    let config = Config::from_env()?;
+    let mut config = Config::from_env()?;
+    config.load_secrets().await?;
+    config.validate()?;
     tracing::info!("Loaded configuration for environment: {}", config.app.environment);
 
     let db = database::connect(&config.database).await?;
     tracing::info!("Connected to database");
 
-    let auth_service = AuthService::new(
-        config.jwt.secret.clone(),
-        config.jwt.expiration_seconds,
-    );
+    let redis = database::connect_redis(&config.redis).await?;
+    tracing::info!("Connected to Redis");
 
-    let app_state = AppState {
-        user_service: UserService::new(db.clone(), auth_service.clone()),
-        order_service: OrderService::new(db.clone()),
-        trading_service: TradingService::new(db.clone()),
-        market_data_service: MarketDataService::new(db.clone()),
-        portfolio_service: PortfolioService::new(db.clone()),
-        auth_service,
-    };
+    let app_state = AppState::new(&config, db.clone(), redis).await?;
+    config.forget_private_key();
+    app_state
+        .pool_metrics
+        .spawn_probe(std::time::Duration::from_secs(config.database.pool_probe_interval_seconds));
+    app_state.runtime_config_service.spawn_listener();
+    app_state.feature_flag_service.spawn_listener();
+    spawn_snapshot_task(app_state.matching_service.clone(), &config);
+    spawn_partition_task(PartitionService::new(db.clone()), &config);
+    if config.ledger_compaction.enabled {
+        let compaction =
+            LedgerCompactionService::new(db.clone(), app_state.storage.clone(), config.ledger_compaction.batch_size);
+        spawn_ledger_compaction_task(compaction, &config);
+    }
+    if config.outbox.enabled {
+        // Events wait in the outbox while NATS is unreachable
+        let nats = async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect(&config.nats.url)
+            .await?;
+        let outbox = OutboxService::new(db, config.outbox.subject_prefix.clone());
+        spawn_outbox_task(outbox, NatsPublisher::new(nats, app_state.nats_breaker.clone()), &config);
+    }
+    spawn_document_task(app_state.document_service.clone(), &config);
+    spawn_analytics_task(app_state.analytics_service.clone(), &config);
+    spawn_scheduled_order_task(app_state.order_service.clone(), &config);
+    spawn_trading_session_task(app_state.trading_pair_service.clone(), &config);
+    spawn_algo_order_task(app_state.algo_order_service.clone(), &config);
+    spawn_grid_bot_task(app_state.grid_bot_service.clone(), &config);
+    spawn_order_import_task(app_state.order_import_service.clone(), &config);
+    spawn_copy_trading_task(app_state.copy_trading_service.clone(), &config);
+    if let Some(sandbox_service) = app_state.sandbox_service.clone() {
+        tracing::warn!("Running in sandbox mode: balances are simulated");
+        spawn_sandbox_task(sandbox_service, &config);
+    }
 
-    let app = create_router(app_state);
+    let app = create_router(app_state, &config);
 
     let addr = std::net::SocketAddr::from(([0, 0, 0, 0], config.server.port));
     tracing::info!("Starting server on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    // Peer addresses feed the API key IP allowlists
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await?;
 
     Ok(())
 }
 
-fn create_router(state: AppState) -> Router {
-    // Public routes (no auth middleware)
-    let public = Router::new()
-        .route("/api/v1/health", get(health_handler))
-        .route("/api/v1/auth/register", post(register_handler))
-        .route("/api/v1/auth/login", post(login_handler))
-        .route("/api/v1/auth/refresh", post(refresh_token_handler))
-        .merge(
-            SwaggerUi::new("/docs")
-                .url("/api-doc/openapi.json", openapi::ApiDoc::openapi()),
-        );
-
-    // Protected routes (with auth middleware)
-    let protected = Router::new()
-        .route("/api/v1/market-data", get(get_all_market_data_handler))
-        .route("/api/v1/market-data/:pair_id", get(get_market_data_handler))
-        .route("/api/v1/order-book/:pair_id", get(get_order_book_handler))
-        .route("/api/v1/trades/:pair_id", get(get_recent_trades_handler))
-        .route("/api/v1/candlesticks/:pair_id", get(get_candlestick_data_handler))
-        .route("/api/v1/user/profile", get(get_user_profile_handler))
-        .route("/api/v1/user/accounts", get(get_user_accounts_handler))
-        .route("/api/v1/user/2fa/enable", post(enable_2fa_handler))
-        .route("/api/v1/user/2fa/confirm", post(confirm_2fa_handler))
-        .route("/api/v1/user/2fa/disable", post(disable_2fa_handler))
-        .route("/api/v1/orders", post(create_order_handler))
-        .route("/api/v1/orders", get(get_user_orders_handler))
-        .route("/api/v1/orders/:order_id", delete(cancel_order_handler))
-        .route("/api/v1/portfolio", get(get_portfolio_handler))
-        .route("/api/v1/portfolio/history", get(get_portfolio_history_handler))
-        .route("/api/v1/trades", get(get_user_trades_handler))
-        .route("/ws", get(crate::websocket::websocket_handler))
-        .layer(axum::middleware::from_fn_with_state(
-            state.clone(),
-            auth_middleware,
-        ));
-
-    public
-        .merge(protected)
-        .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http())
-        .with_state(state)
+fn spawn_snapshot_task(matching_service: MatchingService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.engine.snapshot_interval_seconds);
+    let retained = config.engine.snapshots_retained;
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match matching_service.snapshot_books(retained).await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!("Saved {} order book snapshot(s)", count),
+                Err(e) => tracing::error!("Order book snapshot failed: {}", e),
+            }
+        }
+    });
+}
+
+fn spawn_partition_task(partition_service: PartitionService, config: &Config) {
+    if !config.partitioning.enabled {
+        return;
+    }
+    let partitioning = config.partitioning.clone();
+    let interval = std::time::Duration::from_secs(partitioning.maintenance_interval_seconds);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match partition_service.run_maintenance(&partitioning).await {
+                Ok(report) => {
+                    for partition in &report.archived {
+                        tracing::info!("Archived partition {}", partition);
+                    }
+                    for partition in &report.dropped {
+                        tracing::info!("Dropped partition {}", partition);
+                    }
+                }
+                Err(e) => tracing::error!("Partition maintenance failed: {}", e),
+            }
+        }
+    });
+}
+
+fn spawn_ledger_compaction_task(compaction_service: LedgerCompactionService, config: &Config) {
+    let retention_months = config.ledger_compaction.retention_months;
+    let interval = std::time::Duration::from_secs(config.ledger_compaction.interval_seconds);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match compaction_service.run(retention_months).await {
+                Ok(report) if report.periods.is_empty() => {}
+                Ok(report) => tracing::info!(
+                    "Ledger compaction archived {} entries from {} into {} checkpoint(s)",
+                    report.entries_archived,
+                    report.periods.join(", "),
+                    report.checkpoints
+                ),
+                Err(e) => tracing::error!("Ledger compaction failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Relays the outbox continuously, and prunes published events hourly.
+fn spawn_outbox_task(outbox_service: OutboxService, publisher: NatsPublisher, config: &Config) {
+    let interval = std::time::Duration::from_millis(config.outbox.relay_interval_ms);
+    let batch_size = config.outbox.batch_size;
+    let retention = chrono::Duration::hours(config.outbox.retention_hours as i64);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut pruned_at = std::time::Instant::now();
+        loop {
+            ticker.tick().await;
+            // A full batch means more are waiting
+            loop {
+                match outbox_service.relay(&publisher, batch_size).await {
+                    Ok(count) if count as i64 == batch_size => continue,
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Outbox relay failed: {}", e),
+                }
+                break;
+            }
+            if pruned_at.elapsed() >= std::time::Duration::from_secs(3600) {
+                pruned_at = std::time::Instant::now();
+                match outbox_service.prune(chrono::Utc::now() - retention).await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::debug!("Pruned {} published outbox event(s)", count),
+                    Err(e) => tracing::error!("Outbox pruning failed: {}", e),
+                }
+            }
+        }
+    });
+}
+
+/// Renders requested documents, and queues last month's statements once
+/// per month; queuing is idempotent, so every instance restarting does it.
+fn spawn_document_task(document_service: DocumentService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.documents.generation_interval_seconds);
+    let batch_size = config.documents.batch_size;
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut statements_queued_for = None;
+        loop {
+            ticker.tick().await;
+            let last_month = chrono::Utc::now().date_naive() - chrono::Months::new(1);
+            let month = last_month.format("%Y-%m").to_string();
+            if statements_queued_for.as_ref() != Some(&month) {
+                match document_service.enqueue_statements(last_month).await {
+                    Ok(count) => {
+                        tracing::info!("Queued {} statement(s) for {}", count, month);
+                        statements_queued_for = Some(month);
+                    }
+                    Err(e) => tracing::error!("Queuing statements failed: {}", e),
+                }
+            }
+            match document_service.generate_pending(batch_size).await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!("Generated {} document(s)", count),
+                Err(e) => tracing::error!("Document generation failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Keeps the dashboard rollups current: backfills recent days at startup,
+/// then reruns today and yesterday, so trades settling around midnight land
+/// in the right day.
+fn spawn_analytics_task(analytics_service: AnalyticsService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.analytics.rollup_interval_seconds);
+    let mut lookback = chrono::Days::new(config.analytics.backfill_days.into());
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let today = chrono::Utc::now().date_naive();
+            match analytics_service.rollup(today - lookback, today).await {
+                Ok(days) => {
+                    tracing::debug!("Rolled up analytics for {} day(s)", days);
+                    lookback = chrono::Days::new(1);
+                }
+                Err(e) => tracing::error!("Analytics rollup failed: {}", e),
+            }
+        }
+    });
+}
+
+fn spawn_scheduled_order_task(order_service: OrderService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.trading.scheduled_orders_interval_seconds);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match order_service.activate_due_orders().await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Placed {} scheduled order(s)", count),
+                Err(e) => tracing::error!("Placing scheduled orders failed: {}", e),
+            }
+        }
+    });
+}
+
+fn spawn_trading_session_task(trading_pair_service: TradingPairService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.trading.session_check_interval_seconds);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match trading_pair_service.apply_session_schedules().await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Moved {} trading pair(s) on their session schedule", count),
+                Err(e) => tracing::error!("Applying trading session schedules failed: {}", e),
+            }
+        }
+    });
+}
+
+fn spawn_sandbox_task(sandbox_service: SandboxService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.sandbox.tick_interval_seconds);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match sandbox_service.tick().await {
+                Ok(count) => tracing::debug!("Placed {} sandbox quote(s)", count),
+                Err(e) => tracing::error!("Sandbox market tick failed: {}", e),
+            }
+        }
+    });
+}
+
+fn spawn_algo_order_task(algo_order_service: AlgoOrderService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.trading.algo.tick_interval_seconds);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = algo_order_service.work_due().await {
+                tracing::error!("Working algo orders failed: {}", e);
+            }
+        }
+    });
+}
+
+fn spawn_grid_bot_task(grid_bot_service: GridBotService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.trading.grid.tick_interval_seconds);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = grid_bot_service.rebalance().await {
+                tracing::error!("Rebalancing grid bots failed: {}", e);
+            }
+        }
+    });
 }
 
-async fn health_handler() -> Json<serde_json::Value> {
-    Json(serde_json::json!({
-        "status": "healthy",
-        "timestamp": chrono::Utc::now(),
-        "version": "1.0.0"
-    }))
+fn spawn_order_import_task(order_import_service: OrderImportService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.trading.order_import.interval_seconds);
+    let batch_rows = config.trading.order_import.batch_rows;
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match order_import_service.process_pending(batch_rows).await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!("Processed {} imported order(s)", count),
+                Err(e) => tracing::error!("Processing order imports failed: {}", e),
+            }
+        }
+    });
+}
+
+fn spawn_copy_trading_task(copy_trading_service: CopyTradingService, config: &Config) {
+    let interval = std::time::Duration::from_secs(config.trading.copy.interval_seconds);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match copy_trading_service.mirror_fills().await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!("Mirrored {} lead fill(s)", count),
+                Err(e) => tracing::error!("Mirroring lead fills failed: {}", e),
+            }
+        }
+    });
 }
@@ -1,9 +1,63 @@
 use axum::{
-    extract::{Request, State},
-    http::{HeaderMap, StatusCode},
+    body::Body,
+    extract::{ConnectInfo, MatchedPath, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
     middleware::Next,
     response::Response,
 };
+use cryptotrade_core::{
+    fields::FieldSelection,
+    i18n::LOCALE, AccountStatus, Caller, Claims, CryptoTradeError, LimitsConfig, Locale, SignedRequest, TradingPermission,
+    API_KEY_SCOPE, REQUEST_ID,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use uuid::Uuid;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const API_KEY_HEADER: &str = "x-api-key";
+const API_TIMESTAMP_HEADER: &str = "x-api-timestamp";
+const API_NONCE_HEADER: &str = "x-api-nonce";
+const API_SIGNATURE_HEADER: &str = "x-api-signature";
+const RATE_LIMIT_LIMIT_HEADER: &str = "x-ratelimit-limit";
+const RATE_LIMIT_REMAINING_HEADER: &str = "x-ratelimit-remaining";
+const RATE_LIMIT_RESET_HEADER: &str = "x-ratelimit-reset";
+/// Public, but rate limited by caller, with more allowed to API keys
+const MARKET_DATA_ROUTES: [&str; 15] = [
+    "/api/v1/time",
+    "/api/v1/exchange-info",
+    "/api/v1/market-data",
+    "/api/v1/trading-pairs",
+    "/api/v1/tickers",
+    "/api/v1/currencies",
+    "/api/v1/order-book/",
+    "/api/v1/trades/",
+    "/api/v1/candlesticks/",
+    "/api/v1/indicators/",
+    "/api/v2/market-data/",
+    "/api/v2/order-book/",
+    "/api/v2/trades/",
+    "/api/v2/candlesticks/",
+    "/api/v2/indicators/",
+];
+/// When the market data routes taking a pair id were deprecated in favour
+/// of the `/api/v2` routes taking a symbol, as an RFC 9745 date
+const PAIR_ID_ROUTES_DEPRECATED: &str = "@1792195200";
+/// When they stop being served
+const PAIR_ID_ROUTES_SUNSET: &str = "Sat, 17 Apr 2027 00:00:00 GMT";
+/// The GraphQL endpoint, which only reads
+const GRAPHQL_ROUTE: &str = "/api/v1/graphql";
+/// Takes a CSV upload, as do ticket attachment routes
+const ORDER_IMPORT_ROUTE: &str = "/api/v1/orders/import";
+/// Routes that do a lot of work before responding, given the longer timeout
+const LONG_RUNNING_ROUTES: [&str; 4] = [
+    ORDER_IMPORT_ROUTE,
+    "/api/v1/exports/",
+    "/api/v1/portfolio/rebalance",
+    "/api/v1/admin/analytics/rollup",
+];
 
 // Import AppState from the parent module (main.rs)
 use super::AppState;
@@ -13,13 +67,85 @@ pub async fn auth_middleware(
     headers: HeaderMap,
     mut request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    // Skip auth for public routes
+) -> Result<Response, CryptoTradeError> {
+    // Skip auth for public routes, unless a market data caller signs with
+    // an API key to get the key's higher limits or sends a token to see
+    // their own watchlist
     let path = request.uri().path();
-    if is_public_route(path) {
+    let identified_market_data = is_market_data_route(path)
+        && (headers.contains_key(API_KEY_HEADER) || headers.contains_key(header::AUTHORIZATION));
+    if is_public_route(path) && !identified_market_data {
         return Ok(next.run(request).await);
     }
 
+    if let Some(key) = headers.get(API_KEY_HEADER).and_then(|header| header.to_str().ok()) {
+        let signing_header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|header| header.to_str().ok())
+                .ok_or_else(|| CryptoTradeError::Authentication {
+                    message: format!("Missing {} header", name),
+                })
+        };
+        let timestamp = signing_header(API_TIMESTAMP_HEADER)?;
+        let nonce = signing_header(API_NONCE_HEADER)?;
+        let signature = signing_header(API_SIGNATURE_HEADER)?;
+
+        // The signature covers the raw body, so read it and put it back
+        let (parts, body) = request.into_parts();
+        let limit_bytes = body_limit(&state.limits_config, &parts.method, parts.uri.path());
+        let body = axum::body::to_bytes(body, limit_bytes)
+            .await
+            .map_err(|_| CryptoTradeError::PayloadTooLarge { limit_bytes })?;
+        let path_and_query = parts.uri.path_and_query().map_or(parts.uri.path(), |pq| pq.as_str());
+        let (api_key, user) = state
+            .api_key_service
+            .authenticate(&SignedRequest {
+                key,
+                timestamp,
+                nonce,
+                signature,
+                method: parts.method.as_str(),
+                path: path_and_query,
+                body: &body,
+            })
+            .await?;
+        request = Request::from_parts(parts, Body::from(body));
+        let path = request.uri().path();
+
+        let ip = client_ip(&request, state.server_config.trust_forwarded_for);
+        if !api_key.allows_ip(ip) {
+            return Err(CryptoTradeError::IpNotAllowed {
+                ip: ip.map_or_else(|| "an unknown address".to_string(), |ip| ip.to_string()),
+            });
+        }
+
+        let permission = required_permission(request.method(), path).ok_or_else(|| CryptoTradeError::Authorization {
+            message: "This endpoint is not available to API keys".to_string(),
+        })?;
+        let scope = api_key.scope();
+        if !scope.allows(permission) {
+            return Err(CryptoTradeError::PermissionDenied { permission });
+        }
+        if user.account_status == AccountStatus::Closed && !closed_account_may(request.method(), path) {
+            return Err(CryptoTradeError::AccountClosed);
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        request.extensions_mut().insert(Claims {
+            sub: user.id.to_string(),
+            email: user.email,
+            username: user.username,
+            exp: now,
+            iat: now,
+            role: user.role,
+        });
+        request.extensions_mut().insert(scope.clone());
+        request.extensions_mut().insert(Caller::ApiKey(api_key.id));
+
+        return Ok(API_KEY_SCOPE.scope(scope, next.run(request)).await);
+    }
+
     // Extract Authorization header
     let auth_header = headers
         .get("Authorization")
@@ -28,14 +154,23 @@ pub async fn auth_middleware(
 
     let token = match auth_header {
         Some(token) => token,
-        None => return Err(StatusCode::UNAUTHORIZED),
+        None => {
+            return Err(CryptoTradeError::Authentication {
+                message: "Missing bearer token".to_string(),
+            })
+        }
     };
 
     // Verify JWT token
-    let claims = match state.auth_service.verify_jwt(token) {
-        Ok(token_data) => token_data.claims,
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
-    };
+    let claims = state.auth_service.verify_jwt(token)?.claims;
+
+    // Tokens outlive a closure, so the account is checked on every change
+    if !closed_account_may(request.method(), path) {
+        let user_id = claims.sub.parse().map_err(|_| CryptoTradeError::InvalidUserId)?;
+        if state.user_service.account_status(user_id).await? == AccountStatus::Closed {
+            return Err(CryptoTradeError::AccountClosed);
+        }
+    }
 
     // Add user claims to request extensions
     request.extensions_mut().insert(claims);
@@ -43,17 +178,319 @@ pub async fn auth_middleware(
     Ok(next.run(request).await)
 }
 
+/// While the exchange is in maintenance, refuses anything but reads unless
+/// the caller is an admin. Runs inside `auth_middleware`, which sets the
+/// claims.
+pub async fn maintenance_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, CryptoTradeError> {
+    if request.method() != Method::GET && request.uri().path() != GRAPHQL_ROUTE {
+        let role = request.extensions().get::<Claims>().map_or("", |claims| claims.role.as_str());
+        state.runtime_config_service.ensure_open(role)?;
+    }
+    Ok(next.run(request).await)
+}
+
+/// Switches to the language the caller chose on their profile, which wins
+/// over `Accept-Language`. Runs inside `auth_middleware`, which sets the
+/// claims, so errors from authentication itself follow the header.
+pub async fn profile_locale_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, CryptoTradeError> {
+    let user_id = request
+        .extensions()
+        .get::<Claims>()
+        .and_then(|claims| claims.sub.parse::<Uuid>().ok());
+    let preferred = match user_id {
+        Some(user_id) => state.user_service.preferred_locale(user_id).await?,
+        None => None,
+    };
+
+    Ok(match preferred {
+        Some(locale) => LOCALE.scope(locale, next.run(request)).await,
+        None => next.run(request).await,
+    })
+}
+
+/// Counts market data requests against the caller's tier, hands the tier's
+/// limits to the handler and reports the caller's standing in
+/// `X-RateLimit-*` headers. Runs inside `auth_middleware`, which marks
+/// callers that signed with an API key; everyone else is counted by address.
+pub async fn rate_limit_middleware(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, CryptoTradeError> {
+    if !is_market_data_route(request.uri().path()) {
+        return Ok(next.run(request).await);
+    }
+
+    let caller = request
+        .extensions()
+        .get::<Caller>()
+        .copied()
+        .unwrap_or_else(|| Caller::Anonymous(client_ip(&request, state.server_config.trust_forwarded_for)));
+    let status = state.rate_limit_service.check_market_data(caller).await?;
+    request.extensions_mut().insert(status.tier);
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(RATE_LIMIT_LIMIT_HEADER, HeaderValue::from(status.limit));
+    headers.insert(RATE_LIMIT_REMAINING_HEADER, HeaderValue::from(status.remaining));
+    headers.insert(RATE_LIMIT_RESET_HEADER, HeaderValue::from(status.reset_at.timestamp()));
+    Ok(response)
+}
+
+/// What a closed account may still do: read, including its history, and
+/// request statements of it.
+fn closed_account_may(method: &Method, path: &str) -> bool {
+    *method == Method::GET || path.starts_with("/api/v1/user/documents")
+}
+
+fn client_ip(request: &Request, trust_forwarded_for: bool) -> Option<IpAddr> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    caller_ip(request.headers(), peer, trust_forwarded_for)
+}
+
+/// The caller's address: the socket peer, or behind a trusted reverse proxy
+/// the hop the proxy appended to `X-Forwarded-For`. Earlier hops are
+/// client-supplied and not trusted.
+pub(crate) fn caller_ip(headers: &HeaderMap, peer: Option<SocketAddr>, trust_forwarded_for: bool) -> Option<IpAddr> {
+    if trust_forwarded_for {
+        return headers
+            .get("x-forwarded-for")
+            .and_then(|header| header.to_str().ok())
+            .and_then(|hops| hops.rsplit(',').next())
+            .and_then(|hop| hop.trim().parse().ok());
+    }
+
+    peer.map(|addr| addr.ip())
+}
+
+/// The permission an API key needs for a route, or `None` for routes that
+/// take a logged-in session: key management, 2FA and admin.
+fn required_permission(method: &Method, path: &str) -> Option<TradingPermission> {
+    let session_only = [
+        "/api/v1/user/api-keys",
+        "/api/v1/user/2fa",
+        "/api/v1/user/bank-accounts",
+        "/api/v1/user/close",
+        "/api/v1/user/sessions",
+        "/api/v1/admin",
+        "/api/v1/sandbox",
+    ];
+    if session_only.iter().any(|route| path.starts_with(route)) {
+        return None;
+    }
+
+    match *method {
+        Method::GET => Some(TradingPermission::Read),
+        // Queries only, so a read even though it is posted
+        Method::POST if path == GRAPHQL_ROUTE => Some(TradingPermission::Read),
+        Method::POST | Method::DELETE if path.starts_with("/api/v1/orders") => Some(TradingPermission::SpotTrade),
+        Method::POST | Method::DELETE if path.starts_with("/api/v1/withdrawals") => Some(TradingPermission::Withdraw),
+        _ => None,
+    }
+}
+
 fn is_public_route(path: &str) -> bool {
     let public_routes = [
         "/api/v1/auth/register",
         "/api/v1/auth/login",
         "/api/v1/auth/refresh",
         "/api/v1/health",
-        "/api/v1/market-data",
-        "/api/v1/order-book",
-        "/api/v1/trades",
-        "/api/v1/candlesticks",
     ];
 
-    public_routes.iter().any(|&route| path.starts_with(route))
+    public_routes.iter().any(|&route| path.starts_with(route)) || is_market_data_route(path)
+}
+
+fn is_market_data_route(path: &str) -> bool {
+    MARKET_DATA_ROUTES.iter().any(|&route| path.starts_with(route))
+}
+
+/// Tags every request with an id, reusing a well-formed `x-request-id` sent by
+/// the client or a proxy. The id is echoed in the response header and is in
+/// scope for the handler so error bodies can include it.
+pub async fn request_id_middleware(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let mut response = REQUEST_ID.scope(request_id.clone(), next.run(request)).await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+/// Puts the locale the client prefers in `Accept-Language` in scope, so
+/// error messages are written in it. Error codes never change.
+pub async fn locale_middleware(request: Request, next: Next) -> Response {
+    let locale = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Locale::negotiate)
+        .unwrap_or_default();
+
+    LOCALE.scope(locale, next.run(request)).await
+}
+
+#[derive(Deserialize)]
+struct FieldsQuery {
+    fields: Option<String>,
+}
+
+/// Prunes a successful JSON response to the fields named in `fields=` (see
+/// [`FieldSelection`]), for clients that want less than the whole document.
+/// Layered on the heavy read routes only. Any ETag still describes the
+/// whole document, so conditional requests work as before.
+pub async fn field_selection_middleware(request: Request, next: Next) -> Result<Response, CryptoTradeError> {
+    let selection = Query::<FieldsQuery>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|Query(query)| query.fields)
+        .and_then(|fields| FieldSelection::parse(&fields));
+    let Some(selection) = selection else {
+        return Ok(next.run(request).await);
+    };
+
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if response.status() != StatusCode::OK || !is_json {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX).await.map_err(|_| CryptoTradeError::Internal)?;
+    let document: serde_json::Value = serde_json::from_slice(&body)?;
+    let body = serde_json::to_vec(&selection.apply(document))?;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// The largest body the router accepts for a request; the router sets the
+/// same limits with `DefaultBodyLimit`.
+pub(crate) fn body_limit(limits: &LimitsConfig, method: &Method, path: &str) -> usize {
+    let is_attachment = path.contains("/support/tickets/") && path.ends_with("/attachments");
+    let is_upload = *method == Method::POST && (path == ORDER_IMPORT_ROUTE || is_attachment);
+    if is_upload {
+        limits.upload_limit_bytes
+    } else {
+        limits.body_limit_bytes
+    }
+}
+
+/// Refuses bodies declared larger than the route accepts before reading
+/// them, and replaces the plain text 413 that axum's extractors answer with
+/// for bodies that turn out too large with the usual error body.
+pub async fn body_limit_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, CryptoTradeError> {
+    let limit_bytes = body_limit(&state.limits_config, request.method(), request.uri().path());
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.is_some_and(|length| length > limit_bytes) {
+        return Err(CryptoTradeError::PayloadTooLarge { limit_bytes });
+    }
+
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return Err(CryptoTradeError::PayloadTooLarge { limit_bytes });
+    }
+    Ok(response)
+}
+
+/// Fails a request whose handler has not produced a response in time. Only
+/// the handler is timed: streamed exports, event streams and WebSockets
+/// carry on once their headers are sent.
+pub async fn timeout_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, CryptoTradeError> {
+    let path = request.uri().path();
+    let timeout_seconds = if LONG_RUNNING_ROUTES.iter().any(|route| path.starts_with(route)) {
+        state.limits_config.long_timeout_seconds
+    } else {
+        state.limits_config.timeout_seconds
+    };
+
+    tokio::time::timeout(Duration::from_secs(timeout_seconds), next.run(request))
+        .await
+        .map_err(|_| CryptoTradeError::RequestTimeout { timeout_seconds })
+}
+
+/// Caps the exports running at once, refusing the rest with 503 rather than
+/// queueing them. Layered on the export routes only. A streamed export
+/// holds its permit until the whole body has been sent.
+pub async fn export_concurrency_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, CryptoTradeError> {
+    let permit = state
+        .export_permits
+        .clone()
+        .try_acquire_owned()
+        .map_err(|_| CryptoTradeError::ServerBusy { operation: "export" })?;
+
+    let response = next.run(request).await;
+    Ok(response.map(|body| {
+        Body::from_stream(body.into_data_stream().map(move |chunk| {
+            let _permit = &permit;
+            chunk
+        }))
+    }))
+}
+
+/// Marks a response from a deprecated route with `Deprecation` and `Sunset`
+/// headers and a link to the docs describing its successor, and counts the
+/// caller in [`cryptotrade_core::DeprecatedRouteUsage`]. Layered on the
+/// deprecated routes only.
+pub async fn deprecation_middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| request.uri().path().to_string(), |path| path.as_str().to_string());
+    let caller = match (request.extensions().get::<Caller>(), request.extensions().get::<Claims>()) {
+        (Some(Caller::ApiKey(key_id)), _) => format!("api_key:{}", key_id),
+        (_, Some(claims)) => format!("user:{}", claims.sub),
+        _ => client_ip(&request, state.server_config.trust_forwarded_for)
+            .map_or_else(|| "anonymous".to_string(), |ip| format!("ip:{}", ip)),
+    };
+    state.deprecated_routes.record(&route, &caller);
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static(PAIR_ID_ROUTES_DEPRECATED));
+    headers.insert("sunset", HeaderValue::from_static(PAIR_ID_ROUTES_SUNSET));
+    headers.insert(header::LINK, HeaderValue::from_static("</docs>; rel=\"deprecation\"; type=\"text/html\""));
+    response
 }
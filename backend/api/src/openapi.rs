@@ -1,6 +1,12 @@
 // backend/api/src/openapi.rs
-use crate::handlers;
-use utoipa::OpenApi;
+use cryptotrade_core::ERROR_CATALOG;
+use utoipa::{
+    openapi::{
+        schema::{ObjectBuilder, Type},
+        Deprecated, OpenApi as OpenApiSpec,
+    },
+    Modify, OpenApi,
+};
 
 
 /// Use fully-qualified paths so the derive can resolve them unambiguously at expansion time.
@@ -12,22 +18,172 @@ use utoipa::OpenApi;
         crate::handlers::register_handler,
         crate::handlers::login_handler,
         crate::handlers::refresh_token_handler,
+        crate::handlers::confirm_device_handler,
+        crate::handlers::list_sessions_handler,
+        crate::handlers::revoke_session_handler,
+        crate::handlers::jwt_keys_handler,
         crate::handlers::get_user_profile_handler,
+        crate::handlers::update_locale_handler,
+        crate::handlers::get_watchlist_handler,
+        crate::handlers::add_watchlist_entry_handler,
+        crate::handlers::set_watchlist_handler,
+        crate::handlers::remove_watchlist_entry_handler,
+        crate::handlers::close_account_handler,
         crate::handlers::get_user_accounts_handler,
+        crate::handlers::get_account_holds_handler,
+        crate::handlers::dust_convert_handler,
+        crate::handlers::get_user_limits_handler,
+        crate::handlers::get_user_features_handler,
+        crate::handlers::create_api_key_handler,
+        crate::handlers::list_api_keys_handler,
+        crate::handlers::get_api_usage_handler,
+        crate::handlers::revoke_api_key_handler,
+        crate::handlers::update_api_key_ip_allowlist_handler,
         crate::handlers::enable_2fa_handler,
         crate::handlers::confirm_2fa_handler,
         crate::handlers::disable_2fa_handler,
         crate::handlers::create_order_handler,
         crate::handlers::get_user_orders_handler,
+        crate::handlers::preview_order_handler,
+        crate::handlers::simulate_order_handler,
+        crate::handlers::import_orders_handler,
+        crate::handlers::get_order_import_handler,
+        crate::handlers::get_order_handler,
+        crate::handlers::get_order_fills_handler,
+        crate::handlers::get_order_history_handler,
         crate::handlers::cancel_order_handler,
+        crate::handlers::cancel_replace_orders_handler,
+        crate::handlers::create_algo_order_handler,
+        crate::handlers::list_algo_orders_handler,
+        crate::handlers::get_algo_order_handler,
+        crate::handlers::cancel_algo_order_handler,
+        crate::handlers::create_grid_bot_handler,
+        crate::handlers::list_grid_bots_handler,
+        crate::handlers::get_grid_bot_handler,
+        crate::handlers::start_grid_bot_handler,
+        crate::handlers::stop_grid_bot_handler,
+        crate::handlers::create_alert_template_handler,
+        crate::handlers::list_alert_templates_handler,
+        crate::handlers::disable_alert_template_handler,
+        crate::handlers::list_alert_executions_handler,
+        crate::handlers::alert_webhook_handler,
+        crate::handlers::publish_lead_trader_handler,
+        crate::handlers::unpublish_lead_trader_handler,
+        crate::handlers::list_lead_traders_handler,
+        crate::handlers::create_copy_subscription_handler,
+        crate::handlers::list_copy_subscriptions_handler,
+        crate::handlers::list_copy_trades_handler,
+        crate::handlers::close_copy_subscription_handler,
+        crate::handlers::create_payment_handler,
+        crate::handlers::list_payments_handler,
+        crate::handlers::payment_webhook_handler,
+        crate::handlers::request_document_handler,
+        crate::handlers::list_documents_handler,
+        crate::handlers::download_document_handler,
+        crate::handlers::create_ticket_handler,
+        crate::handlers::list_tickets_handler,
+        crate::handlers::get_ticket_handler,
+        crate::handlers::reply_ticket_handler,
+        crate::handlers::upload_ticket_attachment_handler,
+        crate::handlers::download_ticket_attachment_handler,
+        crate::handlers::add_bank_account_handler,
+        crate::handlers::list_bank_accounts_handler,
+        crate::handlers::verify_bank_account_handler,
+        crate::handlers::remove_bank_account_handler,
+        crate::handlers::create_fiat_withdrawal_handler,
+        crate::handlers::list_fiat_withdrawals_handler,
+        crate::handlers::cancel_fiat_withdrawal_handler,
+        crate::handlers::submit_travel_rule_handler,
+        crate::handlers::get_travel_rule_handler,
+        crate::handlers::get_deposit_address_handler,
+        crate::handlers::deposit_report_handler,
+        crate::handlers::create_crypto_withdrawal_handler,
+        crate::handlers::list_crypto_withdrawals_handler,
+        crate::handlers::cancel_crypto_withdrawal_handler,
         crate::handlers::get_portfolio_handler,
         crate::handlers::get_portfolio_history_handler,
+        crate::handlers::get_allocation_targets_handler,
+        crate::handlers::set_allocation_targets_handler,
+        crate::handlers::plan_rebalance_handler,
+        crate::handlers::execute_rebalance_handler,
+        crate::handlers::get_user_stats_handler,
+        crate::handlers::get_balances_at_handler,
+        crate::handlers::get_statement_handler,
+        crate::handlers::export_trades_handler,
+        crate::handlers::export_orders_handler,
+        crate::handlers::export_trades_file_handler,
+        crate::handlers::export_orders_file_handler,
+        crate::handlers::download_object_handler,
         crate::handlers::get_user_trades_handler,
+        crate::graphql::graphql_handler,
+        crate::handlers::server_time_handler,
+        crate::handlers::exchange_info_handler,
+        crate::handlers::capabilities_handler,
         crate::handlers::get_all_market_data_handler,
         crate::handlers::get_market_data_handler,
+        crate::handlers::get_tickers_handler,
+        crate::handlers::list_currencies_handler,
+        crate::handlers::list_trading_pairs_handler,
         crate::handlers::get_order_book_handler,
+        crate::handlers::get_order_book_snapshot_handler,
+        crate::handlers::get_order_book_stats_handler,
         crate::handlers::get_recent_trades_handler,
-        crate::handlers::get_candlestick_data_handler
+        crate::handlers::get_candlestick_data_handler,
+        crate::handlers::get_indicators_handler,
+        crate::handlers::get_market_data_by_symbol_handler,
+        crate::handlers::get_order_book_by_symbol_handler,
+        crate::handlers::get_order_book_snapshot_by_symbol_handler,
+        crate::handlers::get_order_book_stats_by_symbol_handler,
+        crate::handlers::get_recent_trades_by_symbol_handler,
+        crate::handlers::get_candlestick_data_by_symbol_handler,
+        crate::handlers::get_indicators_by_symbol_handler,
+        crate::sse::market_data_stream_handler,
+        crate::handlers::replay_order_book_handler,
+        crate::handlers::engine_health_handler,
+        crate::handlers::reset_sandbox_handler,
+        crate::handlers::set_price_band_handler,
+        crate::handlers::set_trading_pair_status_handler,
+        crate::handlers::set_trading_sessions_handler,
+        crate::handlers::set_user_limits_handler,
+        crate::handlers::set_user_permissions_handler,
+        crate::handlers::set_account_status_handler,
+        crate::handlers::set_kyc_tier_handler,
+        crate::handlers::get_login_risk_handler,
+        crate::handlers::bust_trade_handler,
+        crate::handlers::get_runtime_config_handler,
+        crate::handlers::update_runtime_config_handler,
+        crate::handlers::list_feature_flags_handler,
+        crate::handlers::update_feature_flag_handler,
+        crate::handlers::reset_feature_flag_handler,
+        crate::handlers::list_withdrawal_queue_handler,
+        crate::handlers::review_withdrawal_handler,
+        crate::handlers::withdrawal_reviews_handler,
+        crate::handlers::list_unmatched_deposits_handler,
+        crate::handlers::credit_unmatched_deposit_handler,
+        crate::handlers::support_queue_handler,
+        crate::handlers::admin_get_ticket_handler,
+        crate::handlers::admin_reply_ticket_handler,
+        crate::handlers::set_ticket_status_handler,
+        crate::handlers::admin_upload_ticket_attachment_handler,
+        crate::handlers::admin_download_ticket_attachment_handler,
+        crate::handlers::list_pending_micro_deposits_handler,
+        crate::handlers::list_compliance_reviews_handler,
+        crate::handlers::resolve_compliance_review_handler,
+        crate::handlers::get_activity_analytics_handler,
+        crate::handlers::get_volume_analytics_handler,
+        crate::handlers::get_fee_revenue_analytics_handler,
+        crate::handlers::get_flows_analytics_handler,
+        crate::handlers::rollup_analytics_handler,
+        crate::handlers::list_treasury_accounts_handler,
+        crate::handlers::list_treasury_sweeps_handler,
+        crate::handlers::sweep_treasury_handler,
+        crate::handlers::create_currency_handler,
+        crate::handlers::update_currency_handler,
+        crate::handlers::get_tenant_handler,
+        crate::handlers::list_tenants_handler,
+        crate::handlers::create_tenant_handler,
+        crate::handlers::update_tenant_handler,
+        crate::handlers::set_pair_tenant_handler
     ),
     components(
         schemas(
@@ -35,8 +191,38 @@ use utoipa::OpenApi;
             cryptotrade_core::LoginRequest,
             cryptotrade_core::AuthResponse,
             cryptotrade_core::KycStatus,
+            cryptotrade_core::KycTier,
+            cryptotrade_core::KycTierLimits,
+            cryptotrade_core::UpdateKycTierRequest,
             cryptotrade_core::UserProfile,
+            cryptotrade_core::UpdateLocaleRequest,
+            cryptotrade_core::Locale,
+            cryptotrade_core::WatchlistEntry,
+            cryptotrade_core::AddWatchlistEntryRequest,
+            cryptotrade_core::SetWatchlistRequest,
+            cryptotrade_core::AccountStatus,
+            cryptotrade_core::CloseAccountRequest,
+            cryptotrade_core::UpdateAccountStatusRequest,
+            cryptotrade_core::AccountStatusChange,
+            cryptotrade_core::BustTradeRequest,
+            cryptotrade_core::TradeBust,
+            cryptotrade_core::ComplianceReviewStatus,
+            cryptotrade_core::ComplianceReview,
+            cryptotrade_core::ResolveComplianceReviewRequest,
+            cryptotrade_core::DailyActivity,
+            cryptotrade_core::DailyPairVolume,
+            cryptotrade_core::DailyFeeRevenue,
+            cryptotrade_core::DailyFlow,
+            cryptotrade_core::RollupRequest,
+            cryptotrade_core::TreasuryAccount,
+            cryptotrade_core::TreasurySweep,
+            cryptotrade_core::SweepTreasuryRequest,
             cryptotrade_core::RefreshTokenRequest,
+            cryptotrade_core::ConfirmDeviceRequest,
+            cryptotrade_core::UserDevice,
+            cryptotrade_core::LoginEvent,
+            cryptotrade_core::LoginEventStatus,
+            cryptotrade_core::AccountLoginRisk,
             cryptotrade_core::TokenResponse,
             cryptotrade_core::Account,
             cryptotrade_core::Order,
@@ -45,27 +231,250 @@ use utoipa::OpenApi;
             cryptotrade_core::OrderStatus,
             cryptotrade_core::TimeInForce,
             cryptotrade_core::CreateOrderRequest,
+            cryptotrade_core::TradingPair,
+            cryptotrade_core::PriceBandRequest,
+            cryptotrade_core::TradingPairStatus,
+            cryptotrade_core::UpdateTradingPairStatusRequest,
+            cryptotrade_core::TradingPairStatusChange,
+            cryptotrade_core::TradingPairTransition,
+            cryptotrade_core::TradingSession,
+            cryptotrade_core::SetTradingSessionsRequest,
+            cryptotrade_core::TradingPairListing,
+            cryptotrade_core::Currency,
+            cryptotrade_core::CreateCurrencyRequest,
+            cryptotrade_core::UpdateCurrencyRequest,
+            cryptotrade_core::Tenant,
+            cryptotrade_core::CreateTenantRequest,
+            cryptotrade_core::UpdateTenantRequest,
+            cryptotrade_core::PairTenantRequest,
+            cryptotrade_core::OrderPreview,
+            cryptotrade_core::OrderSimulation,
+            cryptotrade_core::SimulatedFill,
+            cryptotrade_core::CancelReplaceMode,
+            cryptotrade_core::CancelReplaceStatus,
+            cryptotrade_core::CancelReplaceRequest,
+            cryptotrade_core::CancelReplaceBatchRequest,
+            cryptotrade_core::CancelReplaceResult,
+            cryptotrade_core::AlgoStrategy,
+            cryptotrade_core::AlgoOrderStatus,
+            cryptotrade_core::AlgoOrder,
+            cryptotrade_core::CreateAlgoOrderRequest,
+            cryptotrade_core::AlgoOrderProgress,
+            cryptotrade_core::GridBotStatus,
+            cryptotrade_core::GridBot,
+            cryptotrade_core::CreateGridBotRequest,
+            cryptotrade_core::GridBotReport,
+            cryptotrade_core::AlertTemplate,
+            cryptotrade_core::CreateAlertTemplateRequest,
+            cryptotrade_core::CreatedAlertTemplate,
+            cryptotrade_core::TradingViewAlert,
+            cryptotrade_core::AlertExecutionStatus,
+            cryptotrade_core::AlertExecution,
+            cryptotrade_core::OrderImportStatus,
+            cryptotrade_core::OrderImportRowStatus,
+            cryptotrade_core::OrderImport,
+            cryptotrade_core::OrderImportRow,
+            cryptotrade_core::OrderImportReport,
+            cryptotrade_core::AllocationTarget,
+            cryptotrade_core::SetAllocationTargetsRequest,
+            cryptotrade_core::AllocationDrift,
+            cryptotrade_core::RebalanceTrade,
+            cryptotrade_core::RebalancePlan,
+            cryptotrade_core::RebalanceTradeResult,
+            cryptotrade_core::RebalanceExecution,
+            cryptotrade_core::LeadTrader,
+            cryptotrade_core::PublishLeadTraderRequest,
+            cryptotrade_core::CopySubscriptionStatus,
+            cryptotrade_core::CopySubscription,
+            cryptotrade_core::CreateCopySubscriptionRequest,
+            cryptotrade_core::CopyTradeStatus,
+            cryptotrade_core::CopyTrade,
             cryptotrade_core::Trade,
+            cryptotrade_core::Liquidity,
+            cryptotrade_core::Fill,
+            cryptotrade_core::OrderEvent,
+            cryptotrade_core::OrderEventType,
             cryptotrade_core::MarketData,
+            cryptotrade_core::Ticker,
             cryptotrade_core::OrderBook,
             cryptotrade_core::OrderBookLevel,
+            cryptotrade_core::OrderBookStats,
+            cryptotrade_core::OrderBookSnapshot,
+            cryptotrade_core::ServerTime,
+            cryptotrade_core::ExchangeInfo,
+            cryptotrade_core::RateLimitInfo,
+            cryptotrade_core::ExchangeCapabilities,
+            cryptotrade_core::Capabilities,
+            cryptotrade_core::ApiVersionInfo,
+            cryptotrade_core::Subsystems,
+            cryptotrade_core::FiatCapabilities,
+            cryptotrade_core::ApiChange,
+            cryptotrade_core::ExchangeSymbol,
+            cryptotrade_core::SymbolFilters,
+            cryptotrade_core::DepthBand,
+            cryptotrade_core::ReplayReport,
+            cryptotrade_core::EngineStatus,
+            cryptotrade_core::StreamMessage,
+            cryptotrade_core::PublicTrade,
+            cryptotrade_core::PublicTradeBust,
+            cryptotrade_core::TickerUpdate,
+            cryptotrade_core::BookUpdate,
+            cryptotrade_core::CandleUpdate,
+            cryptotrade_core::Interval,
+            cryptotrade_core::CandleFill,
+            cryptotrade_core::IndicatorPoint,
+            cryptotrade_core::Indicators,
             cryptotrade_core::Candlestick,
             cryptotrade_core::Portfolio,
             cryptotrade_core::AccountBalance,
             cryptotrade_core::PerformanceMetrics,
+            cryptotrade_core::UserStats,
+            cryptotrade_core::UserPairStats,
+            cryptotrade_core::OrderLock,
+            cryptotrade_core::HoldKind,
+            cryptotrade_core::BalanceHold,
+            cryptotrade_core::AccountHolds,
+            cryptotrade_core::DustConvertRequest,
+            cryptotrade_core::DustConversion,
+            cryptotrade_core::DustConversionItem,
+            cryptotrade_core::UserLimits,
+            cryptotrade_core::PairOrderUsage,
+            cryptotrade_core::NotionalUsage,
+            cryptotrade_core::UpdateUserLimitsRequest,
+            cryptotrade_core::LedgerEntryType,
+            cryptotrade_core::LedgerEntry,
+            cryptotrade_core::Statement,
+            cryptotrade_core::HistoricalBalances,
+            cryptotrade_core::ExportFile,
+            cryptotrade_core::HistoricalBalance,
+            cryptotrade_core::TransactionStatus,
+            cryptotrade_core::PaymentMethod,
+            cryptotrade_core::CreatePaymentRequest,
+            cryptotrade_core::BankInstructions,
+            cryptotrade_core::PaymentIntent,
+            cryptotrade_core::Payment,
+            cryptotrade_core::DocumentKind,
+            cryptotrade_core::DocumentStatus,
+            cryptotrade_core::Document,
+            cryptotrade_core::RequestDocumentRequest,
+            cryptotrade_core::BankRail,
+            cryptotrade_core::BankAccountStatus,
+            cryptotrade_core::BankAccount,
+            cryptotrade_core::AddBankAccountRequest,
+            cryptotrade_core::VerifyBankAccountRequest,
+            cryptotrade_core::PendingMicroDeposits,
+            cryptotrade_core::CreateFiatWithdrawalRequest,
+            cryptotrade_core::FiatWithdrawal,
+            cryptotrade_core::TravelRuleInfo,
+            cryptotrade_core::TravelRuleStatus,
+            cryptotrade_core::TravelRuleRecord,
+            cryptotrade_core::WithdrawalAction,
+            cryptotrade_core::ReviewWithdrawalRequest,
+            cryptotrade_core::MemoFormat,
+            cryptotrade_core::DepositAddress,
+            cryptotrade_core::ChainDepositReport,
+            cryptotrade_core::UnmatchedDeposit,
+            cryptotrade_core::CreditUnmatchedDepositRequest,
+            cryptotrade_core::CreateCryptoWithdrawalRequest,
+            cryptotrade_core::CryptoWithdrawal,
+            cryptotrade_core::WithdrawalReview,
+            cryptotrade_core::TicketStatus,
+            cryptotrade_core::TicketCategory,
+            cryptotrade_core::SupportTicket,
+            cryptotrade_core::TicketMessage,
+            cryptotrade_core::TicketAttachment,
+            cryptotrade_core::TicketAccountContext,
+            cryptotrade_core::SupportTicketDetail,
+            cryptotrade_core::CreateTicketRequest,
+            cryptotrade_core::TicketReplyRequest,
+            cryptotrade_core::TicketStatusRequest,
             cryptotrade_core::PortfolioSnapshot,
+            cryptotrade_core::TradingPermission,
+            cryptotrade_core::PermissionScope,
+            cryptotrade_core::ApiKey,
+            cryptotrade_core::CreateApiKeyRequest,
+            cryptotrade_core::CreatedApiKey,
+            cryptotrade_core::ApiKeyUsage,
+            cryptotrade_core::JwtPublicKey,
+            cryptotrade_core::RuntimeSettings,
+            cryptotrade_core::RuntimeConfig,
+            cryptotrade_core::FeatureFlag,
+            cryptotrade_core::UpdateFeatureFlagRequest,
+            cryptotrade_core::UpdateIpAllowlistRequest,
             cryptotrade_core::TwoFactorResponse,
             cryptotrade_core::ConfirmTwoFactorRequest,
-            cryptotrade_core::SuccessResponse
+            cryptotrade_core::SuccessResponse,
+            cryptotrade_core::ErrorCodeInfo,
+            cryptotrade_core::ErrorResponse
         )
     ),
+    modifiers(&ErrorCatalog, &DeprecatedRoutes),
     tags(
         (name = "Authentication", description = "User authentication and authorization"),
         (name = "User Management", description = "User profile and account management"),
+        (name = "API Keys", description = "Scoped keys for programmatic access"),
         (name = "Two-Factor Authentication", description = "2FA setup and management"),
         (name = "Trading", description = "Order management and trade execution"),
+        (name = "Payments", description = "Fiat deposits through card and bank transfer providers"),
+        (name = "Documents", description = "PDF receipts, trade confirmations and monthly statements"),
+        (name = "Fiat Withdrawals", description = "Bank accounts and withdrawals to them over SEPA and ACH"),
+        (name = "Crypto Transfers", description = "On-chain deposits and withdrawals, with memos on shared-address chains"),
+        (name = "Support", description = "Support tickets between users and exchange staff"),
         (name = "Portfolio", description = "Portfolio tracking and history"),
-        (name = "Market Data", description = "Real-time and historical market data")
+        (name = "Market Data", description = "Real-time and historical market data"),
+        (name = "Matching Engine", description = "Event log replay, recovery and engine state"),
+        (name = "Sandbox", description = "Paper trading against a simulated market (sandbox deployments only)"),
+        (name = "Tenants", description = "White-label brokers running on the exchange"),
+        (name = "Admin", description = "Exchange administration (admin role only)")
     )
 )]
 pub struct ApiDoc;
+
+/// Adds an `ErrorCode` schema enumerating every code in the error catalog,
+/// with the HTTP status of each in its description.
+struct ErrorCatalog;
+
+impl Modify for ErrorCatalog {
+    fn modify(&self, openapi: &mut OpenApiSpec) {
+        let mut description = String::from(
+            "Stable machine-readable error codes returned in `ErrorResponse.code`.\n\n| Code | HTTP status | Meaning |\n|---|---|---|\n",
+        );
+        for entry in ERROR_CATALOG {
+            description.push_str(&format!("| `{}` | {} | {} |\n", entry.code, entry.status, entry.description));
+        }
+
+        let schema = ObjectBuilder::new()
+            .schema_type(Type::String)
+            .enum_values(Some(ERROR_CATALOG.iter().map(|entry| entry.code)))
+            .description(Some(description))
+            .build();
+
+        if let Some(components) = openapi.components.as_mut() {
+            components.schemas.insert("ErrorCode".to_string(), schema.into());
+        }
+    }
+}
+
+/// Marks the market data routes taking a pair id as deprecated; each has a
+/// `/api/v2` successor taking the symbol instead.
+struct DeprecatedRoutes;
+
+const DEPRECATED_PATHS: [&str; 7] = [
+    "/api/v1/market-data/{pair_id}",
+    "/api/v1/order-book/{pair_id}",
+    "/api/v1/order-book/{pair_id}/snapshot",
+    "/api/v1/order-book/{pair_id}/stats",
+    "/api/v1/trades/{pair_id}",
+    "/api/v1/candlesticks/{pair_id}",
+    "/api/v1/indicators/{pair_id}",
+];
+
+impl Modify for DeprecatedRoutes {
+    fn modify(&self, openapi: &mut OpenApiSpec) {
+        for path in DEPRECATED_PATHS {
+            if let Some(operation) = openapi.paths.paths.get_mut(path).and_then(|item| item.get.as_mut()) {
+                operation.deprecated = Some(Deprecated::True);
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use axum::{
+    extract::DefaultBodyLimit,
+    http::{header, Extensions, HeaderMap, StatusCode, Version},
+    extract::State,
+    response::{IntoResponse, Json},
+    routing::{delete, get, post, put},
+    Extension, Router,
+};
+use std::sync::Arc;
+use tower_http::{
+    compression::{
+        predicate::{Predicate, SizeAbove},
+        CompressionLayer,
+    },
+    cors::CorsLayer,
+    trace::TraceLayer,
+};
+
+use crate::asyncapi::asyncapi_handler;
+use crate::graphql;
+use crate::handlers::*;
+use crate::middleware::{
+    auth_middleware, body_limit_middleware, deprecation_middleware, export_concurrency_middleware, field_selection_middleware, locale_middleware,
+    maintenance_middleware, profile_locale_middleware, rate_limit_middleware, request_id_middleware, timeout_middleware,
+};
+use crate::openapi::ApiDoc;
+use crate::sse;
+use crate::websocket;
+use crate::AppState;
+use cryptotrade_core::{CompressionConfig, Config};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+pub fn create_router(state: AppState, config: &Config) -> Router {
+    // Public routes (no auth middleware)
+    let mut public = Router::new()
+        .route("/api/v1/health", get(health_handler))
+        .route("/api/v1/health/engine", get(engine_health_handler))
+        .route("/api/v1/capabilities", get(capabilities_handler))
+        .route("/api/v1/auth/register", post(register_handler))
+        .route("/api/v1/auth/login", post(login_handler))
+        .route("/api/v1/auth/refresh", post(refresh_token_handler))
+        .route("/api/v1/auth/devices/confirm", post(confirm_device_handler))
+        .route("/api/v1/auth/keys", get(jwt_keys_handler))
+        .route("/api/v1/payments/webhooks/:method", post(payment_webhook_handler))
+        // Signed with the deposit watcher's secret
+        .route("/api/v1/webhooks/deposits", post(deposit_report_handler))
+        // Authenticated by the template's secret, as TradingView cannot send a token
+        .route("/api/v1/webhooks/alerts/:template_id", post(alert_webhook_handler))
+        // Presigned: the signature in the URL stands in for a token
+        .route("/api/v1/storage/*key", get(download_object_handler))
+        // Branding for white-label front ends, before anyone signs in
+        .route("/api/v1/tenants/:slug", get(get_tenant_handler))
+        // EventSource cannot send an Authorization header
+        .route("/api/v1/stream/market-data", get(sse::market_data_stream_handler))
+        .route("/api-doc/asyncapi.json", get(asyncapi_handler))
+        .merge(
+            SwaggerUi::new("/docs")
+                .url("/api-doc/openapi.json", ApiDoc::openapi()),
+        );
+    if config.app.metrics_enabled {
+        public = public.route("/api/v1/metrics", get(metrics_handler));
+    }
+
+    // Heavy reads that can be pruned with `fields=`
+    let select_fields = axum::middleware::from_fn(field_selection_middleware);
+    // File uploads take larger bodies than the default
+    let upload_limit = DefaultBodyLimit::max(config.limits.upload_limit_bytes);
+    // Market data routes taking a pair id, replaced by the /api/v2 routes
+    // taking a symbol
+    let deprecated = axum::middleware::from_fn_with_state(state.clone(), deprecation_middleware);
+    let limit_exports = axum::middleware::from_fn_with_state(state.clone(), export_concurrency_middleware);
+
+    // Protected routes (with auth middleware)
+    let mut protected = Router::new()
+        .route("/api/v1/time", get(server_time_handler))
+        .route("/api/v1/exchange-info", get(exchange_info_handler).layer(select_fields.clone()))
+        .route("/api/v1/market-data", get(get_all_market_data_handler))
+        .route("/api/v1/market-data/:pair_id", get(get_market_data_handler).layer(deprecated.clone()))
+        .route("/api/v1/tickers", get(get_tickers_handler).layer(select_fields.clone()))
+        .route("/api/v1/currencies", get(list_currencies_handler))
+        .route("/api/v1/trading-pairs", get(list_trading_pairs_handler))
+        .route("/api/v1/order-book/:pair_id", get(get_order_book_handler).layer(deprecated.clone()))
+        .route("/api/v1/order-book/:pair_id/snapshot", get(get_order_book_snapshot_handler).layer(deprecated.clone()))
+        .route("/api/v1/order-book/:pair_id/stats", get(get_order_book_stats_handler).layer(deprecated.clone()))
+        .route("/api/v1/trades/:pair_id", get(get_recent_trades_handler).layer(deprecated.clone()))
+        .route("/api/v1/candlesticks/:pair_id", get(get_candlestick_data_handler).layer(deprecated.clone()))
+        .route("/api/v1/indicators/:pair_id", get(get_indicators_handler).layer(deprecated))
+        .route("/api/v2/market-data/:symbol", get(get_market_data_by_symbol_handler))
+        .route("/api/v2/order-book/:symbol", get(get_order_book_by_symbol_handler))
+        .route("/api/v2/order-book/:symbol/snapshot", get(get_order_book_snapshot_by_symbol_handler))
+        .route("/api/v2/order-book/:symbol/stats", get(get_order_book_stats_by_symbol_handler))
+        .route("/api/v2/trades/:symbol", get(get_recent_trades_by_symbol_handler))
+        .route("/api/v2/candlesticks/:symbol", get(get_candlestick_data_by_symbol_handler))
+        .route("/api/v2/indicators/:symbol", get(get_indicators_by_symbol_handler))
+        .route("/api/v1/user/profile", get(get_user_profile_handler))
+        .route("/api/v1/user/locale", put(update_locale_handler))
+        .route(
+            "/api/v1/user/watchlist",
+            get(get_watchlist_handler).post(add_watchlist_entry_handler).put(set_watchlist_handler),
+        )
+        .route("/api/v1/user/watchlist/:pair_id", delete(remove_watchlist_entry_handler))
+        .route("/api/v1/user/close", post(close_account_handler))
+        .route("/api/v1/user/accounts", get(get_user_accounts_handler))
+        .route("/api/v1/user/accounts/:currency/holds", get(get_account_holds_handler))
+        .route("/api/v1/user/dust-convert", post(dust_convert_handler))
+        .route("/api/v1/user/stats", get(get_user_stats_handler))
+        .route("/api/v1/user/limits", get(get_user_limits_handler))
+        .route("/api/v1/user/features", get(get_user_features_handler))
+        .route("/api/v1/user/balances", get(get_balances_at_handler))
+        .route("/api/v1/user/statement", get(get_statement_handler))
+        .route("/api/v1/exports/trades", get(export_trades_handler).layer(limit_exports.clone()))
+        .route("/api/v1/exports/orders", get(export_orders_handler).layer(limit_exports.clone()))
+        .route("/api/v1/exports/trades/file", post(export_trades_file_handler).layer(limit_exports.clone()))
+        .route("/api/v1/exports/orders/file", post(export_orders_file_handler).layer(limit_exports))
+        .route("/api/v1/user/api-keys", post(create_api_key_handler).get(list_api_keys_handler))
+        .route("/api/v1/user/api-usage", get(get_api_usage_handler))
+        .route("/api/v1/user/api-keys/:key_id", delete(revoke_api_key_handler))
+        .route("/api/v1/user/sessions", get(list_sessions_handler))
+        .route("/api/v1/user/sessions/:device_id", delete(revoke_session_handler))
+        .route("/api/v1/user/api-keys/:key_id/ip-allowlist", put(update_api_key_ip_allowlist_handler))
+        .route("/api/v1/user/2fa/enable", post(enable_2fa_handler))
+        .route("/api/v1/user/2fa/confirm", post(confirm_2fa_handler))
+        .route("/api/v1/user/2fa/disable", post(disable_2fa_handler))
+        .route("/api/v1/orders", post(create_order_handler))
+        .route("/api/v1/orders", get(get_user_orders_handler))
+        .route("/api/v1/orders/preview", get(preview_order_handler))
+        .route("/api/v1/orders/simulate", post(simulate_order_handler))
+        .route("/api/v1/orders/import", post(import_orders_handler).layer(upload_limit))
+        .route("/api/v1/orders/import/:import_id", get(get_order_import_handler))
+        .route("/api/v1/orders/:order_id", get(get_order_handler).delete(cancel_order_handler))
+        .route("/api/v1/orders/:order_id/fills", get(get_order_fills_handler))
+        .route("/api/v1/orders/:order_id/history", get(get_order_history_handler))
+        .route("/api/v1/orders/cancel-replace", post(cancel_replace_orders_handler))
+        .route("/api/v1/algo-orders", post(create_algo_order_handler).get(list_algo_orders_handler))
+        .route(
+            "/api/v1/algo-orders/:algo_order_id",
+            get(get_algo_order_handler).delete(cancel_algo_order_handler),
+        )
+        .route("/api/v1/grid-bots", post(create_grid_bot_handler).get(list_grid_bots_handler))
+        .route("/api/v1/grid-bots/:bot_id", get(get_grid_bot_handler))
+        .route("/api/v1/grid-bots/:bot_id/start", post(start_grid_bot_handler))
+        .route("/api/v1/grid-bots/:bot_id/stop", post(stop_grid_bot_handler))
+        .route("/api/v1/alert-templates", post(create_alert_template_handler).get(list_alert_templates_handler))
+        .route("/api/v1/alert-templates/:template_id", delete(disable_alert_template_handler))
+        .route("/api/v1/alert-templates/:template_id/executions", get(list_alert_executions_handler))
+        .route(
+            "/api/v1/copy-trading/leads",
+            get(list_lead_traders_handler)
+                .post(publish_lead_trader_handler)
+                .delete(unpublish_lead_trader_handler),
+        )
+        .route(
+            "/api/v1/copy-trading/subscriptions",
+            post(create_copy_subscription_handler).get(list_copy_subscriptions_handler),
+        )
+        .route("/api/v1/copy-trading/subscriptions/:subscription_id", delete(close_copy_subscription_handler))
+        .route("/api/v1/copy-trading/subscriptions/:subscription_id/trades", get(list_copy_trades_handler))
+        .route("/api/v1/payments", post(create_payment_handler).get(list_payments_handler))
+        .route("/api/v1/user/documents", post(request_document_handler).get(list_documents_handler))
+        .route("/api/v1/user/documents/:document_id/download", get(download_document_handler))
+        .route("/api/v1/support/tickets", post(create_ticket_handler).get(list_tickets_handler))
+        .route("/api/v1/support/tickets/:ticket_id", get(get_ticket_handler))
+        .route("/api/v1/support/tickets/:ticket_id/messages", post(reply_ticket_handler))
+        .route("/api/v1/support/tickets/:ticket_id/attachments", post(upload_ticket_attachment_handler).layer(upload_limit))
+        .route("/api/v1/support/tickets/:ticket_id/attachments/:attachment_id", get(download_ticket_attachment_handler))
+        .route("/api/v1/user/bank-accounts", post(add_bank_account_handler).get(list_bank_accounts_handler))
+        .route("/api/v1/user/bank-accounts/:bank_account_id", delete(remove_bank_account_handler))
+        .route("/api/v1/user/bank-accounts/:bank_account_id/verify", post(verify_bank_account_handler))
+        .route(
+            "/api/v1/withdrawals/fiat",
+            post(create_fiat_withdrawal_handler).get(list_fiat_withdrawals_handler),
+        )
+        .route("/api/v1/withdrawals/fiat/:withdrawal_id", delete(cancel_fiat_withdrawal_handler))
+        .route(
+            "/api/v1/withdrawals/fiat/:withdrawal_id/travel-rule",
+            post(submit_travel_rule_handler).get(get_travel_rule_handler),
+        )
+        .route("/api/v1/deposits/crypto/:currency/address", get(get_deposit_address_handler))
+        .route(
+            "/api/v1/withdrawals/crypto",
+            post(create_crypto_withdrawal_handler).get(list_crypto_withdrawals_handler),
+        )
+        .route("/api/v1/withdrawals/crypto/:withdrawal_id", delete(cancel_crypto_withdrawal_handler))
+        .route("/api/v1/portfolio", get(get_portfolio_handler).layer(select_fields))
+        .route("/api/v1/portfolio/history", get(get_portfolio_history_handler))
+        .route(
+            "/api/v1/portfolio/allocation",
+            get(get_allocation_targets_handler).put(set_allocation_targets_handler),
+        )
+        .route("/api/v1/portfolio/rebalance", post(plan_rebalance_handler))
+        .route("/api/v1/portfolio/rebalance/:plan_id/execute", post(execute_rebalance_handler))
+        .route("/api/v1/trades", get(get_user_trades_handler))
+        // Replays the whole journal; an audit and recovery tool
+        .route("/api/v1/admin/engine/replay/:pair_id", get(replay_order_book_handler))
+        .route("/api/v1/admin/trading-pairs/:pair_id/price-band", put(set_price_band_handler))
+        .route("/api/v1/admin/trading-pairs/:pair_id/status", put(set_trading_pair_status_handler))
+        .route("/api/v1/admin/trading-pairs/:pair_id/sessions", put(set_trading_sessions_handler))
+        .route("/api/v1/admin/trading-pairs/:pair_id/tenant", put(set_pair_tenant_handler))
+        .route("/api/v1/admin/users/:user_id/limits", put(set_user_limits_handler))
+        .route("/api/v1/admin/users/:user_id/permissions", put(set_user_permissions_handler))
+        .route("/api/v1/admin/users/:user_id/status", put(set_account_status_handler))
+        .route("/api/v1/admin/users/:user_id/kyc-tier", put(set_kyc_tier_handler))
+        .route("/api/v1/admin/users/:user_id/login-risk", get(get_login_risk_handler))
+        .route("/api/v1/admin/trades/:trade_id/bust", post(bust_trade_handler))
+        .route(
+            "/api/v1/admin/runtime-config",
+            get(get_runtime_config_handler).put(update_runtime_config_handler),
+        )
+        .route("/api/v1/admin/feature-flags", get(list_feature_flags_handler))
+        .route("/api/v1/admin/withdrawals", get(list_withdrawal_queue_handler))
+        .route("/api/v1/admin/withdrawals/:withdrawal_id/review", post(review_withdrawal_handler))
+        .route("/api/v1/admin/withdrawals/:withdrawal_id/reviews", get(withdrawal_reviews_handler))
+        .route("/api/v1/admin/deposits/unmatched", get(list_unmatched_deposits_handler))
+        .route("/api/v1/admin/deposits/unmatched/:deposit_id/credit", post(credit_unmatched_deposit_handler))
+        .route("/api/v1/admin/support/tickets", get(support_queue_handler))
+        .route("/api/v1/admin/support/tickets/:ticket_id", get(admin_get_ticket_handler))
+        .route("/api/v1/admin/support/tickets/:ticket_id/messages", post(admin_reply_ticket_handler))
+        .route("/api/v1/admin/support/tickets/:ticket_id/status", put(set_ticket_status_handler))
+        .route("/api/v1/admin/support/tickets/:ticket_id/attachments", post(admin_upload_ticket_attachment_handler).layer(upload_limit))
+        .route(
+            "/api/v1/admin/support/tickets/:ticket_id/attachments/:attachment_id",
+            get(admin_download_ticket_attachment_handler),
+        )
+        .route("/api/v1/admin/bank-accounts/micro-deposits", get(list_pending_micro_deposits_handler))
+        .route("/api/v1/admin/compliance/reviews", get(list_compliance_reviews_handler))
+        .route("/api/v1/admin/analytics/activity", get(get_activity_analytics_handler))
+        .route("/api/v1/admin/analytics/volume", get(get_volume_analytics_handler))
+        .route("/api/v1/admin/analytics/fees", get(get_fee_revenue_analytics_handler))
+        .route("/api/v1/admin/analytics/flows", get(get_flows_analytics_handler))
+        .route("/api/v1/admin/analytics/rollup", post(rollup_analytics_handler))
+        .route("/api/v1/admin/treasury", get(list_treasury_accounts_handler))
+        .route("/api/v1/admin/treasury/sweeps", get(list_treasury_sweeps_handler))
+        .route("/api/v1/admin/treasury/:currency/sweep", post(sweep_treasury_handler))
+        .route("/api/v1/admin/currencies", post(create_currency_handler))
+        .route("/api/v1/admin/currencies/:code", put(update_currency_handler))
+        .route("/api/v1/admin/tenants", get(list_tenants_handler).post(create_tenant_handler))
+        .route("/api/v1/admin/tenants/:tenant_id", put(update_tenant_handler))
+        .route("/api/v1/admin/compliance/reviews/:review_id/resolve", post(resolve_compliance_review_handler))
+        .route(
+            "/api/v1/admin/feature-flags/:key",
+            put(update_feature_flag_handler).delete(reset_feature_flag_handler),
+        )
+        .route("/ws", get(websocket::websocket_handler));
+    if config.graphql.enabled {
+        let schema = graphql::schema(state.clone(), &config.graphql);
+        protected = protected.route("/api/v1/graphql", post(graphql::graphql_handler).layer(Extension(schema)));
+    }
+    if config.sandbox.enabled {
+        protected = protected.route("/api/v1/sandbox/reset", post(reset_sandbox_handler));
+    }
+    let protected = protected
+        .layer(axum::middleware::from_fn_with_state(state.clone(), rate_limit_middleware))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), maintenance_middleware))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), profile_locale_middleware))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ));
+
+    public
+        .merge(protected)
+        .layer(axum::middleware::from_fn_with_state(state.clone(), timeout_middleware))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), body_limit_middleware))
+        .layer(DefaultBodyLimit::max(config.limits.body_limit_bytes))
+        .layer(compression_layer(&config.compression))
+        .layer(axum::middleware::from_fn(locale_middleware))
+        .layer(axum::middleware::from_fn(request_id_middleware))
+        .layer(CorsLayer::permissive())
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
+}
+
+/// Gzip or Brotli, whichever the client prefers, for responses of the
+/// configured content types. Responses without a known length (streamed
+/// exports) are always eligible; event streams are never listed, since
+/// compressing them would hold back events.
+fn compression_layer(config: &CompressionConfig) -> CompressionLayer<impl Predicate> {
+    let content_types = Arc::new(config.content_types.clone());
+    let content_type_allowed = move |_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions| {
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| content_types.iter().any(|allowed| value.starts_with(allowed.as_str())))
+    };
+
+    CompressionLayer::new()
+        .gzip(config.enabled)
+        .br(config.enabled)
+        .compress_when(SizeAbove::new(config.min_size_bytes).and(content_type_allowed))
+}
+
+async fn health_handler() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "healthy",
+        "timestamp": chrono::Utc::now(),
+        "version": "1.0.0"
+    }))
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.pool_metrics.render()
+            + &state.risk_service.metrics().render()
+            + &cryptotrade_core::resilience::render_metrics(&[&state.redis_breaker, &state.nats_breaker])
+            + &state.deprecated_routes.render(),
+    )
+}
@@ -0,0 +1,175 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use cryptotrade_core::{ErrorResponse, StreamMessage, StreamService};
+use futures::{stream, Stream};
+use serde::Deserialize;
+use std::{collections::VecDeque, convert::Infallible};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::{handlers::request_tenant, AppState};
+
+/// Ticker first, trades second; the event id carries one position per channel
+/// in this order, e.g. `41-17`.
+const CHANNEL_COUNT: usize = 2;
+
+#[derive(Debug, Deserialize)]
+pub struct MarketDataStreamQuery {
+    pub trading_pair_id: Uuid,
+}
+
+/// Streams ticker and trade events for one pair. Each event id records the
+/// last sequence sent on both channels, so a reconnecting `EventSource` that
+/// sends `Last-Event-ID` resumes from the replay buffer without losing
+/// messages. A `gap` event means the buffer no longer reaches back far enough
+/// and the client should reload state over REST.
+#[utoipa::path(
+    get,
+    path = "/api/v1/stream/market-data",
+    tag = "Market Data",
+    params(
+        ("trading_pair_id" = Uuid, Query, description = "Trading pair to stream"),
+        ("X-Tenant" = Option<String>, Header, description = "Slug of the white-label broker asking; needed to stream its private pairs"),
+        ("Last-Event-ID" = Option<String>, Header, description = "Id of the last event received, to resume after a reconnect")
+    ),
+    responses(
+        (status = 200, description = "Stream of `ticker`, `trade` and `gap` events", content_type = "text/event-stream", body = String),
+        (status = 404, description = "Trading pair not found", body = ErrorResponse)
+    )
+)]
+pub async fn market_data_stream_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<MarketDataStreamQuery>,
+) -> cryptotrade_core::Result<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    // Public, so only a broker's slug shows its private pairs
+    let tenant_id = request_tenant(&state, &headers).await?;
+    state.tenant_service.ensure_pair_visible_to(params.trading_pair_id, tenant_id).await?;
+
+    let channels = [
+        StreamService::ticker_channel(params.trading_pair_id),
+        StreamService::trades_channel(params.trading_pair_id),
+    ];
+
+    // Subscribe before reading the buffer so nothing published in between is lost
+    let live = state.stream_service.subscribe();
+    let resume_from = headers
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_event_id);
+
+    let mut feed = MarketDataFeed {
+        stream_service: state.stream_service.clone(),
+        live,
+        channels,
+        cursor: [0; CHANNEL_COUNT],
+        backlog: VecDeque::new(),
+        gap: None,
+    };
+
+    match resume_from {
+        Some(cursor) => {
+            feed.cursor = cursor;
+            feed.catch_up().await;
+        }
+        None => {
+            for (position, channel) in feed.channels.iter().enumerate() {
+                feed.cursor[position] = state.stream_service.latest_sequence(channel).await.unwrap_or(0);
+            }
+        }
+    }
+
+    let events = stream::unfold(feed, |mut feed| async move {
+        let event = feed.next_event().await?;
+        Some((Ok(event), feed))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+struct MarketDataFeed {
+    stream_service: StreamService,
+    live: broadcast::Receiver<StreamMessage>,
+    channels: [String; CHANNEL_COUNT],
+    cursor: [i64; CHANNEL_COUNT],
+    backlog: VecDeque<StreamMessage>,
+    gap: Option<Event>,
+}
+
+impl MarketDataFeed {
+    async fn next_event(&mut self) -> Option<Event> {
+        loop {
+            if let Some(gap) = self.gap.take() {
+                return Some(gap);
+            }
+
+            let message = match self.backlog.pop_front() {
+                Some(message) => message,
+                None => match self.live.recv().await {
+                    Ok(message) => message,
+                    Err(RecvError::Lagged(_)) => {
+                        self.catch_up().await;
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+
+            // Replayed and live messages overlap; skip anything already sent
+            let Some(position) = self.channels.iter().position(|channel| *channel == message.channel) else {
+                continue;
+            };
+            if message.sequence <= self.cursor[position] {
+                continue;
+            }
+            self.cursor[position] = message.sequence;
+
+            let name = if position == 0 { "ticker" } else { "trade" };
+            return Event::default()
+                .event(name)
+                .id(format_event_id(&self.cursor))
+                .json_data(&message)
+                .ok();
+        }
+    }
+
+    /// Queues buffered messages after the cursor, or a `gap` event when a
+    /// channel's buffer no longer covers it.
+    async fn catch_up(&mut self) {
+        for position in 0..CHANNEL_COUNT {
+            let channel = &self.channels[position];
+            match self.stream_service.resync(channel, self.cursor[position] + 1).await {
+                Ok(Some(messages)) => self.backlog.extend(messages),
+                _ => {
+                    // Jump to the head of the channel so later events still resume cleanly
+                    self.cursor[position] = self.stream_service.latest_sequence(channel).await.unwrap_or(0);
+                    if self.gap.is_none() {
+                        self.gap = Event::default()
+                            .event("gap")
+                            .id(format_event_id(&self.cursor))
+                            .json_data(serde_json::json!({ "channel": channel }))
+                            .ok();
+                    }
+                }
+            }
+        }
+
+        self.backlog.make_contiguous().sort_by_key(|message| message.timestamp);
+    }
+}
+
+fn format_event_id(cursor: &[i64; CHANNEL_COUNT]) -> String {
+    cursor.map(|sequence| sequence.to_string()).join("-")
+}
+
+fn parse_event_id(id: &str) -> Option<[i64; CHANNEL_COUNT]> {
+    let mut cursor = [0; CHANNEL_COUNT];
+    let mut parts = id.split('-');
+    for slot in cursor.iter_mut() {
+        *slot = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(cursor)
+}
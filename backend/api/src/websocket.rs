@@ -1,22 +1,446 @@
 use axum::{
-    extract::{ws::WebSocket, WebSocketUpgrade},
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    http::HeaderMap,
     response::Response,
+    Extension,
 };
+use cryptotrade_core::{
+    Claims, CreateOrderRequest, CryptoTradeError, Interval, Order, PermissionScope, StreamMessage, TradingPermission,
+    WebSocketConfig, API_KEY_SCOPE, LIVE_CANDLE_INTERVALS,
+};
+use flate2::{write::DeflateEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+use crate::AppState;
+
+/// The empty stored block a sync flush ends with; permessage-deflate leaves
+/// it off the wire.
+const DEFLATE_SYNC_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Messages a client may send. `request_id` is chosen by the client and
+/// echoed back on the matching ack or reject so responses can be correlated
+/// without waiting for each one in turn.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// `encoding` and `compress` switch the frame format of everything the
+    /// server sends from then on, starting with the ack; omitted fields keep
+    /// the current format.
+    Subscribe {
+        channels: Vec<String>,
+        encoding: Option<FeedEncoding>,
+        compress: Option<bool>,
+    },
+    Unsubscribe {
+        channels: Vec<String>,
+    },
+    /// Replays a channel from `from_sequence` (inclusive) after a gap.
+    Resync {
+        channel: String,
+        from_sequence: i64,
+    },
+    PlaceOrder {
+        request_id: String,
+        order: CreateOrderRequest,
+    },
+    CancelOrder {
+        request_id: String,
+        order_id: Uuid,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedEncoding {
+    #[default]
+    Json,
+    Cbor,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Subscribed {
+        channels: Vec<String>,
+    },
+    Unsubscribed {
+        channels: Vec<String>,
+    },
+    Update(StreamMessage),
+    Resync {
+        channel: String,
+        messages: Vec<StreamMessage>,
+    },
+    Ack {
+        request_id: String,
+        order: Box<Order>,
+    },
+    Reject {
+        request_id: Option<String>,
+        code: String,
+        message: String,
+    },
+}
 
-pub async fn websocket_handler(ws: WebSocketUpgrade) -> Response {
-    ws.on_upgrade(handle_socket)
+/// The socket sits behind the auth middleware, so every connection is bound
+/// to the user whose token was presented on the upgrade request.
+pub async fn websocket_handler(
+    ws: WebSocketUpgrade,
+    Extension(claims): Extension<Claims>,
+    api_key_scope: Option<Extension<PermissionScope>>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    let country = state.compliance_service.request_country(&headers);
+    let api_key_scope = api_key_scope.map(|Extension(scope)| scope);
+    // The connection outlives the request, so it carries the key's scope itself
+    ws.on_upgrade(move |socket| async move {
+        match api_key_scope.clone() {
+            Some(scope) => API_KEY_SCOPE.scope(scope, handle_socket(socket, state, claims, country, api_key_scope)).await,
+            None => handle_socket(socket, state, claims, country, None).await,
+        }
+    })
 }
 
-async fn handle_socket(mut socket: WebSocket) {
-    // Basic websocket implementation
-    while let Some(msg) = socket.recv().await {
-        if let Ok(msg) = msg {
-            // Echo back for now - in production this would handle market data subscriptions
-            if socket.send(msg).await.is_err() {
-                break;
+async fn handle_socket(
+    mut socket: WebSocket,
+    state: AppState,
+    claims: Claims,
+    country: Option<String>,
+    api_key_scope: Option<PermissionScope>,
+) {
+    let user_id = claims.sub.parse::<Uuid>().ok();
+    // Runtime limits are fixed for the life of the connection
+    let runtime = state.runtime_config_service.settings();
+    let config = WebSocketConfig {
+        max_messages_per_second: runtime.ws_max_messages_per_second,
+        max_subscriptions: runtime.ws_max_subscriptions,
+        ..state.websocket_config.clone()
+    };
+    let idle_timeout = Duration::from_secs(config.idle_timeout_seconds);
+
+    // Fixed for the connection too: the tenant whose private pairs it may follow
+    let tenant_id = match user_id {
+        Some(user_id) => state.tenant_service.user_tenant_id(user_id).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to look up the tenant of websocket user {}: {}", user_id, e);
+            None
+        }),
+        None => None,
+    };
+
+    let mut feed = state.stream_service.subscribe();
+    let mut session = Session {
+        country,
+        api_key_scope,
+        tenant_id,
+        ..Session::default()
+    };
+    let mut rate_limiter = RateLimiter::new(config.max_messages_per_second);
+    let mut last_seen = Instant::now();
+
+    let mut heartbeat = tokio::time::interval(Duration::from_secs(config.heartbeat_interval_seconds));
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let reply = tokio::select! {
+            msg = socket.recv() => {
+                let msg = match msg {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(msg)) => msg,
+                };
+                last_seen = Instant::now();
+
+                // Every data frame counts against the limit, including ones rejected unread
+                let is_data = matches!(msg, Message::Text(_) | Message::Binary(_));
+                if is_data && !rate_limiter.allow() {
+                    reject(None, "RATE_LIMITED", "Too many messages".to_string())
+                } else {
+                    match msg {
+                        Message::Text(text) => match serde_json::from_str::<ClientMessage>(&text) {
+                            Ok(message) => {
+                                handle_message(&state, &config, user_id, &claims.role, &mut session, message).await
+                            }
+                            Err(e) => reject(None, "INVALID_MESSAGE", e.to_string()),
+                        },
+                        Message::Binary(_) => {
+                            reject(None, "INVALID_MESSAGE", "Binary frames are not supported".to_string())
+                        }
+                        // Pings are answered by the protocol layer; pongs only refresh `last_seen`
+                        _ => continue,
+                    }
+                }
+            }
+            update = feed.recv() => match update {
+                Ok(message) if session.subscriptions.contains(&message.channel) => ServerMessage::Update(message),
+                Ok(_) => continue,
+                // The client sees the skipped sequences and resyncs
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("WebSocket client lagged behind the feed by {} message(s)", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > idle_timeout {
+                    tracing::debug!("Closing idle WebSocket connection");
+                    let _ = socket.send(Message::Close(None)).await;
+                    break;
+                }
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                continue;
             }
-        } else {
+        };
+
+        if !send(&mut socket, &reply, &mut session).await {
             break;
         }
     }
 }
+
+#[derive(Default)]
+struct Session {
+    subscriptions: HashSet<String>,
+    encoding: FeedEncoding,
+    /// The connection's deflate stream while compression is on. It keeps
+    /// its window across frames, as permessage-deflate's context takeover
+    /// does, so repeated field names and channels compress to a few bytes.
+    deflate: Option<DeflateEncoder<Vec<u8>>>,
+    /// Where the connection was opened from
+    country: Option<String>,
+    /// Scope of the API key the connection was opened with; `None` for
+    /// session tokens
+    api_key_scope: Option<PermissionScope>,
+    /// The user's tenant; channels of pairs private to another are refused
+    tenant_id: Option<Uuid>,
+}
+
+impl Session {
+    /// Placing and cancelling orders take a key allowed to trade, as they
+    /// do over REST.
+    fn may_trade(&self) -> Result<(), CryptoTradeError> {
+        match &self.api_key_scope {
+            Some(scope) if !scope.allows(TradingPermission::SpotTrade) => Err(CryptoTradeError::PermissionDenied {
+                permission: TradingPermission::SpotTrade,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Fixed one-second window; cheap enough to run on every inbound frame.
+struct RateLimiter {
+    limit: u32,
+    window_start: Instant,
+    count: u32,
+}
+
+impl RateLimiter {
+    fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            window_start: Instant::now(),
+            count: 0,
+        }
+    }
+
+    fn allow(&mut self) -> bool {
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count <= self.limit
+    }
+}
+
+async fn handle_message(
+    state: &AppState,
+    config: &WebSocketConfig,
+    user_id: Option<Uuid>,
+    role: &str,
+    session: &mut Session,
+    message: ClientMessage,
+) -> ServerMessage {
+    let (request_id, result) = match message {
+        ClientMessage::Subscribe {
+            channels,
+            encoding,
+            compress,
+        } => {
+            if let Some(invalid) = channels.iter().find(|channel| !is_valid_channel(channel)) {
+                return reject(None, "INVALID_CHANNEL", format!("Unknown channel: {}", invalid));
+            }
+            for channel in &channels {
+                if let Err(e) = ensure_channel_visible(state, session, channel).await {
+                    return reject(None, e.error_code(), e.to_string());
+                }
+            }
+            let added = channels.iter().filter(|channel| !session.subscriptions.contains(*channel)).count();
+            if session.subscriptions.len() + added > config.max_subscriptions {
+                return reject(
+                    None,
+                    "SUBSCRIPTION_LIMIT",
+                    format!("At most {} subscriptions per connection", config.max_subscriptions),
+                );
+            }
+            session.subscriptions.extend(channels.iter().cloned());
+            session.encoding = encoding.unwrap_or(session.encoding);
+            match compress {
+                // Turning compression on starts a new stream, which the
+                // client inflates from the ack on
+                Some(true) if session.deflate.is_none() => {
+                    session.deflate = Some(DeflateEncoder::new(Vec::new(), Compression::fast()));
+                }
+                Some(false) => session.deflate = None,
+                _ => {}
+            }
+            return ServerMessage::Subscribed { channels };
+        }
+        ClientMessage::Unsubscribe { channels } => {
+            for channel in &channels {
+                session.subscriptions.remove(channel);
+            }
+            return ServerMessage::Unsubscribed { channels };
+        }
+        ClientMessage::Resync { channel, from_sequence } => {
+            if !is_valid_channel(&channel) {
+                return reject(None, "INVALID_CHANNEL", format!("Unknown channel: {}", channel));
+            }
+            if let Err(e) = ensure_channel_visible(state, session, &channel).await {
+                return reject(None, e.error_code(), e.to_string());
+            }
+            return match state.stream_service.resync(&channel, from_sequence).await {
+                Ok(Some(messages)) => ServerMessage::Resync { channel, messages },
+                Ok(None) => reject(
+                    None,
+                    "RESYNC_UNAVAILABLE",
+                    format!("Sequence {} is no longer buffered; reload a snapshot over REST", from_sequence),
+                ),
+                Err(e) => reject(None, e.error_code(), e.to_string()),
+            };
+        }
+        ClientMessage::PlaceOrder { request_id, order } => {
+            let Some(user_id) = user_id else {
+                return reject(Some(request_id), "INVALID_USER_ID", "Invalid user ID".to_string());
+            };
+            if let Err(e) = session.may_trade() {
+                return reject(Some(request_id), e.error_code(), e.to_string());
+            }
+            if let Err(e) = state.runtime_config_service.ensure_open(role) {
+                return reject(Some(request_id), e.error_code(), e.to_string());
+            }
+            if let Err(e) = state.compliance_service.check_trading(user_id, session.country.as_deref()).await {
+                return reject(Some(request_id), e.error_code(), e.to_string());
+            }
+            (request_id, state.order_service.create_order(user_id, order).await)
+        }
+        ClientMessage::CancelOrder { request_id, order_id } => {
+            let Some(user_id) = user_id else {
+                return reject(Some(request_id), "INVALID_USER_ID", "Invalid user ID".to_string());
+            };
+            if let Err(e) = session.may_trade() {
+                return reject(Some(request_id), e.error_code(), e.to_string());
+            }
+            if let Err(e) = state.runtime_config_service.ensure_open(role) {
+                return reject(Some(request_id), e.error_code(), e.to_string());
+            }
+            (request_id, state.order_service.cancel_order(user_id, order_id).await)
+        }
+    };
+
+    match result {
+        Ok(order) => ServerMessage::Ack {
+            request_id,
+            order: Box::new(order),
+        },
+        Err(e) => reject(Some(request_id), e.error_code(), e.to_string()),
+    }
+}
+
+/// Feed channels are `<kind>:<trading pair id>`, e.g. `trades:<uuid>`;
+/// candle kinds name their interval, as in `candles_1m:<uuid>`.
+fn is_valid_channel(channel: &str) -> bool {
+    let Some((kind, pair_id)) = channel.split_once(':') else {
+        return false;
+    };
+    let known = match kind {
+        "trades" | "book" | "ticker" => true,
+        _ => kind
+            .strip_prefix("candles_")
+            .and_then(|interval| interval.parse::<Interval>().ok())
+            .is_some_and(|interval| LIVE_CANDLE_INTERVALS.contains(&interval)),
+    };
+    known && pair_id.parse::<Uuid>().is_ok()
+}
+
+/// Refuses a valid channel whose pair is private to a tenant other than
+/// the session's, as if the pair did not exist.
+async fn ensure_channel_visible(state: &AppState, session: &Session, channel: &str) -> Result<(), CryptoTradeError> {
+    let Some(pair_id) = channel.split_once(':').and_then(|(_, pair_id)| pair_id.parse::<Uuid>().ok()) else {
+        return Ok(());
+    };
+    state.tenant_service.ensure_pair_visible_to(pair_id, session.tenant_id).await
+}
+
+async fn send(socket: &mut WebSocket, message: &ServerMessage, session: &mut Session) -> bool {
+    match encode(message, session) {
+        Ok(frame) => socket.send(frame).await.is_ok(),
+        Err(e) => {
+            tracing::error!("Failed to encode websocket reply: {}", e);
+            false
+        }
+    }
+}
+
+/// Plain JSON goes out as text frames; CBOR, MessagePack and compressed
+/// payloads go out as binary frames.
+///
+/// A compressed frame is what permessage-deflate (RFC 7692) would carry:
+/// the payload deflated on the connection's stream, sync-flushed, with the
+/// trailing `00 00 ff ff` removed. Clients feed the frames in order to one
+/// raw inflater, appending those four bytes to each.
+fn encode(message: &ServerMessage, session: &mut Session) -> anyhow::Result<Message> {
+    let payload = match session.encoding {
+        FeedEncoding::Json if session.deflate.is_none() => return Ok(Message::Text(serde_json::to_string(message)?)),
+        FeedEncoding::Json => serde_json::to_vec(message)?,
+        FeedEncoding::Cbor => {
+            let mut payload = Vec::new();
+            ciborium::into_writer(message, &mut payload)?;
+            payload
+        }
+        FeedEncoding::MessagePack => rmp_serde::to_vec_named(message)?,
+    };
+
+    let Some(deflate) = session.deflate.as_mut() else {
+        return Ok(Message::Binary(payload));
+    };
+
+    deflate.write_all(&payload)?;
+    deflate.flush()?;
+    let mut frame = std::mem::take(deflate.get_mut());
+    if frame.ends_with(&DEFLATE_SYNC_TAIL) {
+        frame.truncate(frame.len() - DEFLATE_SYNC_TAIL.len());
+    }
+    Ok(Message::Binary(frame))
+}
+
+fn reject(request_id: Option<String>, code: &str, message: String) -> ServerMessage {
+    ServerMessage::Reject {
+        request_id,
+        code: code.to_string(),
+        message,
+    }
+}
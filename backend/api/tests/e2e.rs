@@ -0,0 +1,3955 @@
+//! End-to-end handler tests over a real database and Redis. They need Docker,
+//! or `TEST_DATABASE_URL` and `TEST_REDIS_URL`; run with
+//! `cargo test -p cryptotrade-api --test e2e -- --ignored`.
+
+use axum::http::StatusCode;
+use cryptotrade_core::{
+    payments::sign_bank_notification,
+    services::import_service::{parse_candles, parse_trades},
+    storage::LocalStorage,
+    Account, AccountStatus, AllocationTarget, AlertExecution, AlertExecutionStatus, AlgoOrder, ApiKeyUsage, AlgoOrderProgress, AlgoOrderStatus, AlgoStrategy, CandleUpdate, Candlestick, CopySubscription,
+    CopySubscriptionStatus, CopyTrade, CopyTradeStatus, CreateAlertTemplateRequest, CreateAlgoOrderRequest, CreateCopySubscriptionRequest, CreatedAlertTemplate,
+    CreateGridBotRequest, CreateOrderRequest, Currency, DomainEvent, EventConsumer, EventLogService, EventPublisher, OutboxMessage, OutboxService, GridBot, GridBotReport, GridBotStatus, ImportService, Indicators, Interval, LeadTrader, LedgerCompactionService,
+    BookUpdate, Capabilities, EngineStatus, ExchangeInfo, ExportFile, HistoricalBalances, LedgerEntryType, MarketData, Order, OrderBookSnapshot, OrderBookStats, OrderEvent, OrderEventType, OrderSide, OrderStatus, OrderType, Portfolio, Statement, StreamService,
+    OrderImportReport, OrderImportRowStatus, OrderImportStatus, OrderSimulation, RebalanceExecution, RebalancePlan, SetAllocationTargetsRequest, SimulatedFill, Ticker, TimeInForce, Trade, TradeBust, TradingPairListing, TradingPairStatus, TradingPairTransition, ServerTime, Tenant, UserProfile, WatchlistEntry, KycTier, AccountLoginRisk, LoginEventStatus, sign_deposit_report, CryptoWithdrawal, DepositAddress, UnmatchedDeposit,
+};
+use cryptotrade_test_support::{
+    multipart::{MultipartForm, Part},
+    count_queries, limit_order, Method, TestApp, TestResponse, TestUser, TEST_PASSWORD,
+};
+use rust_decimal::Decimal;
+use serde_json::json;
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn health_is_public() {
+    let app = TestApp::spawn().await;
+
+    app.server.get("/api/v1/health").await.assert_status_ok();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn protected_routes_require_a_token() {
+    let app = TestApp::spawn().await;
+
+    app.server
+        .get("/api/v1/user/profile")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn registered_user_can_log_in_and_read_profile() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+
+    app.server
+        .post("/api/v1/auth/login")
+        .json(&json!({ "email": alice.email, "password": TEST_PASSWORD }))
+        .await
+        .assert_status_ok();
+
+    let profile: UserProfile = app.get(&alice, "/api/v1/user/profile").await.json();
+    assert_eq!(profile.id, alice.id);
+    assert_eq!(profile.username, "alice");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn seeded_balance_shows_in_accounts_and_statement() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(1_000)).await;
+
+    let accounts: Vec<Account> = app.get(&alice, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(usd.available_balance, Some(Decimal::from(1_000)));
+
+    let statement: Statement = app
+        .get(&alice, "/api/v1/user/statement")
+        .add_query_param("currency", "USD")
+        .await
+        .json();
+    assert_eq!(statement.entries.len(), 1);
+    assert_eq!(statement.entries[0].amount, Decimal::from(1_000));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn limit_order_rests_and_can_be_cancelled() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+
+    let order: Order = app
+        .post(&alice, "/api/v1/orders")
+        .json(&CreateOrderRequest {
+            time_in_force: Some(TimeInForce::GTC),
+            ..limit_order(pair.id, OrderSide::Buy, 20_000, 0.25)
+        })
+        .await
+        .json();
+    assert_eq!(order.status, Some(OrderStatus::Open));
+
+    // The notional plus the 0.1% taker fee
+    assert_eq!(order.locked_amount, Some(Decimal::from(5_005)));
+
+    let fetched: Order = app.get(&alice, &format!("/api/v1/orders/{}", order.id)).await.json();
+    assert_eq!(fetched.id, order.id);
+
+    // A fee change while it rests does not change what it gives back
+    sqlx::query("UPDATE trading_pairs SET taker_fee = 0.005 WHERE id = $1")
+        .bind(pair.id)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let cancelled: Order = app.delete(&alice, &format!("/api/v1/orders/{}", order.id)).await.json();
+    assert_eq!(cancelled.status, Some(OrderStatus::Cancelled));
+
+    let accounts: Vec<Account> = app.get(&alice, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(usd.available_balance, Some(Decimal::from(10_000)));
+    assert_eq!(usd.locked_balance, Some(Decimal::ZERO));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn market_buys_only_spend_what_they_locked() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(10_000)).await;
+    app.post(&alice, "/api/v1/orders")
+        .json(&limit_order(pair.id, OrderSide::Sell, 20_000, 0.25))
+        .await
+        .assert_status_ok();
+    let market = |quantity| CreateOrderRequest {
+        order_type: OrderType::Market,
+        price: None,
+        ..limit_order(pair.id, OrderSide::Buy, 0, quantity)
+    };
+
+    // Its lock could not cover the part the book has no price for
+    app.post(&bob, "/api/v1/orders")
+        .json(&market(0.5))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    let order: Order = app.post(&bob, "/api/v1/orders").json(&market(0.25)).await.json();
+    assert_eq!(order.status, Some(OrderStatus::Filled));
+    let accounts: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(usd.available_balance, Some(Decimal::from(4_995)));
+    assert_eq!(usd.locked_balance, Some(Decimal::ZERO));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn other_users_orders_are_not_found() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+
+    let order: Order = app
+        .post(&alice, "/api/v1/orders")
+        .json(&limit_order(pair.id, OrderSide::Buy, 20_000, 0.1))
+        .await
+        .json();
+
+    app.get(&bob, &format!("/api/v1/orders/{}", order.id))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn sandbox_funds_users_and_quotes_a_market() {
+    let app = TestApp::spawn_with(|config| config.sandbox.enabled = true).await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    let alice = app.seed_user("alice").await;
+
+    let accounts: Vec<Account> = app.get(&alice, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(usd.available_balance, Some(Decimal::from(100_000)));
+
+    let sandbox = app.state.sandbox_service.as_ref().expect("sandbox service");
+    assert_eq!(sandbox.tick().await.expect("tick"), 10);
+
+    let order: Order = app
+        .post(&alice, "/api/v1/orders")
+        .json(&CreateOrderRequest {
+            order_type: OrderType::Market,
+            price: None,
+            ..limit_order(pair.id, OrderSide::Buy, 0, 0.01)
+        })
+        .await
+        .json();
+    assert_eq!(order.status, Some(OrderStatus::Filled));
+
+    let accounts: Vec<Account> = app.post(&alice, "/api/v1/sandbox/reset").await.json();
+    let btc = accounts.iter().find(|account| account.currency == "BTC").expect("BTC account");
+    assert_eq!(btc.available_balance, Some(Decimal::from(2)));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn sandbox_routes_are_absent_outside_sandbox_mode() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+
+    app.post(&alice, "/api/v1/sandbox/reset")
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn imported_history_backs_candlesticks() {
+    // The history is from 2024, beyond what anonymous callers may read
+    let app = TestApp::spawn_with(|config| config.market_data.anonymous.history_days = 36_500).await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    let alice = app.seed_user("alice").await;
+    let importer = ImportService::new(app.db.clone());
+
+    let candles = "timestamp,open,high,low,close,volume\n\
+        2024-01-01T00:00:00Z,100,110,95,105,12.5\n\
+        2024-01-01T01:00:00Z,105,108,101,102,3\n";
+    let (candles, _) = parse_candles(candles.as_bytes(), Interval::OneHour).unwrap();
+    assert_eq!(importer.store_candles(pair.id, Interval::OneHour, "test", &candles).await.unwrap(), 2);
+    // Storing the same dump again adds nothing
+    assert_eq!(importer.store_candles(pair.id, Interval::OneHour, "test", &candles).await.unwrap(), 0);
+
+    let trades = "timestamp,price,quantity,side\n\
+        2024-01-01T02:10:00Z,103,0.5,buy\n\
+        2024-01-01T02:40:00Z,104,0.25,sell\n";
+    let (trades, _) = parse_trades(trades.as_bytes()).unwrap();
+    assert_eq!(importer.store_trades(pair.id, "test", &trades).await.unwrap(), 2);
+
+    let candlesticks: Vec<Candlestick> = app
+        .get(&alice, &format!("/api/v1/candlesticks/{}", pair.id))
+        .add_query_params(json!({
+            "interval": "1h",
+            "start_time": "2024-01-01T00:00:00Z",
+            "end_time": "2024-01-01T03:00:00Z",
+        }))
+        .await
+        .json();
+
+    let closes: Vec<Option<Decimal>> = candlesticks.iter().map(|candle| candle.close).collect();
+    assert_eq!(closes, vec![Some(Decimal::from(105)), Some(Decimal::from(102)), Some(Decimal::from(104))]);
+    assert_eq!(candlesticks[2].volume, Some(Decimal::new(75, 2)));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn candle_windows_differing_only_in_time_have_different_etags() {
+    let app = TestApp::spawn_with(|config| config.market_data.anonymous.history_days = 36_500).await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    let alice = app.seed_user("alice").await;
+    let importer = ImportService::new(app.db.clone());
+
+    // Two identical candles an hour apart
+    let candles = "timestamp,open,high,low,close,volume\n\
+        2024-01-01T00:00:00Z,100,100,100,100,1\n\
+        2024-01-01T01:00:00Z,100,100,100,100,1\n";
+    let (candles, _) = parse_candles(candles.as_bytes(), Interval::OneHour).unwrap();
+    importer.store_candles(pair.id, Interval::OneHour, "test", &candles).await.unwrap();
+
+    let path = format!("/api/v1/candlesticks/{}", pair.id);
+    let window = |start: &str, end: &str| {
+        app.get(&alice, &path)
+            .add_query_params(json!({ "interval": "1h", "start_time": start, "end_time": end }))
+    };
+    let first = window("2024-01-01T00:00:00Z", "2024-01-01T00:30:00Z").await;
+    let etag = first.header("etag").to_str().unwrap().to_string();
+
+    // Sliding the window must not look unchanged
+    let second = window("2024-01-01T01:00:00Z", "2024-01-01T01:30:00Z")
+        .add_header("if-none-match", etag.clone())
+        .await;
+    second.assert_status_ok();
+    assert_ne!(second.header("etag").to_str().unwrap(), etag);
+    assert_eq!(first.json::<Vec<Candlestick>>().len(), 1);
+    assert_eq!(second.json::<Vec<Candlestick>>().len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn weekly_and_monthly_candles_follow_the_calendar() {
+    let app = TestApp::spawn_with(|config| config.market_data.anonymous.history_days = 36_500).await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    // 2024-01-03 is a Wednesday
+    let trades = "timestamp,price,quantity\n\
+        2024-01-03T10:00:00Z,100,1\n\
+        2024-01-07T23:59:00Z,110,1\n\
+        2024-01-08T00:00:00Z,120,1\n\
+        2024-02-01T12:00:00Z,130,1\n";
+    let (trades, _) = parse_trades(trades.as_bytes()).unwrap();
+    ImportService::new(app.db.clone()).store_trades(pair.id, "test", &trades).await.unwrap();
+    let candles = |interval: &str| {
+        app.server.get(&format!("/api/v1/candlesticks/{}", pair.id)).add_query_params(json!({
+            "interval": interval,
+            "start_time": "2024-01-04T00:00:00Z",
+            "end_time": "2024-02-29T00:00:00Z",
+        }))
+    };
+    let open_times = |candlesticks: Vec<Candlestick>| -> Vec<String> {
+        candlesticks.iter().map(|candle| candle.timestamp.unwrap().format("%Y-%m-%d").to_string()).collect()
+    };
+
+    // The start is rounded down to the Monday, taking in the whole week
+    let weeks: Vec<Candlestick> = candles("1w").await.json();
+    assert_eq!(weeks[0].volume, Some(Decimal::from(2)));
+    assert_eq!(weeks[0].interval_minutes, 10_080);
+    assert_eq!(open_times(weeks), ["2024-01-01", "2024-01-08", "2024-01-29"]);
+    let filled: Vec<Candlestick> = candles("1w").add_query_param("fill", "previous").await.json();
+    let closes: Vec<Option<Decimal>> = filled.iter().map(|candle| candle.close).collect();
+    let expected = [110, 120, 120, 120, 130, 130, 130, 130, 130].map(|close| Some(Decimal::from(close)));
+    assert_eq!(closes, expected);
+    assert_eq!(filled[2].volume, Some(Decimal::ZERO));
+    assert_eq!(open_times(filled)[2], "2024-01-15");
+    let months: Vec<Candlestick> = candles("1M").await.json();
+    assert_eq!(months[0].close, Some(Decimal::from(120)));
+    assert_eq!(open_times(months), ["2024-01-01", "2024-02-01"]);
+
+    let unsupported = candles("7m").expect_failure().await;
+    unsupported.assert_status_bad_request();
+    assert_eq!(unsupported.json::<serde_json::Value>()["code"], "VALIDATION_ERROR");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn price_band_rejects_limit_orders_far_from_the_last_trade() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(100_000)).await;
+    let limit = |side, price: i64| limit_order(pair.id, side, price, 0.1);
+    let band_path = format!("/api/v1/admin/trading-pairs/{}/price-band", pair.id);
+
+    app.server
+        .put(&band_path)
+        .authorization_bearer(&alice.access_token)
+        .json(&json!({ "price_band_percent": "10" }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+    app.server
+        .put(&band_path)
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "price_band_percent": "10" }))
+        .await
+        .assert_status_ok();
+
+    // Without a trade yet there is nothing to band against
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 20_000)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 20_000)).await.assert_status_ok();
+
+    let rejected = app
+        .post(&bob, "/api/v1/orders")
+        .json(&limit(OrderSide::Buy, 23_000))
+        .expect_failure()
+        .await;
+    rejected.assert_status_bad_request();
+    let body: serde_json::Value = rejected.json();
+    assert_eq!(body["code"], "PRICE_OUT_OF_BOUNDS");
+    assert_eq!(body["details"]["max"], "22000");
+
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 21_000)).await.assert_status_ok();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn repeated_order_is_rejected_within_the_duplicate_window() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+    let mut request = CreateOrderRequest {
+        allow_duplicate: false,
+        ..limit_order(pair.id, OrderSide::Buy, 20_000, 0.1)
+    };
+
+    app.post(&alice, "/api/v1/orders").json(&request).await.assert_status_ok();
+    let repeated = app.post(&alice, "/api/v1/orders").json(&request).expect_failure().await;
+    repeated.assert_status(StatusCode::CONFLICT);
+    assert_eq!(repeated.json::<serde_json::Value>()["code"], "DUPLICATE_ORDER");
+
+    request.allow_duplicate = true;
+    app.post(&alice, "/api/v1/orders").json(&request).await.assert_status_ok();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn admin_risk_limits_cap_open_orders() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(100_000)).await;
+    let limit = |price: i64| limit_order(pair.id, OrderSide::Buy, price, 0.1);
+
+    app.server
+        .put(&format!("/api/v1/admin/users/{}/limits", alice.id))
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "max_open_orders_per_pair": 2, "max_open_notional": "3500" }))
+        .await
+        .assert_status_ok();
+
+    app.post(&alice, "/api/v1/orders").json(&limit(20_000)).await.assert_status_ok();
+
+    // 2000 already open plus 2100 is over the 3500 notional cap
+    let rejected = app.post(&alice, "/api/v1/orders").json(&limit(21_000)).expect_failure().await;
+    rejected.assert_status_bad_request();
+    let body: serde_json::Value = rejected.json();
+    assert_eq!(body["code"], "RISK_LIMIT_EXCEEDED");
+    assert_eq!(body["details"]["limit"], "max_open_notional");
+
+    app.post(&alice, "/api/v1/orders").json(&limit(10_000)).await.assert_status_ok();
+    let rejected = app.post(&alice, "/api/v1/orders").json(&limit(1_000)).expect_failure().await;
+    let body: serde_json::Value = rejected.json();
+    assert_eq!(body["details"]["limit"], "max_open_orders_per_pair");
+
+    let limits: serde_json::Value = app.get(&alice, "/api/v1/user/limits").await.json();
+    assert_eq!(limits["max_open_orders_per_pair"], 2);
+    assert_eq!(limits["custom"], true);
+    assert_eq!(limits["pairs"][0]["open_orders"], 2);
+    assert_eq!(limits["open_notional"][0]["currency"], "USD");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn pre_trade_checks_report_every_failure() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    let limit = |side, price: i64| limit_order(pair.id, side, price, 0.1);
+
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 20_000)).await.assert_status_ok();
+
+    // Crosses her own ask, and she holds no USD to pay for it
+    let rejected = app
+        .post(&alice, "/api/v1/orders")
+        .json(&limit(OrderSide::Buy, 21_000))
+        .expect_failure()
+        .await;
+    rejected.assert_status_bad_request();
+    let body: serde_json::Value = rejected.json();
+    assert_eq!(body["code"], "PRE_TRADE_CHECKS_FAILED");
+    let codes: Vec<&str> = body["details"]["failures"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|failure| failure["code"].as_str().unwrap())
+        .collect();
+    assert_eq!(codes, vec!["INSUFFICIENT_BALANCE", "SELF_TRADE_PREVENTED"]);
+
+    let metrics = app.server.get("/api/v1/metrics").await.text();
+    assert!(metrics.contains("risk_checks_total{check=\"self_trade\",result=\"fail\"} 1"));
+    assert!(metrics.contains("dependency_circuit_state{dependency=\"redis\"} 0"));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn api_keys_are_limited_to_their_permissions_and_pairs() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+    let eth = app.seed_trading_pair("ETH-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+    let limit = |trading_pair_id| limit_order(trading_pair_id, OrderSide::Buy, 1_000, 0.1);
+    let create_key = |permissions: serde_json::Value| {
+        app.post(&alice, "/api/v1/user/api-keys").json(&json!({
+            "label": "strategy",
+            "permissions": permissions,
+            "trading_pair_ids": [btc.id],
+        }))
+    };
+
+    let trader: serde_json::Value = create_key(json!(["Read", "SpotTrade"])).await.json();
+    let reader: serde_json::Value = create_key(json!(["Read"])).await.json();
+    let order = |key: &serde_json::Value, trading_pair_id| {
+        app.signed(key, Method::POST, "/api/v1/orders", Some(&json!(limit(trading_pair_id))))
+    };
+
+    order(&trader, btc.id).await.assert_status_ok();
+
+    let other_pair = order(&trader, eth.id).expect_failure().await;
+    other_pair.assert_status(StatusCode::FORBIDDEN);
+    assert_eq!(other_pair.json::<serde_json::Value>()["code"], "TRADING_PAIR_NOT_PERMITTED");
+
+    app.signed(&reader, Method::GET, "/api/v1/orders", None).await.assert_status_ok();
+    let read_only = order(&reader, btc.id).expect_failure().await;
+    assert_eq!(read_only.json::<serde_json::Value>()["code"], "PERMISSION_DENIED");
+
+    // Keys cannot mint more keys
+    let escalate = json!({ "label": "escalate", "permissions": ["Withdraw"] });
+    app.signed(&trader, Method::POST, "/api/v1/user/api-keys", Some(&escalate))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    let key_id = trader["api_key"]["id"].as_str().unwrap();
+    app.server
+        .delete(&format!("/api/v1/user/api-keys/{}", key_id))
+        .authorization_bearer(&alice.access_token)
+        .await
+        .assert_status_ok();
+    app.signed(&trader, Method::GET, "/api/v1/orders", None)
+        .expect_failure()
+        .await
+        .assert_status_unauthorized();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn read_only_api_keys_cannot_trade_over_the_websocket() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+    let order = limit_order(pair.id, OrderSide::Buy, 1_000, 0.1);
+    let resting: Order = app.post(&alice, "/api/v1/orders").json(&order).await.json();
+    let create_key = |permissions: serde_json::Value| {
+        app.post(&alice, "/api/v1/user/api-keys")
+            .json(&json!({ "label": "socket", "permissions": permissions }))
+    };
+    let reader: serde_json::Value = create_key(json!(["Read"])).await.json();
+    let trader: serde_json::Value = create_key(json!(["Read", "SpotTrade"])).await.json();
+
+    let mut socket = app.signed_websocket(&reader, "/ws").await;
+    socket
+        .send_json(&json!({ "op": "place_order", "request_id": "place", "order": order }))
+        .await;
+    let reply: serde_json::Value = socket.receive_json().await;
+    assert_eq!(reply["type"], "reject");
+    assert_eq!(reply["request_id"], "place");
+    assert_eq!(reply["code"], "PERMISSION_DENIED");
+    socket
+        .send_json(&json!({ "op": "cancel_order", "request_id": "cancel", "order_id": resting.id }))
+        .await;
+    let reply: serde_json::Value = socket.receive_json().await;
+    assert_eq!(reply["code"], "PERMISSION_DENIED");
+    let orders: Vec<Order> = app.get(&alice, "/api/v1/orders").await.json();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].status, Some(OrderStatus::Open));
+
+    let mut socket = app.signed_websocket(&trader, "/ws").await;
+    socket
+        .send_json(&json!({ "op": "cancel_order", "request_id": "cancel", "order_id": resting.id }))
+        .await;
+    let reply: serde_json::Value = socket.receive_json().await;
+    assert_eq!(reply["type"], "ack");
+    assert_eq!(reply["order"]["status"], "Cancelled");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn compressed_websocket_frames_share_one_deflate_stream() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    let key: serde_json::Value = app
+        .post(&alice, "/api/v1/user/api-keys")
+        .json(&json!({ "label": "socket", "permissions": ["Read"] }))
+        .await
+        .json();
+
+    let mut socket = app.signed_websocket(&key, "/ws").await;
+    let mut inflater = flate2::Decompress::new(false);
+    let subscribe = |channel: String, format: serde_json::Value| {
+        let mut message = json!({ "op": "subscribe", "channels": [channel] });
+        message.as_object_mut().unwrap().extend(format.as_object().unwrap().clone());
+        message
+    };
+    let mut replies = Vec::new();
+    for message in [
+        subscribe(format!("trades:{}", pair.id), json!({ "encoding": "msgpack", "compress": true })),
+        subscribe(format!("ticker:{}", pair.id), json!({})),
+    ] {
+        socket.send_json(&message).await;
+        let mut frame = socket.receive_bytes().await.to_vec();
+        frame.extend_from_slice(&[0x00, 0x00, 0xff, 0xff]);
+        let mut payload = Vec::with_capacity(4096);
+        inflater
+            .decompress_vec(&frame, &mut payload, flate2::FlushDecompress::Sync)
+            .expect("frame inflates on the connection's stream");
+        replies.push(rmp_serde::from_slice::<serde_json::Value>(&payload).expect("MessagePack reply"));
+    }
+
+    assert_eq!(replies[0], json!({ "type": "subscribed", "channels": [format!("trades:{}", pair.id)] }));
+    assert_eq!(replies[1], json!({ "type": "subscribed", "channels": [format!("ticker:{}", pair.id)] }));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn api_keys_bound_to_an_allowlist_reject_other_addresses() {
+    let app = TestApp::spawn_with(|config| config.server.trust_forwarded_for = true).await;
+    let alice = app.seed_user("alice").await;
+    let created: serde_json::Value = app
+        .post(&alice, "/api/v1/user/api-keys")
+        .json(&json!({
+            "label": "office",
+            "permissions": ["Read"],
+            "ip_allowlist": ["203.0.113.0/24"],
+        }))
+        .await
+        .json();
+    assert_eq!(created["api_key"]["ip_allowlist"], json!(["203.0.113.0/24"]));
+    let from = |forwarded_for: &str| {
+        app.signed(&created, Method::GET, "/api/v1/orders", None)
+            .add_header("x-forwarded-for", forwarded_for.to_string())
+    };
+
+    // Only the hop appended by the proxy counts
+    from("198.51.100.1, 203.0.113.9").await.assert_status_ok();
+    let rejected = from("203.0.113.9, 198.51.100.1").expect_failure().await;
+    rejected.assert_status(StatusCode::FORBIDDEN);
+    let body: serde_json::Value = rejected.json();
+    assert_eq!(body["code"], "IP_NOT_ALLOWED");
+    assert_eq!(body["details"]["ip"], "198.51.100.1");
+
+    // Widening the allowlist takes 2FA, which alice has not set up
+    let update = app
+        .server
+        .put(&format!("/api/v1/user/api-keys/{}/ip-allowlist", created["api_key"]["id"].as_str().unwrap()))
+        .authorization_bearer(&alice.access_token)
+        .json(&json!({ "ip_allowlist": null, "totp_code": "000000" }))
+        .expect_failure()
+        .await;
+    assert_eq!(update.json::<serde_json::Value>()["code"], "TWO_FACTOR_REQUIRED");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn api_key_requests_must_be_fresh_signed_and_never_replayed() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let created: serde_json::Value = app
+        .post(&alice, "/api/v1/user/api-keys")
+        .json(&json!({ "label": "bot", "permissions": ["Read"] }))
+        .await
+        .json();
+    assert!(created["api_key"].get("secret").is_none());
+    let now = chrono::Utc::now().timestamp_millis();
+    let code = |response: TestResponse| response.json::<serde_json::Value>()["code"].clone();
+
+    app.signed_at(&created, Method::GET, "/api/v1/orders?limit=5", None, now, "nonce-0001")
+        .await
+        .assert_status_ok();
+    let replayed = app
+        .signed_at(&created, Method::GET, "/api/v1/orders?limit=5", None, now, "nonce-0001")
+        .expect_failure()
+        .await;
+    replayed.assert_status_unauthorized();
+    assert_eq!(code(replayed), "NONCE_REUSED");
+
+    let stale = app
+        .signed_at(&created, Method::GET, "/api/v1/orders", None, now - 60_000, "nonce-0002")
+        .expect_failure()
+        .await;
+    assert_eq!(code(stale), "STALE_REQUEST");
+
+    let mut forged = created.clone();
+    forged["secret"] = json!("not-the-secret");
+    let bad_signature = app
+        .signed(&forged, Method::GET, "/api/v1/orders", None)
+        .expect_failure()
+        .await;
+    assert_eq!(code(bad_signature), "INVALID_SIGNATURE");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn maintenance_mode_blocks_changes_until_lifted() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+    let order = limit_order(pair.id, OrderSide::Buy, 1_000, 0.1);
+    let update = |changes: serde_json::Value| {
+        app.server
+            .put("/api/v1/admin/runtime-config")
+            .authorization_bearer(&admin.access_token)
+            .json(&changes)
+    };
+
+    let config: serde_json::Value = update(json!({ "maintenance_mode": true, "maintenance_message": "Upgrading" }))
+        .await
+        .json();
+    assert_eq!(config["overridden"], json!(["maintenance_message", "maintenance_mode"]));
+
+    let blocked = app.post(&alice, "/api/v1/orders").json(&order).expect_failure().await;
+    blocked.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = blocked.json();
+    assert_eq!(body["code"], "MAINTENANCE_MODE");
+    assert_eq!(body["error"], "Upgrading");
+    app.get(&alice, "/api/v1/user/accounts").await.assert_status_ok();
+
+    update(json!({ "mystery": 1 })).expect_failure().await.assert_status_bad_request();
+    update(json!({ "ws_max_subscriptions": 0 })).expect_failure().await.assert_status_bad_request();
+
+    let config: serde_json::Value = update(json!({ "maintenance_mode": null, "maintenance_message": null }))
+        .await
+        .json();
+    assert_eq!(config["settings"]["maintenance_mode"], false);
+    assert_eq!(config["overridden"], json!([]));
+    app.post(&alice, "/api/v1/orders").json(&order).await.assert_status_ok();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn feature_flags_target_users_and_reset_to_default() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+
+    let flag: serde_json::Value = app
+        .server
+        .put("/api/v1/admin/feature-flags/cancel_replace")
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "enabled": false, "user_ids": [bob.id] }))
+        .await
+        .json();
+    assert_eq!(flag["overridden"], true);
+
+    let batch = json!({ "orders": [{
+        "cancel_order_id": uuid::Uuid::new_v4(),
+        "new_order": limit_order(pair.id, OrderSide::Buy, 1_000, 0.1),
+    }] });
+    let refused = app
+        .post(&alice, "/api/v1/orders/cancel-replace")
+        .json(&batch)
+        .expect_failure()
+        .await;
+    refused.assert_status(StatusCode::FORBIDDEN);
+    assert_eq!(refused.json::<serde_json::Value>()["code"], "FEATURE_DISABLED");
+
+    let features = |user| {
+        let request = app.get(user, "/api/v1/user/features");
+        async { request.await.json::<Vec<String>>() }
+    };
+    assert!(features(&alice).await.is_empty());
+    assert_eq!(features(&bob).await, vec!["cancel_replace"]);
+
+    app.server
+        .put("/api/v1/admin/feature-flags/teleport")
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "enabled": true }))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+    app.server
+        .put("/api/v1/admin/feature-flags/cancel_replace")
+        .authorization_bearer(&alice.access_token)
+        .json(&json!({ "enabled": true }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    let flag: serde_json::Value = app
+        .delete(&admin, "/api/v1/admin/feature-flags/cancel_replace")
+        .await
+        .json();
+    assert_eq!(flag["overridden"], false);
+    assert_eq!(flag["enabled"], true);
+    assert_eq!(features(&alice).await, vec!["cancel_replace"]);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn bank_transfer_is_credited_once_when_the_bank_settles_it() {
+    let app = TestApp::spawn_with(|config| {
+        config.payments.bank_transfer.enabled = true;
+        config.payments.bank_transfer.webhook_secret = "bank-secret".to_string();
+        config.payments.bank_transfer.account_number = "12345678".to_string();
+    })
+    .await;
+    let alice = app.seed_user("alice").await;
+
+    app.post(&alice, "/api/v1/payments")
+        .json(&json!({ "method": "card", "amount": "100" }))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+    app.post(&alice, "/api/v1/payments")
+        .json(&json!({ "method": "bank_transfer", "amount": "1" }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    let intent: serde_json::Value = app
+        .post(&alice, "/api/v1/payments")
+        .json(&json!({ "method": "bank_transfer", "amount": "250" }))
+        .await
+        .json();
+    assert_eq!(intent["status"], "pending");
+    let reference = intent["bank_instructions"]["reference"].as_str().unwrap().to_string();
+
+    // Less arrived than was announced; the amount received is credited
+    let notification = serde_json::to_vec(&json!({
+        "reference": reference,
+        "amount": "240.50",
+        "currency": "USD",
+        "status": "settled",
+    }))
+    .unwrap();
+    let webhook = |signature: String| {
+        app.server
+            .post("/api/v1/payments/webhooks/bank_transfer")
+            .add_header("x-bank-signature", signature)
+            .content_type("application/json")
+            .bytes(notification.clone().into())
+    };
+
+    webhook(sign_bank_notification("wrong-secret", &notification))
+        .expect_failure()
+        .await
+        .assert_status_unauthorized();
+    for _ in 0..2 {
+        webhook(sign_bank_notification("bank-secret", &notification))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+    }
+
+    let accounts: Vec<Account> = app.get(&alice, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").unwrap();
+    assert_eq!(usd.available_balance, Some(Decimal::new(24050, 2)));
+
+    let payments: serde_json::Value = app.get(&alice, "/api/v1/payments").await.json();
+    assert_eq!(payments[0]["status"], "confirmed");
+    assert_eq!(payments[0]["amount"], "240.50000000");
+    let statement: Statement = app
+        .get(&alice, "/api/v1/user/statement?currency=USD")
+        .await
+        .json();
+    assert_eq!(statement.entries.len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn fiat_withdrawal_is_paid_out_after_verification_and_approval() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    app.seed_balance(alice.id, "USD", Decimal::new(500, 0)).await;
+
+    let account: serde_json::Value = app
+        .post(&alice, "/api/v1/user/bank-accounts")
+        .json(&json!({
+            "rail": "ach",
+            "holder_name": "Alice",
+            "account_number": "12345678",
+            "routing_code": "021000021",
+        }))
+        .await
+        .json();
+    assert_eq!(account["status"], "pending_verification");
+    assert_eq!(account["account_last4"], "5678");
+    let account_id = account["id"].as_str().unwrap().to_string();
+
+    // Unverified accounts cannot be withdrawn to
+    let withdrawal = json!({ "bank_account_id": account_id, "amount": "100" });
+    app.post(&alice, "/api/v1/withdrawals/fiat")
+        .json(&withdrawal)
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    let pending: serde_json::Value = app.get(&admin, "/api/v1/admin/bank-accounts/micro-deposits").await.json();
+    assert_eq!(pending[0]["bank_account_id"], account_id.as_str());
+    let amounts = pending[0]["micro_deposits"].clone();
+    let verify_path = format!("/api/v1/user/bank-accounts/{}/verify", account_id);
+    app.post(&alice, &verify_path)
+        .json(&json!({ "amounts": ["1.00", "1.00"] }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    let verified: serde_json::Value = app.post(&alice, &verify_path).json(&json!({ "amounts": amounts })).await.json();
+    assert_eq!(verified["status"], "verified");
+
+    let usd = |accounts: Vec<Account>| accounts.into_iter().find(|account| account.currency == "USD").unwrap();
+
+    // A rejected withdrawal gives the held funds back
+    let rejected: serde_json::Value = app.post(&alice, "/api/v1/withdrawals/fiat").json(&withdrawal).await.json();
+    let held = usd(app.get(&alice, "/api/v1/user/accounts").await.json());
+    assert_eq!(held.available_balance, Some(Decimal::new(399, 0)));
+    app.post(&admin, &format!("/api/v1/admin/withdrawals/{}/review", rejected["id"].as_str().unwrap()))
+        .json(&json!({ "action": "reject", "note": "name mismatch" }))
+        .await
+        .assert_status_ok();
+    let released = usd(app.get(&alice, "/api/v1/user/accounts").await.json());
+    assert_eq!(released.available_balance, Some(Decimal::new(500, 0)));
+
+    let created: serde_json::Value = app.post(&alice, "/api/v1/withdrawals/fiat").json(&withdrawal).await.json();
+    assert_eq!(created["status"], "pending");
+    let review_path = format!("/api/v1/admin/withdrawals/{}/review", created["id"].as_str().unwrap());
+    let queue: serde_json::Value = app.get(&admin, "/api/v1/admin/withdrawals").await.json();
+    assert_eq!(queue.as_array().unwrap().len(), 1);
+
+    // Completing needs an approved withdrawal and the bank's reference
+    app.post(&admin, &review_path)
+        .json(&json!({ "action": "complete", "external_id": "ACH-1" }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    app.post(&admin, &review_path)
+        .json(&json!({ "action": "approve" }))
+        .await
+        .assert_status_ok();
+    app.delete(&alice, &format!("/api/v1/withdrawals/fiat/{}", created["id"].as_str().unwrap()))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    app.post(&admin, &review_path)
+        .json(&json!({ "action": "complete" }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    let completed: serde_json::Value = app
+        .post(&admin, &review_path)
+        .json(&json!({ "action": "complete", "external_id": "ACH-1" }))
+        .await
+        .json();
+    assert_eq!(completed["status"], "confirmed");
+
+    let paid = usd(app.get(&alice, "/api/v1/user/accounts").await.json());
+    assert_eq!(paid.balance, Some(Decimal::new(399, 0)));
+    assert_eq!(paid.available_balance, Some(Decimal::new(399, 0)));
+    assert_eq!(paid.locked_balance, Some(Decimal::ZERO));
+    let statement: Statement = app
+        .get(&alice, "/api/v1/user/statement?currency=USD")
+        .await
+        .json();
+    // The seeded adjustment, then the withdrawal and its fee
+    assert_eq!(statement.entries.len(), 3);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn deposit_receipt_is_generated_in_the_background() {
+    let app = TestApp::spawn_with(|config| {
+        config.payments.bank_transfer.enabled = true;
+        config.payments.bank_transfer.webhook_secret = "bank-secret".to_string();
+        config.payments.bank_transfer.account_number = "12345678".to_string();
+    })
+    .await;
+    let alice = app.seed_user("alice").await;
+
+    let intent: serde_json::Value = app
+        .post(&alice, "/api/v1/payments")
+        .json(&json!({ "method": "bank_transfer", "amount": "250" }))
+        .await
+        .json();
+    let receipt = json!({ "kind": "receipt", "reference_id": intent["id"] });
+
+    // Nothing to issue a receipt for until the deposit settles
+    app.post(&alice, "/api/v1/user/documents")
+        .json(&receipt)
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    let notification = serde_json::to_vec(&json!({
+        "reference": intent["bank_instructions"]["reference"],
+        "amount": "250.00",
+        "currency": "USD",
+        "status": "settled",
+    }))
+    .unwrap();
+    app.server
+        .post("/api/v1/payments/webhooks/bank_transfer")
+        .add_header("x-bank-signature", sign_bank_notification("bank-secret", &notification))
+        .content_type("application/json")
+        .bytes(notification.into())
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+
+    let document: serde_json::Value = app.post(&alice, "/api/v1/user/documents").json(&receipt).await.json();
+    assert_eq!(document["status"], "pending");
+    assert!(document["download_url"].is_null());
+    let download = format!("/api/v1/user/documents/{}/download", document["id"].as_str().unwrap());
+    app.get(&alice, &download).expect_failure().await.assert_status_bad_request();
+    // Asking again returns the same document
+    let again: serde_json::Value = app.post(&alice, "/api/v1/user/documents").json(&receipt).await.json();
+    assert_eq!(again["id"], document["id"]);
+
+    assert_eq!(app.state.document_service.generate_pending(10).await.unwrap(), 1);
+    let documents: serde_json::Value = app.get(&alice, "/api/v1/user/documents").await.json();
+    assert_eq!(documents[0]["status"], "ready");
+    assert_eq!(documents[0]["download_url"], download.as_str());
+
+    // The PDF lives in object storage, behind a presigned link
+    let redirect = app.get(&alice, &download).await;
+    redirect.assert_status(StatusCode::TEMPORARY_REDIRECT);
+    let presigned = redirect.header("location").to_str().unwrap().to_string();
+    let pdf = app.server.get(&presigned).await;
+    assert_eq!(pdf.header("content-type"), "application/pdf");
+    assert!(pdf.as_bytes().starts_with(b"%PDF-"));
+    let tampered = presigned.replace("expires=", "expires=1");
+    app.server.get(&tampered).expect_failure().await.assert_status_forbidden();
+    let bob = app.seed_user("bob").await;
+    app.get(&bob, &download).expect_failure().await.assert_status_not_found();
+
+    app.post(&alice, "/api/v1/user/documents")
+        .json(&json!({ "kind": "statement", "month": chrono::Utc::now().format("%Y-%m").to_string() }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    // The month-end run queues a statement for everyone with ledger activity
+    let statements = &app.state.document_service;
+    assert_eq!(statements.enqueue_statements(chrono::Utc::now().date_naive()).await.unwrap(), 1);
+    assert_eq!(statements.enqueue_statements(chrono::Utc::now().date_naive()).await.unwrap(), 0);
+    assert_eq!(statements.generate_pending(10).await.unwrap(), 1);
+    let documents: serde_json::Value = app.get(&alice, "/api/v1/user/documents").await.json();
+    assert_eq!(documents[0]["kind"], "statement");
+    assert_eq!(documents[0]["status"], "ready");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn restricted_accounts_wind_down_and_closed_accounts_are_read_only() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+    let buy = limit_order(pair.id, OrderSide::Buy, 20_000, 0.1);
+    let order: Order = app.post(&alice, "/api/v1/orders").json(&buy).await.json();
+
+    let set_status = |status: &str| {
+        app.server
+            .put(&format!("/api/v1/admin/users/{}/status", alice.id))
+            .authorization_bearer(&admin.access_token)
+            .json(&json!({ "status": status, "reason": "compliance review" }))
+    };
+    let change: serde_json::Value = set_status("restricted").await.json();
+    assert_eq!(change["from_status"], "active");
+    assert_eq!(change["to_status"], "restricted");
+
+    // Restricted: no new orders, but existing ones can still be cancelled
+    let rejected = app.post(&alice, "/api/v1/orders").json(&buy).expect_failure().await;
+    rejected.assert_status(StatusCode::FORBIDDEN);
+    assert_eq!(rejected.json::<serde_json::Value>()["code"], "ACCOUNT_RESTRICTED");
+    app.delete(&alice, &format!("/api/v1/orders/{}", order.id)).await.assert_status_ok();
+
+    // Closing needs an empty account and the password
+    let close = |password: &str| {
+        app.post(&alice, "/api/v1/user/close")
+            .json(&json!({ "password": password, "reason": "moving on" }))
+    };
+    close("wrong-password").expect_failure().await.assert_status_unauthorized();
+    close(TEST_PASSWORD).expect_failure().await.assert_status_bad_request();
+
+    set_status("active").await.assert_status_ok();
+    app.post(&alice, "/api/v1/orders").json(&buy).await.assert_status_ok();
+
+    let bob = app.seed_user("bob").await;
+    let closed: serde_json::Value = app
+        .post(&bob, "/api/v1/user/close")
+        .json(&json!({ "password": TEST_PASSWORD }))
+        .await
+        .json();
+    assert_eq!(closed["to_status"], "closed");
+    assert!(closed["changed_by"].is_null());
+
+    // Closed: history stays readable, nothing else is allowed
+    let profile: UserProfile = app.get(&bob, "/api/v1/user/profile").await.json();
+    assert_eq!(profile.account_status, AccountStatus::Closed);
+    app.get(&bob, "/api/v1/orders").await.assert_status_ok();
+    let rejected = app.post(&bob, "/api/v1/orders").json(&buy).expect_failure().await;
+    assert_eq!(rejected.json::<serde_json::Value>()["code"], "ACCOUNT_CLOSED");
+    app.server
+        .put(&format!("/api/v1/admin/users/{}/status", bob.id))
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "status": "active", "reason": "changed their mind" }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn restricted_jurisdictions_are_blocked_and_sanctions_matches_held_for_review() {
+    let list = std::env::temp_dir().join(format!("sanctions-{}.csv", std::process::id()));
+    std::fs::write(&list, "\"KIM, Jong Un\",DPRK\n").unwrap();
+    let app = TestApp::spawn_with(|config| {
+        config.compliance.sanctions.enabled = true;
+        config.compliance.sanctions.list_path = list.display().to_string();
+    })
+    .await;
+    let admin = app.seed_admin("admin").await;
+    let register = |username: &str, country_header: &str, body: serde_json::Value| {
+        let mut request = json!({
+            "email": format!("{}@example.com", username),
+            "username": username,
+            "password": TEST_PASSWORD,
+        });
+        request.as_object_mut().unwrap().extend(body.as_object().unwrap().clone());
+        app.server
+            .post("/api/v1/auth/register")
+            .add_header("cf-ipcountry", country_header.to_string())
+            .json(&request)
+    };
+    let assert_restricted = |response: TestResponse, country: &str| {
+        response.assert_status(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["code"], "RESTRICTED_JURISDICTION");
+        assert_eq!(body["details"]["country"], country);
+    };
+
+    // Neither the request's origin nor the declared residence may be restricted
+    assert_restricted(register("kp", "KP", json!({})).expect_failure().await, "KP");
+    assert_restricted(register("ir", "DE", json!({ "country": "ir" })).expect_failure().await, "IR");
+
+    // Without a declared country the origin is recorded
+    let auth: serde_json::Value = register("kim", "DE", json!({ "first_name": "Jong-Un", "last_name": "Kim" })).await.json();
+    assert_eq!(auth["user"]["country"], "DE");
+    assert_eq!(auth["user"]["account_status"], "restricted");
+
+    let reviews: Vec<serde_json::Value> = app
+        .server
+        .get("/api/v1/admin/compliance/reviews?status=pending")
+        .authorization_bearer(&admin.access_token)
+        .await
+        .json();
+    assert_eq!(reviews.len(), 1);
+    assert_eq!(reviews[0]["listed_name"], "KIM, Jong Un");
+    assert_eq!(reviews[0]["list_name"], "DPRK");
+    let resolve = |status: &str| {
+        app.server
+            .post(&format!("/api/v1/admin/compliance/reviews/{}/resolve", reviews[0]["id"].as_str().unwrap()))
+            .authorization_bearer(&admin.access_token)
+            .json(&json!({ "status": status, "note": "different date of birth" }))
+    };
+    let cleared: serde_json::Value = resolve("cleared").await.json();
+    assert_eq!(cleared["status"], "cleared");
+    resolve("confirmed").expect_failure().await.assert_status_bad_request();
+    let kim = app
+        .server
+        .post("/api/v1/auth/login")
+        .json(&json!({ "email": "kim@example.com", "password": TEST_PASSWORD }))
+        .await;
+    assert_eq!(kim.json::<serde_json::Value>()["user"]["account_status"], "active");
+
+    // Logging in and trading are refused from restricted countries
+    let alice = app.seed_user("alice").await;
+    let login = app
+        .server
+        .post("/api/v1/auth/login")
+        .add_header("cf-ipcountry", "CU")
+        .json(&json!({ "email": alice.email, "password": TEST_PASSWORD }))
+        .expect_failure()
+        .await;
+    assert_restricted(login, "CU");
+
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+    let buy = limit_order(pair.id, OrderSide::Buy, 20_000, 0.1);
+    let order = app
+        .post(&alice, "/api/v1/orders")
+        .add_header("cf-ipcountry", "SY")
+        .json(&buy)
+        .expect_failure()
+        .await;
+    assert_restricted(order, "SY");
+    app.post(&alice, "/api/v1/orders").json(&buy).await.assert_status_ok();
+
+    std::fs::remove_file(list).unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn large_withdrawals_wait_for_travel_rule_data() {
+    let app = TestApp::spawn_with(|config| {
+        config.compliance.travel_rule.thresholds.insert("USD".to_string(), Decimal::from(250));
+    })
+    .await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    app.seed_balance(alice.id, "USD", Decimal::new(1000, 0)).await;
+
+    let account: serde_json::Value = app
+        .post(&alice, "/api/v1/user/bank-accounts")
+        .json(&json!({
+            "rail": "ach",
+            "holder_name": "Alice Smith",
+            "account_number": "12345678",
+            "routing_code": "021000021",
+        }))
+        .await
+        .json();
+    let account_id = account["id"].as_str().unwrap().to_string();
+    let pending: serde_json::Value = app.get(&admin, "/api/v1/admin/bank-accounts/micro-deposits").await.json();
+    app.post(&alice, &format!("/api/v1/user/bank-accounts/{}/verify", account_id))
+        .json(&json!({ "amounts": pending[0]["micro_deposits"] }))
+        .await
+        .assert_status_ok();
+
+    let small: serde_json::Value = app
+        .post(&alice, "/api/v1/withdrawals/fiat")
+        .json(&json!({ "bank_account_id": account_id, "amount": "100" }))
+        .await
+        .json();
+    assert_eq!(small["travel_rule_required"], false);
+
+    let large: serde_json::Value = app
+        .post(&alice, "/api/v1/withdrawals/fiat")
+        .json(&json!({ "bank_account_id": account_id, "amount": "300" }))
+        .await
+        .json();
+    assert_eq!(large["travel_rule_required"], true);
+    assert!(large["travel_rule_status"].is_null());
+    let review_path = format!("/api/v1/admin/withdrawals/{}/review", large["id"].as_str().unwrap());
+    let travel_rule_path = format!("/api/v1/withdrawals/fiat/{}/travel-rule", large["id"].as_str().unwrap());
+    let approve = || app.post(&admin, &review_path).json(&json!({ "action": "approve" }));
+
+    // Held until the originator and beneficiary have been transmitted
+    approve().expect_failure().await.assert_status_bad_request();
+    app.get(&alice, &travel_rule_path).expect_failure().await.assert_status_not_found();
+    let info = json!({
+        "originator_address": "1 Main St, Springfield",
+        "originator_country": "US",
+        "beneficiary_address": "1 Main St, Springfield",
+        "beneficiary_country": "US",
+    });
+    // Alice's profile has no name to fall back on
+    app.post(&alice, &travel_rule_path)
+        .json(&info)
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    let mut named = info.clone();
+    named["originator_name"] = json!("Alice Smith");
+    let record: serde_json::Value = app.post(&alice, &travel_rule_path).json(&named).await.json();
+    assert_eq!(record["status"], "transmitted");
+    assert_eq!(record["provider"], "record");
+    assert_eq!(record["payload"]["beneficiary"]["name"], "Alice Smith");
+    assert_eq!(record["payload"]["beneficiary"]["institution"], "021000021");
+    app.post(&alice, &travel_rule_path)
+        .json(&named)
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    let approved: serde_json::Value = approve().await.json();
+    assert_eq!(approved["status"], "approved");
+    assert_eq!(approved["travel_rule_status"], "transmitted");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn admin_analytics_read_daily_rollups() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(100_000)).await;
+    let limit = |side| limit_order(pair.id, side, 20_000, 0.1);
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy)).await.assert_status_ok();
+
+    let today = chrono::Utc::now().date_naive().to_string();
+    let read = |report: &str| app.get(&admin, &format!("/api/v1/admin/analytics/{}?from={}", report, today));
+
+    // Nothing shows until the day is rolled up
+    let activity: Vec<serde_json::Value> = read("activity").await.json();
+    assert!(activity.is_empty());
+    app.post(&alice, "/api/v1/admin/analytics/rollup")
+        .json(&json!({ "from": today }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+    app.post(&admin, "/api/v1/admin/analytics/rollup")
+        .json(&json!({ "from": today }))
+        .await
+        .assert_status_ok();
+
+    let activity: Vec<serde_json::Value> = read("activity").await.json();
+    assert_eq!(activity.len(), 1);
+    assert_eq!(activity[0]["active_traders"], 2);
+    assert_eq!(activity[0]["new_registrations"], 3);
+
+    let volume: Vec<serde_json::Value> = read("volume").await.json();
+    assert_eq!(volume[0]["symbol"], "BTC-USD");
+    assert_eq!(volume[0]["trade_count"], 1);
+    assert_eq!(volume[0]["base_volume"], "0.10000000");
+    assert_eq!(volume[0]["quote_volume"], "2000.00000000");
+
+    let fees: Vec<serde_json::Value> = read("fees").await.json();
+    assert_eq!(fees.len(), 1);
+    assert_eq!(fees[0]["currency"], "USD");
+
+    // Seeded balances are adjustments, not deposits
+    let flows: Vec<serde_json::Value> = read("flows").await.json();
+    assert!(flows.is_empty());
+
+    app.get(&admin, "/api/v1/admin/analytics/activity?from=2020-01-01&to=2024-01-01")
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn trading_fees_collect_in_the_treasury_until_swept() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(alice.id, "USD", Decimal::from(1_000)).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(100_000)).await;
+    let limit = |side| limit_order(pair.id, side, 20_000, 0.1);
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy)).await.assert_status_ok();
+
+    // What the two sides paid beyond the trade's notional of 2000 USD
+    let usd = |accounts: Vec<Account>| {
+        accounts.into_iter().find(|account| account.currency == "USD").and_then(|account| account.balance).unwrap()
+    };
+    let alice_usd = usd(app.get(&alice, "/api/v1/user/accounts").await.json());
+    let bob_usd = usd(app.get(&bob, "/api/v1/user/accounts").await.json());
+    let fees = Decimal::from(101_000) - bob_usd - alice_usd;
+    assert!(fees > Decimal::ZERO);
+
+    let treasury: Vec<serde_json::Value> = app.get(&admin, "/api/v1/admin/treasury").await.json();
+    assert_eq!(treasury.len(), 1);
+    assert_eq!(treasury[0]["currency"], "USD");
+    let balance: Decimal = treasury[0]["balance"].as_str().unwrap().parse().unwrap();
+    assert_eq!(balance, fees);
+
+    app.post(&alice, "/api/v1/admin/treasury/USD/sweep")
+        .json(&json!({ "amount": "1", "destination": "Operating account" }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+    app.post(&admin, "/api/v1/admin/treasury/USD/sweep")
+        .json(&json!({ "amount": (fees + Decimal::ONE).to_string(), "destination": "Operating account" }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    app.post(&admin, "/api/v1/admin/treasury/BTC/sweep")
+        .json(&json!({ "amount": "1", "destination": "Operating account" }))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+
+    let sweep: serde_json::Value = app
+        .post(&admin, "/api/v1/admin/treasury/usd/sweep")
+        .json(&json!({ "amount": "1", "destination": "Operating account", "note": "Monthly sweep" }))
+        .await
+        .json();
+    assert_eq!(sweep["currency"], "USD");
+
+    let treasury: Vec<serde_json::Value> = app.get(&admin, "/api/v1/admin/treasury").await.json();
+    let balance: Decimal = treasury[0]["balance"].as_str().unwrap().parse().unwrap();
+    let swept: Decimal = treasury[0]["total_swept"].as_str().unwrap().parse().unwrap();
+    assert_eq!(balance, fees - Decimal::ONE);
+    assert_eq!(swept, Decimal::ONE);
+    let sweeps: Vec<serde_json::Value> = app.get(&admin, "/api/v1/admin/treasury/sweeps?currency=usd").await.json();
+    assert_eq!(sweeps.len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn dust_balances_convert_into_one_currency() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(alice.id, "USD", Decimal::from(1_000)).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(100_000)).await;
+    let limit = |side, quantity| limit_order(pair.id, side, 20_000, quantity);
+    // Sets the BTC-USD price and leaves bob with 0.0002 BTC, worth 4 USD
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 0.0002)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 0.0002)).await.assert_status_ok();
+
+    // Alice's remaining BTC is worth far more than the threshold
+    app.post(&alice, "/api/v1/user/dust-convert")
+        .json(&json!({ "target_currency": "USD" }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    let conversion: serde_json::Value = app
+        .post(&bob, "/api/v1/user/dust-convert")
+        .json(&json!({ "target_currency": "usd" }))
+        .await
+        .json();
+    assert_eq!(conversion["target_currency"], "USD");
+    assert_eq!(conversion["items"][0]["currency"], "BTC");
+    let decimal = |value: &serde_json::Value| value.as_str().unwrap().parse::<Decimal>().unwrap();
+    assert_eq!(decimal(&conversion["items"][0]["converted"]), Decimal::from(4));
+    // 2% of 4 USD
+    assert_eq!(decimal(&conversion["fee"]), Decimal::new(8, 2));
+
+    let accounts: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    let btc = accounts.iter().find(|account| account.currency == "BTC").expect("BTC account");
+    assert_eq!(btc.balance, Some(Decimal::ZERO));
+
+    let statement: Statement = app
+        .get(&bob, "/api/v1/user/statement")
+        .add_query_param("currency", "USD")
+        .await
+        .json();
+    let last = statement.entries.last().unwrap();
+    assert_eq!(last.entry_type, LedgerEntryType::Fee);
+    assert_eq!(last.amount, Decimal::new(-8, 2));
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(last.balance_after, usd.balance);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn scheduled_orders_stay_dormant_until_activated() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let carol = app.seed_user("carol").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(10_000)).await;
+    let buy = |activate_at, lock_funds| CreateOrderRequest {
+        activate_at: Some(activate_at),
+        lock_funds,
+        ..limit_order(pair.id, OrderSide::Buy, 20_000, 0.1)
+    };
+    let soon = chrono::Utc::now() + chrono::Duration::seconds(1);
+
+    app.post(&bob, "/api/v1/orders")
+        .json(&buy(chrono::Utc::now() - chrono::Duration::minutes(1), false))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    // A funded order holds its funds; an unfunded one holds nothing
+    let funded: Order = app.post(&bob, "/api/v1/orders").json(&buy(soon, true)).await.json();
+    assert_eq!(funded.status, Some(OrderStatus::Scheduled));
+    let later: Order = app
+        .post(&bob, "/api/v1/orders")
+        .json(&buy(chrono::Utc::now() + chrono::Duration::days(7), false))
+        .await
+        .json();
+    assert_eq!(later.scheduled_lock, None);
+    let unfunded: Order = app.post(&carol, "/api/v1/orders").json(&buy(soon, false)).await.json();
+
+    let accounts: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(usd.locked_balance, funded.scheduled_lock);
+    let holds: serde_json::Value = app.get(&bob, "/api/v1/user/accounts/USD/holds").await.json();
+    assert_eq!(holds["holds"].as_array().unwrap().len(), 1);
+
+    let cancelled: Order = app.delete(&bob, &format!("/api/v1/orders/{}", later.id)).await.json();
+    assert_eq!(cancelled.status, Some(OrderStatus::Cancelled));
+
+    // Nothing is placed early, and the book stays empty meanwhile
+    assert_eq!(app.state.order_service.activate_due_orders().await.unwrap(), 0);
+    app.post(&alice, "/api/v1/orders")
+        .json(&CreateOrderRequest {
+            side: OrderSide::Sell,
+            activate_at: None,
+            ..buy(soon, false)
+        })
+        .await
+        .assert_status_ok();
+
+    tokio::time::sleep(std::time::Duration::from_millis(1200)).await;
+    assert_eq!(app.state.order_service.activate_due_orders().await.unwrap(), 1);
+
+    let funded: Order = app.get(&bob, &format!("/api/v1/orders/{}", funded.id)).await.json();
+    assert_eq!(funded.status, Some(OrderStatus::Filled));
+    // Carol cannot pay, so her order is rejected on activation
+    let unfunded: Order = app.get(&carol, &format!("/api/v1/orders/{}", unfunded.id)).await.json();
+    assert_eq!(unfunded.status, Some(OrderStatus::Rejected));
+
+    let accounts: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(usd.locked_balance, Some(Decimal::ZERO));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn twap_orders_are_worked_in_child_slices() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(10_000)).await;
+    app.post(&alice, "/api/v1/orders")
+        .json(&limit_order(pair.id, OrderSide::Sell, 20_000, 1.0))
+        .await
+        .assert_status_ok();
+    let twap = |slices, duration_seconds| CreateAlgoOrderRequest {
+        trading_pair_id: pair.id,
+        side: OrderSide::Buy,
+        strategy: AlgoStrategy::Twap,
+        quantity: Decimal::new(2, 1),
+        limit_price: Some(Decimal::from(20_000)),
+        duration_seconds,
+        slices,
+        participation_rate: None,
+    };
+
+    app.post(&bob, "/api/v1/algo-orders")
+        .json(&twap(None, 2))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    // Two slices a second apart, the first placed at once
+    let algo_order: AlgoOrder = app.post(&bob, "/api/v1/algo-orders").json(&twap(Some(2), 2)).await.json();
+    assert_eq!(algo_order.status, AlgoOrderStatus::Active);
+    assert_eq!(app.state.algo_order_service.work_due().await.unwrap(), 1);
+    assert_eq!(app.state.algo_order_service.work_due().await.unwrap(), 0);
+    let progress: AlgoOrderProgress = app.get(&bob, &format!("/api/v1/algo-orders/{}", algo_order.id)).await.json();
+    assert_eq!(progress.children.len(), 1);
+    assert_eq!(progress.percent_complete, Decimal::from(50));
+
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert_eq!(app.state.algo_order_service.work_due().await.unwrap(), 1);
+    let progress: AlgoOrderProgress = app.get(&bob, &format!("/api/v1/algo-orders/{}", algo_order.id)).await.json();
+    assert_eq!(progress.algo_order.status, AlgoOrderStatus::Completed);
+    assert_eq!(progress.algo_order.executed_quantity, Decimal::new(2, 1));
+    assert_eq!(progress.average_price, Some(Decimal::from(20_000)));
+    assert!(progress.children.iter().all(|child| child.status == Some(OrderStatus::Filled)));
+
+    // Cancelling the parent stops further slices
+    let algo_order: AlgoOrder = app.post(&bob, "/api/v1/algo-orders").json(&twap(Some(4), 60)).await.json();
+    assert_eq!(app.state.algo_order_service.work_due().await.unwrap(), 1);
+    let cancelled: AlgoOrderProgress = app.delete(&bob, &format!("/api/v1/algo-orders/{}", algo_order.id)).await.json();
+    assert_eq!(cancelled.algo_order.status, AlgoOrderStatus::Cancelled);
+    assert_eq!(cancelled.algo_order.executed_quantity, Decimal::new(5, 2));
+    app.delete(&bob, &format!("/api/v1/algo-orders/{}", algo_order.id))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    let listed: Vec<AlgoOrder> = app.get(&bob, "/api/v1/algo-orders").await.json();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(app.state.algo_order_service.work_due().await.unwrap(), 0);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn grid_bots_refill_their_ladder_as_orders_fill() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let carol = app.seed_user("carol").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(10_000)).await;
+    app.seed_balance(carol.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(carol.id, "USD", Decimal::from(10_000)).await;
+    let limit = |side, price| limit_order(pair.id, side, price, 0.05);
+    let grid = CreateGridBotRequest {
+        trading_pair_id: pair.id,
+        lower_price: Decimal::from(19_000),
+        upper_price: Decimal::from(21_000),
+        grid_count: 4,
+        investment: Decimal::from(4_000),
+    };
+
+    // Without a last price there is nothing to centre the grid on
+    app.post(&carol, "/api/v1/grid-bots").json(&grid).expect_failure().await.assert_status_bad_request();
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 20_000)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 20_000)).await.assert_status_ok();
+
+    // Buys at 19000 and 19500, sells at 20500 and 21000
+    let report: GridBotReport = app.post(&carol, "/api/v1/grid-bots").json(&grid).await.json();
+    assert_eq!(report.bot.status, GridBotStatus::Running);
+    assert_eq!(report.bot.quantity_per_grid, Decimal::new(5, 2));
+    let prices: Vec<_> = report.open_orders.iter().map(|order| order.price.unwrap()).collect();
+    assert_eq!(prices, [19_000, 19_500, 20_500, 21_000].map(Decimal::from));
+    let bot_path = format!("/api/v1/grid-bots/{}", report.bot.id);
+
+    // The sell at 20500 fills and is replaced by a buy at 20000; that buy
+    // filling completes a round trip worth one step
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 20_500)).await.assert_status_ok();
+    assert_eq!(app.state.grid_bot_service.rebalance().await.unwrap(), 1);
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 20_000)).await.assert_status_ok();
+    assert_eq!(app.state.grid_bot_service.rebalance().await.unwrap(), 1);
+    assert_eq!(app.state.grid_bot_service.rebalance().await.unwrap(), 0);
+
+    let report: GridBotReport = app.get(&carol, &bot_path).await.json();
+    assert_eq!(report.bot.completed_cycles, 1);
+    assert_eq!(report.bot.realized_pnl, Decimal::from(25));
+    assert_eq!(report.open_orders.len(), 4);
+    assert_eq!(report.quote_received - report.quote_spent, Decimal::from(25));
+    assert_eq!(report.total_pnl, Some(Decimal::from(25)));
+
+    let report: GridBotReport = app.post(&carol, &format!("{}/stop", bot_path)).await.json();
+    assert_eq!(report.bot.status, GridBotStatus::Stopped);
+    assert!(report.open_orders.is_empty());
+    let accounts: Vec<Account> = app.get(&carol, "/api/v1/user/accounts").await.json();
+    assert!(accounts.iter().all(|account| account.locked_balance == Some(Decimal::ZERO)));
+
+    let report: GridBotReport = app.post(&carol, &format!("{}/start", bot_path)).await.json();
+    assert_eq!(report.bot.status, GridBotStatus::Running);
+    assert_eq!(report.open_orders.len(), 4);
+    let bots: Vec<GridBot> = app.get(&carol, "/api/v1/grid-bots").await.json();
+    assert_eq!(bots.len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn followers_mirror_lead_fills_and_pay_performance_fees() {
+    let app = TestApp::spawn().await;
+    let lead = app.seed_user("alice").await;
+    let follower = app.seed_user("bob").await;
+    let maker = app.seed_user("carol").await;
+    let bidder = app.seed_user("dave").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(lead.id, "USD", Decimal::from(10_000)).await;
+    app.seed_balance(lead.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(follower.id, "USD", Decimal::from(10_000)).await;
+    app.seed_balance(maker.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bidder.id, "USD", Decimal::from(50_000)).await;
+    let limit = |side, quantity, price| limit_order(pair.id, side, price, quantity);
+
+    let listing: LeadTrader = app
+        .post(&lead, "/api/v1/copy-trading/leads")
+        .json(&json!({ "display_name": "Alice", "performance_fee_rate": "0.2" }))
+        .await
+        .json();
+    assert_eq!(listing.followers, 0);
+    let subscribe = |lead_id| CreateCopySubscriptionRequest {
+        lead_id,
+        budget_currency: "USD".to_string(),
+        budget: Decimal::from(5_000),
+        max_trade_notional: Some(Decimal::from(220)),
+        stop_loss: None,
+    };
+    app.post(&lead, "/api/v1/copy-trading/subscriptions")
+        .json(&subscribe(lead.id))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    let subscription: CopySubscription =
+        app.post(&follower, "/api/v1/copy-trading/subscriptions").json(&subscribe(lead.id)).await.json();
+    let leads: Vec<LeadTrader> = app.get(&follower, "/api/v1/copy-trading/leads").await.json();
+    assert_eq!(leads[0].followers, 1);
+
+    // The lead buys at 20000; the follower's copy is capped at 220 USD
+    app.post(&maker, "/api/v1/orders").json(&limit(OrderSide::Sell, 1.0, 20_000)).await.assert_status_ok();
+    app.post(&lead, "/api/v1/orders").json(&limit(OrderSide::Buy, 0.2, 20_000)).await.assert_status_ok();
+    assert_eq!(app.state.copy_trading_service.mirror_fills().await.unwrap(), 1);
+    assert_eq!(app.state.copy_trading_service.mirror_fills().await.unwrap(), 0);
+
+    // The lead sells at 22000; the follower sells 220 USD worth of the
+    // 0.011 BTC it bought, realizing 20 USD and paying the lead a fifth
+    app.post(&bidder, "/api/v1/orders").json(&limit(OrderSide::Buy, 1.0, 22_000)).await.assert_status_ok();
+    app.post(&lead, "/api/v1/orders").json(&limit(OrderSide::Sell, 0.2, 22_000)).await.assert_status_ok();
+    assert_eq!(app.state.copy_trading_service.mirror_fills().await.unwrap(), 1);
+
+    let trades_path = format!("/api/v1/copy-trading/subscriptions/{}/trades", subscription.id);
+    let trades: Vec<CopyTrade> = app.get(&follower, &trades_path).await.json();
+    assert_eq!(trades.len(), 2);
+    assert!(trades.iter().all(|trade| trade.status == CopyTradeStatus::Filled));
+    assert_eq!(trades[1].quantity, Decimal::new(11, 3));
+    assert_eq!(trades[0].quantity, Decimal::new(1, 2));
+    assert_eq!(trades[0].performance_fee, Decimal::from(4));
+    let subscriptions: Vec<CopySubscription> = app.get(&follower, "/api/v1/copy-trading/subscriptions").await.json();
+    assert_eq!(subscriptions[0].realized_pnl, Decimal::from(20));
+    assert_eq!(subscriptions[0].fees_paid, Decimal::from(4));
+
+    // Leaving stops the subscription, which the follower can then close
+    app.delete(&lead, "/api/v1/copy-trading/leads").await.assert_status_ok();
+    let subscriptions: Vec<CopySubscription> = app.get(&follower, "/api/v1/copy-trading/subscriptions").await.json();
+    assert_eq!(subscriptions[0].status, CopySubscriptionStatus::Stopped);
+    let closed: CopySubscription = app
+        .delete(&follower, &format!("/api/v1/copy-trading/subscriptions/{}", subscription.id))
+        .await
+        .json();
+    assert_eq!(closed.status, CopySubscriptionStatus::Closed);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn market_data_is_rate_limited_with_more_allowed_to_api_keys() {
+    let app = TestApp::spawn_with(|config| {
+        config.server.trust_forwarded_for = true;
+        config.market_data.anonymous.requests_per_minute = 2;
+        config.market_data.api_key_daily_quota = 3;
+    })
+    .await;
+    let alice = app.seed_user("alice").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+    // Counters live in Redis, which other runs share, so each run is a
+    // fresh address
+    let ip = format!("2001:db8::{:x}", uuid::Uuid::new_v4().as_u128() as u16);
+    let anonymous = |path: &str| app.server.get(path).add_header("x-forwarded-for", ip.clone());
+    let order_book = format!("/api/v1/order-book/{}", btc.id);
+
+    let first = anonymous(&order_book).await;
+    first.assert_status_ok();
+    assert_eq!(first.header("x-ratelimit-limit"), "2");
+    assert!(first.header("x-ratelimit-reset").to_str().unwrap().parse::<i64>().is_ok());
+
+    // A minute boundary may reset the count along the way
+    let mut limited = None;
+    for _ in 0..10 {
+        let response = anonymous(&order_book).await;
+        if response.status_code() == StatusCode::TOO_MANY_REQUESTS {
+            limited = Some(response);
+            break;
+        }
+    }
+    let limited = limited.expect("anonymous requests were never limited");
+    assert_eq!(limited.json::<serde_json::Value>()["code"], "RATE_LIMITED");
+    assert!(limited.header("retry-after").to_str().unwrap().parse::<u64>().unwrap() <= 60);
+
+    let old = (chrono::Utc::now() - chrono::Duration::days(60)).to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let candles = format!("/api/v1/candlesticks/{}?start_time={}", btc.id, old);
+    let other_ip = format!("2001:db8::1:{:x}", uuid::Uuid::new_v4().as_u128() as u16);
+    app.server
+        .get(&candles)
+        .add_header("x-forwarded-for", other_ip)
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    let key: serde_json::Value = app
+        .post(&alice, "/api/v1/user/api-keys")
+        .json(&json!({ "label": "feed", "permissions": ["Read"] }))
+        .await
+        .json();
+    let keyed = app.signed(&key, Method::GET, &order_book, None).await;
+    keyed.assert_status_ok();
+    assert_eq!(keyed.header("x-ratelimit-limit"), "1200");
+    app.signed(&key, Method::GET, &candles, None).await.assert_status_ok();
+    app.signed(&key, Method::GET, &format!("/api/v1/trades/{}?limit=1000", btc.id), None)
+        .await
+        .assert_status_ok();
+    let over_quota = app.signed(&key, Method::GET, &order_book, None).expect_failure().await;
+    over_quota.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(over_quota.json::<serde_json::Value>()["details"]["window"], "day");
+
+    let usage: Vec<ApiKeyUsage> = app.get(&alice, "/api/v1/user/api-usage").await.json();
+    assert_eq!(usage.len(), 1);
+    assert_eq!(usage[0].key_id.to_string(), key["api_key"]["id"].as_str().unwrap());
+    assert_eq!(usage[0].daily_quota, 3);
+    assert_eq!(usage[0].used_today, 4);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn tickers_cover_every_pair_in_one_etagged_response() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+    app.seed_trading_pair("ETH-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(100_000)).await;
+    let limit = |side, quantity, price| limit_order(btc.id, side, price, quantity);
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 0.5, 20_000)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 0.1, 20_000)).await.assert_status_ok();
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 0.1, 21_000)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 0.5, 21_000)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 0.1, 19_000)).await.assert_status_ok();
+
+    let response = app.server.get("/api/v1/tickers").await;
+    response.assert_status_ok();
+    assert_eq!(response.header("cache-control"), "public, max-age=2");
+    let tickers: Vec<Ticker> = response.json();
+    let symbols: Vec<&str> = tickers.iter().map(|ticker| ticker.symbol.as_str()).collect();
+    assert_eq!(symbols, ["BTC-USD", "ETH-USD"]);
+
+    let ticker = &tickers[0];
+    assert_eq!(ticker.open_price, Some(Decimal::from(20_000)));
+    assert_eq!(ticker.last_price, Some(Decimal::from(21_000)));
+    assert_eq!(ticker.price_change, Decimal::from(1_000));
+    assert_eq!(ticker.price_change_percent, Decimal::from(5));
+    assert_eq!(ticker.volume, Decimal::new(6, 1));
+    assert_eq!(ticker.trade_count, 3);
+    assert_eq!(ticker.bid_price, Some(Decimal::from(19_000)));
+    assert_eq!(ticker.ask_price, None);
+    assert_eq!(tickers[1].last_price, None);
+    assert_eq!(tickers[1].trade_count, 0);
+
+    let etag = response.header("etag").to_str().unwrap().to_string();
+    app.server
+        .get("/api/v1/tickers")
+        .add_header("if-none-match", etag)
+        .await
+        .assert_status(StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn fills_stream_live_candles_for_every_interval() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(100_000)).await;
+    let limit = |side, quantity| limit_order(pair.id, side, 20_000, quantity);
+    let mut feed = app.state.stream_service.subscribe();
+
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 0.5)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 0.2)).await.assert_status_ok();
+
+    let channel = StreamService::candles_channel(pair.id, Interval::OneMinute);
+    let candle = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            let message = feed.recv().await.unwrap();
+            if message.channel == channel {
+                break serde_json::from_value::<CandleUpdate>(message.data).unwrap();
+            }
+        }
+    })
+    .await
+    .expect("no candle update was published");
+
+    assert_eq!(candle.trading_pair_id, pair.id);
+    assert_eq!(candle.close, Decimal::from(20_000));
+    assert_eq!(candle.volume, Decimal::new(2, 1));
+    assert_eq!(candle.trade_count, 1);
+    assert_eq!(candle.close_time - candle.open_time, chrono::Duration::minutes(1));
+    assert!(!candle.closed);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn indicators_cover_a_full_window_at_every_point() {
+    let app = TestApp::spawn_with(|config| config.market_data.anonymous.history_days = 36_500).await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    let trades = "timestamp,price,quantity\n\
+        2024-01-01T10:00:00Z,100,1\n\
+        2024-01-02T10:00:00Z,102,1\n\
+        2024-01-03T10:00:00Z,104,1\n\
+        2024-01-04T10:00:00Z,106,1\n\
+        2024-01-06T10:00:00Z,110,1\n";
+    let (trades, _) = parse_trades(trades.as_bytes()).unwrap();
+    ImportService::new(app.db.clone()).store_trades(pair.id, "test", &trades).await.unwrap();
+    let indicators = |window: u32| {
+        app.server.get(&format!("/api/v1/indicators/{}", pair.id)).add_query_params(json!({
+            "interval": "1d",
+            "window": window,
+            "limit": 4,
+            "end_time": "2024-01-06T12:00:00Z",
+        }))
+    };
+
+    let response: Indicators = indicators(3).await.json();
+    assert_eq!(response.interval, Interval::OneDay);
+    // The first point's window reaches back to the first day
+    let points = response.points;
+    assert_eq!(points.len(), 4);
+    assert_eq!(points[0].timestamp.format("%Y-%m-%d").to_string(), "2024-01-03");
+    assert_eq!(points[0].sma, Some(Decimal::from(102)));
+    // The quiet fifth day counts at the fourth's close, without volume
+    assert_eq!(points[2].close, Decimal::from(106));
+    assert_eq!(points[3].sma, Some(Decimal::new(10733333333, 8)));
+    assert_eq!(points[3].vwap, Some(Decimal::from(108)));
+    assert!(points.iter().all(|point| point.ema.is_some() && point.atr.is_some()));
+
+    let too_short = indicators(1).expect_failure().await;
+    too_short.assert_status_bad_request();
+    assert_eq!(too_short.json::<serde_json::Value>()["code"], "VALIDATION_ERROR");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn order_book_stats_report_spread_and_liquidity_near_mid() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::from(10)).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(1_000_000)).await;
+    let limit = |side, quantity, price| limit_order(btc.id, side, price, quantity);
+    let stats_path = format!("/api/v1/order-book/{}/stats", btc.id);
+
+    let empty: OrderBookStats = app.server.get(&stats_path).await.json();
+    assert_eq!(empty.mid_price, None);
+    assert!(empty.depth.is_empty());
+
+    // Mid 20,000: the bands reach 100, 200 and 400 either side
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 1.0, 19_900)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 2.0, 19_700)).await.assert_status_ok();
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 0.5, 20_100)).await.assert_status_ok();
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 3.0, 21_000)).await.assert_status_ok();
+
+    let stats: OrderBookStats = app.server.get(&stats_path).await.json();
+    assert_eq!(stats.symbol, "BTC-USD");
+    assert_eq!(stats.mid_price, Some(Decimal::from(20_000)));
+    assert_eq!(stats.spread, Some(Decimal::from(200)));
+    assert_eq!(stats.spread_percent, Some(Decimal::ONE));
+    let percents: Vec<Decimal> = stats.depth.iter().map(|band| band.percent).collect();
+    assert_eq!(percents, [Decimal::new(5, 1), Decimal::ONE, Decimal::TWO]);
+    // The best prices sit on the edge of the narrowest band, which includes them
+    assert_eq!(stats.depth[0].bid_quantity, Decimal::ONE);
+    assert_eq!(stats.depth[0].ask_notional, Decimal::from(10_050));
+    assert_eq!(stats.depth[1].bid_quantity, Decimal::ONE);
+    assert_eq!(stats.depth[2].bid_quantity, Decimal::from(3));
+    assert_eq!(stats.depth[2].ask_quantity, Decimal::new(5, 1));
+
+    app.server
+        .get(&format!("/api/v1/order-book/{}/stats", uuid::Uuid::new_v4()))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn busting_a_trade_reverses_its_settlement_and_fills() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+    for (user, currency, amount) in [(&alice, "BTC", 1), (&alice, "USD", 0), (&bob, "BTC", 0), (&bob, "USD", 100_000)] {
+        app.seed_balance(user.id, currency, Decimal::from(amount)).await;
+    }
+    let limit = |side, quantity| limit_order(btc.id, side, 20_000, quantity);
+    let sell: Order = app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 1.0)).await.json();
+    let buy: Order = app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 0.5)).await.json();
+    let trades: Vec<Trade> = app.get(&bob, "/api/v1/trades").await.json();
+    let trade_id = trades[0].id;
+    let bust_path = format!("/api/v1/admin/trades/{}/bust", trade_id);
+    let bust = |user: &TestUser, reason: &str| {
+        app.server
+            .post(&bust_path)
+            .authorization_bearer(&user.access_token)
+            .json(&json!({ "reason": reason }))
+    };
+
+    bust(&bob, "mine").expect_failure().await.assert_status_forbidden();
+    bust(&admin, " ").expect_failure().await.assert_status_bad_request();
+    let busted: TradeBust = bust(&admin, "Fat-finger price").await.json();
+    assert_eq!(busted.trade.id, trade_id);
+    assert!(busted.trade.busted_at.is_some());
+    assert_eq!(busted.busted_by, admin.id);
+    bust(&admin, "again").expect_failure().await.assert_status_bad_request();
+
+    let balance = |accounts: &[Account], currency: &str| {
+        let account = accounts.iter().find(|account| account.currency == currency).expect("account");
+        (account.balance, account.available_balance)
+    };
+    let bobs: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    assert_eq!(balance(&bobs, "USD"), (Some(Decimal::from(100_000)), Some(Decimal::from(100_000))));
+    assert_eq!(balance(&bobs, "BTC"), (Some(Decimal::ZERO), Some(Decimal::ZERO)));
+    // Half of Alice's BTC is still locked by her resting sell
+    let alices: Vec<Account> = app.get(&alice, "/api/v1/user/accounts").await.json();
+    assert_eq!(balance(&alices, "BTC"), (Some(Decimal::ONE), Some(Decimal::new(5, 1))));
+    assert_eq!(balance(&alices, "USD"), (Some(Decimal::ZERO), Some(Decimal::ZERO)));
+
+    // The filled buy ends cancelled; the sell keeps resting with what was left
+    let buy: Order = app.get(&bob, &format!("/api/v1/orders/{}", buy.id)).await.json();
+    assert_eq!(buy.status, Some(OrderStatus::Cancelled));
+    assert_eq!(buy.filled_quantity, Some(Decimal::ZERO));
+    let sell: Order = app.get(&alice, &format!("/api/v1/orders/{}", sell.id)).await.json();
+    assert_eq!(sell.status, Some(OrderStatus::Open));
+    assert_eq!(sell.remaining_quantity, Some(Decimal::new(5, 1)));
+
+    let public: Vec<Trade> = app.server.get(&format!("/api/v1/trades/{}", btc.id)).await.json();
+    assert!(public.is_empty());
+    // The trade and its fee, then their reversal
+    let statement: Statement = app.get(&bob, "/api/v1/user/statement?currency=USD").await.json();
+    let amounts: Vec<Decimal> = statement.entries.iter().skip(1).map(|entry| entry.amount).collect();
+    assert_eq!(amounts, [-10_000, -10, 10_000, 10].map(Decimal::from));
+    assert!(statement.entries[3..].iter().all(|entry| entry.journal_id == busted.journal_id));
+    assert_eq!(statement.entries[4].balance_after, Some(Decimal::from(100_000)));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn order_history_records_each_transition_with_its_actor() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(100_000)).await;
+    let limit = |side, quantity| limit_order(btc.id, side, 20_000, quantity);
+    let sell: Order = app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 1.0)).await.json();
+    let buy: Order = app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 0.4)).await.json();
+    let trades: Vec<Trade> = app.get(&bob, "/api/v1/trades").await.json();
+    let trade_id = trades[0].id;
+    app.server
+        .post(&format!("/api/v1/admin/trades/{}/bust", trade_id))
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "reason": "Off-market price" }))
+        .await
+        .assert_status_ok();
+    app.delete(&alice, &format!("/api/v1/orders/{}", sell.id)).await.assert_status_ok();
+
+    let sells: Vec<OrderEvent> = app.get(&alice, &format!("/api/v1/orders/{}/history", sell.id)).await.json();
+    let kinds: Vec<_> = sells.iter().map(|event| (event.event_type, event.status, event.actor_id)).collect();
+    assert_eq!(
+        kinds,
+        [
+            (OrderEventType::Created, OrderStatus::Pending, Some(alice.id)),
+            (OrderEventType::Accepted, OrderStatus::Open, None),
+            (OrderEventType::PartiallyFilled, OrderStatus::PartiallyFilled, None),
+            (OrderEventType::FillBusted, OrderStatus::Open, Some(admin.id)),
+            (OrderEventType::Cancelled, OrderStatus::Cancelled, Some(alice.id)),
+        ]
+    );
+    assert_eq!(sells[2].trade_id, Some(trade_id));
+    assert_eq!(sells[2].remaining_quantity, Some(Decimal::new(6, 1)));
+    assert_eq!(sells[3].reason.as_deref(), Some("Off-market price"));
+    assert_eq!(sells[3].filled_quantity, Some(Decimal::ZERO));
+
+    let buys: Vec<OrderEvent> = app.get(&bob, &format!("/api/v1/orders/{}/history", buy.id)).await.json();
+    let kinds: Vec<_> = buys.iter().map(|event| event.event_type).collect();
+    assert_eq!(
+        kinds,
+        [OrderEventType::Created, OrderEventType::Accepted, OrderEventType::Filled, OrderEventType::FillBusted]
+    );
+    assert_eq!(buys.last().map(|event| event.status), Some(OrderStatus::Cancelled));
+
+    app.get(&bob, &format!("/api/v1/orders/{}/history", sell.id))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn concurrent_cancels_release_an_order_once() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+    let order: Order = app
+        .post(&alice, "/api/v1/orders")
+        .json(&limit_order(pair.id, OrderSide::Buy, 20_000, 0.25))
+        .await
+        .json();
+    assert_eq!(order.version, 1);
+
+    let path = format!("/api/v1/orders/{}", order.id);
+    let (first, second) = tokio::join!(app.delete(&alice, &path), app.delete(&alice, &path));
+    let mut statuses = [first.status_code(), second.status_code()];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::BAD_REQUEST]);
+
+    let accounts: Vec<Account> = app.get(&alice, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(usd.available_balance, Some(Decimal::from(10_000)));
+    assert_eq!(usd.locked_balance, Some(Decimal::ZERO));
+
+    let history: Vec<OrderEvent> = app.get(&alice, &format!("{}/history", path)).await.json();
+    let cancels = history.iter().filter(|event| event.event_type == OrderEventType::Cancelled).count();
+    assert_eq!(cancels, 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn buying_an_asset_never_held_opens_its_account() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let sol = app.seed_trading_pair("SOL-USD").await;
+    app.seed_balance(alice.id, "SOL", Decimal::from(10)).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(1_000)).await;
+    let accounts: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    assert!(accounts.iter().all(|account| account.currency != "SOL"));
+
+    let limit = |side| limit_order(sol.id, side, 100, 4.0);
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy)).await.assert_status_ok();
+
+    let accounts: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    let bought = accounts.iter().find(|account| account.currency == "SOL").expect("SOL account");
+    assert_eq!(bought.balance, Some(Decimal::from(4)));
+    assert_eq!(bought.available_balance, Some(Decimal::from(4)));
+    let statement: Statement = app
+        .get(&bob, "/api/v1/user/statement")
+        .add_query_param("currency", "SOL")
+        .await
+        .json();
+    assert_eq!(statement.entries.len(), 1);
+    assert_eq!(statement.entries[0].balance_after, Some(Decimal::from(4)));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn listed_currencies_drive_default_accounts_and_valuation() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+
+    let listed: Currency = app
+        .post(&admin, "/api/v1/admin/currencies")
+        .json(&json!({ "code": "sol", "name": "Solana", "decimals": 9, "open_by_default": true, "usd_price": "150" }))
+        .await
+        .json();
+    assert_eq!(listed.code, "SOL");
+    app.post(&alice, "/api/v1/admin/currencies")
+        .json(&json!({ "code": "XRP", "name": "XRP", "decimals": 6 }))
+        .expect_failure()
+        .await
+        .assert_status_forbidden();
+    app.server
+        .put("/api/v1/admin/currencies/USDT")
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "open_by_default": false }))
+        .await
+        .assert_status_ok();
+
+    let bob = app.seed_user("bob").await;
+    let accounts: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    let mut currencies: Vec<_> = accounts.iter().map(|account| account.currency.as_str()).collect();
+    currencies.sort_unstable();
+    assert_eq!(currencies, ["BTC", "ETH", "SOL", "USD"]);
+    let before: Vec<Account> = app.get(&alice, "/api/v1/user/accounts").await.json();
+    assert!(before.iter().all(|account| account.currency != "SOL"));
+
+    app.seed_balance(bob.id, "SOL", Decimal::from(2)).await;
+    let portfolio: Portfolio = app.get(&bob, "/api/v1/portfolio").await.json();
+    assert_eq!(portfolio.total_value_usd, Decimal::from(300));
+
+    let currencies: Vec<Currency> = app.server.get("/api/v1/currencies").await.json();
+    let usdt = currencies.iter().find(|currency| currency.code == "USDT").expect("USDT listed");
+    assert!(!usdt.open_by_default);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn pairs_open_through_a_call_auction_and_delisting_releases_orders() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let sol = app.seed_trading_pair("SOL-USD").await;
+    app.seed_balance(alice.id, "SOL", Decimal::from(10)).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(1_000)).await;
+    let status_path = format!("/api/v1/admin/trading-pairs/{}/status", sol.id);
+    let set_status = |status: &str| {
+        app.server
+            .put(&status_path)
+            .authorization_bearer(&admin.access_token)
+            .json(&json!({ "status": status }))
+    };
+
+    set_status("call_auction").await.assert_status_ok();
+    let limit = |side, quantity, price| limit_order(sol.id, side, price, quantity);
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 4.0, 100)).await.assert_status_ok();
+    let crossing: Order = app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 3.0, 105)).await.json();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 2.0, 95)).await.assert_status_ok();
+    let resting: Order = app.get(&bob, &format!("/api/v1/orders/{}", crossing.id)).await.json();
+    assert_eq!(resting.status, Some(OrderStatus::Open));
+
+    // 100 and 105 both execute 3, leaving 1 unsold; ties on surplus go to
+    // the lower price when sellers are left over
+    let opened: TradingPairTransition = set_status("trading").await.json();
+    assert_eq!(opened.trading_pair.status, TradingPairStatus::Trading);
+    assert_eq!(opened.opening_price, Some(Decimal::from(100)));
+    assert_eq!(opened.opening_quantity, Some(Decimal::from(3)));
+    let trades: Vec<Trade> = app.get(&bob, "/api/v1/trades").await.json();
+    assert_eq!(trades.len(), 1);
+    assert_eq!(trades[0].price, Some(Decimal::from(100)));
+
+    set_status("halt").await.assert_status_ok();
+    set_status("trading").expect_failure().await.assert_status_bad_request();
+    app.post(&bob, "/api/v1/orders")
+        .json(&limit(OrderSide::Buy, 1.0, 100))
+        .expect_failure()
+        .await
+        .assert_status_forbidden();
+
+    let delisted: TradingPairTransition = set_status("delisting").await.json();
+    assert_eq!(delisted.trading_pair.status, TradingPairStatus::Delisted);
+    assert_eq!(delisted.changes.len(), 2);
+    assert_eq!(delisted.cancelled_orders, 2);
+    for (user, currency, balance) in [(&alice, "SOL", 7), (&bob, "SOL", 3)] {
+        let accounts: Vec<Account> = app.get(user, "/api/v1/user/accounts").await.json();
+        let account = accounts.iter().find(|account| account.currency == currency).expect("account");
+        assert_eq!(account.balance, Some(Decimal::from(balance)));
+        assert_eq!(account.locked_balance, Some(Decimal::ZERO));
+    }
+    let accounts: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(usd.locked_balance, Some(Decimal::ZERO));
+    assert_eq!(usd.available_balance, usd.balance);
+    set_status("trading").expect_failure().await.assert_status_bad_request();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn pairs_take_orders_only_during_their_trading_sessions() {
+    use chrono::{Datelike, Utc};
+
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let aapl = app.seed_trading_pair("AAPL-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(1_000)).await;
+    let sessions_path = format!("/api/v1/admin/trading-pairs/{}/sessions", aapl.id);
+    let set_sessions = |sessions| {
+        app.server
+            .put(&sessions_path)
+            .authorization_bearer(&admin.access_token)
+            .json(&json!({ "sessions": sessions }))
+    };
+    let buy = limit_order(aapl.id, OrderSide::Buy, 190, 1.0);
+
+    let tomorrow = Utc::now().weekday().succ().number_from_monday();
+    set_sessions(json!([
+        { "weekday": tomorrow, "opens_at": "14:30:00", "closes_at": "16:00:00" },
+        { "weekday": tomorrow, "opens_at": "15:00:00", "closes_at": "21:00:00" }
+    ]))
+    .expect_failure()
+    .await
+    .assert_status_bad_request();
+    set_sessions(json!([{ "weekday": tomorrow, "opens_at": "14:30:00", "closes_at": "21:00:00" }]))
+        .await
+        .assert_status_ok();
+
+    let pairs: Vec<TradingPairListing> = app.server.get("/api/v1/trading-pairs").await.json();
+    let listed = pairs.iter().find(|pair| pair.trading_pair.id == aapl.id).expect("pair listed");
+    assert_eq!(listed.sessions.len(), 1);
+    assert!(!listed.in_session);
+    let closed = app.post(&alice, "/api/v1/orders").json(&buy).expect_failure().await;
+    closed.assert_status_forbidden();
+    assert_eq!(closed.json::<serde_json::Value>()["code"], "OUTSIDE_TRADING_SESSION");
+
+    set_sessions(json!([])).await.assert_status_ok();
+    app.post(&alice, "/api/v1/orders").json(&buy).await.assert_status_ok();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn error_messages_follow_the_profile_then_accept_language() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let order_of_nobody = format!("/api/v1/orders/{}", uuid::Uuid::new_v4());
+    let missing_order = |accept_language: &'static str| {
+        app.get(&alice, &order_of_nobody)
+            .add_header("accept-language", accept_language)
+            .expect_failure()
+    };
+
+    let english: serde_json::Value = missing_order("en-GB").await.json();
+    let spanish: serde_json::Value = missing_order("pt;q=0.9, es-MX;q=0.8").await.json();
+    assert_eq!(english["error"], "Order not found");
+    assert_eq!(spanish["code"], english["code"]);
+    assert_eq!(spanish["error"], "La orden no existe.");
+
+    let set_locale = |locale| {
+        app.server
+            .put("/api/v1/user/locale")
+            .authorization_bearer(&alice.access_token)
+            .json(&json!({ "locale": locale }))
+    };
+    let profile: UserProfile = set_locale(json!("de")).await.json();    assert_eq!(profile.locale.as_deref(), Some("de"));
+    let german: serde_json::Value = missing_order("es").await.json();
+    assert_eq!(german["code"], english["code"]);
+    assert_eq!(german["error"], "Die Order existiert nicht.");
+
+    set_locale(json!(null)).await.assert_status_ok();
+    let header_again: serde_json::Value = missing_order("fr").await.json();
+    assert_eq!(header_again["error"], "L'ordre n'existe pas.");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn watchlist_orders_market_data_for_its_owner() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+    let eth = app.seed_trading_pair("ETH-USD").await;
+    let sol = app.seed_trading_pair("SOL-USD").await;
+    let symbols = |entries: Vec<WatchlistEntry>| entries.into_iter().map(|entry| entry.symbol).collect::<Vec<_>>();
+
+    for pair in [&btc, &eth, &sol] {
+        app.post(&alice, "/api/v1/user/watchlist")
+            .json(&json!({ "trading_pair_id": pair.id }))
+            .await
+            .assert_status_ok();
+    }
+    let reordered: Vec<WatchlistEntry> = app
+        .server
+        .put("/api/v1/user/watchlist")
+        .authorization_bearer(&alice.access_token)
+        .json(&json!({ "trading_pair_ids": [sol.id, btc.id, eth.id] }))
+        .await
+        .json();
+    assert_eq!(symbols(reordered), ["SOL-USD", "BTC-USD", "ETH-USD"]);
+    let remaining: Vec<WatchlistEntry> = app
+        .delete(&alice, &format!("/api/v1/user/watchlist/{}", btc.id))
+        .await
+        .json();
+    assert_eq!(remaining.iter().map(|entry| entry.position).collect::<Vec<_>>(), [0, 1]);
+
+    let watched: Vec<MarketData> = app.get(&alice, "/api/v1/market-data?watchlist=true").await.json();
+    let watched: Vec<_> = watched.into_iter().map(|data| data.symbol).collect();
+    assert_eq!(watched, ["SOL-USD", "ETH-USD"]);
+
+    let everything: Vec<MarketData> = app.server.get("/api/v1/market-data").await.json();
+    assert!(everything.iter().any(|data| data.trading_pair_id == btc.id));
+    app.server
+        .get("/api/v1/market-data?watchlist=true")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn book_snapshot_lines_up_with_the_book_feed() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(100_000)).await;
+    let bid = |price| limit_order(pair.id, OrderSide::Buy, price, 1.0);
+    let snapshot_path = format!("/api/v1/order-book/{}/snapshot", pair.id);
+    let mut feed = app.state.stream_service.subscribe();
+
+    app.post(&alice, "/api/v1/orders").json(&bid(19_900)).await.assert_status_ok();
+    app.post(&alice, "/api/v1/orders").json(&bid(19_800)).await.assert_status_ok();
+    let snapshot: OrderBookSnapshot = app.server.get(&snapshot_path).await.json();
+    assert_eq!(snapshot.bids.len(), 2);
+    assert!(snapshot.asks.is_empty());
+    app.post(&alice, "/api/v1/orders").json(&bid(19_700)).await.assert_status_ok();
+
+    let channel = StreamService::book_channel(pair.id);
+    let updates: Vec<BookUpdate> = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        let mut updates = Vec::new();
+        while updates.len() < 3 {
+            let message = feed.recv().await.unwrap();
+            if message.channel == channel {
+                updates.push(serde_json::from_value::<BookUpdate>(message.data).unwrap());
+            }
+        }
+        updates
+    })
+    .await
+    .expect("book updates were not published");
+
+    // The snapshot includes the first two updates; only the third is new
+    let newer: Vec<_> = updates.iter().filter(|update| update.engine_sequence > snapshot.last_update_id).collect();
+    assert_eq!(newer.len(), 1);
+    assert_eq!(newer[0].bids.len(), 3);
+    assert_eq!(updates[1].engine_sequence, snapshot.last_update_id);
+
+    let shallow: OrderBookSnapshot = app.server.get(&format!("{}?depth=1", snapshot_path)).await.json();
+    assert_eq!(shallow.bids.len(), 1);
+    assert_eq!(shallow.bids[0].price, Decimal::from(19_900));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn exchange_info_lists_pairs_filters_and_limits() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+
+    let before = chrono::Utc::now().timestamp_millis();
+    let time: ServerTime = app.server.get("/api/v1/time").await.json();
+    assert!(time.server_time >= before);
+
+    app.server
+        .put(&format!("/api/v1/admin/trading-pairs/{}/price-band", btc.id))
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "price_band_percent": "5" }))
+        .await
+        .assert_status_ok();
+
+    let info: ExchangeInfo = app.server.get("/api/v1/exchange-info").await.json();
+    assert_eq!(info.timezone, "UTC");
+    assert_eq!(info.recv_window_ms, app.config.api_keys.recv_window_ms);
+    let tiers: Vec<_> = info.rate_limits.iter().map(|limit| limit.tier.as_str()).collect();
+    assert_eq!(tiers, ["anonymous", "api_key"]);
+    assert!(info.order_types.contains(&OrderType::StopLossLimit));
+    assert!(!info.capabilities.maintenance_mode);
+    let symbol = info.symbols.iter().find(|symbol| symbol.listing.trading_pair.id == btc.id).expect("pair listed");
+    assert_eq!(symbol.listing.trading_pair.symbol, "BTC-USD");
+    assert_eq!(symbol.filters.price_band_percent, Some(Decimal::from(5)));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn capabilities_report_enabled_subsystems_without_a_token() {
+    let app = TestApp::spawn_with(|config| config.payments.card.enabled = false).await;
+
+    let capabilities: Capabilities = app.server.get("/api/v1/capabilities").await.json();
+    assert_eq!(capabilities.api.version, "v1");
+    assert!(capabilities.api.supported_versions.contains(&capabilities.api.version));
+    assert!(!capabilities.subsystems.margin && !capabilities.subsystems.staking);
+    assert!(!capabilities.subsystems.fiat.card_deposits);
+    assert_eq!(capabilities.subsystems.fiat.deposit_currency, app.config.payments.currency);
+    assert_eq!(capabilities.subsystems.sandbox, app.config.sandbox.enabled);
+    assert!(capabilities.features.iter().any(|feature| feature == "cancel_replace"));
+    assert!(!capabilities.changelog.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn order_export_streams_ndjson_and_resumes_after_from_id() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(100_000)).await;
+    let bid = |price| limit_order(pair.id, OrderSide::Buy, price, 1.0);
+    let lines = |response: TestResponse| -> Vec<Order> {
+        response.text().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    };
+
+    let mut placed = Vec::new();
+    for price in [19_000, 19_100, 19_200] {
+        placed.push(app.post(&alice, "/api/v1/orders").json(&bid(price)).await.json::<Order>().id);
+    }
+
+    let response = app.get(&alice, "/api/v1/exports/orders").await;
+    response.assert_header("content-type", "application/x-ndjson");
+    let exported = lines(response);
+    assert_eq!(exported.iter().map(|order| order.id).collect::<Vec<_>>(), placed);
+
+    let resumed = lines(app.get(&alice, &format!("/api/v1/exports/orders?from_id={}", placed[0])).await);
+    assert_eq!(resumed.iter().map(|order| order.id).collect::<Vec<_>>(), placed[1..]);
+
+    assert!(app.get(&bob, "/api/v1/exports/trades").await.text().is_empty());
+    app.get(&bob, &format!("/api/v1/exports/orders?from_id={}", placed[0]))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn balances_are_rebuilt_at_a_past_moment_from_the_ledger() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let balances_at = |at: chrono::DateTime<chrono::Utc>| {
+        app.get(&alice, &format!("/api/v1/user/balances?at={}", at.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)))
+    };
+
+    app.seed_balance(alice.id, "USD", Decimal::from(1_000)).await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    let checkpoint = chrono::Utc::now();
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    app.seed_balance(alice.id, "USD", Decimal::from(-400)).await;
+
+    let then: HistoricalBalances = balances_at(checkpoint).await.json();
+    let then: Vec<_> = then.balances.iter().map(|b| (b.currency.as_str(), b.balance)).collect();
+    assert_eq!(then, [("BTC", Decimal::ONE), ("USD", Decimal::from(1_000))]);
+
+    let now: HistoricalBalances = app.get(&alice, "/api/v1/user/balances").await.json();
+    let usd = now.balances.iter().find(|b| b.currency == "USD").unwrap();
+    assert_eq!(usd.balance, Decimal::from(600));
+
+    balances_at(chrono::Utc::now() + chrono::Duration::hours(1))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn compacted_ledger_keeps_balances_and_archives_entries() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let dir = std::env::temp_dir().join(format!("cryptotrade-ledger-archive-{}", uuid::Uuid::new_v4()));
+    let compaction = LedgerCompactionService::new(
+        app.db.clone(),
+        std::sync::Arc::new(LocalStorage {
+            dir: dir.clone(),
+            signing_secret: b"test".to_vec(),
+        }),
+        1,
+    );
+
+    app.seed_balance(alice.id, "USD", Decimal::from(1_000)).await;
+    app.seed_balance(alice.id, "USD", Decimal::from(-250)).await;
+    sqlx::query("UPDATE ledger_entries SET created_at = created_at - INTERVAL '3 years'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    app.seed_balance(alice.id, "USD", Decimal::from(50)).await;
+    let long_ago = chrono::Utc::now() - chrono::Duration::days(365 * 2);
+
+    let report = compaction.run(12).await.unwrap();
+
+    assert_eq!(report.periods.len(), 1);
+    assert!(report.entries_archived >= 2);
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ledger_entries WHERE user_id = $1")
+        .bind(alice.id)
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 1);
+    let archived = std::fs::read_dir(dir.join("ledger").join(&report.periods[0])).unwrap().count();
+    assert_eq!(archived as u64, report.entries_archived);
+
+    let then: HistoricalBalances = app
+        .get(&alice, &format!("/api/v1/user/balances?at={}", long_ago.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)))
+        .await
+        .json();
+    assert_eq!(then.balances[0].balance, Decimal::from(750));
+    let now: HistoricalBalances = app.get(&alice, "/api/v1/user/balances").await.json();
+    assert_eq!(now.balances[0].balance, Decimal::from(800));
+
+    assert!(compaction.run(12).await.unwrap().periods.is_empty());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn exports_are_written_to_storage_behind_presigned_links() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+    app.post(&alice, "/api/v1/orders")
+        .json(&CreateOrderRequest {
+            time_in_force: Some(TimeInForce::GTC),
+            ..limit_order(pair.id, OrderSide::Buy, 20_000, 0.25)
+        })
+        .await
+        .assert_status_ok();
+
+    let file: ExportFile = app.post(&alice, "/api/v1/exports/orders/file").await.json();
+    assert_eq!(file.rows, 1);
+    assert!(file.expires_at > chrono::Utc::now());
+
+    let download = app.server.get(&file.download_url).await;
+    assert_eq!(download.header("content-type"), "application/x-ndjson");
+    let order: Order = serde_json::from_str(download.text().lines().next().unwrap()).unwrap();
+    assert_eq!(order.user_id, alice.id);
+
+    let unsigned = file.download_url.split('?').next().unwrap().to_string();
+    app.server.get(&unsigned).expect_failure().await.assert_status_bad_request();
+}
+
+/// Keeps what it is handed; fails every publish while `down`.
+#[derive(Default)]
+struct RecordingPublisher {
+    published: std::sync::Mutex<Vec<(String, uuid::Uuid, Vec<u8>)>>,
+    down: std::sync::atomic::AtomicBool,
+}
+
+#[async_trait::async_trait]
+impl EventPublisher for RecordingPublisher {
+    async fn publish(&self, subject: String, event_id: uuid::Uuid, payload: Vec<u8>) -> cryptotrade_core::Result<()> {
+        if self.down.load(std::sync::atomic::Ordering::SeqCst) {
+            return Err(cryptotrade_core::CryptoTradeError::Io(std::io::Error::other("no servers available")));
+        }
+        self.published.lock().unwrap().push((subject, event_id, payload));
+        Ok(())
+    }
+
+    async fn flush(&self) -> cryptotrade_core::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn outbox_events_are_relayed_at_least_once() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let outbox = OutboxService::new(app.db.clone(), "cryptotrade".to_string());
+    let publisher = RecordingPublisher::default();
+
+    app.server
+        .put(&format!("/api/v1/admin/users/{}/status", alice.id))
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "status": "restricted", "reason": "compliance review" }))
+        .await
+        .assert_status_ok();
+    assert_eq!(outbox.backlog().await.unwrap(), 1);
+
+    // While NATS is down the event stays pending
+    publisher.down.store(true, std::sync::atomic::Ordering::SeqCst);
+    assert!(outbox.relay(&publisher, 10).await.is_err());
+    assert_eq!(outbox.backlog().await.unwrap(), 1);
+
+    publisher.down.store(false, std::sync::atomic::Ordering::SeqCst);
+    assert_eq!(outbox.relay(&publisher, 10).await.unwrap(), 1);
+    assert_eq!(outbox.relay(&publisher, 10).await.unwrap(), 0);
+    assert_eq!(outbox.backlog().await.unwrap(), 0);
+
+    let published = publisher.published.lock().unwrap().clone();
+    let (subject, event_id, payload) = &published[0];
+    assert_eq!(subject, "cryptotrade.account.status_changed");
+    let message: OutboxMessage = serde_json::from_slice(payload).unwrap();
+    assert_eq!(message.id, *event_id);
+    match &message.event {
+        DomainEvent::AccountStatusChanged { change } => assert_eq!(change.user_id, alice.id),
+        other => panic!("unexpected event {:?}", other),
+    }
+
+    assert_eq!(outbox.prune(chrono::Utc::now() + chrono::Duration::seconds(1)).await.unwrap(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn replayed_matches_are_settled_once() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::from(1)).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(1_000)).await;
+
+    let limit = |side| limit_order(pair.id, side, 500, 1.0);
+    let sell: Order = app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell)).await.json();
+    let buy: Order = app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy)).await.json();
+    let balances = || async {
+        sqlx::query_as::<_, (uuid::Uuid, String, Option<Decimal>, Option<Decimal>)>(
+            "SELECT user_id, currency, balance, locked_balance FROM accounts WHERE user_id = ANY($1) ORDER BY user_id, currency",
+        )
+        .bind(vec![alice.id, bob.id])
+        .fetch_all(&app.db)
+        .await
+        .unwrap()
+    };
+    let settled = balances().await;
+
+    let key: String = sqlx::query_scalar("SELECT event_key FROM processed_events WHERE consumer = 'trade_settlement'")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert!(key.starts_with(&pair.id.to_string()));
+
+    // The engine's match is delivered again, as after a crash mid-batch
+    let sell: Order = app.get(&alice, &format!("/api/v1/orders/{}", sell.id)).await.json();
+    let buy: Order = app.get(&bob, &format!("/api/v1/orders/{}", buy.id)).await.json();
+    let replayed = app
+        .state
+        .trading_service
+        .execute_trade(&key, &buy, &sell, OrderSide::Buy, Decimal::from(500), Decimal::ONE)
+        .await
+        .unwrap();
+    assert!(replayed.is_none());
+
+    let trades: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM trades WHERE trading_pair_id = $1")
+        .bind(pair.id)
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(trades, 1);
+    assert_eq!(balances().await, settled);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn locks_of_orders_lost_with_their_journal_batch_are_released_on_recovery() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(1_000)).await;
+
+    let placed: Order = app
+        .post(&alice, "/api/v1/orders")
+        .json(&limit_order(pair.id, OrderSide::Buy, 100, 1.0))
+        .await
+        .json();
+
+    // A second order locked its funds, then its journal batch was lost
+    sqlx::query(
+        "WITH locked AS (UPDATE accounts SET available_balance = available_balance - 200, locked_balance = locked_balance + 200 WHERE user_id = $1 AND currency = 'USD' RETURNING user_id) INSERT INTO pending_order_locks (order_id, user_id, currency, amount) SELECT $2, user_id, 'USD', 200 FROM locked",
+    )
+    .bind(alice.id)
+    .bind(uuid::Uuid::new_v4())
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    app.state.matching_service.recover().await.unwrap();
+
+    // Only the journaled order still holds funds
+    let accounts: Vec<Account> = app.get(&alice, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").expect("USD account");
+    assert_eq!(usd.locked_balance, placed.locked_amount);
+    assert_eq!(usd.available_balance, Some(Decimal::new(8999, 1)));
+    let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pending_order_locks")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(pending, 0);
+}
+
+/// Credits the amount of every deposit it sees to a second currency, and
+/// fails while `down`.
+#[derive(Default)]
+struct MirroringConsumer {
+    down: std::sync::atomic::AtomicBool,
+}
+
+#[async_trait::async_trait]
+impl EventConsumer for MirroringConsumer {
+    fn name(&self) -> &'static str {
+        "deposit_mirror"
+    }
+
+    async fn handle(&self, conn: &mut sqlx::PgConnection, message: &OutboxMessage) -> cryptotrade_core::Result<()> {
+        let DomainEvent::DepositConfirmed { user_id, amount, .. } = &message.event else {
+            return Ok(());
+        };
+        sqlx::query("UPDATE accounts SET balance = balance + $2, available_balance = available_balance + $2 WHERE user_id = $1 AND currency = 'MIRROR'")
+            .bind(user_id)
+            .bind(amount)
+            .execute(&mut *conn)
+            .await?;
+        if self.down.load(std::sync::atomic::Ordering::SeqCst) {
+            return Err(cryptotrade_core::CryptoTradeError::Io(std::io::Error::other("downstream unavailable")));
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn redelivered_outbox_events_are_consumed_once() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    app.seed_balance(alice.id, "MIRROR", Decimal::ZERO).await;
+    let outbox = OutboxService::new(app.db.clone(), "cryptotrade".to_string());
+    let consumer = MirroringConsumer::default();
+    let message = OutboxMessage {
+        id: uuid::Uuid::new_v4(),
+        occurred_at: chrono::Utc::now(),
+        event: DomainEvent::DepositConfirmed {
+            transaction_id: uuid::Uuid::new_v4(),
+            user_id: alice.id,
+            currency: "USD".to_string(),
+            amount: Decimal::from(25),
+        },
+    };
+    let mirrored = || async {
+        sqlx::query_scalar::<_, Option<Decimal>>("SELECT balance FROM accounts WHERE user_id = $1 AND currency = 'MIRROR'")
+            .bind(alice.id)
+            .fetch_one(&app.db)
+            .await
+            .unwrap()
+    };
+
+    // A failed attempt leaves neither the credit nor the processed mark
+    consumer.down.store(true, std::sync::atomic::Ordering::SeqCst);
+    assert!(outbox.consume(&consumer, &message).await.is_err());
+    assert_eq!(mirrored().await, Some(Decimal::ZERO));
+
+    consumer.down.store(false, std::sync::atomic::Ordering::SeqCst);
+    assert!(outbox.consume(&consumer, &message).await.unwrap());
+    assert!(!outbox.consume(&consumer, &message).await.unwrap());
+    assert_eq!(mirrored().await, Some(Decimal::from(25)));
+
+    // Redeliveries racing each other still apply it once
+    let again = OutboxMessage {
+        id: uuid::Uuid::new_v4(),
+        ..message
+    };
+    let (first, second) = tokio::join!(outbox.consume(&consumer, &again), outbox.consume(&consumer, &again));
+    assert!(first.unwrap() ^ second.unwrap());
+    assert_eq!(mirrored().await, Some(Decimal::from(50)));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn white_label_tenants_are_isolated_and_charge_their_own_fees() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let house = app.seed_user("house").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+    let eth = app.seed_trading_pair("ETH-USD").await;
+
+    let tenant: Tenant = app
+        .post(&admin, "/api/v1/admin/tenants")
+        .json(&json!({
+            "slug": "acme",
+            "name": "Acme Brokerage",
+            "api_key_prefix": "acme",
+            "taker_fee": "0.0025",
+            "display_name": "Acme Trade",
+            "primary_color": "#ff6600",
+        }))
+        .await
+        .json();
+    app.post(&admin, "/api/v1/admin/tenants")
+        .json(&json!({ "slug": "acme", "name": "Again", "api_key_prefix": "acme2" }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    let branding: Tenant = app.server.get("/api/v1/tenants/acme").await.json();
+    assert_eq!(branding.display_name.as_deref(), Some("Acme Trade"));
+
+    let registered: serde_json::Value = app
+        .server
+        .post("/api/v1/auth/register")
+        .json(&json!({
+            "email": "carol@example.com",
+            "username": "carol",
+            "password": TEST_PASSWORD,
+            "tenant": "acme",
+        }))
+        .await
+        .json();
+    assert_eq!(registered["user"]["tenant_id"], json!(tenant.id));
+    let carol = TestUser {
+        id: registered["user"]["id"].as_str().unwrap().parse().unwrap(),
+        email: "carol@example.com".to_string(),
+        username: "carol".to_string(),
+        access_token: registered["access_token"].as_str().unwrap().to_string(),
+        refresh_token: registered["refresh_token"].as_str().unwrap().to_string(),
+    };
+
+    // A private pair is listed and tradable for its tenant only
+    app.server
+        .put(&format!("/api/v1/admin/trading-pairs/{}/tenant", eth.id))
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "tenant_id": tenant.id }))
+        .await
+        .assert_status_ok();
+    let symbols = |listings: Vec<TradingPairListing>| listings.into_iter().map(|listing| listing.trading_pair.symbol).collect::<Vec<_>>();
+    let shared: Vec<TradingPairListing> = app.server.get("/api/v1/trading-pairs").await.json();
+    assert_eq!(symbols(shared), vec!["BTC-USD"]);
+    let acme: Vec<TradingPairListing> = app.server.get("/api/v1/trading-pairs").add_header("x-tenant", "acme").await.json();
+    assert_eq!(symbols(acme), vec!["BTC-USD", "ETH-USD"]);
+
+    let limit = |trading_pair_id, side| limit_order(trading_pair_id, side, 100, 1.0);
+    app.seed_balance(house.id, "ETH", Decimal::from(1)).await;
+    app.post(&house, "/api/v1/orders")
+        .json(&limit(eth.id, OrderSide::Sell))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+
+    // So is its market data
+    let ticker_symbols = |tickers: Vec<Ticker>| tickers.into_iter().map(|ticker| ticker.symbol).collect::<Vec<_>>();
+    assert_eq!(ticker_symbols(app.get(&house, "/api/v1/tickers").await.json()), vec!["BTC-USD"]);
+    assert_eq!(ticker_symbols(app.get(&carol, "/api/v1/tickers").await.json()), vec!["BTC-USD", "ETH-USD"]);
+    let market_data: Vec<MarketData> = app.get(&house, "/api/v1/market-data").await.json();
+    assert_eq!(market_data.len(), 1);
+    for route in ["market-data", "order-book", "trades", "candlesticks"] {
+        let path = format!("/api/v1/{}/{}", route, eth.id);
+        app.get(&house, &path).expect_failure().await.assert_status_not_found();
+        app.get(&carol, &path).await.assert_status_ok();
+    }
+    app.get(&house, "/api/v2/order-book/ETH-USD")
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+
+    // On a shared pair, each side pays its own schedule
+    app.seed_balance(house.id, "BTC", Decimal::from(1)).await;
+    app.seed_balance(carol.id, "USD", Decimal::from(1_000)).await;
+    app.post(&house, "/api/v1/orders").json(&limit(btc.id, OrderSide::Sell)).await.assert_status_ok();
+    app.post(&carol, "/api/v1/orders").json(&limit(btc.id, OrderSide::Buy)).await.assert_status_ok();
+    let trades: Vec<Trade> = app.get(&carol, "/api/v1/trades").await.json();
+    assert_eq!(trades[0].buyer_fee, Some(Decimal::new(25, 2)));
+    assert_eq!(trades[0].seller_fee, Some(Decimal::new(10, 2)));
+
+    // Engine health leaves out other tenants' pairs too
+    app.post(&carol, "/api/v1/orders").json(&limit(eth.id, OrderSide::Buy)).await.assert_status_ok();
+    let engine_pairs = |engines: Vec<EngineStatus>| engines.into_iter().map(|engine| engine.trading_pair_id).collect::<Vec<_>>();
+    let shared: Vec<EngineStatus> = app.server.get("/api/v1/health/engine").await.json();
+    assert_eq!(engine_pairs(shared), vec![btc.id]);
+    let acme: Vec<EngineStatus> = app.server.get("/api/v1/health/engine").add_header("x-tenant", "acme").await.json();
+    assert!(engine_pairs(acme).contains(&eth.id));
+
+    let created: serde_json::Value = app
+        .post(&carol, "/api/v1/user/api-keys")
+        .json(&json!({ "label": "bot", "permissions": ["Read"] }))
+        .await
+        .json();
+    assert!(created["api_key"]["key"].as_str().unwrap().starts_with("ctk_acme_"));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn logins_from_new_devices_must_be_confirmed() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let credentials = json!({ "email": alice.email, "password": TEST_PASSWORD });
+    let login_from = |fingerprint: &'static str| {
+        app.server
+            .post("/api/v1/auth/login")
+            .add_header("x-device-fingerprint", fingerprint)
+            .json(&credentials)
+            .expect_failure()
+    };
+
+    // The device alice registered from is already known
+    app.server.post("/api/v1/auth/login").json(&credentials).await.assert_status_ok();
+
+    let held = login_from("laptop").await;
+    held.assert_status(StatusCode::FORBIDDEN);
+    let body: serde_json::Value = held.json();
+    assert_eq!(body["code"], "DEVICE_CONFIRMATION_REQUIRED");
+    let challenge_id: uuid::Uuid = body["details"]["challenge_id"].as_str().unwrap().parse().unwrap();
+
+    // The emailed token is only stored hashed; swap in one the test knows
+    sqlx::query("UPDATE device_challenges SET token_hash = encode(sha256('known-token'::bytea), 'hex') WHERE id = $1")
+        .bind(challenge_id)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let confirm = json!({ "token": "known-token" });
+    app.server.post("/api/v1/auth/devices/confirm").json(&confirm).await.assert_status_ok();
+    app.server
+        .post("/api/v1/auth/devices/confirm")
+        .json(&confirm)
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    login_from("laptop").expect_success().await.assert_status_ok();
+
+    let devices: Vec<serde_json::Value> = app.get(&alice, "/api/v1/user/sessions").await.json();
+    assert_eq!(devices.len(), 2);
+    assert!(devices.iter().all(|device| !device["confirmed_at"].is_null() && device.get("fingerprint").is_none()));
+
+    let laptop = devices[0]["id"].as_str().unwrap();
+    app.delete(&alice, &format!("/api/v1/user/sessions/{}", laptop)).await.assert_status_ok();
+    login_from("laptop").await.assert_status(StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn captcha_is_required_to_register_and_after_failed_logins() {
+    // Stands in for the provider's siteverify endpoint: "solved" passes
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let verify_url = format!("http://{}/siteverify", listener.local_addr().unwrap());
+    let siteverify = axum::Router::new().route(
+        "/siteverify",
+        axum::routing::post(|axum::Form(form): axum::Form<std::collections::HashMap<String, String>>| async move {
+            axum::Json(json!({ "success": form.get("response").map(String::as_str) == Some("solved") }))
+        }),
+    );
+    tokio::spawn(async move { axum::serve(listener, siteverify).await });
+
+    let app = TestApp::spawn_with(|config| {
+        config.captcha.provider = cryptotrade_core::CaptchaProviderKind::Turnstile;
+        config.captcha.secret = "site-secret".to_string();
+        config.captcha.verify_url = verify_url;
+        config.captcha.failed_login_threshold = 2;
+    })
+    .await;
+    let register = |captcha_token: Option<&str>| {
+        app.server
+            .post("/api/v1/auth/register")
+            .json(&json!({
+                "email": "alice@example.com",
+                "username": "alice",
+                "password": TEST_PASSWORD,
+                "captcha_token": captcha_token,
+            }))
+            .expect_failure()
+    };
+
+    for token in [None, Some("wrong")] {
+        let refused = register(token).await;
+        refused.assert_status(StatusCode::FORBIDDEN);
+        assert_eq!(refused.json::<serde_json::Value>()["code"], "CAPTCHA_REQUIRED");
+    }
+    register(Some("solved")).expect_success().await.assert_status_ok();
+
+    let login = |password: &str, captcha_token: Option<&str>| {
+        app.server
+            .post("/api/v1/auth/login")
+            .json(&json!({ "email": "alice@example.com", "password": password, "captcha_token": captcha_token }))
+            .expect_failure()
+    };
+    // Below the threshold no CAPTCHA is asked for
+    login(TEST_PASSWORD, None).expect_success().await.assert_status_ok();
+    for _ in 0..2 {
+        login("wrong-password", None).await.assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    let held = login(TEST_PASSWORD, None).await;
+    held.assert_status(StatusCode::FORBIDDEN);
+    assert_eq!(held.json::<serde_json::Value>()["code"], "CAPTCHA_REQUIRED");
+    login(TEST_PASSWORD, Some("solved")).expect_success().await.assert_status_ok();
+
+    // The successful login clears the failures
+    login(TEST_PASSWORD, None).expect_success().await.assert_status_ok();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn large_withdrawals_need_a_second_admin_to_approve() {
+    let app = TestApp::spawn_with(|config| {
+        config.compliance.travel_rule.thresholds.clear();
+        config.payments.dual_approval_thresholds.insert("USD".to_string(), Decimal::from(500));
+    })
+    .await;
+    let maker = app.seed_admin("maker").await;
+    let checker = app.seed_admin("checker").await;
+    let alice = app.seed_user("alice").await;
+    app.seed_balance(alice.id, "USD", Decimal::new(1000, 0)).await;
+
+    let account: serde_json::Value = app
+        .post(&alice, "/api/v1/user/bank-accounts")
+        .json(&json!({
+            "rail": "ach",
+            "holder_name": "Alice Smith",
+            "account_number": "12345678",
+            "routing_code": "021000021",
+        }))
+        .await
+        .json();
+    let account_id = account["id"].as_str().unwrap().to_string();
+    let pending: serde_json::Value = app.get(&maker, "/api/v1/admin/bank-accounts/micro-deposits").await.json();
+    app.post(&alice, &format!("/api/v1/user/bank-accounts/{}/verify", account_id))
+        .json(&json!({ "amounts": pending[0]["micro_deposits"] }))
+        .await
+        .assert_status_ok();
+
+    let small: serde_json::Value = app
+        .post(&alice, "/api/v1/withdrawals/fiat")
+        .json(&json!({ "bank_account_id": account_id, "amount": "100" }))
+        .await
+        .json();
+    assert_eq!(small["dual_approval_required"], false);
+    let approved: serde_json::Value = app
+        .post(&maker, &format!("/api/v1/admin/withdrawals/{}/review", small["id"].as_str().unwrap()))
+        .json(&json!({ "action": "approve" }))
+        .await
+        .json();
+    assert_eq!(approved["status"], "approved");
+
+    let large: serde_json::Value = app
+        .post(&alice, "/api/v1/withdrawals/fiat")
+        .json(&json!({ "bank_account_id": account_id, "amount": "600" }))
+        .await
+        .json();
+    assert_eq!(large["dual_approval_required"], true);
+    let large_id = large["id"].as_str().unwrap();
+    let review_path = format!("/api/v1/admin/withdrawals/{}/review", large_id);
+
+    let initiated: serde_json::Value = app
+        .post(&maker, &review_path)
+        .json(&json!({ "action": "approve", "note": "matches payroll run" }))
+        .await
+        .json();
+    assert_eq!(initiated["status"], "pending");
+    assert_eq!(initiated["approval_initiated_by"], maker.id.to_string());
+
+    // The initiator cannot confirm their own approval
+    app.post(&maker, &review_path)
+        .json(&json!({ "action": "approve" }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    let confirmed: serde_json::Value = app.post(&checker, &review_path).json(&json!({ "action": "approve" })).await.json();
+    assert_eq!(confirmed["status"], "approved");
+    app.post(&checker, &review_path)
+        .json(&json!({ "action": "complete", "external_id": "ACH-1" }))
+        .await
+        .assert_status_ok();
+
+    let trail: Vec<serde_json::Value> = app
+        .get(&checker, &format!("/api/v1/admin/withdrawals/{}/reviews", large_id))
+        .await
+        .json();
+    let steps: Vec<_> = trail
+        .iter()
+        .map(|review| (review["admin_id"].as_str().unwrap(), review["action"].as_str().unwrap(), review["status_after"].as_str().unwrap()))
+        .collect();
+    let (maker_id, checker_id) = (maker.id.to_string(), checker.id.to_string());
+    assert_eq!(
+        steps,
+        [
+            (maker_id.as_str(), "approve", "pending"),
+            (checker_id.as_str(), "approve", "approved"),
+            (checker_id.as_str(), "complete", "confirmed"),
+        ]
+    );
+    assert_eq!(trail[0]["note"], "matches payroll run");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn support_tickets_move_between_user_and_staff() {
+    let app = TestApp::spawn().await;
+    let agent = app.seed_admin("agent").await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+
+    let opened: serde_json::Value = app
+        .post(&alice, "/api/v1/support/tickets")
+        .json(&json!({
+            "category": "withdrawal",
+            "subject": "Withdrawal stuck",
+            "message": "My withdrawal has been pending for three days.",
+        }))
+        .await
+        .json();
+    assert_eq!(opened["ticket"]["status"], "open");
+    assert_eq!(opened["account"], serde_json::Value::Null);
+    let ticket_id = opened["ticket"]["id"].as_str().unwrap().to_string();
+    let ticket_path = format!("/api/v1/support/tickets/{}", ticket_id);
+    let admin_path = format!("/api/v1/admin/support/tickets/{}", ticket_id);
+
+    // Other users cannot see the ticket, nor can users reach the queue
+    app.get(&bob, &ticket_path).expect_failure().await.assert_status(StatusCode::NOT_FOUND);
+    app.get(&alice, "/api/v1/admin/support/tickets")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    let form = MultipartForm::new().add_part(
+        "file",
+        Part::bytes(b"%PDF-1.4 statement".to_vec()).file_name("statement.pdf").mime_type("application/pdf"),
+    );
+    let attachment: serde_json::Value = app
+        .post(&alice, &format!("{}/attachments", ticket_path))
+        .multipart(form)
+        .await
+        .json();
+    assert_eq!(attachment["content_type"], "application/pdf");
+    assert_eq!(attachment["size_bytes"], 18);
+    let rejected = MultipartForm::new().add_part("file", Part::bytes(b"MZ".to_vec()).file_name("tool.exe"));
+    app.post(&alice, &format!("{}/attachments", ticket_path))
+        .multipart(rejected)
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    app.get(&alice, &format!("{}/attachments/{}", ticket_path, attachment["id"].as_str().unwrap()))
+        .await
+        .assert_status(StatusCode::TEMPORARY_REDIRECT);
+
+    let queue: serde_json::Value = app.get(&agent, "/api/v1/admin/support/tickets").await.json();
+    assert_eq!(queue.as_array().unwrap().len(), 1);
+    let detail: serde_json::Value = app.get(&agent, &admin_path).await.json();
+    assert_eq!(detail["account"]["email"], alice.email);
+    assert_eq!(detail["account"]["other_open_tickets"], 0);
+    assert_eq!(detail["attachments"].as_array().unwrap().len(), 1);
+
+    app.post(&agent, &format!("{}/messages", admin_path))
+        .json(&json!({ "body": "Your bank returned it; please check the account number." }))
+        .await
+        .assert_status_ok();
+    let pending: serde_json::Value = app.get(&alice, &ticket_path).await.json();
+    assert_eq!(pending["ticket"]["status"], "pending");
+    assert_eq!(pending["messages"][1]["from_staff"], true);
+
+    let resolved: serde_json::Value = app
+        .server
+        .put(&format!("{}/status", admin_path))
+        .authorization_bearer(&agent.access_token)
+        .json(&json!({ "status": "resolved" }))
+        .await
+        .json();
+    assert_eq!(resolved["status"], "resolved");
+    assert!(resolved["resolved_at"].is_string());
+
+    // A reply from the user reopens a resolved ticket
+    app.post(&alice, &format!("{}/messages", ticket_path))
+        .json(&json!({ "body": "Still not received." }))
+        .await
+        .assert_status_ok();
+    let reopened: serde_json::Value = app.get(&alice, &ticket_path).await.json();
+    assert_eq!(reopened["ticket"]["status"], "open");
+    assert_eq!(reopened["ticket"]["resolved_at"], serde_json::Value::Null);
+
+    let notifications = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM outbox_events WHERE subject = 'support.ticket_updated'")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(notifications, 4);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn graphql_resolves_orders_with_their_fills_and_pair() {
+    let app = TestApp::spawn_with(|config| config.graphql.max_depth = 4).await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(100_000)).await;
+    let limit = |side| limit_order(pair.id, side, 20_000, 0.1);
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy)).await.assert_status_ok();
+
+    let query = r#"{
+        orders { side status pair { symbol } fills { price quantity liquidity pair { symbol } } }
+        portfolio { accounts { currency balance } }
+        marketData { symbol lastPrice }
+    }"#;
+    let response: serde_json::Value = app.post(&bob, "/api/v1/graphql").json(&json!({ "query": query })).await.json();
+    assert_eq!(response["errors"], serde_json::Value::Null, "{}", response);
+    let order = &response["data"]["orders"][0];
+    assert_eq!(order["side"], "BUY");
+    assert_eq!(order["status"], "FILLED");
+    assert_eq!(order["pair"]["symbol"], "BTC-USD");
+    assert_eq!(order["fills"][0]["quantity"].as_str().unwrap().parse::<Decimal>().unwrap(), Decimal::new(1, 1));
+    assert_eq!(order["fills"][0]["liquidity"], "TAKER");
+    assert_eq!(order["fills"][0]["pair"]["symbol"], "BTC-USD");
+    assert!(response["data"]["portfolio"]["accounts"].as_array().unwrap().iter().any(|account| account["currency"] == "BTC"));
+    // Only the selected fields come back
+    assert_eq!(order.as_object().unwrap().len(), 4);
+
+    // Errors carry the REST code, and queries deeper than allowed are refused
+    let missing: serde_json::Value = app
+        .post(&bob, "/api/v1/graphql")
+        .json(&json!({ "query": format!(r#"{{ order(id: "{}") {{ id }} }}"#, uuid::Uuid::new_v4()) }))
+        .await
+        .json();
+    assert_eq!(missing["errors"][0]["extensions"]["code"], "ORDER_NOT_FOUND");
+    let deep: serde_json::Value = app
+        .post(&bob, "/api/v1/graphql")
+        .json(&json!({ "query": "{ orders { fills { pair { marketData { symbol } } } } }" }))
+        .await
+        .json();
+    assert_eq!(deep["data"], serde_json::Value::Null);
+    assert!(deep["errors"][0]["message"].as_str().unwrap().contains("nested too deep"));
+
+    app.server
+        .post("/api/v1/graphql")
+        .json(&json!({ "query": "{ portfolio { totalValueUsd } }" }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn alert_webhooks_place_orders_within_their_template_limits() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+
+    let created: CreatedAlertTemplate = app
+        .post(&alice, "/api/v1/alert-templates")
+        .json(&CreateAlertTemplateRequest {
+            name: "breakout".to_string(),
+            trading_pair_id: pair.id,
+            order_type: OrderType::Limit,
+            side: None,
+            quantity: Decimal::new(1, 2),
+            max_quantity: Some(Decimal::new(5, 2)),
+            max_notional: None,
+            max_orders_per_day: 1,
+        })
+        .await
+        .json();
+    assert_eq!(created.secret.len(), 64);
+    let alert = |passphrase: &str, quantity: &str| {
+        json!({ "passphrase": passphrase, "action": "buy", "quantity": quantity, "price": "20000", "ticker": "BTCUSD" })
+    };
+
+    app.server
+        .post(&created.webhook_path)
+        .json(&alert("guess", "0.01"))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    app.server
+        .post(&created.webhook_path)
+        .json(&alert(&created.secret, "0.5"))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    let execution: AlertExecution = app.server.post(&created.webhook_path).json(&alert(&created.secret, "0.02")).await.json();
+    assert_eq!(execution.status, AlertExecutionStatus::Executed);
+    let order: Order = app.get(&alice, &format!("/api/v1/orders/{}", execution.order_id.unwrap())).await.json();
+    assert_eq!((order.side, order.price), (Some(OrderSide::Buy), Some(Decimal::from(20_000))));
+    // The same alert again is a replay, and is not recorded
+    app.server
+        .post(&created.webhook_path)
+        .json(&alert(&created.secret, "0.02"))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::CONFLICT);
+    // One order a day
+    app.server
+        .post(&created.webhook_path)
+        .json(&alert(&created.secret, "0.03"))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+
+    // Rejections are recorded, newest first, without the passphrase
+    let executions: Vec<AlertExecution> =
+        app.get(&alice, &format!("/api/v1/alert-templates/{}/executions", created.template.id)).await.json();
+    let statuses: Vec<_> = executions.iter().map(|execution| execution.status).collect();
+    assert_eq!(statuses, [AlertExecutionStatus::Rejected, AlertExecutionStatus::Executed, AlertExecutionStatus::Rejected]);
+    assert!(executions.iter().all(|execution| execution.payload.get("passphrase").is_none()));
+
+    app.delete(&alice, &format!("/api/v1/alert-templates/{}", created.template.id)).await.assert_status_ok();
+    app.server
+        .post(&created.webhook_path)
+        .json(&alert(&created.secret, "0.04"))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+
+    // Market alerts are valued at the last trade, not the price they give,
+    // and BTC-USD has not traded yet
+    let market: CreatedAlertTemplate = app
+        .post(&alice, "/api/v1/alert-templates")
+        .json(&CreateAlertTemplateRequest {
+            name: "dip".to_string(),
+            trading_pair_id: pair.id,
+            order_type: OrderType::Market,
+            side: Some(OrderSide::Buy),
+            quantity: Decimal::new(1, 2),
+            max_quantity: None,
+            max_notional: Some(Decimal::from(500)),
+            max_orders_per_day: 1,
+        })
+        .await
+        .json();
+    app.server
+        .post(&market.webhook_path)
+        .json(&json!({ "passphrase": market.secret, "action": "buy", "price": "1" }))
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn order_imports_place_valid_rows_and_report_the_rest() {
+    let app = TestApp::spawn_with(|config| config.trading.order_import.inline_rows = 3).await;
+    let alice = app.seed_user("alice").await;
+    app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(10_000)).await;
+    let csv = "symbol,side,quantity,price\nBTC-USD,buy,0.1,19000\nDOGE-USD,buy,1,1\nBTC-USD,buy,0.1,-5\n";
+    let statuses = |report: &OrderImportReport| report.rows.iter().map(|row| row.status).collect::<Vec<_>>();
+
+    // A dry run validates without placing anything
+    let report: OrderImportReport = app.post(&alice, "/api/v1/orders/import?dry_run=true").text(csv).await.json();
+    assert_eq!(report.import.status, OrderImportStatus::Completed);
+    assert_eq!(
+        statuses(&report),
+        [OrderImportRowStatus::Valid, OrderImportRowStatus::Rejected, OrderImportRowStatus::Rejected]
+    );
+    assert_eq!(report.rows[1].reason.as_deref(), Some("unknown symbol 'DOGE-USD'"));
+    let orders: Vec<Order> = app.get(&alice, "/api/v1/orders").await.json();
+    assert!(orders.is_empty());
+
+    let report: OrderImportReport = app.post(&alice, "/api/v1/orders/import").text(csv).await.json();
+    assert_eq!((report.placed, report.rejected), (1, 2));
+    let order: Order = app.get(&alice, &format!("/api/v1/orders/{}", report.rows[0].order_id.unwrap())).await.json();
+    assert_eq!(order.price, Some(Decimal::from(19_000)));
+
+    // Larger files are left to the background job
+    let csv = format!("symbol,side,quantity,price\n{}", "BTC-USD,buy,0.01,19000\n".repeat(4));
+    let response = app.post(&alice, "/api/v1/orders/import").text(csv).await;
+    response.assert_status(StatusCode::ACCEPTED);
+    let report: OrderImportReport = response.json();
+    assert_eq!(report.pending, 4);
+    assert_eq!(app.state.order_import_service.process_pending(3).await.unwrap(), 3);
+    assert_eq!(app.state.order_import_service.process_pending(3).await.unwrap(), 1);
+    let report: OrderImportReport = app.get(&alice, &format!("/api/v1/orders/import/{}", report.import.id)).await.json();
+    assert_eq!(report.import.status, OrderImportStatus::Completed);
+    assert_eq!(report.placed, 4);
+
+    app.post(&alice, "/api/v1/orders/import").text("side,price\nbuy,1\n").expect_failure().await.assert_status_bad_request();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn fields_prune_heavy_responses_to_what_was_asked() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "USD", Decimal::from(100)).await;
+
+    let portfolio: serde_json::Value = app.get(&alice, "/api/v1/portfolio?fields=total_value_usd,accounts.currency").await.json();
+    assert_eq!(portfolio, json!({ "total_value_usd": portfolio["total_value_usd"], "accounts": [{ "currency": "USD" }] }));
+
+    let tickers: serde_json::Value = app.server.get("/api/v1/tickers?fields=symbol").await.json();
+    assert_eq!(tickers, json!([{ "symbol": "BTC-USD" }]));
+    let info: serde_json::Value = app.server.get("/api/v1/exchange-info?fields=symbols.symbol,nothing").await.json();
+    assert_eq!(info.as_object().unwrap().keys().collect::<Vec<_>>(), ["symbols"]);
+
+    // Without fields, the whole document
+    let portfolio: Portfolio = app.get(&alice, "/api/v1/portfolio").await.json();
+    assert_eq!(portfolio.accounts.len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn rebalance_plans_trade_towards_targets_once_confirmed() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let carol = app.seed_user("carol").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(100_000)).await;
+    app.seed_balance(carol.id, "BTC", Decimal::new(1, 1)).await;
+    app.seed_balance(carol.id, "USD", Decimal::from(6_000)).await;
+    let limit = |side| limit_order(pair.id, side, 20_000, 0.1);
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell)).await.assert_status_ok();
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy)).await.assert_status_ok();
+    app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell)).await.assert_status_ok();
+
+    app.post(&carol, "/api/v1/portfolio/rebalance").expect_failure().await.assert_status_bad_request();
+    let target = |currency: &str, percent| AllocationTarget {
+        currency: currency.to_string(),
+        target_percent: Decimal::from(percent),
+    };
+    app.server
+        .put("/api/v1/portfolio/allocation")
+        .authorization_bearer(&carol.access_token)
+        .json(&SetAllocationTargetsRequest { targets: vec![target("BTC", 50), target("USD", 40)] })
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    app.server
+        .put("/api/v1/portfolio/allocation")
+        .authorization_bearer(&carol.access_token)
+        .json(&SetAllocationTargetsRequest { targets: vec![target("btc", 50), target("USD", 50)] })
+        .await
+        .assert_status_ok();
+
+    // 2000 of 8000 is in BTC, so 2000 more goes to it
+    let plan: RebalancePlan = app.post(&carol, "/api/v1/portfolio/rebalance").await.json();
+    assert_eq!(plan.total_value, Decimal::from(8_000));
+    let btc = plan.assets.iter().find(|asset| asset.currency == "BTC").unwrap();
+    assert_eq!(btc.drift_percent, Decimal::from(-25));
+    assert_eq!(plan.trades.len(), 1);
+    assert_eq!((plan.trades[0].side, plan.trades[0].quantity), (OrderSide::Buy, Decimal::new(1, 1)));
+
+    let execute = format!("/api/v1/portfolio/rebalance/{}/execute", plan.id);
+    let execution: RebalanceExecution = app.post(&carol, &execute).await.json();
+    assert!(execution.trades[0].error.is_none(), "{:?}", execution.trades[0].error);
+    let order: Order = app.get(&carol, &format!("/api/v1/orders/{}", execution.trades[0].order_id.unwrap())).await.json();
+    assert_eq!(order.status, Some(OrderStatus::Filled));
+    app.post(&carol, &execute).expect_failure().await.assert_status(StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn simulated_orders_report_fills_without_touching_the_book() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::ONE).await;
+    let order = |order_type, side, quantity, price: Option<i64>| CreateOrderRequest {
+        order_type,
+        price: price.map(Decimal::from),
+        ..limit_order(pair.id, side, 0, quantity)
+    };
+    let resting: Order = app
+        .post(&alice, "/api/v1/orders")
+        .json(&order(OrderType::Limit, OrderSide::Sell, 0.25, Some(20_000)))
+        .await
+        .json();
+    app.post(&alice, "/api/v1/orders")
+        .json(&order(OrderType::Limit, OrderSide::Sell, 0.25, Some(20_100)))
+        .await
+        .assert_status_ok();
+
+    // Bob has no USD, which a simulation does not need
+    let market = order(OrderType::Market, OrderSide::Buy, 0.375, None);
+    let simulation: OrderSimulation = app.post(&bob, "/api/v1/orders/simulate").json(&market).await.json();
+    assert_eq!(
+        simulation.fills,
+        vec![
+            SimulatedFill { price: Decimal::from(20_000), quantity: Decimal::new(25, 2) },
+            SimulatedFill { price: Decimal::from(20_100), quantity: Decimal::new(125, 3) },
+        ]
+    );
+    assert_eq!(simulation.notional, Decimal::new(75_125, 1));
+    assert_eq!(simulation.best_price, Some(Decimal::from(20_000)));
+    assert_eq!(simulation.slippage_percent, Some(Decimal::new(1667, 4)));
+    assert_eq!(simulation.fee, simulation.notional * simulation.fee_rate);
+    assert!(simulation.remaining_quantity.is_zero() && !simulation.rests);
+
+    let again: OrderSimulation = app.post(&bob, "/api/v1/orders/simulate").json(&market).await.json();
+    assert_eq!(again.fills, simulation.fills);
+    let resting: Order = app.get(&alice, &format!("/api/v1/orders/{}", resting.id)).await.json();
+    assert_eq!(resting.status, Some(OrderStatus::Open));
+
+    let mut fok = order(OrderType::Limit, OrderSide::Buy, 1.0, Some(20_100));
+    fok.time_in_force = Some(TimeInForce::FOK);
+    let rejected: OrderSimulation = app.post(&bob, "/api/v1/orders/simulate").json(&fok).await.json();
+    assert!(rejected.fills.is_empty());
+    assert_eq!(rejected.rejection_reason.as_deref(), Some("fill_or_kill_unfillable"));
+
+    let mut stop = order(OrderType::StopLoss, OrderSide::Sell, 0.1, None);
+    stop.stop_price = Some(Decimal::from(19_000));
+    app.post(&alice, "/api/v1/orders/simulate").json(&stop).expect_failure().await.assert_status_bad_request();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn orders_placed_together_are_journaled_with_their_rows() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::from(16)).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(1_000)).await;
+    let limit = |side, price| limit_order(pair.id, side, price, 1.0);
+
+    // Placed at once, so the shard journals them in shared batches
+    let sells: Vec<Order> = futures::future::join_all(
+        (100..116).map(|price| {
+            std::future::IntoFuture::into_future(app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, price)))
+        }),
+    )
+    .await
+    .into_iter()
+    .map(|response| response.json())
+    .collect();
+    assert!(sells.iter().all(|order| order.status == Some(OrderStatus::Open)));
+    let buy: Order = app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 101)).await.json();
+    assert_eq!(buy.status, Some(OrderStatus::Filled));
+
+    let history: Vec<OrderEvent> = app.get(&alice, &format!("/api/v1/orders/{}/history", sells[5].id)).await.json();
+    let kinds: Vec<_> = history.iter().map(|event| (event.event_type, event.status, event.actor_id)).collect();
+    assert_eq!(
+        kinds,
+        [
+            (OrderEventType::Created, OrderStatus::Pending, Some(alice.id)),
+            (OrderEventType::Accepted, OrderStatus::Open, None),
+        ]
+    );
+
+    // The book rebuilt from the journal holds exactly the orders left open
+    let mut open: Vec<uuid::Uuid> =
+        sqlx::query_scalar("SELECT id FROM orders WHERE trading_pair_id = $1 AND status = 'open'")
+            .bind(pair.id)
+            .fetch_all(&app.db)
+            .await
+            .unwrap();
+    let recovered = EventLogService::new(app.db.clone()).load_book(pair.id).await.unwrap();
+    let mut resting: Vec<uuid::Uuid> = recovered.book.resting_orders().iter().map(|order| order.order_id).collect();
+    open.sort();
+    resting.sort();
+    assert_eq!(open.len(), 14);
+    assert_eq!(resting, open);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn a_sweep_settles_every_fill_with_consistent_balances() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let pair = app.seed_trading_pair("BTC-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::from(3)).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(1_000)).await;
+    let limit = |side, quantity, price| limit_order(pair.id, side, price, quantity);
+    for price in [100, 101, 102] {
+        app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 1.0, price)).await.assert_status_ok();
+    }
+
+    let buy: Order = app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 3.0, 105)).await.json();
+    assert_eq!(buy.status, Some(OrderStatus::Filled));
+    let trades: Vec<Trade> = app.get(&bob, "/api/v1/trades").await.json();
+    assert_eq!(trades.len(), 3);
+
+    // 303 plus the 0.1% taker fee; the rest of the lock at 105 is released
+    let accounts: Vec<Account> = app.get(&bob, "/api/v1/user/accounts").await.json();
+    let usd = accounts.iter().find(|account| account.currency == "USD").unwrap();
+    assert_eq!(usd.balance, Some(Decimal::new(696_697, 3)));
+    assert_eq!(usd.locked_balance, Some(Decimal::ZERO));
+    let btc = accounts.iter().find(|account| account.currency == "BTC").unwrap();
+    assert_eq!(btc.balance, Some(Decimal::from(3)));
+
+    // Every entry's running balance follows from the one before it
+    let statement: Statement = app
+        .get(&bob, "/api/v1/user/statement")
+        .add_query_param("currency", "USD")
+        .await
+        .json();
+    assert_eq!(statement.entries.len(), 6);
+    assert_eq!(statement.entries[0].balance_after, Some(Decimal::from(900)));
+    for pair in statement.entries.windows(2) {
+        assert_eq!(pair[1].balance_after, pair[0].balance_after.map(|balance| balance + pair[1].amount));
+    }
+    assert_eq!(statement.entries.last().unwrap().balance_after, usd.balance);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn market_data_for_every_pair_is_read_in_one_query() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let bob = app.seed_user("bob").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+    let eth = app.seed_trading_pair("ETH-USD").await;
+    let sol = app.seed_trading_pair("SOL-USD").await;
+    app.seed_balance(alice.id, "BTC", Decimal::from(2)).await;
+    app.seed_balance(bob.id, "USD", Decimal::from(1_000)).await;
+    let limit = |side, quantity, price| limit_order(btc.id, side, price, quantity);
+    for price in [100, 101] {
+        app.post(&alice, "/api/v1/orders").json(&limit(OrderSide::Sell, 1.0, price)).await.assert_status_ok();
+    }
+    app.post(&bob, "/api/v1/orders").json(&limit(OrderSide::Buy, 2.0, 105)).await.assert_status_ok();
+
+    // Warm the pool so opening a connection is not counted
+    let market_data = &app.state.market_data_service;
+    market_data.get_all_market_data(None).await.unwrap();
+
+    let (everything, queries) = count_queries(market_data.get_all_market_data(None)).await;
+    assert_eq!(queries, 1);
+    let everything = everything.unwrap();
+    assert_eq!(everything.iter().map(|data| data.symbol.as_str()).collect::<Vec<_>>(), ["BTC-USD", "ETH-USD", "SOL-USD"]);
+    let btc_data = &everything[0];
+    assert_eq!(btc_data.last_price, Decimal::from(101));
+    assert_eq!((btc_data.low_24h, btc_data.high_24h), (Decimal::from(100), Decimal::from(101)));
+    assert_eq!(btc_data.volume_24h, Decimal::from(201));
+    assert_eq!(btc_data.price_change_percent_24h, Decimal::from(1));
+    assert_eq!(everything[1].last_price, Decimal::ZERO);
+
+    let (watched, queries) = count_queries(market_data.get_market_data_for(&[sol.id, btc.id, eth.id], None)).await;
+    assert_eq!(queries, 1);
+    let watched: Vec<_> = watched.unwrap().into_iter().map(|data| data.symbol).collect();
+    assert_eq!(watched, ["SOL-USD", "BTC-USD", "ETH-USD"]);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn oversized_bodies_and_excess_exports_are_refused_with_structured_errors() {
+    let app = TestApp::spawn_with(|config| {
+        config.limits.body_limit_bytes = 1024;
+        config.limits.upload_limit_bytes = 4096;
+        config.limits.export_concurrency = 1;
+    })
+    .await;
+    let alice = app.seed_user("alice").await;
+    app.seed_trading_pair("BTC-USD").await;
+
+    let oversized = json!({ "padding": "x".repeat(2048) });
+    let response = app.post(&alice, "/api/v1/orders").json(&oversized).expect_failure().await;
+    response.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["code"], "PAYLOAD_TOO_LARGE");
+    assert_eq!(body["details"]["limit_bytes"], 1024);
+
+    // Uploads get the larger limit
+    let csv = format!("symbol,side,quantity,price\n{}", "BTC-USD,buy,0.01,19000\n".repeat(80));
+    app.post(&alice, "/api/v1/orders/import?dry_run=true").text(csv).await.assert_status_ok();
+    let csv = format!("symbol,side,quantity,price\n{}", "BTC-USD,buy,0.01,19000\n".repeat(200));
+    let response = app.post(&alice, "/api/v1/orders/import?dry_run=true").text(csv).expect_failure().await;
+    response.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(response.json::<serde_json::Value>()["details"]["limit_bytes"], 4096);
+
+    // With the only permit taken, another export is refused
+    let permit = app.state.export_permits.clone().try_acquire_owned().unwrap();
+    let response = app.get(&alice, "/api/v1/exports/trades").expect_failure().await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.json::<serde_json::Value>()["code"], "SERVER_BUSY");
+    drop(permit);
+    app.get(&alice, "/api/v1/exports/trades").await.assert_status_ok();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn symbol_routes_replace_the_deprecated_pair_id_routes() {
+    let app = TestApp::spawn().await;
+    let alice = app.seed_user("alice").await;
+    let btc = app.seed_trading_pair("BTC-USD").await;
+
+    let by_symbol = app.server.get("/api/v2/market-data/btc-usd").await;
+    by_symbol.assert_status_ok();
+    assert!(by_symbol.maybe_header("deprecation").is_none());
+    let by_symbol: MarketData = by_symbol.json();
+    assert_eq!(by_symbol.trading_pair_id, btc.id);
+    app.server.get("/api/v2/order-book/BTC-USD/stats").await.assert_status_ok();
+    app.server.get("/api/v2/trades/DOGE-USD").expect_failure().await.assert_status(StatusCode::NOT_FOUND);
+
+    let by_id = app.get(&alice, &format!("/api/v1/market-data/{}", btc.id)).await;
+    by_id.assert_status_ok();
+    assert_eq!(by_id.header("deprecation"), "@1792195200");
+    assert_eq!(by_id.header("sunset"), "Sat, 17 Apr 2027 00:00:00 GMT");
+    app.get(&alice, &format!("/api/v1/market-data/{}", btc.id)).await.assert_status_ok();
+
+    let metrics = app.server.get("/api/v1/metrics").await.text();
+    assert!(metrics.contains("deprecated_route_requests_total{route=\"/api/v1/market-data/:pair_id\"} 2"));
+    assert!(metrics.contains("deprecated_route_callers{route=\"/api/v1/market-data/:pair_id\"} 1"));
+
+    let spec: serde_json::Value = app.server.get("/api-doc/openapi.json").await.json();
+    assert_eq!(spec["paths"]["/api/v1/trades/{pair_id}"]["get"]["deprecated"], true);
+    assert!(spec["paths"]["/api/v2/trades/{symbol}"]["get"].get("deprecated").is_none());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn kyc_tiers_cap_daily_deposits_until_an_admin_raises_the_tier() {
+    let app = TestApp::spawn_with(|config| {
+        config.kyc_tiers.basic.daily_deposit = Decimal::from(1_000);
+        config.payments.bank_transfer.enabled = true;
+        config.payments.bank_transfer.account_number = "12345678".to_string();
+    })
+    .await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+
+    let profile: UserProfile = app.get(&alice, "/api/v1/user/profile").await.json();
+    assert_eq!(profile.kyc_tier, KycTier::Basic);
+    assert_eq!(profile.kyc_limits.daily_deposit, Decimal::from(1_000));
+
+    let deposit = || app.post(&alice, "/api/v1/payments").json(&json!({ "method": "bank_transfer", "amount": "600" }));
+    deposit().await.assert_status_ok();
+    // Pending deposits count towards the day's total
+    let response = deposit().expect_failure().await;
+    response.assert_status_bad_request();
+    let error: serde_json::Value = response.json();
+    assert_eq!(error["code"], "RISK_LIMIT_EXCEEDED");
+    assert_eq!(error["details"]["limit"], "daily_deposit");
+    assert_eq!(error["details"]["current"], "600");
+
+    let tier_path = format!("/api/v1/admin/users/{}/kyc-tier", alice.id);
+    app.server
+        .put(&tier_path)
+        .authorization_bearer(&alice.access_token)
+        .json(&json!({ "kyc_tier": "enhanced" }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+    let profile: UserProfile = app
+        .server
+        .put(&tier_path)
+        .authorization_bearer(&admin.access_token)
+        .json(&json!({ "kyc_tier": "verified" }))
+        .await
+        .json();
+    assert_eq!(profile.kyc_tier, KycTier::Verified);
+    assert_eq!(profile.kyc_limits.daily_deposit, app.config.kyc_tiers.verified.daily_deposit);
+    deposit().await.assert_status_ok();
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn improbable_travel_between_logins_needs_confirmation() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let credentials = json!({ "email": alice.email, "password": TEST_PASSWORD });
+    let login_from = |country: &'static str, latitude: &'static str, longitude: &'static str| {
+        app.server
+            .post("/api/v1/auth/login")
+            .add_header("cf-ipcountry", country)
+            .add_header("cf-iplatitude", latitude)
+            .add_header("cf-iplongitude", longitude)
+            .json(&credentials)
+    };
+
+    login_from("US", "40.7128", "-74.0060").await.assert_status_ok();
+    // London, minutes after New York
+    let held = login_from("GB", "51.5074", "-0.1278").expect_failure().await;
+    held.assert_status(StatusCode::FORBIDDEN);
+    let body: serde_json::Value = held.json();
+    assert_eq!(body["code"], "SUSPICIOUS_LOGIN");
+    let challenge_id: uuid::Uuid = body["details"]["challenge_id"].as_str().unwrap().parse().unwrap();
+
+    // Until confirmed, New York stays the last trusted location
+    login_from("US", "40.7357", "-74.1724").await.assert_status_ok();
+    login_from("GB", "51.5074", "-0.1278").expect_failure().await.assert_status(StatusCode::FORBIDDEN);
+
+    sqlx::query("UPDATE device_challenges SET token_hash = encode(sha256('known-token'::bytea), 'hex') WHERE id = $1")
+        .bind(challenge_id)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    app.server
+        .post("/api/v1/auth/devices/confirm")
+        .json(&json!({ "token": "known-token" }))
+        .await
+        .assert_status_ok();
+    login_from("GB", "51.5074", "-0.1278").await.assert_status_ok();
+
+    let path = format!("/api/v1/admin/users/{}/login-risk", alice.id);
+    app.get(&alice, &path).expect_failure().await.assert_status(StatusCode::FORBIDDEN);
+    let risk: AccountLoginRisk = app.get(&admin, &path).await.json();
+    assert_eq!(risk.risk_score, 90);
+    let statuses: Vec<_> = risk.logins.iter().map(|login| login.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            LoginEventStatus::Allowed,
+            LoginEventStatus::Challenged,
+            LoginEventStatus::Allowed,
+            LoginEventStatus::Confirmed,
+            LoginEventStatus::Allowed,
+            LoginEventStatus::Allowed,
+        ]
+    );
+    assert!(risk.logins[3].improbable_travel);
+    assert!(risk.logins[3].distance_km.unwrap() > 5_000);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn shared_address_deposits_are_credited_by_memo() {
+    let app = TestApp::spawn_with(|config| {
+        config.blockchain.deposit_watcher_secret = "watcher-secret".to_string();
+        config.kyc_tiers.basic.daily_withdrawal = Decimal::from(100);
+    })
+    .await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    app.post(&admin, "/api/v1/admin/currencies")
+        .json(&json!({
+            "code": "XRP",
+            "name": "XRP",
+            "decimals": 6,
+            "memo_format": "tag",
+            "deposit_address": "rExchangeShared",
+            "deposit_confirmations": 2,
+        }))
+        .await
+        .assert_status_ok();
+
+    let address: DepositAddress = app.get(&alice, "/api/v1/deposits/crypto/XRP/address").await.json();
+    assert_eq!(address.address, "rExchangeShared");
+    let memo = address.memo.clone().unwrap();
+    let again: DepositAddress = app.get(&alice, "/api/v1/deposits/crypto/xrp/address").await.json();
+    assert_eq!(again.memo, Some(memo.clone()));
+
+    let report = |tx_id: &str, output_index: i32, memo: &str, confirmations: i32| {
+        let body = serde_json::to_vec(&json!({
+            "currency": "XRP",
+            "address": "rExchangeShared",
+            "memo": memo,
+            "tx_id": tx_id,
+            "output_index": output_index,
+            "amount": "25",
+            "confirmations": confirmations,
+        }))
+        .unwrap();
+        app.server
+            .post("/api/v1/webhooks/deposits")
+            .add_header("x-watcher-signature", sign_deposit_report("watcher-secret", &body))
+            .content_type("application/json")
+            .bytes(body.into())
+    };
+    let xrp = |user| {
+        let request = app.get(user, "/api/v1/user/accounts");
+        async {
+            let accounts: Vec<Account> = request.await.json();
+            accounts
+                .into_iter()
+                .find(|account| account.currency == "XRP")
+                .and_then(|account| account.available_balance)
+                .unwrap_or_default()
+        }
+    };
+
+    // Credited once it has its confirmations, and only once
+    report("tx-1", 0, &memo, 1).await.assert_status(StatusCode::NO_CONTENT);
+    assert_eq!(xrp(&alice).await, Decimal::ZERO);
+    for confirmations in [2, 3] {
+        report("tx-1", 0, &memo, confirmations).await.assert_status(StatusCode::NO_CONTENT);
+    }
+    assert_eq!(xrp(&alice).await, Decimal::new(25, 0));
+
+    // Each output of a transaction paying several users is its own deposit
+    let bob = app.seed_user("bob").await;
+    let bob_address: DepositAddress = app.get(&bob, "/api/v1/deposits/crypto/XRP/address").await.json();
+    report("tx-2", 0, &memo, 2).await.assert_status(StatusCode::NO_CONTENT);
+    report("tx-2", 1, bob_address.memo.as_deref().unwrap(), 2).await.assert_status(StatusCode::NO_CONTENT);
+    assert_eq!(xrp(&alice).await, Decimal::new(50, 0));
+    assert_eq!(xrp(&bob).await, Decimal::new(25, 0));
+
+    // Memos nobody holds wait for an admin, each output on its own
+    report("tx-3", 0, "1", 2).await.assert_status(StatusCode::NO_CONTENT);
+    report("tx-3", 1, "2", 2).await.assert_status(StatusCode::NO_CONTENT);
+    report("tx-3", 1, "2", 3).await.assert_status(StatusCode::NO_CONTENT);
+    let unmatched: Vec<UnmatchedDeposit> = app.get(&admin, "/api/v1/admin/deposits/unmatched").await.json();
+    assert_eq!(unmatched.len(), 2);
+    assert_eq!(unmatched[0].output_index, 0);
+    app.post(&admin, &format!("/api/v1/admin/deposits/unmatched/{}/credit", unmatched[0].id))
+        .json(&json!({ "user_id": alice.id }))
+        .await
+        .assert_status_ok();
+    app.post(&admin, &format!("/api/v1/admin/deposits/unmatched/{}/credit", unmatched[1].id))
+        .json(&json!({ "user_id": alice.id }))
+        .await
+        .assert_status_ok();
+    assert_eq!(xrp(&alice).await, Decimal::new(100, 0));
+    let unmatched: Vec<UnmatchedDeposit> = app.get(&admin, "/api/v1/admin/deposits/unmatched").await.json();
+    assert!(unmatched.is_empty());
+
+    let body = b"{}".to_vec();
+    app.server
+        .post("/api/v1/webhooks/deposits")
+        .add_header("x-watcher-signature", sign_deposit_report("wrong-secret", &body))
+        .bytes(body.into())
+        .expect_failure()
+        .await
+        .assert_status_unauthorized();
+
+    // Withdrawal memos must be destination tags, and sending to the shared
+    // address needs one
+    for (address, memo) in [("rElsewhere", json!("not-a-tag")), ("rExchangeShared", json!(null))] {
+        app.post(&alice, "/api/v1/withdrawals/crypto")
+            .json(&json!({ "currency": "XRP", "address": address, "memo": memo, "amount": "10" }))
+            .expect_failure()
+            .await
+            .assert_status_bad_request();
+    }
+    let withdraw = |amount: &str| {
+        app.post(&alice, "/api/v1/withdrawals/crypto")
+            .json(&json!({ "currency": "XRP", "address": "rElsewhere", "memo": "12345", "amount": amount }))
+    };
+
+    // The daily limit is in USD, so XRP cannot leave until it has a price
+    withdraw("10").expect_failure().await.assert_status_bad_request();
+    let pair = app.seed_trading_pair("XRP-USD").await;
+    let carol = app.seed_user("carol").await;
+    app.seed_balance(carol.id, "USD", Decimal::from(100)).await;
+    app.post(&bob, "/api/v1/orders")
+        .json(&limit_order(pair.id, OrderSide::Sell, 2, 1.0))
+        .await
+        .assert_status_ok();
+    app.post(&carol, "/api/v1/orders")
+        .json(&limit_order(pair.id, OrderSide::Buy, 2, 1.0))
+        .await
+        .assert_status_ok();
+
+    let withdrawal: CryptoWithdrawal = withdraw("10").await.json();
+    assert_eq!(withdrawal.memo.as_deref(), Some("12345"));
+    assert_eq!(xrp(&alice).await, Decimal::new(90, 0));
+    // 51 XRP at 2 USD is past the 100 USD a day
+    let rejected = withdraw("41").expect_failure().await;
+    rejected.assert_status_bad_request();
+    let body: serde_json::Value = rejected.json();
+    assert_eq!(body["details"]["limit"], "daily_withdrawal");
+    assert_eq!(body["details"]["current"], "20");
+    app.delete(&alice, &format!("/api/v1/withdrawals/crypto/{}", withdrawal.id))
+        .await
+        .assert_status_ok();
+    assert_eq!(xrp(&alice).await, Decimal::new(100, 0));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL/TEST_REDIS_URL"]
+async fn journal_replay_is_admin_only() {
+    let app = TestApp::spawn().await;
+    let admin = app.seed_admin("admin").await;
+    let alice = app.seed_user("alice").await;
+    let pair = app.seed_trading_pair("BTC/USDT").await;
+    let path = format!("/api/v1/admin/engine/replay/{}", pair.id);
+
+    app.get(&alice, &path).expect_failure().await.assert_status(StatusCode::FORBIDDEN);
+    app.get(&admin, &path).await.assert_status_ok();
+}
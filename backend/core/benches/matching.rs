@@ -0,0 +1,68 @@
+use chrono::Utc;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use cryptotrade_core::{replay, EngineCommand, LimitOrderBook, SyntheticOrderFlow};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+const FLOW_LENGTH: usize = 10_000;
+
+fn flow(seed: u64, cancel_ratio: f64, cross_ticks: i64) -> Vec<EngineCommand> {
+    SyntheticOrderFlow::new(seed, Decimal::from(30_000))
+        .with_cancel_ratio(cancel_ratio)
+        .with_cross_ticks(cross_ticks)
+        .take(FLOW_LENGTH)
+        .collect()
+}
+
+fn run(book: &mut LimitOrderBook, commands: &[EngineCommand]) {
+    let now = Utc::now();
+    for command in commands {
+        black_box(book.process(command, now));
+    }
+}
+
+fn bench_order_flow(c: &mut Criterion) {
+    let pair_id = Uuid::new_v4();
+    let mut group = c.benchmark_group("matching");
+    group.throughput(Throughput::Elements(FLOW_LENGTH as u64));
+
+    for (name, commands) in [
+        ("passive_adds", flow(1, 0.0, 0)),
+        ("mixed_flow", flow(2, 0.2, 5)),
+        ("aggressive_flow", flow(3, 0.1, 25)),
+        ("cancel_heavy", flow(4, 0.6, 5)),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || LimitOrderBook::new(pair_id),
+                |mut book| run(&mut book, &commands),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_recovery(c: &mut Criterion) {
+    let pair_id = Uuid::new_v4();
+    let mut live = LimitOrderBook::new(pair_id);
+    let journal: Vec<_> = flow(5, 0.2, 5)
+        .iter()
+        .flat_map(|command| live.process(command, Utc::now()))
+        .collect();
+    let snapshot = live.snapshot();
+
+    let mut group = c.benchmark_group("recovery");
+    group.throughput(Throughput::Elements(journal.len() as u64));
+    group.bench_function("replay_full_journal", |b| {
+        b.iter(|| black_box(replay(LimitOrderBook::new(pair_id), &journal)))
+    });
+    group.bench_function("restore_snapshot", |b| {
+        b.iter(|| black_box(LimitOrderBook::from_snapshot(&snapshot)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_order_flow, bench_recovery);
+criterion_main!(benches);
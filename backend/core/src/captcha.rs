@@ -0,0 +1,101 @@
+//! CAPTCHA verification. hCaptcha and Cloudflare Turnstile share the
+//! siteverify protocol: the token the widget hands the client is posted
+//! back with the site's secret, and the answer says whether it was solved.
+//! [`crate::services::CaptchaService`] decides when a request needs one.
+
+use crate::config::{CaptchaConfig, CaptchaProviderKind};
+use crate::error::CryptoTradeError;
+use crate::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+pub const HCAPTCHA_VERIFY_URL: &str = "https://api.hcaptcha.com/siteverify";
+pub const TURNSTILE_VERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+#[async_trait]
+pub trait CaptchaVerifier: Send + Sync {
+    /// Whether `token` is a solved, unused CAPTCHA for this site.
+    async fn verify(&self, token: &str, remote_ip: Option<IpAddr>) -> Result<bool>;
+}
+
+/// The verifier `config` selects, or `None` when CAPTCHAs are disabled.
+pub fn verifier(config: &CaptchaConfig) -> Option<Arc<dyn CaptchaVerifier>> {
+    let default_url = match config.provider {
+        CaptchaProviderKind::Disabled => return None,
+        CaptchaProviderKind::Hcaptcha => HCAPTCHA_VERIFY_URL,
+        CaptchaProviderKind::Turnstile => TURNSTILE_VERIFY_URL,
+    };
+    let url = if config.verify_url.is_empty() { default_url } else { &config.verify_url };
+    Some(Arc::new(SiteVerify::new(url, &config.secret)))
+}
+
+/// A siteverify endpoint, hCaptcha's or Turnstile's.
+pub struct SiteVerify {
+    client: reqwest::Client,
+    url: String,
+    secret: String,
+}
+
+impl SiteVerify {
+    pub fn new(url: &str, secret: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            secret: secret.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+#[async_trait]
+impl CaptchaVerifier for SiteVerify {
+    async fn verify(&self, token: &str, remote_ip: Option<IpAddr>) -> Result<bool> {
+        let mut form = vec![("secret", self.secret.clone()), ("response", token.to_string())];
+        if let Some(ip) = remote_ip {
+            form.push(("remoteip", ip.to_string()));
+        }
+        let response: SiteVerifyResponse = self
+            .client
+            .post(&self.url)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(captcha_error)?
+            .json()
+            .await
+            .map_err(captcha_error)?;
+        if !response.success && !response.error_codes.is_empty() {
+            tracing::debug!("CAPTCHA rejected: {}", response.error_codes.join(", "));
+        }
+        Ok(response.success)
+    }
+}
+
+fn captcha_error(e: reqwest::Error) -> CryptoTradeError {
+    CryptoTradeError::CaptchaProvider { message: e.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_siteverify_response_reads_error_codes() {
+        let rejected: SiteVerifyResponse =
+            serde_json::from_str(r#"{"success": false, "error-codes": ["timeout-or-duplicate"]}"#).unwrap();
+        assert!(!rejected.success);
+        assert_eq!(rejected.error_codes, ["timeout-or-duplicate"]);
+
+        let solved: SiteVerifyResponse = serde_json::from_str(r#"{"success": true, "hostname": "example.com"}"#).unwrap();
+        assert!(solved.success && solved.error_codes.is_empty());
+    }
+}
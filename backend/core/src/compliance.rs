@@ -0,0 +1,267 @@
+//! Sanctions screening and the travel rule. A screener compares a user's
+//! name against a sanctions list and reports the listed names close enough
+//! to need a compliance officer's review;
+//! [`crate::services::ComplianceService`] holds the account until each is
+//! resolved. A travel rule provider passes the originator and beneficiary
+//! of a large withdrawal on to the receiving institution, and
+//! [`crate::services::FiatWithdrawalService`] holds the withdrawal until it
+//! has.
+
+use crate::config::{SanctionsConfig, TravelRuleConfig};
+use crate::error::CryptoTradeError;
+use crate::Result;
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// A listed name resembling the screened one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SanctionsMatch {
+    pub listed_name: String,
+    /// The list or sanctions program the name is on
+    pub list_name: String,
+    /// Share of the names' words in common, from 0 to 1
+    pub score: Decimal,
+}
+
+#[async_trait]
+pub trait SanctionsScreener: Send + Sync {
+    /// Listed names matching `name` at or above the screener's threshold.
+    async fn screen(&self, name: &str) -> Result<Vec<SanctionsMatch>>;
+}
+
+/// A sanctions list held in memory, such as an export of the OFAC SDN or
+/// EU consolidated list, one `name,program` entry per line. Names compare
+/// word by word, ignoring case, punctuation and word order, so
+/// "KIM, Jong Un" matches "Jong-Un Kim".
+pub struct SanctionsList {
+    entries: Vec<ListedName>,
+    threshold: Decimal,
+}
+
+struct ListedName {
+    name: String,
+    program: String,
+    words: HashSet<String>,
+}
+
+impl SanctionsList {
+    pub fn load(config: &SanctionsConfig) -> Result<Self> {
+        let csv = std::fs::read_to_string(&config.list_path)?;
+        Ok(Self::parse(&csv, config.match_threshold))
+    }
+
+    /// Reads `name,program` lines; blank lines and `#` comments are
+    /// skipped, and a name containing commas must be quoted.
+    pub fn parse(csv: &str, threshold: Decimal) -> Self {
+        let entries = csv
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (name, program) = match line.strip_prefix('"') {
+                    Some(quoted) => {
+                        let (name, rest) = quoted.split_once('"')?;
+                        (name, rest.trim_start_matches(','))
+                    }
+                    None => line.split_once(',').unwrap_or((line, "")),
+                };
+                let words = words(name);
+                (!words.is_empty()).then(|| ListedName {
+                    name: name.trim().to_string(),
+                    program: program.trim().to_string(),
+                    words,
+                })
+            })
+            .collect();
+        Self { entries, threshold }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn matches(&self, name: &str) -> Vec<SanctionsMatch> {
+        let screened = words(name);
+        if screened.is_empty() {
+            return Vec::new();
+        }
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                let common = entry.words.intersection(&screened).count();
+                let score = (Decimal::from(common) / Decimal::from(entry.words.len().max(screened.len()))).round_dp(4);
+                (score >= self.threshold).then(|| SanctionsMatch {
+                    listed_name: entry.name.clone(),
+                    list_name: entry.program.clone(),
+                    score,
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl SanctionsScreener for SanctionsList {
+    async fn screen(&self, name: &str) -> Result<Vec<SanctionsMatch>> {
+        Ok(self.matches(name))
+    }
+}
+
+/// One side of a transfer, as the travel rule describes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TravelRuleParty {
+    pub name: String,
+    pub address: String,
+    /// ISO 3166-1 alpha-2
+    pub country: String,
+    /// IBAN or account number
+    pub account: String,
+    /// BIC or routing number of the party's institution; `None` for the
+    /// exchange's own customers
+    pub institution: Option<String>,
+}
+
+/// What travels with a withdrawal: who sends it, who receives it and how much.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TravelRulePayload {
+    pub transfer_id: Uuid,
+    pub currency: String,
+    pub amount: Decimal,
+    pub originator: TravelRuleParty,
+    pub beneficiary: TravelRuleParty,
+}
+
+#[async_trait]
+pub trait TravelRuleProvider: Send + Sync {
+    /// Stored with each record, e.g. `record` or `http`
+    fn name(&self) -> &'static str;
+
+    /// Passes the payload on and returns the provider's reference for it.
+    async fn transmit(&self, payload: &TravelRulePayload) -> Result<String>;
+}
+
+/// Keeps payloads on record only, for institutions that collect travel
+/// rule data out of band or for regulators that ask for it later.
+pub struct RecordOnly;
+
+#[async_trait]
+impl TravelRuleProvider for RecordOnly {
+    fn name(&self) -> &'static str {
+        "record"
+    }
+
+    async fn transmit(&self, payload: &TravelRulePayload) -> Result<String> {
+        Ok(payload.transfer_id.to_string())
+    }
+}
+
+/// Posts payloads as JSON to a travel rule network's gateway, which is
+/// expected to answer with `{"id": "..."}`.
+pub struct HttpTravelRuleProvider {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+}
+
+impl HttpTravelRuleProvider {
+    pub fn new(config: &TravelRuleConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoint: config.endpoint.clone(),
+            api_key: config.api_key.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl TravelRuleProvider for HttpTravelRuleProvider {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn transmit(&self, payload: &TravelRulePayload) -> Result<String> {
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .bearer_auth(&self.api_key)
+            // Resubmitting the same withdrawal never creates a second transfer
+            .header("Idempotency-Key", payload.transfer_id.to_string())
+            .json(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(travel_rule_error)?;
+        let body: serde_json::Value = response.json().await.map_err(travel_rule_error)?;
+        body["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| CryptoTradeError::TravelRuleProvider {
+                message: "response has no id".to_string(),
+            })
+    }
+}
+
+fn travel_rule_error(e: reqwest::Error) -> CryptoTradeError {
+    CryptoTradeError::TravelRuleProvider { message: e.to_string() }
+}
+
+/// A name's words, lower case and without punctuation.
+fn words(name: &str) -> HashSet<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// `code` as an upper case ISO 3166-1 alpha-2 country code, or `None` if it
+/// is not shaped like one.
+pub fn country_code(code: &str) -> Option<String> {
+    let code = code.trim();
+    (code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic())).then(|| code.to_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: &str = "# name,program\n\"KIM, Jong Un\",DPRK\nIvan Petrov Sidorov,RUSSIA-EO14024\n\nNamed Only\n";
+
+    #[test]
+    fn test_parse_reads_quoted_names_and_skips_comments() {
+        let list = SanctionsList::parse(LIST, Decimal::new(8, 1));
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.entries[0].name, "KIM, Jong Un");
+        assert_eq!(list.entries[0].program, "DPRK");
+        assert_eq!(list.entries[2].program, "");
+    }
+
+    #[test]
+    fn test_names_match_regardless_of_order_case_and_punctuation() {
+        let list = SanctionsList::parse(LIST, Decimal::new(8, 1));
+
+        let matches = list.matches("jong-un KIM");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].list_name, "DPRK");
+        assert_eq!(matches[0].score, Decimal::ONE);
+
+        // Two of three words fall short of the threshold
+        assert!(list.matches("Ivan Petrov").is_empty());
+        assert_eq!(SanctionsList::parse(LIST, Decimal::new(6, 1)).matches("Ivan Petrov")[0].score, Decimal::new(6667, 4));
+        assert!(list.matches("Jane Doe").is_empty());
+        assert!(list.matches(" - ").is_empty());
+    }
+
+    #[test]
+    fn test_country_code() {
+        assert_eq!(country_code(" kp "), Some("KP".to_string()));
+        assert_eq!(country_code("PRK"), None);
+        assert_eq!(country_code("1A"), None);
+    }
+}
@@ -1,16 +1,33 @@
-use crate::{config::DatabaseConfig, Result};
-use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::time::Duration;
+use crate::{
+    config::{DatabaseConfig, RedisConfig},
+    error::CryptoTradeError,
+    Result,
+};
+use log::LevelFilter;
+use redis::aio::ConnectionManager;
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    ConnectOptions, PgPool,
+};
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub type Database = PgPool;
 
 pub async fn connect(config: &DatabaseConfig) -> Result<Database> {
+    // sqlx reports slow statements under the `sqlx::query` tracing target
+    // with the SQL, row counts and elapsed time
+    let options = PgConnectOptions::from_str(&config.url)?
+        .log_slow_statements(LevelFilter::Warn, Duration::from_millis(config.slow_query_threshold_ms));
+
     let pool = PgPoolOptions::new()
         .max_connections(config.max_connections)
         .min_connections(config.min_connections)
         .acquire_timeout(Duration::from_secs(config.connect_timeout))
         .idle_timeout(Duration::from_secs(config.idle_timeout))
-        .connect(&config.url)
+        .connect_with(options)
         .await?;
 
     // Run migrations
@@ -19,7 +36,96 @@ pub async fn connect(config: &DatabaseConfig) -> Result<Database> {
     Ok(pool)
 }
 
+pub async fn connect_redis(config: &RedisConfig) -> Result<ConnectionManager> {
+    let client = redis::Client::open(config.url.as_str())?;
+    let connection = tokio::time::timeout(
+        Duration::from_secs(config.connect_timeout),
+        ConnectionManager::new(client),
+    )
+    .await
+    .map_err(|_| CryptoTradeError::Internal)??;
+
+    Ok(connection)
+}
+
 pub async fn health_check(db: &Database) -> Result<()> {
     sqlx::query("SELECT 1").fetch_one(db).await?;
     Ok(())
 }
+
+/// Prometheus metrics for the connection pool. Queries acquire connections
+/// inside sqlx, out of reach of instrumentation, so acquire wait time is
+/// sampled by a probe that takes a connection on an interval: it waits as
+/// long as a query arriving at that moment would have.
+#[derive(Clone)]
+pub struct PoolMetrics {
+    db: Database,
+    wait: Arc<Mutex<AcquireWait>>,
+}
+
+#[derive(Default)]
+struct AcquireWait {
+    count: u64,
+    sum: Duration,
+    last: Duration,
+    timeouts: u64,
+}
+
+impl PoolMetrics {
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            wait: Arc::new(Mutex::new(AcquireWait::default())),
+        }
+    }
+
+    pub fn spawn_probe(&self, interval: Duration) {
+        let metrics = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let started = Instant::now();
+                let acquired = metrics.db.acquire().await;
+                let waited = started.elapsed();
+
+                let mut wait = metrics.wait.lock().unwrap_or_else(|e| e.into_inner());
+                match acquired {
+                    Ok(_) => {
+                        wait.count += 1;
+                        wait.sum += waited;
+                        wait.last = waited;
+                    }
+                    Err(_) => wait.timeouts += 1,
+                }
+            }
+        });
+    }
+
+    /// The metrics in Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let size = self.db.size();
+        let idle = self.db.num_idle() as u32;
+        let wait = self.wait.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP db_pool_connections Open connections by state");
+        let _ = writeln!(out, "# TYPE db_pool_connections gauge");
+        let _ = writeln!(out, "db_pool_connections{{state=\"in_use\"}} {}", size.saturating_sub(idle));
+        let _ = writeln!(out, "db_pool_connections{{state=\"idle\"}} {}", idle);
+        let _ = writeln!(out, "# HELP db_pool_max_connections Configured pool size limit");
+        let _ = writeln!(out, "# TYPE db_pool_max_connections gauge");
+        let _ = writeln!(out, "db_pool_max_connections {}", self.db.options().get_max_connections());
+        let _ = writeln!(out, "# HELP db_pool_acquire_wait_seconds Time to acquire a connection, sampled by the probe");
+        let _ = writeln!(out, "# TYPE db_pool_acquire_wait_seconds summary");
+        let _ = writeln!(out, "db_pool_acquire_wait_seconds_sum {}", wait.sum.as_secs_f64());
+        let _ = writeln!(out, "db_pool_acquire_wait_seconds_count {}", wait.count);
+        let _ = writeln!(out, "# HELP db_pool_acquire_wait_last_seconds Wait of the most recent probe");
+        let _ = writeln!(out, "# TYPE db_pool_acquire_wait_last_seconds gauge");
+        let _ = writeln!(out, "db_pool_acquire_wait_last_seconds {}", wait.last.as_secs_f64());
+        let _ = writeln!(out, "# HELP db_pool_acquire_timeouts_total Probes that timed out waiting for a connection");
+        let _ = writeln!(out, "# TYPE db_pool_acquire_timeouts_total counter");
+        let _ = writeln!(out, "db_pool_acquire_timeouts_total {}", wait.timeouts);
+        out
+    }
+}
@@ -0,0 +1,76 @@
+//! Usage of deprecated API routes, so their remaining callers can be found
+//! and told to migrate before the routes are removed.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+/// Counts requests to each deprecated route and the distinct callers making
+/// them since the server started. Clones share their counts.
+#[derive(Clone, Default)]
+pub struct DeprecatedRouteUsage {
+    routes: Arc<Mutex<BTreeMap<String, RouteUsage>>>,
+}
+
+#[derive(Default)]
+struct RouteUsage {
+    requests: u64,
+    callers: HashSet<String>,
+}
+
+impl DeprecatedRouteUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a request to `route`, a route template such as
+    /// `/api/v1/order-book/:pair_id`, from `caller`. A caller's first
+    /// request is logged so the team can reach out to them.
+    pub fn record(&self, route: &str, caller: &str) {
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let usage = routes.entry(route.to_string()).or_default();
+        usage.requests += 1;
+        if usage.callers.insert(caller.to_string()) {
+            tracing::info!("Deprecated route {} called by {}", route, caller);
+        }
+    }
+
+    /// The counts in Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP deprecated_route_requests_total Requests to deprecated routes");
+        let _ = writeln!(out, "# TYPE deprecated_route_requests_total counter");
+        for (route, usage) in routes.iter() {
+            let _ = writeln!(out, "deprecated_route_requests_total{{route=\"{}\"}} {}", route, usage.requests);
+        }
+        let _ = writeln!(out, "# HELP deprecated_route_callers Distinct callers of deprecated routes since startup");
+        let _ = writeln!(out, "# TYPE deprecated_route_callers gauge");
+        for (route, usage) in routes.iter() {
+            let _ = writeln!(out, "deprecated_route_callers{{route=\"{}\"}} {}", route, usage.callers.len());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_and_distinct_callers_are_counted_per_route() {
+        let usage = DeprecatedRouteUsage::new();
+        usage.record("/api/v1/trades/:pair_id", "api_key:1");
+        usage.record("/api/v1/trades/:pair_id", "api_key:1");
+        usage.record("/api/v1/trades/:pair_id", "ip:192.0.2.1");
+        usage.record("/api/v1/market-data/:pair_id", "api_key:1");
+
+        let metrics = usage.render();
+        assert!(metrics.contains("deprecated_route_requests_total{route=\"/api/v1/trades/:pair_id\"} 3"));
+        assert!(metrics.contains("deprecated_route_callers{route=\"/api/v1/trades/:pair_id\"} 2"));
+        assert!(metrics.contains("deprecated_route_callers{route=\"/api/v1/market-data/:pair_id\"} 1"));
+    }
+}
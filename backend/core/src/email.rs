@@ -0,0 +1,83 @@
+//! Outgoing email. A sender delivers one message at a time; which one is
+//! used follows `email.provider`.
+
+use crate::config::{EmailConfig, EmailProviderKind};
+use crate::error::CryptoTradeError;
+use crate::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, message: &EmailMessage) -> Result<()>;
+}
+
+/// The sender `config` selects.
+pub fn sender(config: &EmailConfig) -> Arc<dyn EmailSender> {
+    match config.provider {
+        EmailProviderKind::Log => Arc::new(LogSender),
+        EmailProviderKind::Http => Arc::new(HttpEmailSender::new(config)),
+    }
+}
+
+/// Writes messages to the log instead of sending them, for development and
+/// deployments without a mail provider.
+pub struct LogSender;
+
+#[async_trait]
+impl EmailSender for LogSender {
+    async fn send(&self, message: &EmailMessage) -> Result<()> {
+        tracing::info!("Email to {}: {}\n{}", message.to, message.subject, message.body);
+        Ok(())
+    }
+}
+
+/// Posts messages as JSON, with the configured sender address as `from`, to
+/// a transactional mail API such as Postmark's or a relay in front of SMTP.
+pub struct HttpEmailSender {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+    from: String,
+}
+
+impl HttpEmailSender {
+    pub fn new(config: &EmailConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoint: config.endpoint.clone(),
+            api_key: config.api_key.clone(),
+            from: config.from.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HttpEmail<'a> {
+    from: &'a str,
+    #[serde(flatten)]
+    message: &'a EmailMessage,
+}
+
+#[async_trait]
+impl EmailSender for HttpEmailSender {
+    async fn send(&self, message: &EmailMessage) -> Result<()> {
+        self.client
+            .post(&self.endpoint)
+            .bearer_auth(&self.api_key)
+            .json(&HttpEmail { from: &self.from, message })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| CryptoTradeError::Io(std::io::Error::other(format!("Sending email failed: {}", e))))?;
+        Ok(())
+    }
+}
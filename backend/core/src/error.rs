@@ -1,4 +1,21 @@
+use crate::{
+    i18n::{self, Locale},
+    models::TradingPermission,
+};
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use utoipa::ToSchema;
+
+tokio::task_local! {
+    /// Id of the HTTP request being handled, set by the API's request id
+    /// middleware so error responses can quote it.
+    pub static REQUEST_ID: String;
+}
 
 #[derive(Error, Debug)]
 pub enum CryptoTradeError {
@@ -32,11 +49,18 @@ pub enum CryptoTradeError {
     #[error("Order cannot be cancelled")]
     OrderNotCancellable,
 
+    #[error("Conflict: {message}")]
+    Conflict { message: String },
+
     #[error("Trading pair not found")]
     TradingPairNotFound,
 
-    #[error("Insufficient balance")]
-    InsufficientBalance,
+    #[error("Insufficient {currency} balance: {required} required, {available} available")]
+    InsufficientBalance {
+        currency: String,
+        required: Decimal,
+        available: Decimal,
+    },
 
     #[error("Invalid order type")]
     InvalidOrderType,
@@ -47,15 +71,114 @@ pub enum CryptoTradeError {
     #[error("Invalid quantity")]
     InvalidQuantity,
 
+    #[error("Price {price} is outside the allowed band of {min} to {max}")]
+    PriceOutOfBounds {
+        price: Decimal,
+        min: Decimal,
+        max: Decimal,
+    },
+
+    #[error("An identical order was placed in the last {window_seconds} seconds")]
+    DuplicateOrder { window_seconds: u64 },
+
+    #[error("Order would exceed the {limit} limit of {max} ({current} in use)")]
+    RiskLimitExceeded {
+        limit: String,
+        max: Decimal,
+        current: Decimal,
+    },
+
+    #[error("Order would trade against your own resting order")]
+    SelfTradePrevented,
+
+    #[error("Order failed {} pre-trade checks", failures.len())]
+    PreTradeChecksFailed { failures: Vec<CryptoTradeError> },
+
+    #[error("Missing the {permission:?} permission")]
+    PermissionDenied { permission: TradingPermission },
+
+    #[error("Trading pair {trading_pair_id} is not permitted")]
+    TradingPairNotPermitted { trading_pair_id: uuid::Uuid },
+
+    #[error("Request signature does not match")]
+    InvalidSignature,
+
+    #[error("Request timestamp is more than {recv_window_ms}ms from server time")]
+    StaleRequest { recv_window_ms: u64 },
+
+    #[error("Nonce has already been used")]
+    NonceReused,
+
+    #[error("API key may not be used from {ip}")]
+    IpNotAllowed { ip: String },
+
+    #[error("{message}")]
+    MaintenanceMode { message: String },
+
+    #[error("{dependency} is unavailable")]
+    DependencyUnavailable { dependency: &'static str },
+
+    #[error("Request did not complete within {timeout_seconds}s")]
+    RequestTimeout { timeout_seconds: u64 },
+
+    #[error("Request body is larger than {limit_bytes} bytes")]
+    PayloadTooLarge { limit_bytes: usize },
+
+    #[error("Too many {operation} requests in progress")]
+    ServerBusy { operation: &'static str },
+
+    #[error("Rate limit of {limit} requests per {window} exceeded")]
+    RateLimited {
+        limit: u64,
+        window: &'static str,
+        retry_after_seconds: u64,
+    },
+
+    #[error("Feature {feature} is not available")]
+    FeatureDisabled { feature: String },
+
+    #[error("Payment provider error: {message}")]
+    PaymentProvider { message: String },
+
+    #[error("Travel rule provider error: {message}")]
+    TravelRuleProvider { message: String },
+
+    #[error("CAPTCHA provider error: {message}")]
+    CaptchaProvider { message: String },
+
+    #[error("Hot wallet signer error: {message}")]
+    Signer { message: String },
+
     #[error("Trading pair not active")]
     TradingPairNotActive,
 
+    #[error("Trading pair is outside its trading hours")]
+    OutsideTradingSession,
+
     #[error("KYC verification required")]
     KycRequired,
 
     #[error("Two-factor authentication required")]
     TwoFactorRequired,
 
+    #[error("A valid CAPTCHA response is required")]
+    CaptchaRequired,
+
+    #[error("Login from a new device must be confirmed through the link sent by email")]
+    DeviceConfirmationRequired { challenge_id: uuid::Uuid },
+
+    #[error("Login from an unusual location must be confirmed through the link sent by email")]
+    SuspiciousLogin { challenge_id: uuid::Uuid },
+
+    #[error("Account is restricted: orders can only be cancelled and funds withdrawn")]
+    AccountRestricted,
+
+    #[error("Account is closed")]
+    AccountClosed,
+
+    #[error("Service is not available in {country}")]
+    RestrictedJurisdiction { country: String },
+
     #[error("Configuration error: {0}")]
     Config(#[from] config::ConfigError),
 
@@ -71,6 +194,15 @@ pub enum CryptoTradeError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Invalid user ID")]
+    InvalidUserId,
+
+    #[error("Invalid user ID in token")]
+    InvalidToken,
+
     #[error("Internal server error")]
     Internal,
 }
@@ -88,38 +220,594 @@ impl CryptoTradeError {
             Self::UserNotFound => "USER_NOT_FOUND",
             Self::OrderNotFound => "ORDER_NOT_FOUND",
             Self::OrderNotCancellable => "ORDER_NOT_CANCELLABLE",
+            Self::Conflict { .. } => "CONFLICT",
             Self::TradingPairNotFound => "TRADING_PAIR_NOT_FOUND",
-            Self::InsufficientBalance => "INSUFFICIENT_BALANCE",
+            Self::InsufficientBalance { .. } => "INSUFFICIENT_BALANCE",
             Self::InvalidOrderType => "INVALID_ORDER_TYPE",
             Self::InvalidPrice => "INVALID_PRICE",
             Self::InvalidQuantity => "INVALID_QUANTITY",
+            Self::PriceOutOfBounds { .. } => "PRICE_OUT_OF_BOUNDS",
+            Self::DuplicateOrder { .. } => "DUPLICATE_ORDER",
+            Self::RiskLimitExceeded { .. } => "RISK_LIMIT_EXCEEDED",
+            Self::SelfTradePrevented => "SELF_TRADE_PREVENTED",
+            Self::PermissionDenied { .. } => "PERMISSION_DENIED",
+            Self::TradingPairNotPermitted { .. } => "TRADING_PAIR_NOT_PERMITTED",
+            Self::IpNotAllowed { .. } => "IP_NOT_ALLOWED",
+            Self::InvalidSignature => "INVALID_SIGNATURE",
+            Self::StaleRequest { .. } => "STALE_REQUEST",
+            Self::NonceReused => "NONCE_REUSED",
+            Self::PreTradeChecksFailed { .. } => "PRE_TRADE_CHECKS_FAILED",
+            Self::MaintenanceMode { .. } => "MAINTENANCE_MODE",
+            Self::DependencyUnavailable { .. } => "SERVICE_UNAVAILABLE",
+            Self::RequestTimeout { .. } => "REQUEST_TIMEOUT",
+            Self::PayloadTooLarge { .. } => "PAYLOAD_TOO_LARGE",
+            Self::ServerBusy { .. } => "SERVER_BUSY",
+            Self::RateLimited { .. } => "RATE_LIMITED",
+            Self::FeatureDisabled { .. } => "FEATURE_DISABLED",
+            Self::PaymentProvider { .. } => "PAYMENT_PROVIDER_ERROR",
+            Self::TravelRuleProvider { .. } => "TRAVEL_RULE_PROVIDER_ERROR",
+            Self::CaptchaProvider { .. } => "CAPTCHA_PROVIDER_ERROR",
+            Self::Signer { .. } => "SIGNER_ERROR",
             Self::TradingPairNotActive => "TRADING_PAIR_NOT_ACTIVE",
+            Self::OutsideTradingSession => "OUTSIDE_TRADING_SESSION",
             Self::KycRequired => "KYC_REQUIRED",
             Self::TwoFactorRequired => "TWO_FACTOR_REQUIRED",
+            Self::CaptchaRequired => "CAPTCHA_REQUIRED",
+            Self::DeviceConfirmationRequired { .. } => "DEVICE_CONFIRMATION_REQUIRED",
+            Self::SuspiciousLogin { .. } => "SUSPICIOUS_LOGIN",
+            Self::AccountRestricted => "ACCOUNT_RESTRICTED",
+            Self::AccountClosed => "ACCOUNT_CLOSED",
+            Self::RestrictedJurisdiction { .. } => "RESTRICTED_JURISDICTION",
             Self::Config(_) => "CONFIGURATION_ERROR",
             Self::Jwt(_) => "JWT_ERROR",
             Self::BCrypt(_) => "BCRYPT_ERROR",
             Self::Totp(_) => "TOTP_ERROR",
             Self::Io(_) => "IO_ERROR",
+            Self::Serialization(_) => "SERIALIZATION_ERROR",
+            Self::InvalidUserId => "INVALID_USER_ID",
+            Self::InvalidToken => "INVALID_TOKEN",
             Self::Internal => "INTERNAL_ERROR",
         }
     }
 
+    /// The message in `locale`. English keeps the detailed message; other
+    /// locales get the code's translation, or English when there is none.
+    pub fn localized_message(&self, locale: Locale) -> String {
+        i18n::error_message(locale, self.error_code())
+            .map(str::to_string)
+            .unwrap_or_else(|| self.to_string())
+    }
+
     pub fn status_code(&self) -> u16 {
         match self {
             Self::Database(_) | Self::Migration(_) | Self::Redis(_) | Self::Internal => 500,
             Self::Authentication { .. } => 401,
+            Self::InvalidSignature | Self::StaleRequest { .. } | Self::NonceReused => 401,
             Self::Authorization { .. } => 403,
             Self::Validation { .. } => 400,
             Self::NotFound { .. } => 404,
             Self::UserNotFound | Self::OrderNotFound | Self::TradingPairNotFound => 404,
             Self::OrderNotCancellable => 400,
-            Self::InsufficientBalance | Self::InvalidOrderType | Self::InvalidPrice | Self::InvalidQuantity => 400,
-            Self::TradingPairNotActive | Self::KycRequired | Self::TwoFactorRequired => 403,
+            Self::InsufficientBalance { .. } | Self::InvalidOrderType | Self::InvalidPrice | Self::InvalidQuantity => 400,
+            Self::PriceOutOfBounds { .. } => 400,
+            Self::Conflict { .. } | Self::DuplicateOrder { .. } => 409,
+            Self::RiskLimitExceeded { .. } | Self::SelfTradePrevented | Self::PreTradeChecksFailed { .. } => 400,
+            Self::MaintenanceMode { .. } | Self::DependencyUnavailable { .. } | Self::ServerBusy { .. } => 503,
+            Self::RequestTimeout { .. } => 408,
+            Self::PayloadTooLarge { .. } => 413,
+            Self::RateLimited { .. } => 429,
+            Self::FeatureDisabled { .. } => 403,
+            Self::PaymentProvider { .. }
+            | Self::TravelRuleProvider { .. }
+            | Self::CaptchaProvider { .. }
+            | Self::Signer { .. } => 502,
+            Self::TradingPairNotActive | Self::OutsideTradingSession | Self::KycRequired | Self::TwoFactorRequired => 403,
+            Self::AccountRestricted
+            | Self::AccountClosed
+            | Self::DeviceConfirmationRequired { .. }
+            | Self::SuspiciousLogin { .. } => 403,
+            Self::CaptchaRequired => 403,
+            Self::RestrictedJurisdiction { .. } => 451,
+            Self::PermissionDenied { .. } | Self::TradingPairNotPermitted { .. } | Self::IpNotAllowed { .. } => 403,
             Self::Config(_) => 500,
-            Self::Jwt(_) | Self::BCrypt(_) | Self::Totp(_) | Self::Io(_) => 500,
+            Self::Jwt(_) | Self::BCrypt(_) | Self::Totp(_) | Self::Io(_) | Self::Serialization(_) => 500,
+            Self::InvalidUserId | Self::InvalidToken => 400,
+        }
+    }
+
+    /// Structured context for clients to act on, where the error has any.
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            Self::InsufficientBalance {
+                currency,
+                required,
+                available,
+            } => Some(serde_json::json!({
+                "currency": currency,
+                "required": required,
+                "available": available,
+            })),
+            Self::PriceOutOfBounds { price, min, max } => Some(serde_json::json!({
+                "price": price,
+                "min": min,
+                "max": max,
+            })),
+            Self::RiskLimitExceeded { limit, max, current } => Some(serde_json::json!({
+                "limit": limit,
+                "max": max,
+                "current": current,
+            })),
+            Self::PermissionDenied { permission } => Some(serde_json::json!({ "permission": permission })),
+            Self::TradingPairNotPermitted { trading_pair_id } => Some(serde_json::json!({
+                "trading_pair_id": trading_pair_id,
+            })),
+            Self::IpNotAllowed { ip } => Some(serde_json::json!({ "ip": ip })),
+            Self::RestrictedJurisdiction { country } => Some(serde_json::json!({ "country": country })),
+            Self::DeviceConfirmationRequired { challenge_id } | Self::SuspiciousLogin { challenge_id } => {
+                Some(serde_json::json!({ "challenge_id": challenge_id }))
+            }
+            Self::FeatureDisabled { feature } => Some(serde_json::json!({ "feature": feature })),
+            Self::DependencyUnavailable { dependency } => Some(serde_json::json!({ "dependency": dependency })),
+            Self::RequestTimeout { timeout_seconds } => Some(serde_json::json!({ "timeout_seconds": timeout_seconds })),
+            Self::PayloadTooLarge { limit_bytes } => Some(serde_json::json!({ "limit_bytes": limit_bytes })),
+            Self::ServerBusy { operation } => Some(serde_json::json!({ "operation": operation })),
+            Self::RateLimited {
+                limit,
+                window,
+                retry_after_seconds,
+            } => Some(serde_json::json!({
+                "limit": limit,
+                "window": window,
+                "retry_after_seconds": retry_after_seconds,
+            })),
+            Self::PreTradeChecksFailed { failures } => Some(serde_json::json!({
+                "failures": failures
+                    .iter()
+                    .map(|failure| serde_json::json!({
+                        "code": failure.error_code(),
+                        "message": failure.localized_message(i18n::current_locale()),
+                        "details": failure.details(),
+                    }))
+                    .collect::<Vec<_>>(),
+            })),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// One of the values of the `ErrorCode` schema
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
+    /// Also returned in the `x-request-id` header
+    pub request_id: Option<String>,
+}
+
+impl IntoResponse for CryptoTradeError {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let request_id = REQUEST_ID.try_with(|id| id.clone()).ok();
+
+        // Internal failures are logged in full; clients only see the code
+        if status.is_server_error() {
+            tracing::error!(request_id = request_id.as_deref(), "Request failed: {}", self);
+        }
+
+        let retry_after = match self {
+            Self::RateLimited { retry_after_seconds, .. } => Some(retry_after_seconds),
+            _ => None,
+        };
+        let body = ErrorResponse {
+            error: self.localized_message(i18n::current_locale()),
+            code: self.error_code().to_string(),
+            details: self.details(),
+            request_id,
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        if let Some(seconds) = retry_after {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
         }
+        response
     }
 }
 
 pub type Result<T> = std::result::Result<T, CryptoTradeError>;
+
+/// A documented error code and the HTTP status it is returned with.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct ErrorCodeInfo {
+    pub code: &'static str,
+    pub status: u16,
+    pub description: &'static str,
+}
+
+/// Every code `error_code()` can return, for API documentation.
+pub const ERROR_CATALOG: &[ErrorCodeInfo] = &[
+    ErrorCodeInfo {
+        code: "DATABASE_ERROR",
+        status: 500,
+        description: "A database operation failed",
+    },
+    ErrorCodeInfo {
+        code: "MIGRATION_ERROR",
+        status: 500,
+        description: "Database migrations could not be applied",
+    },
+    ErrorCodeInfo {
+        code: "REDIS_ERROR",
+        status: 500,
+        description: "A Redis operation failed",
+    },
+    ErrorCodeInfo {
+        code: "AUTHENTICATION_ERROR",
+        status: 401,
+        description: "Missing, invalid or expired credentials",
+    },
+    ErrorCodeInfo {
+        code: "AUTHORIZATION_ERROR",
+        status: 403,
+        description: "The caller may not perform this action",
+    },
+    ErrorCodeInfo {
+        code: "VALIDATION_ERROR",
+        status: 400,
+        description: "The request body or parameters failed validation",
+    },
+    ErrorCodeInfo {
+        code: "NOT_FOUND",
+        status: 404,
+        description: "The requested resource does not exist",
+    },
+    ErrorCodeInfo {
+        code: "USER_NOT_FOUND",
+        status: 404,
+        description: "The user does not exist",
+    },
+    ErrorCodeInfo {
+        code: "ORDER_NOT_FOUND",
+        status: 404,
+        description: "The order does not exist or belongs to another user",
+    },
+    ErrorCodeInfo {
+        code: "ORDER_NOT_CANCELLABLE",
+        status: 400,
+        description: "The order is already filled, cancelled or rejected",
+    },
+    ErrorCodeInfo {
+        code: "CONFLICT",
+        status: 409,
+        description: "The resource kept changing under concurrent updates; read it again and retry",
+    },
+    ErrorCodeInfo {
+        code: "TRADING_PAIR_NOT_FOUND",
+        status: 404,
+        description: "The trading pair does not exist",
+    },
+    ErrorCodeInfo {
+        code: "INSUFFICIENT_BALANCE",
+        status: 400,
+        description: "The account cannot cover the amount to lock",
+    },
+    ErrorCodeInfo {
+        code: "INVALID_ORDER_TYPE",
+        status: 400,
+        description: "The order type is not supported here",
+    },
+    ErrorCodeInfo {
+        code: "INVALID_PRICE",
+        status: 400,
+        description: "The price is missing or not acceptable",
+    },
+    ErrorCodeInfo {
+        code: "INVALID_QUANTITY",
+        status: 400,
+        description: "The quantity is outside the pair's size limits",
+    },
+    ErrorCodeInfo {
+        code: "PRICE_OUT_OF_BOUNDS",
+        status: 400,
+        description: "The limit price is too far from the pair's last traded price",
+    },
+    ErrorCodeInfo {
+        code: "DUPLICATE_ORDER",
+        status: 409,
+        description: "An identical order was just placed; set allow_duplicate to place it anyway",
+    },
+    ErrorCodeInfo {
+        code: "RISK_LIMIT_EXCEEDED",
+        status: 400,
+        description: "The order would take the user past an open order count or notional limit",
+    },
+    ErrorCodeInfo {
+        code: "SELF_TRADE_PREVENTED",
+        status: 400,
+        description: "The order would match against one of the user's own resting orders",
+    },
+    ErrorCodeInfo {
+        code: "PERMISSION_DENIED",
+        status: 403,
+        description: "The API key or account lacks the permission the request needs",
+    },
+    ErrorCodeInfo {
+        code: "TRADING_PAIR_NOT_PERMITTED",
+        status: 403,
+        description: "The API key or account is restricted to other trading pairs",
+    },
+    ErrorCodeInfo {
+        code: "INVALID_SIGNATURE",
+        status: 401,
+        description: "The API key request signature is missing or does not match",
+    },
+    ErrorCodeInfo {
+        code: "STALE_REQUEST",
+        status: 401,
+        description: "The signed request's timestamp is outside the receive window",
+    },
+    ErrorCodeInfo {
+        code: "NONCE_REUSED",
+        status: 401,
+        description: "The signed request's nonce was already used; each request needs a fresh one",
+    },
+    ErrorCodeInfo {
+        code: "IP_NOT_ALLOWED",
+        status: 403,
+        description: "The API key is bound to an IP allowlist that excludes the caller's address",
+    },
+    ErrorCodeInfo {
+        code: "PRE_TRADE_CHECKS_FAILED",
+        status: 400,
+        description: "The order failed several pre-trade checks; details.failures lists each one",
+    },
+    ErrorCodeInfo {
+        code: "MAINTENANCE_MODE",
+        status: 503,
+        description: "The exchange is in maintenance; only reads are accepted until it ends",
+    },
+    ErrorCodeInfo {
+        code: "SERVICE_UNAVAILABLE",
+        status: 503,
+        description: "A service the request needs is failing or not responding; details.dependency names it. Retry shortly",
+    },
+    ErrorCodeInfo {
+        code: "REQUEST_TIMEOUT",
+        status: 408,
+        description: "The request took longer than the route allows; details.timeout_seconds is the limit",
+    },
+    ErrorCodeInfo {
+        code: "PAYLOAD_TOO_LARGE",
+        status: 413,
+        description: "The request body exceeds the route's size limit, given in details.limit_bytes",
+    },
+    ErrorCodeInfo {
+        code: "SERVER_BUSY",
+        status: 503,
+        description: "Too many requests of this kind are already running, e.g. exports; details.operation names it. Retry shortly",
+    },
+    ErrorCodeInfo {
+        code: "RATE_LIMITED",
+        status: 429,
+        description: "Too many requests; retry after the number of seconds in the Retry-After header",
+    },
+    ErrorCodeInfo {
+        code: "FEATURE_DISABLED",
+        status: 403,
+        description: "The feature is switched off or not yet rolled out to the caller",
+    },
+    ErrorCodeInfo {
+        code: "PAYMENT_PROVIDER_ERROR",
+        status: 502,
+        description: "The payment provider refused the request or could not be reached",
+    },
+    ErrorCodeInfo {
+        code: "TRAVEL_RULE_PROVIDER_ERROR",
+        status: 502,
+        description: "Travel rule data was saved but could not be passed on; submitting it again retries",
+    },
+    ErrorCodeInfo {
+        code: "CAPTCHA_PROVIDER_ERROR",
+        status: 502,
+        description: "The CAPTCHA response could not be verified with the provider; try again",
+    },
+    ErrorCodeInfo {
+        code: "SIGNER_ERROR",
+        status: 502,
+        description: "The hot wallet signer could not sign the transaction",
+    },
+    ErrorCodeInfo {
+        code: "TRADING_PAIR_NOT_ACTIVE",
+        status: 403,
+        description: "The trading pair is not open for trading",
+    },
+    ErrorCodeInfo {
+        code: "OUTSIDE_TRADING_SESSION",
+        status: 403,
+        description: "The trading pair only takes orders during its trading sessions",
+    },
+    ErrorCodeInfo {
+        code: "KYC_REQUIRED",
+        status: 403,
+        description: "KYC verification must be completed first",
+    },
+    ErrorCodeInfo {
+        code: "TWO_FACTOR_REQUIRED",
+        status: 403,
+        description: "A two-factor code is required",
+    },
+    ErrorCodeInfo {
+        code: "CAPTCHA_REQUIRED",
+        status: 403,
+        description: "Registration, or a login after repeated failures, needs a solved CAPTCHA in captcha_token",
+    },
+    ErrorCodeInfo {
+        code: "DEVICE_CONFIRMATION_REQUIRED",
+        status: 403,
+        description: "The login comes from an unrecognized device; details.challenge_id names the confirmation emailed to the user",
+    },
+    ErrorCodeInfo {
+        code: "SUSPICIOUS_LOGIN",
+        status: 403,
+        description: "The login comes from too far away to have travelled since the last one; a 2FA code or the confirmation named by details.challenge_id lets it through",
+    },
+    ErrorCodeInfo {
+        code: "ACCOUNT_RESTRICTED",
+        status: 403,
+        description: "The account is restricted; it may only cancel orders and withdraw",
+    },
+    ErrorCodeInfo {
+        code: "ACCOUNT_CLOSED",
+        status: 403,
+        description: "The account is closed; its history can be read but it cannot transact",
+    },
+    ErrorCodeInfo {
+        code: "RESTRICTED_JURISDICTION",
+        status: 451,
+        description: "The request comes from, or the account resides in, a country the exchange does not serve; details.country names it",
+    },
+    ErrorCodeInfo {
+        code: "CONFIGURATION_ERROR",
+        status: 500,
+        description: "The server is misconfigured",
+    },
+    ErrorCodeInfo {
+        code: "JWT_ERROR",
+        status: 500,
+        description: "A token could not be issued or decoded",
+    },
+    ErrorCodeInfo {
+        code: "BCRYPT_ERROR",
+        status: 500,
+        description: "Password hashing failed",
+    },
+    ErrorCodeInfo {
+        code: "TOTP_ERROR",
+        status: 500,
+        description: "Two-factor secret handling failed",
+    },
+    ErrorCodeInfo {
+        code: "IO_ERROR",
+        status: 500,
+        description: "An I/O operation failed",
+    },
+    ErrorCodeInfo {
+        code: "SERIALIZATION_ERROR",
+        status: 500,
+        description: "Data could not be encoded or decoded",
+    },
+    ErrorCodeInfo {
+        code: "INVALID_USER_ID",
+        status: 400,
+        description: "The token's subject is not a valid user id",
+    },
+    ErrorCodeInfo {
+        code: "INVALID_TOKEN",
+        status: 400,
+        description: "The refresh token's subject is not a valid user id",
+    },
+    ErrorCodeInfo {
+        code: "INTERNAL_ERROR",
+        status: 500,
+        description: "An unexpected server error",
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_matches_error_codes() {
+        let errors = [
+            CryptoTradeError::Validation { message: String::new() },
+            CryptoTradeError::OrderNotFound,
+            CryptoTradeError::Conflict { message: String::new() },
+            CryptoTradeError::InsufficientBalance {
+                currency: "USDT".to_string(),
+                required: Decimal::ONE,
+                available: Decimal::ZERO,
+            },
+            CryptoTradeError::PriceOutOfBounds {
+                price: Decimal::ONE,
+                min: Decimal::TWO,
+                max: Decimal::TEN,
+            },
+            CryptoTradeError::DuplicateOrder { window_seconds: 5 },
+            CryptoTradeError::RiskLimitExceeded {
+                limit: "open_orders_per_pair".to_string(),
+                max: Decimal::TEN,
+                current: Decimal::TEN,
+            },
+            CryptoTradeError::SelfTradePrevented,
+            CryptoTradeError::PermissionDenied {
+                permission: TradingPermission::Withdraw,
+            },
+            CryptoTradeError::TradingPairNotPermitted {
+                trading_pair_id: uuid::Uuid::nil(),
+            },
+            CryptoTradeError::InvalidSignature,
+            CryptoTradeError::StaleRequest { recv_window_ms: 5000 },
+            CryptoTradeError::NonceReused,
+            CryptoTradeError::IpNotAllowed {
+                ip: "192.0.2.1".to_string(),
+            },
+            CryptoTradeError::PreTradeChecksFailed {
+                failures: vec![CryptoTradeError::SelfTradePrevented, CryptoTradeError::KycRequired],
+            },
+            CryptoTradeError::MaintenanceMode {
+                message: "Back soon".to_string(),
+            },
+            CryptoTradeError::DependencyUnavailable { dependency: "redis" },
+            CryptoTradeError::RequestTimeout { timeout_seconds: 30 },
+            CryptoTradeError::PayloadTooLarge { limit_bytes: 1024 },
+            CryptoTradeError::ServerBusy { operation: "export" },
+            CryptoTradeError::RateLimited {
+                limit: 60,
+                window: "minute",
+                retry_after_seconds: 30,
+            },
+            CryptoTradeError::FeatureDisabled {
+                feature: "margin".to_string(),
+            },
+            CryptoTradeError::PaymentProvider {
+                message: "card declined".to_string(),
+            },
+            CryptoTradeError::TravelRuleProvider {
+                message: "gateway timeout".to_string(),
+            },
+            CryptoTradeError::CaptchaProvider {
+                message: "gateway timeout".to_string(),
+            },
+            CryptoTradeError::Signer {
+                message: "access denied".to_string(),
+            },
+            CryptoTradeError::CaptchaRequired,
+            CryptoTradeError::InvalidUserId,
+            CryptoTradeError::TradingPairNotActive,
+            CryptoTradeError::OutsideTradingSession,
+            CryptoTradeError::AccountRestricted,
+            CryptoTradeError::AccountClosed,
+            CryptoTradeError::DeviceConfirmationRequired {
+                challenge_id: uuid::Uuid::nil(),
+            },
+            CryptoTradeError::SuspiciousLogin {
+                challenge_id: uuid::Uuid::nil(),
+            },
+            CryptoTradeError::RestrictedJurisdiction {
+                country: "KP".to_string(),
+            },
+            CryptoTradeError::Internal,
+        ];
+
+        for error in errors {
+            let entry = ERROR_CATALOG
+                .iter()
+                .find(|entry| entry.code == error.error_code())
+                .expect("error code missing from catalog");
+            assert_eq!(entry.status, error.status_code());
+        }
+    }
+}
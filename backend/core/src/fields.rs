@@ -0,0 +1,89 @@
+//! Partial responses: JSON documents pruned to the fields a client names in
+//! a `fields=` query parameter, e.g. `fields=total_value_usd,accounts.currency`.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The fields to keep, as dotted paths. Paths apply to every element of an
+/// array, so `accounts.currency` keeps the currency of each account. Names
+/// that match nothing are ignored.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FieldSelection {
+    /// `None` keeps the whole value
+    fields: BTreeMap<String, Option<FieldSelection>>,
+}
+
+impl FieldSelection {
+    /// Parses a comma separated list of paths; `None` if it names none.
+    pub fn parse(fields: &str) -> Option<Self> {
+        let mut selection = Self::default();
+        for path in fields.split(',').map(str::trim).filter(|path| !path.is_empty()) {
+            let segments: Vec<&str> = path.split('.').map(str::trim).collect();
+            if segments.iter().all(|segment| !segment.is_empty()) {
+                selection.insert(&segments);
+            }
+        }
+        (!selection.fields.is_empty()).then_some(selection)
+    }
+
+    fn insert(&mut self, path: &[&str]) {
+        let Some((first, rest)) = path.split_first() else {
+            return;
+        };
+        if rest.is_empty() {
+            self.fields.insert(first.to_string(), None);
+            return;
+        }
+        // A field already kept whole stays whole
+        if let Some(child) = self.fields.entry(first.to_string()).or_insert_with(|| Some(Self::default())) {
+            child.insert(rest);
+        }
+    }
+
+    pub fn apply(&self, value: Value) -> Value {
+        match value {
+            Value::Object(object) => Value::Object(
+                object
+                    .into_iter()
+                    .filter_map(|(key, value)| match self.fields.get(&key)? {
+                        None => Some((key, value)),
+                        Some(child) => Some((key, child.apply(value))),
+                    })
+                    .collect(),
+            ),
+            Value::Array(values) => Value::Array(values.into_iter().map(|value| self.apply(value)).collect()),
+            scalar => scalar,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_selection_keeps_named_paths_through_arrays() {
+        let portfolio = json!({
+            "user_id": "u1",
+            "total_value_usd": "100",
+            "accounts": [
+                {"currency": "BTC", "balance": "1", "locked": "0"},
+                {"currency": "USD", "balance": "5", "locked": "1"}
+            ]
+        });
+        let selection = FieldSelection::parse("total_value_usd, accounts.currency,missing").unwrap();
+
+        assert_eq!(
+            selection.apply(portfolio),
+            json!({"total_value_usd": "100", "accounts": [{"currency": "BTC"}, {"currency": "USD"}]})
+        );
+    }
+
+    #[test]
+    fn test_a_whole_field_wins_over_its_subfields() {
+        assert_eq!(FieldSelection::parse("a.b,a"), FieldSelection::parse("a"));
+        assert_eq!(FieldSelection::parse("a,a.b"), FieldSelection::parse("a"));
+        assert_eq!(FieldSelection::parse(" , a..b"), None);
+    }
+}
@@ -0,0 +1,297 @@
+//! Localized user-facing texts. Error codes are the same in every locale;
+//! only the human readable message changes. English texts live with the
+//! code that produces them, so only the other locales are tabled here.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+tokio::task_local! {
+    /// Locale of the HTTP request being handled, set by the API's locale
+    /// middleware from the user's profile or `Accept-Language`.
+    pub static LOCALE: Locale;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+    Fr,
+    De,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 4] = [Locale::En, Locale::Es, Locale::Fr, Locale::De];
+
+    /// The locale's language tag, as stored on profiles.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+            Locale::Fr => "fr",
+            Locale::De => "de",
+        }
+    }
+
+    /// Reads a language tag by its primary subtag, so `es-MX` is Spanish.
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let language = tag.trim().split(['-', '_']).next()?;
+        Self::ALL.into_iter().find(|locale| locale.tag().eq_ignore_ascii_case(language))
+    }
+
+    /// The supported locale an `Accept-Language` header prefers most, by
+    /// quality and then by order. Languages with `q=0` are refused, and a
+    /// wildcard matches nothing in particular.
+    pub fn negotiate(accept_language: &str) -> Option<Locale> {
+        let mut ranges: Vec<(Locale, f32)> = accept_language
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let locale = Self::from_tag(parts.next()?)?;
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+                (quality > 0.0).then_some((locale, quality))
+            })
+            .collect();
+        // Stable, so equal qualities keep the client's order
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges.first().map(|(locale, _)| *locale)
+    }
+}
+
+/// The locale of the request being handled, English outside of one.
+pub fn current_locale() -> Locale {
+    LOCALE.try_with(|locale| *locale).unwrap_or_default()
+}
+
+/// The message for an error code in `locale`, or `None` for English and
+/// codes without a translation, which keep their English message.
+pub fn error_message(locale: Locale, code: &str) -> Option<&'static str> {
+    let table = match locale {
+        Locale::En => return None,
+        Locale::Es => ES_ERRORS,
+        Locale::Fr => FR_ERRORS,
+        Locale::De => DE_ERRORS,
+    };
+    table.iter().find(|(key, _)| *key == code).map(|(_, message)| *message)
+}
+
+const ES_ERRORS: &[(&str, &str)] = &[
+    ("DATABASE_ERROR", "Se produjo un error interno. Inténtalo de nuevo más tarde."),
+    ("MIGRATION_ERROR", "Se produjo un error interno. Inténtalo de nuevo más tarde."),
+    ("REDIS_ERROR", "Se produjo un error interno. Inténtalo de nuevo más tarde."),
+    ("AUTHENTICATION_ERROR", "Las credenciales faltan, no son válidas o han caducado."),
+    ("AUTHORIZATION_ERROR", "No tienes permiso para realizar esta acción."),
+    ("VALIDATION_ERROR", "La solicitud contiene datos no válidos."),
+    ("NOT_FOUND", "El recurso solicitado no existe."),
+    ("USER_NOT_FOUND", "El usuario no existe."),
+    ("ORDER_NOT_FOUND", "La orden no existe."),
+    ("ORDER_NOT_CANCELLABLE", "La orden ya se ejecutó, se canceló o fue rechazada."),
+    ("CONFLICT", "El recurso cambió mientras se actualizaba. Vuelve a cargarlo e inténtalo de nuevo."),
+    ("TRADING_PAIR_NOT_FOUND", "El par de negociación no existe."),
+    ("INSUFFICIENT_BALANCE", "Saldo insuficiente."),
+    ("INVALID_ORDER_TYPE", "Este tipo de orden no está disponible aquí."),
+    ("INVALID_PRICE", "El precio falta o no es válido."),
+    ("INVALID_QUANTITY", "La cantidad está fuera de los límites del par."),
+    ("PRICE_OUT_OF_BOUNDS", "El precio límite se aleja demasiado del último precio negociado."),
+    ("DUPLICATE_ORDER", "Acabas de enviar una orden idéntica."),
+    ("RISK_LIMIT_EXCEEDED", "La orden supera tu límite de órdenes abiertas o de importe."),
+    ("SELF_TRADE_PREVENTED", "La orden se cruzaría con una de tus propias órdenes."),
+    ("PERMISSION_DENIED", "La clave de API o la cuenta no tiene el permiso necesario."),
+    ("TRADING_PAIR_NOT_PERMITTED", "La clave de API o la cuenta no puede operar este par."),
+    ("INVALID_SIGNATURE", "La firma de la solicitud falta o no es correcta."),
+    ("STALE_REQUEST", "La marca de tiempo de la solicitud firmada está fuera del margen permitido."),
+    ("NONCE_REUSED", "El nonce de la solicitud firmada ya se utilizó."),
+    ("IP_NOT_ALLOWED", "La clave de API no admite solicitudes desde esta dirección IP."),
+    ("PRE_TRADE_CHECKS_FAILED", "La orden no superó varias comprobaciones previas."),
+    ("MAINTENANCE_MODE", "El exchange está en mantenimiento; solo se permiten consultas."),
+    ("SERVICE_UNAVAILABLE", "Un servicio necesario no está disponible. Inténtalo de nuevo en unos instantes."),
+    ("REQUEST_TIMEOUT", "La solicitud tardó demasiado y se canceló."),
+    ("PAYLOAD_TOO_LARGE", "El cuerpo de la solicitud supera el tamaño permitido."),
+    ("SERVER_BUSY", "Hay demasiadas solicitudes de este tipo en curso. Inténtalo de nuevo en unos instantes."),
+    ("RATE_LIMITED", "Demasiadas solicitudes. Espera antes de volver a intentarlo."),
+    ("FEATURE_DISABLED", "Esta función no está disponible."),
+    ("PAYMENT_PROVIDER_ERROR", "El proveedor de pagos no pudo procesar la solicitud."),
+    ("TRAVEL_RULE_PROVIDER_ERROR", "Los datos de la regla de viaje se guardaron pero no se pudieron enviar; vuelve a enviarlos."),
+    ("CAPTCHA_PROVIDER_ERROR", "No se pudo verificar el CAPTCHA; inténtalo de nuevo."),
+    ("SIGNER_ERROR", "No se pudo firmar la transacción; inténtalo más tarde."),
+    ("TRADING_PAIR_NOT_ACTIVE", "El par no está abierto a la negociación."),
+    ("OUTSIDE_TRADING_SESSION", "El par solo acepta órdenes durante su horario de negociación."),
+    ("KYC_REQUIRED", "Primero debes completar la verificación de identidad."),
+    ("TWO_FACTOR_REQUIRED", "Se requiere un código de doble factor."),
+    ("CAPTCHA_REQUIRED", "Resuelve el CAPTCHA para continuar."),
+    ("DEVICE_CONFIRMATION_REQUIRED", "Confirma el inicio de sesión desde este dispositivo con el enlace que te enviamos por correo."),
+    ("SUSPICIOUS_LOGIN", "Inicio de sesión desde una ubicación inusual: confírmalo con el enlace que te enviamos por correo."),
+    ("ACCOUNT_RESTRICTED", "La cuenta está restringida: solo puede cancelar órdenes y retirar fondos."),
+    ("ACCOUNT_CLOSED", "La cuenta está cerrada."),
+    ("RESTRICTED_JURISDICTION", "El servicio no está disponible en tu país."),
+    ("CONFIGURATION_ERROR", "Se produjo un error interno. Inténtalo de nuevo más tarde."),
+    ("JWT_ERROR", "No se pudo procesar el token."),
+    ("BCRYPT_ERROR", "Se produjo un error interno. Inténtalo de nuevo más tarde."),
+    ("TOTP_ERROR", "Se produjo un error interno. Inténtalo de nuevo más tarde."),
+    ("IO_ERROR", "Se produjo un error interno. Inténtalo de nuevo más tarde."),
+    ("SERIALIZATION_ERROR", "No se pudieron leer los datos."),
+    ("INVALID_USER_ID", "El token no identifica a un usuario válido."),
+    ("INVALID_TOKEN", "El token de actualización no es válido."),
+    ("INTERNAL_ERROR", "Se produjo un error interno. Inténtalo de nuevo más tarde."),
+];
+
+const FR_ERRORS: &[(&str, &str)] = &[
+    ("DATABASE_ERROR", "Une erreur interne est survenue. Réessayez plus tard."),
+    ("MIGRATION_ERROR", "Une erreur interne est survenue. Réessayez plus tard."),
+    ("REDIS_ERROR", "Une erreur interne est survenue. Réessayez plus tard."),
+    ("AUTHENTICATION_ERROR", "Identifiants manquants, invalides ou expirés."),
+    ("AUTHORIZATION_ERROR", "Vous n'êtes pas autorisé à effectuer cette action."),
+    ("VALIDATION_ERROR", "La requête contient des données invalides."),
+    ("NOT_FOUND", "La ressource demandée n'existe pas."),
+    ("USER_NOT_FOUND", "L'utilisateur n'existe pas."),
+    ("ORDER_NOT_FOUND", "L'ordre n'existe pas."),
+    ("ORDER_NOT_CANCELLABLE", "L'ordre est déjà exécuté, annulé ou rejeté."),
+    ("CONFLICT", "La ressource a changé pendant la mise à jour. Rechargez-la et réessayez."),
+    ("TRADING_PAIR_NOT_FOUND", "La paire de trading n'existe pas."),
+    ("INSUFFICIENT_BALANCE", "Solde insuffisant."),
+    ("INVALID_ORDER_TYPE", "Ce type d'ordre n'est pas disponible ici."),
+    ("INVALID_PRICE", "Le prix est manquant ou invalide."),
+    ("INVALID_QUANTITY", "La quantité est en dehors des limites de la paire."),
+    ("PRICE_OUT_OF_BOUNDS", "Le prix limite est trop éloigné du dernier prix échangé."),
+    ("DUPLICATE_ORDER", "Vous venez de passer un ordre identique."),
+    ("RISK_LIMIT_EXCEEDED", "L'ordre dépasse votre limite d'ordres ouverts ou de montant."),
+    ("SELF_TRADE_PREVENTED", "L'ordre serait exécuté contre l'un de vos propres ordres."),
+    ("PERMISSION_DENIED", "La clé d'API ou le compte n'a pas l'autorisation requise."),
+    ("TRADING_PAIR_NOT_PERMITTED", "La clé d'API ou le compte ne peut pas négocier cette paire."),
+    ("INVALID_SIGNATURE", "La signature de la requête est manquante ou incorrecte."),
+    ("STALE_REQUEST", "L'horodatage de la requête signée est hors de la fenêtre autorisée."),
+    ("NONCE_REUSED", "Le nonce de la requête signée a déjà été utilisé."),
+    ("IP_NOT_ALLOWED", "La clé d'API n'accepte pas les requêtes depuis cette adresse IP."),
+    ("PRE_TRADE_CHECKS_FAILED", "L'ordre a échoué à plusieurs contrôles préalables."),
+    ("MAINTENANCE_MODE", "La plateforme est en maintenance ; seules les consultations sont possibles."),
+    ("SERVICE_UNAVAILABLE", "Un service nécessaire est indisponible. Réessayez dans quelques instants."),
+    ("REQUEST_TIMEOUT", "La requête a pris trop de temps et a été interrompue."),
+    ("PAYLOAD_TOO_LARGE", "Le corps de la requête dépasse la taille autorisée."),
+    ("SERVER_BUSY", "Trop de requêtes de ce type sont en cours. Réessayez dans quelques instants."),
+    ("RATE_LIMITED", "Trop de requêtes. Patientez avant de réessayer."),
+    ("FEATURE_DISABLED", "Cette fonctionnalité n'est pas disponible."),
+    ("PAYMENT_PROVIDER_ERROR", "Le prestataire de paiement n'a pas pu traiter la requête."),
+    ("TRAVEL_RULE_PROVIDER_ERROR", "Les données de la règle de voyage ont été enregistrées mais n'ont pas pu être transmises ; renvoyez-les."),
+    ("CAPTCHA_PROVIDER_ERROR", "Le CAPTCHA n'a pas pu être vérifié ; réessayez."),
+    ("SIGNER_ERROR", "La transaction n'a pas pu être signée ; réessayez plus tard."),
+    ("TRADING_PAIR_NOT_ACTIVE", "La paire n'est pas ouverte à la négociation."),
+    ("OUTSIDE_TRADING_SESSION", "La paire n'accepte des ordres que pendant ses horaires de négociation."),
+    ("KYC_REQUIRED", "Vous devez d'abord vérifier votre identité."),
+    ("TWO_FACTOR_REQUIRED", "Un code d'authentification à deux facteurs est requis."),
+    ("CAPTCHA_REQUIRED", "Résolvez le CAPTCHA pour continuer."),
+    ("DEVICE_CONFIRMATION_REQUIRED", "Confirmez la connexion depuis cet appareil avec le lien envoyé par e-mail."),
+    ("SUSPICIOUS_LOGIN", "Connexion depuis un lieu inhabituel : confirmez-la avec le lien envoyé par e-mail."),
+    ("ACCOUNT_RESTRICTED", "Le compte est restreint : il peut seulement annuler des ordres et retirer des fonds."),
+    ("ACCOUNT_CLOSED", "Le compte est fermé."),
+    ("RESTRICTED_JURISDICTION", "Le service n'est pas disponible dans votre pays."),
+    ("CONFIGURATION_ERROR", "Une erreur interne est survenue. Réessayez plus tard."),
+    ("JWT_ERROR", "Le jeton n'a pas pu être traité."),
+    ("BCRYPT_ERROR", "Une erreur interne est survenue. Réessayez plus tard."),
+    ("TOTP_ERROR", "Une erreur interne est survenue. Réessayez plus tard."),
+    ("IO_ERROR", "Une erreur interne est survenue. Réessayez plus tard."),
+    ("SERIALIZATION_ERROR", "Les données n'ont pas pu être lues."),
+    ("INVALID_USER_ID", "Le jeton n'identifie pas un utilisateur valide."),
+    ("INVALID_TOKEN", "Le jeton de rafraîchissement est invalide."),
+    ("INTERNAL_ERROR", "Une erreur interne est survenue. Réessayez plus tard."),
+];
+
+const DE_ERRORS: &[(&str, &str)] = &[
+    ("DATABASE_ERROR", "Ein interner Fehler ist aufgetreten. Bitte später erneut versuchen."),
+    ("MIGRATION_ERROR", "Ein interner Fehler ist aufgetreten. Bitte später erneut versuchen."),
+    ("REDIS_ERROR", "Ein interner Fehler ist aufgetreten. Bitte später erneut versuchen."),
+    ("AUTHENTICATION_ERROR", "Anmeldedaten fehlen, sind ungültig oder abgelaufen."),
+    ("AUTHORIZATION_ERROR", "Sie sind für diese Aktion nicht berechtigt."),
+    ("VALIDATION_ERROR", "Die Anfrage enthält ungültige Daten."),
+    ("NOT_FOUND", "Die angeforderte Ressource existiert nicht."),
+    ("USER_NOT_FOUND", "Der Benutzer existiert nicht."),
+    ("ORDER_NOT_FOUND", "Die Order existiert nicht."),
+    ("ORDER_NOT_CANCELLABLE", "Die Order ist bereits ausgeführt, storniert oder abgelehnt."),
+    ("CONFLICT", "Die Ressource wurde während der Aktualisierung geändert. Bitte neu laden und erneut versuchen."),
+    ("TRADING_PAIR_NOT_FOUND", "Das Handelspaar existiert nicht."),
+    ("INSUFFICIENT_BALANCE", "Unzureichendes Guthaben."),
+    ("INVALID_ORDER_TYPE", "Dieser Ordertyp ist hier nicht verfügbar."),
+    ("INVALID_PRICE", "Der Preis fehlt oder ist ungültig."),
+    ("INVALID_QUANTITY", "Die Menge liegt außerhalb der Grenzen des Handelspaars."),
+    ("PRICE_OUT_OF_BOUNDS", "Der Limitpreis weicht zu weit vom letzten Handelspreis ab."),
+    ("DUPLICATE_ORDER", "Sie haben gerade eine identische Order aufgegeben."),
+    ("RISK_LIMIT_EXCEEDED", "Die Order überschreitet Ihr Limit für offene Orders oder Volumen."),
+    ("SELF_TRADE_PREVENTED", "Die Order würde gegen eine Ihrer eigenen Orders ausgeführt."),
+    ("PERMISSION_DENIED", "Dem API-Schlüssel oder Konto fehlt die nötige Berechtigung."),
+    ("TRADING_PAIR_NOT_PERMITTED", "Der API-Schlüssel oder das Konto darf dieses Handelspaar nicht handeln."),
+    ("INVALID_SIGNATURE", "Die Signatur der Anfrage fehlt oder stimmt nicht."),
+    ("STALE_REQUEST", "Der Zeitstempel der signierten Anfrage liegt außerhalb des erlaubten Zeitfensters."),
+    ("NONCE_REUSED", "Die Nonce der signierten Anfrage wurde bereits verwendet."),
+    ("IP_NOT_ALLOWED", "Der API-Schlüssel akzeptiert keine Anfragen von dieser IP-Adresse."),
+    ("PRE_TRADE_CHECKS_FAILED", "Die Order hat mehrere Vorabprüfungen nicht bestanden."),
+    ("MAINTENANCE_MODE", "Die Börse wird gewartet; nur Abfragen sind möglich."),
+    ("SERVICE_UNAVAILABLE", "Ein benötigter Dienst ist nicht erreichbar. Bitte gleich erneut versuchen."),
+    ("REQUEST_TIMEOUT", "Die Anfrage hat zu lange gedauert und wurde abgebrochen."),
+    ("PAYLOAD_TOO_LARGE", "Der Inhalt der Anfrage überschreitet die zulässige Größe."),
+    ("SERVER_BUSY", "Zu viele Anfragen dieser Art laufen bereits. Bitte gleich erneut versuchen."),
+    ("RATE_LIMITED", "Zu viele Anfragen. Bitte warten und erneut versuchen."),
+    ("FEATURE_DISABLED", "Diese Funktion ist nicht verfügbar."),
+    ("PAYMENT_PROVIDER_ERROR", "Der Zahlungsanbieter konnte die Anfrage nicht verarbeiten."),
+    ("TRAVEL_RULE_PROVIDER_ERROR", "Die Travel-Rule-Daten wurden gespeichert, konnten aber nicht übermittelt werden; bitte erneut senden."),
+    ("CAPTCHA_PROVIDER_ERROR", "Das CAPTCHA konnte nicht überprüft werden; bitte erneut versuchen."),
+    ("SIGNER_ERROR", "Die Transaktion konnte nicht signiert werden; bitte später erneut versuchen."),
+    ("TRADING_PAIR_NOT_ACTIVE", "Das Handelspaar ist nicht für den Handel geöffnet."),
+    ("OUTSIDE_TRADING_SESSION", "Das Handelspaar nimmt Orders nur während seiner Handelszeiten an."),
+    ("KYC_REQUIRED", "Bitte schließen Sie zuerst die Identitätsprüfung ab."),
+    ("TWO_FACTOR_REQUIRED", "Ein Zwei-Faktor-Code ist erforderlich."),
+    ("CAPTCHA_REQUIRED", "Bitte lösen Sie das CAPTCHA, um fortzufahren."),
+    ("DEVICE_CONFIRMATION_REQUIRED", "Bitte bestätigen Sie die Anmeldung von diesem Gerät über den per E-Mail gesendeten Link."),
+    ("SUSPICIOUS_LOGIN", "Anmeldung von einem ungewöhnlichen Ort: Bitte bestätigen Sie sie über den per E-Mail gesendeten Link."),
+    ("ACCOUNT_RESTRICTED", "Das Konto ist eingeschränkt: Es kann nur Orders stornieren und Guthaben abheben."),
+    ("ACCOUNT_CLOSED", "Das Konto ist geschlossen."),
+    ("RESTRICTED_JURISDICTION", "Der Dienst ist in Ihrem Land nicht verfügbar."),
+    ("CONFIGURATION_ERROR", "Ein interner Fehler ist aufgetreten. Bitte später erneut versuchen."),
+    ("JWT_ERROR", "Das Token konnte nicht verarbeitet werden."),
+    ("BCRYPT_ERROR", "Ein interner Fehler ist aufgetreten. Bitte später erneut versuchen."),
+    ("TOTP_ERROR", "Ein interner Fehler ist aufgetreten. Bitte später erneut versuchen."),
+    ("IO_ERROR", "Ein interner Fehler ist aufgetreten. Bitte später erneut versuchen."),
+    ("SERIALIZATION_ERROR", "Die Daten konnten nicht gelesen werden."),
+    ("INVALID_USER_ID", "Das Token verweist auf keinen gültigen Benutzer."),
+    ("INVALID_TOKEN", "Das Aktualisierungstoken ist ungültig."),
+    ("INTERNAL_ERROR", "Ein interner Fehler ist aufgetreten. Bitte später erneut versuchen."),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ERROR_CATALOG;
+
+    #[test]
+    fn test_negotiate_prefers_quality_then_order() {
+        assert_eq!(Locale::negotiate("fr-CH, fr;q=0.9, en;q=0.8, de;q=0.7"), Some(Locale::Fr));
+        assert_eq!(Locale::negotiate("ja, de;q=0.5, es;q=0.9"), Some(Locale::Es));
+        assert_eq!(Locale::negotiate("es;q=0, *"), None);
+        assert_eq!(Locale::negotiate("DE-at"), Some(Locale::De));
+        assert_eq!(Locale::from_tag("pt-BR"), None);
+    }
+
+    #[test]
+    fn test_localized_message_keeps_english_detail() {
+        let error = crate::error::CryptoTradeError::Validation {
+            message: "quantity must be positive".to_string(),
+        };
+        assert_eq!(error.localized_message(Locale::En), "Validation error: quantity must be positive");
+        assert_eq!(error.localized_message(Locale::Fr), "La requête contient des données invalides.");
+    }
+
+    #[test]
+    fn test_every_error_code_is_translated() {
+        for locale in Locale::ALL.into_iter().filter(|locale| *locale != Locale::En) {
+            for entry in ERROR_CATALOG {
+                assert!(
+                    error_message(locale, entry.code).is_some(),
+                    "{} has no {} translation",
+                    entry.code,
+                    locale.tag()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Rolling indicators over a candle series, for `/api/v1/indicators`.
+
+use crate::models::{Candlestick, IndicatorPoint};
+use rust_decimal::Decimal;
+
+/// Decimals indicator values are rounded to, as prices are stored
+const VALUE_SCALE: u32 = 8;
+
+/// One point per complete candle, each with the indicators over the
+/// `window` candles ending at it: VWAP at the typical price, simple and
+/// exponential moving averages of the close, and Wilder's average true
+/// range. Values stay `None` until `window` candles have been seen; the
+/// EMA and ATR are seeded with the simple average of their first window.
+pub fn indicator_points(candles: &[Candlestick], window: usize) -> Vec<IndicatorPoint> {
+    let window = window.max(1);
+    let size = Decimal::from(window);
+    let alpha = Decimal::TWO / (size + Decimal::ONE);
+
+    let mut points = Vec::with_capacity(candles.len());
+    let mut closes = Vec::new();
+    let mut true_ranges = Vec::new();
+    let mut weighted = Vec::new();
+    let mut previous_close: Option<Decimal> = None;
+    let mut ema: Option<Decimal> = None;
+    let mut atr: Option<Decimal> = None;
+
+    for candle in candles {
+        let (Some(timestamp), Some(high), Some(low), Some(close)) = (candle.timestamp, candle.high, candle.low, candle.close)
+        else {
+            continue;
+        };
+        let volume = candle.volume.unwrap_or_default();
+
+        let true_range = match previous_close {
+            Some(previous) => (high - low).max((high - previous).abs()).max((low - previous).abs()),
+            None => high - low,
+        };
+        previous_close = Some(close);
+        closes.push(close);
+        true_ranges.push(true_range);
+        weighted.push(((high + low + close) / Decimal::from(3) * volume, volume));
+
+        let full = closes.len() >= window;
+        let sma = full.then(|| closes[closes.len() - window..].iter().sum::<Decimal>() / size);
+        if full {
+            ema = Some(match ema {
+                Some(previous) => previous + alpha * (close - previous),
+                None => sma.unwrap_or(close),
+            });
+            atr = Some(match atr {
+                Some(previous) => (previous * (size - Decimal::ONE) + true_range) / size,
+                None => true_ranges[true_ranges.len() - window..].iter().sum::<Decimal>() / size,
+            });
+        }
+        let vwap = full
+            .then(|| {
+                let (value, volume) = weighted[weighted.len() - window..]
+                    .iter()
+                    .fold((Decimal::ZERO, Decimal::ZERO), |(value, total), (v, q)| (value + v, total + q));
+                (!volume.is_zero()).then(|| value / volume)
+            })
+            .flatten();
+
+        let round = |value: Option<Decimal>| value.map(|value| value.round_dp(VALUE_SCALE));
+        points.push(IndicatorPoint {
+            timestamp,
+            close,
+            vwap: round(vwap),
+            sma: round(sma),
+            ema: round(ema),
+            atr: round(atr),
+        });
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration};
+
+    fn candles(bars: &[(i64, i64, i64, i64)]) -> Vec<Candlestick> {
+        let start = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap().to_utc();
+        bars.iter()
+            .enumerate()
+            .map(|(i, &(high, low, close, volume))| Candlestick {
+                timestamp: Some(start + Duration::hours(i as i64)),
+                open: Some(Decimal::from(close)),
+                high: Some(Decimal::from(high)),
+                low: Some(Decimal::from(low)),
+                close: Some(Decimal::from(close)),
+                volume: Some(Decimal::from(volume)),
+                interval_minutes: 60,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_indicators_start_once_the_window_is_full() {
+        let points = indicator_points(&candles(&[(12, 9, 10, 1), (12, 11, 12, 3), (15, 12, 14, 0), (14, 10, 11, 2)]), 2);
+
+        assert_eq!(points.len(), 4);
+        assert!(points[0].sma.is_none() && points[0].ema.is_none() && points[0].atr.is_none() && points[0].vwap.is_none());
+        assert_eq!(points[1].sma, Some(Decimal::from(11)));
+        // Seeded with the SMA, then 11 + 2/3 * (14 - 11)
+        assert_eq!(points[1].ema, Some(Decimal::from(11)));
+        assert_eq!(points[2].ema, Some(Decimal::from(13)));
+        // True ranges 3, 2 (from the previous close of 10), 3 and 4, smoothed
+        assert_eq!(points[1].atr, Some(Decimal::new(25, 1)));
+        assert_eq!(points[2].atr, Some(Decimal::new(275, 2)));
+        assert_eq!(points[3].atr, Some(Decimal::new(3375, 3)));
+        // Typical prices 31/3 and 35/3 with volumes 1 and 3
+        assert_eq!(points[1].vwap, Some(Decimal::new(1133333333, 8)));
+        // Only the last candle of the window has volume
+        assert_eq!(points[3].vwap, Some(Decimal::new(1166666667, 8)));
+    }
+
+    #[test]
+    fn test_windows_without_volume_have_no_vwap() {
+        let points = indicator_points(&candles(&[(10, 10, 10, 0), (11, 11, 11, 0)]), 2);
+        assert_eq!(points[1].vwap, None);
+        assert_eq!(points[1].sma, Some(Decimal::new(105, 1)));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::error::CryptoTradeError;
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use utoipa::ToSchema;
+
+/// A candlestick interval. Buckets of up to a day start on multiples of the
+/// interval counted from the Unix epoch, so days start at midnight UTC;
+/// weeks start on Monday and months on the first, both at midnight UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub enum Interval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "3m")]
+    ThreeMinutes,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "30m")]
+    ThirtyMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "2h")]
+    TwoHours,
+    #[serde(rename = "4h")]
+    FourHours,
+    #[serde(rename = "6h")]
+    SixHours,
+    #[serde(rename = "12h")]
+    TwelveHours,
+    #[serde(rename = "1d")]
+    OneDay,
+    #[serde(rename = "1w")]
+    OneWeek,
+    #[serde(rename = "1M")]
+    OneMonth,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 13] = [
+        Interval::OneMinute,
+        Interval::ThreeMinutes,
+        Interval::FiveMinutes,
+        Interval::FifteenMinutes,
+        Interval::ThirtyMinutes,
+        Interval::OneHour,
+        Interval::TwoHours,
+        Interval::FourHours,
+        Interval::SixHours,
+        Interval::TwelveHours,
+        Interval::OneDay,
+        Interval::OneWeek,
+        Interval::OneMonth,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::OneMinute => "1m",
+            Interval::ThreeMinutes => "3m",
+            Interval::FiveMinutes => "5m",
+            Interval::FifteenMinutes => "15m",
+            Interval::ThirtyMinutes => "30m",
+            Interval::OneHour => "1h",
+            Interval::TwoHours => "2h",
+            Interval::FourHours => "4h",
+            Interval::SixHours => "6h",
+            Interval::TwelveHours => "12h",
+            Interval::OneDay => "1d",
+            Interval::OneWeek => "1w",
+            Interval::OneMonth => "1M",
+        }
+    }
+
+    /// Length in minutes, as stored with imported candles. Months count as
+    /// 30 days here; their buckets follow the calendar.
+    pub fn minutes(&self) -> i32 {
+        match self {
+            Interval::OneMinute => 1,
+            Interval::ThreeMinutes => 3,
+            Interval::FiveMinutes => 5,
+            Interval::FifteenMinutes => 15,
+            Interval::ThirtyMinutes => 30,
+            Interval::OneHour => 60,
+            Interval::TwoHours => 120,
+            Interval::FourHours => 240,
+            Interval::SixHours => 360,
+            Interval::TwelveHours => 720,
+            Interval::OneDay => 1_440,
+            Interval::OneWeek => 10_080,
+            Interval::OneMonth => 43_200,
+        }
+    }
+
+    /// Start of the bucket `at` falls in.
+    pub fn bucket_start(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Interval::OneWeek => {
+                let day = at.date_naive();
+                midnight(day - Duration::days(i64::from(day.weekday().num_days_from_monday())))
+            }
+            Interval::OneMonth => midnight(at.date_naive().with_day(1).unwrap_or(at.date_naive())),
+            _ => {
+                let seconds = i64::from(self.minutes()) * 60;
+                let start = at.timestamp().div_euclid(seconds) * seconds;
+                DateTime::from_timestamp(start, 0).unwrap_or(at)
+            }
+        }
+    }
+
+    /// End of the bucket starting at `start`, exclusive.
+    pub fn bucket_end(&self, start: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Interval::OneMonth => start.checked_add_months(Months::new(1)).unwrap_or(start),
+            _ => start + Duration::minutes(i64::from(self.minutes())),
+        }
+    }
+
+    /// Start of the bucket `count` buckets before the one starting at
+    /// `start`.
+    pub fn buckets_before(&self, start: DateTime<Utc>, count: u32) -> DateTime<Utc> {
+        match self {
+            Interval::OneMonth => start.checked_sub_months(Months::new(count)).unwrap_or(start),
+            _ => start - Duration::minutes(i64::from(self.minutes()) * i64::from(count)),
+        }
+    }
+
+    /// The Postgres `date_trunc` unit for intervals that follow the
+    /// calendar rather than a fixed length.
+    pub fn calendar_unit(&self) -> Option<&'static str> {
+        match self {
+            Interval::OneWeek => Some("week"),
+            Interval::OneMonth => Some("month"),
+            _ => None,
+        }
+    }
+}
+
+fn midnight(day: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&day.and_time(chrono::NaiveTime::MIN))
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Interval {
+    type Err = CryptoTradeError;
+
+    /// Case matters: `1m` is a minute and `1M` a month.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Interval::ALL
+            .into_iter()
+            .find(|interval| interval.as_str() == value)
+            .ok_or_else(|| CryptoTradeError::Validation {
+                message: format!(
+                    "Unsupported interval {:?}; use one of {}",
+                    value,
+                    Interval::ALL.map(|interval| interval.as_str()).join(", ")
+                ),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_parse_round_trips_and_rejects_unknown_values() {
+        for interval in Interval::ALL {
+            assert_eq!(interval.as_str().parse::<Interval>().unwrap(), interval);
+        }
+        assert_eq!("1M".parse::<Interval>().unwrap(), Interval::OneMonth);
+        for unknown in ["", "2m", "1H", "60", "1 m"] {
+            assert!(matches!(unknown.parse::<Interval>(), Err(CryptoTradeError::Validation { .. })));
+        }
+    }
+
+    #[test]
+    fn test_buckets_align_to_the_interval_start() {
+        let time = at("2024-02-29T13:47:12Z");
+        assert_eq!(Interval::FifteenMinutes.bucket_start(time), at("2024-02-29T13:45:00Z"));
+        assert_eq!(Interval::FourHours.bucket_start(time), at("2024-02-29T12:00:00Z"));
+        assert_eq!(Interval::TwelveHours.bucket_start(time), at("2024-02-29T12:00:00Z"));
+        assert_eq!(Interval::OneDay.bucket_start(time), at("2024-02-29T00:00:00Z"));
+        // A Thursday; weeks start on Monday
+        assert_eq!(Interval::OneWeek.bucket_start(time), at("2024-02-26T00:00:00Z"));
+        assert_eq!(Interval::OneWeek.bucket_start(at("2024-02-26T00:00:00Z")), at("2024-02-26T00:00:00Z"));
+        assert_eq!(Interval::OneMonth.bucket_start(time), at("2024-02-01T00:00:00Z"));
+
+        assert_eq!(Interval::OneMonth.bucket_end(at("2024-02-01T00:00:00Z")), at("2024-03-01T00:00:00Z"));
+        assert_eq!(Interval::OneWeek.bucket_end(at("2024-02-26T00:00:00Z")), at("2024-03-04T00:00:00Z"));
+        assert_eq!(Interval::OneHour.bucket_end(at("2024-02-29T13:00:00Z")), at("2024-02-29T14:00:00Z"));
+        assert_eq!(Interval::OneMonth.buckets_before(at("2024-03-01T00:00:00Z"), 13), at("2023-02-01T00:00:00Z"));
+        assert_eq!(Interval::FourHours.buckets_before(at("2024-02-29T12:00:00Z"), 4), at("2024-02-28T20:00:00Z"));
+    }
+}
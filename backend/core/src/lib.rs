@@ -1,15 +1,38 @@
 pub mod auth;
+pub mod captcha;
+pub mod compliance;
 pub mod config;
 pub mod database;
+pub mod deprecation;
+pub mod email;
 pub mod error;
+pub mod fields;
+pub mod i18n;
+pub mod indicators;
+pub mod interval;
+pub mod matching;
 pub mod models;
+pub mod oracle;
+pub mod payments;
+pub mod pdf;
+pub mod repositories;
+pub mod resilience;
+pub mod secrets;
 pub mod services;
+pub mod signer;
+pub mod storage;
 pub mod utils;
 
 pub use auth::*;
 pub use config::*;
 pub use database::*;
+pub use deprecation::DeprecatedRouteUsage;
 pub use error::*;
+pub use i18n::Locale;
+pub use interval::Interval;
+pub use matching::*;
 pub use models::*;
+pub use repositories::*;
+pub use resilience::CircuitBreaker;
 pub use services::*;
 pub use utils::*;
@@ -0,0 +1,779 @@
+use super::event::{EngineCommand, EngineEvent, EngineEventKind, NewOrder};
+use crate::models::{DepthBand, OrderBook, OrderBookLevel, OrderBookStats, OrderSide, OrderType, TimeInForce};
+use chrono::{DateTime, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use uuid::Uuid;
+
+/// Decimal places quantities are stored with
+const QUANTITY_SCALE: u32 = 8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestingOrder {
+    pub order_id: Uuid,
+    pub user_id: Uuid,
+    pub side: OrderSide,
+    pub price: Decimal,
+    pub remaining_quantity: Decimal,
+}
+
+/// In-memory price-time priority book for a single trading pair.
+///
+/// The book is fully deterministic: applying the same sequence of commands to
+/// an empty book always yields the same events and the same resting orders,
+/// which is what makes event-log replay possible.
+#[derive(Debug, Clone)]
+pub struct LimitOrderBook {
+    trading_pair_id: Uuid,
+    bids: BTreeMap<Decimal, VecDeque<RestingOrder>>,
+    asks: BTreeMap<Decimal, VecDeque<RestingOrder>>,
+    index: HashMap<Uuid, (OrderSide, Decimal)>,
+    last_sequence: i64,
+    /// In a call auction orders accumulate without matching
+    auction: bool,
+}
+
+impl LimitOrderBook {
+    pub fn new(trading_pair_id: Uuid) -> Self {
+        Self {
+            trading_pair_id,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            index: HashMap::new(),
+            last_sequence: 0,
+            auction: false,
+        }
+    }
+
+    pub fn trading_pair_id(&self) -> Uuid {
+        self.trading_pair_id
+    }
+
+    pub fn last_sequence(&self) -> i64 {
+        self.last_sequence
+    }
+
+    pub fn in_auction(&self) -> bool {
+        self.auction
+    }
+
+    pub fn contains(&self, order_id: Uuid) -> bool {
+        self.index.contains_key(&order_id)
+    }
+
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.bids.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.asks.keys().next().copied()
+    }
+
+    /// Applies a command and returns the sequenced events it produced. The
+    /// first event is always the journaled input, followed by its outputs.
+    pub fn process(&mut self, command: &EngineCommand, at: DateTime<Utc>) -> Vec<EngineEvent> {
+        let mut kinds = vec![command.to_event_kind()];
+
+        match command {
+            EngineCommand::AddOrder(order) => self.add_order(order, &mut kinds),
+            EngineCommand::CancelOrder { order_id } => self.cancel_order(*order_id, &mut kinds),
+            EngineCommand::AmendOrder { order_id, quantity } => {
+                self.amend_order(*order_id, *quantity, &mut kinds)
+            }
+            EngineCommand::StartAuction => self.auction = true,
+            EngineCommand::Uncross => self.uncross(&mut kinds),
+        }
+
+        kinds
+            .into_iter()
+            .map(|kind| {
+                self.last_sequence += 1;
+                EngineEvent {
+                    trading_pair_id: self.trading_pair_id,
+                    sequence: self.last_sequence,
+                    kind,
+                    created_at: at,
+                }
+            })
+            .collect()
+    }
+
+    /// The events `order` would produce against the book as it stands,
+    /// leaving the book itself untouched.
+    pub fn simulate(&self, order: &NewOrder, at: DateTime<Utc>) -> Vec<EngineEvent> {
+        self.clone().process(&EngineCommand::AddOrder(order.clone()), at)
+    }
+
+    pub fn bid_levels(&self, depth: usize) -> Vec<OrderBookLevel> {
+        self.bids.iter().rev().take(depth).map(|(price, queue)| aggregate(*price, queue)).collect()
+    }
+
+    pub fn ask_levels(&self, depth: usize) -> Vec<OrderBookLevel> {
+        self.asks.iter().take(depth).map(|(price, queue)| aggregate(*price, queue)).collect()
+    }
+
+    pub fn to_order_book(&self, symbol: String, depth: usize) -> OrderBook {
+        OrderBook {
+            trading_pair_id: self.trading_pair_id,
+            symbol,
+            bids: self.bid_levels(depth),
+            asks: self.ask_levels(depth),
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// Spread, mid price and the liquidity within each of `bands_percent`
+    /// of the mid price.
+    pub fn stats(&self, symbol: String, bands_percent: &[Decimal]) -> OrderBookStats {
+        let (best_bid, best_ask) = (self.best_bid(), self.best_ask());
+        let mid_price = best_bid.zip(best_ask).map(|(bid, ask)| (bid + ask) / Decimal::TWO);
+        let spread = best_bid.zip(best_ask).map(|(bid, ask)| ask - bid);
+        let hundred = Decimal::ONE_HUNDRED;
+
+        let depth = mid_price.map_or_else(Vec::new, |mid| {
+            bands_percent
+                .iter()
+                .map(|&percent| {
+                    let (lowest_bid, highest_ask) = (mid * (hundred - percent) / hundred, mid * (hundred + percent) / hundred);
+                    let (bid_quantity, bid_notional) = band_liquidity(self.bids.range(lowest_bid..));
+                    let (ask_quantity, ask_notional) = band_liquidity(self.asks.range(..=highest_ask));
+                    DepthBand {
+                        percent,
+                        bid_quantity,
+                        bid_notional,
+                        ask_quantity,
+                        ask_notional,
+                    }
+                })
+                .collect()
+        });
+
+        OrderBookStats {
+            trading_pair_id: self.trading_pair_id,
+            symbol,
+            engine_sequence: self.last_sequence,
+            best_bid,
+            best_ask,
+            mid_price,
+            spread,
+            spread_percent: spread.zip(mid_price).map(|(spread, mid)| (spread / mid * hundred).round_dp(8)),
+            depth,
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// All resting orders in priority order, bids first.
+    pub fn resting_orders(&self) -> Vec<RestingOrder> {
+        self.bids
+            .values()
+            .rev()
+            .chain(self.asks.values())
+            .flat_map(|queue| queue.iter().cloned())
+            .collect()
+    }
+
+    pub(crate) fn restore_resting(&mut self, order: RestingOrder) {
+        self.index.insert(order.order_id, (order.side, order.price));
+        self.side_mut(order.side).entry(order.price).or_default().push_back(order);
+    }
+
+    pub(crate) fn set_last_sequence(&mut self, sequence: i64) {
+        self.last_sequence = sequence;
+    }
+
+    pub(crate) fn set_auction(&mut self, auction: bool) {
+        self.auction = auction;
+    }
+
+    /// The price a call auction would open at now, with the quantity that
+    /// would trade at it, or `None` if no bid crosses an ask.
+    ///
+    /// The opening price is the limit price that executes the most quantity;
+    /// ties go to the price leaving the smallest unmatched surplus, then to
+    /// the highest such price if buyers are left over and the lowest if
+    /// sellers are.
+    pub fn opening_price(&self) -> Option<(Decimal, Decimal)> {
+        let candidates: BTreeSet<Decimal> = self.bids.keys().chain(self.asks.keys()).copied().collect();
+        let mut best: Option<(Decimal, Decimal, Decimal)> = None;
+
+        for price in candidates {
+            let (demand, _) = band_liquidity(self.bids.range(price..));
+            let (supply, _) = band_liquidity(self.asks.range(..=price));
+            let volume = demand.min(supply);
+            if volume.is_zero() {
+                continue;
+            }
+
+            let surplus = demand - supply;
+            let better = best.is_none_or(|(_, best_volume, best_surplus)| {
+                volume > best_volume
+                    || (volume == best_volume
+                        && (surplus.abs() < best_surplus.abs()
+                            || (surplus.abs() == best_surplus.abs() && surplus > Decimal::ZERO)))
+            });
+            if better {
+                best = Some((price, volume, surplus));
+            }
+        }
+
+        best.map(|(price, volume, _)| (price, volume))
+    }
+
+    fn add_order(&mut self, order: &NewOrder, out: &mut Vec<EngineEventKind>) {
+        let reject = |reason: &str| EngineEventKind::Rejected {
+            order_id: order.order_id,
+            reason: reason.to_string(),
+        };
+
+        if order.quantity <= Decimal::ZERO {
+            out.push(reject("invalid_quantity"));
+            return;
+        }
+        if order.quote_budget.is_some_and(|budget| budget <= Decimal::ZERO) {
+            out.push(reject("invalid_quote_budget"));
+            return;
+        }
+        if self.index.contains_key(&order.order_id) {
+            out.push(reject("duplicate_order_id"));
+            return;
+        }
+
+        let limit_price = match order.order_type {
+            OrderType::Market => None,
+            OrderType::Limit => match order.price {
+                Some(price) if price > Decimal::ZERO => Some(price),
+                _ => {
+                    out.push(reject("invalid_price"));
+                    return;
+                }
+            },
+            _ => {
+                out.push(reject("unsupported_order_type"));
+                return;
+            }
+        };
+
+        if self.auction {
+            match limit_price {
+                Some(price) if matches!(order.time_in_force, TimeInForce::GTC | TimeInForce::GTD) => {
+                    self.rest(order, price, order.quantity);
+                }
+                _ => out.push(reject("auction_accepts_resting_limit_orders_only")),
+            }
+            return;
+        }
+
+        if order.time_in_force == TimeInForce::FOK
+            && self.fillable_quantity(order, limit_price) < order.quantity
+        {
+            out.push(reject("fill_or_kill_unfillable"));
+            return;
+        }
+
+        let remaining = self.match_order(order, limit_price, out);
+        if remaining.is_zero() {
+            return;
+        }
+
+        match limit_price {
+            Some(price) if matches!(order.time_in_force, TimeInForce::GTC | TimeInForce::GTD) => {
+                self.rest(order, price, remaining);
+            }
+            _ => out.push(EngineEventKind::Cancelled {
+                order_id: order.order_id,
+                remaining_quantity: remaining,
+            }),
+        }
+    }
+
+    fn rest(&mut self, order: &NewOrder, price: Decimal, remaining_quantity: Decimal) {
+        self.side_mut(order.side).entry(price).or_default().push_back(RestingOrder {
+            order_id: order.order_id,
+            user_id: order.user_id,
+            side: order.side,
+            price,
+            remaining_quantity,
+        });
+        self.index.insert(order.order_id, (order.side, price));
+    }
+
+    /// Ends the auction by matching the opening quantity at the opening
+    /// price, best prices first and in time priority within a level. Auction
+    /// fills have no aggressor; the bid is journaled as the taker, which is
+    /// what its lock was sized for.
+    fn uncross(&mut self, out: &mut Vec<EngineEventKind>) {
+        self.auction = false;
+        let Some((price, volume)) = self.opening_price() else {
+            out.push(EngineEventKind::Uncrossed {
+                price: None,
+                quantity: Decimal::ZERO,
+            });
+            return;
+        };
+
+        let mut remaining = volume;
+        while !remaining.is_zero() {
+            let (Some(bid), Some(ask)) = (self.front(OrderSide::Buy), self.front(OrderSide::Sell)) else {
+                break;
+            };
+            let quantity = remaining.min(bid.remaining_quantity).min(ask.remaining_quantity);
+            out.push(EngineEventKind::Matched {
+                maker_order_id: ask.order_id,
+                taker_order_id: bid.order_id,
+                maker_user_id: ask.user_id,
+                taker_user_id: bid.user_id,
+                taker_side: OrderSide::Buy,
+                price,
+                quantity,
+            });
+            self.reduce_front(OrderSide::Buy, quantity);
+            self.reduce_front(OrderSide::Sell, quantity);
+            remaining -= quantity;
+        }
+
+        out.push(EngineEventKind::Uncrossed {
+            price: Some(price),
+            quantity: volume,
+        });
+    }
+
+    /// The order first in line at the side's best price.
+    fn front(&self, side: OrderSide) -> Option<RestingOrder> {
+        let levels = match side {
+            OrderSide::Buy => self.bids.iter().next_back(),
+            OrderSide::Sell => self.asks.iter().next(),
+        };
+        levels.and_then(|(_, queue)| queue.front().cloned())
+    }
+
+    /// Takes `quantity` off the order first in line at the side's best
+    /// price, removing it once nothing is left.
+    fn reduce_front(&mut self, side: OrderSide, quantity: Decimal) {
+        let Some(price) = self.best_price(side) else {
+            return;
+        };
+        let queue = self.side_mut(side).get_mut(&price).expect("price level exists");
+        let order = queue.front_mut().expect("price levels are never empty");
+        order.remaining_quantity -= quantity;
+
+        if order.remaining_quantity.is_zero() {
+            let order_id = order.order_id;
+            queue.pop_front();
+            if queue.is_empty() {
+                self.side_mut(side).remove(&price);
+            }
+            self.index.remove(&order_id);
+        }
+    }
+
+    /// Crosses the taker against the opposite side and returns the unfilled quantity.
+    fn match_order(
+        &mut self,
+        taker: &NewOrder,
+        limit_price: Option<Decimal>,
+        out: &mut Vec<EngineEventKind>,
+    ) -> Decimal {
+        let maker_side = opposite(taker.side);
+        let mut remaining = taker.quantity;
+        let mut budget = taker.quote_budget;
+
+        while !remaining.is_zero() {
+            let Some(price) = self.best_price(maker_side) else {
+                break;
+            };
+            if !crosses(taker.side, limit_price, price) {
+                break;
+            }
+
+            let queue = self.side_mut(maker_side).get_mut(&price).expect("price level exists");
+            let maker = queue.front_mut().expect("price levels are never empty");
+            let quantity = within_budget(budget, price, remaining.min(maker.remaining_quantity));
+            if quantity.is_zero() {
+                break;
+            }
+            if let Some(left) = budget.as_mut() {
+                *left -= quantity * price;
+            }
+            maker.remaining_quantity -= quantity;
+            remaining -= quantity;
+
+            out.push(EngineEventKind::Matched {
+                maker_order_id: maker.order_id,
+                taker_order_id: taker.order_id,
+                maker_user_id: maker.user_id,
+                taker_user_id: taker.user_id,
+                taker_side: taker.side,
+                price,
+                quantity,
+            });
+
+            let filled_maker = if maker.remaining_quantity.is_zero() {
+                queue.pop_front().map(|maker| maker.order_id)
+            } else {
+                None
+            };
+            if queue.is_empty() {
+                self.side_mut(maker_side).remove(&price);
+            }
+            if let Some(order_id) = filled_maker {
+                self.index.remove(&order_id);
+            }
+        }
+
+        remaining
+    }
+
+    /// How much of `order` would fill against the book, stepping through
+    /// makers the way `match_order` does so a quote budget caps it alike.
+    fn fillable_quantity(&self, order: &NewOrder, limit_price: Option<Decimal>) -> Decimal {
+        let levels: Box<dyn Iterator<Item = (&Decimal, &VecDeque<RestingOrder>)>> = match order.side {
+            OrderSide::Buy => Box::new(self.asks.iter()),
+            OrderSide::Sell => Box::new(self.bids.iter().rev()),
+        };
+
+        let mut available = Decimal::ZERO;
+        let mut budget = order.quote_budget;
+        for (price, queue) in levels {
+            if !crosses(order.side, limit_price, *price) {
+                break;
+            }
+            for maker in queue {
+                let wanted = (order.quantity - available).min(maker.remaining_quantity);
+                let quantity = within_budget(budget, *price, wanted);
+                if quantity.is_zero() {
+                    return available;
+                }
+                if let Some(left) = budget.as_mut() {
+                    *left -= quantity * price;
+                }
+                available += quantity;
+            }
+        }
+        available
+    }
+
+    fn cancel_order(&mut self, order_id: Uuid, out: &mut Vec<EngineEventKind>) {
+        match self.remove_resting(order_id) {
+            Some(order) => out.push(EngineEventKind::Cancelled {
+                order_id,
+                remaining_quantity: order.remaining_quantity,
+            }),
+            None => out.push(EngineEventKind::Rejected {
+                order_id,
+                reason: "unknown_order".to_string(),
+            }),
+        }
+    }
+
+    /// Amendments may only reduce quantity so the order keeps its time priority.
+    fn amend_order(&mut self, order_id: Uuid, quantity: Decimal, out: &mut Vec<EngineEventKind>) {
+        let reject = |reason: &str| EngineEventKind::Rejected {
+            order_id,
+            reason: reason.to_string(),
+        };
+
+        let Some((side, price)) = self.index.get(&order_id).copied() else {
+            out.push(reject("unknown_order"));
+            return;
+        };
+        let resting = self
+            .side_mut(side)
+            .get_mut(&price)
+            .and_then(|queue| queue.iter_mut().find(|order| order.order_id == order_id))
+            .expect("indexed order is resting");
+
+        if quantity <= Decimal::ZERO || quantity >= resting.remaining_quantity {
+            out.push(reject("invalid_amend_quantity"));
+            return;
+        }
+
+        resting.remaining_quantity = quantity;
+        out.push(EngineEventKind::Amended { order_id, quantity });
+    }
+
+    fn remove_resting(&mut self, order_id: Uuid) -> Option<RestingOrder> {
+        let (side, price) = self.index.remove(&order_id)?;
+        let levels = self.side_mut(side);
+        let queue = levels.get_mut(&price)?;
+        let position = queue.iter().position(|order| order.order_id == order_id)?;
+        let order = queue.remove(position);
+        if queue.is_empty() {
+            levels.remove(&price);
+        }
+        order
+    }
+
+    fn best_price(&self, side: OrderSide) -> Option<Decimal> {
+        match side {
+            OrderSide::Buy => self.best_bid(),
+            OrderSide::Sell => self.best_ask(),
+        }
+    }
+
+    fn side_mut(&mut self, side: OrderSide) -> &mut BTreeMap<Decimal, VecDeque<RestingOrder>> {
+        match side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        }
+    }
+}
+
+/// `quantity` cut down to what `budget` still pays for at `price`, floored
+/// to the quantity scale so the notional never exceeds the budget.
+fn within_budget(budget: Option<Decimal>, price: Decimal, quantity: Decimal) -> Decimal {
+    match budget {
+        Some(left) => quantity.min((left / price).round_dp_with_strategy(QUANTITY_SCALE, RoundingStrategy::ToZero)),
+        None => quantity,
+    }
+}
+
+fn opposite(side: OrderSide) -> OrderSide {
+    match side {
+        OrderSide::Buy => OrderSide::Sell,
+        OrderSide::Sell => OrderSide::Buy,
+    }
+}
+
+fn crosses(taker_side: OrderSide, limit_price: Option<Decimal>, maker_price: Decimal) -> bool {
+    match (taker_side, limit_price) {
+        (_, None) => true,
+        (OrderSide::Buy, Some(limit)) => maker_price <= limit,
+        (OrderSide::Sell, Some(limit)) => maker_price >= limit,
+    }
+}
+
+fn aggregate(price: Decimal, queue: &VecDeque<RestingOrder>) -> OrderBookLevel {
+    OrderBookLevel {
+        price,
+        quantity: queue.iter().map(|order| order.remaining_quantity).sum(),
+        count: queue.len() as i32,
+    }
+}
+
+/// Total quantity and quote value resting at the given levels.
+fn band_liquidity<'a>(levels: impl Iterator<Item = (&'a Decimal, &'a VecDeque<RestingOrder>)>) -> (Decimal, Decimal) {
+    levels.fold((Decimal::ZERO, Decimal::ZERO), |(quantity, notional), (price, queue)| {
+        let level = aggregate(*price, queue).quantity;
+        (quantity + level, notional + level * price)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(side: OrderSide, price: i64, quantity: i64, time_in_force: TimeInForce) -> EngineCommand {
+        EngineCommand::AddOrder(NewOrder {
+            order_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(Decimal::from(price)),
+            quantity: Decimal::from(quantity),
+            time_in_force,
+            quote_budget: None,
+        })
+    }
+
+    fn kinds(events: &[EngineEvent]) -> Vec<&'static str> {
+        events.iter().map(|event| event.kind.event_type()).collect()
+    }
+
+    #[test]
+    fn test_crossing_limit_order_matches_at_maker_price() {
+        let mut book = LimitOrderBook::new(Uuid::new_v4());
+        book.process(&limit(OrderSide::Sell, 100, 5, TimeInForce::GTC), Utc::now());
+        let events = book.process(&limit(OrderSide::Buy, 105, 3, TimeInForce::GTC), Utc::now());
+
+        assert_eq!(kinds(&events), vec!["order_added", "matched"]);
+        match &events[1].kind {
+            EngineEventKind::Matched { price, quantity, .. } => {
+                assert_eq!(*price, Decimal::from(100));
+                assert_eq!(*quantity, Decimal::from(3));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(book.ask_levels(10)[0].quantity, Decimal::from(2));
+        assert!(book.bid_levels(10).is_empty());
+        assert_eq!(book.last_sequence(), 3);
+    }
+
+    #[test]
+    fn test_simulation_leaves_the_book_untouched() {
+        let mut book = LimitOrderBook::new(Uuid::new_v4());
+        book.process(&limit(OrderSide::Sell, 100, 2, TimeInForce::GTC), Utc::now());
+        book.process(&limit(OrderSide::Sell, 101, 2, TimeInForce::GTC), Utc::now());
+
+        let EngineCommand::AddOrder(order) = limit(OrderSide::Buy, 101, 3, TimeInForce::GTC) else {
+            unreachable!()
+        };
+        let events = book.simulate(&order, Utc::now());
+
+        assert_eq!(kinds(&events), vec!["order_added", "matched", "matched"]);
+        assert_eq!(book.ask_levels(10).len(), 2);
+        assert_eq!(book.ask_levels(10)[0].quantity, Decimal::from(2));
+        assert!(!book.contains(order.order_id));
+        assert_eq!(book.last_sequence(), 2);
+    }
+
+    #[test]
+    fn test_ioc_remainder_is_cancelled_and_fok_rejected() {
+        let mut book = LimitOrderBook::new(Uuid::new_v4());
+        book.process(&limit(OrderSide::Sell, 100, 2, TimeInForce::GTC), Utc::now());
+
+        let fok = book.process(&limit(OrderSide::Buy, 100, 3, TimeInForce::FOK), Utc::now());
+        assert_eq!(kinds(&fok), vec!["order_added", "rejected"]);
+
+        let ioc = book.process(&limit(OrderSide::Buy, 100, 3, TimeInForce::IOC), Utc::now());
+        assert_eq!(kinds(&ioc), vec!["order_added", "matched", "cancelled"]);
+        assert!(book.resting_orders().is_empty());
+    }
+
+    #[test]
+    fn test_market_buy_stops_at_its_quote_budget() {
+        let mut book = LimitOrderBook::new(Uuid::new_v4());
+        book.process(&limit(OrderSide::Sell, 100, 1, TimeInForce::GTC), Utc::now());
+        book.process(&limit(OrderSide::Sell, 150, 2, TimeInForce::GTC), Utc::now());
+
+        // 200 buys the unit at 100 and two thirds of a unit at 150
+        let market = NewOrder {
+            order_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Market,
+            price: None,
+            quantity: Decimal::from(3),
+            time_in_force: TimeInForce::IOC,
+            quote_budget: Some(Decimal::from(200)),
+        };
+        let fok = NewOrder {
+            time_in_force: TimeInForce::FOK,
+            ..market.clone()
+        };
+        assert_eq!(kinds(&book.simulate(&fok, Utc::now())), vec!["order_added", "rejected"]);
+
+        let events = book.process(&EngineCommand::AddOrder(market), Utc::now());
+        assert_eq!(kinds(&events), vec!["order_added", "matched", "matched", "cancelled"]);
+        let notional: Decimal = events
+            .iter()
+            .filter_map(|event| match &event.kind {
+                EngineEventKind::Matched { price, quantity, .. } => Some(price * quantity),
+                _ => None,
+            })
+            .sum();
+        assert!(notional <= Decimal::from(200));
+        assert_eq!(book.ask_levels(1)[0].quantity, Decimal::new(133333334, 8));
+    }
+
+    #[test]
+    fn test_cancel_and_amend_resting_order() {
+        let mut book = LimitOrderBook::new(Uuid::new_v4());
+        let command = limit(OrderSide::Buy, 99, 10, TimeInForce::GTC);
+        let order_id = command.order_id().unwrap();
+        book.process(&command, Utc::now());
+
+        let amend = EngineCommand::AmendOrder { order_id, quantity: Decimal::from(4) };
+        assert_eq!(kinds(&book.process(&amend, Utc::now())), vec!["amend_requested", "amended"]);
+        assert_eq!(book.bid_levels(1)[0].quantity, Decimal::from(4));
+
+        let cancel = EngineCommand::CancelOrder { order_id };
+        assert_eq!(kinds(&book.process(&cancel, Utc::now())), vec!["cancel_requested", "cancelled"]);
+        assert_eq!(kinds(&book.process(&cancel, Utc::now())), vec!["cancel_requested", "rejected"]);
+        assert!(!book.contains(order_id));
+    }
+
+    #[test]
+    fn test_auction_accumulates_then_uncrosses_at_one_price() {
+        let mut book = LimitOrderBook::new(Uuid::new_v4());
+        book.process(&EngineCommand::StartAuction, Utc::now());
+
+        // Demand at 100/101/102 is 6/4/1 and supply 3/5/8, so 101 executes
+        // the most
+        for (side, price, quantity) in [
+            (OrderSide::Buy, 102, 1),
+            (OrderSide::Buy, 101, 3),
+            (OrderSide::Buy, 100, 2),
+            (OrderSide::Sell, 99, 3),
+            (OrderSide::Sell, 101, 2),
+            (OrderSide::Sell, 102, 3),
+        ] {
+            let events = book.process(&limit(side, price, quantity, TimeInForce::GTC), Utc::now());
+            assert_eq!(kinds(&events), vec!["order_added"]);
+        }
+        let market = EngineCommand::AddOrder(NewOrder {
+            order_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Market,
+            price: None,
+            quantity: Decimal::ONE,
+            time_in_force: TimeInForce::IOC,
+            quote_budget: None,
+        });
+        assert_eq!(kinds(&book.process(&market, Utc::now())), vec!["order_added", "rejected"]);
+        assert_eq!(book.opening_price(), Some((Decimal::from(101), Decimal::from(4))));
+
+        let events = book.process(&EngineCommand::Uncross, Utc::now());
+        assert_eq!(kinds(&events).last(), Some(&"uncrossed"));
+        let fills: Vec<_> = events
+            .iter()
+            .filter_map(|event| match &event.kind {
+                EngineEventKind::Matched { price, quantity, .. } => Some((*price, *quantity)),
+                _ => None,
+            })
+            .collect();
+        assert!(fills.iter().all(|(price, _)| *price == Decimal::from(101)));
+        assert_eq!(fills.iter().map(|(_, quantity)| *quantity).sum::<Decimal>(), Decimal::from(4));
+        assert!(!book.in_auction());
+        assert_eq!(book.best_bid(), Some(Decimal::from(100)));
+        assert_eq!(book.best_ask(), Some(Decimal::from(101)));
+        assert_eq!(book.ask_levels(1)[0].quantity, Decimal::ONE);
+    }
+
+    #[test]
+    fn test_uncross_without_crossed_orders_opens_without_price() {
+        let mut book = LimitOrderBook::new(Uuid::new_v4());
+        book.process(&EngineCommand::StartAuction, Utc::now());
+        book.process(&limit(OrderSide::Buy, 99, 1, TimeInForce::GTC), Utc::now());
+        book.process(&limit(OrderSide::Sell, 101, 1, TimeInForce::GTC), Utc::now());
+
+        let events = book.process(&EngineCommand::Uncross, Utc::now());
+        assert_eq!(kinds(&events), vec!["uncross_requested", "uncrossed"]);
+        assert_eq!(
+            events[1].kind,
+            EngineEventKind::Uncrossed {
+                price: None,
+                quantity: Decimal::ZERO
+            }
+        );
+        assert_eq!(book.resting_orders().len(), 2);
+    }
+
+    #[test]
+    fn test_stats_sum_liquidity_within_each_band_of_mid() {
+        let mut book = LimitOrderBook::new(Uuid::new_v4());
+        let bands = [Decimal::new(5, 1), Decimal::ONE, Decimal::TWO];
+        assert!(book.stats("BTC-USD".to_string(), &bands).depth.is_empty());
+
+        // Mid 100: the bands reach 99.5/100.5, 99/101 and 98/102
+        for (side, price, quantity) in [
+            (OrderSide::Buy, 99, 2),
+            (OrderSide::Buy, 98, 3),
+            (OrderSide::Buy, 97, 5),
+            (OrderSide::Sell, 101, 1),
+            (OrderSide::Sell, 102, 4),
+        ] {
+            book.process(&limit(side, price, quantity, TimeInForce::GTC), Utc::now());
+        }
+        let stats = book.stats("BTC-USD".to_string(), &bands);
+
+        assert_eq!(stats.mid_price, Some(Decimal::from(100)));
+        assert_eq!(stats.spread, Some(Decimal::TWO));
+        assert_eq!(stats.spread_percent, Some(Decimal::TWO));
+        let quantities: Vec<_> = stats.depth.iter().map(|band| (band.bid_quantity, band.ask_quantity)).collect();
+        assert_eq!(quantities, [(0, 0), (2, 1), (5, 5)].map(|(bid, ask)| (Decimal::from(bid), Decimal::from(ask))));
+        assert_eq!(stats.depth[2].bid_notional, Decimal::from(2 * 99 + 3 * 98));
+        assert_eq!(stats.depth[2].ask_notional, Decimal::from(101 + 4 * 102));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{interval::Interval, models::CandleUpdate};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Intervals streamed live, each on its own channel
+pub const LIVE_CANDLE_INTERVALS: [Interval; 6] = [
+    Interval::OneMinute,
+    Interval::FiveMinutes,
+    Interval::FifteenMinutes,
+    Interval::OneHour,
+    Interval::FourHours,
+    Interval::OneDay,
+];
+
+/// The open candle of every live interval for one pair, built from the
+/// pair's fills as the engine produces them. Only buckets that trade are
+/// opened, so a quiet bucket produces no updates at all.
+///
+/// Candles start from the first fill seen after a restart; clients wanting
+/// a bucket's full history read it over REST.
+#[derive(Debug)]
+pub struct LiveCandles {
+    trading_pair_id: Uuid,
+    open: HashMap<Interval, CandleUpdate>,
+    /// Per interval, the end of the last bucket closed; later fills stamped
+    /// before it are too late to include
+    closed_until: HashMap<Interval, DateTime<Utc>>,
+}
+
+impl LiveCandles {
+    pub fn new(trading_pair_id: Uuid) -> Self {
+        Self {
+            trading_pair_id,
+            open: HashMap::new(),
+            closed_until: HashMap::new(),
+        }
+    }
+
+    /// Adds a fill to every interval, returning the updates to publish: the
+    /// close of any bucket the fill moved past, then each candle's new state.
+    pub fn record(&mut self, price: Decimal, quantity: Decimal, at: DateTime<Utc>) -> Vec<CandleUpdate> {
+        let mut updates = Vec::new();
+        for interval in LIVE_CANDLE_INTERVALS {
+            if self.closed_until.get(&interval).is_some_and(|until| at < *until) {
+                continue;
+            }
+            let open_time = interval.bucket_start(at);
+
+            if self.open.get(&interval).is_some_and(|candle| candle.open_time < open_time) {
+                if let Some(mut finished) = self.open.remove(&interval) {
+                    self.closed_until.insert(interval, finished.close_time);
+                    finished.closed = true;
+                    updates.push(finished);
+                }
+            }
+
+            let candle = self.open.entry(interval).or_insert_with(|| CandleUpdate {
+                trading_pair_id: self.trading_pair_id,
+                interval,
+                open_time,
+                close_time: interval.bucket_end(open_time),
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume: Decimal::ZERO,
+                trade_count: 0,
+                closed: false,
+            });
+            candle.high = candle.high.max(price);
+            candle.low = candle.low.min(price);
+            candle.close = price;
+            candle.volume += quantity;
+            candle.trade_count += 1;
+            updates.push(candle.clone());
+        }
+        updates
+    }
+
+    /// Closes the candles whose buckets ended by `now`.
+    pub fn close_due(&mut self, now: DateTime<Utc>) -> Vec<CandleUpdate> {
+        let due: Vec<Interval> = self
+            .open
+            .iter()
+            .filter(|(_, candle)| candle.close_time <= now)
+            .map(|(interval, _)| *interval)
+            .collect();
+
+        let mut closed = Vec::new();
+        for interval in due {
+            if let Some(mut candle) = self.open.remove(&interval) {
+                self.closed_until.insert(interval, candle.close_time);
+                candle.closed = true;
+                closed.push(candle);
+            }
+        }
+        closed.sort_by_key(|candle| candle.close_time);
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    fn minute(updates: &[CandleUpdate]) -> Vec<&CandleUpdate> {
+        updates.iter().filter(|candle| candle.interval == Interval::OneMinute).collect()
+    }
+
+    #[test]
+    fn test_fills_update_every_interval() {
+        let mut candles = LiveCandles::new(Uuid::nil());
+        candles.record(Decimal::from(100), Decimal::ONE, at("2024-01-01T10:00:05Z"));
+        let updates = candles.record(Decimal::from(104), Decimal::from(2), at("2024-01-01T10:00:40Z"));
+
+        assert_eq!(updates.len(), LIVE_CANDLE_INTERVALS.len());
+        let candle = minute(&updates)[0];
+        assert_eq!(candle.open_time, at("2024-01-01T10:00:00Z"));
+        assert_eq!(candle.close_time, at("2024-01-01T10:01:00Z"));
+        assert_eq!((candle.open, candle.high, candle.close), (Decimal::from(100), Decimal::from(104), Decimal::from(104)));
+        assert_eq!((candle.volume, candle.trade_count, candle.closed), (Decimal::from(3), 2, false));
+        let day = updates.iter().find(|candle| candle.interval == Interval::OneDay).unwrap();
+        assert_eq!(day.open_time, at("2024-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_buckets_close_once_on_the_boundary_or_the_next_fill() {
+        let mut candles = LiveCandles::new(Uuid::nil());
+        candles.record(Decimal::from(100), Decimal::ONE, at("2024-01-01T10:00:05Z"));
+
+        // A fill in the next minute closes the first one before opening its own
+        let updates = candles.record(Decimal::from(101), Decimal::ONE, at("2024-01-01T10:01:10Z"));
+        let minutes = minute(&updates);
+        assert_eq!(minutes.len(), 2);
+        assert!(minutes[0].closed && minutes[0].open_time == at("2024-01-01T10:00:00Z"));
+        assert!(!minutes[1].closed && minutes[1].trade_count == 1);
+
+        assert!(candles.close_due(at("2024-01-01T10:01:59Z")).is_empty());
+        let closed = candles.close_due(at("2024-01-01T10:02:00Z"));
+        assert_eq!(closed.len(), 1);
+        assert!(closed[0].closed && closed[0].interval == Interval::OneMinute);
+        assert!(candles.close_due(at("2024-01-01T10:02:30Z")).is_empty());
+
+        // Too late for the closed minute, though the hour is still open
+        let late = candles.record(Decimal::from(99), Decimal::ONE, at("2024-01-01T10:01:59Z"));
+        assert!(minute(&late).is_empty());
+        assert_eq!(late.len(), LIVE_CANDLE_INTERVALS.len() - 1);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::models::{OrderSide, OrderType, TimeInForce};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Commands accepted by the matching engine. Every command is journaled as an
+/// input event before it is applied to the book.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EngineCommand {
+    AddOrder(NewOrder),
+    CancelOrder { order_id: Uuid },
+    AmendOrder { order_id: Uuid, quantity: Decimal },
+    /// Stops continuous matching: limit orders rest, even crossed, until
+    /// `Uncross`
+    StartAuction,
+    /// Ends the call auction by matching the crossed orders at a single
+    /// opening price, then resumes continuous matching
+    Uncross,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewOrder {
+    pub order_id: Uuid,
+    pub user_id: Uuid,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub price: Option<Decimal>,
+    pub quantity: Decimal,
+    pub time_in_force: TimeInForce,
+    /// Most quote a market buy may spend on notional; matching stops short
+    /// of `quantity` rather than exceed it. `None` for every other order.
+    #[serde(default)]
+    pub quote_budget: Option<Decimal>,
+}
+
+/// A single entry of the append-only engine event log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineEvent {
+    pub trading_pair_id: Uuid,
+    pub sequence: i64,
+    pub kind: EngineEventKind,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineEventKind {
+    // Inputs
+    OrderAdded(NewOrder),
+    CancelRequested { order_id: Uuid },
+    AmendRequested { order_id: Uuid, quantity: Decimal },
+    AuctionStarted,
+    UncrossRequested,
+
+    // Outputs
+    Matched {
+        maker_order_id: Uuid,
+        taker_order_id: Uuid,
+        maker_user_id: Uuid,
+        taker_user_id: Uuid,
+        taker_side: OrderSide,
+        price: Decimal,
+        quantity: Decimal,
+    },
+    Cancelled { order_id: Uuid, remaining_quantity: Decimal },
+    Amended { order_id: Uuid, quantity: Decimal },
+    Rejected { order_id: Uuid, reason: String },
+    /// The auction's opening price and the quantity matched at it; no price
+    /// if nothing crossed
+    Uncrossed { price: Option<Decimal>, quantity: Decimal },
+}
+
+impl EngineEventKind {
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            Self::OrderAdded(_)
+                | Self::CancelRequested { .. }
+                | Self::AmendRequested { .. }
+                | Self::AuctionStarted
+                | Self::UncrossRequested
+        )
+    }
+
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            Self::OrderAdded(_) => "order_added",
+            Self::CancelRequested { .. } => "cancel_requested",
+            Self::AmendRequested { .. } => "amend_requested",
+            Self::AuctionStarted => "auction_started",
+            Self::UncrossRequested => "uncross_requested",
+            Self::Matched { .. } => "matched",
+            Self::Cancelled { .. } => "cancelled",
+            Self::Amended { .. } => "amended",
+            Self::Rejected { .. } => "rejected",
+            Self::Uncrossed { .. } => "uncrossed",
+        }
+    }
+
+    /// The order an event is about; for matches this is the taker. Auction
+    /// events are about the whole book.
+    pub fn order_id(&self) -> Option<Uuid> {
+        match self {
+            Self::OrderAdded(order) => Some(order.order_id),
+            Self::Matched { taker_order_id, .. } => Some(*taker_order_id),
+            Self::CancelRequested { order_id }
+            | Self::AmendRequested { order_id, .. }
+            | Self::Cancelled { order_id, .. }
+            | Self::Amended { order_id, .. }
+            | Self::Rejected { order_id, .. } => Some(*order_id),
+            Self::AuctionStarted | Self::UncrossRequested | Self::Uncrossed { .. } => None,
+        }
+    }
+
+    /// Reconstructs the command that produced an input event.
+    pub fn to_command(&self) -> Option<EngineCommand> {
+        match self {
+            Self::OrderAdded(order) => Some(EngineCommand::AddOrder(order.clone())),
+            Self::CancelRequested { order_id } => Some(EngineCommand::CancelOrder { order_id: *order_id }),
+            Self::AmendRequested { order_id, quantity } => Some(EngineCommand::AmendOrder {
+                order_id: *order_id,
+                quantity: *quantity,
+            }),
+            Self::AuctionStarted => Some(EngineCommand::StartAuction),
+            Self::UncrossRequested => Some(EngineCommand::Uncross),
+            _ => None,
+        }
+    }
+}
+
+impl EngineCommand {
+    pub fn order_id(&self) -> Option<Uuid> {
+        match self {
+            Self::AddOrder(order) => Some(order.order_id),
+            Self::CancelOrder { order_id } | Self::AmendOrder { order_id, .. } => Some(*order_id),
+            Self::StartAuction | Self::Uncross => None,
+        }
+    }
+
+    pub(crate) fn to_event_kind(&self) -> EngineEventKind {
+        match self {
+            Self::AddOrder(order) => EngineEventKind::OrderAdded(order.clone()),
+            Self::CancelOrder { order_id } => EngineEventKind::CancelRequested { order_id: *order_id },
+            Self::AmendOrder { order_id, quantity } => EngineEventKind::AmendRequested {
+                order_id: *order_id,
+                quantity: *quantity,
+            },
+            Self::StartAuction => EngineEventKind::AuctionStarted,
+            Self::Uncross => EngineEventKind::UncrossRequested,
+        }
+    }
+}
@@ -0,0 +1,17 @@
+pub mod book;
+pub mod candles;
+pub mod event;
+pub mod quoting;
+pub mod replay;
+pub mod router;
+pub mod snapshot;
+pub mod synthetic;
+
+pub use book::*;
+pub use candles::*;
+pub use event::*;
+pub use quoting::*;
+pub use replay::*;
+pub use router::*;
+pub use snapshot::*;
+pub use synthetic::*;
@@ -0,0 +1,141 @@
+use crate::models::OrderSide;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rust_decimal::prelude::*;
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+/// A resting limit order of a [`QuoteLadder`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub side: OrderSide,
+    pub price: Decimal,
+    pub quantity: Decimal,
+}
+
+/// Symmetric bids and asks stepping away from a mid price, as quoted by the
+/// sandbox house account and the market-maker tool.
+#[derive(Debug, Clone)]
+pub struct QuoteLadder {
+    /// Price levels on each side
+    pub levels: u32,
+    /// Distance between the best bid and best ask, in basis points
+    pub spread_bps: u32,
+    /// Distance between consecutive levels, in basis points
+    pub level_step_bps: u32,
+    /// Quote currency value of each level
+    pub level_notional: Decimal,
+    pub price_precision: u32,
+    pub quantity_precision: u32,
+    /// Levels whose quantity rounds below this are left out
+    pub min_quantity: Decimal,
+}
+
+impl QuoteLadder {
+    /// Best level first on each side, bids and asks interleaved. Prices are
+    /// rounded away from the mid so the spread never narrows below the
+    /// configured one.
+    pub fn quotes(&self, mid: Decimal) -> Vec<Quote> {
+        let half_spread = Decimal::from(self.spread_bps) / Decimal::TWO;
+
+        let mut quotes = Vec::new();
+        for level in 0..self.levels {
+            let offset = (half_spread + Decimal::from(level * self.level_step_bps)) / BPS;
+            let bid = (mid * (Decimal::ONE - offset))
+                .round_dp_with_strategy(self.price_precision, RoundingStrategy::ToZero);
+            let ask = (mid * (Decimal::ONE + offset))
+                .round_dp_with_strategy(self.price_precision, RoundingStrategy::AwayFromZero);
+
+            for (side, price) in [(OrderSide::Buy, bid), (OrderSide::Sell, ask)] {
+                if price <= Decimal::ZERO {
+                    continue;
+                }
+                let quantity = (self.level_notional / price)
+                    .round_dp_with_strategy(self.quantity_precision, RoundingStrategy::ToZero);
+                if quantity.is_zero() || quantity < self.min_quantity {
+                    continue;
+                }
+                quotes.push(Quote { side, price, quantity });
+            }
+        }
+
+        quotes
+    }
+}
+
+/// Seeded multiplicative random walk; the same seed always takes the same
+/// path for the same sequence of calls.
+pub struct RandomWalk {
+    rng: StdRng,
+    volatility_bps: u32,
+}
+
+impl RandomWalk {
+    pub fn new(seed: u64, volatility_bps: u32) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            volatility_bps,
+        }
+    }
+
+    /// Moves `price` by up to `volatility_bps` in either direction.
+    pub fn step(&mut self, price: Decimal) -> Decimal {
+        let draw = Decimal::from_f64(self.rng.gen_range(-1.0..=1.0)).unwrap_or(Decimal::ZERO);
+        price * (Decimal::ONE + draw * Decimal::from(self.volatility_bps) / BPS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ladder() -> QuoteLadder {
+        QuoteLadder {
+            levels: 3,
+            spread_bps: 10,
+            level_step_bps: 10,
+            level_notional: Decimal::from(5000),
+            price_precision: 2,
+            quantity_precision: 4,
+            min_quantity: Decimal::new(1, 4),
+        }
+    }
+
+    #[test]
+    fn test_ladder_steps_away_from_mid() {
+        let quotes = ladder().quotes(Decimal::from(60_000));
+
+        let bids: Vec<Decimal> = quotes.iter().filter(|q| q.side == OrderSide::Buy).map(|q| q.price).collect();
+        let asks: Vec<Decimal> = quotes.iter().filter(|q| q.side == OrderSide::Sell).map(|q| q.price).collect();
+        assert_eq!(bids, vec![Decimal::from(59_970), Decimal::from(59_910), Decimal::from(59_850)]);
+        assert_eq!(asks, vec![Decimal::from(60_030), Decimal::from(60_090), Decimal::from(60_150)]);
+
+        // 5000 / 59970 = 0.08337..., truncated to 4 places
+        assert_eq!(quotes[0].quantity, Decimal::new(833, 4));
+    }
+
+    #[test]
+    fn test_ladder_skips_levels_below_min_quantity() {
+        let mut ladder = ladder();
+        ladder.level_notional = Decimal::ONE;
+
+        assert!(ladder.quotes(Decimal::from(60_000)).is_empty());
+    }
+
+    #[test]
+    fn test_same_seed_replays_the_same_walk() {
+        let walk = |seed: u64| {
+            let mut walk = RandomWalk::new(seed, 20);
+            let mut price = Decimal::from(100);
+            (0..20)
+                .map(|_| {
+                    price = walk.step(price);
+                    price
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(walk(7), walk(7));
+        assert_ne!(walk(7), walk(8));
+        assert!(walk(7).iter().all(|price| (*price - Decimal::from(100)).abs() < Decimal::from(5)));
+    }
+}
@@ -0,0 +1,49 @@
+use uuid::Uuid;
+
+/// Maps trading pairs onto a fixed number of matching shards.
+///
+/// The mapping depends only on the pair ID and shard count, so it is stable
+/// across restarts and identical on every node that shares the same config.
+#[derive(Debug, Clone, Copy)]
+pub struct ShardRouter {
+    shard_count: usize,
+}
+
+impl ShardRouter {
+    pub fn new(shard_count: usize) -> Self {
+        Self {
+            shard_count: shard_count.max(1),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shard_count
+    }
+
+    pub fn shard_for(&self, trading_pair_id: Uuid) -> usize {
+        (trading_pair_id.as_u128() % self.shard_count as u128) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing_is_stable_and_in_range() {
+        let router = ShardRouter::new(4);
+        for _ in 0..100 {
+            let pair_id = Uuid::new_v4();
+            let shard = router.shard_for(pair_id);
+            assert!(shard < 4);
+            assert_eq!(shard, ShardRouter::new(4).shard_for(pair_id));
+        }
+    }
+
+    #[test]
+    fn test_zero_shards_falls_back_to_one() {
+        let router = ShardRouter::new(0);
+        assert_eq!(router.shard_count(), 1);
+        assert_eq!(router.shard_for(Uuid::new_v4()), 0);
+    }
+}
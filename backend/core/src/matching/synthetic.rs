@@ -0,0 +1,127 @@
+use super::event::{EngineCommand, NewOrder};
+use crate::models::{OrderSide, OrderType, TimeInForce};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+const MAX_TRACKED_ORDERS: usize = 10_000;
+
+/// Seeded generator of realistic-looking order flow around a mid price, used
+/// by benchmarks and load tools. The same seed always yields the same stream.
+pub struct SyntheticOrderFlow {
+    rng: StdRng,
+    mid_price: Decimal,
+    tick_size: Decimal,
+    depth_ticks: i64,
+    cross_ticks: i64,
+    cancel_ratio: f64,
+    user_ids: Vec<Uuid>,
+    placed: Vec<Uuid>,
+}
+
+impl SyntheticOrderFlow {
+    pub fn new(seed: u64, mid_price: Decimal) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let user_ids = (0..100).map(|_| random_uuid(&mut rng)).collect();
+
+        Self {
+            rng,
+            mid_price,
+            tick_size: (mid_price / Decimal::from(10_000)).max(Decimal::new(1, 8)),
+            depth_ticks: 50,
+            cross_ticks: 5,
+            cancel_ratio: 0.2,
+            user_ids,
+            placed: Vec::new(),
+        }
+    }
+
+    /// Fraction of commands that cancel a previously placed order.
+    pub fn with_cancel_ratio(mut self, cancel_ratio: f64) -> Self {
+        self.cancel_ratio = cancel_ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// How many ticks past the mid an aggressive order may be priced; zero
+    /// keeps every order at or behind the mid.
+    pub fn with_cross_ticks(mut self, cross_ticks: i64) -> Self {
+        self.cross_ticks = cross_ticks.max(0);
+        self
+    }
+
+    pub fn next_order(&mut self) -> NewOrder {
+        let side = if self.rng.gen_bool(0.5) { OrderSide::Buy } else { OrderSide::Sell };
+        let offset = self.rng.gen_range(-self.cross_ticks..=self.depth_ticks);
+        let distance = self.tick_size * Decimal::from(offset);
+        let price = match side {
+            OrderSide::Buy => self.mid_price - distance,
+            OrderSide::Sell => self.mid_price + distance,
+        };
+
+        let order = NewOrder {
+            order_id: random_uuid(&mut self.rng),
+            user_id: self.user_ids[self.rng.gen_range(0..self.user_ids.len())],
+            side,
+            order_type: OrderType::Limit,
+            price: Some(price.max(self.tick_size)),
+            quantity: Decimal::new(self.rng.gen_range(1..=1_000), 2),
+            time_in_force: TimeInForce::GTC,
+            quote_budget: None,
+        };
+
+        if self.placed.len() >= MAX_TRACKED_ORDERS {
+            self.placed.swap_remove(0);
+        }
+        self.placed.push(order.order_id);
+        order
+    }
+
+    pub fn next_command(&mut self) -> EngineCommand {
+        if !self.placed.is_empty() && self.rng.gen_bool(self.cancel_ratio) {
+            let index = self.rng.gen_range(0..self.placed.len());
+            return EngineCommand::CancelOrder {
+                order_id: self.placed.swap_remove(index),
+            };
+        }
+        EngineCommand::AddOrder(self.next_order())
+    }
+}
+
+impl Iterator for SyntheticOrderFlow {
+    type Item = EngineCommand;
+
+    fn next(&mut self) -> Option<EngineCommand> {
+        Some(self.next_command())
+    }
+}
+
+fn random_uuid(rng: &mut StdRng) -> Uuid {
+    uuid::Builder::from_random_bytes(rng.gen()).into_uuid()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_yields_same_flow() {
+        let first: Vec<_> = SyntheticOrderFlow::new(7, Decimal::from(100)).take(500).collect();
+        let second: Vec<_> = SyntheticOrderFlow::new(7, Decimal::from(100)).take(500).collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_passive_flow_stays_behind_mid() {
+        let mut flow = SyntheticOrderFlow::new(1, Decimal::from(100))
+            .with_cancel_ratio(0.0)
+            .with_cross_ticks(0);
+        for _ in 0..500 {
+            let order = flow.next_order();
+            let price = order.price.unwrap();
+            match order.side {
+                OrderSide::Buy => assert!(price <= Decimal::from(100)),
+                OrderSide::Sell => assert!(price >= Decimal::from(100)),
+            }
+        }
+    }
+}
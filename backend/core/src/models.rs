@@ -1,3 +1,4 @@
+use crate::{i18n::Locale, interval::Interval};
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
@@ -18,8 +19,33 @@ pub struct User {
     pub two_fa_enabled: Option<bool>,
     pub two_fa_secret: Option<String>,
     pub kyc_status: Option<KycStatus>,
+    pub kyc_tier: KycTier,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
+    /// `user` or `admin`
+    pub role: String,
+    pub account_status: AccountStatus,
+    /// ISO 3166-1 alpha-2 country of residence
+    pub country: Option<String>,
+    /// Preferred language tag; `None` follows `Accept-Language`
+    pub locale: Option<String>,
+    /// White-label broker the user signed up through; `None` for the
+    /// exchange's own users
+    pub tenant_id: Option<Uuid>,
+    /// Failed logins since the last successful one
+    pub failed_login_attempts: i32,
+}
+
+/// Where an account is in its lifecycle. Accounts are never deleted: a
+/// restricted account can only cancel orders and withdraw, and a closed one
+/// can still read its history but not transact. Closing is final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "account_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    Active,
+    Restricted,
+    Closed,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, ToSchema)]
@@ -31,6 +57,44 @@ pub enum KycStatus {
     Required,
 }
 
+/// How far a user's identity has been verified, which sets their limits
+/// (see [`KycTierLimits`]). Admins move users between tiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "kyc_tier", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum KycTier {
+    #[default]
+    Basic,
+    Verified,
+    Enhanced,
+}
+
+/// What a KYC tier allows. Amounts are in the units of the currency they
+/// apply to, like the open notional limit, and days are UTC days.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct KycTierLimits {
+    /// Fiat deposited per currency per day. Chain deposits are not held to
+    /// it: the funds arrive whether or not they are accepted
+    #[schema(value_type = String)]
+    pub daily_deposit: Decimal,
+    /// Withdrawn per currency per day, fees excluded. Crypto is valued in
+    /// the tiers' reference currency at the current price
+    #[schema(value_type = String)]
+    pub daily_withdrawal: Decimal,
+    /// Notional of resting orders per quote currency; an admin override of
+    /// the user's limits wins over it
+    #[schema(value_type = String)]
+    pub max_open_notional: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UpdateKycTierRequest {
+    pub kyc_tier: KycTier,
+}
+
+/// Decimals amounts are kept to, as in the accounts table
+pub const AMOUNT_SCALE: u32 = 8;
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct Account {
     pub id: Uuid,
@@ -74,6 +138,276 @@ pub struct TradingPair {
     pub taker_fee: Option<Decimal>,
 
     pub created_at: Option<DateTime<Utc>>,
+
+    /// Limit prices further than this percentage from the last trade are
+    /// rejected; unset means no band
+    #[schema(value_type = Option<String>)]
+    pub price_band_percent: Option<Decimal>,
+
+    /// Where the pair is in its lifecycle; `is_active` follows it
+    pub status: TradingPairStatus,
+
+    /// The only tenant whose users may trade the pair; `null` when shared
+    pub tenant_id: Option<Uuid>,
+}
+
+/// Where a trading pair is in its lifecycle. Orders are accepted during the
+/// call auction and in continuous trading only; a halted pair reopens
+/// through another call auction, and delisted is final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "trading_pair_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TradingPairStatus {
+    /// Listed for display ahead of trading
+    Announced,
+    /// Orders rest without matching until the book uncrosses at a single
+    /// opening price
+    CallAuction,
+    Trading,
+    Halt,
+    /// Open orders are being cancelled ahead of removal
+    Delisting,
+    Delisted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UpdateTradingPairStatusRequest {
+    pub status: TradingPairStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TradingPairStatusChange {
+    pub id: Uuid,
+    pub trading_pair_id: Uuid,
+    pub from_status: TradingPairStatus,
+    pub to_status: TradingPairStatus,
+    pub changed_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A weekly window in which a pair takes orders, in UTC. `closes_at` is
+/// exclusive; a session running to the end of the day closes at 23:59:59.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TradingSession {
+    /// ISO weekday, 1 = Monday through 7 = Sunday
+    pub weekday: i16,
+    #[schema(value_type = String, example = "14:30:00")]
+    pub opens_at: chrono::NaiveTime,
+    #[schema(value_type = String, example = "21:00:00")]
+    pub closes_at: chrono::NaiveTime,
+}
+
+/// Replaces a pair's trading sessions; an empty list lets it trade around
+/// the clock.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetTradingSessionsRequest {
+    pub sessions: Vec<TradingSession>,
+}
+
+/// A pair as listed publicly, with its trading hours.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TradingPairListing {
+    #[serde(flatten)]
+    pub trading_pair: TradingPair,
+    /// Empty when the pair trades around the clock
+    pub sessions: Vec<TradingSession>,
+    /// Whether the current time falls in one of the sessions
+    pub in_session: bool,
+}
+
+/// The server clock, for signing requests within the receive window.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ServerTime {
+    /// Unix milliseconds, the unit of `X-API-Timestamp`
+    pub server_time: i64,
+}
+
+/// What a client needs to trade here, in one document: the pairs and the
+/// rules their orders must follow, request limits and supported features.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExchangeInfo {
+    pub timezone: String,
+    /// Unix milliseconds
+    pub server_time: i64,
+    /// How far a signed request's timestamp may be from `server_time`
+    pub recv_window_ms: u64,
+    pub rate_limits: Vec<RateLimitInfo>,
+    pub order_types: Vec<OrderType>,
+    pub time_in_force: Vec<TimeInForce>,
+    pub capabilities: ExchangeCapabilities,
+    pub symbols: Vec<ExchangeSymbol>,
+}
+
+/// Market data limits of one caller tier.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RateLimitInfo {
+    /// `anonymous` (counted by address) or `api_key`
+    pub tier: String,
+    pub requests_per_minute: u64,
+    /// Market data requests per UTC day; unset means unlimited
+    pub daily_quota: Option<u64>,
+    pub order_book_depth: usize,
+    pub max_trades: i64,
+    pub max_candles: i32,
+    pub history_days: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExchangeCapabilities {
+    /// Order entry and sequenced feeds at `/ws`
+    pub websocket: bool,
+    /// Market data as server-sent events
+    pub server_sent_events: bool,
+    /// Whether the test-funds sandbox is on
+    pub sandbox: bool,
+    /// Whether only reads are accepted right now
+    pub maintenance_mode: bool,
+}
+
+/// What this deployment offers, for SDKs that feature-detect rather than
+/// assume. Subsystems this build does not have are reported as off.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Capabilities {
+    pub api: ApiVersionInfo,
+    pub subsystems: Subsystems,
+    /// Feature flags on for every caller
+    pub features: Vec<String>,
+    /// Notable additions and changes to the public API, newest first
+    pub changelog: Vec<ApiChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiVersionInfo {
+    /// Path prefix of the current version, e.g. `v1`
+    pub version: String,
+    pub supported_versions: Vec<String>,
+    /// Version of the server build
+    pub build: String,
+    pub openapi_url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Subsystems {
+    pub spot: bool,
+    pub margin: bool,
+    pub staking: bool,
+    pub fiat: FiatCapabilities,
+    /// Whether the test-funds sandbox is on
+    pub sandbox: bool,
+    pub algo_orders: bool,
+    pub grid_bots: bool,
+    pub copy_trading: bool,
+    pub support_tickets: bool,
+    /// Queries at `/api/v1/graphql`
+    pub graphql: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FiatCapabilities {
+    pub card_deposits: bool,
+    pub bank_transfer_deposits: bool,
+    /// SEPA and ACH withdrawals to verified bank accounts
+    pub withdrawals: bool,
+    /// The currency deposits fund
+    pub deposit_currency: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiChange {
+    pub date: chrono::NaiveDate,
+    pub summary: String,
+}
+
+/// A listed pair with the limits its orders are checked against.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExchangeSymbol {
+    #[serde(flatten)]
+    pub listing: TradingPairListing,
+    pub filters: SymbolFilters,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SymbolFilters {
+    #[schema(value_type = Option<String>)]
+    pub min_quantity: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub max_quantity: Option<Decimal>,
+    /// Band around the last trade limit prices must fall in, the pair's
+    /// own or the exchange default; unset means no band
+    #[schema(value_type = Option<String>)]
+    pub price_band_percent: Option<Decimal>,
+}
+
+/// A pair on a user's watchlist. Positions run from 0 in the order the
+/// user arranged the list.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct WatchlistEntry {
+    pub trading_pair_id: Uuid,
+    pub symbol: String,
+    pub position: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Adds a pair to the end of the watchlist.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AddWatchlistEntryRequest {
+    pub trading_pair_id: Uuid,
+}
+
+/// Replaces the watchlist with these pairs, in this order.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetWatchlistRequest {
+    pub trading_pair_ids: Vec<Uuid>,
+}
+
+/// The outcome of moving a pair to a new status: its changes in order, and
+/// what the move set off.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TradingPairTransition {
+    pub trading_pair: TradingPair,
+    pub changes: Vec<TradingPairStatusChange>,
+    /// Set when leaving a call auction in which bids crossed asks
+    #[schema(value_type = Option<String>)]
+    pub opening_price: Option<Decimal>,
+    /// Quantity matched when the call auction uncrossed
+    #[schema(value_type = Option<String>)]
+    pub opening_quantity: Option<Decimal>,
+    /// Orders cancelled by delisting
+    pub cancelled_orders: u64,
+}
+
+/// A listed currency.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Currency {
+    pub code: String,
+    pub name: String,
+    pub decimals: i32,
+    pub is_active: bool,
+    /// Whether registration opens an account in it
+    pub open_by_default: bool,
+    /// Reference price portfolios are valued at; unset values balances at zero
+    #[schema(value_type = Option<String>)]
+    pub usd_price: Option<Decimal>,
+    /// How the chain's memos are written; unset for chains without memos
+    pub memo_format: Option<MemoFormat>,
+    /// The address every user deposits to, each with their own memo
+    pub deposit_address: Option<String>,
+    /// Confirmations before a deposit is credited
+    pub deposit_confirmations: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// How a chain writes the memo that tells deposits to a shared address
+/// apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "memo_format", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MemoFormat {
+    /// A destination tag, an unsigned 32 bit number, as on XRP
+    Tag,
+    /// Free text of up to 28 bytes, as on Stellar
+    Text,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
@@ -96,6 +430,14 @@ pub struct Order {
     #[schema(value_type = String)]
     pub remaining_quantity: Option<Decimal>,
 
+    /// Total quote currency exchanged across all fills, before fees
+    #[schema(value_type = String)]
+    pub cumulative_quote_quantity: Option<Decimal>,
+
+    /// Volume-weighted price of all fills; null until the first fill
+    #[schema(value_type = String)]
+    pub average_fill_price: Option<Decimal>,
+
     pub status: Option<OrderStatus>,
     pub time_in_force: Option<TimeInForce>,
 
@@ -105,10 +447,25 @@ pub struct Order {
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
     pub expires_at: Option<DateTime<Utc>>,
+
+    /// When a scheduled order is placed
+    pub activate_at: Option<DateTime<Utc>>,
+
+    /// Funds held while the order is scheduled
+    #[schema(value_type = Option<String>)]
+    pub scheduled_lock: Option<Decimal>,
+    /// Funds locked when the order was placed, released pro rata to what
+    /// it has left unfilled
+    #[schema(value_type = Option<String>)]
+    pub locked_amount: Option<Decimal>,
+    /// The algo order this order is a slice of
+    pub parent_order_id: Option<Uuid>,
+    /// Bumped by every update, for compare-and-swap updates
+    pub version: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, ToSchema)]
-#[sqlx(type_name = "order_type", rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "order_type", rename_all = "snake_case")]
 pub enum OrderType {
     Market,
     Limit,
@@ -118,16 +475,30 @@ pub enum OrderType {
     TakeProfitLimit,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, ToSchema)]
+impl sqlx::postgres::PgHasArrayType for OrderType {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_order_type")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
 #[sqlx(type_name = "order_side", rename_all = "lowercase")]
 pub enum OrderSide {
     Buy,
     Sell,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, ToSchema)]
-#[sqlx(type_name = "order_status", rename_all = "lowercase")]
+impl sqlx::postgres::PgHasArrayType for OrderSide {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_order_side")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "order_status", rename_all = "snake_case")]
 pub enum OrderStatus {
+    /// Waiting for its `activate_at` time
+    Scheduled,
     Pending,
     Open,
     PartiallyFilled,
@@ -137,7 +508,7 @@ pub enum OrderStatus {
     Expired,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
 #[sqlx(type_name = "time_in_force", rename_all = "lowercase")]
 pub enum TimeInForce {
     GTC,
@@ -146,6 +517,12 @@ pub enum TimeInForce {
     GTD,
 }
 
+impl sqlx::postgres::PgHasArrayType for TimeInForce {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_time_in_force")
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct Trade {
     pub id: Uuid,
@@ -167,9 +544,115 @@ pub struct Trade {
     #[schema(value_type = String)]
     pub seller_fee: Option<Decimal>,
 
+    /// The side of the incoming order that matched against the resting one
+    pub taker_side: OrderSide,
+
+    pub created_at: Option<DateTime<Utc>>,
+
+    /// Set once an admin has reversed the trade
+    pub busted_at: Option<DateTime<Utc>>,
+}
+
+impl Trade {
+    /// Whether `order_id`, one of the two orders in this trade, made or took liquidity.
+    pub fn liquidity(&self, order_id: Uuid) -> Liquidity {
+        let side = if order_id == self.buyer_order_id { OrderSide::Buy } else { OrderSide::Sell };
+        if side == self.taker_side {
+            Liquidity::Taker
+        } else {
+            Liquidity::Maker
+        }
+    }
+
+    /// The fee charged to the owner of `order_id`.
+    pub fn fee(&self, order_id: Uuid) -> Decimal {
+        let fee = if order_id == self.buyer_order_id { self.buyer_fee } else { self.seller_fee };
+        fee.unwrap_or(Decimal::ZERO)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Liquidity {
+    /// The order was resting on the book
+    Maker,
+    /// The order took liquidity from the book
+    Taker,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BustTradeRequest {
+    /// Recorded in the audit log
+    pub reason: String,
+}
+
+/// A reversed trade: `journal_id` is the ledger journal that undid its
+/// settlement.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TradeBust {
+    pub trade: Trade,
+    pub journal_id: Uuid,
+    pub reason: String,
+    pub busted_by: Uuid,
+}
+
+/// One trade against an order, as seen by the order's owner.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Fill {
+    pub trade_id: Uuid,
+    pub order_id: Uuid,
+    #[schema(value_type = String)]
+    pub price: Decimal,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    #[schema(value_type = String)]
+    pub quote_quantity: Decimal,
+    #[schema(value_type = String)]
+    pub fee: Decimal,
+    pub fee_currency: String,
+    pub liquidity: Liquidity,
     pub created_at: Option<DateTime<Utc>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "order_event_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OrderEventType {
+    /// Placed, or scheduled for later
+    Created,
+    /// Handed to the matching engine, or resting until its stop triggers
+    Accepted,
+    PartiallyFilled,
+    Filled,
+    Amended,
+    Cancelled,
+    Rejected,
+    Expired,
+    /// A trade that filled the order was reversed by an admin
+    FillBusted,
+}
+
+/// One state transition of an order, with the order's status and
+/// quantities right after it.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct OrderEvent {
+    pub id: Uuid,
+    pub sequence: i64,
+    pub order_id: Uuid,
+    pub event_type: OrderEventType,
+    pub status: OrderStatus,
+    #[schema(value_type = Option<String>)]
+    pub filled_quantity: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub remaining_quantity: Option<Decimal>,
+    pub trade_id: Option<Uuid>,
+    /// The user or admin behind the transition; `None` for the matching
+    /// engine and background jobs
+    pub actor_id: Option<Uuid>,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct MarketData {
     pub trading_pair_id: Uuid,
@@ -202,6 +685,39 @@ pub struct MarketData {
     pub updated_at: DateTime<Utc>,
 }
 
+/// Compact 24-hour statistics for one pair, as served in bulk by
+/// `/api/v1/tickers`. Prices are `null` when the pair has not traded in the
+/// last 24 hours, and bid and ask when that side of the book is empty.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Ticker {
+    pub symbol: String,
+    pub base_currency: String,
+    pub quote_currency: String,
+    #[schema(value_type = Option<String>)]
+    pub last_price: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub open_price: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub high_price: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub low_price: Option<Decimal>,
+    #[schema(value_type = String)]
+    pub price_change: Decimal,
+    #[schema(value_type = String)]
+    pub price_change_percent: Decimal,
+    /// Traded in the base currency
+    #[schema(value_type = String)]
+    pub volume: Decimal,
+    /// Traded in the quote currency
+    #[schema(value_type = String)]
+    pub quote_volume: Decimal,
+    pub trade_count: i64,
+    #[schema(value_type = Option<String>)]
+    pub bid_price: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub ask_price: Option<Decimal>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct OrderBook {
     pub trading_pair_id: Uuid,
@@ -211,6 +727,20 @@ pub struct OrderBook {
     pub timestamp: DateTime<Utc>,
 }
 
+/// The live book at a point in the engine's sequence, to start a local
+/// copy that `book` feed updates then keep current.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderBookSnapshot {
+    pub trading_pair_id: Uuid,
+    pub symbol: String,
+    /// Engine sequence the snapshot reflects; feed updates with an
+    /// `engine_sequence` at or below it are already included
+    pub last_update_id: i64,
+    pub bids: Vec<OrderBookLevel>,
+    pub asks: Vec<OrderBookLevel>,
+    pub timestamp: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct OrderBookLevel {
     #[schema(value_type = String)]
@@ -222,6 +752,158 @@ pub struct OrderBookLevel {
     pub count: i32,
 }
 
+/// Spread and resting liquidity near the mid price, read from the
+/// matching engine's book. The mid price, spread and depth are absent
+/// while either side of the book is empty.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderBookStats {
+    pub trading_pair_id: Uuid,
+    pub symbol: String,
+    pub engine_sequence: i64,
+    #[schema(value_type = Option<String>)]
+    pub best_bid: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub best_ask: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub mid_price: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub spread: Option<Decimal>,
+    /// The spread as a percentage of the mid price
+    #[schema(value_type = Option<String>)]
+    pub spread_percent: Option<Decimal>,
+    /// Narrowest band first
+    pub depth: Vec<DepthBand>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Liquidity resting within `percent` of the mid price on each side,
+/// cumulative from the best price outwards.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DepthBand {
+    #[schema(value_type = String)]
+    pub percent: Decimal,
+    #[schema(value_type = String)]
+    pub bid_quantity: Decimal,
+    /// Quote currency value of the bid quantity at its prices
+    #[schema(value_type = String)]
+    pub bid_notional: Decimal,
+    #[schema(value_type = String)]
+    pub ask_quantity: Decimal,
+    #[schema(value_type = String)]
+    pub ask_notional: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReplayReport {
+    pub trading_pair_id: Uuid,
+    pub last_sequence: i64,
+    pub events_replayed: i64,
+    pub divergent_sequences: Vec<i64>,
+    pub order_book: OrderBook,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EngineStatus {
+    pub trading_pair_id: Uuid,
+    pub shard: i32,
+    pub last_sequence: i64,
+    pub last_snapshot_sequence: Option<i64>,
+}
+
+/// A message on a WebSocket feed channel. Sequences increase by one per
+/// channel, so a client that sees a jump knows it missed messages.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StreamMessage {
+    pub channel: String,
+    pub sequence: i64,
+    #[schema(value_type = Object)]
+    pub data: serde_json::Value,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PublicTrade {
+    pub trading_pair_id: Uuid,
+    #[schema(value_type = String)]
+    pub price: Decimal,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    pub taker_side: OrderSide,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Published on the trades channel when a trade is busted, so feed
+/// consumers can take it back out of their volume and candles.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PublicTradeBust {
+    pub trading_pair_id: Uuid,
+    pub trade_id: Uuid,
+    #[schema(value_type = String)]
+    pub price: Decimal,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    pub taker_side: OrderSide,
+    pub traded_at: DateTime<Utc>,
+    pub busted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TickerUpdate {
+    pub trading_pair_id: Uuid,
+    #[schema(value_type = Option<String>)]
+    pub last_price: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub best_bid: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub best_ask: Option<Decimal>,
+    pub engine_sequence: i64,
+}
+
+/// The state of one live candle, published on its `candles_<interval>`
+/// channel after every fill and once more, with `closed` set, when its
+/// bucket ends.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CandleUpdate {
+    pub trading_pair_id: Uuid,
+    pub interval: Interval,
+    pub open_time: DateTime<Utc>,
+    /// End of the bucket, exclusive
+    pub close_time: DateTime<Utc>,
+    #[schema(value_type = String)]
+    pub open: Decimal,
+    #[schema(value_type = String)]
+    pub high: Decimal,
+    #[schema(value_type = String)]
+    pub low: Decimal,
+    #[schema(value_type = String)]
+    pub close: Decimal,
+    #[schema(value_type = String)]
+    pub volume: Decimal,
+    pub trade_count: i64,
+    /// Set on the final update of the bucket only
+    pub closed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BookUpdate {
+    pub trading_pair_id: Uuid,
+    pub engine_sequence: i64,
+    pub bids: Vec<OrderBookLevel>,
+    pub asks: Vec<OrderBookLevel>,
+}
+
+/// What a candlestick response puts in buckets without trades.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CandleFill {
+    /// Leave them out
+    #[default]
+    None,
+    /// A flat zero-volume candle at the previous close, from the first
+    /// bucket that has one
+    Previous,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct Candlestick {
     pub timestamp: Option<DateTime<Utc>>,
@@ -244,6 +926,34 @@ pub struct Candlestick {
     pub interval_minutes: i32,
 }
 
+/// Indicators of one candle, over the window of candles ending at it;
+/// `null` until a full window is available.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct IndicatorPoint {
+    pub timestamp: DateTime<Utc>,
+    #[schema(value_type = String)]
+    pub close: Decimal,
+    /// Volume-weighted typical price; `null` when nothing traded
+    #[schema(value_type = Option<String>)]
+    pub vwap: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub sma: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub ema: Option<Decimal>,
+    /// Average true range, smoothed as Wilder does
+    #[schema(value_type = Option<String>)]
+    pub atr: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Indicators {
+    pub trading_pair_id: Uuid,
+    pub interval: Interval,
+    /// Candles each indicator is computed over
+    pub window: u32,
+    pub points: Vec<IndicatorPoint>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct PortfolioSnapshot {
     pub id: Uuid,
@@ -295,6 +1005,14 @@ pub struct RegisterRequest {
     pub password: String,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
+    /// Country of residence, ISO 3166-1 alpha-2; taken from the request's
+    /// location when not given
+    #[validate(length(equal = 2))]
+    pub country: Option<String>,
+    /// Slug of the white-label broker signing the user up
+    pub tenant: Option<String>,
+    /// Response token of the CAPTCHA widget, when CAPTCHAs are enabled
+    pub captcha_token: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
@@ -303,6 +1021,9 @@ pub struct LoginRequest {
     pub email: String,
     pub password: String,
     pub totp_code: Option<String>,
+    /// Response token of the CAPTCHA widget; needed after repeated failed
+    /// logins when CAPTCHAs are enabled
+    pub captcha_token: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -323,6 +1044,20 @@ pub struct UserProfile {
     pub is_verified: bool,
     pub two_fa_enabled: bool,
     pub kyc_status: KycStatus,
+    pub kyc_tier: KycTier,
+    /// What the user's KYC tier allows
+    pub kyc_limits: KycTierLimits,
+    pub account_status: AccountStatus,
+    pub country: Option<String>,
+    pub locale: Option<String>,
+    pub tenant_id: Option<Uuid>,
+}
+
+/// Sets the language error messages and notifications use; `None` goes
+/// back to following the `Accept-Language` header.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UpdateLocaleRequest {
+    pub locale: Option<Locale>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
@@ -340,30 +1075,344 @@ pub struct CreateOrderRequest {
 
     #[schema(value_type = String)]
     pub stop_price: Option<Decimal>,
-}
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct Portfolio {
-    pub user_id: Uuid,
+    /// Place the order even if an identical one was placed moments ago
+    #[serde(default)]
+    pub allow_duplicate: bool,
 
-    #[schema(value_type = String)]
-    pub total_value_usd: Decimal,
+    /// Keep the order dormant until this time, then place it
+    pub activate_at: Option<DateTime<Utc>>,
 
-    pub accounts: Vec<AccountBalance>,
-    pub performance_24h: PerformanceMetrics,
-    pub open_orders_count: i64,
-    pub total_trades: i64,
+    /// Hold a scheduled order's funds from the start, so it cannot fail for
+    /// want of balance when it activates
+    #[serde(default)]
+    pub lock_funds: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct AccountBalance {
-    pub currency: String,
+/// Lists a new currency.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateCurrencyRequest {
+    /// Upper case ticker, e.g. `SOL`
+    #[validate(length(min = 2, max = 10))]
+    pub code: String,
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(range(min = 0, max = 18))]
+    pub decimals: i32,
+    #[serde(default)]
+    pub open_by_default: bool,
+    #[schema(value_type = Option<String>)]
+    pub usd_price: Option<Decimal>,
+    pub memo_format: Option<MemoFormat>,
+    /// Shared deposit address; needs a memo format
+    #[validate(length(min = 1, max = 255))]
+    pub deposit_address: Option<String>,
+    /// 1 by default
+    #[validate(range(min = 1))]
+    pub deposit_confirmations: Option<i32>,
+}
 
-    #[schema(value_type = String)]
-    pub balance: Decimal,
+/// Changes a listed currency; omitted fields are left as they are.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdateCurrencyRequest {
+    pub name: Option<String>,
+    pub is_active: Option<bool>,
+    pub open_by_default: Option<bool>,
+    #[schema(value_type = Option<String>)]
+    pub usd_price: Option<Decimal>,
+    pub memo_format: Option<MemoFormat>,
+    pub deposit_address: Option<String>,
+    pub deposit_confirmations: Option<i32>,
+}
 
-    #[schema(value_type = String)]
-    pub available_balance: Decimal,
+/// A white-label broker running on the exchange. Its users trade the shared
+/// pairs and its own at its fee schedule, and its front end is branded
+/// from the display fields.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Tenant {
+    pub id: Uuid,
+    /// Identifies the tenant in URLs and the `X-Tenant` header
+    pub slug: String,
+    pub name: String,
+    /// Every API key of the tenant's users starts `ctk_<prefix>_`
+    pub api_key_prefix: String,
+    /// Replaces the pair's maker fee for the tenant's users when set
+    #[schema(value_type = Option<String>)]
+    pub maker_fee: Option<Decimal>,
+    /// Replaces the pair's taker fee for the tenant's users when set
+    #[schema(value_type = Option<String>)]
+    pub taker_fee: Option<Decimal>,
+    pub display_name: Option<String>,
+    pub logo_url: Option<String>,
+    /// `#rrggbb`
+    pub primary_color: Option<String>,
+    pub support_email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Onboards a white-label broker.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateTenantRequest {
+    /// Lower case letters, digits and dashes
+    #[validate(length(min = 2, max = 50))]
+    pub slug: String,
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    /// Lower case letters and digits
+    #[validate(length(min = 2, max = 12))]
+    pub api_key_prefix: String,
+    #[schema(value_type = Option<String>)]
+    pub maker_fee: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub taker_fee: Option<Decimal>,
+    #[validate(length(max = 100))]
+    pub display_name: Option<String>,
+    #[validate(url)]
+    pub logo_url: Option<String>,
+    pub primary_color: Option<String>,
+    #[validate(email)]
+    pub support_email: Option<String>,
+}
+
+/// Replaces a tenant's name, fee schedule and branding; its slug and API
+/// key prefix are fixed.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct UpdateTenantRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[schema(value_type = Option<String>)]
+    pub maker_fee: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub taker_fee: Option<Decimal>,
+    #[validate(length(max = 100))]
+    pub display_name: Option<String>,
+    #[validate(url)]
+    pub logo_url: Option<String>,
+    pub primary_color: Option<String>,
+    #[validate(email)]
+    pub support_email: Option<String>,
+}
+
+/// Makes a pair private to a tenant or, with `null`, shares it again.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PairTenantRequest {
+    pub tenant_id: Option<Uuid>,
+}
+
+/// A device the user has logged in from, listed under their sessions.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct UserDevice {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    #[serde(skip)]
+    pub fingerprint: String,
+    pub user_agent: Option<String>,
+    /// Address of the latest login from the device
+    pub ip_address: Option<String>,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    /// `null` until the device is confirmed; logins from it are held until then
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Confirms a new device with the token from the link emailed to the user.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct ConfirmDeviceRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub token: String,
+}
+
+/// Whether a login was let through. A challenged login counts as trusted
+/// once the user confirms it through the emailed link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "login_event_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum LoginEventStatus {
+    Allowed,
+    Challenged,
+    Confirmed,
+}
+
+/// A successful password check, where it came from, and how it compares
+/// with the user's last trusted login.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct LoginEvent {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub ip_address: Option<String>,
+    /// ISO 3166-1 alpha-2 code, when the CDN reported one
+    pub country: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// From the last trusted login, when both were geolocated
+    pub distance_km: Option<i32>,
+    /// The speed the user would have had to travel at since the last
+    /// trusted login
+    pub speed_kmh: Option<i32>,
+    /// From 0, nothing changed, to 100, improbable travel from another
+    /// country and address
+    pub risk_score: i32,
+    pub improbable_travel: bool,
+    pub status: LoginEventStatus,
+    pub created_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+/// How risky an account's recent logins look, for admins.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AccountLoginRisk {
+    pub user_id: Uuid,
+    /// The highest score of the logins in the risk window
+    pub risk_score: i32,
+    /// Logins in the risk window, newest first
+    pub logins: Vec<LoginEvent>,
+}
+
+/// Sets or, with `null`, removes a pair's price band.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PriceBandRequest {
+    /// Percentage either side of the last traded price
+    #[schema(value_type = Option<String>)]
+    pub price_band_percent: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderPreview {
+    pub trading_pair_id: Uuid,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    /// The limit price, or for market orders the average price of the resting
+    /// liquidity the order would take
+    #[schema(value_type = Option<String>)]
+    pub estimated_price: Option<Decimal>,
+    #[schema(value_type = String)]
+    pub estimated_notional: Decimal,
+    #[schema(value_type = String)]
+    pub fee_rate: Decimal,
+    #[schema(value_type = String)]
+    pub estimated_fee: Decimal,
+    pub fee_currency: String,
+    pub lock_currency: String,
+    #[schema(value_type = String)]
+    pub lock_amount: Decimal,
+    #[schema(value_type = String)]
+    pub available_balance: Decimal,
+    pub sufficient_balance: bool,
+}
+
+/// What an order would do if it were placed now: its fills against the live
+/// book and what would become of the rest. Nothing is placed or locked.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderSimulation {
+    pub trading_pair_id: Uuid,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    /// One per price level taken, best first
+    pub fills: Vec<SimulatedFill>,
+    #[schema(value_type = String)]
+    pub filled_quantity: Decimal,
+    #[schema(value_type = String)]
+    pub remaining_quantity: Decimal,
+    #[schema(value_type = Option<String>)]
+    pub average_price: Option<Decimal>,
+    /// The best opposite price before the order
+    #[schema(value_type = Option<String>)]
+    pub best_price: Option<Decimal>,
+    /// How much worse than `best_price` the average fill is, in percent
+    #[schema(value_type = Option<String>)]
+    pub slippage_percent: Option<Decimal>,
+    #[schema(value_type = String)]
+    pub notional: Decimal,
+    #[schema(value_type = String)]
+    pub fee_rate: Decimal,
+    #[schema(value_type = String)]
+    pub fee: Decimal,
+    pub fee_currency: String,
+    /// Whether the unfilled remainder would rest on the book
+    pub rests: bool,
+    /// Why the engine would reject the order, e.g. a FOK it cannot fill
+    pub rejection_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SimulatedFill {
+    #[schema(value_type = String)]
+    pub price: Decimal,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReplaceMode {
+    /// Do not place the replacement if the cancel fails
+    #[default]
+    StopOnFailure,
+    /// Place the replacement even if the cancel fails
+    AllowFailure,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReplaceStatus {
+    Success,
+    Failure,
+    NotAttempted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CancelReplaceRequest {
+    pub cancel_order_id: Uuid,
+    #[validate]
+    pub new_order: CreateOrderRequest,
+    pub mode: Option<CancelReplaceMode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CancelReplaceBatchRequest {
+    #[validate(length(min = 1, max = 20))]
+    pub orders: Vec<CancelReplaceRequest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CancelReplaceResult {
+    pub cancel_order_id: Uuid,
+    pub cancel_result: CancelReplaceStatus,
+    pub new_order_result: CancelReplaceStatus,
+    pub cancelled_order: Option<Order>,
+    pub new_order: Option<Order>,
+    pub error_code: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Portfolio {
+    pub user_id: Uuid,
+
+    #[schema(value_type = String)]
+    pub total_value_usd: Decimal,
+
+    pub accounts: Vec<AccountBalance>,
+    pub performance_24h: PerformanceMetrics,
+    pub open_orders_count: i64,
+    pub total_trades: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AccountBalance {
+    pub currency: String,
+
+    #[schema(value_type = String)]
+    pub balance: Decimal,
+
+    #[schema(value_type = String)]
+    pub available_balance: Decimal,
 
     #[schema(value_type = String)]
     pub locked_balance: Decimal,
@@ -389,3 +1438,1542 @@ pub struct PerformanceMetrics {
     #[schema(value_type = String)]
     pub total_fees_24h: Decimal,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UserStats {
+    pub user_id: Uuid,
+
+    /// Traded notional over the last 30 days across all pairs, in USD
+    #[schema(value_type = String)]
+    pub volume_30d_usd: Decimal,
+
+    pub pairs: Vec<UserPairStats>,
+    pub order_locks: Vec<OrderLock>,
+}
+
+/// The user's activity and current fee rates on one active trading pair.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UserPairStats {
+    pub trading_pair_id: Uuid,
+    pub symbol: String,
+
+    /// Traded notional over the last 30 days, in the pair's quote currency
+    #[schema(value_type = String)]
+    pub volume_30d: Decimal,
+
+    #[schema(value_type = String)]
+    pub maker_fee: Decimal,
+
+    #[schema(value_type = String)]
+    pub taker_fee: Decimal,
+
+    pub open_orders: i64,
+}
+
+/// The part of `locked_balance` held for one open order.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct OrderLock {
+    pub order_id: Uuid,
+    pub trading_pair_id: Uuid,
+    pub side: OrderSide,
+    pub currency: String,
+
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldKind {
+    Order,
+    Withdrawal,
+}
+
+/// One reason part of an account's balance is locked.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BalanceHold {
+    pub kind: HoldKind,
+    /// The order or withdrawal transaction holding the funds
+    pub reference_id: Uuid,
+    pub trading_pair_id: Option<Uuid>,
+
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+}
+
+/// The risk limits in force for a user and how much of them is in use.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UserLimits {
+    pub max_open_orders_per_pair: i32,
+
+    /// Cap on the notional of resting orders, per quote currency
+    #[schema(value_type = String)]
+    pub max_open_notional: Decimal,
+
+    /// Whether an admin has overridden the defaults for this user
+    pub custom: bool,
+
+    pub pairs: Vec<PairOrderUsage>,
+    pub open_notional: Vec<NotionalUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct PairOrderUsage {
+    pub trading_pair_id: Uuid,
+    pub symbol: String,
+    pub open_orders: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct NotionalUsage {
+    pub currency: String,
+
+    #[schema(value_type = String)]
+    pub open_notional: Decimal,
+}
+
+/// Overrides a user's risk limits; a `null` field restores the default.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UpdateUserLimitsRequest {
+    pub max_open_orders_per_pair: Option<i32>,
+
+    #[schema(value_type = Option<String>)]
+    pub max_open_notional: Option<Decimal>,
+}
+
+/// Settings admins can change while the API runs. Unknown fields are
+/// rejected so a misspelt key in an update is not silently ignored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeSettings {
+    /// Refuses every change of state except by admins; reads keep working
+    pub maintenance_mode: bool,
+    /// Shown to users while in maintenance
+    pub maintenance_message: Option<String>,
+    /// Price band of pairs without their own; `null` leaves them unbanded
+    #[schema(value_type = Option<String>)]
+    pub default_price_band_percent: Option<Decimal>,
+    /// Seconds an identical order is rejected for; 0 turns the check off
+    pub duplicate_window_seconds: u64,
+    /// Applies to WebSocket connections opened after the change
+    pub ws_max_messages_per_second: u32,
+    pub ws_max_subscriptions: usize,
+}
+
+/// The runtime settings in force, and which of them admins have overridden.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RuntimeConfig {
+    pub settings: RuntimeSettings,
+    pub overridden: Vec<String>,
+}
+
+/// Who a feature flag is on for. A flag is off outside `environments`
+/// (empty means every environment); inside them it is on for `user_ids`,
+/// and otherwise, if `enabled`, for `rollout_percent` percent of users.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FeatureFlag {
+    pub key: String,
+    pub description: String,
+    pub enabled: bool,
+    pub environments: Vec<String>,
+    pub user_ids: Vec<Uuid>,
+    pub rollout_percent: u8,
+    /// False while the flag is at its built-in default
+    pub overridden: bool,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateFeatureFlagRequest {
+    pub enabled: bool,
+    #[serde(default)]
+    pub environments: Vec<String>,
+    #[serde(default)]
+    pub user_ids: Vec<Uuid>,
+    /// Defaults to 100
+    pub rollout_percent: Option<u8>,
+}
+
+/// An action a user or API key may be allowed to take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "trading_permission", rename_all = "snake_case")]
+pub enum TradingPermission {
+    Read,
+    SpotTrade,
+    Margin,
+    Withdraw,
+}
+
+impl sqlx::postgres::PgHasArrayType for TradingPermission {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_trading_permission")
+    }
+}
+
+/// What a caller may do; `None` leaves that dimension unrestricted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct PermissionScope {
+    pub permissions: Option<Vec<TradingPermission>>,
+    pub trading_pair_ids: Option<Vec<Uuid>>,
+}
+
+impl PermissionScope {
+    pub fn allows(&self, permission: TradingPermission) -> bool {
+        self.permissions
+            .as_ref()
+            .is_none_or(|permissions| permissions.contains(&permission))
+    }
+
+    pub fn allows_pair(&self, trading_pair_id: Uuid) -> bool {
+        self.trading_pair_ids
+            .as_ref()
+            .is_none_or(|pair_ids| pair_ids.contains(&trading_pair_id))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub label: String,
+    /// Sent in the `X-API-Key` header
+    pub key: String,
+    /// Signs requests; only ever returned in a [`CreatedApiKey`]
+    #[serde(skip)]
+    pub secret: Option<String>,
+    pub permissions: Vec<TradingPermission>,
+    /// Pairs the key may trade; `null` for all
+    pub trading_pair_ids: Option<Vec<Uuid>>,
+    /// Networks the key may be used from; `null` for any
+    pub ip_allowlist: Option<Vec<String>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    pub fn scope(&self) -> PermissionScope {
+        PermissionScope {
+            permissions: Some(self.permissions.clone()),
+            trading_pair_ids: self.trading_pair_ids.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateApiKeyRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub label: String,
+    #[validate(length(min = 1))]
+    pub permissions: Vec<TradingPermission>,
+    pub trading_pair_ids: Option<Vec<Uuid>>,
+    /// CIDR blocks or single addresses, e.g. `203.0.113.0/24`
+    pub ip_allowlist: Option<Vec<String>>,
+}
+
+/// Replaces a key's IP allowlist; `null` allows any address. Needs a
+/// current 2FA code.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UpdateIpAllowlistRequest {
+    pub ip_allowlist: Option<Vec<String>>,
+    pub totp_code: String,
+}
+
+/// A new API key with its secret, which is not shown again.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreatedApiKey {
+    pub api_key: ApiKey,
+    pub secret: String,
+}
+
+/// How much of its market data allowance an API key has used.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyUsage {
+    pub key_id: Uuid,
+    pub label: String,
+    pub requests_per_minute: u64,
+    /// Requests in the current one-minute window
+    pub used_this_minute: u64,
+    pub minute_resets_at: DateTime<Utc>,
+    pub daily_quota: u64,
+    /// Requests since midnight UTC
+    pub used_today: u64,
+    pub day_resets_at: DateTime<Utc>,
+}
+
+/// A public key other services can verify access tokens with, matched on
+/// the token's `kid` header.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JwtPublicKey {
+    pub kid: String,
+    /// JWS algorithm name, `RS256` or `EdDSA`
+    pub algorithm: String,
+    /// PEM-encoded
+    pub public_key: String,
+    pub sign_from: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AccountHolds {
+    pub currency: String,
+
+    #[schema(value_type = String)]
+    pub locked_balance: Decimal,
+
+    /// Sum of `holds`; any difference from `locked_balance` is unaccounted for
+    #[schema(value_type = String)]
+    pub total_held: Decimal,
+
+    pub holds: Vec<BalanceHold>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "ledger_entry_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerEntryType {
+    Trade,
+    Fee,
+    Deposit,
+    Withdrawal,
+    Transfer,
+    Adjustment,
+}
+
+impl sqlx::postgres::PgHasArrayType for LedgerEntryType {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_ledger_entry_type")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct LedgerEntry {
+    pub id: Uuid,
+    pub sequence: i64,
+    /// Shared by all entries of one balance change; they sum to zero per currency
+    pub journal_id: Uuid,
+    /// `None` for the exchange's own side of a journal
+    pub user_id: Option<Uuid>,
+    pub currency: String,
+    pub entry_type: LedgerEntryType,
+
+    /// Positive for credits, negative for debits
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+
+    #[schema(value_type = String)]
+    pub balance_after: Option<Decimal>,
+
+    /// The trade, transaction or account the journal was posted for
+    pub reference_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user's balances as the ledger stood at `at`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HistoricalBalances {
+    pub at: DateTime<Utc>,
+    pub balances: Vec<HistoricalBalance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct HistoricalBalance {
+    pub currency: String,
+    #[schema(value_type = String)]
+    pub balance: Decimal,
+}
+
+/// An export written to object storage.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExportFile {
+    /// Presigned; works without a token until `expires_at`
+    pub download_url: String,
+    pub expires_at: DateTime<Utc>,
+    /// NDJSON lines in the file
+    pub rows: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Statement {
+    pub currency: String,
+    pub entries: Vec<LedgerEntry>,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "transaction_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+    Pending,
+    /// A fiat withdrawal an admin approved, while the transfer is sent
+    Approved,
+    Confirmed,
+    Failed,
+    Cancelled,
+}
+
+/// How a fiat deposit is paid; stored as `transactions.provider`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentMethod {
+    Card,
+    BankTransfer,
+}
+
+impl PaymentMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Card => "card",
+            Self::BankTransfer => "bank_transfer",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePaymentRequest {
+    pub method: PaymentMethod,
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+}
+
+/// Where to send a bank transfer; the reference must be quoted so the
+/// transfer can be matched to the deposit.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BankInstructions {
+    pub account_name: String,
+    pub account_number: String,
+    pub routing_number: String,
+    pub reference: String,
+}
+
+/// A fiat deposit waiting for the user to pay.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PaymentIntent {
+    pub id: Uuid,
+    pub method: PaymentMethod,
+    pub currency: String,
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    pub status: TransactionStatus,
+    /// For card payments, completes the payment in the processor's client
+    pub client_secret: Option<String>,
+    pub bank_instructions: Option<BankInstructions>,
+}
+
+/// A fiat deposit and how far it got. `amount` is what was received once
+/// the deposit is confirmed.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Payment {
+    pub id: Uuid,
+    /// `card` or `bank_transfer`
+    pub provider: String,
+    pub external_id: Option<String>,
+    pub currency: String,
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    pub status: TransactionStatus,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "bank_rail", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum BankRail {
+    /// Euro transfers to an IBAN
+    Sepa,
+    /// US dollar transfers to a routing and account number
+    Ach,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "bank_account_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum BankAccountStatus {
+    PendingVerification,
+    Verified,
+    VerificationFailed,
+    Removed,
+}
+
+/// A bank account as shown to its owner; only the last digits of the
+/// account number are returned.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BankAccount {
+    pub id: Uuid,
+    pub rail: BankRail,
+    pub holder_name: String,
+    pub account_last4: String,
+    pub routing_code: String,
+    pub currency: String,
+    pub status: BankAccountStatus,
+    pub created_at: DateTime<Utc>,
+    pub verified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct AddBankAccountRequest {
+    pub rail: BankRail,
+    #[validate(length(min = 1, max = 140))]
+    pub holder_name: String,
+    /// IBAN for SEPA, account number for ACH
+    pub account_number: String,
+    /// BIC for SEPA, routing number for ACH
+    pub routing_code: String,
+}
+
+/// The two micro-deposit amounts, in either order.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VerifyBankAccountRequest {
+    #[schema(value_type = Vec<String>)]
+    pub amounts: Vec<Decimal>,
+}
+
+/// Micro-deposits waiting to be sent to a newly added account.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct PendingMicroDeposits {
+    pub bank_account_id: Uuid,
+    pub user_id: Uuid,
+    pub rail: BankRail,
+    pub holder_name: String,
+    pub account_number: String,
+    pub routing_code: String,
+    pub currency: String,
+    #[schema(value_type = Vec<String>)]
+    pub micro_deposits: Vec<Decimal>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateFiatWithdrawalRequest {
+    pub bank_account_id: Uuid,
+    /// Paid out to the bank; the rail's fee is held on top
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    /// Required before approval when the amount reaches the currency's
+    /// travel rule threshold; may also be submitted later
+    pub travel_rule: Option<TravelRuleInfo>,
+}
+
+/// Originator and beneficiary details the travel rule asks for. Names and
+/// countries default to the user's profile and the bank account holder.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct TravelRuleInfo {
+    pub originator_name: Option<String>,
+    #[validate(length(min = 1, max = 200))]
+    pub originator_address: String,
+    #[validate(length(equal = 2))]
+    pub originator_country: Option<String>,
+    pub beneficiary_name: Option<String>,
+    #[validate(length(min = 1, max = 200))]
+    pub beneficiary_address: String,
+    #[validate(length(equal = 2))]
+    pub beneficiary_country: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "travel_rule_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TravelRuleStatus {
+    Pending,
+    Transmitted,
+    /// The provider could not be reached or refused the data; submitting
+    /// it again retries
+    Failed,
+}
+
+/// Travel rule data on file for a withdrawal.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TravelRuleRecord {
+    pub withdrawal_id: Uuid,
+    /// The originator, beneficiary and amount as transmitted
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+    pub provider: String,
+    pub status: TravelRuleStatus,
+    /// The provider's id for the transfer
+    pub reference: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub transmitted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct FiatWithdrawal {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub bank_account_id: Uuid,
+    pub currency: String,
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    #[schema(value_type = String)]
+    pub fee: Option<Decimal>,
+    pub status: TransactionStatus,
+    /// The bank's reference for the transfer, once sent
+    pub external_id: Option<String>,
+    /// Why an admin rejected or failed the withdrawal
+    pub note: Option<String>,
+    /// The amount reached the travel rule threshold; the withdrawal is not
+    /// approved until its data has been transmitted
+    pub travel_rule_required: bool,
+    pub travel_rule_status: Option<TravelRuleStatus>,
+    /// The amount reached the dual approval threshold; a second admin must
+    /// confirm the approval
+    pub dual_approval_required: bool,
+    /// The admin who initiated the approval, while it awaits confirmation
+    pub approval_initiated_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Where a user sends deposits of a currency.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DepositAddress {
+    pub currency: String,
+    pub address: String,
+    /// The user's memo on the shared address; a deposit without it cannot
+    /// be credited automatically
+    #[sqlx(rename = "tag")]
+    pub memo: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A transfer the deposit watcher saw on chain, reported again as it
+/// gains confirmations.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChainDepositReport {
+    pub currency: String,
+    pub address: String,
+    pub memo: Option<String>,
+    /// The transfer's transaction hash
+    pub tx_id: String,
+    /// Which output, or transfer log on token contracts, of the transaction
+    /// pays this deposit; 0 on chains paying one recipient per transaction
+    #[serde(default)]
+    pub output_index: i32,
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    pub confirmations: i32,
+}
+
+/// A deposit to a shared address whose memo was missing or matched no
+/// user, held until an admin credits it to its sender.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct UnmatchedDeposit {
+    pub id: Uuid,
+    pub currency: String,
+    pub address: String,
+    pub memo: Option<String>,
+    pub tx_id: String,
+    pub output_index: i32,
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    pub credited_to: Option<Uuid>,
+    pub credited_by: Option<Uuid>,
+    pub credited_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreditUnmatchedDepositRequest {
+    /// The sender, once they have shown the deposit is theirs
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateCryptoWithdrawalRequest {
+    pub currency: String,
+    #[validate(length(min = 1, max = 255))]
+    pub address: String,
+    /// The recipient's memo or destination tag, on chains that use them
+    pub memo: Option<String>,
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct CryptoWithdrawal {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub currency: String,
+    pub address: Option<String>,
+    pub memo: Option<String>,
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    pub status: TransactionStatus,
+    /// The transaction hash, once broadcast
+    pub external_id: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "withdrawal_action", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalAction {
+    /// Pending to approved; the transfer is then sent. Above the dual
+    /// approval threshold the first approval only initiates it, and a
+    /// different admin's confirms it
+    Approve,
+    /// Pending to cancelled, releasing the funds
+    Reject,
+    /// Approved to confirmed once the transfer is sent; debits the balance
+    Complete,
+    /// Approved to failed when the bank refuses the transfer, releasing the funds
+    Fail,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReviewWithdrawalRequest {
+    pub action: WithdrawalAction,
+    /// The bank's transfer reference; required to complete
+    pub external_id: Option<String>,
+    pub note: Option<String>,
+}
+
+/// One admin action on a fiat withdrawal, kept as its audit trail.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct WithdrawalReview {
+    pub id: Uuid,
+    pub withdrawal_id: Uuid,
+    pub admin_id: Uuid,
+    pub action: WithdrawalAction,
+    pub status_before: TransactionStatus,
+    /// The same as `status_before` for an approval awaiting confirmation
+    pub status_after: TransactionStatus,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "document_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentKind {
+    /// For a completed deposit or withdrawal
+    Receipt,
+    TradeConfirmation,
+    /// Every ledger entry of one calendar month
+    Statement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "document_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+/// A PDF generated for a user in the background.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Document {
+    pub id: Uuid,
+    pub kind: DocumentKind,
+    /// The transaction or trade id, or the statement's month as `YYYY-MM`
+    pub subject: String,
+    pub status: DocumentStatus,
+    /// Why generation failed; requesting the document again retries it
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Where to fetch the PDF, once ready
+    #[sqlx(skip)]
+    pub download_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RequestDocumentRequest {
+    pub kind: DocumentKind,
+    /// The transaction for a receipt, or the trade for a trade confirmation
+    pub reference_id: Option<Uuid>,
+    /// The month of a statement, as `YYYY-MM`
+    pub month: Option<String>,
+}
+
+/// Closes the caller's account. The account must hold no funds and have
+/// no open orders.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CloseAccountRequest {
+    pub password: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateAccountStatusRequest {
+    pub status: AccountStatus,
+    /// Recorded with the change and shown to the user
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct AccountStatusChange {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub from_status: AccountStatus,
+    pub to_status: AccountStatus,
+    pub reason: String,
+    /// The admin who made the change; `None` when users closed their own
+    /// account or screening restricted it
+    pub changed_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "compliance_review_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ComplianceReviewStatus {
+    Pending,
+    /// A false positive; the account is no longer held for it
+    Cleared,
+    /// A true match; the account stays restricted
+    Confirmed,
+}
+
+/// A possible sanctions list match found when screening a user's name.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct ComplianceReview {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub screened_name: String,
+    pub listed_name: String,
+    pub list_name: String,
+    /// Share of the names' words in common, from 0 to 1
+    #[schema(value_type = String)]
+    pub score: Decimal,
+    pub status: ComplianceReviewStatus,
+    pub note: Option<String>,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ResolveComplianceReviewRequest {
+    /// `cleared` or `confirmed`
+    pub status: ComplianceReviewStatus,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DailyActivity {
+    pub day: chrono::NaiveDate,
+    /// Users who placed an order or took part in a trade
+    pub active_traders: i32,
+    pub new_registrations: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DailyPairVolume {
+    pub day: chrono::NaiveDate,
+    pub trading_pair_id: Uuid,
+    pub symbol: String,
+    pub trade_count: i32,
+    #[schema(value_type = String)]
+    pub base_volume: Decimal,
+    #[schema(value_type = String)]
+    pub quote_volume: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DailyFeeRevenue {
+    pub day: chrono::NaiveDate,
+    pub currency: String,
+    #[schema(value_type = String)]
+    pub fee_revenue: Decimal,
+}
+
+/// Money users moved in and out of the exchange.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DailyFlow {
+    pub day: chrono::NaiveDate,
+    pub currency: String,
+    #[schema(value_type = String)]
+    pub deposits: Decimal,
+    pub deposit_count: i32,
+    #[schema(value_type = String)]
+    pub withdrawals: Decimal,
+    pub withdrawal_count: i32,
+    /// Deposits less withdrawals
+    #[schema(value_type = String)]
+    pub net_flow: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RollupRequest {
+    pub from: chrono::NaiveDate,
+    /// Inclusive; defaults to `from`
+    pub to: Option<chrono::NaiveDate>,
+}
+
+/// The exchange's own account in one currency, into which fees are collected.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TreasuryAccount {
+    pub currency: String,
+    #[schema(value_type = String)]
+    pub balance: Decimal,
+    #[schema(value_type = String)]
+    pub total_collected: Decimal,
+    #[schema(value_type = String)]
+    pub total_swept: Decimal,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TreasurySweep {
+    pub id: Uuid,
+    pub currency: String,
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    pub destination: String,
+    pub note: Option<String>,
+    pub swept_by: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SweepTreasuryRequest {
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    /// Where the funds are paid out to, e.g. the operating bank account
+    pub destination: String,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DustConvertRequest {
+    /// The currency the dust is converted into
+    pub target_currency: String,
+    /// Only convert these currencies; by default every balance that is dust
+    pub currencies: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DustConversion {
+    pub id: Uuid,
+    pub target_currency: String,
+    /// Credited in the target currency, after fees
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    #[schema(value_type = String)]
+    pub fee: Decimal,
+    pub created_at: DateTime<Utc>,
+    #[sqlx(skip)]
+    pub items: Vec<DustConversionItem>,
+}
+
+/// One balance swept by a dust conversion.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DustConversionItem {
+    pub currency: String,
+    /// The whole available balance that was converted
+    #[schema(value_type = String)]
+    pub amount: Decimal,
+    /// Oracle price of one unit in the target currency
+    #[schema(value_type = String)]
+    pub price: Decimal,
+    /// `amount` at `price`, before the fee
+    #[schema(value_type = String)]
+    pub converted: Decimal,
+    #[schema(value_type = String)]
+    pub fee: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "algo_strategy", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AlgoStrategy {
+    /// Equal slices spread evenly over the duration
+    Twap,
+    /// A fixed share of the pair's market volume
+    Pov,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "algo_order_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AlgoOrderStatus {
+    Active,
+    Completed,
+    Cancelled,
+    /// A slice could not be placed; `error` says why
+    Failed,
+}
+
+/// A parent order worked in slices by the algo order service.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct AlgoOrder {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub trading_pair_id: Uuid,
+    pub side: OrderSide,
+    pub strategy: AlgoStrategy,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    #[schema(value_type = Option<String>)]
+    pub limit_price: Option<Decimal>,
+    pub duration_seconds: i32,
+    pub slice_count: Option<i32>,
+    #[schema(value_type = Option<String>)]
+    pub participation_rate: Option<Decimal>,
+    /// Filled across all child orders
+    #[schema(value_type = String)]
+    pub executed_quantity: Decimal,
+    pub slices_sent: i32,
+    pub status: AlgoOrderStatus,
+    pub error: Option<String>,
+    pub next_slice_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateAlgoOrderRequest {
+    pub trading_pair_id: Uuid,
+    pub side: OrderSide,
+    pub strategy: AlgoStrategy,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    /// Worst price a slice may trade at; slices are market orders without it
+    #[schema(value_type = Option<String>)]
+    pub limit_price: Option<Decimal>,
+    /// How long a TWAP order is spread over, or how long a POV order keeps
+    /// participating at most
+    pub duration_seconds: i32,
+    /// TWAP: the number of slices
+    pub slices: Option<i32>,
+    /// POV: share of market volume to trade, e.g. 0.1 for 10%
+    #[schema(value_type = Option<String>)]
+    pub participation_rate: Option<Decimal>,
+}
+
+/// An algo order with the child orders placed for it so far.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AlgoOrderProgress {
+    pub algo_order: AlgoOrder,
+    /// Share of the quantity executed, from 0 to 100
+    #[schema(value_type = String)]
+    pub percent_complete: Decimal,
+    /// Volume-weighted price of the fills so far
+    #[schema(value_type = Option<String>)]
+    pub average_price: Option<Decimal>,
+    pub children: Vec<Order>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "grid_bot_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum GridBotStatus {
+    Running,
+    Stopped,
+    /// An order could not be placed; `error` says why
+    Failed,
+}
+
+/// A grid trading bot and the ladder it was configured with.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct GridBot {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub trading_pair_id: Uuid,
+    #[schema(value_type = String)]
+    pub lower_price: Decimal,
+    #[schema(value_type = String)]
+    pub upper_price: Decimal,
+    /// Number of steps between the lower and upper price
+    pub grid_count: i32,
+    /// Quote currency the ladder is sized for
+    #[schema(value_type = String)]
+    pub investment: Decimal,
+    /// Base quantity of every grid order
+    #[schema(value_type = String)]
+    pub quantity_per_grid: Decimal,
+    pub status: GridBotStatus,
+    /// Earned by completed round trips, one grid step each, before fees
+    #[schema(value_type = String)]
+    pub realized_pnl: Decimal,
+    pub completed_cycles: i32,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateGridBotRequest {
+    pub trading_pair_id: Uuid,
+    #[schema(value_type = String)]
+    pub lower_price: Decimal,
+    #[schema(value_type = String)]
+    pub upper_price: Decimal,
+    pub grid_count: i32,
+    /// Quote currency to size the ladder for. Buy orders draw on the quote
+    /// balance and sell orders on the base balance.
+    #[schema(value_type = String)]
+    pub investment: Decimal,
+}
+
+/// A bot with its open orders and what it has traded, all before fees.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct GridBotReport {
+    pub bot: GridBot,
+    pub open_orders: Vec<Order>,
+    #[schema(value_type = String)]
+    pub base_bought: Decimal,
+    #[schema(value_type = String)]
+    pub quote_spent: Decimal,
+    #[schema(value_type = String)]
+    pub base_sold: Decimal,
+    #[schema(value_type = String)]
+    pub quote_received: Decimal,
+    /// Quote received less spent, with the base still held valued at the
+    /// last price; null without one
+    #[schema(value_type = Option<String>)]
+    pub total_pnl: Option<Decimal>,
+}
+
+/// A user whose fills others can copy.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct LeadTrader {
+    pub user_id: Uuid,
+    pub display_name: String,
+    /// Share of a follower's realized profit paid to the lead
+    #[schema(value_type = String)]
+    pub performance_fee_rate: Decimal,
+    pub is_active: bool,
+    /// Active subscriptions
+    pub followers: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PublishLeadTraderRequest {
+    pub display_name: String,
+    #[schema(value_type = String)]
+    pub performance_fee_rate: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "copy_subscription_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CopySubscriptionStatus {
+    Active,
+    /// Mirroring stopped by a risk limit or the lead leaving; `stop_reason`
+    /// says why
+    Stopped,
+    /// Closed by the follower
+    Closed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct CopySubscription {
+    pub id: Uuid,
+    pub follower_id: Uuid,
+    pub lead_id: Uuid,
+    pub budget_currency: String,
+    #[schema(value_type = String)]
+    pub budget: Decimal,
+    #[schema(value_type = Option<String>)]
+    pub max_trade_notional: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub stop_loss: Option<Decimal>,
+    #[schema(value_type = String)]
+    pub performance_fee_rate: Decimal,
+    pub status: CopySubscriptionStatus,
+    pub stop_reason: Option<String>,
+    /// Profit from selling what was bought, before performance fees
+    #[schema(value_type = String)]
+    pub realized_pnl: Decimal,
+    /// Realized profit performance fees have already been charged on
+    #[schema(value_type = String)]
+    pub high_water_mark: Decimal,
+    #[schema(value_type = String)]
+    pub fees_paid: Decimal,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateCopySubscriptionRequest {
+    pub lead_id: Uuid,
+    /// Only the lead's fills on pairs quoted in this currency are mirrored
+    pub budget_currency: String,
+    /// Most of the budget currency the subscription may have invested at once
+    #[schema(value_type = String)]
+    pub budget: Decimal,
+    /// Largest mirrored order, in the budget currency
+    #[schema(value_type = Option<String>)]
+    pub max_trade_notional: Option<Decimal>,
+    /// Stop mirroring once the subscription has lost this much
+    #[schema(value_type = Option<String>)]
+    pub stop_loss: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "copy_trade_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CopyTradeStatus {
+    Filled,
+    /// Too small to mirror or beyond a limit; `note` says which
+    Skipped,
+    Failed,
+}
+
+/// A lead fill and what a subscription did with it.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct CopyTrade {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub lead_trade_id: Uuid,
+    pub trading_pair_id: Uuid,
+    pub side: OrderSide,
+    #[schema(value_type = String)]
+    pub lead_quantity: Decimal,
+    /// Filled for the follower
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    #[schema(value_type = String)]
+    pub quote_quantity: Decimal,
+    pub order_id: Option<Uuid>,
+    pub status: CopyTradeStatus,
+    pub note: Option<String>,
+    #[schema(value_type = String)]
+    pub performance_fee: Decimal,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "ticket_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TicketStatus {
+    /// Waiting on support
+    Open,
+    /// Support replied; waiting on the user
+    Pending,
+    Resolved,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "ticket_category", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TicketCategory {
+    Account,
+    Deposit,
+    Withdrawal,
+    Trading,
+    Verification,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct SupportTicket {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub category: TicketCategory,
+    pub subject: String,
+    pub status: TicketStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TicketMessage {
+    pub id: Uuid,
+    pub ticket_id: Uuid,
+    pub author_id: Uuid,
+    /// Written by support rather than the ticket's user
+    pub from_staff: bool,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TicketAttachment {
+    pub id: Uuid,
+    pub ticket_id: Uuid,
+    pub uploaded_by: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    #[serde(skip)]
+    pub storage_key: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The account a ticket is about, shown to support next to the ticket.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TicketAccountContext {
+    pub user_id: Uuid,
+    pub email: String,
+    pub username: String,
+    pub account_status: AccountStatus,
+    pub kyc_status: Option<KycStatus>,
+    pub two_fa_enabled: Option<bool>,
+    pub country: Option<String>,
+    pub tenant_id: Option<Uuid>,
+    pub registered_at: Option<DateTime<Utc>>,
+    /// The user's other tickets not yet resolved
+    pub other_open_tickets: i64,
+}
+
+/// A ticket with its conversation; `account` is only filled in for support.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SupportTicketDetail {
+    pub ticket: SupportTicket,
+    pub messages: Vec<TicketMessage>,
+    pub attachments: Vec<TicketAttachment>,
+    pub account: Option<TicketAccountContext>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateTicketRequest {
+    pub category: TicketCategory,
+    #[validate(length(min = 3, max = 200))]
+    pub subject: String,
+    /// The first message of the conversation
+    #[validate(length(min = 1, max = 10000))]
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct TicketReplyRequest {
+    #[validate(length(min = 1, max = 10000))]
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TicketStatusRequest {
+    pub status: TicketStatus,
+}
+
+/// An order an alert webhook places, with the limits it is held to.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct AlertTemplate {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub trading_pair_id: Uuid,
+    /// `market` or `limit`; limit orders take their price from the alert
+    pub order_type: OrderType,
+    /// Fixed side, or null to follow the alert's `action`
+    pub side: Option<OrderSide>,
+    /// Ordered when the alert names no quantity
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    #[schema(value_type = String)]
+    pub max_quantity: Decimal,
+    /// Most an alert may order, in the quote currency
+    #[schema(value_type = Option<String>)]
+    pub max_notional: Option<Decimal>,
+    /// Alerts executed per UTC day
+    pub max_orders_per_day: i32,
+    #[serde(skip)]
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+    pub disabled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateAlertTemplateRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    pub trading_pair_id: Uuid,
+    pub order_type: OrderType,
+    pub side: Option<OrderSide>,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    /// Defaults to `quantity`
+    #[schema(value_type = Option<String>)]
+    pub max_quantity: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub max_notional: Option<Decimal>,
+    #[validate(range(min = 1, max = 1000))]
+    pub max_orders_per_day: i32,
+}
+
+/// A new template with its webhook secret, which is shown only here.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreatedAlertTemplate {
+    pub template: AlertTemplate,
+    /// Put in alerts as `passphrase`, or sign their bodies with it
+    pub secret: String,
+    /// Where alerts for this template are posted
+    pub webhook_path: String,
+}
+
+/// The body of an alert, as a TradingView alert message would be written
+/// with its placeholders, e.g.
+/// `{"passphrase": "...", "action": "{{strategy.order.action}}", "quantity": "{{strategy.order.contracts}}", "price": "{{close}}", "ticker": "{{ticker}}"}`.
+/// A body the template received in the past day is refused as a replay.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct TradingViewAlert {
+    /// The template's secret, for senders that cannot sign
+    #[serde(default, skip_serializing)]
+    pub passphrase: Option<String>,
+    /// `buy` or `sell`; must match a template with a fixed side
+    pub action: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub quantity: Option<Decimal>,
+    /// Limit price; market orders are valued at the last trade instead
+    #[schema(value_type = Option<String>)]
+    pub price: Option<Decimal>,
+    /// Checked against the template's pair when given, e.g. `BTCUSD`
+    pub ticker: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "alert_execution_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AlertExecutionStatus {
+    Executed,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct AlertExecution {
+    pub id: Uuid,
+    pub template_id: Uuid,
+    pub payload: serde_json::Value,
+    pub status: AlertExecutionStatus,
+    pub order_id: Option<Uuid>,
+    /// Why the alert placed no order
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "order_import_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OrderImportStatus {
+    Pending,
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "order_import_row_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OrderImportRowStatus {
+    Pending,
+    /// Passed validation in a dry run
+    Valid,
+    Placed,
+    Rejected,
+}
+
+/// A CSV of limit orders uploaded for placement, or only validation when
+/// `dry_run`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct OrderImport {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub dry_run: bool,
+    pub status: OrderImportStatus,
+    pub total_rows: i32,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct OrderImportRow {
+    /// Line in the file, counting the header as line 1
+    pub line: i32,
+    pub status: OrderImportRowStatus,
+    pub order_id: Option<Uuid>,
+    /// Why the row was rejected
+    pub reason: Option<String>,
+}
+
+/// An import with the outcome of every row so far.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderImportReport {
+    pub import: OrderImport,
+    pub pending: i64,
+    pub valid: i64,
+    pub placed: i64,
+    pub rejected: i64,
+    pub rows: Vec<OrderImportRow>,
+}
+
+/// The share of a user's portfolio value an asset should make up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct AllocationTarget {
+    pub currency: String,
+    /// Percent of the portfolio's value, e.g. 60 for 60%
+    #[schema(value_type = String)]
+    pub target_percent: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetAllocationTargetsRequest {
+    /// Replaces every target; they must sum to 100. An empty list clears
+    /// them.
+    pub targets: Vec<AllocationTarget>,
+}
+
+/// How far an asset has drifted from its target. Assets the oracle cannot
+/// price have no value and are left out of the total.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AllocationDrift {
+    pub currency: String,
+    #[schema(value_type = String)]
+    pub balance: Decimal,
+    /// In the reference currency
+    #[schema(value_type = Option<String>)]
+    pub price: Option<Decimal>,
+    #[schema(value_type = Option<String>)]
+    pub value: Option<Decimal>,
+    #[schema(value_type = String)]
+    pub current_percent: Decimal,
+    #[schema(value_type = String)]
+    pub target_percent: Decimal,
+    /// Current less target, in percentage points
+    #[schema(value_type = String)]
+    pub drift_percent: Decimal,
+}
+
+/// A market order proposed to move the portfolio towards its targets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RebalanceTrade {
+    pub trading_pair_id: Uuid,
+    pub symbol: String,
+    pub side: OrderSide,
+    #[schema(value_type = String)]
+    pub quantity: Decimal,
+    /// The oracle's price the trade was sized at, in the pair's quote
+    #[schema(value_type = String)]
+    pub estimated_price: Decimal,
+    /// What the trade moves, in the reference currency
+    #[schema(value_type = String)]
+    pub value: Decimal,
+}
+
+/// The portfolio's drift from its targets and the trades that would close
+/// it. The trades can be placed as proposed until `expires_at`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RebalancePlan {
+    pub id: Uuid,
+    pub reference_currency: String,
+    #[schema(value_type = String)]
+    pub total_value: Decimal,
+    pub assets: Vec<AllocationDrift>,
+    pub trades: Vec<RebalanceTrade>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RebalanceTradeResult {
+    pub trade: RebalanceTrade,
+    pub order_id: Option<Uuid>,
+    /// Why the order was not placed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RebalanceExecution {
+    pub plan_id: Uuid,
+    pub trades: Vec<RebalanceTradeResult>,
+}
@@ -0,0 +1,61 @@
+//! Reference prices for converting between currencies outside the order
+//! book, such as [`crate::services::DustService`] sweeping small balances
+//! into one asset. An oracle quotes how much of one currency a unit of
+//! another is worth.
+
+use crate::database::Database;
+use crate::Result;
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+
+#[async_trait]
+pub trait PriceOracle: Send + Sync {
+    /// The price of one `base` in `quote`, or `None` if there is none.
+    async fn price(&self, base: &str, quote: &str) -> Result<Option<Decimal>>;
+}
+
+/// Prices from the exchange's own last trades. A currency pair is quoted
+/// from the active trading pair in either direction, so USD is priced in
+/// BTC by inverting the last BTC-USD trade.
+pub struct LastTradePrices {
+    db: Database,
+}
+
+impl LastTradePrices {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl PriceOracle for LastTradePrices {
+    async fn price(&self, base: &str, quote: &str) -> Result<Option<Decimal>> {
+        if base == quote {
+            return Ok(Some(Decimal::ONE));
+        }
+
+        let last = sqlx::query_as::<_, (Decimal, bool)>(
+            r#"
+            SELECT t.price, tp.base_currency = $1
+            FROM trading_pairs tp
+            JOIN LATERAL (
+                SELECT price FROM trades WHERE trading_pair_id = tp.id AND busted_at IS NULL ORDER BY created_at DESC LIMIT 1
+            ) t ON true
+            WHERE tp.is_active = true
+              AND ((tp.base_currency = $1 AND tp.quote_currency = $2) OR (tp.base_currency = $2 AND tp.quote_currency = $1))
+            ORDER BY tp.base_currency = $1 DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(base)
+        .bind(quote)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(last.and_then(|(price, direct)| match direct {
+            true => Some(price),
+            false if price.is_zero() => None,
+            false => Some(Decimal::ONE / price),
+        }))
+    }
+}
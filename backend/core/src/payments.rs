@@ -0,0 +1,294 @@
+//! Payment providers that fund fiat balances. A provider opens a payment
+//! for a deposit and later reports, through a signed webhook, whether the
+//! money arrived; [`crate::services::PaymentService`] records the deposit
+//! and credits it once settled.
+
+use crate::config::{BankTransferConfig, CardProcessorConfig};
+use crate::error::CryptoTradeError;
+use crate::models::{BankInstructions, PaymentMethod};
+use crate::Result;
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rust_decimal::Decimal;
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// What the user needs to complete a payment.
+#[derive(Debug, Clone)]
+pub struct ProviderPayment {
+    /// The provider's id for the payment, quoted back in its webhooks
+    pub external_id: String,
+    pub client_secret: Option<String>,
+    pub bank_instructions: Option<BankInstructions>,
+}
+
+/// A payment's outcome, as reported by a webhook.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaymentEvent {
+    Settled {
+        external_id: String,
+        amount: Decimal,
+        currency: String,
+    },
+    Failed {
+        external_id: String,
+    },
+}
+
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    fn method(&self) -> PaymentMethod;
+
+    /// Opens a payment of `amount` for the deposit `payment_id`.
+    async fn create_payment(&self, payment_id: Uuid, amount: Decimal, currency: &str) -> Result<ProviderPayment>;
+
+    /// Checks a webhook's signature and reads the outcome it reports;
+    /// `None` for events that do not settle or fail a payment.
+    fn parse_webhook(&self, headers: &HeaderMap, body: &[u8]) -> Result<Option<PaymentEvent>>;
+}
+
+/// A card processor speaking Stripe's PaymentIntents API. Webhooks carry
+/// `Stripe-Signature: t=<unix seconds>,v1=<hex HMAC-SHA256 of "t.body">`.
+pub struct CardProcessor {
+    client: reqwest::Client,
+    config: CardProcessorConfig,
+}
+
+impl CardProcessor {
+    pub fn new(config: CardProcessorConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    fn verify_signature(&self, header: &str, body: &[u8], now: i64) -> Result<()> {
+        let mut timestamp = None;
+        let mut signatures = Vec::new();
+        for part in header.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+                Some(("v1", value)) => signatures.extend(hex::decode(value).ok()),
+                _ => {}
+            }
+        }
+        let timestamp = timestamp.ok_or(CryptoTradeError::InvalidSignature)?;
+        if (now - timestamp).abs() > self.config.webhook_tolerance_seconds {
+            return Err(CryptoTradeError::InvalidSignature);
+        }
+
+        let mac = hmac(&self.config.webhook_secret, &[timestamp.to_string().as_bytes(), b".", body]);
+        // Several signatures are sent while the endpoint secret is rolled
+        if signatures.iter().any(|signature| mac.clone().verify_slice(signature).is_ok()) {
+            Ok(())
+        } else {
+            Err(CryptoTradeError::InvalidSignature)
+        }
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for CardProcessor {
+    fn method(&self) -> PaymentMethod {
+        PaymentMethod::Card
+    }
+
+    async fn create_payment(&self, payment_id: Uuid, amount: Decimal, currency: &str) -> Result<ProviderPayment> {
+        let cents = (amount * Decimal::ONE_HUNDRED).trunc().to_string();
+        let currency = currency.to_lowercase();
+        let payment_id = payment_id.to_string();
+        let response = self
+            .client
+            .post(format!("{}/v1/payment_intents", self.config.api_base.trim_end_matches('/')))
+            .bearer_auth(&self.config.api_key)
+            // Retrying the same deposit never opens a second payment
+            .header("Idempotency-Key", payment_id.as_str())
+            .form(&[
+                ("amount", cents.as_str()),
+                ("currency", currency.as_str()),
+                ("metadata[payment_id]", payment_id.as_str()),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(provider_error)?;
+        let intent: serde_json::Value = response.json().await.map_err(provider_error)?;
+
+        let external_id = intent["id"].as_str().ok_or_else(|| CryptoTradeError::PaymentProvider {
+            message: "payment intent has no id".to_string(),
+        })?;
+        Ok(ProviderPayment {
+            external_id: external_id.to_string(),
+            client_secret: intent["client_secret"].as_str().map(str::to_string),
+            bank_instructions: None,
+        })
+    }
+
+    fn parse_webhook(&self, headers: &HeaderMap, body: &[u8]) -> Result<Option<PaymentEvent>> {
+        let signature = headers
+            .get("stripe-signature")
+            .and_then(|value| value.to_str().ok())
+            .ok_or(CryptoTradeError::InvalidSignature)?;
+        self.verify_signature(signature, body, Utc::now().timestamp())?;
+
+        let event: serde_json::Value = serde_json::from_slice(body)?;
+        let intent = &event["data"]["object"];
+        let Some(external_id) = intent["id"].as_str().map(str::to_string) else {
+            return Ok(None);
+        };
+        Ok(match event["type"].as_str() {
+            Some("payment_intent.succeeded") => Some(PaymentEvent::Settled {
+                external_id,
+                amount: Decimal::from(intent["amount_received"].as_i64().unwrap_or_default()) / Decimal::ONE_HUNDRED,
+                currency: intent["currency"].as_str().unwrap_or_default().to_uppercase(),
+            }),
+            Some("payment_intent.payment_failed" | "payment_intent.canceled") => {
+                Some(PaymentEvent::Failed { external_id })
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Transfers into the exchange's bank account. Each deposit gets a
+/// reference the user quotes on the transfer; the bank's notifications are
+/// JSON (`reference`, `amount`, `currency`, `status` of `settled` or
+/// `returned`) signed in `X-Bank-Signature` with the hex HMAC-SHA256 of the
+/// body.
+pub struct BankTransfer {
+    config: BankTransferConfig,
+}
+
+impl BankTransfer {
+    pub fn new(config: BankTransferConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for BankTransfer {
+    fn method(&self) -> PaymentMethod {
+        PaymentMethod::BankTransfer
+    }
+
+    async fn create_payment(&self, payment_id: Uuid, _amount: Decimal, _currency: &str) -> Result<ProviderPayment> {
+        // Short enough to type into a banking app
+        let reference = format!("CT{}", &payment_id.simple().to_string()[..12].to_uppercase());
+        Ok(ProviderPayment {
+            external_id: reference.clone(),
+            client_secret: None,
+            bank_instructions: Some(BankInstructions {
+                account_name: self.config.account_name.clone(),
+                account_number: self.config.account_number.clone(),
+                routing_number: self.config.routing_number.clone(),
+                reference,
+            }),
+        })
+    }
+
+    fn parse_webhook(&self, headers: &HeaderMap, body: &[u8]) -> Result<Option<PaymentEvent>> {
+        let signature = headers
+            .get("x-bank-signature")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| hex::decode(value).ok())
+            .ok_or(CryptoTradeError::InvalidSignature)?;
+        hmac(&self.config.webhook_secret, &[body])
+            .verify_slice(&signature)
+            .map_err(|_| CryptoTradeError::InvalidSignature)?;
+
+        let notification: serde_json::Value = serde_json::from_slice(body)?;
+        let Some(external_id) = notification["reference"].as_str().map(str::to_string) else {
+            return Ok(None);
+        };
+        Ok(match notification["status"].as_str() {
+            Some("settled") => Some(PaymentEvent::Settled {
+                external_id,
+                amount: notification["amount"]
+                    .as_str()
+                    .and_then(|amount| amount.parse().ok())
+                    .ok_or_else(|| CryptoTradeError::Validation {
+                        message: "Bank notification has no valid amount".to_string(),
+                    })?,
+                currency: notification["currency"].as_str().unwrap_or_default().to_uppercase(),
+            }),
+            Some("returned") => Some(PaymentEvent::Failed { external_id }),
+            _ => None,
+        })
+    }
+}
+
+/// The hex HMAC-SHA256 a bank notification is signed with.
+pub fn sign_bank_notification(secret: &str, body: &[u8]) -> String {
+    hex::encode(hmac(secret, &[body]).finalize().into_bytes())
+}
+
+fn hmac(secret: &str, parts: &[&[u8]]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac
+}
+
+fn provider_error(e: reqwest::Error) -> CryptoTradeError {
+    CryptoTradeError::PaymentProvider { message: e.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card() -> CardProcessor {
+        CardProcessor::new(CardProcessorConfig {
+            enabled: true,
+            api_base: "http://localhost".to_string(),
+            api_key: "sk_test".to_string(),
+            webhook_secret: "whsec_test".to_string(),
+            webhook_tolerance_seconds: 300,
+        })
+    }
+
+    fn card_signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+        let mac = hmac(secret, &[timestamp.to_string().as_bytes(), b".", body]);
+        format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_card_webhook_signature_must_match_and_be_recent() {
+        let body = br#"{"type":"payment_intent.succeeded"}"#;
+        let now = 1_700_000_000;
+
+        assert!(card().verify_signature(&card_signature("whsec_test", now, body), body, now).is_ok());
+        assert!(card().verify_signature(&card_signature("whsec_other", now, body), body, now).is_err());
+        assert!(card().verify_signature(&card_signature("whsec_test", now, body), b"{}", now).is_err());
+        assert!(card().verify_signature(&card_signature("whsec_test", now - 301, body), body, now).is_err());
+        // A rolled secret's signature alongside the current one
+        let rolled = format!(
+            "{},v1={}",
+            card_signature("whsec_test", now, body),
+            "00".repeat(32)
+        );
+        assert!(card().verify_signature(&rolled, body, now).is_ok());
+    }
+
+    #[test]
+    fn test_card_webhook_reports_settled_amount_in_currency_units() {
+        let body = br#"{"type":"payment_intent.succeeded","data":{"object":{"id":"pi_1","amount_received":12345,"currency":"usd"}}}"#;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "stripe-signature",
+            card_signature("whsec_test", Utc::now().timestamp(), body).parse().unwrap(),
+        );
+
+        assert_eq!(
+            card().parse_webhook(&headers, body).unwrap(),
+            Some(PaymentEvent::Settled {
+                external_id: "pi_1".to_string(),
+                amount: Decimal::new(12345, 2),
+                currency: "USD".to_string(),
+            })
+        );
+    }
+}
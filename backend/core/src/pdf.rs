@@ -0,0 +1,195 @@
+//! A small PDF writer for the documents users download: receipts, trade
+//! confirmations and statements. Documents are plain text set in Courier
+//! so columns padded with spaces line up; pages break automatically and
+//! carry the title and a page number.
+
+use std::fmt::Write as _;
+
+const PAGE_WIDTH: u32 = 595;
+const PAGE_HEIGHT: u32 = 842;
+const MARGIN: u32 = 50;
+const TITLE_SIZE: u32 = 14;
+const BODY_SIZE: u32 = 9;
+const LEADING: u32 = 12;
+/// Courier glyphs are 0.6 em wide, so this many fit between the margins
+const LINE_CHARS: usize = ((PAGE_WIDTH - 2 * MARGIN) * 10 / (BODY_SIZE * 6)) as usize;
+/// Body lines below the title and above the footer
+const PAGE_LINES: usize = ((PAGE_HEIGHT - 2 * MARGIN - 3 * LEADING) / LEADING) as usize;
+
+#[derive(Debug, Clone, Default)]
+pub struct PdfDocument {
+    title: String,
+    lines: Vec<String>,
+}
+
+impl PdfDocument {
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Adds a line, wrapping it when it is wider than the page.
+    pub fn line(&mut self, text: impl AsRef<str>) -> &mut Self {
+        let chars: Vec<char> = text.as_ref().chars().collect();
+        if chars.is_empty() {
+            self.lines.push(String::new());
+        }
+        for chunk in chars.chunks(LINE_CHARS) {
+            self.lines.push(chunk.iter().collect());
+        }
+        self
+    }
+
+    pub fn blank(&mut self) -> &mut Self {
+        self.line("")
+    }
+
+    /// A label and its value, aligned with the other fields.
+    pub fn field(&mut self, label: &str, value: impl std::fmt::Display) -> &mut Self {
+        self.line(format!("{:<22}{}", label, value))
+    }
+
+    pub fn render(&self) -> Vec<u8> {
+        let pages: Vec<&[String]> = if self.lines.is_empty() {
+            vec![&[]]
+        } else {
+            self.lines.chunks(PAGE_LINES).collect()
+        };
+
+        // Objects 1-4 are the catalog, page tree and fonts; each page then
+        // takes two, itself and its content stream
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                (0..pages.len()).map(|i| format!("{} 0 R", 5 + 2 * i)).collect::<Vec<_>>().join(" "),
+                pages.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_string(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_string(),
+        ];
+        for (index, lines) in pages.iter().enumerate() {
+            let content = self.page_content(lines, index + 1, pages.len());
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                6 + 2 * index
+            ));
+            objects.push(format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content));
+        }
+
+        let mut out = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", index + 1, object).as_bytes());
+        }
+        let xref = out.len();
+        let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(trailer, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            trailer,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        );
+        out.extend_from_slice(trailer.as_bytes());
+        out
+    }
+
+    fn page_content(&self, lines: &[String], page: usize, pages: usize) -> String {
+        let top = PAGE_HEIGHT - MARGIN;
+        let mut content = format!(
+            "BT /F1 {} Tf {} {} Td ({}) Tj ET\nBT /F2 {} Tf {} TL {} {} Td\n",
+            TITLE_SIZE,
+            MARGIN,
+            top,
+            escape(&self.title),
+            BODY_SIZE,
+            LEADING,
+            MARGIN,
+            top - 2 * LEADING
+        );
+        for line in lines {
+            let _ = writeln!(content, "({}) Tj T*", escape(line));
+        }
+        let _ = write!(
+            content,
+            "ET\nBT /F2 {} Tf {} {} Td (Page {} of {}) Tj ET",
+            BODY_SIZE,
+            MARGIN,
+            MARGIN - LEADING,
+            page,
+            pages
+        );
+        content
+    }
+}
+
+/// Escapes a PDF string literal. The standard fonts cover Latin-1 only, so
+/// anything outside printable ASCII is replaced.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object_offsets(pdf: &str) -> Vec<usize> {
+        let xref = pdf[pdf.rfind("startxref\n").unwrap() + 10..].lines().next().unwrap().parse::<usize>().unwrap();
+        pdf[xref..]
+            .lines()
+            .skip(3)
+            .take_while(|line| line.ends_with(" n "))
+            .map(|line| line[..10].parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_xref_points_at_every_object() {
+        let mut document = PdfDocument::new("Receipt");
+        document.field("Amount", "100.00 USD").line("Paid (in full) to C:\\bank");
+        let pdf = String::from_utf8(document.render()).unwrap();
+
+        assert!(pdf.starts_with("%PDF-1.4\n") && pdf.ends_with("%%EOF\n"));
+        let offsets = object_offsets(&pdf);
+        assert_eq!(offsets.len(), 6);
+        for (index, offset) in offsets.into_iter().enumerate() {
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", index + 1)));
+        }
+        assert!(pdf.contains("(Paid \\(in full\\) to C:\\\\bank) Tj"));
+    }
+
+    #[test]
+    fn test_long_documents_break_into_pages() {
+        let mut document = PdfDocument::new("Statement");
+        for i in 0..PAGE_LINES + 1 {
+            document.line(format!("entry {}", i));
+        }
+        document.line("x".repeat(LINE_CHARS + 1)).line("caf\u{e9}");
+        let pdf = String::from_utf8(document.render()).unwrap();
+
+        assert!(pdf.contains("/Count 2"));
+        assert!(pdf.contains("(Page 2 of 2) Tj"));
+        assert!(pdf.contains(&format!("({}) Tj", "x".repeat(LINE_CHARS))));
+        assert!(pdf.contains("(x) Tj") && pdf.contains("(caf?) Tj"));
+        assert_eq!(object_offsets(&pdf).len(), 8);
+    }
+}
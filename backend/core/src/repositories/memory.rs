@@ -0,0 +1,136 @@
+use super::*;
+use std::sync::Mutex;
+
+/// In-memory repositories for unit tests. Fill the collections directly;
+/// queries filter them the way the Postgres implementation's SQL does.
+#[derive(Default)]
+pub struct InMemoryRepository {
+    pub accounts: Mutex<Vec<Account>>,
+    pub orders: Mutex<Vec<Order>>,
+    pub trades: Mutex<Vec<Trade>>,
+    pub trading_pairs: Mutex<Vec<TradingPair>>,
+    pub currencies: Mutex<Vec<Currency>>,
+    pub withdrawals: Mutex<Vec<PendingWithdrawal>>,
+    pub portfolio_snapshots: Mutex<Vec<PortfolioSnapshot>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn is_open(order: &Order) -> bool {
+    match order.status {
+        Some(OrderStatus::Pending) | Some(OrderStatus::Open) | Some(OrderStatus::PartiallyFilled) => true,
+        Some(OrderStatus::Scheduled) => order.scheduled_lock.is_some(),
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl AccountRepository for InMemoryRepository {
+    async fn find_accounts(&self, user_id: Uuid) -> Result<Vec<Account>> {
+        Ok(lock(&self.accounts).iter().filter(|account| account.user_id == user_id).cloned().collect())
+    }
+
+    async fn find_account(&self, user_id: Uuid, currency: &str) -> Result<Option<Account>> {
+        Ok(lock(&self.accounts)
+            .iter()
+            .find(|account| account.user_id == user_id && account.currency == currency)
+            .cloned())
+    }
+
+    async fn find_pending_withdrawals(&self, user_id: Uuid, currency: &str) -> Result<Vec<PendingWithdrawal>> {
+        Ok(lock(&self.withdrawals)
+            .iter()
+            .filter(|withdrawal| withdrawal.user_id == user_id && withdrawal.currency == currency)
+            .cloned()
+            .collect())
+    }
+
+    async fn find_portfolio_snapshots(&self, user_id: Uuid, days: i32) -> Result<Vec<PortfolioSnapshot>> {
+        let since = Utc::now() - chrono::Duration::days(days as i64);
+        let mut snapshots: Vec<PortfolioSnapshot> = lock(&self.portfolio_snapshots)
+            .iter()
+            .filter(|snapshot| snapshot.user_id == user_id && snapshot.created_at.is_some_and(|at| at >= since))
+            .cloned()
+            .collect();
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+        Ok(snapshots)
+    }
+}
+
+#[async_trait]
+impl OrderRepository for InMemoryRepository {
+    async fn find_open_orders(&self, user_id: Uuid) -> Result<Vec<Order>> {
+        let mut orders: Vec<Order> = lock(&self.orders)
+            .iter()
+            .filter(|order| order.user_id == user_id && is_open(order))
+            .cloned()
+            .collect();
+        orders.sort_by_key(|order| order.created_at);
+        Ok(orders)
+    }
+}
+
+#[async_trait]
+impl TradeRepository for InMemoryRepository {
+    async fn count_user_trades(&self, user_id: Uuid) -> Result<i64> {
+        Ok(lock(&self.trades)
+            .iter()
+            .filter(|trade| trade.buyer_user_id == user_id || trade.seller_user_id == user_id)
+            .count() as i64)
+    }
+
+    async fn user_trade_totals(&self, user_id: Uuid, since: DateTime<Utc>) -> Result<TradeTotals> {
+        let mut totals = TradeTotals::default();
+        for trade in lock(&self.trades).iter().filter(|trade| {
+            (trade.buyer_user_id == user_id || trade.seller_user_id == user_id)
+                && trade.created_at.is_some_and(|at| at >= since)
+        }) {
+            let notional = trade.price.unwrap_or_default() * trade.quantity.unwrap_or_default();
+            if trade.buyer_user_id == user_id {
+                totals.buy_volume += notional;
+                totals.fees += trade.buyer_fee.unwrap_or_default();
+            } else {
+                totals.fees += trade.seller_fee.unwrap_or_default();
+            }
+            if trade.seller_user_id == user_id {
+                totals.sell_volume += notional;
+            }
+        }
+        Ok(totals)
+    }
+
+    async fn user_volume_by_pair(&self, user_id: Uuid, since: DateTime<Utc>) -> Result<Vec<(Uuid, Decimal)>> {
+        let mut volumes: Vec<(Uuid, Decimal)> = Vec::new();
+        for trade in lock(&self.trades).iter().filter(|trade| {
+            (trade.buyer_user_id == user_id || trade.seller_user_id == user_id)
+                && trade.created_at.is_some_and(|at| at >= since)
+        }) {
+            let notional = trade.price.unwrap_or_default() * trade.quantity.unwrap_or_default();
+            match volumes.iter_mut().find(|(pair_id, _)| *pair_id == trade.trading_pair_id) {
+                Some((_, volume)) => *volume += notional,
+                None => volumes.push((trade.trading_pair_id, notional)),
+            }
+        }
+        Ok(volumes)
+    }
+}
+
+#[async_trait]
+impl TradingPairRepository for InMemoryRepository {
+    async fn find_trading_pairs(&self) -> Result<Vec<TradingPair>> {
+        let mut pairs = lock(&self.trading_pairs).clone();
+        pairs.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        Ok(pairs)
+    }
+}
+
+#[async_trait]
+impl CurrencyRepository for InMemoryRepository {
+    async fn find_currencies(&self) -> Result<Vec<Currency>> {
+        let mut currencies = lock(&self.currencies).clone();
+        currencies.sort_by(|a, b| a.code.cmp(&b.code));
+        Ok(currencies)
+    }
+}
@@ -0,0 +1,73 @@
+pub mod memory;
+pub mod postgres;
+
+pub use memory::*;
+pub use postgres::*;
+
+use crate::{models::*, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// A withdrawal still holding funds in its account.
+#[derive(Debug, Clone)]
+pub struct PendingWithdrawal {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub currency: String,
+    /// Amount plus fee, as held
+    pub amount: Decimal,
+}
+
+/// A user's trade flow over a period, valued in each trade's quote currency.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TradeTotals {
+    pub buy_volume: Decimal,
+    pub sell_volume: Decimal,
+    pub fees: Decimal,
+}
+
+#[async_trait]
+pub trait AccountRepository: Send + Sync {
+    async fn find_accounts(&self, user_id: Uuid) -> Result<Vec<Account>>;
+    async fn find_account(&self, user_id: Uuid, currency: &str) -> Result<Option<Account>>;
+    async fn find_pending_withdrawals(&self, user_id: Uuid, currency: &str) -> Result<Vec<PendingWithdrawal>>;
+    async fn find_portfolio_snapshots(&self, user_id: Uuid, days: i32) -> Result<Vec<PortfolioSnapshot>>;
+}
+
+#[async_trait]
+pub trait OrderRepository: Send + Sync {
+    /// Orders that are pending, open or partially filled, or scheduled with
+    /// their funds held, oldest first.
+    async fn find_open_orders(&self, user_id: Uuid) -> Result<Vec<Order>>;
+}
+
+#[async_trait]
+pub trait TradeRepository: Send + Sync {
+    async fn count_user_trades(&self, user_id: Uuid) -> Result<i64>;
+    async fn user_trade_totals(&self, user_id: Uuid, since: DateTime<Utc>) -> Result<TradeTotals>;
+    /// Traded notional per trading pair since `since`; pairs without trades are omitted.
+    async fn user_volume_by_pair(&self, user_id: Uuid, since: DateTime<Utc>) -> Result<Vec<(Uuid, Decimal)>>;
+}
+
+#[async_trait]
+pub trait TradingPairRepository: Send + Sync {
+    async fn find_trading_pairs(&self) -> Result<Vec<TradingPair>>;
+}
+
+#[async_trait]
+pub trait CurrencyRepository: Send + Sync {
+    async fn find_currencies(&self) -> Result<Vec<Currency>>;
+}
+
+/// Everything a service may need; implemented by both backends.
+pub trait Repository:
+    AccountRepository + OrderRepository + TradeRepository + TradingPairRepository + CurrencyRepository
+{
+}
+
+impl<T> Repository for T where
+    T: AccountRepository + OrderRepository + TradeRepository + TradingPairRepository + CurrencyRepository
+{
+}
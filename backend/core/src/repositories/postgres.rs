@@ -0,0 +1,149 @@
+use super::*;
+use crate::database::Database;
+use sqlx::Row;
+
+/// The repositories backed by Postgres.
+#[derive(Clone)]
+pub struct PgRepository {
+    db: Database,
+}
+
+impl PgRepository {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl AccountRepository for PgRepository {
+    async fn find_accounts(&self, user_id: Uuid) -> Result<Vec<Account>> {
+        sqlx::query_as::<_, Account>("SELECT * FROM accounts WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn find_account(&self, user_id: Uuid, currency: &str) -> Result<Option<Account>> {
+        sqlx::query_as::<_, Account>("SELECT * FROM accounts WHERE user_id = $1 AND currency = $2")
+            .bind(user_id)
+            .bind(currency)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn find_pending_withdrawals(&self, user_id: Uuid, currency: &str) -> Result<Vec<PendingWithdrawal>> {
+        let rows = sqlx::query(
+            "SELECT id, amount + COALESCE(fee, 0) as amount FROM transactions WHERE user_id = $1 AND currency = $2 AND transaction_type = 'withdrawal' AND status IN ('pending', 'approved') ORDER BY created_at"
+        )
+        .bind(user_id)
+        .bind(currency)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| PendingWithdrawal {
+                id: row.get("id"),
+                user_id,
+                currency: currency.to_string(),
+                amount: row.get("amount"),
+            })
+            .collect())
+    }
+
+    async fn find_portfolio_snapshots(&self, user_id: Uuid, days: i32) -> Result<Vec<PortfolioSnapshot>> {
+        sqlx::query_as::<_, PortfolioSnapshot>(
+            "SELECT * FROM portfolio_snapshots WHERE user_id = $1 AND created_at >= NOW() - make_interval(days => $2) ORDER BY created_at DESC"
+        )
+        .bind(user_id)
+        .bind(days)
+        .fetch_all(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+}
+
+#[async_trait]
+impl OrderRepository for PgRepository {
+    async fn find_open_orders(&self, user_id: Uuid) -> Result<Vec<Order>> {
+        sqlx::query_as::<_, Order>(
+            "SELECT * FROM orders WHERE user_id = $1 AND (status IN ('pending', 'open', 'partially_filled') OR (status = 'scheduled' AND scheduled_lock IS NOT NULL)) ORDER BY created_at"
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+}
+
+#[async_trait]
+impl TradeRepository for PgRepository {
+    async fn count_user_trades(&self, user_id: Uuid) -> Result<i64> {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM trades WHERE buyer_user_id = $1 OR seller_user_id = $1")
+            .bind(user_id)
+            .fetch_one(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn user_trade_totals(&self, user_id: Uuid, since: DateTime<Utc>) -> Result<TradeTotals> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                COALESCE(SUM(CASE WHEN buyer_user_id = $1 THEN price * quantity ELSE 0 END), 0) as buy_volume,
+                COALESCE(SUM(CASE WHEN seller_user_id = $1 THEN price * quantity ELSE 0 END), 0) as sell_volume,
+                COALESCE(SUM(CASE WHEN buyer_user_id = $1 THEN buyer_fee ELSE seller_fee END), 0) as total_fees
+            FROM trades
+            WHERE (buyer_user_id = $1 OR seller_user_id = $1)
+              AND created_at >= $2
+            "#
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(TradeTotals {
+            buy_volume: row.get("buy_volume"),
+            sell_volume: row.get("sell_volume"),
+            fees: row.get("total_fees"),
+        })
+    }
+
+    async fn user_volume_by_pair(&self, user_id: Uuid, since: DateTime<Utc>) -> Result<Vec<(Uuid, Decimal)>> {
+        let rows = sqlx::query(
+            "SELECT trading_pair_id, SUM(price * quantity) as volume FROM trades WHERE (buyer_user_id = $1 OR seller_user_id = $1) AND created_at >= $2 GROUP BY trading_pair_id"
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("trading_pair_id"), row.get("volume")))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl CurrencyRepository for PgRepository {
+    async fn find_currencies(&self) -> Result<Vec<Currency>> {
+        sqlx::query_as::<_, Currency>("SELECT * FROM currencies ORDER BY code")
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+}
+
+#[async_trait]
+impl TradingPairRepository for PgRepository {
+    async fn find_trading_pairs(&self) -> Result<Vec<TradingPair>> {
+        sqlx::query_as::<_, TradingPair>("SELECT * FROM trading_pairs ORDER BY symbol")
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+}
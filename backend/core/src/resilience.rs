@@ -0,0 +1,325 @@
+//! Timeouts, retries and circuit breaking around calls to the services the
+//! exchange depends on but does not own, such as Redis and NATS.
+
+use crate::{config::ResilienceConfig, error::CryptoTradeError, Result};
+use rand::Rng;
+use std::{
+    fmt::Write,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Guards one dependency. Every call gets a timeout; a run of failed or
+/// timed out calls opens the breaker, which then fails calls at once with
+/// [`CryptoTradeError::DependencyUnavailable`] instead of letting each wait
+/// on a dependency that is down. Once `open_seconds` pass a single call is
+/// let through as a probe: success closes the breaker, failure opens it
+/// again. Clones share their state.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    dependency: &'static str,
+    config: ResilienceConfig,
+    inner: Arc<Mutex<Breaker>>,
+}
+
+struct Breaker {
+    state: BreakerState,
+    counters: Counters,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakerState {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// The probe is in flight
+    HalfOpen,
+}
+
+#[derive(Default)]
+struct Counters {
+    successes: u64,
+    failures: u64,
+    timeouts: u64,
+    rejections: u64,
+    retries: u64,
+    opened: u64,
+}
+
+/// Why an attempt did not succeed
+enum Failure {
+    /// The breaker refused it; retrying would be refused as well
+    Rejected,
+    Failed(CryptoTradeError),
+}
+
+impl CircuitBreaker {
+    pub fn new(dependency: &'static str, config: &ResilienceConfig) -> Self {
+        Self {
+            dependency,
+            config: config.clone(),
+            inner: Arc::new(Mutex::new(Breaker {
+                state: BreakerState::Closed { failures: 0 },
+                counters: Counters::default(),
+            })),
+        }
+    }
+
+    /// Whether calls are currently being refused.
+    pub fn is_open(&self) -> bool {
+        matches!(self.lock().state, BreakerState::Open { until } if Instant::now() < until)
+    }
+
+    /// Runs an idempotent operation, retrying failures up to `max_retries`
+    /// times with exponential backoff and full jitter.
+    pub async fn call<T, E, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = std::result::Result<T, E>>,
+        E: Into<CryptoTradeError>,
+    {
+        let mut retries = 0;
+        loop {
+            match self.attempt(&mut operation).await {
+                Ok(value) => return Ok(value),
+                Err(Failure::Failed(_)) if retries < self.config.max_retries => {
+                    retries += 1;
+                    self.lock().counters.retries += 1;
+                    tokio::time::sleep(self.backoff(retries)).await;
+                }
+                Err(Failure::Failed(e)) => return Err(e),
+                Err(Failure::Rejected) => return Err(self.unavailable()),
+            }
+        }
+    }
+
+    /// Runs an operation once, for writes that a retry could apply twice.
+    pub async fn call_once<T, E>(&self, operation: impl Future<Output = std::result::Result<T, E>>) -> Result<T>
+    where
+        E: Into<CryptoTradeError>,
+    {
+        match self.attempt(|| operation).await {
+            Ok(value) => Ok(value),
+            Err(Failure::Failed(e)) => Err(e),
+            Err(Failure::Rejected) => Err(self.unavailable()),
+        }
+    }
+
+    /// Starts the operation only once the breaker admits it.
+    async fn attempt<T, E, Fut>(&self, operation: impl FnOnce() -> Fut) -> std::result::Result<T, Failure>
+    where
+        Fut: Future<Output = std::result::Result<T, E>>,
+        E: Into<CryptoTradeError>,
+    {
+        if !self.admit() {
+            self.lock().counters.rejections += 1;
+            return Err(Failure::Rejected);
+        }
+
+        match tokio::time::timeout(Duration::from_millis(self.config.timeout_ms), operation()).await {
+            Ok(Ok(value)) => {
+                self.record(true);
+                Ok(value)
+            }
+            Ok(Err(e)) => {
+                self.record(false);
+                Err(Failure::Failed(e.into()))
+            }
+            Err(_) => {
+                self.lock().counters.timeouts += 1;
+                self.record(false);
+                Err(Failure::Failed(self.unavailable()))
+            }
+        }
+    }
+
+    fn admit(&self) -> bool {
+        let mut breaker = self.lock();
+        match breaker.state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                breaker.state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => false,
+        }
+    }
+
+    fn record(&self, succeeded: bool) {
+        let open_for = Duration::from_secs(self.config.open_seconds);
+        let mut breaker = self.lock();
+        if succeeded {
+            breaker.counters.successes += 1;
+            breaker.state = BreakerState::Closed { failures: 0 };
+            return;
+        }
+
+        breaker.counters.failures += 1;
+        let failures = match breaker.state {
+            BreakerState::Closed { failures } => failures + 1,
+            // A failed probe reopens at once
+            BreakerState::HalfOpen => self.config.failure_threshold,
+            // Already open through a call that started before it opened
+            BreakerState::Open { .. } => return,
+        };
+        if failures >= self.config.failure_threshold {
+            breaker.state = BreakerState::Open {
+                until: Instant::now() + open_for,
+            };
+            breaker.counters.opened += 1;
+            tracing::warn!("Circuit breaker for {} opened for {:?}", self.dependency, open_for);
+        } else {
+            breaker.state = BreakerState::Closed { failures };
+        }
+    }
+
+    /// The wait before the `retry`th retry: a random duration up to the
+    /// base delay doubled for each earlier retry.
+    fn backoff(&self, retry: u32) -> Duration {
+        let ceiling = self.config.retry_base_delay_ms.saturating_mul(1 << (retry - 1).min(16));
+        Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling))
+    }
+
+    fn unavailable(&self) -> CryptoTradeError {
+        CryptoTradeError::DependencyUnavailable {
+            dependency: self.dependency,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Breaker> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The state and call outcomes of `breakers` in Prometheus text exposition
+/// format.
+pub fn render_metrics(breakers: &[&CircuitBreaker]) -> String {
+    let snapshots: Vec<_> = breakers
+        .iter()
+        .map(|breaker| {
+            let inner = breaker.lock();
+            let state = match inner.state {
+                BreakerState::Closed { .. } => 0,
+                BreakerState::HalfOpen => 1,
+                BreakerState::Open { .. } => 2,
+            };
+            let counters = &inner.counters;
+            let outcomes = [
+                ("success", counters.successes),
+                ("failure", counters.failures - counters.timeouts),
+                ("timeout", counters.timeouts),
+                ("rejected", counters.rejections),
+            ];
+            (breaker.dependency, state, outcomes, counters.retries, counters.opened)
+        })
+        .collect();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# HELP dependency_circuit_state Circuit breaker state: 0 closed, 1 half open, 2 open");
+    let _ = writeln!(out, "# TYPE dependency_circuit_state gauge");
+    for (dependency, state, ..) in &snapshots {
+        let _ = writeln!(out, "dependency_circuit_state{{dependency=\"{}\"}} {}", dependency, state);
+    }
+    let _ = writeln!(out, "# HELP dependency_calls_total Calls to a dependency by outcome");
+    let _ = writeln!(out, "# TYPE dependency_calls_total counter");
+    for (dependency, _, outcomes, ..) in &snapshots {
+        for (outcome, count) in outcomes {
+            let _ = writeln!(out, "dependency_calls_total{{dependency=\"{}\",outcome=\"{}\"}} {}", dependency, outcome, count);
+        }
+    }
+    let _ = writeln!(out, "# HELP dependency_retries_total Retries of failed idempotent calls");
+    let _ = writeln!(out, "# TYPE dependency_retries_total counter");
+    for (dependency, _, _, retries, _) in &snapshots {
+        let _ = writeln!(out, "dependency_retries_total{{dependency=\"{}\"}} {}", dependency, retries);
+    }
+    let _ = writeln!(out, "# HELP dependency_circuit_opened_total Times the circuit breaker opened");
+    let _ = writeln!(out, "# TYPE dependency_circuit_opened_total counter");
+    for (dependency, _, _, _, opened) in &snapshots {
+        let _ = writeln!(out, "dependency_circuit_opened_total{{dependency=\"{}\"}} {}", dependency, opened);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn config(open_seconds: u64) -> ResilienceConfig {
+        ResilienceConfig {
+            timeout_ms: 50,
+            max_retries: 2,
+            retry_base_delay_ms: 1,
+            failure_threshold: 2,
+            open_seconds,
+        }
+    }
+
+    fn failing() -> std::result::Result<(), CryptoTradeError> {
+        Err(CryptoTradeError::Io(std::io::Error::other("connection refused")))
+    }
+
+    #[tokio::test]
+    async fn test_breaker_opens_after_repeated_failures_and_rejects_without_calling() {
+        let breaker = CircuitBreaker::new("redis", &config(60));
+        let calls = AtomicU32::new(0);
+        let operation = || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async { failing() }
+        };
+
+        assert!(matches!(breaker.call_once(operation()).await, Err(CryptoTradeError::Io(_))));
+        assert!(!breaker.is_open());
+        assert!(breaker.call_once(operation()).await.is_err());
+        assert!(breaker.is_open());
+
+        let rejected = breaker.call(operation).await;
+        assert!(matches!(rejected, Err(CryptoTradeError::DependencyUnavailable { dependency: "redis" })));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let metrics = render_metrics(&[&breaker]);
+        assert!(metrics.contains("dependency_circuit_state{dependency=\"redis\"} 2"));
+        assert!(metrics.contains("dependency_calls_total{dependency=\"redis\",outcome=\"rejected\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_after_the_open_period_closes_the_breaker() {
+        let breaker = CircuitBreaker::new("nats", &config(0));
+        for _ in 0..2 {
+            let _ = breaker.call_once(async { failing() }).await;
+        }
+
+        // The open period has passed, so the next call probes and succeeds
+        assert_eq!(breaker.call_once(async { Ok::<_, CryptoTradeError>(7) }).await.unwrap(), 7);
+        assert!(render_metrics(&[&breaker]).contains("dependency_circuit_state{dependency=\"nats\"} 0"));
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_calls_are_retried_and_slow_calls_time_out() {
+        let breaker = CircuitBreaker::new("redis", &ResilienceConfig { failure_threshold: 10, ..config(60) });
+        let calls = AtomicU32::new(0);
+        let flaky = breaker
+            .call(|| {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move { if call < 2 { failing().map(|_| 0) } else { Ok(call) } }
+            })
+            .await;
+        assert_eq!(flaky.unwrap(), 2);
+
+        let slow = breaker
+            .call_once(async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok::<_, CryptoTradeError>(())
+            })
+            .await;
+        assert!(matches!(slow, Err(CryptoTradeError::DependencyUnavailable { .. })));
+
+        let metrics = render_metrics(&[&breaker]);
+        assert!(metrics.contains("dependency_retries_total{dependency=\"redis\"} 2"));
+        assert!(metrics.contains("dependency_calls_total{dependency=\"redis\",outcome=\"timeout\"} 1"));
+    }
+}
@@ -0,0 +1,318 @@
+//! Secrets kept out of the environment. [`Config::load_secrets`] asks the
+//! configured provider for each name in [`SECRET_NAMES`] and overrides the
+//! matching setting; names the provider does not have keep their value from
+//! the environment. [`Config::validate`] refuses the built-in placeholder
+//! secrets in production.
+
+use crate::config::{Config, SecretsBackend, SecretsConfig, SignerBackend, DEFAULT_JWT_SECRET, DEFAULT_PRIVATE_KEY};
+use async_trait::async_trait;
+use chrono::Utc;
+use config::ConfigError;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+pub const JWT_SECRET: &str = "JWT_SECRET";
+pub const JWT_KEYS: &str = "JWT_KEYS";
+pub const BLOCKCHAIN_PRIVATE_KEY: &str = "BLOCKCHAIN_PRIVATE_KEY";
+pub const CARD_PROCESSOR_API_KEY: &str = "CARD_PROCESSOR_API_KEY";
+pub const CARD_WEBHOOK_SECRET: &str = "CARD_WEBHOOK_SECRET";
+pub const BANK_WEBHOOK_SECRET: &str = "BANK_WEBHOOK_SECRET";
+pub const DEPOSIT_WATCHER_SECRET: &str = "DEPOSIT_WATCHER_SECRET";
+
+/// Every secret the server reads
+pub const SECRET_NAMES: [&str; 7] = [
+    JWT_SECRET,
+    JWT_KEYS,
+    BLOCKCHAIN_PRIVATE_KEY,
+    CARD_PROCESSOR_API_KEY,
+    CARD_WEBHOOK_SECRET,
+    BANK_WEBHOOK_SECRET,
+    DEPOSIT_WATCHER_SECRET,
+];
+
+#[async_trait]
+pub trait SecretsProvider: Send + Sync {
+    /// The secret's value, or `None` if the provider does not hold it.
+    async fn get(&self, name: &str) -> Result<Option<String>, ConfigError>;
+}
+
+/// Builds the provider selected by `secrets.provider`.
+pub fn provider(config: &SecretsConfig) -> Result<Box<dyn SecretsProvider>, ConfigError> {
+    Ok(match config.provider {
+        SecretsBackend::Env => Box::new(EnvSecrets),
+        SecretsBackend::File => Box::new(FileSecrets {
+            dir: PathBuf::from(&config.dir),
+        }),
+        SecretsBackend::Vault => Box::new(VaultSecrets {
+            client: reqwest::Client::new(),
+            url: format!(
+                "{}/v1/{}/data/{}",
+                config.vault_addr.trim_end_matches('/'),
+                config.vault_mount,
+                config.vault_path
+            ),
+            token: required_env("VAULT_TOKEN")?,
+        }),
+        SecretsBackend::AwsSecretsManager => Box::new(AwsSecretsManager {
+            client: reqwest::Client::new(),
+            region: config.aws_region.clone(),
+            secret_id: config.aws_secret_id.clone(),
+            access_key_id: required_env("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: required_env("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        }),
+    })
+}
+
+impl Config {
+    /// Overrides secrets from the configured provider.
+    pub async fn load_secrets(&mut self) -> Result<(), ConfigError> {
+        let provider = provider(&self.secrets)?;
+        self.apply_secrets(provider.as_ref()).await
+    }
+
+    pub async fn apply_secrets(&mut self, provider: &dyn SecretsProvider) -> Result<(), ConfigError> {
+        for name in SECRET_NAMES {
+            let Some(value) = provider.get(name).await? else {
+                continue;
+            };
+            match name {
+                JWT_SECRET => self.jwt.secret = value,
+                JWT_KEYS => {
+                    self.jwt.keys = serde_json::from_str(&value)
+                        .map_err(|e| ConfigError::Message(format!("{} is not a valid key list: {}", JWT_KEYS, e)))?
+                }
+                CARD_PROCESSOR_API_KEY => self.payments.card.api_key = value,
+                CARD_WEBHOOK_SECRET => self.payments.card.webhook_secret = value,
+                BANK_WEBHOOK_SECRET => self.payments.bank_transfer.webhook_secret = value,
+                DEPOSIT_WATCHER_SECRET => self.blockchain.deposit_watcher_secret = value,
+                _ => self.blockchain.private_key = value,
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of the secrets still at their built-in placeholder.
+    pub fn default_secrets(&self) -> Vec<&'static str> {
+        let mut defaults = Vec::new();
+        if self.jwt.secret == DEFAULT_JWT_SECRET {
+            defaults.push(JWT_SECRET);
+        }
+        // Only the local signer uses the key
+        if self.blockchain.signer.backend == SignerBackend::Local && self.blockchain.private_key == DEFAULT_PRIVATE_KEY {
+            defaults.push(BLOCKCHAIN_PRIVATE_KEY);
+        }
+        defaults
+    }
+}
+
+/// Environment variables named after the secrets.
+pub struct EnvSecrets;
+
+#[async_trait]
+impl SecretsProvider for EnvSecrets {
+    async fn get(&self, name: &str) -> Result<Option<String>, ConfigError> {
+        Ok(std::env::var(name).ok())
+    }
+}
+
+/// One file per secret, named after it in lower case, as Docker and
+/// Kubernetes mount them (`/run/secrets/jwt_secret`).
+pub struct FileSecrets {
+    pub dir: PathBuf,
+}
+
+#[async_trait]
+impl SecretsProvider for FileSecrets {
+    async fn get(&self, name: &str) -> Result<Option<String>, ConfigError> {
+        let path = self.dir.join(name.to_lowercase());
+        match tokio::fs::read_to_string(&path).await {
+            Ok(value) => Ok(Some(value.trim_end().to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(ConfigError::Message(format!("cannot read {}: {}", path.display(), e))),
+        }
+    }
+}
+
+/// A HashiCorp Vault KV version 2 secret whose keys are the secret names,
+/// read with the token in `VAULT_TOKEN`.
+pub struct VaultSecrets {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+#[async_trait]
+impl SecretsProvider for VaultSecrets {
+    async fn get(&self, name: &str) -> Result<Option<String>, ConfigError> {
+        let response = self
+            .client
+            .get(&self.url)
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ConfigError::Message(format!("Vault request failed: {}", e)))?;
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| ConfigError::Message(format!("Vault returned an invalid response: {}", e)))?;
+
+        Ok(body["data"]["data"][name].as_str().map(str::to_string))
+    }
+}
+
+/// An AWS Secrets Manager secret whose value is a JSON object keyed by the
+/// secret names. Credentials come from the standard `AWS_*` variables.
+pub struct AwsSecretsManager {
+    client: reqwest::Client,
+    region: String,
+    secret_id: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+#[async_trait]
+impl SecretsProvider for AwsSecretsManager {
+    async fn get(&self, name: &str) -> Result<Option<String>, ConfigError> {
+        let host = format!("secretsmanager.{}.amazonaws.com", self.region);
+        let body = serde_json::json!({ "SecretId": self.secret_id }).to_string();
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.push(("x-amz-target", "secretsmanager.GetSecretValue".to_string()));
+        let authorization = sigv4_authorization(
+            "POST",
+            "/",
+            &self.access_key_id,
+            &self.secret_access_key,
+            &self.region,
+            "secretsmanager",
+            &amz_date,
+            &headers,
+            body.as_bytes(),
+        );
+
+        let mut request = self.client.post(format!("https://{}/", host)).body(body);
+        for (header, value) in headers.iter().filter(|(header, _)| *header != "host") {
+            request = request.header(*header, value);
+        }
+        let response = request
+            .header("authorization", authorization)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ConfigError::Message(format!("Secrets Manager request failed: {}", e)))?;
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| ConfigError::Message(format!("Secrets Manager returned an invalid response: {}", e)))?;
+
+        let secret: serde_json::Value = body["SecretString"]
+            .as_str()
+            .map(serde_json::from_str)
+            .transpose()
+            .map_err(|e| ConfigError::Message(format!("secret {} is not a JSON object: {}", self.secret_id, e)))?
+            .unwrap_or_default();
+        Ok(secret[name].as_str().map(str::to_string))
+    }
+}
+
+/// The `Authorization` header of an AWS Signature Version 4 request with no
+/// query string. `path` must already be URI-encoded. `headers` must be
+/// sorted by name and include `host` and `x-amz-date`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sigv4_authorization(
+    method: &str,
+    path: &str,
+    access_key_id: &str,
+    secret_access_key: &str,
+    region: &str,
+    service: &str,
+    amz_date: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> String {
+    let date = &amz_date[..8];
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method,
+        path,
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body))
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let signature = hmac(&signing_key(secret_access_key, date, region, service), string_to_sign.as_bytes());
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key_id,
+        scope,
+        signed_headers,
+        hex::encode(signature)
+    )
+}
+
+pub(crate) fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let date_key = hmac(format!("AWS4{}", secret_access_key).as_bytes(), date.as_bytes());
+    let region_key = hmac(&date_key, region.as_bytes());
+    let service_key = hmac(&region_key, service.as_bytes());
+    hmac(&service_key, b"aws4_request")
+}
+
+pub(crate) fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    Hmac::<Sha256>::new_from_slice(key)
+        .expect("HMAC accepts keys of any length")
+        .chain_update(data)
+        .finalize()
+        .into_bytes()
+        .to_vec()
+}
+
+fn required_env(name: &str) -> Result<String, ConfigError> {
+    std::env::var(name).map_err(|_| ConfigError::Message(format!("{} must be set for the secrets provider", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_key_matches_the_aws_example() {
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+
+        assert_eq!(hex::encode(key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+    }
+
+    #[tokio::test]
+    async fn test_file_secrets_override_config() {
+        let dir = std::env::temp_dir().join(format!("cryptotrade-secrets-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("jwt_secret"), "from-file\n").unwrap();
+
+        let mut config = Config::from_env().unwrap();
+        config.apply_secrets(&FileSecrets { dir: dir.clone() }).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(config.jwt.secret, "from-file");
+        assert_eq!(config.default_secrets(), vec![BLOCKCHAIN_PRIVATE_KEY]);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::{
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    oracle::{LastTradePrices, PriceOracle},
+    services::{user_service::account_status, OrderService},
+    Result,
+};
+use hmac::{Hmac, Mac};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Header carrying the hex HMAC-SHA256 of an alert's body, keyed with the
+/// template's secret
+pub const ALERT_SIGNATURE_HEADER: &str = "x-alert-signature";
+
+/// How long an alert's exact body is remembered, to turn away replays
+const REPLAY_WINDOW_HOURS: i32 = 24;
+
+/// Places orders from TradingView-style alert webhooks (see migration 051).
+/// Each alert names a template, which fixes the pair, order type and limits;
+/// the alert fills in the side, quantity and price. Alerts that pass the
+/// secret check are recorded whether or not they place an order, so users
+/// can see why one did not.
+#[derive(Clone)]
+pub struct AlertService {
+    db: Database,
+    order_service: OrderService,
+    oracle: Arc<dyn PriceOracle>,
+}
+
+impl AlertService {
+    /// Values market orders for `max_notional` at the exchange's last trade.
+    pub fn new(db: Database, order_service: OrderService) -> Self {
+        Self {
+            oracle: Arc::new(LastTradePrices::new(db.clone())),
+            db,
+            order_service,
+        }
+    }
+
+    pub async fn create_template(&self, user_id: Uuid, request: CreateAlertTemplateRequest) -> Result<CreatedAlertTemplate> {
+        request.validate().map_err(|e| CryptoTradeError::Validation {
+            message: e.to_string(),
+        })?;
+        let invalid = |message: &str| Err(CryptoTradeError::Validation {
+            message: message.to_string(),
+        });
+        if !matches!(request.order_type, OrderType::Market | OrderType::Limit) {
+            return invalid("Alerts can only place market or limit orders");
+        }
+        if request.quantity <= Decimal::ZERO {
+            return invalid("quantity must be positive");
+        }
+        let max_quantity = request.max_quantity.unwrap_or(request.quantity);
+        if max_quantity < request.quantity {
+            return invalid("max_quantity must be at least quantity");
+        }
+        if request.max_notional.is_some_and(|max| max <= Decimal::ZERO) {
+            return invalid("max_notional must be positive");
+        }
+        self.order_service.get_trading_pair(request.trading_pair_id).await?;
+
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+        let template = sqlx::query_as::<_, AlertTemplate>(
+            r#"
+            INSERT INTO alert_templates
+                (user_id, name, trading_pair_id, order_type, side, quantity, max_quantity, max_notional, max_orders_per_day, secret)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(request.name.trim())
+        .bind(request.trading_pair_id)
+        .bind(request.order_type)
+        .bind(request.side)
+        .bind(request.quantity)
+        .bind(max_quantity)
+        .bind(request.max_notional)
+        .bind(request.max_orders_per_day)
+        .bind(&secret)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => CryptoTradeError::Conflict {
+                message: "An alert template with this name already exists".to_string(),
+            },
+            _ => e.into(),
+        })?;
+
+        Ok(CreatedAlertTemplate {
+            webhook_path: format!("/api/v1/webhooks/alerts/{}", template.id),
+            template,
+            secret,
+        })
+    }
+
+    /// The user's templates in use, oldest first.
+    pub async fn list_templates(&self, user_id: Uuid) -> Result<Vec<AlertTemplate>> {
+        sqlx::query_as::<_, AlertTemplate>(
+            "SELECT * FROM alert_templates WHERE user_id = $1 AND disabled_at IS NULL ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Stops a template taking alerts. Its executions stay on record.
+    pub async fn disable_template(&self, user_id: Uuid, template_id: Uuid) -> Result<AlertTemplate> {
+        sqlx::query_as::<_, AlertTemplate>(
+            "UPDATE alert_templates SET disabled_at = NOW() WHERE id = $1 AND user_id = $2 AND disabled_at IS NULL RETURNING *",
+        )
+        .bind(template_id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(template_not_found)
+    }
+
+    /// The template's latest alerts, newest first.
+    pub async fn executions(&self, user_id: Uuid, template_id: Uuid) -> Result<Vec<AlertExecution>> {
+        sqlx::query_scalar::<_, Uuid>("SELECT id FROM alert_templates WHERE id = $1 AND user_id = $2")
+            .bind(template_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(template_not_found)?;
+        sqlx::query_as::<_, AlertExecution>(
+            "SELECT * FROM alert_executions WHERE template_id = $1 ORDER BY created_at DESC LIMIT 100",
+        )
+        .bind(template_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Checks an alert's signature or passphrase, then places the
+    /// template's order if the alert keeps within its limits. Alerts for one
+    /// template are handled one at a time, so the daily count holds, and a
+    /// body the template already received in the replay window is refused
+    /// without being recorded.
+    pub async fn handle_alert(&self, template_id: Uuid, body: &[u8], signature: Option<&str>) -> Result<AlertExecution> {
+        let mut tx = self.db.begin().await?;
+        let template = sqlx::query_as::<_, AlertTemplate>(
+            "SELECT * FROM alert_templates WHERE id = $1 AND disabled_at IS NULL FOR UPDATE",
+        )
+        .bind(template_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(template_not_found)?;
+
+        let mut payload: serde_json::Value = serde_json::from_slice(body).map_err(|_| CryptoTradeError::Validation {
+            message: "Alert body must be a JSON object".to_string(),
+        })?;
+        let alert: TradingViewAlert = serde_json::from_value(payload.clone()).map_err(|e| CryptoTradeError::Validation {
+            message: format!("Invalid alert: {}", e),
+        })?;
+        authenticate(&template.secret, body, signature, alert.passphrase.as_deref())?;
+        if let Some(fields) = payload.as_object_mut() {
+            fields.remove("passphrase");
+        }
+        let body_digest = Sha256::digest(body).to_vec();
+        let replayed = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM alert_executions
+                WHERE template_id = $1 AND body_digest = $2 AND created_at > NOW() - make_interval(hours => $3)
+            )
+            "#,
+        )
+        .bind(template.id)
+        .bind(&body_digest)
+        .bind(REPLAY_WINDOW_HOURS)
+        .fetch_one(&mut *tx)
+        .await?;
+        if replayed {
+            return Err(CryptoTradeError::Conflict {
+                message: "This alert was already received; vary repeated alerts, e.g. with {{timenow}}".to_string(),
+            });
+        }
+
+        let placed = match self.check_alert(&mut tx, &template, &alert).await {
+            Ok(request) => self.order_service.create_order(template.user_id, request).await,
+            Err(e) => Err(e),
+        };
+        let (status, order_id, reason) = match &placed {
+            Ok(order) => (AlertExecutionStatus::Executed, Some(order.id), None),
+            Err(e) => (AlertExecutionStatus::Rejected, None, Some(e.to_string())),
+        };
+        let execution = sqlx::query_as::<_, AlertExecution>(
+            r#"
+            INSERT INTO alert_executions (template_id, payload, status, order_id, reason, body_digest)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(template.id)
+        .bind(&payload)
+        .bind(status)
+        .bind(order_id)
+        .bind(&reason)
+        .bind(&body_digest)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        match placed {
+            Ok(order) => {
+                tracing::info!("Alert template {} placed order {}", template.id, order.id);
+                Ok(execution)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The order `alert` asks for, if it keeps within the template's limits.
+    async fn check_alert(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        template: &AlertTemplate,
+        alert: &TradingViewAlert,
+    ) -> Result<CreateOrderRequest> {
+        account_status(&self.db, template.user_id).await?.ensure_can_trade()?;
+        let pair = self.order_service.get_trading_pair(template.trading_pair_id).await?;
+        let invalid = |message: String| CryptoTradeError::Validation { message };
+
+        if let Some(ticker) = alert.ticker.as_deref() {
+            if !ticker_matches(ticker, &pair.symbol) {
+                return Err(invalid(format!("Alert is for {}, template trades {}", ticker, pair.symbol)));
+            }
+        }
+        let action = alert.action.as_deref().map(parse_action).transpose()?;
+        let side = match (template.side, action) {
+            (Some(side), Some(action)) if side != action => {
+                return Err(invalid(format!("Template only places {:?} orders", side).to_lowercase()));
+            }
+            (Some(side), _) | (None, Some(side)) => side,
+            (None, None) => return Err(invalid("Alert must give an action of buy or sell".to_string())),
+        };
+
+        let quantity = alert.quantity.unwrap_or(template.quantity);
+        if quantity <= Decimal::ZERO {
+            return Err(CryptoTradeError::InvalidQuantity);
+        }
+        if quantity > template.max_quantity {
+            return Err(CryptoTradeError::RiskLimitExceeded {
+                limit: "alert quantity".to_string(),
+                max: template.max_quantity,
+                current: quantity,
+            });
+        }
+        let price = match template.order_type {
+            OrderType::Limit => Some(alert.price.ok_or_else(|| invalid("Limit alerts must give a price".to_string()))?),
+            _ => None,
+        };
+        if let Some(max_notional) = template.max_notional {
+            // Market orders fill wherever the book is, whatever the alert
+            // says the price was
+            let valued_at = match price {
+                Some(price) => Some(price),
+                None => self.oracle.price(&pair.base_currency, &pair.quote_currency).await?,
+            };
+            let valued_at = valued_at.ok_or_else(|| invalid(format!("{} has no price to value the alert at", pair.symbol)))?;
+            if quantity * valued_at > max_notional {
+                return Err(CryptoTradeError::RiskLimitExceeded {
+                    limit: "alert notional".to_string(),
+                    max: max_notional,
+                    current: quantity * valued_at,
+                });
+            }
+        }
+        let executed_today = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM alert_executions
+            WHERE template_id = $1 AND status = 'executed' AND created_at >= date_trunc('day', NOW() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'
+            "#,
+        )
+        .bind(template.id)
+        .fetch_one(&mut **tx)
+        .await?;
+        if executed_today >= i64::from(template.max_orders_per_day) {
+            return Err(CryptoTradeError::RiskLimitExceeded {
+                limit: "alert orders per day".to_string(),
+                max: Decimal::from(template.max_orders_per_day),
+                current: Decimal::from(executed_today),
+            });
+        }
+
+        Ok(CreateOrderRequest {
+            trading_pair_id: pair.id,
+            order_type: template.order_type,
+            side,
+            quantity: quantity.to_f64().ok_or(CryptoTradeError::InvalidQuantity)?,
+            price,
+            time_in_force: None,
+            stop_price: None,
+            // Strategies may well repeat an order moments apart
+            allow_duplicate: true,
+            activate_at: None,
+            lock_funds: false,
+        })
+    }
+}
+
+/// Accepts a valid signature of the body or, failing one, the passphrase.
+fn authenticate(secret: &str, body: &[u8], signature: Option<&str>, passphrase: Option<&str>) -> Result<()> {
+    let mac = || Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    let valid = match (signature, passphrase) {
+        (Some(signature), _) => hex::decode(signature.trim())
+            .is_ok_and(|signature| mac().chain_update(body).verify_slice(&signature).is_ok()),
+        // Compared as MACs so the comparison takes the same time wherever
+        // the passphrase differs
+        (None, Some(passphrase)) => {
+            let expected = mac().chain_update(secret.as_bytes()).finalize().into_bytes();
+            mac().chain_update(passphrase.as_bytes()).verify_slice(&expected).is_ok()
+        }
+        (None, None) => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(CryptoTradeError::InvalidSignature)
+    }
+}
+
+fn parse_action(action: &str) -> Result<OrderSide> {
+    match action.trim().to_ascii_lowercase().as_str() {
+        "buy" | "long" => Ok(OrderSide::Buy),
+        "sell" | "short" => Ok(OrderSide::Sell),
+        other => Err(CryptoTradeError::Validation {
+            message: format!("Unknown alert action '{}'", other),
+        }),
+    }
+}
+
+/// Whether a chart ticker such as `BTCUSD` or `BINANCE:BTCUSD` names the
+/// pair `BTC-USD`.
+fn ticker_matches(ticker: &str, symbol: &str) -> bool {
+    let ticker = ticker.rsplit(':').next().unwrap_or(ticker);
+    let normalize = |value: &str| {
+        value
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_uppercase())
+            .collect::<String>()
+    };
+    normalize(ticker) == normalize(symbol)
+}
+
+fn template_not_found() -> CryptoTradeError {
+    CryptoTradeError::NotFound {
+        message: "Alert template not found".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alerts_authenticate_by_signature_or_passphrase() {
+        let body = br#"{"action": "buy"}"#;
+        let signature = hex::encode(
+            Hmac::<Sha256>::new_from_slice(b"secret").unwrap().chain_update(body).finalize().into_bytes(),
+        );
+
+        assert!(authenticate("secret", body, Some(&signature), None).is_ok());
+        assert!(authenticate("other", body, Some(&signature), None).is_err());
+        // A bad signature is not rescued by a good passphrase
+        assert!(authenticate("secret", body, Some("00"), Some("secret")).is_err());
+        assert!(authenticate("secret", body, None, Some("secret")).is_ok());
+        assert!(authenticate("secret", body, None, Some("secreT")).is_err());
+        assert!(authenticate("secret", body, None, None).is_err());
+    }
+
+    #[test]
+    fn test_chart_tickers_match_pair_symbols() {
+        assert!(ticker_matches("BTCUSD", "BTC-USD"));
+        assert!(ticker_matches("BINANCE:btcusd", "BTC-USD"));
+        assert!(!ticker_matches("ETHUSD", "BTC-USD"));
+        assert_eq!(parse_action(" Sell ").unwrap(), OrderSide::Sell);
+        assert!(parse_action("hold").is_err());
+    }
+}
@@ -0,0 +1,373 @@
+use crate::{
+    config::AlgoOrderConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    services::{user_service::account_status, OrderService},
+    Result,
+};
+use chrono::{Duration, Utc};
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+use uuid::Uuid;
+
+/// Decimals a slice's quantity is kept to, as in the orders table
+const QUANTITY_SCALE: u32 = 8;
+
+/// Works TWAP and POV parent orders. A background task calls
+/// [`AlgoOrderService::work_due`], which places the next slice of every
+/// parent that is due one as an ordinary child order: a limit IOC order at
+/// the parent's limit price, or a market order if it has none. Slices
+/// smaller than the pair's minimum order size are held back until they
+/// grow large enough.
+#[derive(Clone)]
+pub struct AlgoOrderService {
+    db: Database,
+    order_service: OrderService,
+    config: AlgoOrderConfig,
+}
+
+impl AlgoOrderService {
+    pub fn new(db: Database, order_service: OrderService, config: AlgoOrderConfig) -> Self {
+        Self {
+            db,
+            order_service,
+            config,
+        }
+    }
+
+    pub async fn create(&self, user_id: Uuid, request: CreateAlgoOrderRequest) -> Result<AlgoOrder> {
+        account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        let invalid = |message: String| Err(CryptoTradeError::Validation { message });
+        if request.quantity <= Decimal::ZERO {
+            return invalid("quantity must be positive".to_string());
+        }
+        if request.limit_price.is_some_and(|price| price <= Decimal::ZERO) {
+            return Err(CryptoTradeError::InvalidPrice);
+        }
+        if request.duration_seconds <= 0 || request.duration_seconds as u32 > self.config.max_duration_seconds {
+            return invalid(format!(
+                "duration_seconds must be between 1 and {}",
+                self.config.max_duration_seconds
+            ));
+        }
+        let (slice_count, participation_rate) = match request.strategy {
+            AlgoStrategy::Twap => match request.slices {
+                Some(slices) if slices > 0 && slices as u32 <= self.config.max_slices => (Some(slices), None),
+                _ => return invalid(format!("A TWAP order needs between 1 and {} slices", self.config.max_slices)),
+            },
+            AlgoStrategy::Pov => match request.participation_rate {
+                Some(rate) if rate > Decimal::ZERO && rate <= Decimal::ONE => (None, Some(rate)),
+                _ => return invalid("A POV order needs a participation_rate above 0 and at most 1".to_string()),
+            },
+        };
+
+        let active = sqlx::query_scalar::<_, Option<bool>>("SELECT is_active FROM trading_pairs WHERE id = $1")
+            .bind(request.trading_pair_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)?;
+        if active != Some(true) {
+            return invalid("Trading pair is not active".to_string());
+        }
+
+        // A TWAP order's first slice goes out at once; a POV order first
+        // waits for some market volume to participate in
+        let now = Utc::now();
+        let next_slice_at = match request.strategy {
+            AlgoStrategy::Twap => now,
+            AlgoStrategy::Pov => now + Duration::seconds(self.config.pov_interval_seconds.into()),
+        };
+        let algo_order = sqlx::query_as::<_, AlgoOrder>(
+            r#"
+            INSERT INTO algo_orders (user_id, trading_pair_id, side, strategy, quantity, limit_price, duration_seconds, slice_count, participation_rate, next_slice_at, volume_since, ends_at, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $11, $11)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(request.trading_pair_id)
+        .bind(request.side)
+        .bind(request.strategy)
+        .bind(request.quantity)
+        .bind(request.limit_price)
+        .bind(request.duration_seconds)
+        .bind(slice_count)
+        .bind(participation_rate)
+        .bind(next_slice_at)
+        .bind(now)
+        .bind(now + Duration::seconds(request.duration_seconds.into()))
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(algo_order)
+    }
+
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<AlgoOrder>> {
+        let algo_orders = sqlx::query_as::<_, AlgoOrder>(
+            "SELECT * FROM algo_orders WHERE user_id = $1 ORDER BY created_at DESC LIMIT 100",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(algo_orders)
+    }
+
+    pub async fn progress(&self, user_id: Uuid, algo_order_id: Uuid) -> Result<AlgoOrderProgress> {
+        let mut algo_order = sqlx::query_as::<_, AlgoOrder>("SELECT * FROM algo_orders WHERE id = $1 AND user_id = $2")
+            .bind(algo_order_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(not_found)?;
+        let children = sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE parent_order_id = $1 ORDER BY created_at")
+            .bind(algo_order_id)
+            .fetch_all(&self.db)
+            .await?;
+
+        let filled: Decimal = children.iter().filter_map(|order| order.filled_quantity).sum();
+        let notional: Decimal = children.iter().filter_map(|order| order.cumulative_quote_quantity).sum();
+        // Children may have filled since the parent was last worked
+        algo_order.executed_quantity = filled;
+        Ok(AlgoOrderProgress {
+            percent_complete: (algo_order.executed_quantity / algo_order.quantity * Decimal::ONE_HUNDRED).round_dp(2),
+            average_price: (filled > Decimal::ZERO).then(|| (notional / filled).round_dp(QUANTITY_SCALE)),
+            algo_order,
+            children,
+        })
+    }
+
+    /// Stops placing slices and cancels any child order still resting.
+    /// What has already been executed stays executed.
+    pub async fn cancel(&self, user_id: Uuid, algo_order_id: Uuid) -> Result<AlgoOrderProgress> {
+        // Waits for a slice being placed to finish
+        let mut tx = self.db.begin().await?;
+        let status = sqlx::query_scalar::<_, AlgoOrderStatus>(
+            "SELECT status FROM algo_orders WHERE id = $1 AND user_id = $2 FOR UPDATE",
+        )
+        .bind(algo_order_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(not_found)?;
+        if status != AlgoOrderStatus::Active {
+            return Err(CryptoTradeError::Validation {
+                message: "Algo order is no longer active".to_string(),
+            });
+        }
+        sqlx::query("UPDATE algo_orders SET status = 'cancelled', updated_at = NOW() WHERE id = $1")
+            .bind(algo_order_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        let resting = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM orders WHERE parent_order_id = $1 AND status IN ('open', 'partially_filled')",
+        )
+        .bind(algo_order_id)
+        .fetch_all(&self.db)
+        .await?;
+        for order_id in resting {
+            self.order_service.cancel_order(user_id, order_id).await?;
+        }
+        sqlx::query("UPDATE algo_orders SET executed_quantity = $2 WHERE id = $1")
+            .bind(algo_order_id)
+            .bind(self.executed(algo_order_id).await?)
+            .execute(&self.db)
+            .await?;
+
+        self.progress(user_id, algo_order_id).await
+    }
+
+    /// Places the next slice of every algo order due one and returns how
+    /// many slices were placed.
+    pub async fn work_due(&self) -> Result<u32> {
+        let due = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM algo_orders WHERE status = 'active' AND next_slice_at <= NOW() ORDER BY next_slice_at LIMIT 100",
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut placed = 0;
+        for algo_order_id in due {
+            if self.work(algo_order_id).await? {
+                placed += 1;
+            }
+        }
+        Ok(placed)
+    }
+
+    /// Works one algo order, holding its row locked so it is never worked
+    /// twice at once or cancelled halfway. Returns whether a slice was placed.
+    async fn work(&self, algo_order_id: Uuid) -> Result<bool> {
+        let mut tx = self.db.begin().await?;
+        let Some(algo_order) = sqlx::query_as::<_, AlgoOrder>(
+            "SELECT * FROM algo_orders WHERE id = $1 AND status = 'active' AND next_slice_at <= NOW() FOR UPDATE SKIP LOCKED",
+        )
+        .bind(algo_order_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(false);
+        };
+        let min_size = sqlx::query_scalar::<_, Decimal>("SELECT min_order_size FROM trading_pairs WHERE id = $1")
+            .bind(algo_order.trading_pair_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let now = Utc::now();
+        let remaining = algo_order.quantity - algo_order.executed_quantity;
+        let mut slices_sent = algo_order.slices_sent;
+        let mut volume_since = None;
+        let (slice, next_slice_at, last) = match algo_order.strategy {
+            AlgoStrategy::Twap => {
+                let slice_count = algo_order.slice_count.unwrap_or(1);
+                let interval = Duration::milliseconds(i64::from(algo_order.duration_seconds) * 1000 / i64::from(slice_count));
+                slices_sent += 1;
+                (
+                    twap_slice(remaining, slice_count - algo_order.slices_sent),
+                    algo_order.created_at + interval * slices_sent,
+                    slices_sent >= slice_count,
+                )
+            }
+            AlgoStrategy::Pov => {
+                let volume = sqlx::query_scalar::<_, Decimal>(
+                    "SELECT COALESCE(SUM(quantity), 0) FROM trades WHERE trading_pair_id = $1 AND created_at > (SELECT volume_since FROM algo_orders WHERE id = $2) AND created_at <= $3 AND buyer_user_id <> $4 AND seller_user_id <> $4",
+                )
+                .bind(algo_order.trading_pair_id)
+                .bind(algo_order.id)
+                .bind(now)
+                .bind(algo_order.user_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                let slice = pov_slice(volume, algo_order.participation_rate.unwrap_or(Decimal::ZERO), remaining);
+                // Volume too thin for a slice keeps counting towards the next
+                if slice >= min_size && !slice.is_zero() {
+                    volume_since = Some(now);
+                }
+                (
+                    slice,
+                    now + Duration::seconds(self.config.pov_interval_seconds.into()),
+                    now >= algo_order.ends_at,
+                )
+            }
+        };
+
+        let mut status = AlgoOrderStatus::Active;
+        let mut error = None;
+        let mut placed = false;
+        if slice >= min_size && !slice.is_zero() {
+            match self.place_slice(&algo_order, slice).await {
+                Ok(()) => placed = true,
+                Err(e) => {
+                    tracing::warn!("Algo order {} failed placing a slice: {}", algo_order.id, e);
+                    status = AlgoOrderStatus::Failed;
+                    error = Some(e.to_string());
+                }
+            }
+        }
+
+        let executed = self.executed(algo_order.id).await?;
+        if status == AlgoOrderStatus::Active && (executed >= algo_order.quantity || last) {
+            status = AlgoOrderStatus::Completed;
+        }
+        sqlx::query(
+            "UPDATE algo_orders SET executed_quantity = $2, slices_sent = $3, status = $4, error = $5, next_slice_at = $6, volume_since = COALESCE($7, volume_since), updated_at = NOW() WHERE id = $1",
+        )
+        .bind(algo_order.id)
+        .bind(executed)
+        .bind(slices_sent)
+        .bind(status)
+        .bind(error)
+        .bind(next_slice_at)
+        .bind(volume_since)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(placed)
+    }
+
+    async fn place_slice(&self, algo_order: &AlgoOrder, quantity: Decimal) -> Result<()> {
+        let request = CreateOrderRequest {
+            trading_pair_id: algo_order.trading_pair_id,
+            order_type: if algo_order.limit_price.is_some() { OrderType::Limit } else { OrderType::Market },
+            side: algo_order.side,
+            quantity: quantity.to_f64().ok_or(CryptoTradeError::InvalidQuantity)?,
+            price: algo_order.limit_price,
+            // A slice never rests; what it cannot fill now rolls into the next
+            time_in_force: algo_order.limit_price.map(|_| TimeInForce::IOC),
+            stop_price: None,
+            allow_duplicate: true,
+            activate_at: None,
+            lock_funds: false,
+        };
+        let order = self.order_service.create_order(algo_order.user_id, request).await?;
+        sqlx::query("UPDATE orders SET parent_order_id = $1, version = version + 1 WHERE id = $2")
+            .bind(algo_order.id)
+            .bind(order.id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn executed(&self, algo_order_id: Uuid) -> Result<Decimal> {
+        let executed = sqlx::query_scalar::<_, Decimal>(
+            "SELECT COALESCE(SUM(filled_quantity), 0) FROM orders WHERE parent_order_id = $1",
+        )
+        .bind(algo_order_id)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(executed)
+    }
+}
+
+fn not_found() -> CryptoTradeError {
+    CryptoTradeError::NotFound {
+        message: "Algo order not found".to_string(),
+    }
+}
+
+/// An equal share of what is left over the slices left; the last slice
+/// takes everything, so rounding never leaves a remainder.
+fn twap_slice(remaining: Decimal, slices_left: i32) -> Decimal {
+    if slices_left <= 1 {
+        return remaining;
+    }
+    (remaining / Decimal::from(slices_left)).round_dp_with_strategy(QUANTITY_SCALE, RoundingStrategy::ToZero)
+}
+
+/// `rate` of the market volume, but never more than is left.
+fn pov_slice(volume: Decimal, rate: Decimal, remaining: Decimal) -> Decimal {
+    (volume * rate)
+        .round_dp_with_strategy(QUANTITY_SCALE, RoundingStrategy::ToZero)
+        .min(remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_twap_slices_add_up_to_the_quantity() {
+        let mut remaining = Decimal::ONE;
+        let mut slices = Vec::new();
+        for slices_left in (1..=3).rev() {
+            let slice = twap_slice(remaining, slices_left);
+            remaining -= slice;
+            slices.push(slice);
+        }
+        assert_eq!(slices[0], Decimal::new(33333333, 8));
+        assert_eq!(slices[2], Decimal::new(33333334, 8));
+        assert_eq!(remaining, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_pov_slice_follows_volume_up_to_what_is_left() {
+        assert_eq!(pov_slice(Decimal::from(5), Decimal::new(1, 1), Decimal::ONE), Decimal::new(5, 1));
+        assert_eq!(pov_slice(Decimal::from(50), Decimal::new(1, 1), Decimal::ONE), Decimal::ONE);
+        assert_eq!(pov_slice(Decimal::ZERO, Decimal::new(1, 1), Decimal::ONE), Decimal::ZERO);
+    }
+}
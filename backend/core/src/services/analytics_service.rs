@@ -0,0 +1,212 @@
+use crate::{database::Database, error::CryptoTradeError, models::*, Result};
+use chrono::{Days, NaiveDate, NaiveTime, Utc};
+
+/// Longest range one request may read or roll up
+const MAX_RANGE_DAYS: u64 = 366;
+/// Dashboards show this many days unless asked otherwise
+const DEFAULT_RANGE_DAYS: u64 = 30;
+
+/// Admin dashboard figures. Each UTC day is summarised into rollup tables
+/// by [`AnalyticsService::rollup`], which a background task reruns for the
+/// current and previous day; the dashboards only ever read the rollups.
+#[derive(Clone)]
+pub struct AnalyticsService {
+    db: Database,
+}
+
+impl AnalyticsService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Recomputes the rollups of every day from `from` to `to` inclusive and
+    /// returns how many days were rolled up. Safe to rerun; each day's rows
+    /// are replaced.
+    pub async fn rollup(&self, from: NaiveDate, to: NaiveDate) -> Result<u32> {
+        let (from, to) = range(Some(from), Some(to))?;
+        let mut days = 0;
+        for day in from.iter_days().take_while(|day| *day <= to) {
+            self.rollup_day(day).await?;
+            days += 1;
+        }
+        Ok(days)
+    }
+
+    async fn rollup_day(&self, day: NaiveDate) -> Result<()> {
+        let start = day.and_time(NaiveTime::MIN).and_utc();
+        let end = start + chrono::Duration::days(1);
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO analytics_daily (day, active_traders, new_registrations, updated_at)
+            SELECT $1,
+                (SELECT COUNT(*) FROM (
+                    SELECT user_id FROM orders WHERE created_at >= $2 AND created_at < $3
+                    UNION SELECT buyer_user_id FROM trades WHERE created_at >= $2 AND created_at < $3
+                    UNION SELECT seller_user_id FROM trades WHERE created_at >= $2 AND created_at < $3
+                ) traders),
+                (SELECT COUNT(*) FROM users WHERE created_at >= $2 AND created_at < $3),
+                NOW()
+            ON CONFLICT (day) DO UPDATE SET
+                active_traders = EXCLUDED.active_traders,
+                new_registrations = EXCLUDED.new_registrations,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(day)
+        .bind(start)
+        .bind(end)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM analytics_daily_pairs WHERE day = $1")
+            .bind(day)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO analytics_daily_pairs (day, trading_pair_id, trade_count, base_volume, quote_volume)
+            SELECT $1, trading_pair_id, COUNT(*), SUM(quantity), SUM(price * quantity)
+            FROM trades
+            WHERE created_at >= $2 AND created_at < $3
+            GROUP BY trading_pair_id
+            "#,
+        )
+        .bind(day)
+        .bind(start)
+        .bind(end)
+        .execute(&mut *tx)
+        .await?;
+
+        // Fees are credited to the exchange's own entries, and treasury
+        // sweeps debit them, so only credits count; deposits and withdrawals
+        // are read from the users' side
+        sqlx::query("DELETE FROM analytics_daily_currencies WHERE day = $1")
+            .bind(day)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO analytics_daily_currencies (day, currency, fee_revenue, deposits, deposit_count, withdrawals, withdrawal_count)
+            SELECT $1, currency,
+                COALESCE(SUM(amount) FILTER (WHERE user_id IS NULL AND entry_type = 'fee' AND amount > 0), 0),
+                COALESCE(SUM(amount) FILTER (WHERE user_id IS NOT NULL AND entry_type = 'deposit'), 0),
+                COUNT(*) FILTER (WHERE user_id IS NOT NULL AND entry_type = 'deposit'),
+                COALESCE(-SUM(amount) FILTER (WHERE user_id IS NOT NULL AND entry_type = 'withdrawal'), 0),
+                COUNT(*) FILTER (WHERE user_id IS NOT NULL AND entry_type = 'withdrawal')
+            FROM ledger_entries
+            WHERE created_at >= $2 AND created_at < $3 AND entry_type IN ('fee', 'deposit', 'withdrawal')
+            GROUP BY currency
+            "#,
+        )
+        .bind(day)
+        .bind(start)
+        .bind(end)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn activity(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<DailyActivity>> {
+        let (from, to) = range(from, to)?;
+        let activity = sqlx::query_as::<_, DailyActivity>(
+            "SELECT day, active_traders, new_registrations FROM analytics_daily WHERE day BETWEEN $1 AND $2 ORDER BY day",
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(activity)
+    }
+
+    pub async fn pair_volumes(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<DailyPairVolume>> {
+        let (from, to) = range(from, to)?;
+        let volumes = sqlx::query_as::<_, DailyPairVolume>(
+            r#"
+            SELECT r.day, r.trading_pair_id, tp.symbol, r.trade_count, r.base_volume, r.quote_volume
+            FROM analytics_daily_pairs r
+            JOIN trading_pairs tp ON tp.id = r.trading_pair_id
+            WHERE r.day BETWEEN $1 AND $2
+            ORDER BY r.day, tp.symbol
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(volumes)
+    }
+
+    pub async fn fee_revenue(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<DailyFeeRevenue>> {
+        let (from, to) = range(from, to)?;
+        let revenue = sqlx::query_as::<_, DailyFeeRevenue>(
+            "SELECT day, currency, fee_revenue FROM analytics_daily_currencies WHERE day BETWEEN $1 AND $2 AND fee_revenue <> 0 ORDER BY day, currency",
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(revenue)
+    }
+
+    pub async fn flows(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<DailyFlow>> {
+        let (from, to) = range(from, to)?;
+        let flows = sqlx::query_as::<_, DailyFlow>(
+            r#"
+            SELECT day, currency, deposits, deposit_count, withdrawals, withdrawal_count, deposits - withdrawals AS net_flow
+            FROM analytics_daily_currencies
+            WHERE day BETWEEN $1 AND $2 AND (deposit_count > 0 OR withdrawal_count > 0)
+            ORDER BY day, currency
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(flows)
+    }
+}
+
+/// The inclusive range a request covers: by default the last
+/// `DEFAULT_RANGE_DAYS` days up to today.
+fn range(from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<(NaiveDate, NaiveDate)> {
+    let to = to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = from.unwrap_or_else(|| to - Days::new(DEFAULT_RANGE_DAYS - 1));
+    if from > to {
+        return Err(CryptoTradeError::Validation {
+            message: "from must not be after to".to_string(),
+        });
+    }
+    if to - Days::new(MAX_RANGE_DAYS - 1) > from {
+        return Err(CryptoTradeError::Validation {
+            message: format!("At most {} days can be requested at once", MAX_RANGE_DAYS),
+        });
+    }
+    Ok((from, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_range_defaults_and_limits() {
+        let (from, to) = range(None, Some(date("2024-03-31"))).unwrap();
+        assert_eq!((from, to), (date("2024-03-02"), date("2024-03-31")));
+
+        assert!(range(Some(date("2024-01-01")), Some(date("2024-12-31"))).is_ok());
+        assert!(range(Some(date("2024-01-01")), Some(date("2025-01-01"))).is_err());
+        assert!(range(Some(date("2024-02-01")), Some(date("2024-01-31"))).is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use crate::{
+    auth::AuthService, config::ApiKeyConfig, database::Database, error::CryptoTradeError, models::*,
+    resilience::CircuitBreaker, services::tenant_service::tenant_of, Result,
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use ipnet::IpNet;
+use redis::aio::ConnectionManager;
+use sha2::Sha256;
+use std::net::IpAddr;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Prefix of every public key, so keys are recognisable in logs and configs
+const KEY_PREFIX: &str = "ctk_";
+
+/// Nonces shorter than this are too easy to repeat by accident
+const MIN_NONCE_LEN: usize = 8;
+const MAX_NONCE_LEN: usize = 64;
+
+/// API keys let programs act for a user without their password or a
+/// session. Each key carries its own permissions and may be tied to a set
+/// of trading pairs; it can never do more than its owner. A key can also be
+/// bound to an IP allowlist, which only changes with a 2FA code so a stolen
+/// session cannot open a key up to the attacker's network.
+///
+/// The secret is returned once, at creation, and never travels again:
+/// requests carry an HMAC-SHA256 signature over [`signature_payload`],
+/// with a timestamp that must fall within the receive window and a nonce
+/// that Redis remembers, so a captured request cannot be replayed.
+#[derive(Clone)]
+pub struct ApiKeyService {
+    db: Database,
+    redis: ConnectionManager,
+    breaker: CircuitBreaker,
+    auth_service: AuthService,
+    config: ApiKeyConfig,
+}
+
+/// The signing headers of a request, with what they sign.
+pub struct SignedRequest<'a> {
+    pub key: &'a str,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: &'a str,
+    pub nonce: &'a str,
+    /// Hex-encoded HMAC-SHA256 of the payload
+    pub signature: &'a str,
+    pub method: &'a str,
+    /// Path and query string
+    pub path: &'a str,
+    pub body: &'a [u8],
+}
+
+impl ApiKeyService {
+    pub fn new(db: Database, redis: ConnectionManager, breaker: CircuitBreaker, auth_service: AuthService, config: ApiKeyConfig) -> Self {
+        Self {
+            db,
+            redis,
+            breaker,
+            auth_service,
+            config,
+        }
+    }
+
+    pub async fn create_key(&self, user_id: Uuid, request: CreateApiKeyRequest) -> Result<CreatedApiKey> {
+        request.validate().map_err(|e| CryptoTradeError::Validation {
+            message: e.to_string(),
+        })?;
+
+        let ip_allowlist = request.ip_allowlist.as_deref().map(parse_allowlist).transpose()?;
+
+        let tenant = tenant_of(&self.db, user_id).await?;
+        let key = format!("{}{}", key_namespace(tenant.as_ref()), hex::encode(rand::random::<[u8; 16]>()));
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+
+        let api_key = sqlx::query_as::<_, ApiKey>(
+            "INSERT INTO api_keys (id, user_id, label, key, secret, permissions, trading_pair_ids, ip_allowlist) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(&request.label)
+        .bind(&key)
+        .bind(&secret)
+        .bind(&request.permissions)
+        .bind(&request.trading_pair_ids)
+        .bind(&ip_allowlist)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(CreatedApiKey { api_key, secret })
+    }
+
+    /// The user's keys, revoked ones included, newest first.
+    pub async fn list_keys(&self, user_id: Uuid) -> Result<Vec<ApiKey>> {
+        sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn revoke_key(&self, user_id: Uuid, key_id: Uuid) -> Result<ApiKey> {
+        sqlx::query_as::<_, ApiKey>(
+            "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, $1) WHERE id = $2 AND user_id = $3 RETURNING *",
+        )
+        .bind(Utc::now())
+        .bind(key_id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(CryptoTradeError::NotFound {
+            message: "API key not found".to_string(),
+        })
+    }
+
+    /// Replaces the key's IP allowlist. The user must have 2FA enabled and
+    /// send a current code.
+    pub async fn update_ip_allowlist(&self, user_id: Uuid, key_id: Uuid, request: UpdateIpAllowlistRequest) -> Result<ApiKey> {
+        let ip_allowlist = request.ip_allowlist.as_deref().map(parse_allowlist).transpose()?;
+
+        let (two_fa_enabled, two_fa_secret) = sqlx::query_as::<_, (Option<bool>, Option<String>)>(
+            "SELECT two_fa_enabled, two_fa_secret FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(CryptoTradeError::UserNotFound)?;
+        let secret = match two_fa_secret {
+            Some(secret) if two_fa_enabled.unwrap_or(false) => secret,
+            _ => return Err(CryptoTradeError::TwoFactorRequired),
+        };
+        if !self.auth_service.verify_2fa_code(&secret, &request.totp_code)? {
+            return Err(CryptoTradeError::Authentication {
+                message: "Invalid 2FA code".to_string(),
+            });
+        }
+
+        sqlx::query_as::<_, ApiKey>(
+            "UPDATE api_keys SET ip_allowlist = $1 WHERE id = $2 AND user_id = $3 AND revoked_at IS NULL RETURNING *",
+        )
+        .bind(&ip_allowlist)
+        .bind(key_id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(CryptoTradeError::NotFound {
+            message: "API key not found".to_string(),
+        })
+    }
+
+    /// Verifies a signed request and resolves it to the key and its owner.
+    /// Unknown, revoked and inactive-owner keys fail like a bad signature;
+    /// the nonce is only spent once the signature checks out.
+    pub async fn authenticate(&self, request: &SignedRequest<'_>) -> Result<(ApiKey, User)> {
+        let timestamp: i64 = request.timestamp.parse().map_err(|_| CryptoTradeError::InvalidSignature)?;
+        let recv_window_ms = self.config.recv_window_ms;
+        if (Utc::now().timestamp_millis() - timestamp).unsigned_abs() > recv_window_ms {
+            return Err(CryptoTradeError::StaleRequest { recv_window_ms });
+        }
+        if !(MIN_NONCE_LEN..=MAX_NONCE_LEN).contains(&request.nonce.len()) {
+            return Err(CryptoTradeError::Validation {
+                message: format!("Nonce must be {} to {} characters", MIN_NONCE_LEN, MAX_NONCE_LEN),
+            });
+        }
+
+        let api_key = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE key = $1 AND revoked_at IS NULL")
+            .bind(request.key)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::InvalidSignature)?;
+        let secret = api_key.secret.as_deref().ok_or(CryptoTradeError::InvalidSignature)?;
+        let signature = hex::decode(request.signature).map_err(|_| CryptoTradeError::InvalidSignature)?;
+        let payload = signature_payload(request.timestamp, request.nonce, request.method, request.path, request.body);
+        // verify_slice compares in constant time
+        hmac_for(secret)
+            .chain_update(&payload)
+            .verify_slice(&signature)
+            .map_err(|_| CryptoTradeError::InvalidSignature)?;
+
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+            .bind(api_key.user_id)
+            .fetch_one(&self.db)
+            .await?;
+        if !user.is_active.unwrap_or(false) {
+            return Err(CryptoTradeError::InvalidSignature);
+        }
+        let tenant = tenant_of(&self.db, user.id).await?;
+        if !in_namespace(&api_key.key, tenant.as_ref()) {
+            return Err(CryptoTradeError::InvalidSignature);
+        }
+
+        self.spend_nonce(&api_key, request.nonce).await?;
+
+        sqlx::query("UPDATE api_keys SET last_used_at = $1 WHERE id = $2")
+            .bind(Utc::now())
+            .bind(api_key.id)
+            .execute(&self.db)
+            .await?;
+
+        Ok((api_key, user))
+    }
+
+    /// Records the nonce for as long as a request carrying it could still
+    /// be within the window, failing if it was already recorded. Signed
+    /// requests are refused while Redis is unavailable, since a replay
+    /// could not be told apart, and the write is never retried: a retry of
+    /// one that landed would find its own nonce.
+    async fn spend_nonce(&self, api_key: &ApiKey, nonce: &str) -> Result<()> {
+        let mut redis = self.redis.clone();
+        let fresh: Option<String> = self
+            .breaker
+            .call_once(
+                redis::cmd("SET")
+                    .arg(format!("api_key_nonce:{}:{}", api_key.id, nonce))
+                    .arg(1)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(self.config.recv_window_ms * 2)
+                    .query_async(&mut redis),
+            )
+            .await?;
+
+        match fresh {
+            Some(_) => Ok(()),
+            None => Err(CryptoTradeError::NonceReused),
+        }
+    }
+}
+
+impl ApiKey {
+    /// Whether the key may be used from `ip`; an unknown address only
+    /// passes when the key has no allowlist.
+    pub fn allows_ip(&self, ip: Option<IpAddr>) -> bool {
+        let Some(allowlist) = &self.ip_allowlist else {
+            return true;
+        };
+        let Some(ip) = ip else {
+            return false;
+        };
+        allowlist
+            .iter()
+            .filter_map(|network| network.parse::<IpNet>().ok())
+            .any(|network| network.contains(&ip))
+    }
+}
+
+/// Where the keys of `tenant`'s users start: `ctk_<prefix>_`, or just
+/// `ctk_` for the exchange's own users.
+fn key_namespace(tenant: Option<&Tenant>) -> String {
+    match tenant {
+        Some(tenant) => format!("{}{}_", KEY_PREFIX, tenant.api_key_prefix),
+        None => KEY_PREFIX.to_string(),
+    }
+}
+
+/// Whether `key` was issued in `tenant`'s namespace. The random part is
+/// hex, so a tenant key can never pass for one of the exchange's own.
+fn in_namespace(key: &str, tenant: Option<&Tenant>) -> bool {
+    key.strip_prefix(key_namespace(tenant).as_str())
+        .is_some_and(|random| random.len() == 32 && random.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Parses allowlist entries into canonical CIDR form; a bare address is a
+/// network of one.
+fn parse_allowlist(entries: &[String]) -> Result<Vec<String>> {
+    if entries.is_empty() {
+        return Err(CryptoTradeError::Validation {
+            message: "IP allowlist must not be empty; use null to allow any address".to_string(),
+        });
+    }
+
+    entries
+        .iter()
+        .map(|entry| {
+            let entry = entry.trim();
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map(|network| network.trunc().to_string())
+                .map_err(|_| CryptoTradeError::Validation {
+                    message: format!("'{}' is not an IP address or CIDR block", entry),
+                })
+        })
+        .collect()
+}
+
+/// What a request signature covers: the timestamp, nonce, upper-case
+/// method, path with query string, and raw body, concatenated as they are.
+pub fn signature_payload(timestamp: &str, nonce: &str, method: &str, path: &str, body: &[u8]) -> Vec<u8> {
+    [timestamp.as_bytes(), nonce.as_bytes(), method.as_bytes(), path.as_bytes(), body].concat()
+}
+
+/// Signs a request the way clients must: hex-encoded HMAC-SHA256 of
+/// [`signature_payload`], keyed with the API secret.
+pub fn sign_request(secret: &str, timestamp: &str, nonce: &str, method: &str, path: &str, body: &[u8]) -> String {
+    let payload = signature_payload(timestamp, nonce, method, path, body);
+    hex::encode(hmac_for(secret).chain_update(payload).finalize().into_bytes())
+}
+
+fn hmac_for(secret: &str) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_with(allowlist: &[&str]) -> ApiKey {
+        ApiKey {
+            id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            label: "test".to_string(),
+            key: "ctk_test".to_string(),
+            secret: None,
+            permissions: vec![TradingPermission::Read],
+            trading_pair_ids: None,
+            ip_allowlist: Some(parse_allowlist(&allowlist.iter().map(|entry| entry.to_string()).collect::<Vec<_>>()).unwrap()),
+            created_at: Utc::now(),
+            last_used_at: None,
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn test_signature_covers_every_part_of_the_request() {
+        let signature = sign_request("secret", "1700000000000", "nonce-123", "POST", "/api/v1/orders", b"{}");
+
+        assert_eq!(signature.len(), 64);
+        assert_eq!(
+            signature,
+            sign_request("secret", "1700000000000", "nonce-123", "POST", "/api/v1/orders", b"{}")
+        );
+        for changed in [
+            sign_request("other", "1700000000000", "nonce-123", "POST", "/api/v1/orders", b"{}"),
+            sign_request("secret", "1700000000001", "nonce-123", "POST", "/api/v1/orders", b"{}"),
+            sign_request("secret", "1700000000000", "nonce-124", "POST", "/api/v1/orders", b"{}"),
+            sign_request("secret", "1700000000000", "nonce-123", "GET", "/api/v1/orders", b"{}"),
+            sign_request("secret", "1700000000000", "nonce-123", "POST", "/api/v1/orders?x=1", b"{}"),
+            sign_request("secret", "1700000000000", "nonce-123", "POST", "/api/v1/orders", b"{ }"),
+        ] {
+            assert_ne!(signature, changed);
+        }
+    }
+
+    #[test]
+    fn test_keys_are_namespaced_by_tenant() {
+        let tenant = Tenant {
+            id: Uuid::nil(),
+            slug: "acme".to_string(),
+            name: "Acme Brokerage".to_string(),
+            api_key_prefix: "acme".to_string(),
+            maker_fee: None,
+            taker_fee: None,
+            display_name: None,
+            logo_url: None,
+            primary_color: None,
+            support_email: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let random = "0123456789abcdef0123456789abcdef";
+
+        assert!(in_namespace(&format!("ctk_{}", random), None));
+        assert!(in_namespace(&format!("ctk_acme_{}", random), Some(&tenant)));
+        assert!(!in_namespace(&format!("ctk_acme_{}", random), None));
+        assert!(!in_namespace(&format!("ctk_{}", random), Some(&tenant)));
+        assert!(!in_namespace("ctk_acme_short", Some(&tenant)));
+    }
+
+    #[test]
+    fn test_allowlist_entries_are_canonical_networks() {
+        let entries = vec!["203.0.113.77/24".to_string(), " 198.51.100.7 ".to_string(), "2001:db8::1".to_string()];
+
+        assert_eq!(
+            parse_allowlist(&entries).unwrap(),
+            vec!["203.0.113.0/24", "198.51.100.7/32", "2001:db8::1/128"]
+        );
+        assert!(parse_allowlist(&["10.0.0.0/33".to_string()]).is_err());
+        assert!(parse_allowlist(&[]).is_err());
+    }
+
+    #[test]
+    fn test_allowlist_matches_addresses_inside_its_networks() {
+        let key = key_with(&["203.0.113.0/24", "2001:db8::/32"]);
+
+        assert!(key.allows_ip("203.0.113.200".parse().ok()));
+        assert!(key.allows_ip("2001:db8::42".parse().ok()));
+        assert!(!key.allows_ip("203.0.114.1".parse().ok()));
+        assert!(!key.allows_ip(None));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{
+    captcha::{self, CaptchaVerifier},
+    config::CaptchaConfig,
+    database::Database,
+    error::CryptoTradeError,
+    Result,
+};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Asks for a solved CAPTCHA on registration and on logins to an account
+/// with repeated recent failures (counted by
+/// [`crate::services::UserService::login`], see migration 048). With the
+/// provider disabled, as in development and tests, nothing is asked.
+#[derive(Clone)]
+pub struct CaptchaService {
+    db: Database,
+    verifier: Option<Arc<dyn CaptchaVerifier>>,
+    config: CaptchaConfig,
+}
+
+impl CaptchaService {
+    /// Uses the provider selected in `config`.
+    pub fn new(db: Database, config: CaptchaConfig) -> Self {
+        Self {
+            db,
+            verifier: captcha::verifier(&config),
+            config,
+        }
+    }
+
+    pub async fn check_registration(&self, token: Option<&str>, remote_ip: Option<IpAddr>) -> Result<()> {
+        self.verify(token, remote_ip).await
+    }
+
+    /// Checks the CAPTCHA only once the account has failed
+    /// `failed_login_threshold` logins in a row, the latest within the
+    /// window. Failures are counted per account, so logins to emails with
+    /// no account are never asked for one.
+    pub async fn check_login(&self, email: &str, token: Option<&str>, remote_ip: Option<IpAddr>) -> Result<()> {
+        if self.verifier.is_none() {
+            return Ok(());
+        }
+        let needed = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT failed_login_attempts >= $2
+                AND ($2 = 0 OR last_failed_login_at > NOW() - make_interval(mins => $3))
+            FROM users WHERE email = $1
+            "#,
+        )
+        .bind(email)
+        .bind(self.config.failed_login_threshold)
+        .bind(self.config.failed_login_window_minutes as i32)
+        .fetch_optional(&self.db)
+        .await?
+        .unwrap_or(false);
+        if !needed {
+            return Ok(());
+        }
+        self.verify(token, remote_ip).await
+    }
+
+    async fn verify(&self, token: Option<&str>, remote_ip: Option<IpAddr>) -> Result<()> {
+        let Some(verifier) = &self.verifier else {
+            return Ok(());
+        };
+        let token = token.map(str::trim).filter(|token| !token.is_empty());
+        match token {
+            Some(token) if verifier.verify(token, remote_ip).await? => Ok(()),
+            _ => Err(CryptoTradeError::CaptchaRequired),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{
+    compliance::{country_code, SanctionsScreener},
+    config::ComplianceConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    services::UserService,
+    Result,
+};
+use axum::http::HeaderMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Why screening restricted an account; the restriction is lifted only if
+/// this is still the account's reason once every review is cleared.
+const SCREENING_REASON: &str = "Held for review of a possible sanctions list match";
+
+/// Keeps restricted jurisdictions out and screens users against sanctions
+/// lists. Where a request comes from is read from the header the CDN sets;
+/// where a user lives is the country recorded at registration.
+#[derive(Clone)]
+pub struct ComplianceService {
+    db: Database,
+    user_service: UserService,
+    config: ComplianceConfig,
+    screener: Option<Arc<dyn SanctionsScreener>>,
+}
+
+impl ComplianceService {
+    pub fn new(db: Database, user_service: UserService, config: ComplianceConfig) -> Self {
+        Self {
+            db,
+            user_service,
+            config,
+            screener: None,
+        }
+    }
+
+    /// Screens new users with `screener`.
+    pub fn with_screener(mut self, screener: Arc<dyn SanctionsScreener>) -> Self {
+        self.screener = Some(screener);
+        self
+    }
+
+    /// The country the request comes from, if the CDN reported one.
+    pub fn request_country(&self, headers: &HeaderMap) -> Option<String> {
+        if self.config.country_header.is_empty() {
+            return None;
+        }
+        headers
+            .get(self.config.country_header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .and_then(country_code)
+    }
+
+    pub fn ensure_allowed(&self, country: Option<&str>) -> Result<()> {
+        match country {
+            Some(country) if self.config.restricted_countries.iter().any(|restricted| restricted == country) => {
+                Err(CryptoTradeError::RestrictedJurisdiction {
+                    country: country.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuses registrations from, or declaring residence in, a restricted
+    /// country, and records the request's country as the residence when
+    /// none was declared.
+    pub fn check_registration(&self, headers: &HeaderMap, request: &mut RegisterRequest) -> Result<()> {
+        let origin = self.request_country(headers);
+        self.ensure_allowed(origin.as_deref())?;
+        request.country = match request.country.as_deref() {
+            Some(declared) => Some(country_code(declared).ok_or_else(|| CryptoTradeError::Validation {
+                message: "country must be an ISO 3166-1 alpha-2 code".to_string(),
+            })?),
+            None => origin,
+        };
+        self.ensure_allowed(request.country.as_deref())
+    }
+
+    pub fn check_login(&self, headers: &HeaderMap) -> Result<()> {
+        self.ensure_allowed(self.request_country(headers).as_deref())
+    }
+
+    /// Refuses orders from a restricted country or by a user residing in
+    /// one, including users whose country was restricted after they
+    /// registered.
+    pub async fn check_trading(&self, user_id: Uuid, origin: Option<&str>) -> Result<()> {
+        self.ensure_allowed(origin)?;
+        let residence = sqlx::query_scalar::<_, Option<String>>("SELECT country FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::UserNotFound)?;
+        self.ensure_allowed(residence.as_deref())
+    }
+
+    /// Screens the user's full name and holds each match for review,
+    /// restricting the account meanwhile. Does nothing when screening is
+    /// off or the user gave no name.
+    pub async fn screen_user(&self, user_id: Uuid) -> Result<Vec<ComplianceReview>> {
+        let Some(screener) = &self.screener else {
+            return Ok(Vec::new());
+        };
+        let user = self.user_service.get_user_by_id(user_id).await?;
+        let name = [user.first_name.as_deref(), user.last_name.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if name.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut reviews = Vec::new();
+        for found in screener.screen(&name).await? {
+            let review = sqlx::query_as::<_, ComplianceReview>(
+                "INSERT INTO compliance_reviews (user_id, screened_name, listed_name, list_name, score) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+            )
+            .bind(user_id)
+            .bind(&name)
+            .bind(&found.listed_name)
+            .bind(&found.list_name)
+            .bind(found.score)
+            .fetch_one(&self.db)
+            .await?;
+            reviews.push(review);
+        }
+        if !reviews.is_empty() {
+            tracing::warn!("User {} matched {} sanctions list entries", user_id, reviews.len());
+            if user.account_status == AccountStatus::Active {
+                self.user_service
+                    .change_account_status(user_id, AccountStatus::Restricted, SCREENING_REASON, None)
+                    .await?;
+            }
+        }
+
+        Ok(reviews)
+    }
+
+    pub async fn list_reviews(&self, status: Option<ComplianceReviewStatus>) -> Result<Vec<ComplianceReview>> {
+        let reviews = sqlx::query_as::<_, ComplianceReview>(
+            "SELECT * FROM compliance_reviews WHERE $1::compliance_review_status IS NULL OR status = $1 ORDER BY created_at",
+        )
+        .bind(status)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(reviews)
+    }
+
+    /// Records a compliance officer's decision. Once none of a user's
+    /// matches is pending or confirmed, an account restricted by screening
+    /// is reinstated.
+    pub async fn resolve_review(&self, admin_id: Uuid, review_id: Uuid, request: ResolveComplianceReviewRequest) -> Result<ComplianceReview> {
+        if request.status == ComplianceReviewStatus::Pending {
+            return Err(CryptoTradeError::Validation {
+                message: "A review can only be cleared or confirmed".to_string(),
+            });
+        }
+        if request.note.trim().is_empty() {
+            return Err(CryptoTradeError::Validation {
+                message: "A note is required".to_string(),
+            });
+        }
+
+        let review = sqlx::query_as::<_, ComplianceReview>(
+            "UPDATE compliance_reviews SET status = $2, note = $3, reviewed_by = $4, reviewed_at = NOW() WHERE id = $1 AND status = 'pending' RETURNING *",
+        )
+        .bind(review_id)
+        .bind(request.status)
+        .bind(request.note.trim())
+        .bind(admin_id)
+        .fetch_optional(&self.db)
+        .await?;
+        let Some(review) = review else {
+            let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM compliance_reviews WHERE id = $1)")
+                .bind(review_id)
+                .fetch_one(&self.db)
+                .await?;
+            return Err(if exists {
+                CryptoTradeError::Validation {
+                    message: "Review has already been resolved".to_string(),
+                }
+            } else {
+                CryptoTradeError::NotFound {
+                    message: "Compliance review not found".to_string(),
+                }
+            });
+        };
+
+        let held = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM compliance_reviews WHERE user_id = $1 AND status <> 'cleared')",
+        )
+        .bind(review.user_id)
+        .fetch_one(&self.db)
+        .await?;
+        if !held {
+            let restricted_by_screening = sqlx::query_scalar::<_, bool>(
+                "SELECT account_status = 'restricted' AND status_reason IS NOT DISTINCT FROM $2 FROM users WHERE id = $1",
+            )
+            .bind(review.user_id)
+            .bind(SCREENING_REASON)
+            .fetch_one(&self.db)
+            .await?;
+            if restricted_by_screening {
+                self.user_service
+                    .change_account_status(review.user_id, AccountStatus::Active, "Sanctions list matches cleared", Some(admin_id))
+                    .await?;
+            }
+        }
+
+        Ok(review)
+    }
+}
@@ -0,0 +1,703 @@
+use crate::{
+    config::CopyTradingConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    oracle::{LastTradePrices, PriceOracle},
+    services::{user_service::account_status, LedgerService, OrderService, Posting},
+    Result,
+};
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Mirrors lead traders' fills into their followers' accounts. A background
+/// task calls [`CopyTradingService::mirror_fills`], which places a market
+/// order for each follower sized by the follower's budget relative to the
+/// lead's equity. A subscription only sells what it bought, never invests
+/// more than its budget, and pays the lead a performance fee on realized
+/// profit above its high-water mark.
+#[derive(Clone)]
+pub struct CopyTradingService {
+    db: Database,
+    ledger: LedgerService,
+    order_service: OrderService,
+    config: CopyTradingConfig,
+    oracle: Arc<dyn PriceOracle>,
+}
+
+/// A lead fill a subscription has not seen yet
+#[derive(sqlx::FromRow)]
+struct LeadFill {
+    id: Uuid,
+    trading_pair_id: Uuid,
+    side: OrderSide,
+    price: Decimal,
+    quantity: Decimal,
+    min_order_size: Option<Decimal>,
+    quantity_precision: Option<i32>,
+}
+
+/// What came of mirroring one lead fill
+struct Mirrored {
+    quantity: Decimal,
+    quote_quantity: Decimal,
+    order_id: Option<Uuid>,
+    status: CopyTradeStatus,
+    note: Option<String>,
+}
+
+impl Mirrored {
+    fn skipped(note: impl Into<String>) -> Self {
+        Self {
+            quantity: Decimal::ZERO,
+            quote_quantity: Decimal::ZERO,
+            order_id: None,
+            status: CopyTradeStatus::Skipped,
+            note: Some(note.into()),
+        }
+    }
+}
+
+impl CopyTradingService {
+    /// Values lead equity at the exchange's last trades.
+    pub fn new(db: Database, ledger: LedgerService, order_service: OrderService, config: CopyTradingConfig) -> Self {
+        Self {
+            oracle: Arc::new(LastTradePrices::new(db.clone())),
+            db,
+            ledger,
+            order_service,
+            config,
+        }
+    }
+
+    pub fn with_oracle(mut self, oracle: Arc<dyn PriceOracle>) -> Self {
+        self.oracle = oracle;
+        self
+    }
+
+    /// Publishes the caller as a lead trader, or updates their listing.
+    /// Existing subscriptions keep the fee rate they were taken out at.
+    pub async fn publish(&self, user_id: Uuid, request: PublishLeadTraderRequest) -> Result<LeadTrader> {
+        account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        let display_name = request.display_name.trim();
+        if display_name.is_empty() || display_name.chars().count() > 50 {
+            return Err(CryptoTradeError::Validation {
+                message: "display_name must be 1 to 50 characters".to_string(),
+            });
+        }
+        let rate = request.performance_fee_rate;
+        if rate < Decimal::ZERO || rate > self.config.max_performance_fee_rate {
+            return Err(CryptoTradeError::Validation {
+                message: format!("performance_fee_rate must be between 0 and {}", self.config.max_performance_fee_rate),
+            });
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO lead_traders (user_id, display_name, performance_fee_rate)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE SET
+                display_name = EXCLUDED.display_name,
+                performance_fee_rate = EXCLUDED.performance_fee_rate,
+                is_active = true,
+                updated_at = NOW()
+            "#,
+        )
+        .bind(user_id)
+        .bind(display_name)
+        .bind(rate)
+        .execute(&self.db)
+        .await?;
+
+        self.lead(user_id).await?.ok_or(CryptoTradeError::Internal)
+    }
+
+    /// Withdraws the caller's listing and stops every subscription to it.
+    pub async fn unpublish(&self, user_id: Uuid) -> Result<LeadTrader> {
+        let mut tx = self.db.begin().await?;
+        let updated = sqlx::query("UPDATE lead_traders SET is_active = false, updated_at = NOW() WHERE user_id = $1 AND is_active")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        if updated.rows_affected() == 0 {
+            return Err(CryptoTradeError::NotFound {
+                message: "You are not a published lead trader".to_string(),
+            });
+        }
+        sqlx::query(
+            "UPDATE copy_subscriptions SET status = 'stopped', stop_reason = 'The lead trader stopped sharing', updated_at = NOW() WHERE lead_id = $1 AND status = 'active'",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.lead(user_id).await?.ok_or(CryptoTradeError::Internal)
+    }
+
+    /// Published leads, most followed first.
+    pub async fn leads(&self) -> Result<Vec<LeadTrader>> {
+        let leads = sqlx::query_as::<_, LeadTrader>(
+            r#"
+            SELECT l.user_id, l.display_name, l.performance_fee_rate, l.is_active, l.created_at,
+                   (SELECT COUNT(*) FROM copy_subscriptions s WHERE s.lead_id = l.user_id AND s.status = 'active') AS followers
+            FROM lead_traders l
+            WHERE l.is_active
+            ORDER BY followers DESC, l.created_at
+            LIMIT 100
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(leads)
+    }
+
+    async fn lead(&self, user_id: Uuid) -> Result<Option<LeadTrader>> {
+        let lead = sqlx::query_as::<_, LeadTrader>(
+            r#"
+            SELECT l.user_id, l.display_name, l.performance_fee_rate, l.is_active, l.created_at,
+                   (SELECT COUNT(*) FROM copy_subscriptions s WHERE s.lead_id = l.user_id AND s.status = 'active') AS followers
+            FROM lead_traders l
+            WHERE l.user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(lead)
+    }
+
+    /// Starts following a lead. Only fills from now on are mirrored.
+    pub async fn subscribe(&self, follower_id: Uuid, request: CreateCopySubscriptionRequest) -> Result<CopySubscription> {
+        account_status(&self.db, follower_id).await?.ensure_can_trade()?;
+        let invalid = |message: String| Err(CryptoTradeError::Validation { message });
+        if request.lead_id == follower_id {
+            return invalid("You cannot copy yourself".to_string());
+        }
+        let currency = request.budget_currency.trim().to_uppercase();
+        if request.budget < self.config.min_budget {
+            return invalid(format!("budget must be at least {}", self.config.min_budget));
+        }
+        if request.max_trade_notional.is_some_and(|limit| limit <= Decimal::ZERO)
+            || request.stop_loss.is_some_and(|limit| limit <= Decimal::ZERO)
+        {
+            return invalid("Risk limits must be positive".to_string());
+        }
+        let lead = self
+            .lead(request.lead_id)
+            .await?
+            .filter(|lead| lead.is_active)
+            .ok_or_else(|| CryptoTradeError::NotFound {
+                message: "Lead trader not found".to_string(),
+            })?;
+
+        let subscription = sqlx::query_as::<_, CopySubscription>(
+            r#"
+            INSERT INTO copy_subscriptions (follower_id, lead_id, budget_currency, budget, max_trade_notional, stop_loss, performance_fee_rate)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (follower_id, lead_id) WHERE status <> 'closed' DO NOTHING
+            RETURNING *
+            "#,
+        )
+        .bind(follower_id)
+        .bind(lead.user_id)
+        .bind(&currency)
+        .bind(request.budget)
+        .bind(request.max_trade_notional)
+        .bind(request.stop_loss)
+        .bind(lead.performance_fee_rate)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| CryptoTradeError::Validation {
+            message: "You already follow this lead trader".to_string(),
+        })?;
+
+        tracing::info!("User {} started copying {} with {} {}", follower_id, lead.user_id, request.budget, currency);
+        Ok(subscription)
+    }
+
+    pub async fn subscriptions(&self, follower_id: Uuid) -> Result<Vec<CopySubscription>> {
+        let subscriptions = sqlx::query_as::<_, CopySubscription>(
+            "SELECT * FROM copy_subscriptions WHERE follower_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(follower_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(subscriptions)
+    }
+
+    pub async fn copy_trades(&self, follower_id: Uuid, subscription_id: Uuid) -> Result<Vec<CopyTrade>> {
+        self.subscription(follower_id, subscription_id).await?;
+        let trades = sqlx::query_as::<_, CopyTrade>(
+            "SELECT * FROM copy_trades WHERE subscription_id = $1 ORDER BY created_at DESC LIMIT 500",
+        )
+        .bind(subscription_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(trades)
+    }
+
+    /// Stops following. What the subscription bought stays in the
+    /// follower's balances.
+    pub async fn close(&self, follower_id: Uuid, subscription_id: Uuid) -> Result<CopySubscription> {
+        // Waits for a mirroring pass over this subscription to finish
+        let mut tx = self.db.begin().await?;
+        let subscription = sqlx::query_as::<_, CopySubscription>(
+            "SELECT * FROM copy_subscriptions WHERE id = $1 AND follower_id = $2 FOR NO KEY UPDATE",
+        )
+        .bind(subscription_id)
+        .bind(follower_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(not_found)?;
+        if subscription.status == CopySubscriptionStatus::Closed {
+            return Err(CryptoTradeError::Validation {
+                message: "Subscription is already closed".to_string(),
+            });
+        }
+        let subscription = sqlx::query_as::<_, CopySubscription>(
+            "UPDATE copy_subscriptions SET status = 'closed', closed_at = NOW(), updated_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(subscription_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(subscription)
+    }
+
+    async fn subscription(&self, follower_id: Uuid, subscription_id: Uuid) -> Result<CopySubscription> {
+        sqlx::query_as::<_, CopySubscription>("SELECT * FROM copy_subscriptions WHERE id = $1 AND follower_id = $2")
+            .bind(subscription_id)
+            .bind(follower_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(not_found)
+    }
+
+    /// Mirrors the lead fills every active subscription has not seen yet
+    /// and returns how many orders were placed.
+    pub async fn mirror_fills(&self) -> Result<u32> {
+        let subscriptions = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM copy_subscriptions WHERE status = 'active' ORDER BY mirrored_until LIMIT 500",
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut placed = 0;
+        for subscription_id in subscriptions {
+            placed += self.mirror_subscription(subscription_id).await?;
+        }
+        Ok(placed)
+    }
+
+    /// Holds the subscription's row locked, so it is never mirrored twice at
+    /// once or closed halfway. The lock is NO KEY so that rows referencing
+    /// it can still be written.
+    async fn mirror_subscription(&self, subscription_id: Uuid) -> Result<u32> {
+        let mut tx = self.db.begin().await?;
+        let Some(subscription) = sqlx::query_as::<_, CopySubscription>(
+            "SELECT * FROM copy_subscriptions WHERE id = $1 AND status = 'active' FOR NO KEY UPDATE SKIP LOCKED",
+        )
+        .bind(subscription_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(0);
+        };
+
+        // Fills sharing the cursor's timestamp may not all have been seen,
+        // so the cursor is inclusive and seen fills are left out
+        let fills = sqlx::query_as::<_, LeadFill>(
+            r#"
+            SELECT t.id, t.trading_pair_id,
+                   CASE WHEN t.buyer_user_id = s.lead_id THEN 'buy' ELSE 'sell' END::order_side AS side,
+                   t.price, t.quantity, tp.min_order_size, tp.quantity_precision
+            FROM copy_subscriptions s
+            JOIN trades t ON (t.buyer_user_id = s.lead_id OR t.seller_user_id = s.lead_id) AND t.created_at >= s.mirrored_until
+            JOIN trading_pairs tp ON tp.id = t.trading_pair_id AND tp.quote_currency = s.budget_currency
+            WHERE s.id = $1
+              -- The follower's own orders matching the lead's are not the lead's to copy
+              AND s.follower_id NOT IN (t.buyer_user_id, t.seller_user_id)
+              AND NOT EXISTS (SELECT 1 FROM copy_trades c WHERE c.subscription_id = s.id AND c.lead_trade_id = t.id)
+            ORDER BY t.created_at, t.id
+            LIMIT 100
+            "#,
+        )
+        .bind(subscription.id)
+        .fetch_all(&mut *tx)
+        .await?;
+        if fills.is_empty() {
+            return Ok(0);
+        }
+
+        let lead_equity = self.equity(subscription.lead_id, &subscription.budget_currency).await?;
+        let mut placed = 0;
+        let mut realized_pnl = subscription.realized_pnl;
+        let mut high_water_mark = subscription.high_water_mark;
+        let mut fees_paid = subscription.fees_paid;
+        for fill in &fills {
+            let mirrored = self.mirror(&mut tx, &subscription, fill, lead_equity).await?;
+            let mut performance_fee = Decimal::ZERO;
+            if mirrored.status == CopyTradeStatus::Filled {
+                placed += 1;
+                let gain = self.record_position(&mut tx, subscription.id, fill, &mirrored).await?;
+                realized_pnl += gain;
+                let (fee, mark) = performance_fee_due(realized_pnl, high_water_mark, subscription.performance_fee_rate);
+                if fee.is_zero() {
+                    high_water_mark = mark;
+                } else {
+                    performance_fee = self.charge_performance_fee(&mut tx, &subscription, fee).await?;
+                    fees_paid += performance_fee;
+                    high_water_mark = paid_up_mark(high_water_mark, mark, fee, performance_fee);
+                }
+            }
+            sqlx::query(
+                "INSERT INTO copy_trades (subscription_id, lead_trade_id, trading_pair_id, side, lead_quantity, quantity, quote_quantity, order_id, status, note, performance_fee) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            )
+            .bind(subscription.id)
+            .bind(fill.id)
+            .bind(fill.trading_pair_id)
+            .bind(fill.side)
+            .bind(fill.quantity)
+            .bind(mirrored.quantity)
+            .bind(mirrored.quote_quantity)
+            .bind(mirrored.order_id)
+            .bind(mirrored.status)
+            .bind(mirrored.note)
+            .bind(performance_fee)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let stop_reason = match subscription.stop_loss {
+            Some(stop_loss) => {
+                let pnl = realized_pnl + self.unrealized_pnl(&mut tx, subscription.id).await?;
+                (pnl <= -stop_loss).then(|| format!("Stop loss of {} {} reached", stop_loss, subscription.budget_currency))
+            }
+            None => None,
+        };
+        sqlx::query(
+            r#"
+            UPDATE copy_subscriptions SET
+                realized_pnl = $2, high_water_mark = $3, fees_paid = $4,
+                mirrored_until = GREATEST(mirrored_until, (SELECT MAX(created_at) FROM trades WHERE id = ANY($5))),
+                status = CASE WHEN $6::text IS NULL THEN status ELSE 'stopped' END,
+                stop_reason = COALESCE($6, stop_reason),
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(subscription.id)
+        .bind(realized_pnl)
+        .bind(high_water_mark)
+        .bind(fees_paid)
+        .bind(fills.iter().map(|fill| fill.id).collect::<Vec<_>>())
+        .bind(stop_reason)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(placed)
+    }
+
+    /// Sizes and places the follower's order for one lead fill.
+    async fn mirror(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        subscription: &CopySubscription,
+        fill: &LeadFill,
+        lead_equity: Decimal,
+    ) -> Result<Mirrored> {
+        if lead_equity <= Decimal::ZERO {
+            return Ok(Mirrored::skipped("The lead's equity cannot be valued"));
+        }
+        let scale = fill.quantity_precision.unwrap_or(8).clamp(0, 8) as u32;
+        let mut quantity = mirror_quantity(fill.quantity, subscription.budget / lead_equity, scale);
+        let (held, invested) = sqlx::query_as::<_, (Decimal, Decimal)>(
+            "SELECT COALESCE(SUM(quantity) FILTER (WHERE trading_pair_id = $2), 0), COALESCE(SUM(cost), 0) FROM copy_positions WHERE subscription_id = $1",
+        )
+        .bind(subscription.id)
+        .bind(fill.trading_pair_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let floor = |value: Decimal| value.round_dp_with_strategy(scale, RoundingStrategy::ToZero);
+        if let Some(limit) = subscription.max_trade_notional {
+            quantity = quantity.min(floor(limit / fill.price));
+        }
+        quantity = match fill.side {
+            OrderSide::Buy => quantity.min(floor((subscription.budget - invested).max(Decimal::ZERO) / fill.price)),
+            OrderSide::Sell => quantity.min(held),
+        };
+        if quantity.is_zero() || quantity < fill.min_order_size.unwrap_or(Decimal::ZERO) {
+            let note = match fill.side {
+                OrderSide::Sell if held.is_zero() => "Nothing bought to sell",
+                _ => "Below the minimum order size after sizing and limits",
+            };
+            return Ok(Mirrored::skipped(note));
+        }
+
+        let request = CreateOrderRequest {
+            trading_pair_id: fill.trading_pair_id,
+            order_type: OrderType::Market,
+            side: fill.side,
+            quantity: quantity.to_f64().ok_or(CryptoTradeError::InvalidQuantity)?,
+            price: None,
+            time_in_force: None,
+            stop_price: None,
+            allow_duplicate: true,
+            activate_at: None,
+            lock_funds: false,
+        };
+        match self.order_service.create_order(subscription.follower_id, request).await {
+            Ok(order) => {
+                let filled = order.filled_quantity.unwrap_or(Decimal::ZERO);
+                Ok(Mirrored {
+                    quantity: filled,
+                    quote_quantity: order.cumulative_quote_quantity.unwrap_or(Decimal::ZERO),
+                    order_id: Some(order.id),
+                    status: if filled.is_zero() { CopyTradeStatus::Skipped } else { CopyTradeStatus::Filled },
+                    note: filled.is_zero().then(|| "Nothing to match against".to_string()),
+                })
+            }
+            Err(e) => {
+                tracing::warn!("Copying trade {} for subscription {} failed: {}", fill.id, subscription.id, e);
+                Ok(Mirrored {
+                    status: CopyTradeStatus::Failed,
+                    note: Some(e.to_string()),
+                    ..Mirrored::skipped("")
+                })
+            }
+        }
+    }
+
+    /// Books a mirrored fill into the subscription's position and returns
+    /// the profit it realized.
+    async fn record_position(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        subscription_id: Uuid,
+        fill: &LeadFill,
+        mirrored: &Mirrored,
+    ) -> Result<Decimal> {
+        let (held, cost) = sqlx::query_as::<_, (Decimal, Decimal)>(
+            r#"
+            INSERT INTO copy_positions (subscription_id, trading_pair_id) VALUES ($1, $2)
+            ON CONFLICT (subscription_id, trading_pair_id) DO UPDATE SET quantity = copy_positions.quantity
+            RETURNING quantity, cost
+            "#,
+        )
+        .bind(subscription_id)
+        .bind(fill.trading_pair_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let (held, cost, gain) = match fill.side {
+            OrderSide::Buy => (held + mirrored.quantity, cost + mirrored.quote_quantity, Decimal::ZERO),
+            OrderSide::Sell => {
+                let (cost_left, gain) = sell_at_cost(held, cost, mirrored.quantity, mirrored.quote_quantity);
+                (held - mirrored.quantity, cost_left, gain)
+            }
+        };
+        sqlx::query("UPDATE copy_positions SET quantity = $3, cost = $4 WHERE subscription_id = $1 AND trading_pair_id = $2")
+            .bind(subscription_id)
+            .bind(fill.trading_pair_id)
+            .bind(held)
+            .bind(cost)
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(gain)
+    }
+
+    /// Moves the fee from the follower's budget currency balance to the
+    /// lead's, or as much of it as is available, and returns what was paid.
+    /// The caller holds the high-water mark back by what went unpaid.
+    async fn charge_performance_fee(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        subscription: &CopySubscription,
+        fee: Decimal,
+    ) -> Result<Decimal> {
+        let currency = &subscription.budget_currency;
+        let available = sqlx::query_scalar::<_, Decimal>(
+            "SELECT COALESCE(available_balance, 0) FROM accounts WHERE user_id = $1 AND currency = $2 FOR UPDATE",
+        )
+        .bind(subscription.follower_id)
+        .bind(currency)
+        .fetch_optional(&mut *conn)
+        .await?
+        .unwrap_or(Decimal::ZERO);
+        let fee = fee.min(available);
+        if fee <= Decimal::ZERO {
+            return Ok(Decimal::ZERO);
+        }
+        let follower_balance = sqlx::query_scalar::<_, Decimal>(
+            "UPDATE accounts SET balance = balance - $3, available_balance = available_balance - $3, updated_at = NOW() WHERE user_id = $1 AND currency = $2 RETURNING balance",
+        )
+        .bind(subscription.follower_id)
+        .bind(currency)
+        .bind(fee)
+        .fetch_one(&mut *conn)
+        .await?;
+        let lead_balance = sqlx::query_scalar::<_, Decimal>(
+            r#"
+            INSERT INTO accounts (user_id, currency, balance, available_balance, locked_balance)
+            VALUES ($1, $2, $3, $3, 0)
+            ON CONFLICT (user_id, currency) DO UPDATE SET
+                balance = COALESCE(accounts.balance, 0) + EXCLUDED.balance,
+                available_balance = COALESCE(accounts.available_balance, 0) + EXCLUDED.available_balance,
+                updated_at = NOW()
+            RETURNING balance
+            "#,
+        )
+        .bind(subscription.lead_id)
+        .bind(currency)
+        .bind(fee)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let posting = |user_id, amount, balance_after| Posting {
+            user_id: Some(user_id),
+            currency: currency.clone(),
+            entry_type: LedgerEntryType::Transfer,
+            amount,
+            balance_after: Some(balance_after),
+        };
+        self.ledger
+            .post_in(
+                conn,
+                Uuid::new_v4(),
+                Some(subscription.id),
+                &[
+                    posting(subscription.follower_id, -fee, follower_balance),
+                    posting(subscription.lead_id, fee, lead_balance),
+                ],
+            )
+            .await?;
+
+        Ok(fee)
+    }
+
+    /// What the subscription's positions would gain or lose if sold at the
+    /// last price; positions without one count at cost.
+    async fn unrealized_pnl(&self, conn: &mut sqlx::PgConnection, subscription_id: Uuid) -> Result<Decimal> {
+        let positions = sqlx::query_as::<_, (String, String, Decimal, Decimal)>(
+            "SELECT tp.base_currency, tp.quote_currency, p.quantity, p.cost FROM copy_positions p JOIN trading_pairs tp ON tp.id = p.trading_pair_id WHERE p.subscription_id = $1 AND p.quantity > 0",
+        )
+        .bind(subscription_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut pnl = Decimal::ZERO;
+        for (base, quote, quantity, cost) in positions {
+            if let Some(price) = self.oracle.price(&base, &quote).await? {
+                pnl += quantity * price - cost;
+            }
+        }
+        Ok(pnl)
+    }
+
+    /// The user's balances valued in `currency`. Balances the oracle cannot
+    /// price are left out.
+    async fn equity(&self, user_id: Uuid, currency: &str) -> Result<Decimal> {
+        let balances = sqlx::query_as::<_, (String, Decimal)>(
+            "SELECT currency, COALESCE(balance, 0) FROM accounts WHERE user_id = $1 AND balance > 0",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut equity = Decimal::ZERO;
+        for (held, balance) in balances {
+            if let Some(price) = self.oracle.price(&held, currency).await? {
+                equity += balance * price;
+            }
+        }
+        Ok(equity)
+    }
+}
+
+fn not_found() -> CryptoTradeError {
+    CryptoTradeError::NotFound {
+        message: "Copy subscription not found".to_string(),
+    }
+}
+
+/// The lead's quantity scaled by the follower's share, rounded down.
+fn mirror_quantity(lead_quantity: Decimal, ratio: Decimal, scale: u32) -> Decimal {
+    (lead_quantity * ratio).round_dp_with_strategy(scale, RoundingStrategy::ToZero)
+}
+
+/// Sells `sold` of a position of `held` bought for `cost`, at average cost.
+/// Returns the cost of what is left and the profit realized.
+fn sell_at_cost(held: Decimal, cost: Decimal, sold: Decimal, proceeds: Decimal) -> (Decimal, Decimal) {
+    if held.is_zero() {
+        return (cost, proceeds);
+    }
+    let sold_cost = (cost * sold.min(held) / held).round_dp(AMOUNT_SCALE);
+    (cost - sold_cost, proceeds - sold_cost)
+}
+
+/// The fee due on realized profit above the high-water mark, and the new
+/// mark. Profit lost and made back is not charged twice.
+fn performance_fee_due(realized_pnl: Decimal, high_water_mark: Decimal, rate: Decimal) -> (Decimal, Decimal) {
+    if realized_pnl <= high_water_mark {
+        return (Decimal::ZERO, high_water_mark);
+    }
+    let fee = ((realized_pnl - high_water_mark) * rate).round_dp_with_strategy(AMOUNT_SCALE, RoundingStrategy::ToZero);
+    (fee, realized_pnl)
+}
+
+/// How far the high-water mark moves when only `paid` of the `due` fee
+/// could be charged: in proportion, so the unpaid profit is charged on the
+/// next gain the follower can cover.
+fn paid_up_mark(high_water_mark: Decimal, mark: Decimal, due: Decimal, paid: Decimal) -> Decimal {
+    if paid >= due {
+        return mark;
+    }
+    high_water_mark + ((mark - high_water_mark) * paid / due).round_dp_with_strategy(AMOUNT_SCALE, RoundingStrategy::ToZero)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mirror_quantity_scales_and_rounds_down() {
+        assert_eq!(mirror_quantity(Decimal::ONE, Decimal::new(1, 1), 8), Decimal::new(1, 1));
+        assert_eq!(mirror_quantity(Decimal::ONE, Decimal::ONE / Decimal::from(3), 4), Decimal::new(3333, 4));
+    }
+
+    #[test]
+    fn test_sell_at_cost_realizes_against_average_cost() {
+        // Half of 2 bought for 100 sold for 60
+        let (cost_left, gain) = sell_at_cost(Decimal::from(2), Decimal::from(100), Decimal::ONE, Decimal::from(60));
+        assert_eq!((cost_left, gain), (Decimal::from(50), Decimal::from(10)));
+        let (cost_left, gain) = sell_at_cost(Decimal::ONE, Decimal::from(50), Decimal::ONE, Decimal::from(40));
+        assert_eq!((cost_left, gain), (Decimal::ZERO, Decimal::from(-10)));
+    }
+
+    #[test]
+    fn test_performance_fee_is_only_charged_above_the_high_water_mark() {
+        let rate = Decimal::new(2, 1);
+        assert_eq!(performance_fee_due(Decimal::from(100), Decimal::ZERO, rate), (Decimal::from(20), Decimal::from(100)));
+        // A loss, then a recovery to the old mark, owes nothing
+        assert_eq!(performance_fee_due(Decimal::from(60), Decimal::from(100), rate), (Decimal::ZERO, Decimal::from(100)));
+        assert_eq!(performance_fee_due(Decimal::from(110), Decimal::from(100), rate), (Decimal::from(2), Decimal::from(110)));
+    }
+
+    #[test]
+    fn test_high_water_mark_only_moves_past_paid_profit() {
+        // 20 due on 100 of profit, of which 5 could be paid
+        assert_eq!(paid_up_mark(Decimal::ZERO, Decimal::from(100), Decimal::from(20), Decimal::from(5)), Decimal::from(25));
+        assert_eq!(paid_up_mark(Decimal::from(25), Decimal::from(100), Decimal::from(15), Decimal::ZERO), Decimal::from(25));
+        assert_eq!(paid_up_mark(Decimal::from(25), Decimal::from(100), Decimal::from(15), Decimal::from(15)), Decimal::from(100));
+    }
+}
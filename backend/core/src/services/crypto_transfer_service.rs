@@ -0,0 +1,540 @@
+use crate::{
+    config::BlockchainConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    services::{
+        outbox_service::{enqueue_event, DomainEvent},
+        user_service::{account_status, get_or_create_account},
+        LedgerService, Posting, RiskLimitService,
+    },
+    Result,
+};
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use rust_decimal::Decimal;
+use sha2::Sha256;
+use sqlx::PgConnection;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Carries the hex HMAC-SHA256 of a deposit report under the watcher secret
+pub const DEPOSIT_REPORT_SIGNATURE_HEADER: &str = "x-watcher-signature";
+
+/// Longest text memo a chain accepts, in bytes
+const MAX_TEXT_MEMO_BYTES: usize = 28;
+
+/// Tries at a memo nobody holds before giving up
+const MEMO_ATTEMPTS: usize = 5;
+
+const WITHDRAWAL_COLUMNS: &str =
+    "id, user_id, currency, address, memo, amount, status, external_id, created_at, updated_at";
+
+/// On-chain deposits and withdrawals for chains that pay every user's
+/// deposits to one shared address (see migration 056). Each user gets their
+/// own memo on it; the deposit watcher reports transfers as they confirm,
+/// and one is credited to the user holding its memo once it has the
+/// currency's confirmations. Transfers whose memo is missing or unknown are
+/// held for an admin to credit by hand.
+#[derive(Clone)]
+pub struct CryptoTransferService {
+    db: Database,
+    ledger: LedgerService,
+    risk_limits: RiskLimitService,
+    watcher_secret: String,
+}
+
+impl CryptoTransferService {
+    pub fn new(db: Database, ledger: LedgerService, risk_limits: RiskLimitService, config: &BlockchainConfig) -> Self {
+        Self {
+            db,
+            ledger,
+            risk_limits,
+            watcher_secret: config.deposit_watcher_secret.clone(),
+        }
+    }
+
+    /// The user's deposit address for `currency`, giving them a memo on
+    /// the shared address the first time.
+    pub async fn deposit_address(&self, user_id: Uuid, currency: &str) -> Result<DepositAddress> {
+        let currency = self.currency(currency).await?;
+        let address = currency.deposit_address.ok_or_else(|| CryptoTradeError::NotFound {
+            message: format!("{} deposits are not available", currency.code),
+        })?;
+
+        for _ in 0..MEMO_ATTEMPTS {
+            if let Some(existing) = sqlx::query_as::<_, DepositAddress>(
+                "SELECT currency, address, tag, created_at FROM deposit_addresses WHERE user_id = $1 AND currency = $2 AND network = $2 AND is_active",
+            )
+            .bind(user_id)
+            .bind(&currency.code)
+            .fetch_optional(&self.db)
+            .await?
+            {
+                return Ok(existing);
+            }
+
+            // Memos are numbers so they are valid destination tags as well as
+            // text memos; a taken one, or a concurrent first request, retries
+            let memo = rand::thread_rng().gen_range(1..=u32::MAX).to_string();
+            let created = sqlx::query_as::<_, DepositAddress>(
+                "INSERT INTO deposit_addresses (user_id, currency, address, tag, network) VALUES ($1, $2, $3, $4, $2) ON CONFLICT DO NOTHING RETURNING currency, address, tag, created_at",
+            )
+            .bind(user_id)
+            .bind(&currency.code)
+            .bind(&address)
+            .bind(&memo)
+            .fetch_optional(&self.db)
+            .await?;
+            if let Some(created) = created {
+                return Ok(created);
+            }
+        }
+        Err(CryptoTradeError::Conflict {
+            message: "Could not assign a deposit memo; try again".to_string(),
+        })
+    }
+
+    /// Applies a report from the deposit watcher, signed with the watcher
+    /// secret in [`DEPOSIT_REPORT_SIGNATURE_HEADER`].
+    pub async fn handle_deposit_report(&self, headers: &HeaderMap, body: &[u8]) -> Result<()> {
+        if self.watcher_secret.is_empty() {
+            return Err(CryptoTradeError::NotFound {
+                message: "Deposit reports are not accepted".to_string(),
+            });
+        }
+        let signature = headers
+            .get(DEPOSIT_REPORT_SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| hex::decode(value.trim()).ok())
+            .ok_or(CryptoTradeError::InvalidSignature)?;
+        mac(&self.watcher_secret)
+            .chain_update(body)
+            .verify_slice(&signature)
+            .map_err(|_| CryptoTradeError::InvalidSignature)?;
+
+        let report: ChainDepositReport = serde_json::from_slice(body).map_err(|e| CryptoTradeError::Validation {
+            message: format!("Invalid deposit report: {}", e),
+        })?;
+        self.record_deposit(report).await
+    }
+
+    /// Records a transfer to a deposit address and credits it once it has
+    /// enough confirmations. Reporting the same transfer again only updates
+    /// its confirmations, so nothing is credited twice.
+    ///
+    /// Unlike fiat payments, chain deposits are not held to the KYC tier's
+    /// daily deposit limit: the funds are on the exchange's address whether
+    /// or not they are credited, and refusing them would only strand them.
+    /// The tier's withdrawal limit still applies to taking them out.
+    pub async fn record_deposit(&self, report: ChainDepositReport) -> Result<()> {
+        let currency = self.currency(&report.currency).await?;
+        if report.amount <= Decimal::ZERO || report.tx_id.trim().is_empty() || report.output_index < 0 {
+            return Err(CryptoTradeError::Validation {
+                message: "A deposit needs a transaction id, an output index and a positive amount".to_string(),
+            });
+        }
+        // Chains without memos identify the user by the address alone
+        let memo = report
+            .memo
+            .as_deref()
+            .map(str::trim)
+            .filter(|memo| !memo.is_empty() && currency.memo_format.is_some());
+        let confirmed = report.confirmations >= currency.deposit_confirmations;
+
+        let user_id = sqlx::query_scalar::<_, Uuid>(
+            "SELECT user_id FROM deposit_addresses WHERE currency = $1 AND address = $2 AND tag IS NOT DISTINCT FROM $3 AND is_active",
+        )
+        .bind(&currency.code)
+        .bind(&report.address)
+        .bind(memo)
+        .fetch_optional(&self.db)
+        .await?;
+        let Some(user_id) = user_id else {
+            if confirmed {
+                let held = sqlx::query(
+                    "INSERT INTO unmatched_deposits (currency, address, memo, tx_id, output_index, amount) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (currency, tx_id, output_index) DO NOTHING",
+                )
+                .bind(&currency.code)
+                .bind(&report.address)
+                .bind(memo)
+                .bind(&report.tx_id)
+                .bind(report.output_index)
+                .bind(report.amount)
+                .execute(&self.db)
+                .await?;
+                if held.rows_affected() > 0 {
+                    tracing::warn!(
+                        "Holding {} {} deposit {}:{} to {} with unknown memo {:?}",
+                        report.amount,
+                        currency.code,
+                        report.tx_id,
+                        report.output_index,
+                        report.address,
+                        memo
+                    );
+                }
+            }
+            return Ok(());
+        };
+
+        let mut tx = self.db.begin().await?;
+        let (id, status) = sqlx::query_as::<_, (Uuid, TransactionStatus)>(
+            r#"
+            INSERT INTO transactions (user_id, transaction_type, currency, amount, status, provider, external_id, address, memo, confirmations, required_confirmations)
+            VALUES ($1, 'deposit', $2, $3, 'pending', 'chain', $4, $5, $6, $7, $8)
+            ON CONFLICT (provider, external_id) WHERE provider IS NOT NULL
+            DO UPDATE SET confirmations = GREATEST(transactions.confirmations, EXCLUDED.confirmations), updated_at = NOW()
+            RETURNING id, status
+            "#,
+        )
+        .bind(user_id)
+        .bind(&currency.code)
+        .bind(report.amount)
+        .bind(deposit_external_id(&currency.code, &report.tx_id, report.output_index))
+        .bind(&report.address)
+        .bind(memo)
+        .bind(report.confirmations)
+        .bind(currency.deposit_confirmations)
+        .fetch_one(&mut *tx)
+        .await?;
+        if status != TransactionStatus::Pending || !confirmed {
+            tx.commit().await?;
+            return Ok(());
+        }
+
+        sqlx::query("UPDATE transactions SET status = 'confirmed', updated_at = NOW() WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        self.credit(tx, id, user_id, &currency.code, report.amount).await
+    }
+
+    /// Deposits waiting for an admin, oldest first.
+    pub async fn unmatched_deposits(&self) -> Result<Vec<UnmatchedDeposit>> {
+        sqlx::query_as::<_, UnmatchedDeposit>(
+            "SELECT * FROM unmatched_deposits WHERE credited_to IS NULL ORDER BY created_at",
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Credits a held deposit to the user who sent it.
+    pub async fn credit_unmatched(&self, admin_id: Uuid, deposit_id: Uuid, user_id: Uuid) -> Result<UnmatchedDeposit> {
+        account_status(&self.db, user_id).await?;
+        let mut tx = self.db.begin().await?;
+        let deposit = sqlx::query_as::<_, UnmatchedDeposit>(
+            "UPDATE unmatched_deposits SET credited_to = $2, credited_by = $3, credited_at = NOW() WHERE id = $1 AND credited_to IS NULL RETURNING *",
+        )
+        .bind(deposit_id)
+        .bind(user_id)
+        .bind(admin_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| CryptoTradeError::NotFound {
+            message: "No uncredited deposit with this id".to_string(),
+        })?;
+        let id = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO transactions (user_id, transaction_type, currency, amount, status, provider, external_id, address, memo) VALUES ($1, 'deposit', $2, $3, 'confirmed', 'chain', $4, $5, $6) RETURNING id",
+        )
+        .bind(user_id)
+        .bind(&deposit.currency)
+        .bind(deposit.amount)
+        .bind(deposit_external_id(&deposit.currency, &deposit.tx_id, deposit.output_index))
+        .bind(&deposit.address)
+        .bind(&deposit.memo)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        self.credit(tx, id, user_id, &deposit.currency, deposit.amount).await?;
+        tracing::info!(
+            "Admin {} credited unmatched deposit {} to user {}",
+            admin_id,
+            deposit.id,
+            user_id
+        );
+        Ok(deposit)
+    }
+
+    /// Adds a confirmed deposit to the balance and posts it to the ledger in
+    /// the same transaction.
+    async fn credit(
+        &self,
+        mut tx: sqlx::Transaction<'_, sqlx::Postgres>,
+        id: Uuid,
+        user_id: Uuid,
+        currency: &str,
+        amount: Decimal,
+    ) -> Result<()> {
+        get_or_create_account(&mut *tx, user_id, currency).await?;
+        let balance = sqlx::query_scalar::<_, Decimal>(
+            "UPDATE accounts SET balance = COALESCE(balance, 0) + $3, available_balance = COALESCE(available_balance, 0) + $3, updated_at = NOW() WHERE user_id = $1 AND currency = $2 RETURNING balance",
+        )
+        .bind(user_id)
+        .bind(currency)
+        .bind(amount)
+        .fetch_one(&mut *tx)
+        .await?;
+        let event = DomainEvent::DepositConfirmed {
+            transaction_id: id,
+            user_id,
+            currency: currency.to_string(),
+            amount,
+        };
+        enqueue_event(&mut *tx, &event).await?;
+
+        let postings = [
+            Posting {
+                user_id: Some(user_id),
+                currency: currency.to_string(),
+                entry_type: LedgerEntryType::Deposit,
+                amount,
+                balance_after: Some(balance),
+            },
+            Posting {
+                user_id: None,
+                currency: currency.to_string(),
+                entry_type: LedgerEntryType::Deposit,
+                amount: -amount,
+                balance_after: None,
+            },
+        ];
+        self.ledger.post_in(&mut tx, id, Some(id), &postings).await?;
+        tx.commit().await?;
+        tracing::info!(
+            "Credited {} {} to user {} for deposit {}",
+            amount,
+            currency,
+            user_id,
+            id
+        );
+
+        Ok(())
+    }
+
+    /// Holds the amount and queues the withdrawal for the hot wallet. The
+    /// memo must suit the currency's chain, and one is required to send to
+    /// the exchange's own shared address.
+    pub async fn create_withdrawal(
+        &self,
+        user_id: Uuid,
+        request: CreateCryptoWithdrawalRequest,
+    ) -> Result<CryptoWithdrawal> {
+        account_status(&self.db, user_id).await?.ensure_can_withdraw()?;
+        request
+            .validate()
+            .map_err(|e| CryptoTradeError::Validation { message: e.to_string() })?;
+        let currency = self.currency(&request.currency).await?;
+        let address = request.address.trim();
+        let memo = request.memo.as_deref().map(str::trim).filter(|memo| !memo.is_empty());
+        validate_memo(&currency, memo)?;
+        if memo.is_none() && currency.deposit_address.as_deref() == Some(address) {
+            return Err(CryptoTradeError::Validation {
+                message: "A memo is required to send to an exchange deposit address".to_string(),
+            });
+        }
+        let amount = request.amount;
+        if amount <= Decimal::ZERO {
+            return Err(CryptoTradeError::Validation {
+                message: "Amount must be positive".to_string(),
+            });
+        }
+        if amount.normalize().scale() > currency.decimals as u32 {
+            return Err(CryptoTradeError::Validation {
+                message: format!("{} amounts have at most {} decimals", currency.code, currency.decimals),
+            });
+        }
+
+        let mut tx = self.db.begin().await?;
+        self.risk_limits
+            .check_daily_crypto_withdrawal(&mut tx, user_id, &currency.code, amount)
+            .await?;
+        let held = sqlx::query(
+            "UPDATE accounts SET available_balance = available_balance - $1, locked_balance = locked_balance + $1, updated_at = NOW() WHERE user_id = $2 AND currency = $3 AND available_balance >= $1",
+        )
+        .bind(amount)
+        .bind(user_id)
+        .bind(&currency.code)
+        .execute(&mut *tx)
+        .await?;
+        if held.rows_affected() == 0 {
+            let available = sqlx::query_scalar::<_, Option<Decimal>>(
+                "SELECT available_balance FROM accounts WHERE user_id = $1 AND currency = $2",
+            )
+            .bind(user_id)
+            .bind(&currency.code)
+            .fetch_optional(&mut *tx)
+            .await?
+            .flatten()
+            .unwrap_or(Decimal::ZERO);
+            return Err(CryptoTradeError::InsufficientBalance {
+                currency: currency.code,
+                required: amount,
+                available,
+            });
+        }
+        let withdrawal = sqlx::query_as::<_, CryptoWithdrawal>(&format!(
+            "INSERT INTO transactions (user_id, transaction_type, currency, amount, fee, status, address, memo) VALUES ($1, 'withdrawal', $2, $3, 0, 'pending', $4, $5) RETURNING {}",
+            WITHDRAWAL_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(&currency.code)
+        .bind(amount)
+        .bind(address)
+        .bind(memo)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(withdrawal)
+    }
+
+    pub async fn list_withdrawals(&self, user_id: Uuid) -> Result<Vec<CryptoWithdrawal>> {
+        sqlx::query_as::<_, CryptoWithdrawal>(&format!(
+            "SELECT {} FROM transactions WHERE user_id = $1 AND transaction_type = 'withdrawal' AND address IS NOT NULL ORDER BY created_at DESC",
+            WITHDRAWAL_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Lets the user take back a withdrawal that has not been sent.
+    pub async fn cancel_withdrawal(&self, user_id: Uuid, withdrawal_id: Uuid) -> Result<CryptoWithdrawal> {
+        let mut tx = self.db.begin().await?;
+        let withdrawal = sqlx::query_as::<_, CryptoWithdrawal>(&format!(
+            "SELECT {} FROM transactions WHERE id = $1 AND user_id = $2 AND transaction_type = 'withdrawal' AND address IS NOT NULL FOR UPDATE",
+            WITHDRAWAL_COLUMNS
+        ))
+        .bind(withdrawal_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| CryptoTradeError::NotFound {
+            message: "Withdrawal not found".to_string(),
+        })?;
+        if withdrawal.status != TransactionStatus::Pending {
+            return Err(CryptoTradeError::Validation {
+                message: "Only pending withdrawals can be cancelled".to_string(),
+            });
+        }
+
+        release(&mut tx, &withdrawal).await?;
+        let withdrawal = sqlx::query_as::<_, CryptoWithdrawal>(&format!(
+            "UPDATE transactions SET status = 'cancelled', updated_at = NOW() WHERE id = $1 RETURNING {}",
+            WITHDRAWAL_COLUMNS
+        ))
+        .bind(withdrawal_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(withdrawal)
+    }
+
+    async fn currency(&self, code: &str) -> Result<Currency> {
+        sqlx::query_as::<_, Currency>("SELECT * FROM currencies WHERE code = $1 AND is_active")
+            .bind(code.trim().to_uppercase())
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(|| CryptoTradeError::NotFound {
+                message: format!("Currency {} is not listed", code),
+            })
+    }
+}
+
+/// The hex HMAC-SHA256 a deposit report is signed with.
+pub fn sign_deposit_report(secret: &str, body: &[u8]) -> String {
+    hex::encode(mac(secret).chain_update(body).finalize().into_bytes())
+}
+
+fn mac(secret: &str) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length")
+}
+
+/// The key a chain deposit's transaction is stored under: one per output,
+/// as a transaction may pay several deposits.
+fn deposit_external_id(currency: &str, tx_id: &str, output_index: i32) -> String {
+    format!("{}:{}:{}", currency, tx_id, output_index)
+}
+
+/// Checks a withdrawal memo against what the currency's chain accepts.
+fn validate_memo(currency: &Currency, memo: Option<&str>) -> Result<()> {
+    let invalid = |message: String| Err(CryptoTradeError::Validation { message });
+    match (currency.memo_format, memo) {
+        (_, None) => Ok(()),
+        (None, Some(_)) => invalid(format!("{} withdrawals do not take a memo", currency.code)),
+        (Some(MemoFormat::Tag), Some(memo)) if memo.parse::<u32>().is_err() => invalid(format!(
+            "{} destination tags are whole numbers up to {}",
+            currency.code,
+            u32::MAX
+        )),
+        (Some(MemoFormat::Text), Some(memo)) if memo.len() > MAX_TEXT_MEMO_BYTES => invalid(format!(
+            "{} memos are at most {} bytes",
+            currency.code, MAX_TEXT_MEMO_BYTES
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Returns a withdrawal's held amount to the available balance.
+async fn release(conn: &mut PgConnection, withdrawal: &CryptoWithdrawal) -> Result<()> {
+    sqlx::query(
+        "UPDATE accounts SET available_balance = available_balance + $1, locked_balance = locked_balance - $1, updated_at = NOW() WHERE user_id = $2 AND currency = $3",
+    )
+    .bind(withdrawal.amount)
+    .bind(withdrawal.user_id)
+    .bind(&withdrawal.currency)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn currency(memo_format: Option<MemoFormat>) -> Currency {
+        Currency {
+            code: "XRP".to_string(),
+            name: "XRP".to_string(),
+            decimals: 6,
+            is_active: true,
+            open_by_default: false,
+            usd_price: None,
+            memo_format,
+            deposit_address: None,
+            deposit_confirmations: 1,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_withdrawal_memos_must_suit_the_chain() {
+        let tag = currency(Some(MemoFormat::Tag));
+        assert!(validate_memo(&tag, None).is_ok());
+        assert!(validate_memo(&tag, Some("4294967295")).is_ok());
+        assert!(validate_memo(&tag, Some("4294967296")).is_err());
+        assert!(validate_memo(&tag, Some("memo")).is_err());
+
+        let text = currency(Some(MemoFormat::Text));
+        assert!(validate_memo(&text, Some("invoice 42")).is_ok());
+        assert!(validate_memo(&text, Some(&"x".repeat(29))).is_err());
+
+        assert!(validate_memo(&currency(None), Some("123")).is_err());
+    }
+
+    #[test]
+    fn test_deposit_reports_are_signed_over_the_body() {
+        let signature = sign_deposit_report("secret", b"{}");
+        assert_eq!(signature.len(), 64);
+        assert_ne!(signature, sign_deposit_report("secret", b"{ }"));
+        assert_ne!(signature, sign_deposit_report("other", b"{}"));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{database::Database, error::CryptoTradeError, models::*, Result};
+use rust_decimal::Decimal;
+use validator::Validate;
+
+/// The currencies table: what the exchange lists, which accounts new users
+/// get and what balances are worth in portfolios.
+#[derive(Clone)]
+pub struct CurrencyService {
+    db: Database,
+}
+
+impl CurrencyService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Every listed currency, inactive ones included, by code.
+    pub async fn list_currencies(&self) -> Result<Vec<Currency>> {
+        sqlx::query_as::<_, Currency>("SELECT * FROM currencies ORDER BY code")
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn create_currency(&self, request: CreateCurrencyRequest) -> Result<Currency> {
+        request.validate().map_err(|e| CryptoTradeError::Validation {
+            message: e.to_string(),
+        })?;
+        let code = request.code.trim().to_uppercase();
+        if !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(CryptoTradeError::Validation {
+                message: "Currency code must be letters and digits only".to_string(),
+            });
+        }
+        validate_usd_price(request.usd_price)?;
+        if request.deposit_address.is_some() && request.memo_format.is_none() {
+            return Err(shared_address_needs_memo());
+        }
+
+        sqlx::query_as::<_, Currency>(
+            "INSERT INTO currencies (code, name, decimals, open_by_default, usd_price, memo_format, deposit_address, deposit_confirmations) VALUES ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, 1)) ON CONFLICT (code) DO NOTHING RETURNING *",
+        )
+        .bind(&code)
+        .bind(request.name.trim())
+        .bind(request.decimals)
+        .bind(request.open_by_default)
+        .bind(request.usd_price)
+        .bind(request.memo_format)
+        .bind(request.deposit_address.as_deref().map(str::trim))
+        .bind(request.deposit_confirmations)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| CryptoTradeError::Validation {
+            message: format!("Currency {} is already listed", code),
+        })
+    }
+
+    pub async fn update_currency(&self, code: &str, request: UpdateCurrencyRequest) -> Result<Currency> {
+        if request.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+            return Err(CryptoTradeError::Validation {
+                message: "Currency name must not be empty".to_string(),
+            });
+        }
+        validate_usd_price(request.usd_price)?;
+        if request.deposit_address.as_deref().is_some_and(|address| address.trim().is_empty()) {
+            return Err(CryptoTradeError::Validation {
+                message: "Deposit address must not be empty".to_string(),
+            });
+        }
+        if request.deposit_confirmations.is_some_and(|confirmations| confirmations < 1) {
+            return Err(CryptoTradeError::Validation {
+                message: "Deposit confirmations must be at least 1".to_string(),
+            });
+        }
+
+        sqlx::query_as::<_, Currency>(
+            r#"
+            UPDATE currencies SET
+                name = COALESCE($2, name),
+                is_active = COALESCE($3, is_active),
+                open_by_default = COALESCE($4, open_by_default),
+                usd_price = COALESCE($5, usd_price),
+                memo_format = COALESCE($6, memo_format),
+                deposit_address = COALESCE($7, deposit_address),
+                deposit_confirmations = COALESCE($8, deposit_confirmations),
+                updated_at = NOW()
+            WHERE code = $1
+            RETURNING *
+            "#,
+        )
+        .bind(code.to_uppercase())
+        .bind(request.name.as_deref().map(str::trim))
+        .bind(request.is_active)
+        .bind(request.open_by_default)
+        .bind(request.usd_price)
+        .bind(request.memo_format)
+        .bind(request.deposit_address.as_deref().map(str::trim))
+        .bind(request.deposit_confirmations)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.constraint() == Some("currencies_shared_address_memo") => {
+                shared_address_needs_memo()
+            }
+            e => e.into(),
+        })?
+        .ok_or_else(|| CryptoTradeError::NotFound {
+            message: format!("Currency {} is not listed", code),
+        })
+    }
+}
+
+fn shared_address_needs_memo() -> CryptoTradeError {
+    CryptoTradeError::Validation {
+        message: "A shared deposit address needs a memo format".to_string(),
+    }
+}
+
+fn validate_usd_price(usd_price: Option<Decimal>) -> Result<()> {
+    if usd_price.is_some_and(|price| price < Decimal::ZERO) {
+        return Err(CryptoTradeError::Validation {
+            message: "USD price must not be negative".to_string(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use crate::{
+    config::DevicesConfig,
+    database::Database,
+    email::{EmailMessage, EmailSender},
+    error::CryptoTradeError,
+    models::*,
+    services::login_risk_service,
+    Result,
+};
+use axum::http::HeaderMap;
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Header a client may send with a stable identifier of its own, such as
+/// one generated at install time; without it the device is told apart by
+/// its user agent and languages
+pub const FINGERPRINT_HEADER: &str = "x-device-fingerprint";
+
+/// Longest user agent stored with a device
+const MAX_USER_AGENT_LEN: usize = 500;
+
+/// The device a login comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    /// SHA-256 hex of what identifies the device
+    pub fingerprint: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    /// Where the CDN placed the request; see [`DeviceInfo::located`]
+    pub country: Option<String>,
+    /// Latitude and longitude in degrees
+    pub coordinates: Option<(f64, f64)>,
+}
+
+impl DeviceInfo {
+    pub fn from_headers(headers: &HeaderMap, ip: Option<IpAddr>) -> Self {
+        let user_agent = header(headers, "user-agent");
+        let identity = match header(headers, FINGERPRINT_HEADER) {
+            Some(fingerprint) => format!("client:{}", fingerprint),
+            None => format!(
+                "agent:{}\n{}",
+                user_agent.unwrap_or_default(),
+                header(headers, "accept-language").unwrap_or_default()
+            ),
+        };
+        Self {
+            fingerprint: sha256_hex(&identity),
+            user_agent: user_agent.map(|agent| agent.chars().take(MAX_USER_AGENT_LEN).collect()),
+            ip_address: ip.map(|ip| ip.to_string()),
+            country: None,
+            coordinates: None,
+        }
+    }
+
+    /// Adds where the request comes from, for the geo-velocity check.
+    pub fn located(self, country: Option<String>, coordinates: Option<(f64, f64)>) -> Self {
+        Self {
+            country,
+            coordinates,
+            ..self
+        }
+    }
+}
+
+fn header<'h>(headers: &'h HeaderMap, name: &str) -> Option<&'h str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Known devices and the new-device login challenge (see migration 047).
+/// A login from a device the user has not confirmed issues no tokens; the
+/// user is emailed a link that confirms it, unless they passed 2FA in the
+/// same login, which confirms it on the spot. Revoking a device makes its
+/// next login confirm again; tokens it already holds run until they expire.
+/// Logins [`crate::services::LoginRiskService`] challenged for improbable
+/// travel are held the same way, whatever the device.
+#[derive(Clone)]
+pub struct DeviceService {
+    db: Database,
+    email: Arc<dyn EmailSender>,
+    config: DevicesConfig,
+}
+
+impl DeviceService {
+    pub fn new(db: Database, email: Arc<dyn EmailSender>, config: DevicesConfig) -> Self {
+        Self { db, email, config }
+    }
+
+    /// Records a device as confirmed, as for the one a user registers from.
+    pub async fn record_confirmed(&self, user_id: Uuid, device: &DeviceInfo) -> Result<UserDevice> {
+        let device = self.record_seen(user_id, device).await?;
+        self.mark_confirmed(device.id).await
+    }
+
+    /// Lets a login from `device` through, or fails with
+    /// [`CryptoTradeError::DeviceConfirmationRequired`] after emailing a
+    /// confirmation link; with [`CryptoTradeError::SuspiciousLogin`] if
+    /// `login` was challenged. Accounts with no device on record yet, from
+    /// before devices were recorded, trust the first one they log in from.
+    pub async fn check_login(&self, user: &User, device: &DeviceInfo, two_factor_passed: bool, login: &LoginEvent) -> Result<()> {
+        let recorded = self.record_seen(user.id, device).await?;
+        if login.status == LoginEventStatus::Challenged {
+            return self.challenge(user, &recorded, login).await;
+        }
+        if recorded.confirmed_at.is_some() && recorded.revoked_at.is_none() {
+            return Ok(());
+        }
+
+        let first_device = sqlx::query_scalar::<_, bool>(
+            "SELECT NOT EXISTS (SELECT 1 FROM user_devices WHERE user_id = $1 AND id <> $2)",
+        )
+        .bind(user.id)
+        .bind(recorded.id)
+        .fetch_one(&self.db)
+        .await?;
+        if !self.config.confirm_new_devices || two_factor_passed || first_device {
+            self.mark_confirmed(recorded.id).await?;
+            return Ok(());
+        }
+
+        self.challenge(user, &recorded, login).await
+    }
+
+    /// Holds `login` until the user follows the link emailed to them, and
+    /// fails with the error telling the client so.
+    async fn challenge(&self, user: &User, device: &UserDevice, login: &LoginEvent) -> Result<()> {
+        let token = hex::encode(rand::random::<[u8; 32]>());
+        let mut tx = self.db.begin().await?;
+        let challenge_id = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO device_challenges (device_id, login_event_id, token_hash, expires_at) VALUES ($1, $2, $3, $4) RETURNING id",
+        )
+        .bind(device.id)
+        .bind(login.id)
+        .bind(sha256_hex(&token))
+        .bind(Utc::now() + Duration::minutes(self.config.challenge_ttl_minutes))
+        .fetch_one(&mut *tx)
+        .await?;
+        // Not a trusted location until confirmed
+        sqlx::query("UPDATE login_events SET status = 'challenged' WHERE id = $1")
+            .bind(login.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        let link = format!("{}?token={}", self.config.confirm_url, token);
+        let (subject, body, error) = if login.status == LoginEventStatus::Challenged {
+            (
+                "Confirm your login from a new location",
+                format!(
+                    "A login to your account was attempted from {}, too far from your last login to have travelled in between.\n\n\
+                     If this was you, confirm the login within {} minutes:\n{}\n\n\
+                     If it was not, change your password now.",
+                    login_risk_service::describe(login),
+                    self.config.challenge_ttl_minutes,
+                    link
+                ),
+                CryptoTradeError::SuspiciousLogin { challenge_id },
+            )
+        } else {
+            (
+                "Confirm your new device",
+                format!(
+                    "A login to your account was attempted from a device we have not seen before ({}, {}).\n\n\
+                     If this was you, confirm the device within {} minutes:\n{}\n\n\
+                     If it was not, change your password now.",
+                    device.user_agent.as_deref().unwrap_or("unknown browser"),
+                    device.ip_address.as_deref().unwrap_or("unknown address"),
+                    self.config.challenge_ttl_minutes,
+                    link
+                ),
+                CryptoTradeError::DeviceConfirmationRequired { challenge_id },
+            )
+        };
+        self.email
+            .send(&EmailMessage {
+                to: user.email.clone(),
+                subject: subject.to_string(),
+                body,
+            })
+            .await?;
+
+        Err(error)
+    }
+
+    /// Confirms the device, and the login held with it, an emailed link was
+    /// sent for. Each link works once, until it expires.
+    pub async fn confirm(&self, token: &str) -> Result<UserDevice> {
+        let (device_id, login_event_id) = sqlx::query_as::<_, (Uuid, Option<Uuid>)>(
+            r#"
+            UPDATE device_challenges SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING device_id, login_event_id
+            "#,
+        )
+        .bind(sha256_hex(token.trim()))
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(CryptoTradeError::InvalidToken)?;
+
+        if let Some(login_event_id) = login_event_id {
+            sqlx::query("UPDATE login_events SET status = 'confirmed', confirmed_at = NOW() WHERE id = $1")
+                .bind(login_event_id)
+                .execute(&self.db)
+                .await?;
+        }
+        self.mark_confirmed(device_id).await
+    }
+
+    /// The user's devices, most recently seen first.
+    pub async fn list_devices(&self, user_id: Uuid) -> Result<Vec<UserDevice>> {
+        sqlx::query_as::<_, UserDevice>("SELECT * FROM user_devices WHERE user_id = $1 ORDER BY last_seen_at DESC")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn revoke_device(&self, user_id: Uuid, device_id: Uuid) -> Result<UserDevice> {
+        sqlx::query_as::<_, UserDevice>(
+            "UPDATE user_devices SET revoked_at = COALESCE(revoked_at, NOW()) WHERE id = $1 AND user_id = $2 RETURNING *",
+        )
+        .bind(device_id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(CryptoTradeError::NotFound {
+            message: "Device not found".to_string(),
+        })
+    }
+
+    async fn record_seen(&self, user_id: Uuid, device: &DeviceInfo) -> Result<UserDevice> {
+        sqlx::query_as::<_, UserDevice>(
+            r#"
+            INSERT INTO user_devices (user_id, fingerprint, user_agent, ip_address)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, fingerprint) DO UPDATE SET
+                user_agent = EXCLUDED.user_agent,
+                ip_address = EXCLUDED.ip_address,
+                last_seen_at = NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(&device.fingerprint)
+        .bind(&device.user_agent)
+        .bind(&device.ip_address)
+        .fetch_one(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+
+    async fn mark_confirmed(&self, device_id: Uuid) -> Result<UserDevice> {
+        sqlx::query_as::<_, UserDevice>(
+            "UPDATE user_devices SET confirmed_at = NOW(), revoked_at = NULL WHERE id = $1 RETURNING *",
+        )
+        .bind(device_id)
+        .fetch_one(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+}
+
+fn sha256_hex(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(entries: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in entries {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_fingerprint_prefers_the_client_identifier() {
+        let browser = headers(&[("user-agent", "Firefox/130"), ("accept-language", "en-GB")]);
+        let other_language = headers(&[("user-agent", "Firefox/130"), ("accept-language", "de-DE")]);
+        let app = headers(&[("user-agent", "Firefox/130"), (FINGERPRINT_HEADER, "install-1")]);
+        let same_app = headers(&[("user-agent", "Safari/17"), (FINGERPRINT_HEADER, "install-1")]);
+
+        let device = DeviceInfo::from_headers(&browser, None);
+        assert_eq!(device.fingerprint.len(), 64);
+        assert_eq!(device.user_agent.as_deref(), Some("Firefox/130"));
+        assert_eq!(device, DeviceInfo::from_headers(&browser, None));
+        assert_ne!(device.fingerprint, DeviceInfo::from_headers(&other_language, None).fingerprint);
+        assert_ne!(device.fingerprint, DeviceInfo::from_headers(&app, None).fingerprint);
+        assert_eq!(
+            DeviceInfo::from_headers(&app, None).fingerprint,
+            DeviceInfo::from_headers(&same_app, "10.0.0.1".parse().ok()).fingerprint
+        );
+    }
+}
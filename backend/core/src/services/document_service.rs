@@ -0,0 +1,488 @@
+use crate::{
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    pdf::PdfDocument,
+    services::ledger_service::balances_until,
+    storage::{self, ObjectStore, PresignedUrl},
+    Result,
+};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const DOCUMENT_COLUMNS: &str = "id, kind, subject, status, error, created_at, completed_at";
+
+/// PDF receipts, trade confirmations and monthly statements. Requests are
+/// queued in `documents` (see migration 022) and rendered by
+/// [`DocumentService::generate_pending`], which the API runs in the
+/// background and kept in object storage; last month's statements are
+/// queued for every user with ledger activity by
+/// [`DocumentService::enqueue_statements`].
+#[derive(Clone)]
+pub struct DocumentService {
+    db: Database,
+    /// The exchange's name, heading every document
+    issuer: String,
+    storage: Arc<dyn ObjectStore>,
+    presign_ttl: Duration,
+}
+
+/// Where a ready document's PDF is fetched from.
+pub enum DocumentBody {
+    /// Rendered before documents moved to object storage
+    Inline(Vec<u8>),
+    Presigned(PresignedUrl),
+}
+
+impl Document {
+    fn with_download_url(mut self) -> Self {
+        if self.status == DocumentStatus::Ready {
+            self.download_url = Some(format!("/api/v1/user/documents/{}/download", self.id));
+        }
+        self
+    }
+
+    pub fn file_name(&self) -> String {
+        let kind = match self.kind {
+            DocumentKind::Receipt => "receipt",
+            DocumentKind::TradeConfirmation => "trade-confirmation",
+            DocumentKind::Statement => "statement",
+        };
+        format!("{}-{}.pdf", kind, self.subject)
+    }
+}
+
+impl DocumentService {
+    pub fn new(db: Database, issuer: String, storage: Arc<dyn ObjectStore>, presign_ttl: Duration) -> Self {
+        Self {
+            db,
+            issuer,
+            storage,
+            presign_ttl,
+        }
+    }
+
+    /// Queues a document, or returns the one already requested for the
+    /// same subject; a failed one is queued again.
+    pub async fn request(&self, user_id: Uuid, request: RequestDocumentRequest) -> Result<Document> {
+        let subject = match request.kind {
+            DocumentKind::Receipt => {
+                let id = required_reference(request.reference_id)?;
+                let transaction = sqlx::query_as::<_, (String, TransactionStatus)>(
+                    "SELECT transaction_type::text, status FROM transactions WHERE id = $1 AND user_id = $2",
+                )
+                .bind(id)
+                .bind(user_id)
+                .fetch_optional(&self.db)
+                .await?;
+                match transaction {
+                    None => {
+                        return Err(CryptoTradeError::NotFound {
+                            message: "Transaction not found".to_string(),
+                        })
+                    }
+                    Some((kind, TransactionStatus::Confirmed)) if kind == "deposit" || kind == "withdrawal" => {}
+                    Some(_) => {
+                        return Err(CryptoTradeError::Validation {
+                            message: "Receipts are only issued for completed deposits and withdrawals".to_string(),
+                        })
+                    }
+                }
+                id.to_string()
+            }
+            DocumentKind::TradeConfirmation => {
+                let id = required_reference(request.reference_id)?;
+                let exists = sqlx::query_scalar::<_, bool>(
+                    "SELECT EXISTS (SELECT 1 FROM trades WHERE id = $1 AND (buyer_user_id = $2 OR seller_user_id = $2))",
+                )
+                .bind(id)
+                .bind(user_id)
+                .fetch_one(&self.db)
+                .await?;
+                if !exists {
+                    return Err(CryptoTradeError::NotFound {
+                        message: "Trade not found".to_string(),
+                    });
+                }
+                id.to_string()
+            }
+            DocumentKind::Statement => {
+                let month = request.month.as_deref().and_then(parse_month).ok_or_else(|| CryptoTradeError::Validation {
+                    message: "Statements need a month as YYYY-MM".to_string(),
+                })?;
+                if month >= month_start(Utc::now().date_naive()) {
+                    return Err(CryptoTradeError::Validation {
+                        message: "Statements are issued once the month has ended".to_string(),
+                    });
+                }
+                month.format("%Y-%m").to_string()
+            }
+        };
+
+        let document = sqlx::query_as::<_, Document>(&format!(
+            r#"
+            INSERT INTO documents (user_id, kind, subject) VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, kind, subject) DO UPDATE SET
+                status = CASE WHEN documents.status = 'failed' THEN 'pending' ELSE documents.status END,
+                error = CASE WHEN documents.status = 'failed' THEN NULL ELSE documents.error END
+            RETURNING {}
+            "#,
+            DOCUMENT_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(request.kind)
+        .bind(subject)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(document.with_download_url())
+    }
+
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<Document>> {
+        let documents = sqlx::query_as::<_, Document>(&format!(
+            "SELECT {} FROM documents WHERE user_id = $1 ORDER BY created_at DESC",
+            DOCUMENT_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(documents.into_iter().map(Document::with_download_url).collect())
+    }
+
+    /// The document and where to fetch its PDF, once ready.
+    pub async fn download(&self, user_id: Uuid, document_id: Uuid) -> Result<(Document, DocumentBody)> {
+        let DocumentContent {
+            document,
+            content,
+            storage_key,
+        } = sqlx::query_as::<_, DocumentContent>(&format!(
+            "SELECT {}, content, storage_key FROM documents WHERE id = $1 AND user_id = $2",
+            DOCUMENT_COLUMNS
+        ))
+        .bind(document_id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| CryptoTradeError::NotFound {
+            message: "Document not found".to_string(),
+        })?;
+
+        let body = match (storage_key, content) {
+            _ if document.status != DocumentStatus::Ready => None,
+            (Some(key), _) => Some(DocumentBody::Presigned(storage::presign(self.storage.as_ref(), &key, self.presign_ttl))),
+            (None, Some(content)) => Some(DocumentBody::Inline(content)),
+            (None, None) => None,
+        };
+        match body {
+            Some(body) => Ok((document.with_download_url(), body)),
+            None => Err(CryptoTradeError::Validation {
+                message: "Document is not ready".to_string(),
+            }),
+        }
+    }
+
+    /// Queues the statement for `month` of every user with ledger entries
+    /// in it; statements already queued are left alone, so this can run
+    /// repeatedly.
+    pub async fn enqueue_statements(&self, month: NaiveDate) -> Result<u64> {
+        let start = month_start(month);
+        let result = sqlx::query(
+            r#"
+            INSERT INTO documents (user_id, kind, subject)
+            SELECT DISTINCT user_id, 'statement'::document_kind, $3
+            FROM ledger_entries
+            WHERE user_id IS NOT NULL AND created_at >= $1 AND created_at < $2
+            ON CONFLICT (user_id, kind, subject) DO NOTHING
+            "#,
+        )
+        .bind(midnight(start))
+        .bind(midnight(next_month(start)))
+        .bind(start.format("%Y-%m").to_string())
+        .execute(&self.db)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Renders up to `limit` pending documents, oldest first; returns how
+    /// many were rendered. Instances running this concurrently take
+    /// different documents.
+    pub async fn generate_pending(&self, limit: i64) -> Result<usize> {
+        let mut generated = 0;
+        while (generated as i64) < limit {
+            let mut tx = self.db.begin().await?;
+            let Some((id, user_id, kind, subject)) = sqlx::query_as::<_, (Uuid, Uuid, DocumentKind, String)>(
+                "SELECT id, user_id, kind, subject FROM documents WHERE status = 'pending' ORDER BY created_at LIMIT 1 FOR UPDATE SKIP LOCKED",
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+            else {
+                break;
+            };
+
+            let key = format!("documents/{}/{}.pdf", user_id, id);
+            let stored = match self.render(user_id, kind, &subject).await {
+                Ok(pdf) => self.storage.put(&key, pdf.render(), "application/pdf").await,
+                Err(e) => Err(e),
+            };
+            match stored {
+                Ok(()) => {
+                    sqlx::query("UPDATE documents SET status = 'ready', storage_key = $2, completed_at = NOW() WHERE id = $1")
+                        .bind(id)
+                        .bind(&key)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to generate document {}: {}", id, e);
+                    sqlx::query("UPDATE documents SET status = 'failed', error = $2, completed_at = NOW() WHERE id = $1")
+                        .bind(id)
+                        .bind(e.to_string())
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+            tx.commit().await?;
+            generated += 1;
+        }
+
+        Ok(generated)
+    }
+
+    async fn render(&self, user_id: Uuid, kind: DocumentKind, subject: &str) -> Result<PdfDocument> {
+        let (username, email) = sqlx::query_as::<_, (String, String)>("SELECT username, email FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&self.db)
+            .await?;
+        let holder = format!("{} <{}>", username, email);
+
+        match kind {
+            DocumentKind::Receipt => self.render_receipt(user_id, &holder, subject.parse().map_err(invalid_subject)?).await,
+            DocumentKind::TradeConfirmation => {
+                self.render_trade_confirmation(user_id, &holder, subject.parse().map_err(invalid_subject)?).await
+            }
+            DocumentKind::Statement => {
+                let month = parse_month(subject).ok_or_else(|| invalid_subject(subject))?;
+                self.render_statement(user_id, &holder, month).await
+            }
+        }
+    }
+
+    async fn render_receipt(&self, user_id: Uuid, holder: &str, transaction_id: Uuid) -> Result<PdfDocument> {
+        let (transaction_type, currency, amount, fee, provider, external_id, bank_rail, bank_account, created_at, updated_at) =
+            sqlx::query_as::<_, ReceiptRow>(
+                r#"
+                SELECT t.transaction_type::text, t.currency, t.amount, t.fee, t.provider, t.external_id,
+                       b.rail::text, b.account_number, t.created_at, t.updated_at
+                FROM transactions t
+                LEFT JOIN bank_accounts b ON b.id = t.bank_account_id
+                WHERE t.id = $1 AND t.user_id = $2 AND t.status = 'confirmed'
+                "#,
+            )
+            .bind(transaction_id)
+            .bind(user_id)
+            .fetch_one(&self.db)
+            .await?;
+        let withdrawal = transaction_type == "withdrawal";
+        let fee = fee.unwrap_or_default();
+
+        let mut pdf = PdfDocument::new(format!(
+            "{} - {} receipt",
+            self.issuer,
+            if withdrawal { "Withdrawal" } else { "Deposit" }
+        ));
+        pdf.field("Receipt number", transaction_id)
+            .field("Issued", Utc::now().format("%Y-%m-%d %H:%M UTC"))
+            .field("Account holder", holder)
+            .blank()
+            .field("Amount", format!("{} {}", amount, currency))
+            .field("Fee", format!("{} {}", fee, currency));
+        if withdrawal {
+            pdf.field("Total debited", format!("{} {}", amount + fee, currency));
+        } else {
+            pdf.field("Total credited", format!("{} {}", amount - fee, currency));
+        }
+        match (bank_rail, bank_account) {
+            (Some(rail), Some(account)) => {
+                let last4 = &account[account.len().saturating_sub(4)..];
+                pdf.field("Method", format!("{} to account ending {}", rail.to_uppercase(), last4));
+            }
+            _ => {
+                pdf.field("Method", provider.as_deref().unwrap_or("on-chain").replace('_', " "));
+            }
+        }
+        if let Some(external_id) = external_id {
+            pdf.field("Reference", external_id);
+        }
+        if let Some(created_at) = created_at {
+            pdf.field("Requested", created_at.format("%Y-%m-%d %H:%M UTC"));
+        }
+        if let Some(updated_at) = updated_at {
+            pdf.field("Completed", updated_at.format("%Y-%m-%d %H:%M UTC"));
+        }
+
+        Ok(pdf)
+    }
+
+    async fn render_trade_confirmation(&self, user_id: Uuid, holder: &str, trade_id: Uuid) -> Result<PdfDocument> {
+        let (symbol, quote_currency, buyer_user_id, price, quantity, buyer_fee, seller_fee, created_at) =
+            sqlx::query_as::<_, TradeRow>(
+                r#"
+                SELECT p.symbol, p.quote_currency, t.buyer_user_id, t.price, t.quantity,
+                       t.buyer_fee, t.seller_fee, t.created_at
+                FROM trades t
+                JOIN trading_pairs p ON p.id = t.trading_pair_id
+                WHERE t.id = $1 AND (t.buyer_user_id = $2 OR t.seller_user_id = $2)
+                "#,
+            )
+            .bind(trade_id)
+            .bind(user_id)
+            .fetch_one(&self.db)
+            .await?;
+        let bought = buyer_user_id == user_id;
+        let price = price.unwrap_or_default();
+        let quantity = quantity.unwrap_or_default();
+        let fee = if bought { buyer_fee } else { seller_fee }.unwrap_or_default();
+        let notional = price * quantity;
+
+        let mut pdf = PdfDocument::new(format!("{} - Trade confirmation", self.issuer));
+        pdf.field("Trade", trade_id)
+            .field("Issued", Utc::now().format("%Y-%m-%d %H:%M UTC"))
+            .field("Account holder", holder)
+            .blank()
+            .field("Executed", created_at.format("%Y-%m-%d %H:%M:%S UTC"))
+            .field("Pair", &symbol)
+            .field("Side", if bought { "Buy" } else { "Sell" })
+            .field("Quantity", quantity)
+            .field("Price", format!("{} {}", price, quote_currency))
+            .field("Notional", format!("{} {}", notional, quote_currency))
+            .field("Fee", format!("{} {}", fee, quote_currency))
+            .field(
+                if bought { "Total paid" } else { "Total received" },
+                format!("{} {}", if bought { notional + fee } else { notional - fee }, quote_currency),
+            );
+
+        Ok(pdf)
+    }
+
+    async fn render_statement(&self, user_id: Uuid, holder: &str, month: NaiveDate) -> Result<PdfDocument> {
+        let start = midnight(month);
+        let end = midnight(next_month(month));
+        let entries = sqlx::query_as::<_, LedgerEntry>(
+            "SELECT * FROM ledger_entries WHERE user_id = $1 AND created_at >= $2 AND created_at < $3 ORDER BY currency, sequence",
+        )
+        .bind(user_id)
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.db)
+        .await?;
+        // Each currency opens at its balance before the month
+        let openings = balances_until(&self.db, user_id, start, false).await?;
+
+        let mut currencies: Vec<&str> = openings.iter().map(|opening| opening.currency.as_str()).collect();
+        currencies.extend(entries.iter().map(|entry| entry.currency.as_str()));
+        currencies.sort_unstable();
+        currencies.dedup();
+
+        let mut pdf = PdfDocument::new(format!("{} - Statement for {}", self.issuer, month.format("%B %Y")));
+        pdf.field("Account holder", holder)
+            .field("Period", format!("{} to {}", month, next_month(month).pred_opt().unwrap_or(month)))
+            .field("Issued", Utc::now().format("%Y-%m-%d %H:%M UTC"));
+        for currency in currencies {
+            let opening = openings
+                .iter()
+                .find(|opening| opening.currency == currency)
+                .map(|opening| opening.balance)
+                .unwrap_or_default();
+            let mut closing = opening;
+
+            pdf.blank().line(currency).line(format!("{:<40}{:>30}", "Opening balance", opening));
+            for entry in entries.iter().filter(|entry| entry.currency == currency) {
+                closing = entry.balance_after.unwrap_or(closing + entry.amount);
+                pdf.line(format!(
+                    "{:<18}{:<12}{:>20}{:>20}",
+                    entry.created_at.format("%Y-%m-%d %H:%M"),
+                    format!("{:?}", entry.entry_type).to_lowercase(),
+                    signed(entry.amount),
+                    closing
+                ));
+            }
+            pdf.line(format!("{:<40}{:>30}", "Closing balance", closing));
+        }
+
+        Ok(pdf)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct DocumentContent {
+    #[sqlx(flatten)]
+    document: Document,
+    content: Option<Vec<u8>>,
+    storage_key: Option<String>,
+}
+
+type ReceiptRow = (
+    String,
+    String,
+    Decimal,
+    Option<Decimal>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<DateTime<Utc>>,
+    Option<DateTime<Utc>>,
+);
+
+type TradeRow = (
+    String,
+    String,
+    Uuid,
+    Option<Decimal>,
+    Option<Decimal>,
+    Option<Decimal>,
+    Option<Decimal>,
+    DateTime<Utc>,
+);
+
+fn required_reference(reference_id: Option<Uuid>) -> Result<Uuid> {
+    reference_id.ok_or_else(|| CryptoTradeError::Validation {
+        message: "reference_id is required".to_string(),
+    })
+}
+
+fn invalid_subject(subject: impl std::fmt::Display) -> CryptoTradeError {
+    CryptoTradeError::Validation {
+        message: format!("Invalid document subject {}", subject),
+    }
+}
+
+/// The first day of a `YYYY-MM` month.
+fn parse_month(month: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").ok()
+}
+
+fn month_start(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+fn next_month(month: NaiveDate) -> NaiveDate {
+    month + Months::new(1)
+}
+
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+fn signed(amount: Decimal) -> String {
+    if amount > Decimal::ZERO {
+        format!("+{}", amount)
+    } else {
+        amount.to_string()
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    config::DustConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    oracle::{LastTradePrices, PriceOracle},
+    services::{user_service::account_status, LedgerService, Posting},
+    Result,
+};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Decimals oracle prices are kept to; inverted prices need more than amounts
+const PRICE_SCALE: u32 = 12;
+
+/// Sweeps balances too small to trade into one currency. Each is valued at
+/// the oracle price and the fee is credited to the treasury; the balances,
+/// the ledger journal and the record of the conversion are written in one
+/// transaction.
+#[derive(Clone)]
+pub struct DustService {
+    db: Database,
+    ledger: LedgerService,
+    config: DustConfig,
+    oracle: Arc<dyn PriceOracle>,
+}
+
+impl DustService {
+    /// Prices dust at the exchange's last trades.
+    pub fn new(db: Database, ledger: LedgerService, config: DustConfig) -> Self {
+        Self {
+            oracle: Arc::new(LastTradePrices::new(db.clone())),
+            db,
+            ledger,
+            config,
+        }
+    }
+
+    pub fn with_oracle(mut self, oracle: Arc<dyn PriceOracle>) -> Self {
+        self.oracle = oracle;
+        self
+    }
+
+    /// Converts every available balance worth less than the configured
+    /// threshold, or only those in `request.currencies`, into the target
+    /// currency. Balances the oracle cannot price are left alone.
+    pub async fn convert(&self, user_id: Uuid, request: DustConvertRequest) -> Result<DustConversion> {
+        account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        let target = request.target_currency.trim().to_uppercase();
+        if target.is_empty() {
+            return Err(CryptoTradeError::Validation {
+                message: "target_currency is required".to_string(),
+            });
+        }
+        let only: Option<Vec<String>> = request
+            .currencies
+            .map(|currencies| currencies.iter().map(|currency| currency.trim().to_uppercase()).collect());
+
+        let mut tx = self.db.begin().await?;
+        let balances = sqlx::query_as::<_, (String, Decimal)>(
+            "SELECT currency, available_balance FROM accounts WHERE user_id = $1 AND currency <> $2 AND available_balance > 0 AND ($3::text[] IS NULL OR currency = ANY($3)) ORDER BY currency FOR UPDATE",
+        )
+        .bind(user_id)
+        .bind(&target)
+        .bind(&only)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut items = Vec::new();
+        for (currency, amount) in balances {
+            let Some(value) = self.oracle.price(&currency, &self.config.reference_currency).await? else {
+                continue;
+            };
+            if amount * value >= self.config.threshold {
+                continue;
+            }
+            let Some(price) = self.oracle.price(&currency, &target).await? else {
+                continue;
+            };
+            let price = price.round_dp_with_strategy(PRICE_SCALE, RoundingStrategy::ToZero);
+            let (converted, fee) = convert(amount, price, self.config.fee_rate);
+            if converted.is_zero() {
+                continue;
+            }
+            items.push(DustConversionItem {
+                currency,
+                amount,
+                price,
+                converted,
+                fee,
+            });
+        }
+        if items.is_empty() {
+            return Err(CryptoTradeError::Validation {
+                message: format!("No balances worth less than {} {} can be converted to {}", self.config.threshold, self.config.reference_currency, target),
+            });
+        }
+        let converted: Decimal = items.iter().map(|item| item.converted).sum();
+        let fee: Decimal = items.iter().map(|item| item.fee).sum();
+
+        let posting = |user_id: Option<Uuid>, currency: &str, entry_type, amount, balance_after| Posting {
+            user_id,
+            currency: currency.to_string(),
+            entry_type,
+            amount,
+            balance_after,
+        };
+        let mut postings = Vec::new();
+        for item in &items {
+            let balance = sqlx::query_scalar::<_, Decimal>(
+                "UPDATE accounts SET balance = balance - $3, available_balance = available_balance - $3, updated_at = NOW() WHERE user_id = $1 AND currency = $2 RETURNING balance",
+            )
+            .bind(user_id)
+            .bind(&item.currency)
+            .bind(item.amount)
+            .fetch_one(&mut *tx)
+            .await?;
+            postings.push(posting(Some(user_id), &item.currency, LedgerEntryType::Trade, -item.amount, Some(balance)));
+            postings.push(posting(None, &item.currency, LedgerEntryType::Trade, item.amount, None));
+        }
+
+        let target_balance = sqlx::query_scalar::<_, Decimal>(
+            r#"
+            INSERT INTO accounts (user_id, currency, balance, available_balance, locked_balance)
+            VALUES ($1, $2, $3, $3, 0)
+            ON CONFLICT (user_id, currency) DO UPDATE SET
+                balance = COALESCE(accounts.balance, 0) + EXCLUDED.balance,
+                available_balance = COALESCE(accounts.available_balance, 0) + EXCLUDED.available_balance,
+                updated_at = NOW()
+            RETURNING balance
+            "#,
+        )
+        .bind(user_id)
+        .bind(&target)
+        .bind(converted - fee)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // Each item's proceeds and fee are entries of their own, so the
+        // running balance steps through them up to the final one
+        let mut running = target_balance - (converted - fee);
+        for item in &items {
+            running += item.converted;
+            postings.push(posting(Some(user_id), &target, LedgerEntryType::Trade, item.converted, Some(running)));
+            running -= item.fee;
+            postings.push(posting(Some(user_id), &target, LedgerEntryType::Fee, -item.fee, Some(running)));
+        }
+        postings.push(posting(None, &target, LedgerEntryType::Trade, -converted, None));
+        if !fee.is_zero() {
+            let treasury = self.ledger.credit_treasury_in(&mut tx, &target, fee).await?;
+            postings.push(posting(None, &target, LedgerEntryType::Fee, fee, Some(treasury)));
+        }
+        postings.retain(|posting| !posting.amount.is_zero());
+
+        let mut conversion = sqlx::query_as::<_, DustConversion>(
+            "INSERT INTO dust_conversions (user_id, target_currency, amount, fee) VALUES ($1, $2, $3, $4) RETURNING id, target_currency, amount, fee, created_at",
+        )
+        .bind(user_id)
+        .bind(&target)
+        .bind(converted - fee)
+        .bind(fee)
+        .fetch_one(&mut *tx)
+        .await?;
+        for item in &items {
+            sqlx::query(
+                "INSERT INTO dust_conversion_items (conversion_id, currency, amount, price, converted, fee) VALUES ($1, $2, $3, $4, $5, $6)",
+            )
+            .bind(conversion.id)
+            .bind(&item.currency)
+            .bind(item.amount)
+            .bind(item.price)
+            .bind(item.converted)
+            .bind(item.fee)
+            .execute(&mut *tx)
+            .await?;
+        }
+        self.ledger.post_in(&mut tx, conversion.id, Some(conversion.id), &postings).await?;
+        tx.commit().await?;
+
+        tracing::info!("User {} converted {} balances into {} {}", user_id, items.len(), conversion.amount, target);
+        conversion.items = items;
+        Ok(conversion)
+    }
+}
+
+/// `amount` converted at `price` and the fee kept from it, both in the
+/// target currency. The conversion rounds down and the fee rounds up, so
+/// neither ever credits more than the balance was worth.
+fn convert(amount: Decimal, price: Decimal, fee_rate: Decimal) -> (Decimal, Decimal) {
+    let converted = (amount * price).round_dp_with_strategy(AMOUNT_SCALE, RoundingStrategy::ToZero);
+    let fee = (converted * fee_rate)
+        .round_dp_with_strategy(AMOUNT_SCALE, RoundingStrategy::AwayFromZero)
+        .min(converted);
+    (converted, fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_convert_rounds_in_the_exchanges_favour() {
+        assert_eq!(convert(dec("0.5"), dec("3"), dec("0.02")), (dec("1.5"), dec("0.03")));
+        // 3 USD into BTC at an inverted BTC-USD price of 60000
+        let (converted, fee) = convert(dec("3"), dec("0.000016666667"), dec("0.02"));
+        assert_eq!(converted, dec("0.00005"));
+        assert_eq!(fee, dec("0.000001"));
+        // A fee smaller than the last decimal still costs one unit of it
+        assert_eq!(convert(dec("1"), dec("0.00000010"), dec("0.02")), (dec("0.0000001"), dec("0.00000001")));
+        assert_eq!(convert(dec("1"), dec("0.000000001"), dec("0.02")), (Decimal::ZERO, Decimal::ZERO));
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{
+    database::Database,
+    matching::{replay, BookSnapshot, EngineEvent, EngineEventKind, LimitOrderBook, NewOrder, Replay},
+    Result,
+};
+use rust_decimal::Decimal;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// One command's events as journaled, with what was locked for the order
+/// it adds if that order has no row yet.
+pub struct JournalEntry<'a> {
+    pub events: &'a [EngineEvent],
+    pub new_order_lock: Option<Decimal>,
+}
+
+#[derive(Clone)]
+pub struct EventLogService {
+    db: Database,
+}
+
+impl EventLogService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Appends the events of a batch of commands in a single transaction, so
+    /// each command's input and outputs are either all journaled or not at
+    /// all. The orders that `new_order_lock` entries add are persisted with
+    /// them, so no order is in a book without its row.
+    ///
+    /// With `async_commit` the commit does not wait for the WAL flush. A
+    /// crash can then lose the journal's last moments, but only whole
+    /// batches and never one something later depends on: any later
+    /// synchronous commit, such as a trade settling a fill, flushes the WAL
+    /// up to it first.
+    pub async fn append_batch(&self, entries: &[JournalEntry<'_>], async_commit: bool) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+        if async_commit {
+            sqlx::query("SET LOCAL synchronous_commit = off").execute(&mut *tx).await?;
+        }
+
+        let events: Vec<&EngineEvent> = entries.iter().flat_map(|entry| entry.events).collect();
+        let payloads = events
+            .iter()
+            .map(|event| serde_json::to_value(&event.kind))
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        sqlx::query!(
+            "INSERT INTO engine_events (trading_pair_id, sequence, event_type, order_id, payload, created_at) SELECT * FROM UNNEST($1::UUID[], $2::BIGINT[], $3::VARCHAR[], $4::UUID[], $5::JSONB[], $6::TIMESTAMPTZ[])",
+            &events.iter().map(|event| event.trading_pair_id).collect::<Vec<_>>(),
+            &events.iter().map(|event| event.sequence).collect::<Vec<_>>(),
+            &events.iter().map(|event| event.kind.event_type().to_string()).collect::<Vec<_>>(),
+            // A nullable element type, which the macro cannot check
+            events.iter().map(|event| event.kind.order_id()).collect::<Vec<_>>() as _,
+            &payloads,
+            &events.iter().map(|event| event.created_at).collect::<Vec<_>>(),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let added: Vec<(&EngineEvent, &NewOrder, Decimal)> = entries
+            .iter()
+            .filter_map(|entry| match (entry.events.first(), entry.new_order_lock) {
+                (Some(event @ EngineEvent { kind: EngineEventKind::OrderAdded(order), .. }), Some(locked)) => {
+                    Some((event, order, locked))
+                }
+                _ => None,
+            })
+            .collect();
+        if !added.is_empty() {
+            insert_orders(&mut tx, &added).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_events(
+        &self,
+        trading_pair_id: Uuid,
+        after_sequence: i64,
+        up_to_sequence: Option<i64>,
+    ) -> Result<Vec<EngineEvent>> {
+        let rows = sqlx::query!(
+            "SELECT trading_pair_id, sequence, payload, created_at FROM engine_events WHERE trading_pair_id = $1 AND sequence > $2 AND ($3::BIGINT IS NULL OR sequence <= $3) ORDER BY sequence ASC",
+            trading_pair_id,
+            after_sequence,
+            up_to_sequence,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(EngineEvent {
+                    trading_pair_id: row.trading_pair_id,
+                    sequence: row.sequence,
+                    kind: serde_json::from_value(row.payload)?,
+                    created_at: row.created_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Rebuilds a pair's book from an empty state, optionally stopping after
+    /// the command that contains `up_to_sequence` to inspect the book as it was
+    /// at that point.
+    pub async fn replay(&self, trading_pair_id: Uuid, up_to_sequence: Option<i64>) -> Result<Replay> {
+        let mut events = self.get_events(trading_pair_id, 0, None).await?;
+
+        if let Some(up_to) = up_to_sequence {
+            let cutoff = events
+                .iter()
+                .position(|event| event.sequence > up_to && event.kind.is_input())
+                .unwrap_or(events.len());
+            events.truncate(cutoff);
+        }
+
+        Ok(replay(LimitOrderBook::new(trading_pair_id), &events))
+    }
+
+    /// Restores a pair's book from its latest snapshot plus the journal tail.
+    pub async fn load_book(&self, trading_pair_id: Uuid) -> Result<Replay> {
+        let book = match self.latest_snapshot(trading_pair_id).await? {
+            Some(snapshot) => LimitOrderBook::from_snapshot(&snapshot),
+            None => LimitOrderBook::new(trading_pair_id),
+        };
+        let events = self.get_events(trading_pair_id, book.last_sequence(), None).await?;
+
+        Ok(replay(book, &events))
+    }
+
+    pub async fn save_snapshot(&self, snapshot: &BookSnapshot, keep: i64) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO order_book_snapshots (trading_pair_id, sequence, orders, in_auction, created_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (trading_pair_id, sequence) DO NOTHING",
+            snapshot.trading_pair_id,
+            snapshot.sequence,
+            serde_json::to_value(&snapshot.orders)?,
+            snapshot.in_auction,
+            snapshot.created_at,
+        )
+        .execute(&self.db)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM order_book_snapshots WHERE trading_pair_id = $1 AND sequence NOT IN (SELECT sequence FROM order_book_snapshots WHERE trading_pair_id = $1 ORDER BY sequence DESC LIMIT $2)",
+            snapshot.trading_pair_id,
+            keep,
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn latest_snapshot(&self, trading_pair_id: Uuid) -> Result<Option<BookSnapshot>> {
+        let row = sqlx::query!(
+            "SELECT trading_pair_id, sequence, orders, in_auction, created_at FROM order_book_snapshots WHERE trading_pair_id = $1 ORDER BY sequence DESC LIMIT 1",
+            trading_pair_id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        row.map(|row| {
+            Ok(BookSnapshot {
+                trading_pair_id: row.trading_pair_id,
+                sequence: row.sequence,
+                orders: serde_json::from_value(row.orders)?,
+                in_auction: row.in_auction,
+                created_at: row.created_at,
+            })
+        })
+        .transpose()
+    }
+}
+
+/// Persists orders added to the book, open at their full quantity and
+/// holding what was locked for them, with the history an order placed
+/// outside the journal gets: created by its owner, then accepted. Their
+/// rows now account for their locks, so the pending locks are cleared.
+async fn insert_orders(tx: &mut Transaction<'_, Postgres>, added: &[(&EngineEvent, &NewOrder, Decimal)]) -> Result<()> {
+    let ids: Vec<Uuid> = added.iter().map(|(_, order, _)| order.order_id).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO orders (id, user_id, trading_pair_id, order_type, side, quantity, price, filled_quantity, remaining_quantity, status, time_in_force, locked_amount, created_at, updated_at)
+        SELECT id, user_id, trading_pair_id, order_type, side, quantity, price, 0, quantity, 'open', time_in_force, locked_amount, created_at, created_at
+        FROM UNNEST($1::UUID[], $2::UUID[], $3::UUID[], $4::order_type[], $5::order_side[], $6::DECIMAL[], $7::DECIMAL[], $8::time_in_force[], $9::DECIMAL[], $10::TIMESTAMPTZ[])
+            AS added(id, user_id, trading_pair_id, order_type, side, quantity, price, time_in_force, locked_amount, created_at)
+        "#,
+        &ids,
+        &added.iter().map(|(_, order, _)| order.user_id).collect::<Vec<_>>(),
+        &added.iter().map(|(event, _, _)| event.trading_pair_id).collect::<Vec<_>>(),
+        // Enum and nullable element types, which the macro cannot check
+        added.iter().map(|(_, order, _)| order.order_type).collect::<Vec<_>>() as _,
+        added.iter().map(|(_, order, _)| order.side).collect::<Vec<_>>() as _,
+        &added.iter().map(|(_, order, _)| order.quantity).collect::<Vec<_>>(),
+        added.iter().map(|(_, order, _)| order.price).collect::<Vec<_>>() as _,
+        added.iter().map(|(_, order, _)| order.time_in_force).collect::<Vec<_>>() as _,
+        &added.iter().map(|(_, _, locked)| *locked).collect::<Vec<_>>(),
+        &added.iter().map(|(event, _, _)| event.created_at).collect::<Vec<_>>(),
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    // Ordered so each order's events are sequenced created, then accepted
+    sqlx::query!(
+        r#"
+        INSERT INTO order_events (order_id, event_type, status, filled_quantity, remaining_quantity, actor_id)
+        SELECT orders.id, transition.event_type, transition.status, 0, orders.quantity,
+               CASE WHEN transition.event_type = 'created' THEN orders.user_id END
+        FROM orders
+        CROSS JOIN (VALUES (1, 'created'::order_event_type, 'pending'::order_status), (2, 'accepted', 'open')) AS transition(step, event_type, status)
+        WHERE orders.id = ANY($1)
+        ORDER BY orders.created_at, orders.id, transition.step
+        "#,
+        &ids,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM pending_order_locks WHERE order_id = ANY($1)", &ids)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use crate::{
+    config::{Config, MarketDataTier},
+    models::*,
+    services::{FeatureFlagService, RuntimeConfigService, TradingPairService},
+    Result,
+};
+use chrono::{NaiveDate, Utc};
+use uuid::Uuid;
+
+/// The API version served under `/api/{version}`
+pub const API_VERSION: &str = "v1";
+
+/// Changes to the public API worth a client's attention, newest first. Add
+/// an entry with every endpoint, field or error code clients can rely on.
+const API_CHANGELOG: [(&str, &str); 20] = [
+    ("2026-10-17", "Memo chains: GET /api/v1/deposits/crypto/{currency}/address returns a shared address with the caller's memo, and /api/v1/withdrawals/crypto takes a validated memo"),
+    ("2026-10-17", "Logins from improbably far since the last one answer SUSPICIOUS_LOGIN until confirmed by email or 2FA; admins read risk scores at /api/v1/admin/users/{id}/login-risk"),
+    ("2026-10-17", "KYC tiers cap daily deposits, withdrawals and open notional; the profile shows kyc_tier and kyc_limits"),
+    ("2026-10-17", "Market data routes by symbol under /api/v2; the /api/v1 routes taking a pair id are deprecated and sunset on 2027-04-17"),
+    ("2026-10-17", "Oversized bodies answer PAYLOAD_TOO_LARGE (413), slow requests REQUEST_TIMEOUT (408) and excess concurrent exports SERVER_BUSY (503)"),
+    ("2026-10-17", "SERVICE_UNAVAILABLE (503) when Redis is down for signed requests; market data is served without rate limits meanwhile"),
+    ("2026-10-17", "POST /api/v1/orders/simulate returns the fills, slippage and fees an order would get, without placing it"),
+    ("2026-10-17", "Target allocations at /api/v1/portfolio/allocation, with rebalance plans placed on confirmation"),
+    ("2026-10-17", "fields= prunes the portfolio, exchange info and tickers to the named fields"),
+    ("2026-10-17", "POST /api/v1/orders/import places limit orders from a CSV, with dry runs; large files are processed in the background"),
+    ("2026-10-17", "TradingView-style alerts at /api/v1/webhooks/alerts/{id} place orders from templates at /api/v1/alert-templates"),
+    ("2026-10-17", "POST /api/v1/graphql queries portfolio, orders with fills and pair, trades and market data"),
+    ("2026-10-17", "GET /api/v1/capabilities reports the optional subsystems enabled on a deployment"),
+    ("2026-10-17", "Support tickets under /api/v1/support/tickets, with file attachments"),
+    ("2026-10-17", "Fiat withdrawals above a threshold stay pending until a second admin approves them"),
+    ("2026-10-17", "Registration, and logins after repeated failures, take a captcha_token (CAPTCHA_REQUIRED)"),
+    ("2026-10-17", "Logins from new devices answer DEVICE_CONFIRMATION_REQUIRED; known devices are listed at /api/v1/user/sessions"),
+    ("2026-10-17", "White-label tenants: branding at /api/v1/tenants/{slug} and the X-Tenant header"),
+    ("2026-10-17", "Trade and order exports stream as resumable NDJSON under /api/v1/exports"),
+    ("2026-10-17", "GET /api/v1/time and GET /api/v1/exchange-info"),
+];
+
+/// Assembles the exchange info document from the listed pairs, the static
+/// config and the runtime settings in force.
+#[derive(Clone)]
+pub struct ExchangeInfoService {
+    trading_pair_service: TradingPairService,
+    runtime: RuntimeConfigService,
+    feature_flags: FeatureFlagService,
+    rate_limits: Vec<RateLimitInfo>,
+    recv_window_ms: u64,
+    sandbox: bool,
+    graphql: bool,
+    fiat: FiatCapabilities,
+}
+
+impl ExchangeInfoService {
+    pub fn new(
+        trading_pair_service: TradingPairService,
+        runtime: RuntimeConfigService,
+        feature_flags: FeatureFlagService,
+        config: &Config,
+    ) -> Self {
+        let market_data = &config.market_data;
+        Self {
+            trading_pair_service,
+            runtime,
+            feature_flags,
+            rate_limits: vec![
+                rate_limit("anonymous", &market_data.anonymous, None),
+                rate_limit("api_key", &market_data.api_key, Some(market_data.api_key_daily_quota)),
+            ],
+            recv_window_ms: config.api_keys.recv_window_ms,
+            sandbox: config.sandbox.enabled,
+            graphql: config.graphql.enabled,
+            fiat: FiatCapabilities {
+                card_deposits: config.payments.card.enabled,
+                bank_transfer_deposits: config.payments.bank_transfer.enabled,
+                withdrawals: true,
+                deposit_currency: config.payments.currency.clone(),
+            },
+        }
+    }
+
+    /// The subsystems this deployment runs, the API version and its
+    /// changelog. Margin and staking are not part of this build.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            api: ApiVersionInfo {
+                version: API_VERSION.to_string(),
+                supported_versions: vec![API_VERSION.to_string()],
+                build: env!("CARGO_PKG_VERSION").to_string(),
+                openapi_url: "/api-doc/openapi.json".to_string(),
+            },
+            subsystems: Subsystems {
+                spot: true,
+                margin: false,
+                staking: false,
+                fiat: self.fiat.clone(),
+                sandbox: self.sandbox,
+                algo_orders: true,
+                grid_bots: true,
+                copy_trading: true,
+                support_tickets: true,
+                graphql: self.graphql,
+            },
+            features: self
+                .feature_flags
+                .list()
+                .into_iter()
+                .filter(|flag| self.feature_flags.is_enabled(&flag.key, None))
+                .map(|flag| flag.key)
+                .collect(),
+            changelog: changelog(),
+        }
+    }
+
+    pub fn server_time(&self) -> ServerTime {
+        ServerTime {
+            server_time: Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// The rules and the pairs `tenant_id` can trade.
+    pub async fn exchange_info(&self, tenant_id: Option<Uuid>) -> Result<ExchangeInfo> {
+        let settings = self.runtime.settings();
+        let symbols = self
+            .trading_pair_service
+            .list_trading_pairs(tenant_id)
+            .await?
+            .into_iter()
+            .map(|listing| {
+                let pair = &listing.trading_pair;
+                let filters = SymbolFilters {
+                    min_quantity: pair.min_order_size,
+                    max_quantity: pair.max_order_size,
+                    price_band_percent: pair.price_band_percent.or(settings.default_price_band_percent),
+                };
+                ExchangeSymbol { listing, filters }
+            })
+            .collect();
+
+        Ok(ExchangeInfo {
+            timezone: "UTC".to_string(),
+            server_time: Utc::now().timestamp_millis(),
+            recv_window_ms: self.recv_window_ms,
+            rate_limits: self.rate_limits.clone(),
+            order_types: vec![
+                OrderType::Market,
+                OrderType::Limit,
+                OrderType::StopLoss,
+                OrderType::TakeProfit,
+                OrderType::StopLossLimit,
+                OrderType::TakeProfitLimit,
+            ],
+            time_in_force: vec![TimeInForce::GTC, TimeInForce::IOC, TimeInForce::FOK, TimeInForce::GTD],
+            capabilities: ExchangeCapabilities {
+                websocket: true,
+                server_sent_events: true,
+                sandbox: self.sandbox,
+                maintenance_mode: settings.maintenance_mode,
+            },
+            symbols,
+        })
+    }
+}
+
+fn changelog() -> Vec<ApiChange> {
+    API_CHANGELOG
+        .iter()
+        .map(|&(date, summary)| ApiChange {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").expect("changelog dates are ISO 8601"),
+            summary: summary.to_string(),
+        })
+        .collect()
+}
+
+fn rate_limit(tier: &str, limits: &MarketDataTier, daily_quota: Option<u64>) -> RateLimitInfo {
+    RateLimitInfo {
+        tier: tier.to_string(),
+        requests_per_minute: limits.requests_per_minute,
+        daily_quota,
+        order_book_depth: limits.order_book_depth,
+        max_trades: limits.max_trades,
+        max_candles: limits.max_candles,
+        history_days: limits.history_days,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changelog_is_newest_first() {
+        let changelog = changelog();
+        assert_eq!(changelog.len(), API_CHANGELOG.len());
+        assert!(changelog.windows(2).all(|pair| pair[0].date >= pair[1].date));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    storage::{self, ObjectStore},
+    Result,
+};
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Rows read ahead of what the client has taken. When the buffer is full
+/// the query waits, so a slow download holds back the database instead of
+/// piling rows up in memory.
+const EXPORT_BUFFER_ROWS: usize = 256;
+
+/// Rows of an export in the order they are read, oldest first. Dropping it
+/// stops the query.
+pub type ExportRows<T> = mpsc::Receiver<Result<T>>;
+
+/// A user's full trade and order history, streamed row by row from the
+/// database rather than collected. Exports run oldest first and resume
+/// after `from_id`, the last row a client received, so a broken download
+/// picks up where it stopped. They can also be written to object storage
+/// as a file, fetched through a presigned URL.
+#[derive(Clone)]
+pub struct ExportService {
+    db: Database,
+    storage: Arc<dyn ObjectStore>,
+    presign_ttl: Duration,
+}
+
+impl ExportService {
+    pub fn new(db: Database, storage: Arc<dyn ObjectStore>, presign_ttl: Duration) -> Self {
+        Self {
+            db,
+            storage,
+            presign_ttl,
+        }
+    }
+
+    pub async fn trades_file(&self, user_id: Uuid) -> Result<ExportFile> {
+        let rows = self.trades(user_id, None).await?;
+        self.write_file(user_id, "trades", rows).await
+    }
+
+    pub async fn orders_file(&self, user_id: Uuid) -> Result<ExportFile> {
+        let rows = self.orders(user_id, None).await?;
+        self.write_file(user_id, "orders", rows).await
+    }
+
+    pub async fn trades(&self, user_id: Uuid, from_id: Option<Uuid>) -> Result<ExportRows<Trade>> {
+        let after = match from_id {
+            Some(id) => Some(
+                sqlx::query_scalar::<_, DateTime<Utc>>(
+                    "SELECT created_at FROM trades WHERE id = $1 AND (buyer_user_id = $2 OR seller_user_id = $2)",
+                )
+                .bind(id)
+                .bind(user_id)
+                .fetch_optional(&self.db)
+                .await?
+                .map(|created_at| (created_at, id))
+                .ok_or_else(|| CryptoTradeError::NotFound {
+                    message: "from_id is not one of your trades".to_string(),
+                })?,
+            ),
+            None => None,
+        };
+
+        let db = self.db.clone();
+        Ok(spawn_export(move |sender| async move {
+            let rows = sqlx::query_as::<_, Trade>(
+                r#"
+                SELECT * FROM trades
+                WHERE (buyer_user_id = $1 OR seller_user_id = $1)
+                  AND ($2::timestamptz IS NULL OR (created_at, id) > ($2, $3))
+                ORDER BY created_at, id
+                "#,
+            )
+            .bind(user_id)
+            .bind(after.map(|(created_at, _)| created_at))
+            .bind(after.map(|(_, id)| id))
+            .fetch(&db);
+            forward(rows, sender).await;
+        }))
+    }
+
+    pub async fn orders(&self, user_id: Uuid, from_id: Option<Uuid>) -> Result<ExportRows<Order>> {
+        let after = match from_id {
+            Some(id) => Some(
+                sqlx::query_scalar::<_, DateTime<Utc>>("SELECT created_at FROM orders WHERE id = $1 AND user_id = $2")
+                    .bind(id)
+                    .bind(user_id)
+                    .fetch_optional(&self.db)
+                    .await?
+                    .map(|created_at| (created_at, id))
+                    .ok_or_else(|| CryptoTradeError::NotFound {
+                        message: "from_id is not one of your orders".to_string(),
+                    })?,
+            ),
+            None => None,
+        };
+
+        let db = self.db.clone();
+        Ok(spawn_export(move |sender| async move {
+            let rows = sqlx::query_as::<_, Order>(
+                r#"
+                SELECT * FROM orders
+                WHERE user_id = $1
+                  AND ($2::timestamptz IS NULL OR (created_at, id) > ($2, $3))
+                ORDER BY created_at, id
+                "#,
+            )
+            .bind(user_id)
+            .bind(after.map(|(created_at, _)| created_at))
+            .bind(after.map(|(_, id)| id))
+            .fetch(&db);
+            forward(rows, sender).await;
+        }))
+    }
+
+    async fn write_file<T: Serialize>(&self, user_id: Uuid, name: &str, mut rows: ExportRows<T>) -> Result<ExportFile> {
+        let mut body = Vec::new();
+        let mut count = 0;
+        while let Some(row) = rows.recv().await {
+            serde_json::to_writer(&mut body, &row?)?;
+            body.push(b'\n');
+            count += 1;
+        }
+
+        let key = format!("exports/{}/{}-{}.ndjson", user_id, name, Utc::now().format("%Y%m%dT%H%M%S%.3fZ"));
+        self.storage.put(&key, body, "application/x-ndjson").await?;
+        let url = storage::presign(self.storage.as_ref(), &key, self.presign_ttl);
+        Ok(ExportFile {
+            download_url: url.url,
+            expires_at: url.expires_at,
+            rows: count,
+        })
+    }
+}
+
+/// Runs `export` in its own task, feeding a bounded channel.
+fn spawn_export<T, F, Fut>(export: F) -> ExportRows<T>
+where
+    T: Send + 'static,
+    F: FnOnce(mpsc::Sender<Result<T>>) -> Fut,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(EXPORT_BUFFER_ROWS);
+    tokio::spawn(export(sender));
+    receiver
+}
+
+/// Sends rows until the query ends, fails or the client goes away.
+async fn forward<T>(rows: impl Stream<Item = sqlx::Result<T>>, sender: mpsc::Sender<Result<T>>) {
+    futures::pin_mut!(rows);
+    while let Some(row) = rows.next().await {
+        let failed = row.is_err();
+        if sender.send(row.map_err(Into::into)).await.is_err() || failed {
+            break;
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{database::Database, error::CryptoTradeError, models::*, Result};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::postgres::PgListener;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Postgres channel announcing a change to `feature_flags`
+const CHANNEL: &str = "feature_flags";
+
+pub const CANCEL_REPLACE: &str = "cancel_replace";
+
+/// Every flag the code consults: its key, whether it is on before an admin
+/// touches it, and what it gates. New capabilities start off here and are
+/// rolled out through the admin API.
+const FLAGS: [(&str, bool, &str); 1] = [(CANCEL_REPLACE, true, "Batch cancel-replace of orders")];
+
+/// Feature flags targeted by environment and user. Flags are declared in
+/// [`FLAGS`]; admins override them in `feature_flags` (see migration 019).
+/// Lookups are served from memory and every instance reloads when a change
+/// is announced over Postgres `NOTIFY`.
+#[derive(Clone)]
+pub struct FeatureFlagService {
+    db: Database,
+    environment: String,
+    flags: Arc<RwLock<HashMap<String, FeatureFlag>>>,
+}
+
+impl FeatureFlag {
+    fn default_for(key: &str, enabled: bool, description: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            description: description.to_string(),
+            enabled,
+            environments: Vec::new(),
+            user_ids: Vec::new(),
+            rollout_percent: 100,
+            overridden: false,
+            updated_at: None,
+        }
+    }
+
+    /// Whether the flag is on in `environment` for `user_id`; callers with
+    /// no user only see flags rolled out to everyone.
+    pub fn is_on(&self, environment: &str, user_id: Option<Uuid>) -> bool {
+        if !self.environments.is_empty() && !self.environments.iter().any(|env| env == environment) {
+            return false;
+        }
+        match user_id {
+            Some(user_id) if self.user_ids.contains(&user_id) => true,
+            _ if !self.enabled => false,
+            Some(user_id) => rollout_bucket(&self.key, user_id) < self.rollout_percent,
+            None => self.rollout_percent >= 100,
+        }
+    }
+}
+
+/// A user's place in `0..100` for `key`. Hashing the key with the user
+/// keeps a user's bucket stable as a rollout widens, while spreading
+/// early adopters differently for each flag.
+fn rollout_bucket(key: &str, user_id: Uuid) -> u8 {
+    let digest = Sha256::new()
+        .chain_update(key.as_bytes())
+        .chain_update(user_id.as_bytes())
+        .finalize();
+    (u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) % 100) as u8
+}
+
+impl FeatureFlagService {
+    pub async fn load(db: Database, environment: String) -> Result<Self> {
+        let flags = fetch(&db).await?;
+        Ok(Self {
+            db,
+            environment,
+            flags: Arc::new(RwLock::new(flags)),
+        })
+    }
+
+    pub fn is_enabled(&self, key: &str, user_id: Option<Uuid>) -> bool {
+        self.flags
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .is_some_and(|flag| flag.is_on(&self.environment, user_id))
+    }
+
+    /// Fails with `FeatureDisabled` unless `key` is on for `user_id`.
+    pub fn ensure_enabled(&self, key: &str, user_id: Uuid) -> Result<()> {
+        if !self.is_enabled(key, Some(user_id)) {
+            return Err(CryptoTradeError::FeatureDisabled { feature: key.to_string() });
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<FeatureFlag> {
+        let mut flags: Vec<_> = self.flags.read().unwrap_or_else(|e| e.into_inner()).values().cloned().collect();
+        flags.sort_by(|a, b| a.key.cmp(&b.key));
+        flags
+    }
+
+    /// Keys of the flags on for `user_id`, for clients that hide features
+    /// still being rolled out.
+    pub fn enabled_for(&self, user_id: Uuid) -> Vec<String> {
+        self.list()
+            .into_iter()
+            .filter(|flag| flag.is_on(&self.environment, Some(user_id)))
+            .map(|flag| flag.key)
+            .collect()
+    }
+
+    pub async fn update(&self, admin_id: Uuid, key: &str, request: UpdateFeatureFlagRequest) -> Result<FeatureFlag> {
+        ensure_declared(key)?;
+        let rollout_percent = request.rollout_percent.unwrap_or(100);
+        if rollout_percent > 100 {
+            return Err(CryptoTradeError::Validation {
+                message: "Rollout percent must be at most 100".to_string(),
+            });
+        }
+
+        let mut tx = self.db.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO feature_flags (key, enabled, environments, user_ids, rollout_percent, updated_by)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (key) DO UPDATE SET
+                enabled = EXCLUDED.enabled,
+                environments = EXCLUDED.environments,
+                user_ids = EXCLUDED.user_ids,
+                rollout_percent = EXCLUDED.rollout_percent,
+                updated_by = EXCLUDED.updated_by,
+                updated_at = NOW()
+            "#,
+        )
+        .bind(key)
+        .bind(request.enabled)
+        .bind(&request.environments)
+        .bind(&request.user_ids)
+        .bind(rollout_percent as i16)
+        .bind(admin_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("SELECT pg_notify($1, $2)").bind(CHANNEL).bind(key).execute(&mut *tx).await?;
+        tx.commit().await?;
+
+        self.reload().await?;
+        self.get(key)
+    }
+
+    /// Drops the admin override so the flag returns to its default.
+    pub async fn reset(&self, key: &str) -> Result<FeatureFlag> {
+        ensure_declared(key)?;
+        let mut tx = self.db.begin().await?;
+        sqlx::query("DELETE FROM feature_flags WHERE key = $1")
+            .bind(key)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("SELECT pg_notify($1, $2)").bind(CHANNEL).bind(key).execute(&mut *tx).await?;
+        tx.commit().await?;
+
+        self.reload().await?;
+        self.get(key)
+    }
+
+    fn get(&self, key: &str) -> Result<FeatureFlag> {
+        self.flags
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .cloned()
+            .ok_or_else(|| unknown_flag(key))
+    }
+
+    pub async fn reload(&self) -> Result<()> {
+        let flags = fetch(&self.db).await?;
+        *self.flags.write().unwrap_or_else(|e| e.into_inner()) = flags;
+        Ok(())
+    }
+
+    /// Reloads on every announced change and after reconnecting, since
+    /// announcements made while the connection was down are lost.
+    pub fn spawn_listener(&self) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = service.listen().await {
+                    tracing::error!("Feature flag listener failed: {}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+    }
+
+    async fn listen(&self) -> Result<()> {
+        let mut listener = PgListener::connect_with(&self.db).await?;
+        listener.listen(CHANNEL).await?;
+        self.reload().await?;
+
+        loop {
+            listener.try_recv().await?;
+            self.reload().await?;
+        }
+    }
+}
+
+fn ensure_declared(key: &str) -> Result<()> {
+    if !FLAGS.iter().any(|(declared, _, _)| *declared == key) {
+        return Err(unknown_flag(key));
+    }
+    Ok(())
+}
+
+fn unknown_flag(key: &str) -> CryptoTradeError {
+    CryptoTradeError::NotFound {
+        message: format!("Feature flag {} not found", key),
+    }
+}
+
+type FeatureFlagRow = (String, bool, Vec<String>, Vec<Uuid>, i16, DateTime<Utc>);
+
+async fn fetch(db: &Database) -> Result<HashMap<String, FeatureFlag>> {
+    let rows = sqlx::query_as::<_, FeatureFlagRow>(
+        "SELECT key, enabled, environments, user_ids, rollout_percent, updated_at FROM feature_flags",
+    )
+    .fetch_all(db)
+    .await?;
+    let mut overrides: HashMap<_, _> = rows.into_iter().map(|row| (row.0.clone(), row)).collect();
+
+    // Rows for flags the code no longer declares are ignored
+    Ok(FLAGS
+        .iter()
+        .map(|&(key, enabled, description)| {
+            let mut flag = FeatureFlag::default_for(key, enabled, description);
+            if let Some((_, enabled, environments, user_ids, rollout_percent, updated_at)) = overrides.remove(key) {
+                flag.enabled = enabled;
+                flag.environments = environments;
+                flag.user_ids = user_ids;
+                flag.rollout_percent = rollout_percent as u8;
+                flag.overridden = true;
+                flag.updated_at = Some(updated_at);
+            }
+            (key.to_string(), flag)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flag(enabled: bool, rollout_percent: u8) -> FeatureFlag {
+        FeatureFlag {
+            enabled,
+            rollout_percent,
+            ..FeatureFlag::default_for("margin", false, "")
+        }
+    }
+
+    #[test]
+    fn test_rollout_grows_without_dropping_users() {
+        let users: Vec<_> = (0..1000).map(|_| Uuid::new_v4()).collect();
+        let on = |flag: &FeatureFlag| users.iter().filter(|&&user| flag.is_on("production", Some(user))).count();
+
+        assert_eq!(on(&flag(true, 0)), 0);
+        assert_eq!(on(&flag(true, 100)), users.len());
+        assert!((150..350).contains(&on(&flag(true, 25))));
+        for &user in &users {
+            if flag(true, 10).is_on("production", Some(user)) {
+                assert!(flag(true, 50).is_on("production", Some(user)));
+            }
+        }
+        assert!(!flag(true, 50).is_on("production", None));
+    }
+
+    #[test]
+    fn test_targeting_by_environment_and_user() {
+        let tester = Uuid::new_v4();
+        let flag = FeatureFlag {
+            environments: vec!["staging".to_string()],
+            user_ids: vec![tester],
+            ..flag(false, 100)
+        };
+
+        assert!(flag.is_on("staging", Some(tester)));
+        assert!(!flag.is_on("staging", Some(Uuid::new_v4())));
+        assert!(!flag.is_on("production", Some(tester)));
+    }
+}
@@ -0,0 +1,821 @@
+use crate::{
+    compliance::{country_code, HttpTravelRuleProvider, RecordOnly, TravelRuleParty, TravelRulePayload, TravelRuleProvider},
+    config::{PaymentsConfig, TravelRuleConfig, TravelRuleProviderKind},
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    services::{
+        outbox_service::{enqueue_event, DomainEvent},
+        user_service::account_status,
+        LedgerService, Posting, RiskLimitService,
+    },
+    Result,
+};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+const WITHDRAWAL_COLUMNS: &str = "id, user_id, bank_account_id, currency, amount, fee, status, external_id, note, travel_rule_required, \
+     (SELECT status FROM travel_rule_records WHERE withdrawal_id = transactions.id) AS travel_rule_status, dual_approval_required, approval_initiated_by, \
+     created_at, updated_at";
+
+#[derive(sqlx::FromRow)]
+struct BankAccountRow {
+    id: Uuid,
+    rail: BankRail,
+    holder_name: String,
+    account_number: String,
+    routing_code: String,
+    currency: String,
+    status: BankAccountStatus,
+    micro_deposits: Vec<Decimal>,
+    verification_attempts: i32,
+    created_at: DateTime<Utc>,
+    verified_at: Option<DateTime<Utc>>,
+}
+
+impl From<BankAccountRow> for BankAccount {
+    fn from(row: BankAccountRow) -> Self {
+        let last4 = row.account_number.len().saturating_sub(4);
+        Self {
+            id: row.id,
+            rail: row.rail,
+            holder_name: row.holder_name,
+            account_last4: row.account_number[last4..].to_string(),
+            routing_code: row.routing_code,
+            currency: row.currency,
+            status: row.status,
+            created_at: row.created_at,
+            verified_at: row.verified_at,
+        }
+    }
+}
+
+impl BankRail {
+    /// The only currency the rail carries.
+    pub fn currency(&self) -> &'static str {
+        match self {
+            Self::Sepa => "EUR",
+            Self::Ach => "USD",
+        }
+    }
+}
+
+/// Fiat withdrawals to verified bank accounts. A withdrawal holds its amount
+/// and fee until an admin rejects it or the transfer is reported sent, and
+/// only then leaves the balance and the ledger. One reaching its currency's
+/// travel rule threshold cannot be approved until its originator and
+/// beneficiary have been transmitted.
+#[derive(Clone)]
+pub struct FiatWithdrawalService {
+    db: Database,
+    ledger: LedgerService,
+    risk_limits: RiskLimitService,
+    config: PaymentsConfig,
+    travel_rule: TravelRuleConfig,
+    travel_rule_provider: Arc<dyn TravelRuleProvider>,
+}
+
+impl FiatWithdrawalService {
+    /// Uses the travel rule provider selected in `travel_rule`.
+    pub fn new(
+        db: Database,
+        ledger: LedgerService,
+        risk_limits: RiskLimitService,
+        config: PaymentsConfig,
+        travel_rule: TravelRuleConfig,
+    ) -> Self {
+        let travel_rule_provider: Arc<dyn TravelRuleProvider> = match travel_rule.provider {
+            TravelRuleProviderKind::Record => Arc::new(RecordOnly),
+            TravelRuleProviderKind::Http => Arc::new(HttpTravelRuleProvider::new(&travel_rule)),
+        };
+        Self {
+            db,
+            ledger,
+            risk_limits,
+            config,
+            travel_rule,
+            travel_rule_provider,
+        }
+    }
+
+    pub fn with_travel_rule_provider(mut self, provider: Arc<dyn TravelRuleProvider>) -> Self {
+        self.travel_rule_provider = provider;
+        self
+    }
+
+    /// Registers an account and picks the two micro-deposits the user must
+    /// confirm before withdrawing to it.
+    pub async fn add_bank_account(&self, user_id: Uuid, request: AddBankAccountRequest) -> Result<BankAccount> {
+        account_status(&self.db, user_id).await?.ensure_can_withdraw()?;
+        request.validate().map_err(|e| CryptoTradeError::Validation { message: e.to_string() })?;
+        let account_number = normalize(&request.account_number);
+        let routing_code = normalize(&request.routing_code);
+        let valid = match request.rail {
+            BankRail::Sepa => is_valid_iban(&account_number) && is_valid_bic(&routing_code),
+            BankRail::Ach => {
+                (4..=17).contains(&account_number.len())
+                    && account_number.chars().all(|c| c.is_ascii_digit())
+                    && is_valid_routing_number(&routing_code)
+            }
+        };
+        if !valid {
+            return Err(CryptoTradeError::Validation {
+                message: format!("Invalid {:?} account or routing details", request.rail),
+            });
+        }
+
+        let micro_deposits = {
+            let mut rng = rand::thread_rng();
+            let first = rng.gen_range(1..=99);
+            let second = loop {
+                let cents = rng.gen_range(1..=99);
+                if cents != first {
+                    break cents;
+                }
+            };
+            vec![Decimal::new(first, 2), Decimal::new(second, 2)]
+        };
+
+        let row = sqlx::query_as::<_, BankAccountRow>(
+            "INSERT INTO bank_accounts (user_id, rail, holder_name, account_number, routing_code, currency, micro_deposits) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+        )
+        .bind(user_id)
+        .bind(request.rail)
+        .bind(request.holder_name.trim())
+        .bind(&account_number)
+        .bind(&routing_code)
+        .bind(request.rail.currency())
+        .bind(&micro_deposits)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    pub async fn list_bank_accounts(&self, user_id: Uuid) -> Result<Vec<BankAccount>> {
+        let rows = sqlx::query_as::<_, BankAccountRow>(
+            "SELECT * FROM bank_accounts WHERE user_id = $1 AND status <> 'removed' ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Checks the micro-deposit amounts the user reports. Too many wrong
+    /// guesses fail the account.
+    pub async fn verify_bank_account(&self, user_id: Uuid, bank_account_id: Uuid, mut amounts: Vec<Decimal>) -> Result<BankAccount> {
+        let mut tx = self.db.begin().await?;
+        let row = sqlx::query_as::<_, BankAccountRow>("SELECT * FROM bank_accounts WHERE id = $1 AND user_id = $2 FOR UPDATE")
+            .bind(bank_account_id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(bank_account_not_found)?;
+        if row.status != BankAccountStatus::PendingVerification {
+            return Err(CryptoTradeError::Validation {
+                message: "Bank account is not awaiting verification".to_string(),
+            });
+        }
+
+        let mut expected = row.micro_deposits.clone();
+        expected.sort();
+        amounts.sort();
+        if amounts == expected {
+            let row = sqlx::query_as::<_, BankAccountRow>(
+                "UPDATE bank_accounts SET status = 'verified', verified_at = NOW() WHERE id = $1 RETURNING *",
+            )
+            .bind(bank_account_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            return Ok(row.into());
+        }
+
+        let attempts = row.verification_attempts + 1;
+        let status = if attempts >= self.config.max_verification_attempts {
+            BankAccountStatus::VerificationFailed
+        } else {
+            BankAccountStatus::PendingVerification
+        };
+        sqlx::query("UPDATE bank_accounts SET verification_attempts = $2, status = $3 WHERE id = $1")
+            .bind(bank_account_id)
+            .bind(attempts)
+            .bind(status)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Err(CryptoTradeError::Validation {
+            message: match status {
+                BankAccountStatus::VerificationFailed => "Amounts do not match; add the account again".to_string(),
+                _ => format!(
+                    "Amounts do not match; {} attempts left",
+                    self.config.max_verification_attempts - attempts
+                ),
+            },
+        })
+    }
+
+    /// Hides the account; withdrawals already made to it carry on.
+    pub async fn remove_bank_account(&self, user_id: Uuid, bank_account_id: Uuid) -> Result<()> {
+        let result = sqlx::query("UPDATE bank_accounts SET status = 'removed' WHERE id = $1 AND user_id = $2 AND status <> 'removed'")
+            .bind(bank_account_id)
+            .bind(user_id)
+            .execute(&self.db)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(bank_account_not_found());
+        }
+
+        Ok(())
+    }
+
+    /// Accounts whose micro-deposits still have to be sent, oldest first.
+    pub async fn pending_micro_deposits(&self) -> Result<Vec<PendingMicroDeposits>> {
+        let pending = sqlx::query_as::<_, PendingMicroDeposits>(
+            "SELECT id AS bank_account_id, user_id, rail, holder_name, account_number, routing_code, currency, micro_deposits, created_at FROM bank_accounts WHERE status = 'pending_verification' ORDER BY created_at",
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(pending)
+    }
+
+    /// Holds the amount and the rail's fee and queues the withdrawal for
+    /// an admin.
+    pub async fn create_withdrawal(&self, user_id: Uuid, request: CreateFiatWithdrawalRequest) -> Result<FiatWithdrawal> {
+        account_status(&self.db, user_id).await?.ensure_can_withdraw()?;
+        let account = sqlx::query_as::<_, BankAccountRow>("SELECT * FROM bank_accounts WHERE id = $1 AND user_id = $2 AND status <> 'removed'")
+            .bind(request.bank_account_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(bank_account_not_found)?;
+        if account.status != BankAccountStatus::Verified {
+            return Err(CryptoTradeError::Validation {
+                message: "Bank account is not verified".to_string(),
+            });
+        }
+        let amount = request.amount;
+        if amount < self.config.min_withdrawal {
+            return Err(CryptoTradeError::Validation {
+                message: format!("Amount must be at least {} {}", self.config.min_withdrawal, account.currency),
+            });
+        }
+        if amount.normalize().scale() > 2 {
+            return Err(CryptoTradeError::Validation {
+                message: "Amount must be in whole cents".to_string(),
+            });
+        }
+        if let Some(info) = &request.travel_rule {
+            info.validate().map_err(|e| CryptoTradeError::Validation { message: e.to_string() })?;
+        }
+        let fee = match account.rail {
+            BankRail::Sepa => self.config.sepa_withdrawal_fee,
+            BankRail::Ach => self.config.ach_withdrawal_fee,
+        };
+        let total = amount + fee;
+        let travel_rule_required = self
+            .travel_rule
+            .thresholds
+            .get(&account.currency)
+            .is_some_and(|threshold| amount >= *threshold);
+        let dual_approval_required = self
+            .config
+            .dual_approval_thresholds
+            .get(&account.currency)
+            .is_some_and(|threshold| amount >= *threshold);
+
+        let mut tx = self.db.begin().await?;
+        self.risk_limits
+            .check_daily_withdrawal(&mut tx, user_id, &account.currency, amount)
+            .await?;
+        let held = sqlx::query(
+            "UPDATE accounts SET available_balance = available_balance - $1, locked_balance = locked_balance + $1, updated_at = NOW() WHERE user_id = $2 AND currency = $3 AND available_balance >= $1",
+        )
+        .bind(total)
+        .bind(user_id)
+        .bind(&account.currency)
+        .execute(&mut *tx)
+        .await?;
+        if held.rows_affected() == 0 {
+            let available = sqlx::query_scalar::<_, Option<Decimal>>(
+                "SELECT available_balance FROM accounts WHERE user_id = $1 AND currency = $2",
+            )
+            .bind(user_id)
+            .bind(&account.currency)
+            .fetch_optional(&mut *tx)
+            .await?
+            .flatten()
+            .unwrap_or(Decimal::ZERO);
+            return Err(CryptoTradeError::InsufficientBalance {
+                currency: account.currency,
+                required: total,
+                available,
+            });
+        }
+        let withdrawal = sqlx::query_as::<_, FiatWithdrawal>(&format!(
+            "INSERT INTO transactions (user_id, transaction_type, currency, amount, fee, status, bank_account_id, travel_rule_required, dual_approval_required) VALUES ($1, 'withdrawal', $2, $3, $4, 'pending', $5, $6, $7) RETURNING {}",
+            WITHDRAWAL_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(&account.currency)
+        .bind(amount)
+        .bind(fee)
+        .bind(account.id)
+        .bind(travel_rule_required)
+        .bind(dual_approval_required)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        match request.travel_rule {
+            Some(info) => {
+                let record = self.transmit_travel_rule(&withdrawal, &account, info).await?;
+                Ok(FiatWithdrawal {
+                    travel_rule_status: Some(record.status),
+                    ..withdrawal
+                })
+            }
+            None => Ok(withdrawal),
+        }
+    }
+
+    /// Records and transmits the travel rule data of a withdrawal still
+    /// awaiting approval, replacing any submitted before; this is also how
+    /// a failed transmission is retried.
+    pub async fn submit_travel_rule(&self, user_id: Uuid, withdrawal_id: Uuid, info: TravelRuleInfo) -> Result<TravelRuleRecord> {
+        info.validate().map_err(|e| CryptoTradeError::Validation { message: e.to_string() })?;
+        let withdrawal = sqlx::query_as::<_, FiatWithdrawal>(&format!(
+            "SELECT {} FROM transactions WHERE id = $1 AND user_id = $2 AND bank_account_id IS NOT NULL",
+            WITHDRAWAL_COLUMNS
+        ))
+        .bind(withdrawal_id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(withdrawal_not_found)?;
+        if withdrawal.status != TransactionStatus::Pending {
+            return Err(CryptoTradeError::Validation {
+                message: "Travel rule data can only be submitted before approval".to_string(),
+            });
+        }
+        if withdrawal.travel_rule_status == Some(TravelRuleStatus::Transmitted) {
+            return Err(CryptoTradeError::Validation {
+                message: "Travel rule data has already been transmitted".to_string(),
+            });
+        }
+        let account = sqlx::query_as::<_, BankAccountRow>("SELECT * FROM bank_accounts WHERE id = $1")
+            .bind(withdrawal.bank_account_id)
+            .fetch_one(&self.db)
+            .await?;
+
+        let record = self.transmit_travel_rule(&withdrawal, &account, info).await?;
+        match (record.status, &record.error) {
+            (TravelRuleStatus::Failed, Some(error)) => Err(CryptoTradeError::TravelRuleProvider { message: error.clone() }),
+            _ => Ok(record),
+        }
+    }
+
+    pub async fn travel_rule_record(&self, user_id: Uuid, withdrawal_id: Uuid) -> Result<TravelRuleRecord> {
+        sqlx::query_as::<_, TravelRuleRecord>(
+            "SELECT withdrawal_id, payload, provider, status, reference, error, created_at, transmitted_at FROM travel_rule_records WHERE withdrawal_id = $1 AND user_id = $2",
+        )
+        .bind(withdrawal_id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| CryptoTradeError::NotFound {
+            message: "No travel rule data for this withdrawal".to_string(),
+        })
+    }
+
+    /// Saves the payload, then hands it to the provider. A provider failure
+    /// is recorded on the returned record rather than returned, since the
+    /// data itself is kept.
+    async fn transmit_travel_rule(&self, withdrawal: &FiatWithdrawal, account: &BankAccountRow, info: TravelRuleInfo) -> Result<TravelRuleRecord> {
+        let (first_name, last_name, country) = sqlx::query_as::<_, (Option<String>, Option<String>, Option<String>)>(
+            "SELECT first_name, last_name, country FROM users WHERE id = $1",
+        )
+        .bind(withdrawal.user_id)
+        .fetch_one(&self.db)
+        .await?;
+        let profile_name = [first_name, last_name].into_iter().flatten().collect::<Vec<_>>().join(" ");
+        let originator_name = info
+            .originator_name
+            .filter(|name| !name.trim().is_empty())
+            .or_else(|| (!profile_name.trim().is_empty()).then_some(profile_name))
+            .ok_or_else(|| CryptoTradeError::Validation {
+                message: "originator_name is required when the profile has no name".to_string(),
+            })?;
+        let country_of = |code: Option<&str>, field: &str| {
+            code.and_then(country_code).ok_or_else(|| CryptoTradeError::Validation {
+                message: format!("{} must be an ISO 3166-1 alpha-2 code", field),
+            })
+        };
+        let payload = TravelRulePayload {
+            transfer_id: withdrawal.id,
+            currency: withdrawal.currency.clone(),
+            amount: withdrawal.amount,
+            originator: TravelRuleParty {
+                name: originator_name,
+                address: info.originator_address,
+                country: country_of(info.originator_country.as_deref().or(country.as_deref()), "originator_country")?,
+                account: withdrawal.user_id.to_string(),
+                institution: None,
+            },
+            beneficiary: TravelRuleParty {
+                name: info
+                    .beneficiary_name
+                    .filter(|name| !name.trim().is_empty())
+                    .unwrap_or_else(|| account.holder_name.clone()),
+                address: info.beneficiary_address,
+                country: country_of(Some(&info.beneficiary_country), "beneficiary_country")?,
+                account: account.account_number.clone(),
+                institution: Some(account.routing_code.clone()),
+            },
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO travel_rule_records (withdrawal_id, user_id, payload, provider)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (withdrawal_id) DO UPDATE SET
+                payload = EXCLUDED.payload,
+                provider = EXCLUDED.provider,
+                status = 'pending',
+                reference = NULL,
+                error = NULL
+            "#,
+        )
+        .bind(withdrawal.id)
+        .bind(withdrawal.user_id)
+        .bind(serde_json::to_value(&payload)?)
+        .bind(self.travel_rule_provider.name())
+        .execute(&self.db)
+        .await?;
+
+        let (status, reference, error) = match self.travel_rule_provider.transmit(&payload).await {
+            Ok(reference) => (TravelRuleStatus::Transmitted, Some(reference), None),
+            Err(e) => {
+                tracing::warn!("Travel rule data for withdrawal {} was not transmitted: {}", withdrawal.id, e);
+                (TravelRuleStatus::Failed, None, Some(e.to_string()))
+            }
+        };
+        let record = sqlx::query_as::<_, TravelRuleRecord>(
+            "UPDATE travel_rule_records SET status = $2, reference = $3, error = $4, transmitted_at = CASE WHEN $2 = 'transmitted'::travel_rule_status THEN NOW() END WHERE withdrawal_id = $1 RETURNING withdrawal_id, payload, provider, status, reference, error, created_at, transmitted_at",
+        )
+        .bind(withdrawal.id)
+        .bind(status)
+        .bind(reference)
+        .bind(error)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(record)
+    }
+
+    pub async fn list_withdrawals(&self, user_id: Uuid) -> Result<Vec<FiatWithdrawal>> {
+        let withdrawals = sqlx::query_as::<_, FiatWithdrawal>(&format!(
+            "SELECT {} FROM transactions WHERE user_id = $1 AND bank_account_id IS NOT NULL ORDER BY created_at DESC",
+            WITHDRAWAL_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(withdrawals)
+    }
+
+    /// Withdrawals in `status`, oldest first; by default those an admin
+    /// still has to act on.
+    pub async fn review_queue(&self, status: Option<TransactionStatus>) -> Result<Vec<FiatWithdrawal>> {
+        let withdrawals = sqlx::query_as::<_, FiatWithdrawal>(&format!(
+            "SELECT {} FROM transactions WHERE bank_account_id IS NOT NULL AND (status = $1 OR ($1 IS NULL AND status IN ('pending', 'approved'))) ORDER BY created_at",
+            WITHDRAWAL_COLUMNS
+        ))
+        .bind(status)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(withdrawals)
+    }
+
+    /// Lets the user take back a withdrawal no admin has approved yet.
+    pub async fn cancel_withdrawal(&self, user_id: Uuid, withdrawal_id: Uuid) -> Result<FiatWithdrawal> {
+        let mut tx = self.db.begin().await?;
+        let withdrawal = self.lock_withdrawal(&mut tx, withdrawal_id).await?;
+        if withdrawal.user_id != user_id {
+            return Err(withdrawal_not_found());
+        }
+        if withdrawal.status != TransactionStatus::Pending {
+            return Err(CryptoTradeError::Validation {
+                message: "Only pending withdrawals can be cancelled".to_string(),
+            });
+        }
+
+        release(&mut tx, &withdrawal).await?;
+        let withdrawal = set_status(&mut tx, withdrawal_id, TransactionStatus::Cancelled, None, None, None).await?;
+        tx.commit().await?;
+
+        Ok(withdrawal)
+    }
+
+    /// Moves a withdrawal through the admin workflow: approve or reject a
+    /// pending one, then complete or fail it once the transfer is attempted.
+    /// One needing dual approval stays pending after its first approval,
+    /// until a different admin approves it too. Every action is recorded in
+    /// the withdrawal's audit trail.
+    pub async fn review(&self, admin_id: Uuid, withdrawal_id: Uuid, request: ReviewWithdrawalRequest) -> Result<FiatWithdrawal> {
+        let mut tx = self.db.begin().await?;
+        let withdrawal = self.lock_withdrawal(&mut tx, withdrawal_id).await?;
+        let status = match (request.action, withdrawal.status) {
+            (WithdrawalAction::Approve, TransactionStatus::Pending) => TransactionStatus::Approved,
+            (WithdrawalAction::Reject, TransactionStatus::Pending) => TransactionStatus::Cancelled,
+            (WithdrawalAction::Complete, TransactionStatus::Approved) => TransactionStatus::Confirmed,
+            (WithdrawalAction::Fail, TransactionStatus::Approved) => TransactionStatus::Failed,
+            (action, status) => {
+                return Err(CryptoTradeError::Validation {
+                    message: format!("Cannot {:?} a {:?} withdrawal", action, status).to_lowercase(),
+                })
+            }
+        };
+        if status == TransactionStatus::Approved
+            && withdrawal.travel_rule_required
+            && withdrawal.travel_rule_status != Some(TravelRuleStatus::Transmitted)
+        {
+            return Err(CryptoTradeError::Validation {
+                message: "Travel rule data must be transmitted before the withdrawal is approved".to_string(),
+            });
+        }
+        if status == TransactionStatus::Approved && withdrawal.dual_approval_required {
+            match withdrawal.approval_initiated_by {
+                None => {
+                    let initiated = sqlx::query_as::<_, FiatWithdrawal>(&format!(
+                        "UPDATE transactions SET approval_initiated_by = $2, updated_at = NOW() WHERE id = $1 RETURNING {}",
+                        WITHDRAWAL_COLUMNS
+                    ))
+                    .bind(withdrawal_id)
+                    .bind(admin_id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                    record_review(&mut tx, &withdrawal, admin_id, request.action, withdrawal.status, request.note.as_deref()).await?;
+                    tx.commit().await?;
+                    return Ok(initiated);
+                }
+                Some(initiator) if initiator == admin_id => {
+                    return Err(CryptoTradeError::Authorization {
+                        message: "A different admin must confirm the approval".to_string(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        if status == TransactionStatus::Confirmed && request.external_id.as_deref().is_none_or(str::is_empty) {
+            return Err(CryptoTradeError::Validation {
+                message: "The bank's transfer reference is required to complete a withdrawal".to_string(),
+            });
+        }
+
+        let fee = withdrawal.fee.unwrap_or(Decimal::ZERO);
+        let mut balance = None;
+        match status {
+            TransactionStatus::Cancelled | TransactionStatus::Failed => release(&mut tx, &withdrawal).await?,
+            TransactionStatus::Confirmed => {
+                balance = Some(
+                    sqlx::query_scalar::<_, Decimal>(
+                        "UPDATE accounts SET balance = balance - $1, locked_balance = locked_balance - $1, updated_at = NOW() WHERE user_id = $2 AND currency = $3 RETURNING balance",
+                    )
+                    .bind(withdrawal.amount + fee)
+                    .bind(withdrawal.user_id)
+                    .bind(&withdrawal.currency)
+                    .fetch_one(&mut *tx)
+                    .await?,
+                );
+            }
+            _ => {}
+        }
+        let updated = set_status(
+            &mut tx,
+            withdrawal_id,
+            status,
+            Some(admin_id),
+            request.external_id.as_deref(),
+            request.note.as_deref(),
+        )
+        .await?;
+        record_review(&mut tx, &withdrawal, admin_id, request.action, status, request.note.as_deref()).await?;
+        let event = DomainEvent::WithdrawalStatusChanged {
+            withdrawal: updated.clone(),
+        };
+        enqueue_event(&mut *tx, &event).await?;
+
+        // The journal is posted with the balance change it records
+        if let Some(balance) = balance {
+            let currency = &withdrawal.currency;
+            let treasury = if fee.is_zero() {
+                None
+            } else {
+                Some(self.ledger.credit_treasury_in(&mut tx, currency, fee).await?)
+            };
+            let postings = [
+                Posting {
+                    user_id: Some(withdrawal.user_id),
+                    currency: currency.clone(),
+                    entry_type: LedgerEntryType::Withdrawal,
+                    amount: -withdrawal.amount,
+                    balance_after: Some(balance + fee),
+                },
+                Posting {
+                    user_id: Some(withdrawal.user_id),
+                    currency: currency.clone(),
+                    entry_type: LedgerEntryType::Fee,
+                    amount: -fee,
+                    balance_after: Some(balance),
+                },
+                Posting {
+                    user_id: None,
+                    currency: currency.clone(),
+                    entry_type: LedgerEntryType::Withdrawal,
+                    amount: withdrawal.amount,
+                    balance_after: None,
+                },
+                Posting {
+                    user_id: None,
+                    currency: currency.clone(),
+                    entry_type: LedgerEntryType::Fee,
+                    amount: fee,
+                    balance_after: treasury,
+                },
+            ];
+            self.ledger.post_in(&mut tx, withdrawal.id, Some(withdrawal.id), &postings).await?;
+        }
+        tx.commit().await?;
+
+        Ok(updated)
+    }
+
+    /// The withdrawal's audit trail, oldest action first.
+    pub async fn review_history(&self, withdrawal_id: Uuid) -> Result<Vec<WithdrawalReview>> {
+        sqlx::query_as::<_, WithdrawalReview>("SELECT * FROM withdrawal_reviews WHERE withdrawal_id = $1 ORDER BY created_at, id")
+            .bind(withdrawal_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn lock_withdrawal(&self, tx: &mut sqlx::PgConnection, withdrawal_id: Uuid) -> Result<FiatWithdrawal> {
+        sqlx::query_as::<_, FiatWithdrawal>(&format!(
+            "SELECT {} FROM transactions WHERE id = $1 AND bank_account_id IS NOT NULL FOR UPDATE",
+            WITHDRAWAL_COLUMNS
+        ))
+        .bind(withdrawal_id)
+        .fetch_optional(tx)
+        .await?
+        .ok_or_else(withdrawal_not_found)
+    }
+}
+
+/// Returns a withdrawal's held amount and fee to the available balance.
+async fn release(tx: &mut sqlx::PgConnection, withdrawal: &FiatWithdrawal) -> Result<()> {
+    sqlx::query(
+        "UPDATE accounts SET available_balance = available_balance + $1, locked_balance = locked_balance - $1, updated_at = NOW() WHERE user_id = $2 AND currency = $3",
+    )
+    .bind(withdrawal.amount + withdrawal.fee.unwrap_or(Decimal::ZERO))
+    .bind(withdrawal.user_id)
+    .bind(&withdrawal.currency)
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn record_review(
+    tx: &mut sqlx::PgConnection,
+    withdrawal: &FiatWithdrawal,
+    admin_id: Uuid,
+    action: WithdrawalAction,
+    status_after: TransactionStatus,
+    note: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO withdrawal_reviews (withdrawal_id, admin_id, action, status_before, status_after, note) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(withdrawal.id)
+    .bind(admin_id)
+    .bind(action)
+    .bind(withdrawal.status)
+    .bind(status_after)
+    .bind(note)
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn set_status(
+    tx: &mut sqlx::PgConnection,
+    withdrawal_id: Uuid,
+    status: TransactionStatus,
+    reviewed_by: Option<Uuid>,
+    external_id: Option<&str>,
+    note: Option<&str>,
+) -> Result<FiatWithdrawal> {
+    let withdrawal = sqlx::query_as::<_, FiatWithdrawal>(&format!(
+        "UPDATE transactions SET status = $2, reviewed_by = COALESCE($3, reviewed_by), external_id = COALESCE($4, external_id), note = COALESCE($5, note), updated_at = NOW() WHERE id = $1 RETURNING {}",
+        WITHDRAWAL_COLUMNS
+    ))
+    .bind(withdrawal_id)
+    .bind(status)
+    .bind(reviewed_by)
+    .bind(external_id)
+    .bind(note)
+    .fetch_one(tx)
+    .await?;
+
+    Ok(withdrawal)
+}
+
+fn bank_account_not_found() -> CryptoTradeError {
+    CryptoTradeError::NotFound {
+        message: "Bank account not found".to_string(),
+    }
+}
+
+fn withdrawal_not_found() -> CryptoTradeError {
+    CryptoTradeError::NotFound {
+        message: "Withdrawal not found".to_string(),
+    }
+}
+
+fn normalize(value: &str) -> String {
+    value.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase()
+}
+
+/// Checks an IBAN's shape and its ISO 13616 mod-97 check digits.
+fn is_valid_iban(iban: &str) -> bool {
+    let bytes = iban.as_bytes();
+    if !(15..=34).contains(&bytes.len())
+        || !bytes[..2].iter().all(u8::is_ascii_uppercase)
+        || !bytes[2..4].iter().all(u8::is_ascii_digit)
+        || !bytes.iter().all(u8::is_ascii_alphanumeric)
+    {
+        return false;
+    }
+
+    // Country and check digits move to the end; letters count as 10..35
+    let mut remainder = 0u32;
+    for &byte in bytes[4..].iter().chain(&bytes[..4]) {
+        let value = if byte.is_ascii_digit() { (byte - b'0') as u32 } else { (byte - b'A') as u32 + 10 };
+        let shift = if value >= 10 { 100 } else { 10 };
+        remainder = (remainder * shift + value) % 97;
+    }
+    remainder == 1
+}
+
+fn is_valid_bic(bic: &str) -> bool {
+    bic.is_ascii()
+        && (bic.len() == 8 || bic.len() == 11)
+        && bic[..6].chars().all(|c| c.is_ascii_uppercase())
+        && bic[6..].chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Checks an ABA routing number's 3-7-1 weighted checksum.
+fn is_valid_routing_number(routing: &str) -> bool {
+    if routing.len() != 9 || !routing.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let sum: u32 = routing
+        .bytes()
+        .zip([3, 7, 1].iter().cycle())
+        .map(|(digit, weight)| (digit - b'0') as u32 * weight)
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iban_check_digits() {
+        assert!(is_valid_iban(&normalize("DE89 3704 0044 0532 0130 00")));
+        assert!(is_valid_iban("GB82WEST12345698765432"));
+        assert!(!is_valid_iban("DE88370400440532013000"));
+        assert!(!is_valid_iban("DE89"));
+    }
+
+    #[test]
+    fn test_routing_number_checksum() {
+        assert!(is_valid_routing_number("021000021"));
+        assert!(is_valid_routing_number("011401533"));
+        assert!(!is_valid_routing_number("021000022"));
+        assert!(!is_valid_routing_number("02100002"));
+    }
+}
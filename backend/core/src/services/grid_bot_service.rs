@@ -0,0 +1,470 @@
+use crate::{
+    config::GridBotConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    oracle::{LastTradePrices, PriceOracle},
+    services::{user_service::account_status, OrderService},
+    Result,
+};
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Runs grid trading bots. Starting a bot places its ladder around the
+/// current price; a background task calls [`GridBotService::rebalance`],
+/// which replaces each filled grid order with the opposite order one level
+/// away.
+#[derive(Clone)]
+pub struct GridBotService {
+    db: Database,
+    order_service: OrderService,
+    config: GridBotConfig,
+    oracle: Arc<dyn PriceOracle>,
+}
+
+/// A filled grid order waiting to be replaced
+#[derive(sqlx::FromRow)]
+struct FilledGridOrder {
+    order_id: Uuid,
+    level: i32,
+    side: OrderSide,
+    counter_to: Option<Uuid>,
+    filled_quantity: Option<Decimal>,
+}
+
+impl GridBotService {
+    /// Centres new ladders on the exchange's last trade.
+    pub fn new(db: Database, order_service: OrderService, config: GridBotConfig) -> Self {
+        Self {
+            oracle: Arc::new(LastTradePrices::new(db.clone())),
+            db,
+            order_service,
+            config,
+        }
+    }
+
+    pub fn with_oracle(mut self, oracle: Arc<dyn PriceOracle>) -> Self {
+        self.oracle = oracle;
+        self
+    }
+
+    /// Creates a bot and places its ladder.
+    pub async fn create(&self, user_id: Uuid, request: CreateGridBotRequest) -> Result<GridBotReport> {
+        account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        let invalid = |message: String| Err(CryptoTradeError::Validation { message });
+        if request.lower_price <= Decimal::ZERO || request.upper_price <= request.lower_price {
+            return invalid("upper_price must be above lower_price, which must be positive".to_string());
+        }
+        if request.grid_count < 2 || request.grid_count as u32 > self.config.max_grid_count {
+            return invalid(format!("grid_count must be between 2 and {}", self.config.max_grid_count));
+        }
+        if request.investment <= Decimal::ZERO {
+            return invalid("investment must be positive".to_string());
+        }
+        let pair = self.trading_pair(request.trading_pair_id).await?;
+        if pair.is_active != Some(true) {
+            return invalid("Trading pair is not active".to_string());
+        }
+        self.ensure_below_bot_limit(user_id).await?;
+
+        let price = self.last_price(&pair).await?.ok_or_else(|| CryptoTradeError::Validation {
+            message: format!("{} has no market price to centre the grid on", pair.symbol),
+        })?;
+        let quantity = (request.investment / (Decimal::from(request.grid_count) * price))
+            .round_dp_with_strategy(pair.quantity_precision.unwrap_or(8) as u32, RoundingStrategy::ToZero);
+        if quantity < pair.min_order_size.unwrap_or(Decimal::ZERO) || quantity.is_zero() {
+            return invalid("investment is too small for an order at every grid level".to_string());
+        }
+
+        let bot = sqlx::query_as::<_, GridBot>(
+            "INSERT INTO grid_bots (user_id, trading_pair_id, lower_price, upper_price, grid_count, investment, quantity_per_grid) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+        )
+        .bind(user_id)
+        .bind(pair.id)
+        .bind(request.lower_price)
+        .bind(request.upper_price)
+        .bind(request.grid_count)
+        .bind(request.investment)
+        .bind(quantity)
+        .fetch_one(&self.db)
+        .await?;
+        tracing::info!("User {} started grid bot {} on {}", user_id, bot.id, pair.symbol);
+
+        self.place_ladder(&bot, &pair, price).await?;
+        self.report(user_id, bot.id).await
+    }
+
+    /// Restarts a stopped or failed bot with a fresh ladder around the
+    /// current price.
+    pub async fn start(&self, user_id: Uuid, bot_id: Uuid) -> Result<GridBotReport> {
+        account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        let bot = self.bot(user_id, bot_id).await?;
+        if bot.status == GridBotStatus::Running {
+            return Err(CryptoTradeError::Validation {
+                message: "Grid bot is already running".to_string(),
+            });
+        }
+        self.ensure_below_bot_limit(user_id).await?;
+        let pair = self.trading_pair(bot.trading_pair_id).await?;
+        let price = self.last_price(&pair).await?.ok_or_else(|| CryptoTradeError::Validation {
+            message: format!("{} has no market price to centre the grid on", pair.symbol),
+        })?;
+
+        let bot = sqlx::query_as::<_, GridBot>(
+            "UPDATE grid_bots SET status = 'running', error = NULL, stopped_at = NULL, updated_at = NOW() WHERE id = $1 AND status <> 'running' RETURNING *",
+        )
+        .bind(bot_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| CryptoTradeError::Validation {
+            message: "Grid bot is already running".to_string(),
+        })?;
+
+        // The new ladder supersedes whatever the old one left unreplaced
+        sqlx::query("UPDATE grid_bot_orders SET replaced = true WHERE bot_id = $1")
+            .bind(bot_id)
+            .execute(&self.db)
+            .await?;
+        self.place_ladder(&bot, &pair, price).await?;
+        self.report(user_id, bot_id).await
+    }
+
+    /// Stops a bot and cancels its open orders. What it bought or sold
+    /// stays in the user's balances.
+    pub async fn stop(&self, user_id: Uuid, bot_id: Uuid) -> Result<GridBotReport> {
+        // Waits for a rebalance of this bot to finish
+        let mut tx = self.db.begin().await?;
+        let status = sqlx::query_scalar::<_, GridBotStatus>(
+            "SELECT status FROM grid_bots WHERE id = $1 AND user_id = $2 FOR NO KEY UPDATE",
+        )
+        .bind(bot_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(not_found)?;
+        if status == GridBotStatus::Stopped {
+            return Err(CryptoTradeError::Validation {
+                message: "Grid bot is already stopped".to_string(),
+            });
+        }
+        sqlx::query("UPDATE grid_bots SET status = 'stopped', stopped_at = NOW(), updated_at = NOW() WHERE id = $1")
+            .bind(bot_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        self.cancel_open_orders(user_id, bot_id).await?;
+        tracing::info!("User {} stopped grid bot {}", user_id, bot_id);
+        self.report(user_id, bot_id).await
+    }
+
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<GridBot>> {
+        let bots = sqlx::query_as::<_, GridBot>(
+            "SELECT * FROM grid_bots WHERE user_id = $1 ORDER BY created_at DESC LIMIT 100",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(bots)
+    }
+
+    pub async fn report(&self, user_id: Uuid, bot_id: Uuid) -> Result<GridBotReport> {
+        let bot = self.bot(user_id, bot_id).await?;
+        let orders = sqlx::query_as::<_, Order>(
+            "SELECT o.* FROM orders o JOIN grid_bot_orders g ON g.order_id = o.id WHERE g.bot_id = $1 ORDER BY o.price",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        let (mut base_bought, mut quote_spent, mut base_sold, mut quote_received) =
+            (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+        for order in &orders {
+            let filled = order.filled_quantity.unwrap_or(Decimal::ZERO);
+            let quote = order.cumulative_quote_quantity.unwrap_or(Decimal::ZERO);
+            match order.side {
+                Some(OrderSide::Buy) => {
+                    base_bought += filled;
+                    quote_spent += quote;
+                }
+                Some(OrderSide::Sell) => {
+                    base_sold += filled;
+                    quote_received += quote;
+                }
+                None => {}
+            }
+        }
+        let pair = self.trading_pair(bot.trading_pair_id).await?;
+        let total_pnl = self
+            .last_price(&pair)
+            .await?
+            .map(|price| (quote_received - quote_spent + (base_bought - base_sold) * price).round_dp(8));
+
+        Ok(GridBotReport {
+            bot,
+            open_orders: orders
+                .into_iter()
+                .filter(|order| matches!(order.status, Some(OrderStatus::Open | OrderStatus::PartiallyFilled)))
+                .collect(),
+            base_bought,
+            quote_spent,
+            base_sold,
+            quote_received,
+            total_pnl,
+        })
+    }
+
+    /// Replaces the filled orders of every running bot and returns how many
+    /// orders were placed.
+    pub async fn rebalance(&self) -> Result<u32> {
+        let bots = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT DISTINCT g.bot_id
+            FROM grid_bot_orders g
+            JOIN grid_bots b ON b.id = g.bot_id
+            JOIN orders o ON o.id = g.order_id
+            WHERE b.status = 'running' AND NOT g.replaced AND o.status = 'filled'
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut placed = 0;
+        for bot_id in bots {
+            placed += self.rebalance_bot(bot_id).await?;
+        }
+        Ok(placed)
+    }
+
+    /// Holds the bot's row locked, so it is never rebalanced twice at once
+    /// or stopped halfway. The lock is NO KEY so that the grid orders placed
+    /// meanwhile, on other connections, can still check their foreign key.
+    async fn rebalance_bot(&self, bot_id: Uuid) -> Result<u32> {
+        let mut tx = self.db.begin().await?;
+        let Some(bot) = sqlx::query_as::<_, GridBot>(
+            "SELECT * FROM grid_bots WHERE id = $1 AND status = 'running' FOR NO KEY UPDATE SKIP LOCKED",
+        )
+        .bind(bot_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(0);
+        };
+        let filled = sqlx::query_as::<_, FilledGridOrder>(
+            r#"
+            SELECT g.order_id, g.level, g.side, g.counter_to, o.filled_quantity
+            FROM grid_bot_orders g
+            JOIN orders o ON o.id = g.order_id
+            WHERE g.bot_id = $1 AND NOT g.replaced AND o.status = 'filled'
+            ORDER BY o.updated_at
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        let pair = self.trading_pair(bot.trading_pair_id).await?;
+        let levels = grid_levels(bot.lower_price, bot.upper_price, bot.grid_count, price_scale(&pair));
+
+        let mut placed = 0;
+        let (mut realized, mut cycles) = (Decimal::ZERO, 0);
+        let mut error = None;
+        for order in filled {
+            let (level, side) = match order.side {
+                OrderSide::Buy => (order.level + 1, OrderSide::Sell),
+                OrderSide::Sell => (order.level - 1, OrderSide::Buy),
+            };
+            // A fill at the edge of the range has no level beyond it to
+            // replace it at
+            if level >= 0 && (level as usize) < levels.len() {
+                if let Err(e) = self.place(&bot, &levels, level, side, Some(order.order_id)).await {
+                    tracing::warn!("Grid bot {} failed replacing order {}: {}", bot.id, order.order_id, e);
+                    error = Some(e.to_string());
+                    break;
+                }
+                placed += 1;
+            }
+            // Only a replacement closes a round trip; the initial ladder
+            // merely opens one
+            if order.counter_to.is_some() {
+                realized += order.filled_quantity.unwrap_or(Decimal::ZERO) * (levels[1] - levels[0]);
+                cycles += 1;
+            }
+            sqlx::query("UPDATE grid_bot_orders SET replaced = true WHERE order_id = $1")
+                .bind(order.order_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query(
+            "UPDATE grid_bots SET realized_pnl = realized_pnl + $2, completed_cycles = completed_cycles + $3, status = CASE WHEN $4::text IS NULL THEN status ELSE 'failed' END, error = COALESCE($4, error), updated_at = NOW() WHERE id = $1",
+        )
+        .bind(bot.id)
+        .bind(realized)
+        .bind(cycles)
+        .bind(error)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(placed)
+    }
+
+    /// Places a buy at every level below `price` and a sell at every level
+    /// above it, leaving out the level nearest to it, which the first fill
+    /// on either side will refill. A bot whose ladder cannot be placed in
+    /// full is marked failed and its orders are cancelled.
+    async fn place_ladder(&self, bot: &GridBot, pair: &TradingPair, price: Decimal) -> Result<()> {
+        let levels = grid_levels(bot.lower_price, bot.upper_price, bot.grid_count, price_scale(pair));
+        for (level, side) in initial_ladder(&levels, price) {
+            if let Err(e) = self.place(bot, &levels, level as i32, side, None).await {
+                sqlx::query("UPDATE grid_bots SET status = 'failed', error = $2, stopped_at = NOW(), updated_at = NOW() WHERE id = $1")
+                    .bind(bot.id)
+                    .bind(e.to_string())
+                    .execute(&self.db)
+                    .await?;
+                self.cancel_open_orders(bot.user_id, bot.id).await?;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn place(&self, bot: &GridBot, levels: &[Decimal], level: i32, side: OrderSide, counter_to: Option<Uuid>) -> Result<()> {
+        let price = levels[level as usize];
+        let request = CreateOrderRequest {
+            trading_pair_id: bot.trading_pair_id,
+            order_type: OrderType::Limit,
+            side,
+            quantity: bot.quantity_per_grid.to_f64().ok_or(CryptoTradeError::InvalidQuantity)?,
+            price: Some(price),
+            time_in_force: Some(TimeInForce::GTC),
+            stop_price: None,
+            allow_duplicate: true,
+            activate_at: None,
+            lock_funds: false,
+        };
+        let order = self.order_service.create_order(bot.user_id, request).await?;
+        sqlx::query("INSERT INTO grid_bot_orders (order_id, bot_id, level, side, counter_to) VALUES ($1, $2, $3, $4, $5)")
+            .bind(order.id)
+            .bind(bot.id)
+            .bind(level)
+            .bind(side)
+            .bind(counter_to)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn cancel_open_orders(&self, user_id: Uuid, bot_id: Uuid) -> Result<()> {
+        let open = sqlx::query_scalar::<_, Uuid>(
+            "SELECT o.id FROM orders o JOIN grid_bot_orders g ON g.order_id = o.id WHERE g.bot_id = $1 AND o.status IN ('pending', 'open', 'partially_filled')",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.db)
+        .await?;
+        for order_id in open {
+            self.order_service.cancel_order(user_id, order_id).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn ensure_below_bot_limit(&self, user_id: Uuid) -> Result<()> {
+        let running = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM grid_bots WHERE user_id = $1 AND status = 'running'",
+        )
+        .bind(user_id)
+        .fetch_one(&self.db)
+        .await?;
+        if running >= i64::from(self.config.max_bots_per_user) {
+            return Err(CryptoTradeError::Validation {
+                message: format!("At most {} grid bots may run at once", self.config.max_bots_per_user),
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn bot(&self, user_id: Uuid, bot_id: Uuid) -> Result<GridBot> {
+        sqlx::query_as::<_, GridBot>("SELECT * FROM grid_bots WHERE id = $1 AND user_id = $2")
+            .bind(bot_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(not_found)
+    }
+
+    async fn trading_pair(&self, trading_pair_id: Uuid) -> Result<TradingPair> {
+        sqlx::query_as::<_, TradingPair>("SELECT * FROM trading_pairs WHERE id = $1")
+            .bind(trading_pair_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)
+    }
+
+    async fn last_price(&self, pair: &TradingPair) -> Result<Option<Decimal>> {
+        self.oracle.price(&pair.base_currency, &pair.quote_currency).await
+    }
+}
+
+fn not_found() -> CryptoTradeError {
+    CryptoTradeError::NotFound {
+        message: "Grid bot not found".to_string(),
+    }
+}
+
+fn price_scale(pair: &TradingPair) -> u32 {
+    pair.price_precision.unwrap_or(8).clamp(0, 8) as u32
+}
+
+/// The `grid_count + 1` evenly spaced prices from `lower` to `upper`.
+fn grid_levels(lower: Decimal, upper: Decimal, grid_count: i32, scale: u32) -> Vec<Decimal> {
+    let step = (upper - lower) / Decimal::from(grid_count);
+    (0..=grid_count)
+        .map(|level| (lower + step * Decimal::from(level)).round_dp(scale))
+        .collect()
+}
+
+/// The side of the order each level starts with; the level nearest to
+/// `price` starts empty.
+fn initial_ladder(levels: &[Decimal], price: Decimal) -> Vec<(usize, OrderSide)> {
+    let nearest = (0..levels.len())
+        .min_by_key(|&level| (levels[level] - price).abs())
+        .unwrap_or(0);
+    (0..levels.len())
+        .filter(|&level| level != nearest)
+        .map(|level| (level, if levels[level] < price { OrderSide::Buy } else { OrderSide::Sell }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_levels_span_the_range() {
+        let levels = grid_levels(Decimal::from(100), Decimal::from(200), 4, 2);
+        assert_eq!(levels, [100, 125, 150, 175, 200].map(Decimal::from));
+        let levels = grid_levels(Decimal::from(1), Decimal::from(2), 3, 2);
+        assert_eq!(levels[1], Decimal::new(133, 2));
+        assert_eq!(levels[3], Decimal::from(2));
+    }
+
+    #[test]
+    fn test_initial_ladder_buys_below_and_sells_above_the_price() {
+        let levels = grid_levels(Decimal::from(100), Decimal::from(200), 4, 2);
+        let ladder = initial_ladder(&levels, Decimal::from(140));
+        assert_eq!(
+            ladder,
+            vec![(0, OrderSide::Buy), (1, OrderSide::Buy), (3, OrderSide::Sell), (4, OrderSide::Sell)]
+        );
+        // Outside the range every level is on one side
+        let ladder = initial_ladder(&levels, Decimal::from(300));
+        assert_eq!(ladder.len(), 4);
+        assert!(ladder.iter().all(|(_, side)| *side == OrderSide::Buy));
+    }
+}
@@ -0,0 +1,445 @@
+use crate::{database::Database, error::CryptoTradeError, interval::Interval, models::*, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use sqlx::{Postgres, QueryBuilder};
+use std::collections::HashSet;
+use std::io::Read;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Rows per INSERT, well under Postgres' bind parameter limit
+const INSERT_BATCH: usize = 1000;
+
+/// Widest scale the history tables store
+const MAX_SCALE: u32 = 8;
+
+/// Loads external OHLCV and trade CSV dumps into `candlesticks` and
+/// `imported_trades` (see migration 011) so a new pair has chart history
+/// from the day it launches. Dumps are validated row by row with
+/// [`parse_candles`] and [`parse_trades`] first; storing an overlapping or
+/// repeated dump leaves the rows already stored untouched.
+#[derive(Clone)]
+pub struct ImportService {
+    db: Database,
+}
+
+/// One OHLCV row of a candle dump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoricalCandle {
+    pub open_time: DateTime<Utc>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+}
+
+/// One row of a trade dump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoricalTrade {
+    pub external_id: String,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub taker_side: Option<OrderSide>,
+    pub traded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectedRow {
+    /// Line in the file, counting the header as line 1
+    pub line: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub rows_read: usize,
+    /// Rows new to the database; 0 until stored
+    pub imported: u64,
+    /// Rows repeated within the file or already stored
+    pub duplicates: u64,
+    pub rejected: Vec<RejectedRow>,
+}
+
+/// Candle columns: `timestamp,open,high,low,close,volume`
+#[derive(Deserialize)]
+struct CandleRecord {
+    #[serde(alias = "time", alias = "open_time")]
+    timestamp: String,
+    open: String,
+    high: String,
+    low: String,
+    close: String,
+    volume: String,
+}
+
+/// Trade columns: `timestamp,price,quantity`, optionally `side` (of the
+/// taker) and `id`
+#[derive(Deserialize)]
+struct TradeRecord {
+    #[serde(alias = "time")]
+    timestamp: String,
+    price: String,
+    #[serde(alias = "amount", alias = "size")]
+    quantity: String,
+    #[serde(default)]
+    side: Option<String>,
+    #[serde(default, alias = "trade_id")]
+    id: Option<String>,
+}
+
+impl ImportService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub async fn find_trading_pair(&self, symbol: &str) -> Result<TradingPair> {
+        sqlx::query_as::<_, TradingPair>("SELECT * FROM trading_pairs WHERE symbol = $1")
+            .bind(symbol)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)
+    }
+
+    /// Stores validated candles of one interval, skipping open times already
+    /// stored, and returns how many were new.
+    pub async fn store_candles(
+        &self,
+        trading_pair_id: Uuid,
+        interval: Interval,
+        source: &str,
+        candles: &[HistoricalCandle],
+    ) -> Result<u64> {
+        let mut inserted = 0;
+        let mut tx = self.db.begin().await?;
+        for batch in candles.chunks(INSERT_BATCH) {
+            let mut query = QueryBuilder::<Postgres>::new(
+                "INSERT INTO candlesticks (trading_pair_id, interval_minutes, open_time, open, high, low, close, volume, source) ",
+            );
+            query.push_values(batch, |mut row, candle| {
+                row.push_bind(trading_pair_id)
+                    .push_bind(interval.minutes())
+                    .push_bind(candle.open_time)
+                    .push_bind(candle.open)
+                    .push_bind(candle.high)
+                    .push_bind(candle.low)
+                    .push_bind(candle.close)
+                    .push_bind(candle.volume)
+                    .push_bind(source);
+            });
+            query.push(" ON CONFLICT DO NOTHING");
+
+            inserted += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+
+        Ok(inserted)
+    }
+
+    /// Stores validated trades, skipping ids already stored for `source`,
+    /// and returns how many were new.
+    pub async fn store_trades(&self, trading_pair_id: Uuid, source: &str, trades: &[HistoricalTrade]) -> Result<u64> {
+        let mut inserted = 0;
+        let mut tx = self.db.begin().await?;
+        for batch in trades.chunks(INSERT_BATCH) {
+            let mut query = QueryBuilder::<Postgres>::new(
+                "INSERT INTO imported_trades (trading_pair_id, source, external_id, price, quantity, taker_side, traded_at) ",
+            );
+            query.push_values(batch, |mut row, trade| {
+                row.push_bind(trading_pair_id)
+                    .push_bind(source)
+                    .push_bind(&trade.external_id)
+                    .push_bind(trade.price)
+                    .push_bind(trade.quantity)
+                    .push_bind(trade.taker_side)
+                    .push_bind(trade.traded_at);
+            });
+            query.push(" ON CONFLICT DO NOTHING");
+
+            inserted += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+
+        Ok(inserted)
+    }
+}
+
+impl ImportReport {
+    /// Accounts for `stored` of `valid` rows being new to the database.
+    pub fn record_stored(&mut self, valid: usize, stored: u64) {
+        self.imported += stored;
+        self.duplicates += valid as u64 - stored;
+    }
+}
+
+/// Validates a candle dump; rows repeating an earlier open time count as
+/// duplicates.
+pub fn parse_candles(reader: impl Read, interval: Interval) -> Result<(Vec<HistoricalCandle>, ImportReport)> {
+    let mut report = ImportReport::default();
+    let mut seen = HashSet::new();
+    let mut candles = Vec::new();
+
+    let mut csv = csv_reader(reader);
+    require_columns(&mut csv, &["timestamp", "open", "high", "low", "close", "volume"])?;
+    for (line, record) in records::<CandleRecord, _>(&mut csv) {
+        report.rows_read += 1;
+        let candle = record.and_then(|record| validate_candle(record, interval));
+        match candle {
+            Ok(candle) if !seen.insert(candle.open_time) => report.duplicates += 1,
+            Ok(candle) => candles.push(candle),
+            Err(reason) => report.rejected.push(RejectedRow { line, reason }),
+        }
+    }
+
+    Ok((candles, report))
+}
+
+/// Validates a trade dump. Rows without an
+/// `id` are keyed on their time, price, quantity and side, so two identical
+/// trades in the same millisecond are taken to be one.
+pub fn parse_trades(reader: impl Read) -> Result<(Vec<HistoricalTrade>, ImportReport)> {
+    let mut report = ImportReport::default();
+    let mut seen = HashSet::new();
+    let mut trades = Vec::new();
+
+    let mut csv = csv_reader(reader);
+    require_columns(&mut csv, &["timestamp", "price", "quantity"])?;
+    for (line, record) in records::<TradeRecord, _>(&mut csv) {
+        report.rows_read += 1;
+        match record.and_then(validate_trade) {
+            Ok(trade) if !seen.insert(trade.external_id.clone()) => report.duplicates += 1,
+            Ok(trade) => trades.push(trade),
+            Err(reason) => report.rejected.push(RejectedRow { line, reason }),
+        }
+    }
+
+    Ok((trades, report))
+}
+
+fn validate_candle(record: CandleRecord, interval: Interval) -> std::result::Result<HistoricalCandle, String> {
+    let open_time = parse_timestamp(&record.timestamp)?;
+    if interval.bucket_start(open_time) != open_time {
+        return Err(format!("{} is not the start of a {} candle", open_time, interval));
+    }
+    if open_time > Utc::now() {
+        return Err(format!("{} is in the future", open_time));
+    }
+
+    let open = decimal(&record.open, "open")?;
+    let high = decimal(&record.high, "high")?;
+    let low = decimal(&record.low, "low")?;
+    let close = decimal(&record.close, "close")?;
+    if [open, high, low, close].iter().any(|price| *price <= Decimal::ZERO) {
+        return Err("prices must be positive".to_string());
+    }
+    if high < open.max(close) || low > open.min(close) {
+        return Err("high/low do not contain open and close".to_string());
+    }
+
+    let volume = decimal(&record.volume, "volume")?;
+    if volume < Decimal::ZERO {
+        return Err("volume must not be negative".to_string());
+    }
+
+    Ok(HistoricalCandle {
+        open_time,
+        open,
+        high,
+        low,
+        close,
+        volume,
+    })
+}
+
+fn validate_trade(record: TradeRecord) -> std::result::Result<HistoricalTrade, String> {
+    let traded_at = parse_timestamp(&record.timestamp)?;
+    if traded_at > Utc::now() {
+        return Err(format!("{} is in the future", traded_at));
+    }
+
+    let price = decimal(&record.price, "price")?;
+    let quantity = decimal(&record.quantity, "quantity")?;
+    if price <= Decimal::ZERO || quantity <= Decimal::ZERO {
+        return Err("price and quantity must be positive".to_string());
+    }
+
+    let taker_side = match record.side.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("") => None,
+        Some("buy") => Some(OrderSide::Buy),
+        Some("sell") => Some(OrderSide::Sell),
+        Some(other) => return Err(format!("unknown side '{}'", other)),
+    };
+
+    let external_id = match record.id.filter(|id| !id.is_empty()) {
+        Some(id) => id,
+        None => format!(
+            "{}:{}:{}:{}",
+            traded_at.timestamp_millis(),
+            price.normalize(),
+            quantity.normalize(),
+            taker_side.map_or("", |side| match side {
+                OrderSide::Buy => "buy",
+                OrderSide::Sell => "sell",
+            })
+        ),
+    };
+
+    Ok(HistoricalTrade {
+        external_id,
+        price,
+        quantity,
+        taker_side,
+        traded_at,
+    })
+}
+
+/// RFC 3339, or a Unix timestamp in seconds or milliseconds.
+fn parse_timestamp(value: &str) -> std::result::Result<DateTime<Utc>, String> {
+    if let Ok(epoch) = value.parse::<i64>() {
+        // Seconds reach 10^11 only in the year 5138
+        let parsed = if epoch.abs() >= 100_000_000_000 {
+            Utc.timestamp_millis_opt(epoch).single()
+        } else {
+            Utc.timestamp_opt(epoch, 0).single()
+        };
+        return parsed.ok_or_else(|| format!("timestamp {} is out of range", value));
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| format!("'{}' is not an RFC 3339 or Unix timestamp", value))
+}
+
+/// Parsed from the text itself; going through a float would lose digits.
+pub(crate) fn decimal(value: &str, column: &str) -> std::result::Result<Decimal, String> {
+    let value = Decimal::from_str(value)
+        .or_else(|_| Decimal::from_scientific(value))
+        .map_err(|_| format!("{} '{}' is not a number", column, value))?
+        .normalize();
+    if value.scale() > MAX_SCALE {
+        return Err(format!("{} has more than {} decimal places", column, MAX_SCALE));
+    }
+    Ok(value)
+}
+
+pub(crate) fn csv_reader<R: Read>(reader: R) -> csv::Reader<R> {
+    csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader)
+}
+
+/// Lowercases the header so column names match in any case, then checks
+/// that `columns` (or one of their aliases) are all present.
+pub(crate) fn require_columns<R: Read>(csv: &mut csv::Reader<R>, columns: &[&str]) -> Result<()> {
+    let headers: csv::StringRecord = csv
+        .headers()
+        .map_err(|e| validation(&format!("Unreadable CSV header: {}", e)))?
+        .iter()
+        .map(str::to_ascii_lowercase)
+        .collect();
+
+    let missing: Vec<&str> = columns
+        .iter()
+        .copied()
+        .filter(|column| !aliases(column).iter().any(|name| headers.iter().any(|header| header == *name)))
+        .collect();
+    if !missing.is_empty() {
+        return Err(validation(&format!("CSV is missing column(s): {}", missing.join(", "))));
+    }
+
+    csv.set_headers(headers);
+    Ok(())
+}
+
+/// Every header accepted for a column, matching the serde aliases above.
+fn aliases(column: &str) -> &[&str] {
+    match column {
+        "timestamp" => &["timestamp", "time", "open_time"],
+        "quantity" => &["quantity", "amount", "size"],
+        "price" => &["price"],
+        "open" => &["open"],
+        "high" => &["high"],
+        "low" => &["low"],
+        "close" => &["close"],
+        "volume" => &["volume"],
+        "symbol" => &["symbol", "pair"],
+        "side" => &["side"],
+        _ => &[],
+    }
+}
+
+/// Deserialized rows with their line numbers; a row that does not parse is
+/// an error for that row alone.
+pub(crate) fn records<T: serde::de::DeserializeOwned, R: Read>(
+    csv: &mut csv::Reader<R>,
+) -> impl Iterator<Item = (u64, std::result::Result<T, String>)> + '_ {
+    let headers = csv.headers().cloned().unwrap_or_default();
+    csv.records().enumerate().map(move |(index, record)| {
+        let line = match &record {
+            Ok(record) => record.position().map(|position| position.line()),
+            Err(e) => e.position().map(|position| position.line()),
+        };
+        let parsed = record
+            .and_then(|record| record.deserialize(Some(&headers)))
+            .map_err(|e| e.to_string());
+        (line.unwrap_or(index as u64 + 2), parsed)
+    })
+}
+
+fn validation(message: &str) -> CryptoTradeError {
+    CryptoTradeError::Validation {
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candles_are_validated_row_by_row() {
+        let csv = "\
+timestamp,open,high,low,close,volume
+2024-01-01T00:00:00Z,100,110,95,105,12.5
+2024-01-01T01:00:00Z,105,104,100,102,3
+1704074400,102,103,101,102.5,1
+2024-01-01T03:30:00Z,102,103,101,102.5,1
+2024-01-01T00:00:00Z,100,110,95,105,12.5
+";
+        let (candles, report) = parse_candles(csv.as_bytes(), Interval::OneHour).unwrap();
+
+        assert_eq!(report.rows_read, 5);
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[1].open_time, Utc.with_ymd_and_hms(2024, 1, 1, 2, 0, 0).unwrap());
+        assert_eq!(report.duplicates, 1);
+        let lines: Vec<u64> = report.rejected.iter().map(|row| row.line).collect();
+        // High below open; not on an hour boundary
+        assert_eq!(lines, vec![3, 5]);
+    }
+
+    #[test]
+    fn test_trades_without_ids_are_keyed_on_their_contents() {
+        let csv = "\
+timestamp,price,quantity,side
+1704067200000,42000.5,0.10,Buy
+1704067200000,42000.50,0.1,buy
+1704067200000,42000.5,0.1,sell
+1704067201000,42001,0,buy
+1704067202000,42001,0.2,hold
+";
+        let (trades, report) = parse_trades(csv.as_bytes()).unwrap();
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(report.duplicates, 1);
+        assert_eq!(report.rejected.len(), 2);
+        assert_eq!(trades[0].taker_side, Some(OrderSide::Buy));
+        assert_eq!(trades[0].external_id, "1704067200000:42000.5:0.1:buy");
+    }
+
+    #[test]
+    fn test_missing_columns_reject_the_whole_file() {
+        let csv = "time,price\n1704067200,42000\n";
+        let error = parse_trades(csv.as_bytes()).unwrap_err();
+
+        assert!(error.to_string().contains("quantity"));
+    }
+}
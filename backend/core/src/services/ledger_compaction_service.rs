@@ -0,0 +1,152 @@
+use crate::{database::Database, models::*, storage::ObjectStore, Result};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use futures::StreamExt;
+use std::sync::Arc;
+
+/// Advisory lock key held while a month is compacted, so instances running
+/// the job at the same time do not archive the same entries twice
+const COMPACTION_LOCK: i64 = 0x6c65_6467_6572;
+
+/// Keeps `ledger_entries` from growing without bound. Month by month, the
+/// entries older than the retention are archived to object storage as NDJSON,
+/// rolled into one checkpoint per account and currency (see migration 042)
+/// and deleted, all in one transaction. Point-in-time balances and
+/// statements read the checkpoints in place of the deleted entries.
+#[derive(Clone)]
+pub struct LedgerCompactionService {
+    db: Database,
+    storage: Arc<dyn ObjectStore>,
+    /// Most entries written to one archive object
+    batch_size: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct CompactionReport {
+    /// Months compacted, as `YYYY-MM`
+    pub periods: Vec<String>,
+    pub entries_archived: u64,
+    pub checkpoints: u64,
+}
+
+impl LedgerCompactionService {
+    pub fn new(db: Database, storage: Arc<dyn ObjectStore>, batch_size: i64) -> Self {
+        Self { db, storage, batch_size }
+    }
+
+    /// Compacts every month that ended more than `retention_months` whole
+    /// months ago, oldest first.
+    pub async fn run(&self, retention_months: u32) -> Result<CompactionReport> {
+        let mut report = CompactionReport::default();
+        let today = Utc::now().date_naive();
+        let Some(cutoff) = NaiveDate::from_ymd_opt(today.year(), today.month(), 1)
+            .and_then(|month| month.checked_sub_months(Months::new(retention_months)))
+        else {
+            return Ok(report);
+        };
+
+        loop {
+            let oldest = sqlx::query_scalar::<_, Option<DateTime<Utc>>>("SELECT MIN(created_at) FROM ledger_entries")
+                .fetch_one(&self.db)
+                .await?;
+            let Some(oldest) = oldest else { break };
+            let oldest = oldest.date_naive();
+            let Some(month) = NaiveDate::from_ymd_opt(oldest.year(), oldest.month(), 1) else {
+                break;
+            };
+            if month >= cutoff || !self.compact_month(month, &mut report).await? {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Archives, checkpoints and deletes everything before the end of
+    /// `month`. Returns false when another instance holds the lock.
+    async fn compact_month(&self, month: NaiveDate, report: &mut CompactionReport) -> Result<bool> {
+        let period = month.format("%Y-%m").to_string();
+        let period_end = (month + Months::new(1))
+            .and_hms_opt(0, 0, 0)
+            .map(|end| end.and_utc())
+            .unwrap_or_else(Utc::now);
+
+        let mut tx = self.db.begin().await?;
+        let locked = sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_xact_lock($1)")
+            .bind(COMPACTION_LOCK)
+            .fetch_one(&mut *tx)
+            .await?;
+        if !locked {
+            return Ok(false);
+        }
+
+        // Archived first: entries are only deleted once they are safely
+        // stored, and a failed upload rolls the whole month back
+        let mut archived = 0u64;
+        {
+            let mut entries = sqlx::query_as::<_, LedgerEntry>(
+                "SELECT * FROM ledger_entries WHERE created_at < $1 ORDER BY sequence",
+            )
+            .bind(period_end)
+            .fetch(&mut *tx);
+            let mut batch = Vec::new();
+            while let Some(entry) = entries.next().await {
+                batch.push(entry?);
+                if batch.len() as i64 >= self.batch_size {
+                    archived += self.archive_batch(&period, &mut batch).await?;
+                }
+            }
+            archived += self.archive_batch(&period, &mut batch).await?;
+        }
+
+        let checkpoints = sqlx::query(
+            r#"
+            INSERT INTO ledger_checkpoints (user_id, currency, period_end, balance, entry_count)
+            SELECT e.user_id, e.currency, $1, COALESCE(previous.balance, 0) + SUM(e.amount), COUNT(*)
+            FROM ledger_entries e
+            LEFT JOIN LATERAL (
+                SELECT balance FROM ledger_checkpoints c
+                WHERE c.user_id IS NOT DISTINCT FROM e.user_id AND c.currency = e.currency AND c.period_end < $1
+                ORDER BY c.period_end DESC
+                LIMIT 1
+            ) previous ON TRUE
+            WHERE e.created_at < $1
+            GROUP BY e.user_id, e.currency, previous.balance
+            "#,
+        )
+        .bind(period_end)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        sqlx::query("DELETE FROM ledger_entries WHERE created_at < $1")
+            .bind(period_end)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        tracing::info!("Compacted ledger for {}: {} entries into {} checkpoint(s)", period, archived, checkpoints);
+        report.periods.push(period);
+        report.entries_archived += archived;
+        report.checkpoints += checkpoints;
+        Ok(true)
+    }
+
+    /// Writes a batch as one object named after its first sequence, so a
+    /// month compacted again after a failure overwrites the same objects.
+    async fn archive_batch(&self, period: &str, batch: &mut Vec<LedgerEntry>) -> Result<u64> {
+        let Some(first) = batch.first() else {
+            return Ok(0);
+        };
+        let key = format!("ledger/{}/{:012}.ndjson", period, first.sequence);
+        let mut body = Vec::new();
+        for entry in batch.iter() {
+            serde_json::to_writer(&mut body, entry)?;
+            body.push(b'\n');
+        }
+        self.storage.put(&key, body, "application/x-ndjson").await?;
+
+        let count = batch.len() as u64;
+        batch.clear();
+        Ok(count)
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    Result,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use sqlx::PgConnection;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// One leg of a journal.
+#[derive(Debug, Clone)]
+pub struct Posting {
+    /// `None` posts to the exchange's own account
+    pub user_id: Option<Uuid>,
+    pub currency: String,
+    pub entry_type: LedgerEntryType,
+    pub amount: Decimal,
+    pub balance_after: Option<Decimal>,
+}
+
+#[derive(Clone)]
+pub struct LedgerService {
+    db: Database,
+}
+
+impl LedgerService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Records a balance change. Refuses journals that do not sum to zero in
+    /// every currency, since those would mean money appeared or vanished.
+    pub async fn post(&self, journal_id: Uuid, reference_id: Option<Uuid>, postings: &[Posting]) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+        self.post_in(&mut tx, journal_id, reference_id, postings).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Posts a journal as part of the caller's transaction, so it is
+    /// recorded together with the balance changes it describes or not at all.
+    pub async fn post_in(
+        &self,
+        conn: &mut PgConnection,
+        journal_id: Uuid,
+        reference_id: Option<Uuid>,
+        postings: &[Posting],
+    ) -> Result<()> {
+        let mut totals: HashMap<&str, Decimal> = HashMap::new();
+        for posting in postings {
+            *totals.entry(&posting.currency).or_default() += posting.amount;
+        }
+        if let Some((currency, total)) = totals.iter().find(|(_, total)| !total.is_zero()) {
+            tracing::error!("Unbalanced journal {}: {} {} left over", journal_id, total, currency);
+            return Err(CryptoTradeError::Internal);
+        }
+
+        // One statement for the whole journal; entries are sequenced in posting order
+        sqlx::query!(
+            r#"
+            INSERT INTO ledger_entries (journal_id, user_id, currency, entry_type, amount, balance_after, reference_id, created_at)
+            SELECT $1, user_id, currency, entry_type, amount, balance_after, $2, $3
+            FROM UNNEST($4::UUID[], $5::VARCHAR[], $6::ledger_entry_type[], $7::DECIMAL[], $8::DECIMAL[])
+                WITH ORDINALITY AS postings(user_id, currency, entry_type, amount, balance_after, position)
+            ORDER BY position
+            "#,
+            journal_id,
+            reference_id,
+            Utc::now(),
+            // Enum and nullable element types, which the macro cannot check
+            postings.iter().map(|posting| posting.user_id).collect::<Vec<_>>() as _,
+            &postings.iter().map(|posting| posting.currency.clone()).collect::<Vec<_>>(),
+            postings.iter().map(|posting| posting.entry_type).collect::<Vec<_>>() as _,
+            &postings.iter().map(|posting| posting.amount).collect::<Vec<_>>(),
+            postings.iter().map(|posting| posting.balance_after).collect::<Vec<_>>() as _,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Adds collected fees to the treasury account for `currency`, opening
+    /// it if needed, and returns its new balance for the fee entry's
+    /// `balance_after`.
+    pub async fn credit_treasury_in(&self, conn: &mut PgConnection, currency: &str, amount: Decimal) -> Result<Decimal> {
+        let balance = sqlx::query_scalar!(
+            r#"
+            INSERT INTO treasury_accounts (currency, balance, total_collected)
+            VALUES ($1, $2, $2)
+            ON CONFLICT (currency) DO UPDATE SET
+                balance = treasury_accounts.balance + EXCLUDED.balance,
+                total_collected = treasury_accounts.total_collected + EXCLUDED.total_collected,
+                updated_at = NOW()
+            RETURNING balance AS "balance!"
+            "#,
+            currency,
+            amount,
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(balance)
+    }
+
+    /// Takes fees back out of the treasury, as when the trade they were
+    /// charged on is busted, and returns its new balance. Fails if they
+    /// have been swept out since.
+    pub async fn refund_fees_in(&self, conn: &mut PgConnection, currency: &str, amount: Decimal) -> Result<Decimal> {
+        sqlx::query_scalar!(
+            r#"UPDATE treasury_accounts SET balance = balance - $2, total_collected = total_collected - $2, updated_at = NOW() WHERE currency = $1 AND balance >= $2 RETURNING balance AS "balance!""#,
+            currency,
+            amount,
+        )
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| CryptoTradeError::Validation {
+            message: format!("The treasury no longer holds {} {} of fees to refund", amount, currency),
+        })
+    }
+
+    /// The user's balance in every currency they have held as of `at`,
+    /// inclusive, read in one statement so all currencies come from the
+    /// same snapshot.
+    pub async fn balances_at(&self, user_id: Uuid, at: DateTime<Utc>) -> Result<HistoricalBalances> {
+        if at > Utc::now() {
+            return Err(CryptoTradeError::Validation {
+                message: "at must not be in the future".to_string(),
+            });
+        }
+
+        let balances = balances_until(&self.db, user_id, at, true).await?;
+        Ok(HistoricalBalances { at, balances })
+    }
+
+    /// A user's entries in one currency, oldest first, each carrying the
+    /// balance it left behind.
+    pub async fn get_statement(
+        &self,
+        user_id: Uuid,
+        currency: &str,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> Result<Statement> {
+        let limit = limit.unwrap_or(100).clamp(1, 1000);
+        let offset = offset.unwrap_or(0).max(0);
+
+        // One extra row tells whether another page follows
+        let mut entries = sqlx::query_as!(
+            LedgerEntry,
+            r#"
+            SELECT id, sequence, journal_id, user_id, currency, entry_type AS "entry_type: LedgerEntryType", amount, balance_after, reference_id, created_at
+            FROM ledger_entries
+            WHERE user_id = $1 AND currency = $2 AND ($3::timestamptz IS NULL OR created_at >= $3) AND ($4::timestamptz IS NULL OR created_at < $4)
+            ORDER BY sequence ASC
+            LIMIT $5 OFFSET $6
+            "#,
+            user_id,
+            currency,
+            start,
+            end,
+            limit + 1,
+            offset,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let has_more = entries.len() as i64 > limit;
+        entries.truncate(limit as usize);
+
+        Ok(Statement {
+            currency: currency.to_string(),
+            entries,
+            has_more,
+        })
+    }
+}
+
+/// A user's balances up to `end`, including entries made exactly at `end`
+/// when `inclusive`: the latest compaction checkpoint of each currency at or
+/// before `end` (see migration 042), plus the entries made since it.
+pub(crate) async fn balances_until(
+    db: &Database,
+    user_id: Uuid,
+    end: DateTime<Utc>,
+    inclusive: bool,
+) -> Result<Vec<HistoricalBalance>> {
+    sqlx::query_as!(
+        HistoricalBalance,
+        r#"
+        WITH checkpoints AS (
+            SELECT DISTINCT ON (currency) currency, period_end, balance
+            FROM ledger_checkpoints
+            WHERE user_id = $1 AND period_end <= $2
+            ORDER BY currency, period_end DESC
+        )
+        SELECT currency AS "currency!", SUM(amount) AS "balance!"
+        FROM (
+            SELECT currency, balance AS amount FROM checkpoints
+            UNION ALL
+            SELECT e.currency, e.amount
+            FROM ledger_entries e
+            LEFT JOIN checkpoints c ON c.currency = e.currency
+            WHERE e.user_id = $1
+              AND (e.created_at < $2 OR ($3 AND e.created_at = $2))
+              AND (c.period_end IS NULL OR e.created_at >= c.period_end)
+        ) movements
+        GROUP BY currency
+        ORDER BY currency
+        "#,
+        user_id,
+        end,
+        inclusive,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Into::into)
+}
@@ -0,0 +1,333 @@
+use crate::{
+    config::LoginRiskConfig,
+    database::Database,
+    email::{EmailMessage, EmailSender},
+    models::*,
+    services::DeviceInfo,
+    Result,
+};
+use axum::http::HeaderMap;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Mean radius of the Earth
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Parts of a login's risk score; an improbable move scores all three
+const NEW_ADDRESS_SCORE: i32 = 10;
+const NEW_COUNTRY_SCORE: i32 = 30;
+const IMPROBABLE_TRAVEL_SCORE: i32 = 60;
+
+/// Geo-velocity checks on logins (see migration 055). Each login that
+/// passes the password check is compared with the user's last trusted
+/// one. If reaching the new location in the time between would have taken
+/// improbable travel, the login is held for a step-up: a 2FA code, or the
+/// link [`crate::services::DeviceService`] emails. Every login is scored
+/// for admins, improbable or not.
+#[derive(Clone)]
+pub struct LoginRiskService {
+    db: Database,
+    email: Arc<dyn EmailSender>,
+    config: LoginRiskConfig,
+}
+
+/// How a login compares with the last trusted one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TravelAssessment {
+    pub distance_km: Option<f64>,
+    pub speed_kmh: Option<f64>,
+    pub risk_score: i32,
+    pub improbable: bool,
+}
+
+impl LoginRiskService {
+    pub fn new(db: Database, email: Arc<dyn EmailSender>, config: LoginRiskConfig) -> Self {
+        Self { db, email, config }
+    }
+
+    /// The caller's latitude and longitude, if the CDN reported valid ones.
+    pub fn coordinates(&self, headers: &HeaderMap) -> Option<(f64, f64)> {
+        let degrees = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<f64>().ok())
+        };
+        let latitude = degrees(&self.config.latitude_header)?;
+        let longitude = degrees(&self.config.longitude_header)?;
+        ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)).then_some((latitude, longitude))
+    }
+
+    /// Records where a user registered from, the first trusted location.
+    pub async fn record_registration(&self, user_id: Uuid, device: &DeviceInfo) -> Result<LoginEvent> {
+        self.insert(user_id, device, &assess_travel(&self.config, None, device, Utc::now()), LoginEventStatus::Allowed)
+            .await
+    }
+
+    /// Scores a login against the last trusted one. Improbable travel is
+    /// challenged unless the user passed 2FA in the same login, in which
+    /// case they are told of it instead.
+    pub async fn record_login(&self, user: &User, device: &DeviceInfo, two_factor_passed: bool) -> Result<LoginEvent> {
+        let previous = sqlx::query_as::<_, LoginEvent>(
+            "SELECT * FROM login_events WHERE user_id = $1 AND status <> 'challenged' ORDER BY COALESCE(confirmed_at, created_at) DESC LIMIT 1",
+        )
+        .bind(user.id)
+        .fetch_optional(&self.db)
+        .await?;
+        let assessment = assess_travel(&self.config, previous.as_ref(), device, Utc::now());
+        let status = if assessment.improbable && self.config.challenge && !two_factor_passed {
+            LoginEventStatus::Challenged
+        } else {
+            LoginEventStatus::Allowed
+        };
+        let login = self.insert(user.id, device, &assessment, status).await?;
+
+        // A challenged login is notified by the challenge's email instead
+        let allowed_improbable = previous.filter(|_| assessment.improbable && status == LoginEventStatus::Allowed);
+        if let Some(previous) = allowed_improbable {
+            tracing::warn!("Improbable travel for user {} allowed without a challenge", user.id);
+            self.email
+                .send(&EmailMessage {
+                    to: user.email.clone(),
+                    subject: "New login from an unusual location".to_string(),
+                    body: format!(
+                        "Your account was logged in to from {}, {} from where you last logged in {}.\n\n\
+                         If this was not you, change your password and revoke the device under your sessions now.",
+                        describe(&login),
+                        describe_distance(assessment.distance_km),
+                        describe_elapsed(login.created_at - previous.created_at)
+                    ),
+                })
+                .await?;
+        }
+        Ok(login)
+    }
+
+    /// The account's recent logins and the highest score among them.
+    pub async fn account_risk(&self, user_id: Uuid) -> Result<AccountLoginRisk> {
+        let logins = sqlx::query_as::<_, LoginEvent>(
+            "SELECT * FROM login_events WHERE user_id = $1 AND created_at >= $2 ORDER BY created_at DESC",
+        )
+        .bind(user_id)
+        .bind(Utc::now() - Duration::days(self.config.risk_window_days))
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(AccountLoginRisk {
+            user_id,
+            risk_score: logins.iter().map(|login| login.risk_score).max().unwrap_or(0),
+            logins,
+        })
+    }
+
+    async fn insert(
+        &self,
+        user_id: Uuid,
+        device: &DeviceInfo,
+        assessment: &TravelAssessment,
+        status: LoginEventStatus,
+    ) -> Result<LoginEvent> {
+        sqlx::query_as::<_, LoginEvent>(
+            r#"
+            INSERT INTO login_events (user_id, ip_address, country, latitude, longitude, distance_km, speed_kmh, risk_score, improbable_travel, status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(&device.ip_address)
+        .bind(&device.country)
+        .bind(device.coordinates.map(|(latitude, _)| latitude))
+        .bind(device.coordinates.map(|(_, longitude)| longitude))
+        .bind(assessment.distance_km.map(|km| km.round() as i32))
+        .bind(assessment.speed_kmh.map(|kmh| kmh.min(i32::MAX as f64).round() as i32))
+        .bind(assessment.risk_score)
+        .bind(assessment.improbable)
+        .bind(status)
+        .fetch_one(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+}
+
+/// Compares a login from `device` at `now` with the `previous` trusted
+/// one. With coordinates for both, the move is improbable if it is long
+/// and faster than `max_speed_kmh`; without, if the country changed within
+/// `country_change_minutes`.
+pub fn assess_travel(
+    config: &LoginRiskConfig,
+    previous: Option<&LoginEvent>,
+    device: &DeviceInfo,
+    now: DateTime<Utc>,
+) -> TravelAssessment {
+    let Some(previous) = previous else {
+        return TravelAssessment {
+            distance_km: None,
+            speed_kmh: None,
+            risk_score: 0,
+            improbable: false,
+        };
+    };
+    // A minute at least, so that logins in quick succession do not divide by zero
+    let elapsed = (now - previous.created_at).max(Duration::minutes(1));
+    let previous_coordinates = previous.latitude.zip(previous.longitude);
+    let distance_km = previous_coordinates
+        .zip(device.coordinates)
+        .map(|(from, to)| distance_km(from, to));
+    let speed_kmh = distance_km.map(|km| km / (elapsed.num_seconds() as f64 / 3600.0));
+
+    let new_address = device.ip_address.is_some() && device.ip_address != previous.ip_address;
+    let new_country = matches!((&previous.country, &device.country), (Some(from), Some(to)) if from != to);
+    let improbable = match (distance_km, speed_kmh) {
+        (Some(km), Some(kmh)) => km >= config.min_distance_km as f64 && kmh > config.max_speed_kmh as f64,
+        _ => new_country && elapsed < Duration::minutes(config.country_change_minutes),
+    };
+
+    let risk_score = [
+        (new_address, NEW_ADDRESS_SCORE),
+        (new_country, NEW_COUNTRY_SCORE),
+        (improbable, IMPROBABLE_TRAVEL_SCORE),
+    ]
+    .iter()
+    .filter(|(applies, _)| *applies)
+    .map(|(_, score)| score)
+    .sum::<i32>()
+    .min(100);
+
+    TravelAssessment {
+        distance_km,
+        speed_kmh,
+        risk_score,
+        improbable,
+    }
+}
+
+/// Great-circle distance between two points given in degrees, by the
+/// haversine formula.
+pub fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Where a login came from, for the user.
+pub fn describe(login: &LoginEvent) -> String {
+    match (&login.country, &login.ip_address) {
+        (Some(country), Some(ip)) => format!("{} ({})", ip, country),
+        (None, Some(ip)) => ip.clone(),
+        (Some(country), None) => country.clone(),
+        (None, None) => "an unknown address".to_string(),
+    }
+}
+
+fn describe_distance(distance_km: Option<f64>) -> String {
+    match distance_km {
+        Some(km) => format!("{:.0} km", km),
+        None => "far".to_string(),
+    }
+}
+
+fn describe_elapsed(elapsed: Duration) -> String {
+    match elapsed.num_minutes() {
+        minutes if minutes < 120 => format!("{} minutes before", minutes.max(1)),
+        minutes => format!("{} hours before", minutes / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEW_YORK: (f64, f64) = (40.7128, -74.0060);
+    const LONDON: (f64, f64) = (51.5074, -0.1278);
+    const NEWARK: (f64, f64) = (40.7357, -74.1724);
+
+    fn config() -> LoginRiskConfig {
+        LoginRiskConfig {
+            latitude_header: "cf-iplatitude".to_string(),
+            longitude_header: "cf-iplongitude".to_string(),
+            max_speed_kmh: 1000,
+            min_distance_km: 500,
+            country_change_minutes: 60,
+            challenge: true,
+            risk_window_days: 30,
+        }
+    }
+
+    fn login(ip: &str, country: &str, coordinates: Option<(f64, f64)>, at: DateTime<Utc>) -> LoginEvent {
+        LoginEvent {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            ip_address: Some(ip.to_string()),
+            country: Some(country.to_string()),
+            latitude: coordinates.map(|(latitude, _)| latitude),
+            longitude: coordinates.map(|(_, longitude)| longitude),
+            distance_km: None,
+            speed_kmh: None,
+            risk_score: 0,
+            improbable_travel: false,
+            status: LoginEventStatus::Allowed,
+            created_at: at,
+            confirmed_at: None,
+        }
+    }
+
+    fn device(ip: &str, country: &str, coordinates: Option<(f64, f64)>) -> DeviceInfo {
+        DeviceInfo {
+            fingerprint: String::new(),
+            user_agent: None,
+            ip_address: Some(ip.to_string()),
+            country: Some(country.to_string()),
+            coordinates,
+        }
+    }
+
+    #[test]
+    fn test_distance_km() {
+        assert!((distance_km(NEW_YORK, LONDON) - 5570.0).abs() < 10.0);
+        assert!(distance_km(NEW_YORK, NEWARK) < 20.0);
+        assert_eq!(distance_km(LONDON, LONDON), 0.0);
+    }
+
+    #[test]
+    fn test_crossing_the_atlantic_within_an_hour_is_improbable() {
+        let now = Utc::now();
+        let previous = login("192.0.2.1", "US", Some(NEW_YORK), now - Duration::hours(1));
+
+        let assessment = assess_travel(&config(), Some(&previous), &device("198.51.100.7", "GB", Some(LONDON)), now);
+        assert!(assessment.improbable);
+        assert_eq!(assessment.risk_score, 100);
+        assert!(assessment.speed_kmh.unwrap() > 5000.0);
+
+        // A flight's worth of time later it is plausible
+        let later = now + Duration::hours(8);
+        let assessment = assess_travel(&config(), Some(&previous), &device("198.51.100.7", "GB", Some(LONDON)), later);
+        assert!(!assessment.improbable);
+        assert_eq!(assessment.risk_score, 40);
+    }
+
+    #[test]
+    fn test_short_moves_and_unchanged_logins_are_not_improbable() {
+        let now = Utc::now();
+        let previous = login("192.0.2.1", "US", Some(NEW_YORK), now - Duration::seconds(5));
+
+        let nearby = assess_travel(&config(), Some(&previous), &device("192.0.2.9", "US", Some(NEWARK)), now);
+        assert!(!nearby.improbable);
+        assert_eq!(nearby.risk_score, 10);
+        let same = assess_travel(&config(), Some(&previous), &device("192.0.2.1", "US", Some(NEW_YORK)), now);
+        assert_eq!(same.risk_score, 0);
+        assert_eq!(assess_travel(&config(), None, &device("192.0.2.1", "GB", None), now).risk_score, 0);
+    }
+
+    #[test]
+    fn test_without_coordinates_a_quick_change_of_country_is_improbable() {
+        let now = Utc::now();
+        let previous = login("192.0.2.1", "US", None, now - Duration::minutes(30));
+
+        assert!(assess_travel(&config(), Some(&previous), &device("198.51.100.7", "GB", None), now).improbable);
+        let later = now + Duration::hours(2);
+        assert!(!assess_travel(&config(), Some(&previous), &device("198.51.100.7", "GB", None), later).improbable);
+    }
+}
@@ -1,148 +1,281 @@
 use crate::{
     database::Database,
     error::CryptoTradeError,
+    indicators::indicator_points,
+    interval::Interval,
     models::*,
     Result,
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use rust_decimal::Decimal;
 use sqlx::Row;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::{Duration as StdDuration, Instant},
+};
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
+/// Tickers for the pairs each tenant sees, and when they were computed
+type CachedTickers = HashMap<Option<Uuid>, (Instant, Arc<Vec<Ticker>>)>;
+
+/// Most candles an indicator may be computed over
+const MAX_INDICATOR_WINDOW: u32 = 200;
+
 #[derive(Clone)]
 pub struct MarketDataService {
     db: Database,
+    tickers: Arc<Mutex<CachedTickers>>,
+    tickers_ttl: StdDuration,
 }
 
 impl MarketDataService {
+    /// Computes tickers afresh on every request; see
+    /// [`MarketDataService::with_tickers_cache`].
     pub fn new(db: Database) -> Self {
-        Self { db }
+        Self {
+            db,
+            tickers: Arc::new(Mutex::new(HashMap::new())),
+            tickers_ttl: StdDuration::ZERO,
+        }
     }
 
-    pub async fn get_market_data(&self, trading_pair_id: Uuid) -> Result<MarketData> {
-        let now = Utc::now();
-        let yesterday = now - Duration::hours(24);
+    /// Serves tickers from memory for up to `ttl` after computing them.
+    pub fn with_tickers_cache(mut self, ttl: StdDuration) -> Self {
+        self.tickers_ttl = ttl;
+        self
+    }
+
+    pub fn tickers_cache_ttl(&self) -> StdDuration {
+        self.tickers_ttl
+    }
 
-        let stats = sqlx::query(
+    /// 24-hour tickers for every active pair `tenant_id` sees (the shared
+    /// ones, plus its own), ordered by symbol. Concurrent callers wait for
+    /// one computation rather than each running the query.
+    pub async fn get_tickers(&self, tenant_id: Option<Uuid>) -> Result<Arc<Vec<Ticker>>> {
+        let mut cached = self.tickers.lock().await;
+        if let Some((computed_at, tickers)) = cached.get(&tenant_id) {
+            if computed_at.elapsed() < self.tickers_ttl {
+                return Ok(tickers.clone());
+            }
+        }
+
+        let rows = sqlx::query(
             r#"
-            SELECT
-                tp.id as trading_pair_id,
-                tp.symbol,
-                COALESCE(t.last_price, 0) as last_price,
-                COALESCE(t.volume_24h, 0) as volume_24h,
-                COALESCE(t.high_24h, 0) as high_24h,
-                COALESCE(t.low_24h, 0) as low_24h,
-                COALESCE(t.first_price_24h, 0) as first_price_24h
-            FROM trading_pairs tp
-            LEFT JOIN (
+            WITH day AS (
                 SELECT
                     trading_pair_id,
-                    (SELECT price FROM trades t2
-                     WHERE t2.trading_pair_id = t1.trading_pair_id
-                       AND t2.created_at >= $1
-                     ORDER BY t2.created_at DESC
-                     LIMIT 1) as last_price,
-                    SUM(quantity * price) as volume_24h,
-                    MAX(price) as high_price,
-                    MIN(price) as low_price,
-                    (SELECT price FROM trades t3
-                     WHERE t3.trading_pair_id = t1.trading_pair_id
-                       AND t3.created_at >= $1
-                     ORDER BY t3.created_at ASC
-                     LIMIT 1) as first_price_24h
-                FROM trades t1
-                WHERE t1.created_at >= $1
+                    (array_agg(price ORDER BY created_at ASC))[1] AS open_price,
+                    (array_agg(price ORDER BY created_at DESC))[1] AS last_price,
+                    MAX(price) AS high_price,
+                    MIN(price) AS low_price,
+                    SUM(quantity) AS volume,
+                    SUM(quantity * price) AS quote_volume,
+                    COUNT(*) AS trade_count
+                FROM trades
+                WHERE created_at >= $1 AND busted_at IS NULL
                 GROUP BY trading_pair_id
-            ) t ON tp.id = t.trading_pair_id
-            WHERE tp.is_active = true AND tp.id = $2
-            "#
+            ),
+            book AS (
+                SELECT
+                    trading_pair_id,
+                    MAX(price) FILTER (WHERE side = 'buy') AS bid_price,
+                    MIN(price) FILTER (WHERE side = 'sell') AS ask_price
+                FROM orders
+                WHERE status IN ('open', 'partially_filled') AND price IS NOT NULL
+                GROUP BY trading_pair_id
+            )
+            SELECT
+                tp.symbol, tp.base_currency, tp.quote_currency,
+                day.open_price, day.last_price, day.high_price, day.low_price,
+                COALESCE(day.volume, 0) AS volume,
+                COALESCE(day.quote_volume, 0) AS quote_volume,
+                COALESCE(day.trade_count, 0) AS trade_count,
+                book.bid_price, book.ask_price
+            FROM trading_pairs tp
+            LEFT JOIN day ON day.trading_pair_id = tp.id
+            LEFT JOIN book ON book.trading_pair_id = tp.id
+            WHERE tp.is_active = true AND (tp.tenant_id IS NULL OR tp.tenant_id = $2)
+            ORDER BY tp.symbol
+            "#,
         )
-        .bind(yesterday)
-        .bind(trading_pair_id)
-        .fetch_optional(&self.db)
+        .bind(Utc::now() - Duration::hours(24))
+        .bind(tenant_id)
+        .fetch_all(&self.db)
         .await?;
 
-        if let Some(stat) = stats {
-            let last_price: Decimal = stat.get("last_price");
-            let first_price: Decimal = stat.get("first_price_24h");
-            let price_change = last_price - first_price;
-            let price_change_percent = if first_price > Decimal::ZERO {
-                (price_change / first_price) * Decimal::from(100)
-            } else {
-                Decimal::ZERO
-            };
-
-            Ok(MarketData {
-                trading_pair_id,
-                symbol: stat.get("symbol"),
-                last_price,
-                volume_24h: stat.get("volume_24h"),
-                high_24h: stat.get("high_24h"),
-                low_24h: stat.get("low_24h"),
-                price_change_24h: price_change,
-                price_change_percent_24h: price_change_percent,
-                bid_price: None,
-                ask_price: None,
-                updated_at: now,
+        let tickers: Vec<Ticker> = rows
+            .into_iter()
+            .map(|row| {
+                let open_price: Option<Decimal> = row.get("open_price");
+                let last_price: Option<Decimal> = row.get("last_price");
+                let (price_change, price_change_percent) = price_change(open_price, last_price);
+                Ticker {
+                    symbol: row.get("symbol"),
+                    base_currency: row.get("base_currency"),
+                    quote_currency: row.get("quote_currency"),
+                    last_price,
+                    open_price,
+                    high_price: row.get("high_price"),
+                    low_price: row.get("low_price"),
+                    price_change,
+                    price_change_percent,
+                    volume: row.get("volume"),
+                    quote_volume: row.get("quote_volume"),
+                    trade_count: row.get("trade_count"),
+                    bid_price: row.get("bid_price"),
+                    ask_price: row.get("ask_price"),
+                }
             })
-        } else {
-            Err(CryptoTradeError::NotFound {
+            .collect();
+
+        let tickers = Arc::new(tickers);
+        cached.insert(tenant_id, (Instant::now(), tickers.clone()));
+        Ok(tickers)
+    }
+
+    /// A pair's market data; pairs private to a tenant other than
+    /// `tenant_id` are not found.
+    pub async fn get_market_data(&self, trading_pair_id: Uuid, tenant_id: Option<Uuid>) -> Result<MarketData> {
+        self.fetch_market_data(Some(&[trading_pair_id]), tenant_id)
+            .await?
+            .pop()
+            .ok_or(CryptoTradeError::NotFound {
                 message: "Trading pair not found".to_string(),
             })
-        }
     }
 
-    pub async fn get_all_market_data(&self) -> Result<Vec<MarketData>> {
-        let trading_pairs = sqlx::query("SELECT id FROM trading_pairs WHERE is_active = true")
-            .fetch_all(&self.db)
-            .await?;
+    /// Market data for every active pair `tenant_id` sees, ordered by symbol.
+    pub async fn get_all_market_data(&self, tenant_id: Option<Uuid>) -> Result<Vec<MarketData>> {
+        self.fetch_market_data(None, tenant_id).await
+    }
 
-        let mut market_data = Vec::new();
-        for row in trading_pairs {
-            let trading_pair_id: Uuid = row.get("id");
-            if let Ok(data) = self.get_market_data(trading_pair_id).await {
-                market_data.push(data);
-            }
-        }
+    /// Market data for these pairs, in the order given. Pairs that are not
+    /// active or that `tenant_id` does not see are left out.
+    pub async fn get_market_data_for(&self, trading_pair_ids: &[Uuid], tenant_id: Option<Uuid>) -> Result<Vec<MarketData>> {
+        let mut market_data: HashMap<Uuid, MarketData> = self
+            .fetch_market_data(Some(trading_pair_ids), tenant_id)
+            .await?
+            .into_iter()
+            .map(|data| (data.trading_pair_id, data))
+            .collect();
+        Ok(trading_pair_ids.iter().filter_map(|id| market_data.remove(id)).collect())
+    }
+
+    /// 24h statistics of the active pairs `tenant_id` sees, or of those among
+    /// `trading_pair_ids`, in a single pass over the day's trades whatever
+    /// the number of pairs.
+    async fn fetch_market_data(&self, trading_pair_ids: Option<&[Uuid]>, tenant_id: Option<Uuid>) -> Result<Vec<MarketData>> {
+        let now = Utc::now();
+
+        let rows = sqlx::query(
+            r#"
+            WITH day AS (
+                SELECT
+                    trading_pair_id,
+                    (array_agg(price ORDER BY created_at DESC))[1] AS last_price,
+                    (array_agg(price ORDER BY created_at ASC))[1] AS first_price_24h,
+                    SUM(quantity * price) AS volume_24h,
+                    MAX(price) AS high_24h,
+                    MIN(price) AS low_24h
+                FROM trades
+                WHERE created_at >= $1 AND busted_at IS NULL
+                  AND ($2::UUID[] IS NULL OR trading_pair_id = ANY($2))
+                GROUP BY trading_pair_id
+            )
+            SELECT
+                tp.id AS trading_pair_id,
+                tp.symbol,
+                COALESCE(day.last_price, 0) AS last_price,
+                COALESCE(day.volume_24h, 0) AS volume_24h,
+                COALESCE(day.high_24h, 0) AS high_24h,
+                COALESCE(day.low_24h, 0) AS low_24h,
+                COALESCE(day.first_price_24h, 0) AS first_price_24h
+            FROM trading_pairs tp
+            LEFT JOIN day ON day.trading_pair_id = tp.id
+            WHERE tp.is_active = true AND ($2::UUID[] IS NULL OR tp.id = ANY($2))
+              AND (tp.tenant_id IS NULL OR tp.tenant_id = $3)
+            ORDER BY tp.symbol
+            "#,
+        )
+        .bind(now - Duration::hours(24))
+        .bind(trading_pair_ids)
+        .bind(tenant_id)
+        .fetch_all(&self.db)
+        .await?;
 
-        Ok(market_data)
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let last_price: Decimal = row.get("last_price");
+                let first_price: Decimal = row.get("first_price_24h");
+                let price_change = last_price - first_price;
+                let price_change_percent = if first_price > Decimal::ZERO {
+                    (price_change / first_price) * Decimal::from(100)
+                } else {
+                    Decimal::ZERO
+                };
+
+                MarketData {
+                    trading_pair_id: row.get("trading_pair_id"),
+                    symbol: row.get("symbol"),
+                    last_price,
+                    volume_24h: row.get("volume_24h"),
+                    high_24h: row.get("high_24h"),
+                    low_24h: row.get("low_24h"),
+                    price_change_24h: price_change,
+                    price_change_percent_24h: price_change_percent,
+                    bid_price: None,
+                    ask_price: None,
+                    updated_at: now,
+                }
+            })
+            .collect())
     }
 
     pub async fn get_candlestick_data(
         &self,
         trading_pair_id: Uuid,
-        interval: String,
+        interval: Interval,
         start_time: Option<chrono::DateTime<Utc>>,
         end_time: Option<chrono::DateTime<Utc>>,
         limit: Option<i32>,
+        fill: CandleFill,
     ) -> Result<Vec<Candlestick>> {
-        let start = start_time.unwrap_or_else(|| Utc::now() - Duration::days(1));
-        let end = end_time.unwrap_or_else(|| Utc::now());
+        // A start inside a bucket takes in the whole bucket
+        let start = interval.bucket_start(start_time.unwrap_or_else(|| Utc::now() - Duration::days(1)));
+        let end = end_time.unwrap_or_else(Utc::now);
         let limit = limit.unwrap_or(1000).min(5000);
 
-        let interval_minutes = match interval.as_str() {
-            "1m" => 1,
-            "5m" => 5,
-            "15m" => 15,
-            "1h" => 60,
-            "4h" => 240,
-            "1d" => 1440,
-            _ => 60,
-        };
+        let interval_minutes = interval.minutes();
 
         let rows = sqlx::query(
             r#"
+            WITH history AS (
+                SELECT price, quantity, created_at FROM trades
+                WHERE trading_pair_id = $1 AND created_at >= $2 AND created_at <= $3 AND busted_at IS NULL
+                UNION ALL
+                SELECT price, quantity, traded_at FROM imported_trades
+                WHERE trading_pair_id = $1 AND traded_at >= $2 AND traded_at <= $3
+            ),
+            bucketed AS (
+                SELECT price, quantity, created_at,
+                    CASE WHEN $6::text IS NULL
+                        THEN to_timestamp(floor(EXTRACT(EPOCH FROM created_at) / ($4 * 60)) * ($4 * 60))
+                        ELSE date_trunc($6::text, created_at, 'UTC')
+                    END as bucket_time
+                FROM history
+            )
             SELECT
-                date_trunc('minute', created_at) - INTERVAL '1 minute' * (EXTRACT(MINUTE FROM created_at)::int % $4) as bucket_time,
-                (SELECT price FROM trades t1 WHERE t1.trading_pair_id = $1 AND date_trunc('minute', t1.created_at) - INTERVAL '1 minute' * (EXTRACT(MINUTE FROM t1.created_at)::int % $4) = bucket_time ORDER BY t1.created_at ASC LIMIT 1) as open_price,
+                bucket_time,
+                (array_agg(price ORDER BY created_at ASC))[1] as open_price,
                 MAX(price) as high_price,
                 MIN(price) as low_price,
-                (SELECT price FROM trades t2 WHERE t2.trading_pair_id = $1 AND date_trunc('minute', t2.created_at) - INTERVAL '1 minute' * (EXTRACT(MINUTE FROM t2.created_at)::int % $4) = bucket_time ORDER BY t2.created_at DESC LIMIT 1) as close_price,
+                (array_agg(price ORDER BY created_at DESC))[1] as close_price,
                 SUM(quantity) as volume
-            FROM trades
-            WHERE trading_pair_id = $1
-              AND created_at >= $2
-              AND created_at <= $3
+            FROM bucketed
             GROUP BY bucket_time
             ORDER BY bucket_time
             LIMIT $5
@@ -153,11 +286,12 @@ impl MarketDataService {
         .bind(end)
         .bind(interval_minutes)
         .bind(limit)
+        .bind(interval.calendar_unit())
         .fetch_all(&self.db)
         .await?;
 
-        let candlesticks: Vec<Candlestick> = rows.into_iter().map(|row| {
-            Candlestick {
+        let mut candlesticks: BTreeMap<Option<DateTime<Utc>>, Candlestick> = rows.into_iter().map(|row| {
+            let candlestick = Candlestick {
                 timestamp: row.get("bucket_time"),
                 open: row.get("open_price"),
                 high: row.get("high_price"),
@@ -165,9 +299,261 @@ impl MarketDataService {
                 close: row.get("close_price"),
                 volume: row.get("volume"),
                 interval_minutes,
-            }
+            };
+            (candlestick.timestamp, candlestick)
         }).collect();
 
-        Ok(candlesticks)
+        // Imported candles fill the buckets that have no trades to build from
+        let imported = sqlx::query(
+            r#"
+            SELECT open_time, open, high, low, close, volume
+            FROM candlesticks
+            WHERE trading_pair_id = $1
+              AND interval_minutes = $2
+              AND open_time >= $3
+              AND open_time <= $4
+            ORDER BY open_time
+            LIMIT $5
+            "#
+        )
+        .bind(trading_pair_id)
+        .bind(interval_minutes)
+        .bind(start)
+        .bind(end)
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await?;
+
+        for row in imported {
+            let timestamp: Option<DateTime<Utc>> = row.get("open_time");
+            candlesticks.entry(timestamp).or_insert_with(|| Candlestick {
+                timestamp,
+                open: row.get("open"),
+                high: row.get("high"),
+                low: row.get("low"),
+                close: row.get("close"),
+                volume: row.get("volume"),
+                interval_minutes,
+            });
+        }
+
+        match fill {
+            CandleFill::None => Ok(candlesticks.into_values().take(limit as usize).collect()),
+            CandleFill::Previous => {
+                let previous_close = self.close_before(trading_pair_id, interval, start).await?;
+                let end = end.min(Utc::now());
+                Ok(fill_gaps(candlesticks, interval, start, end, previous_close, limit as usize))
+            }
+        }
+    }
+
+    /// `limit` points of indicators over `window` candles each, the last
+    /// for the bucket `end_time` (by default now) falls in. The candles
+    /// before the first point are read too, so every point has a full
+    /// window, and empty buckets count at the previous close. Refuses
+    /// ranges reaching back before `earliest`.
+    pub async fn get_indicators(
+        &self,
+        trading_pair_id: Uuid,
+        interval: Interval,
+        window: u32,
+        end_time: Option<DateTime<Utc>>,
+        limit: i32,
+        earliest: DateTime<Utc>,
+    ) -> Result<Indicators> {
+        if !(2..=MAX_INDICATOR_WINDOW).contains(&window) {
+            return Err(CryptoTradeError::Validation {
+                message: format!("window must be between 2 and {}", MAX_INDICATOR_WINDOW),
+            });
+        }
+        if limit < 1 {
+            return Err(CryptoTradeError::Validation {
+                message: "limit must be positive".to_string(),
+            });
+        }
+
+        let end = end_time.unwrap_or_else(Utc::now);
+        let candle_count = limit as u32 + window - 1;
+        let start = interval.buckets_before(interval.bucket_start(end), candle_count - 1);
+        if start < earliest {
+            return Err(CryptoTradeError::Validation {
+                message: format!(
+                    "{} {} candles before {} reach back further than allowed; use a smaller limit or window",
+                    candle_count, interval, end
+                ),
+            });
+        }
+
+        let candles = self
+            .get_candlestick_data(trading_pair_id, interval, Some(start), Some(end), Some(candle_count as i32), CandleFill::Previous)
+            .await?;
+        let points = indicator_points(&candles, window as usize);
+        let skip = points.len().saturating_sub(limit as usize);
+
+        Ok(Indicators {
+            trading_pair_id,
+            interval,
+            window,
+            points: points.into_iter().skip(skip).collect(),
+        })
+    }
+
+    /// The last price before `at`, from trades, imported trades or imported
+    /// candles of `interval`, so gaps at the start of a range can be filled.
+    async fn close_before(&self, trading_pair_id: Uuid, interval: Interval, at: DateTime<Utc>) -> Result<Option<Decimal>> {
+        let close = sqlx::query_scalar::<_, Decimal>(
+            r#"
+            SELECT price FROM (
+                (SELECT price, created_at AS at FROM trades
+                 WHERE trading_pair_id = $1 AND created_at < $2 AND busted_at IS NULL ORDER BY created_at DESC LIMIT 1)
+                UNION ALL
+                (SELECT price, traded_at FROM imported_trades
+                 WHERE trading_pair_id = $1 AND traded_at < $2 ORDER BY traded_at DESC LIMIT 1)
+                UNION ALL
+                (SELECT close, open_time FROM candlesticks
+                 WHERE trading_pair_id = $1 AND interval_minutes = $3 AND open_time < $2
+                 ORDER BY open_time DESC LIMIT 1)
+            ) latest
+            ORDER BY at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(trading_pair_id)
+        .bind(at)
+        .bind(interval.minutes())
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(close)
+    }
+}
+
+/// Candles for every bucket from `start` to `end`, with buckets that have
+/// none filled by a flat zero-volume candle at the previous close. Buckets
+/// before the first known price stay empty.
+fn fill_gaps(
+    candles: BTreeMap<Option<DateTime<Utc>>, Candlestick>,
+    interval: Interval,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    mut previous_close: Option<Decimal>,
+    limit: usize,
+) -> Vec<Candlestick> {
+    let mut candles = candles.into_values().peekable();
+    let mut filled = Vec::new();
+    let mut bucket = interval.bucket_start(start);
+
+    while bucket <= end && filled.len() < limit {
+        let mut covered = false;
+        while let Some(candle) = candles.next_if(|candle| candle.timestamp.is_none_or(|at| at <= bucket)) {
+            covered |= candle.timestamp == Some(bucket);
+            previous_close = candle.close.or(previous_close);
+            filled.push(candle);
+        }
+
+        match previous_close {
+            Some(close) if !covered => filled.push(Candlestick {
+                timestamp: Some(bucket),
+                open: Some(close),
+                high: Some(close),
+                low: Some(close),
+                close: Some(close),
+                volume: Some(Decimal::ZERO),
+                interval_minutes: interval.minutes(),
+            }),
+            // Nothing to carry forward yet: skip ahead to the first candle
+            None => match candles.peek().and_then(|candle| candle.timestamp) {
+                Some(next) => {
+                    bucket = interval.bucket_start(next);
+                    continue;
+                }
+                None => break,
+            },
+            Some(_) => {}
+        }
+        bucket = interval.bucket_end(bucket);
+    }
+
+    filled.truncate(limit);
+    filled
+}
+
+/// Change from `open` to `last`, absolute and in percent rounded to two
+/// decimals; zero unless the pair traded.
+fn price_change(open: Option<Decimal>, last: Option<Decimal>) -> (Decimal, Decimal) {
+    match (open, last) {
+        (Some(open), Some(last)) if !open.is_zero() => {
+            let change = last - open;
+            (change, (change / open * Decimal::from(100)).round_dp(2))
+        }
+        _ => (Decimal::ZERO, Decimal::ZERO),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(time: &str, close: i64) -> Candlestick {
+        let close = Some(Decimal::from(close));
+        Candlestick {
+            timestamp: Some(at(time)),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: Some(Decimal::ONE),
+            interval_minutes: 60,
+        }
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_fill_gaps_carries_the_previous_close_forward() {
+        let candles: BTreeMap<_, _> = [candle("2024-01-01T02:00:00Z", 105), candle("2024-01-01T04:00:00Z", 110)]
+            .into_iter()
+            .map(|candle| (candle.timestamp, candle))
+            .collect();
+        let fill = |previous_close, limit| {
+            fill_gaps(
+                candles.clone(),
+                Interval::OneHour,
+                at("2024-01-01T00:30:00Z"),
+                at("2024-01-01T05:00:00Z"),
+                previous_close,
+                limit,
+            )
+        };
+
+        let filled = fill(None, 100);
+        let closes: Vec<_> = filled.iter().map(|candle| (candle.timestamp.unwrap().format("%H").to_string(), candle.close.unwrap())).collect();
+        let expected = [("02", 105), ("03", 105), ("04", 110), ("05", 110)];
+        assert_eq!(closes, expected.map(|(hour, close)| (hour.to_string(), Decimal::from(close))));
+        assert_eq!(filled[1].volume, Some(Decimal::ZERO));
+        assert_eq!(filled[1].high, Some(Decimal::from(105)));
+
+        // A close from before the range fills its first buckets too
+        let filled = fill(Some(Decimal::from(100)), 3);
+        let closes: Vec<_> = filled.iter().map(|candle| candle.close.unwrap()).collect();
+        assert_eq!(closes, [Decimal::from(100), Decimal::from(100), Decimal::from(105)]);
+        assert_eq!(filled[0].timestamp, Some(at("2024-01-01T00:00:00Z")));
+
+        assert!(fill_gaps(BTreeMap::new(), Interval::OneHour, at("2024-01-01T00:00:00Z"), at("2024-01-02T00:00:00Z"), None, 10).is_empty());
+    }
+
+    #[test]
+    fn test_price_change_is_zero_without_trades() {
+        assert_eq!(price_change(None, None), (Decimal::ZERO, Decimal::ZERO));
+        assert_eq!(
+            price_change(Some(Decimal::from(300)), Some(Decimal::from(301))),
+            (Decimal::ONE, Decimal::new(33, 2))
+        );
+        assert_eq!(
+            price_change(Some(Decimal::from(200)), Some(Decimal::from(150))),
+            (Decimal::from(-50), Decimal::from(-25))
+        );
     }
 }
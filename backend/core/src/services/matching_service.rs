@@ -0,0 +1,618 @@
+use crate::{
+    database::Database,
+    error::CryptoTradeError,
+    matching::{EngineCommand, EngineEvent, EngineEventKind, LimitOrderBook, LiveCandles, NewOrder, ShardRouter},
+    models::*,
+    config::EngineConfig,
+    services::{event_log_service::JournalEntry, order_service::release_pending_locks, EventLogService, StreamService},
+    Result,
+};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+const SHARD_QUEUE_CAPACITY: usize = 1024;
+const BOOK_UPDATE_DEPTH: usize = 20;
+/// Distances from the mid price, in percent, that book stats report the
+/// liquidity within
+const DEPTH_BANDS_PERCENT: [(i64, u32); 3] = [(5, 1), (1, 0), (2, 0)];
+/// How often shards look for live candles whose bucket has ended
+const CANDLE_CLOSE_CHECK: Duration = Duration::from_millis(250);
+
+/// Routes engine commands to the shard that owns the trading pair.
+///
+/// Each shard is a worker task that exclusively owns the books of its pairs,
+/// so pairs on different shards match in parallel while commands for one
+/// pair stay strictly ordered. Books are restored from the latest snapshot
+/// plus the event log, either eagerly at startup via `recover` or lazily the
+/// first time a pair is touched, so the journal (not process memory) is the
+/// source of truth.
+#[derive(Clone)]
+pub struct MatchingService {
+    db: Database,
+    event_log: EventLogService,
+    router: ShardRouter,
+    shards: Arc<Vec<mpsc::Sender<ShardRequest>>>,
+}
+
+enum ShardRequest {
+    Submit(Submission),
+    Recover {
+        trading_pair_id: Uuid,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    Snapshot {
+        retain: i64,
+        reply: oneshot::Sender<Result<usize>>,
+    },
+    Status {
+        reply: oneshot::Sender<Vec<EngineStatus>>,
+    },
+    BookStats {
+        trading_pair_id: Uuid,
+        symbol: String,
+        reply: oneshot::Sender<Result<OrderBookStats>>,
+    },
+    BookSnapshot {
+        trading_pair_id: Uuid,
+        symbol: String,
+        depth: usize,
+        reply: oneshot::Sender<Result<OrderBookSnapshot>>,
+    },
+    Simulate {
+        trading_pair_id: Uuid,
+        order: NewOrder,
+        reply: oneshot::Sender<Result<BookSimulation>>,
+    },
+}
+
+struct Submission {
+    trading_pair_id: Uuid,
+    command: EngineCommand,
+    /// What was locked for the order the command adds, if it has no row yet
+    new_order_lock: Option<Decimal>,
+    reply: oneshot::Sender<Result<Vec<EngineEvent>>>,
+}
+
+/// What an order would do against a pair's live book, from `simulate`.
+#[derive(Debug, Clone)]
+pub struct BookSimulation {
+    /// The best opposite price before the order
+    pub best_price: Option<Decimal>,
+    pub events: Vec<EngineEvent>,
+}
+
+impl MatchingService {
+    /// Spawns one worker per shard; must be called from within a Tokio runtime.
+    pub fn new(db: Database, event_log: EventLogService, stream: StreamService, config: &EngineConfig) -> Self {
+        let router = ShardRouter::new(config.shard_count);
+
+        let shards = (0..router.shard_count())
+            .map(|shard_id| {
+                let (sender, receiver) = mpsc::channel(SHARD_QUEUE_CAPACITY);
+                let worker = ShardWorker {
+                    shard_id,
+                    event_log: event_log.clone(),
+                    stream: stream.clone(),
+                    books: HashMap::new(),
+                    snapshot_sequences: HashMap::new(),
+                    candles: HashMap::new(),
+                    journal_batch_size: config.journal_batch_size,
+                    async_journal_commit: config.async_journal_commit,
+                };
+                tokio::spawn(worker.run(receiver));
+                sender
+            })
+            .collect();
+
+        Self {
+            db,
+            event_log,
+            router,
+            shards: Arc::new(shards),
+        }
+    }
+
+    pub async fn submit(&self, trading_pair_id: Uuid, command: EngineCommand) -> Result<Vec<EngineEvent>> {
+        self.submit_command(trading_pair_id, command, None).await
+    }
+
+    /// Adds an order that has no row yet, with `locked` already locked for
+    /// it. The order goes to the book first and its row is written with the
+    /// journal batch that records it, so placing it costs no database round
+    /// trip before matching.
+    pub async fn place(&self, trading_pair_id: Uuid, order: NewOrder, locked: Decimal) -> Result<Vec<EngineEvent>> {
+        self.submit_command(trading_pair_id, EngineCommand::AddOrder(order), Some(locked)).await
+    }
+
+    async fn submit_command(
+        &self,
+        trading_pair_id: Uuid,
+        command: EngineCommand,
+        new_order_lock: Option<Decimal>,
+    ) -> Result<Vec<EngineEvent>> {
+        let (reply, response) = oneshot::channel();
+        self.send(
+            self.router.shard_for(trading_pair_id),
+            ShardRequest::Submit(Submission {
+                trading_pair_id,
+                command,
+                new_order_lock,
+                reply,
+            }),
+        )
+        .await?;
+        response.await.map_err(|_| CryptoTradeError::Internal)?
+    }
+
+    /// Rebuilds the books of all active pairs on their owning shards, and
+    /// releases what was locked for orders that never got their row: lost
+    /// with a journal batch that did not commit, or with the process before
+    /// it was written. Called once at startup before the API accepts orders.
+    pub async fn recover(&self) -> Result<()> {
+        let pair_ids = sqlx::query_scalar::<_, Uuid>("SELECT id FROM trading_pairs WHERE is_active = true")
+            .fetch_all(&self.db)
+            .await?;
+
+        let mut pending = Vec::with_capacity(pair_ids.len());
+        for trading_pair_id in pair_ids {
+            let (reply, response) = oneshot::channel();
+            self.send(
+                self.router.shard_for(trading_pair_id),
+                ShardRequest::Recover { trading_pair_id, reply },
+            )
+            .await?;
+            pending.push(response);
+        }
+
+        for response in pending {
+            response.await.map_err(|_| CryptoTradeError::Internal)??;
+        }
+
+        let pending_locks = sqlx::query_scalar::<_, Uuid>("SELECT order_id FROM pending_order_locks")
+            .fetch_all(&self.db)
+            .await?;
+        let released = release_pending_locks(&self.db, &pending_locks).await?;
+        if released > 0 {
+            tracing::warn!("Released the locks of {} order(s) lost before they were journaled", released);
+        }
+
+        Ok(())
+    }
+
+    /// Persists a snapshot of every book that changed since its last snapshot,
+    /// keeping the newest `retain` snapshots per pair.
+    pub async fn snapshot_books(&self, retain: i64) -> Result<usize> {
+        let mut saved = 0;
+        for shard in 0..self.shards.len() {
+            let (reply, response) = oneshot::channel();
+            self.send(shard, ShardRequest::Snapshot { retain, reply }).await?;
+            saved += response.await.map_err(|_| CryptoTradeError::Internal)??;
+        }
+        Ok(saved)
+    }
+
+    pub async fn status(&self) -> Vec<EngineStatus> {
+        let mut status = Vec::new();
+        for shard in 0..self.shards.len() {
+            let (reply, response) = oneshot::channel();
+            if self.send(shard, ShardRequest::Status { reply }).await.is_ok() {
+                status.extend(response.await.unwrap_or_default());
+            }
+        }
+        status.sort_by_key(|entry| entry.trading_pair_id);
+        status
+    }
+
+    /// Spread and near-mid liquidity of the pair's live book.
+    pub async fn book_stats(&self, trading_pair_id: Uuid) -> Result<OrderBookStats> {
+        let symbol = sqlx::query_scalar::<_, String>("SELECT symbol FROM trading_pairs WHERE id = $1")
+            .bind(trading_pair_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)?;
+
+        let (reply, response) = oneshot::channel();
+        self.send(
+            self.router.shard_for(trading_pair_id),
+            ShardRequest::BookStats {
+                trading_pair_id,
+                symbol,
+                reply,
+            },
+        )
+        .await?;
+        response.await.map_err(|_| CryptoTradeError::Internal)?
+    }
+
+    /// The pair's live book to `depth` levels per side, stamped with the
+    /// engine sequence it reflects. Book feed updates carry the same
+    /// sequence, so a client applies only those after the snapshot's.
+    pub async fn book_snapshot(&self, trading_pair_id: Uuid, depth: usize) -> Result<OrderBookSnapshot> {
+        let symbol = sqlx::query_scalar::<_, String>("SELECT symbol FROM trading_pairs WHERE id = $1")
+            .bind(trading_pair_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)?;
+
+        let (reply, response) = oneshot::channel();
+        self.send(
+            self.router.shard_for(trading_pair_id),
+            ShardRequest::BookSnapshot {
+                trading_pair_id,
+                symbol,
+                depth,
+                reply,
+            },
+        )
+        .await?;
+        response.await.map_err(|_| CryptoTradeError::Internal)?
+    }
+
+    /// Runs `order` against a copy of the pair's live book. Nothing is
+    /// journaled or published and the live book is left as it was.
+    pub async fn simulate(&self, trading_pair_id: Uuid, order: NewOrder) -> Result<BookSimulation> {
+        let (reply, response) = oneshot::channel();
+        self.send(
+            self.router.shard_for(trading_pair_id),
+            ShardRequest::Simulate {
+                trading_pair_id,
+                order,
+                reply,
+            },
+        )
+        .await?;
+        response.await.map_err(|_| CryptoTradeError::Internal)?
+    }
+
+    pub async fn replay(&self, trading_pair_id: Uuid, up_to_sequence: Option<i64>) -> Result<ReplayReport> {
+        let symbol = sqlx::query_scalar::<_, String>("SELECT symbol FROM trading_pairs WHERE id = $1")
+            .bind(trading_pair_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)?;
+
+        let replayed = self.event_log.replay(trading_pair_id, up_to_sequence).await?;
+
+        Ok(ReplayReport {
+            trading_pair_id,
+            last_sequence: replayed.book.last_sequence(),
+            events_replayed: replayed.events_replayed as i64,
+            divergent_sequences: replayed.divergent_sequences,
+            order_book: replayed.book.to_order_book(symbol, usize::MAX),
+        })
+    }
+
+    async fn send(&self, shard: usize, request: ShardRequest) -> Result<()> {
+        self.shards[shard].send(request).await.map_err(|_| {
+            tracing::error!("Matching shard {} is not running", shard);
+            CryptoTradeError::Internal
+        })
+    }
+}
+
+struct ShardWorker {
+    shard_id: usize,
+    event_log: EventLogService,
+    stream: StreamService,
+    books: HashMap<Uuid, LimitOrderBook>,
+    snapshot_sequences: HashMap<Uuid, i64>,
+    /// Live candles of the shard's pairs, fed by their fills
+    candles: HashMap<Uuid, LiveCandles>,
+    journal_batch_size: usize,
+    async_journal_commit: bool,
+}
+
+impl ShardWorker {
+    async fn run(mut self, mut requests: mpsc::Receiver<ShardRequest>) {
+        let mut candle_clock = tokio::time::interval(CANDLE_CLOSE_CHECK);
+        candle_clock.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            let request = tokio::select! {
+                request = requests.recv() => match request {
+                    Some(request) => request,
+                    None => break,
+                },
+                _ = candle_clock.tick() => {
+                    self.close_candles();
+                    continue;
+                }
+            };
+
+            let ShardRequest::Submit(submission) = request else {
+                self.handle(request).await;
+                continue;
+            };
+
+            // Whatever queued up while the last batch was journaling is
+            // matched now and journaled with this one
+            let mut batch = vec![submission];
+            let mut next = None;
+            while batch.len() < self.journal_batch_size {
+                match requests.try_recv() {
+                    Ok(ShardRequest::Submit(submission)) => batch.push(submission),
+                    Ok(other) => {
+                        next = Some(other);
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+            self.submit_batch(batch).await;
+            if let Some(request) = next {
+                self.handle(request).await;
+            }
+        }
+    }
+
+    async fn handle(&mut self, request: ShardRequest) {
+        match request {
+            ShardRequest::Submit(submission) => self.submit_batch(vec![submission]).await,
+            ShardRequest::Recover { trading_pair_id, reply } => {
+                let _ = reply.send(self.recover(trading_pair_id).await);
+            }
+            ShardRequest::Snapshot { retain, reply } => {
+                let _ = reply.send(self.snapshot(retain).await);
+            }
+            ShardRequest::Status { reply } => {
+                let _ = reply.send(self.status());
+            }
+            ShardRequest::BookStats {
+                trading_pair_id,
+                symbol,
+                reply,
+            } => {
+                let _ = reply.send(self.book_stats(trading_pair_id, symbol).await);
+            }
+            ShardRequest::BookSnapshot {
+                trading_pair_id,
+                symbol,
+                depth,
+                reply,
+            } => {
+                let _ = reply.send(self.book_snapshot(trading_pair_id, symbol, depth).await);
+            }
+            ShardRequest::Simulate {
+                trading_pair_id,
+                order,
+                reply,
+            } => {
+                let _ = reply.send(self.simulate(trading_pair_id, order).await);
+            }
+        }
+    }
+
+    /// Applies a batch of commands to their books, journals them in one
+    /// transaction and only then answers, publishes and updates candles, so
+    /// nothing is settled or seen that the journal might not have.
+    async fn submit_batch(&mut self, batch: Vec<Submission>) {
+        let mut processed = Vec::with_capacity(batch.len());
+        for submission in batch {
+            match self.book(submission.trading_pair_id).await {
+                Ok(book) => {
+                    let events = book.process(&submission.command, Utc::now());
+                    processed.push((submission, events));
+                }
+                Err(e) => {
+                    let _ = submission.reply.send(Err(e));
+                }
+            }
+        }
+        if processed.is_empty() {
+            return;
+        }
+
+        let entries: Vec<JournalEntry> = processed
+            .iter()
+            .map(|(submission, events)| JournalEntry {
+                events,
+                new_order_lock: submission.new_order_lock,
+            })
+            .collect();
+        if let Err(e) = self.event_log.append_batch(&entries, self.async_journal_commit).await {
+            tracing::error!("Failed to journal {} engine command(s) on shard {}: {}", processed.len(), self.shard_id, e);
+            for (submission, _) in processed {
+                // The book is now ahead of the journal; drop it so the next
+                // command rebuilds it from what was actually persisted.
+                self.books.remove(&submission.trading_pair_id);
+                let _ = submission.reply.send(Err(CryptoTradeError::Internal));
+            }
+            return;
+        }
+
+        for (submission, events) in processed {
+            if let Some(book) = self.books.get(&submission.trading_pair_id) {
+                // The command is already journaled; a feed failure must not fail it
+                if let Err(e) = self.publish(book, &events) {
+                    tracing::error!("Failed to publish feed updates for pair {}: {}", submission.trading_pair_id, e);
+                }
+            }
+            self.record_candles(submission.trading_pair_id, &events);
+            let _ = submission.reply.send(Ok(events));
+        }
+    }
+
+    async fn book_stats(&mut self, trading_pair_id: Uuid, symbol: String) -> Result<OrderBookStats> {
+        let bands = DEPTH_BANDS_PERCENT.map(|(num, scale)| Decimal::new(num, scale));
+        Ok(self.book(trading_pair_id).await?.stats(symbol, &bands))
+    }
+
+    async fn book_snapshot(&mut self, trading_pair_id: Uuid, symbol: String, depth: usize) -> Result<OrderBookSnapshot> {
+        let book = self.book(trading_pair_id).await?;
+        let order_book = book.to_order_book(symbol, depth);
+        Ok(OrderBookSnapshot {
+            trading_pair_id,
+            symbol: order_book.symbol,
+            last_update_id: book.last_sequence(),
+            bids: order_book.bids,
+            asks: order_book.asks,
+            timestamp: order_book.timestamp,
+        })
+    }
+
+    async fn simulate(&mut self, trading_pair_id: Uuid, order: NewOrder) -> Result<BookSimulation> {
+        let book = self.book(trading_pair_id).await?;
+        let best_price = match order.side {
+            OrderSide::Buy => book.best_ask(),
+            OrderSide::Sell => book.best_bid(),
+        };
+        Ok(BookSimulation {
+            best_price,
+            events: book.simulate(&order, Utc::now()),
+        })
+    }
+
+    /// The pair's book, loaded from the journal the first time it is needed.
+    async fn book(&mut self, trading_pair_id: Uuid) -> Result<&mut LimitOrderBook> {
+        Ok(match self.books.entry(trading_pair_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.event_log.load_book(trading_pair_id).await?.book),
+        })
+    }
+
+    /// Adds the command's fills to the pair's live candles and publishes
+    /// what changed.
+    fn record_candles(&mut self, trading_pair_id: Uuid, events: &[EngineEvent]) {
+        let candles = self
+            .candles
+            .entry(trading_pair_id)
+            .or_insert_with(|| LiveCandles::new(trading_pair_id));
+        for event in events {
+            if let EngineEventKind::Matched { price, quantity, .. } = &event.kind {
+                for update in candles.record(*price, *quantity, event.created_at) {
+                    publish_candle(&self.stream, &update);
+                }
+            }
+        }
+    }
+
+    /// Publishes the final state of every live candle whose bucket has ended.
+    fn close_candles(&mut self) {
+        let now = Utc::now();
+        for candles in self.candles.values_mut() {
+            for update in candles.close_due(now) {
+                publish_candle(&self.stream, &update);
+            }
+        }
+    }
+
+    /// Publishes the public side of a command's outcome: one message per fill
+    /// and, unless the command was rejected outright, the new book depth and
+    /// ticker.
+    fn publish(&self, book: &LimitOrderBook, events: &[EngineEvent]) -> Result<()> {
+        let trading_pair_id = book.trading_pair_id();
+        let mut book_changed = false;
+        let mut last_price = None;
+
+        for event in events {
+            match &event.kind {
+                EngineEventKind::Matched {
+                    taker_side,
+                    price,
+                    quantity,
+                    ..
+                } => {
+                    let trade = PublicTrade {
+                        trading_pair_id,
+                        price: *price,
+                        quantity: *quantity,
+                        taker_side: *taker_side,
+                        created_at: event.created_at,
+                    };
+                    self.stream
+                        .publish(StreamService::trades_channel(trading_pair_id), serde_json::to_value(&trade)?);
+                    last_price = Some(*price);
+                    book_changed = true;
+                }
+                EngineEventKind::Rejected { .. } => {}
+                _ => book_changed = true,
+            }
+        }
+
+        if book_changed {
+            let update = BookUpdate {
+                trading_pair_id,
+                engine_sequence: book.last_sequence(),
+                bids: book.bid_levels(BOOK_UPDATE_DEPTH),
+                asks: book.ask_levels(BOOK_UPDATE_DEPTH),
+            };
+            self.stream
+                .publish(StreamService::book_channel(trading_pair_id), serde_json::to_value(&update)?);
+
+            let ticker = TickerUpdate {
+                trading_pair_id,
+                last_price,
+                best_bid: book.best_bid(),
+                best_ask: book.best_ask(),
+                engine_sequence: book.last_sequence(),
+            };
+            self.stream
+                .publish(StreamService::ticker_channel(trading_pair_id), serde_json::to_value(&ticker)?);
+        }
+
+        Ok(())
+    }
+
+    async fn recover(&mut self, trading_pair_id: Uuid) -> Result<()> {
+        let recovered = self.event_log.load_book(trading_pair_id).await?;
+        if !recovered.divergent_sequences.is_empty() {
+            tracing::warn!(
+                "Replay of pair {} diverged from the journal at {} sequence(s)",
+                trading_pair_id,
+                recovered.divergent_sequences.len()
+            );
+        }
+        tracing::info!(
+            "Shard {} recovered book for pair {} at sequence {}",
+            self.shard_id,
+            trading_pair_id,
+            recovered.book.last_sequence()
+        );
+        self.books.insert(trading_pair_id, recovered.book);
+        Ok(())
+    }
+
+    async fn snapshot(&mut self, retain: i64) -> Result<usize> {
+        let snapshots: Vec<_> = self
+            .books
+            .values()
+            .filter(|book| {
+                let taken = self.snapshot_sequences.get(&book.trading_pair_id()).copied().unwrap_or(0);
+                book.last_sequence() > taken
+            })
+            .map(|book| book.snapshot())
+            .collect();
+
+        for snapshot in &snapshots {
+            self.event_log.save_snapshot(snapshot, retain).await?;
+            self.snapshot_sequences.insert(snapshot.trading_pair_id, snapshot.sequence);
+        }
+
+        Ok(snapshots.len())
+    }
+
+    fn status(&self) -> Vec<EngineStatus> {
+        self.books
+            .values()
+            .map(|book| EngineStatus {
+                trading_pair_id: book.trading_pair_id(),
+                shard: self.shard_id as i32,
+                last_sequence: book.last_sequence(),
+                last_snapshot_sequence: self.snapshot_sequences.get(&book.trading_pair_id()).copied(),
+            })
+            .collect()
+    }
+}
+
+fn publish_candle(stream: &StreamService, update: &CandleUpdate) {
+    match serde_json::to_value(update) {
+        Ok(data) => stream.publish(StreamService::candles_channel(update.trading_pair_id, update.interval), data),
+        Err(e) => tracing::error!("Failed to encode candle update: {}", e),
+    }
+}
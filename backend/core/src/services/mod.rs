@@ -1,11 +1,91 @@
+pub mod alert_service;
+pub mod algo_order_service;
+pub mod analytics_service;
+pub mod api_key_service;
+pub mod captcha_service;
+pub mod compliance_service;
+pub mod copy_trading_service;
+pub mod crypto_transfer_service;
+pub mod currency_service;
+pub mod device_service;
+pub mod document_service;
+pub mod dust_service;
+pub mod event_log_service;
+pub mod exchange_info_service;
+pub mod export_service;
+pub mod feature_flag_service;
+pub mod fiat_withdrawal_service;
+pub mod grid_bot_service;
+pub mod import_service;
+pub mod ledger_compaction_service;
+pub mod ledger_service;
+pub mod login_risk_service;
 pub mod market_data_service;
+pub mod matching_service;
+pub mod order_import_service;
 pub mod order_service;
+pub mod outbox_service;
+pub mod partition_service;
+pub mod payment_service;
 pub mod portfolio_service;
+pub mod rate_limit_service;
+pub mod rebalance_service;
+pub mod risk_limit_service;
+pub mod risk_service;
+pub mod runtime_config_service;
+pub mod sandbox_service;
+pub mod stream_service;
+pub mod support_service;
+pub mod tenant_service;
+pub mod trade_bust_service;
+pub mod trading_pair_service;
 pub mod trading_service;
+pub mod treasury_service;
 pub mod user_service;
+pub mod watchlist_service;
 
+pub use alert_service::{AlertService, ALERT_SIGNATURE_HEADER};
+pub use algo_order_service::AlgoOrderService;
+pub use analytics_service::AnalyticsService;
+pub use api_key_service::{sign_request, ApiKeyService, SignedRequest};
+pub use captcha_service::CaptchaService;
+pub use compliance_service::ComplianceService;
+pub use copy_trading_service::CopyTradingService;
+pub use crypto_transfer_service::{sign_deposit_report, CryptoTransferService, DEPOSIT_REPORT_SIGNATURE_HEADER};
+pub use currency_service::CurrencyService;
+pub use device_service::{DeviceInfo, DeviceService};
+pub use document_service::{DocumentBody, DocumentService};
+pub use dust_service::DustService;
+pub use event_log_service::EventLogService;
+pub use exchange_info_service::ExchangeInfoService;
+pub use export_service::{ExportRows, ExportService};
+pub use feature_flag_service::FeatureFlagService;
+pub use fiat_withdrawal_service::FiatWithdrawalService;
+pub use grid_bot_service::GridBotService;
+pub use import_service::{ImportReport, ImportService};
+pub use ledger_compaction_service::{CompactionReport, LedgerCompactionService};
+pub use ledger_service::{LedgerService, Posting};
+pub use login_risk_service::LoginRiskService;
 pub use market_data_service::MarketDataService;
+pub use matching_service::MatchingService;
+pub use order_import_service::OrderImportService;
 pub use order_service::OrderService;
+pub use outbox_service::{claim_event, enqueue_event, DomainEvent, EventConsumer, EventPublisher, NatsPublisher, OutboxMessage, OutboxService};
+pub use partition_service::PartitionService;
+pub use payment_service::PaymentService;
 pub use portfolio_service::PortfolioService;
+pub use rate_limit_service::{Caller, RateLimitService, RateLimitStatus};
+pub use rebalance_service::RebalanceService;
+pub use risk_limit_service::RiskLimitService;
+pub use risk_service::{PreTradeOrder, RiskMetrics, RiskService};
+pub use runtime_config_service::RuntimeConfigService;
+pub use sandbox_service::SandboxService;
+pub use stream_service::StreamService;
+pub use support_service::{SupportService, TicketActor};
+pub use tenant_service::TenantService;
+pub use trade_bust_service::TradeBustService;
+pub use trading_pair_service::TradingPairService;
 pub use trading_service::TradingService;
+pub use treasury_service::TreasuryService;
 pub use user_service::UserService;
+pub use watchlist_service::WatchlistService;
@@ -0,0 +1,345 @@
+use crate::{
+    config::OrderImportConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    services::{
+        import_service::{csv_reader, decimal, records, require_columns},
+        user_service::account_status,
+        OrderService,
+    },
+    Result,
+};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::Deserialize;
+use sqlx::{Postgres, QueryBuilder};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use uuid::Uuid;
+
+/// Rows per INSERT, well under Postgres' bind parameter limit
+const INSERT_BATCH: usize = 1000;
+
+/// Places limit orders in bulk from a CSV (see migration 052). Rows are
+/// parsed with [`parse_orders`] on upload; small files are then placed
+/// before the upload returns, larger ones by
+/// [`OrderImportService::process_pending`], which the API runs in the
+/// background. Each row is placed, or in a dry run validated, on its own:
+/// one bad row does not stop the rest.
+#[derive(Clone)]
+pub struct OrderImportService {
+    db: Database,
+    order_service: OrderService,
+    config: OrderImportConfig,
+}
+
+/// One row of an order CSV.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedOrder {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub quantity: Decimal,
+    pub price: Decimal,
+    pub time_in_force: TimeInForce,
+}
+
+/// Order columns: `symbol,side,quantity,price`, optionally `time_in_force`
+/// (GTC by default)
+#[derive(Deserialize)]
+struct OrderRecord {
+    #[serde(alias = "pair")]
+    symbol: String,
+    side: String,
+    #[serde(alias = "amount", alias = "size")]
+    quantity: String,
+    price: String,
+    #[serde(default)]
+    time_in_force: Option<String>,
+}
+
+impl OrderImportService {
+    pub fn new(db: Database, order_service: OrderService, config: OrderImportConfig) -> Self {
+        Self {
+            db,
+            order_service,
+            config,
+        }
+    }
+
+    /// Records the file's rows, rejecting those that do not parse or name
+    /// an unknown pair, and processes them at once if the file is small.
+    pub async fn upload(&self, user_id: Uuid, csv: &[u8], dry_run: bool) -> Result<OrderImportReport> {
+        if !dry_run {
+            account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        }
+        let rows = parse_orders(csv)?;
+        if rows.is_empty() || rows.len() > self.config.max_rows {
+            return Err(CryptoTradeError::Validation {
+                message: format!("An import must have between 1 and {} orders", self.config.max_rows),
+            });
+        }
+
+        let symbols: Vec<String> = rows
+            .iter()
+            .filter_map(|(_, row)| row.as_ref().ok().map(|order| order.symbol.clone()))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let pairs: HashMap<String, Uuid> =
+            sqlx::query_as::<_, (String, Uuid)>("SELECT symbol, id FROM trading_pairs WHERE symbol = ANY($1)")
+                .bind(&symbols)
+                .fetch_all(&self.db)
+                .await?
+                .into_iter()
+                .collect();
+        let rows: Vec<(i32, std::result::Result<CreateOrderRequest, String>)> = rows
+            .into_iter()
+            .map(|(line, row)| {
+                let request = row.and_then(|order| {
+                    let trading_pair_id = *pairs.get(&order.symbol).ok_or_else(|| format!("unknown symbol '{}'", order.symbol))?;
+                    order_request(trading_pair_id, &order)
+                });
+                (line as i32, request)
+            })
+            .collect();
+
+        let mut tx = self.db.begin().await?;
+        let import = sqlx::query_as::<_, OrderImport>(
+            "INSERT INTO order_imports (user_id, dry_run, total_rows) VALUES ($1, $2, $3) RETURNING *",
+        )
+        .bind(user_id)
+        .bind(dry_run)
+        .bind(rows.len() as i32)
+        .fetch_one(&mut *tx)
+        .await?;
+        for batch in rows.chunks(INSERT_BATCH) {
+            let mut query = QueryBuilder::<Postgres>::new("INSERT INTO order_import_rows (import_id, line, request, status, reason) ");
+            query.push_values(batch, |mut row, (line, request)| {
+                let (request, status, reason) = match request {
+                    Ok(request) => (serde_json::to_value(request).ok(), OrderImportRowStatus::Pending, None),
+                    Err(reason) => (None, OrderImportRowStatus::Rejected, Some(reason.clone())),
+                };
+                row.push_bind(import.id)
+                    .push_bind(*line)
+                    .push_bind(request)
+                    .push_bind(status)
+                    .push_bind(reason);
+            });
+            query.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+
+        if rows.len() <= self.config.inline_rows {
+            self.process(Some(import.id), rows.len() as i64).await?;
+        }
+        self.report(user_id, import.id).await
+    }
+
+    /// The import with every row's outcome so far.
+    pub async fn report(&self, user_id: Uuid, import_id: Uuid) -> Result<OrderImportReport> {
+        let import = sqlx::query_as::<_, OrderImport>("SELECT * FROM order_imports WHERE id = $1 AND user_id = $2")
+            .bind(import_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(|| CryptoTradeError::NotFound {
+                message: "Order import not found".to_string(),
+            })?;
+        let rows = sqlx::query_as::<_, OrderImportRow>(
+            "SELECT line, status, order_id, reason FROM order_import_rows WHERE import_id = $1 ORDER BY line",
+        )
+        .bind(import_id)
+        .fetch_all(&self.db)
+        .await?;
+        let count = |status| rows.iter().filter(|row| row.status == status).count() as i64;
+
+        Ok(OrderImportReport {
+            pending: count(OrderImportRowStatus::Pending),
+            valid: count(OrderImportRowStatus::Valid),
+            placed: count(OrderImportRowStatus::Placed),
+            rejected: count(OrderImportRowStatus::Rejected),
+            import,
+            rows,
+        })
+    }
+
+    /// Processes up to `limit` rows of pending imports, oldest import
+    /// first; returns how many were processed. Instances running this
+    /// concurrently take different imports.
+    pub async fn process_pending(&self, limit: i64) -> Result<usize> {
+        self.process(None, limit).await
+    }
+
+    /// Works through `only`, or any pending import, and completes each
+    /// import once it has no rows left.
+    async fn process(&self, only: Option<Uuid>, limit: i64) -> Result<usize> {
+        let mut processed = 0;
+        loop {
+            let mut tx = self.db.begin().await?;
+            let Some(import) = sqlx::query_as::<_, OrderImport>(
+                r#"
+                SELECT * FROM order_imports
+                WHERE status = 'pending' AND ($1::uuid IS NULL OR id = $1)
+                ORDER BY created_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+                "#,
+            )
+            .bind(only)
+            .fetch_optional(&mut *tx)
+            .await?
+            else {
+                break;
+            };
+
+            let rows = sqlx::query_as::<_, (i32, serde_json::Value)>(
+                "SELECT line, request FROM order_import_rows WHERE import_id = $1 AND status = 'pending' ORDER BY line LIMIT $2",
+            )
+            .bind(import.id)
+            .bind(limit - processed as i64)
+            .fetch_all(&mut *tx)
+            .await?;
+            for (line, request) in rows {
+                self.process_row(&import, line, request).await?;
+                processed += 1;
+            }
+
+            // Rows are updated outside the transaction, so each one placed
+            // stays recorded as placed whatever happens to the rest
+            let remaining = sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS (SELECT 1 FROM order_import_rows WHERE import_id = $1 AND status = 'pending')",
+            )
+            .bind(import.id)
+            .fetch_one(&self.db)
+            .await?;
+            if !remaining {
+                sqlx::query("UPDATE order_imports SET status = 'completed', completed_at = NOW() WHERE id = $1")
+                    .bind(import.id)
+                    .execute(&mut *tx)
+                    .await?;
+                tracing::info!("Order import {} completed", import.id);
+            }
+            tx.commit().await?;
+
+            if remaining && processed as i64 >= limit {
+                break;
+            }
+        }
+
+        Ok(processed)
+    }
+
+    async fn process_row(&self, import: &OrderImport, line: i32, request: serde_json::Value) -> Result<()> {
+        let outcome = match serde_json::from_value::<CreateOrderRequest>(request) {
+            Ok(request) if import.dry_run => self.order_service.preview_order(import.user_id, request).await.map(|_| None),
+            Ok(request) => self.order_service.create_order(import.user_id, request).await.map(|order| Some(order.id)),
+            Err(e) => Err(e.into()),
+        };
+        let (status, order_id, reason) = match outcome {
+            Ok(None) => (OrderImportRowStatus::Valid, None, None),
+            Ok(Some(order_id)) => (OrderImportRowStatus::Placed, Some(order_id), None),
+            Err(e) => (OrderImportRowStatus::Rejected, None, Some(e.to_string())),
+        };
+        sqlx::query("UPDATE order_import_rows SET status = $3, order_id = $4, reason = $5 WHERE import_id = $1 AND line = $2")
+            .bind(import.id)
+            .bind(line)
+            .bind(status)
+            .bind(order_id)
+            .bind(reason)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Parses an order CSV into its rows with their line numbers; a row that
+/// does not parse is an error for that row alone.
+pub fn parse_orders(reader: impl Read) -> Result<Vec<(u64, std::result::Result<ImportedOrder, String>)>> {
+    let mut csv = csv_reader(reader);
+    require_columns(&mut csv, &["symbol", "side", "quantity", "price"])?;
+
+    Ok(records::<OrderRecord, _>(&mut csv)
+        .map(|(line, record)| (line, record.and_then(validate_order)))
+        .collect())
+}
+
+fn validate_order(record: OrderRecord) -> std::result::Result<ImportedOrder, String> {
+    let side = match record.side.to_ascii_lowercase().as_str() {
+        "buy" => OrderSide::Buy,
+        "sell" => OrderSide::Sell,
+        other => return Err(format!("unknown side '{}'", other)),
+    };
+    let quantity = decimal(&record.quantity, "quantity")?;
+    let price = decimal(&record.price, "price")?;
+    if price <= Decimal::ZERO || quantity <= Decimal::ZERO {
+        return Err("price and quantity must be positive".to_string());
+    }
+    let time_in_force = match record.time_in_force.as_deref().map(str::to_ascii_uppercase).as_deref() {
+        None | Some("") | Some("GTC") => TimeInForce::GTC,
+        Some("IOC") => TimeInForce::IOC,
+        Some("FOK") => TimeInForce::FOK,
+        Some(other) => return Err(format!("time_in_force must be GTC, IOC or FOK, not '{}'", other)),
+    };
+
+    Ok(ImportedOrder {
+        symbol: record.symbol.to_ascii_uppercase(),
+        side,
+        quantity,
+        price,
+        time_in_force,
+    })
+}
+
+fn order_request(trading_pair_id: Uuid, order: &ImportedOrder) -> std::result::Result<CreateOrderRequest, String> {
+    Ok(CreateOrderRequest {
+        trading_pair_id,
+        order_type: OrderType::Limit,
+        side: order.side,
+        quantity: order.quantity.to_f64().ok_or("quantity is out of range")?,
+        price: Some(order.price),
+        time_in_force: Some(order.time_in_force),
+        stop_price: None,
+        // A file may well list the same order twice on purpose
+        allow_duplicate: true,
+        activate_at: None,
+        lock_funds: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_rows_are_validated_one_by_one() {
+        let csv = "\
+Symbol,Side,Quantity,Price,Time_In_Force
+btc-usd,BUY,0.5,20000,
+BTC-USD,sell,1,21000,ioc
+BTC-USD,hold,1,21000,
+BTC-USD,buy,-1,20000,
+BTC-USD,buy,1,20000,gtd
+";
+        let rows = parse_orders(csv.as_bytes()).unwrap();
+
+        assert_eq!(rows.len(), 5);
+        assert_eq!(
+            rows[0].1,
+            Ok(ImportedOrder {
+                symbol: "BTC-USD".to_string(),
+                side: OrderSide::Buy,
+                quantity: Decimal::new(5, 1),
+                price: Decimal::from(20_000),
+                time_in_force: TimeInForce::GTC,
+            })
+        );
+        assert_eq!(rows[1].1.as_ref().unwrap().time_in_force, TimeInForce::IOC);
+        let rejected: Vec<u64> = rows.iter().filter(|(_, row)| row.is_err()).map(|(line, _)| *line).collect();
+        assert_eq!(rejected, vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn test_order_files_need_the_order_columns() {
+        assert!(parse_orders("symbol,side,quantity\nBTC-USD,buy,1\n".as_bytes()).is_err());
+    }
+}
@@ -1,65 +1,118 @@
 use crate::{
     database::Database,
     error::CryptoTradeError,
+    matching::{EngineCommand, EngineEvent, EngineEventKind, NewOrder},
     models::*,
+    services::{
+        tenant_service::{ensure_pair_visible, with_fee_schedule},
+        user_service::account_status,
+        trading_service::MatchSettlement,
+        MatchingService, PreTradeOrder, RiskService, RuntimeConfigService, TradingService,
+    },
     Result,
 };
-use chrono::Utc;
-use rust_decimal::Decimal;
-use sqlx::Row;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+use sqlx::{PgExecutor, Row};
 use uuid::Uuid;
 use validator::Validate;
 
 #[derive(Clone)]
 pub struct OrderService {
     db: Database,
+    matching_service: MatchingService,
+    trading_service: TradingService,
+    risk_service: RiskService,
+    runtime: RuntimeConfigService,
+    max_schedule_days: u32,
 }
 
 impl OrderService {
-    pub fn new(db: Database) -> Self {
-        Self { db }
+    pub fn new(
+        db: Database,
+        matching_service: MatchingService,
+        trading_service: TradingService,
+        risk_service: RiskService,
+        runtime: RuntimeConfigService,
+        max_schedule_days: u32,
+    ) -> Self {
+        Self {
+            db,
+            matching_service,
+            trading_service,
+            risk_service,
+            runtime,
+            max_schedule_days,
+        }
     }
 
     pub async fn create_order(&self, user_id: Uuid, request: CreateOrderRequest) -> Result<Order> {
-        request.validate().map_err(|e| CryptoTradeError::Validation {
-            message: e.to_string(),
-        })?;
-
-        // Validate trading pair
-        let trading_pair = self.get_trading_pair(request.trading_pair_id).await?;
-        if !trading_pair.is_active.unwrap_or(false) {
-            return Err(CryptoTradeError::TradingPairNotActive);
+        account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        let (trading_pair, quantity) = self.validate_order_request(user_id, &request).await?;
+        if let Some(activate_at) = request.activate_at {
+            return self.schedule_order(user_id, &trading_pair, &request, quantity, activate_at).await;
         }
+        let preview = self.estimate_order(user_id, &trading_pair, &request, quantity).await?;
+        self.risk_service
+            .check_order(&PreTradeOrder {
+                user_id,
+                trading_pair: &trading_pair,
+                request: &request,
+                quantity,
+                preview: Some(&preview),
+            })
+            .await?;
 
-        let quantity = Decimal::from_f64_retain(request.quantity)
-            .ok_or(CryptoTradeError::InvalidQuantity)?;
-
-        let min_size = trading_pair.min_order_size.unwrap_or(Decimal::ZERO);
-        let max_size = trading_pair.max_order_size.unwrap_or(Decimal::from(1000000));
-
-        if quantity < min_size || quantity > max_size {
-            return Err(CryptoTradeError::InvalidQuantity);
-        }
+        let window_seconds = self.runtime.settings().duplicate_window_seconds;
+        let fingerprint = if request.allow_duplicate || window_seconds == 0 {
+            None
+        } else {
+            Some(self.claim_fingerprint(user_id, &request, quantity, window_seconds).await?)
+        };
 
-        if matches!(request.order_type, OrderType::Limit | OrderType::StopLossLimit | OrderType::TakeProfitLimit) {
-            if request.price.is_none() {
-                return Err(CryptoTradeError::InvalidPrice);
+        let placed = self.place_order(user_id, &request, quantity, &preview).await;
+        if let (Err(_), Some(fingerprint)) = (&placed, fingerprint) {
+            // A failed attempt must not block the user's retry
+            if let Err(e) = self.release_fingerprint(user_id, &fingerprint).await {
+                tracing::warn!("Failed to release order fingerprint for user {}: {}", user_id, e);
             }
         }
+        placed
+    }
 
-        let required_currency = match request.side {
-            OrderSide::Buy => &trading_pair.quote_currency,
-            OrderSide::Sell => &trading_pair.base_currency,
-        };
-
-        let required_amount = match request.side {
-            OrderSide::Buy => quantity * request.price.unwrap_or(Decimal::ZERO),
-            OrderSide::Sell => quantity,
-        };
+    /// Orders the engine matches go to it straight after their funds are
+    /// locked, and their row is written with the journal; stop orders rest
+    /// outside the book, so they are only recorded.
+    async fn place_order(&self, user_id: Uuid, request: &CreateOrderRequest, quantity: Decimal, preview: &OrderPreview) -> Result<Order> {
+        let order_id = Uuid::new_v4();
+        if matches!(request.order_type, OrderType::Market | OrderType::Limit) {
+            self.lock_pending(order_id, user_id, &preview.lock_currency, preview.lock_amount).await?;
+            let order = NewOrder {
+                order_id,
+                user_id,
+                side: request.side,
+                order_type: request.order_type,
+                price: request.price,
+                quantity,
+                time_in_force: request.time_in_force.unwrap_or(TimeInForce::GTC),
+                quote_budget: quote_budget(preview),
+            };
+            let events = match self.matching_service.place(request.trading_pair_id, order, preview.lock_amount).await {
+                Ok(events) => events,
+                Err(e) => {
+                    // The order was never journaled, so nothing else holds the lock
+                    if let Err(unlock_error) = release_pending_locks(&self.db, &[order_id]).await {
+                        tracing::error!("Failed to release the lock of unplaced order {}: {}", order_id, unlock_error);
+                    }
+                    return Err(e);
+                }
+            };
+            self.apply_engine_events(&events, None).await?;
+            return self.get_order(order_id).await;
+        }
 
-        self.lock_balance(user_id, required_currency, required_amount).await?;
+        self.lock_balance(user_id, &preview.lock_currency, preview.lock_amount).await?;
 
-        let order_id = Uuid::new_v4();
         let now = Utc::now();
 
         let order = sqlx::query_as::<_, Order>(
@@ -77,12 +130,404 @@ impl OrderService {
         .bind(now)
         .fetch_one(&self.db)
         .await?;
+        record_order_event(&self.db, order.id, OrderEventType::Created, Some(user_id), None, None).await?;
+
+        self.submit_to_matching_engine(&order, preview).await?;
+
+        self.get_order(order.id).await
+    }
+
+    /// Records an order to be placed at `activate_at`. Only the request
+    /// itself is validated now; the pre-trade checks run when the order
+    /// activates. With `lock_funds` it holds what it would lock if placed
+    /// now until then.
+    async fn schedule_order(
+        &self,
+        user_id: Uuid,
+        trading_pair: &TradingPair,
+        request: &CreateOrderRequest,
+        quantity: Decimal,
+        activate_at: DateTime<Utc>,
+    ) -> Result<Order> {
+        let now = Utc::now();
+        if activate_at <= now {
+            return Err(CryptoTradeError::Validation {
+                message: "activate_at must be in the future".to_string(),
+            });
+        }
+        if activate_at > now + Duration::days(self.max_schedule_days.into()) {
+            return Err(CryptoTradeError::Validation {
+                message: format!("Orders can be scheduled at most {} days ahead", self.max_schedule_days),
+            });
+        }
 
-        self.submit_to_matching_engine(&order).await?;
+        let scheduled_lock = if request.lock_funds {
+            let preview = self.estimate_order(user_id, trading_pair, request, quantity).await?;
+            self.lock_balance(user_id, &preview.lock_currency, preview.lock_amount).await?;
+            Some(preview.lock_amount)
+        } else {
+            None
+        };
+
+        let order = sqlx::query_as::<_, Order>(
+            "INSERT INTO orders (id, user_id, trading_pair_id, order_type, side, quantity, price, filled_quantity, remaining_quantity, status, time_in_force, stop_price, activate_at, scheduled_lock, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, 0, $6, 'scheduled', $8, $9, $10, $11, $12, $12) RETURNING *"
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(request.trading_pair_id)
+        .bind(request.order_type)
+        .bind(request.side)
+        .bind(quantity)
+        .bind(request.price)
+        .bind(request.time_in_force.unwrap_or(TimeInForce::GTC))
+        .bind(request.stop_price)
+        .bind(activate_at)
+        .bind(scheduled_lock)
+        .bind(now)
+        .fetch_one(&self.db)
+        .await?;
+        record_order_event(&self.db, order.id, OrderEventType::Created, Some(user_id), None, None).await?;
 
         Ok(order)
     }
 
+    /// Places the scheduled orders that have come due, earliest first, and
+    /// returns how many were placed. An order failing its pre-trade checks
+    /// is rejected and releases what it held.
+    pub async fn activate_due_orders(&self) -> Result<u32> {
+        let due = sqlx::query_as::<_, Order>(
+            "SELECT * FROM orders WHERE status = 'scheduled' AND activate_at <= NOW() ORDER BY activate_at LIMIT 100",
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut placed = 0;
+        for order in due {
+            // Claiming the order keeps a concurrent run or a cancel from
+            // acting on it too
+            let claimed = sqlx::query("UPDATE orders SET status = 'pending', version = version + 1, updated_at = NOW() WHERE id = $1 AND status = 'scheduled'")
+                .bind(order.id)
+                .execute(&self.db)
+                .await?;
+            if claimed.rows_affected() == 0 {
+                continue;
+            }
+
+            let preview = match self.check_activation(&order).await {
+                Ok(preview) => preview,
+                Err(e) => {
+                    tracing::info!("Rejected scheduled order {}: {}", order.id, e);
+                    sqlx::query("UPDATE orders SET status = 'rejected', version = version + 1, updated_at = NOW() WHERE id = $1")
+                        .bind(order.id)
+                        .execute(&self.db)
+                        .await?;
+                    record_order_event(&self.db, order.id, OrderEventType::Rejected, None, None, Some(&e.to_string())).await?;
+                    self.release_scheduled_lock(&order).await?;
+                    continue;
+                }
+            };
+            self.submit_to_matching_engine(&order, &preview).await?;
+            placed += 1;
+        }
+
+        Ok(placed)
+    }
+
+    /// Runs the pre-trade checks on a scheduled order that has come due and
+    /// moves what it held to what it locks now, returning the preview the
+    /// new lock was sized by.
+    async fn check_activation(&self, order: &Order) -> Result<OrderPreview> {
+        account_status(&self.db, order.user_id).await?.ensure_can_trade()?;
+        let quantity = order.quantity.unwrap_or(Decimal::ZERO);
+        let request = CreateOrderRequest {
+            trading_pair_id: order.trading_pair_id,
+            order_type: order.order_type.ok_or(CryptoTradeError::InvalidOrderType)?,
+            side: order.side.ok_or(CryptoTradeError::InvalidOrderType)?,
+            quantity: quantity.to_f64().ok_or(CryptoTradeError::InvalidQuantity)?,
+            price: order.price,
+            time_in_force: order.time_in_force,
+            stop_price: order.stop_price,
+            allow_duplicate: true,
+            activate_at: None,
+            lock_funds: false,
+        };
+        let trading_pair = self.trading_pair_for(order.trading_pair_id, order.user_id).await?;
+
+        // What the order already holds counts towards its balance check
+        let held = order.scheduled_lock.unwrap_or(Decimal::ZERO);
+        let mut preview = self.estimate_order(order.user_id, &trading_pair, &request, quantity).await?;
+        preview.available_balance += held;
+        preview.sufficient_balance = preview.available_balance >= preview.lock_amount;
+        self.risk_service
+            .check_order(&PreTradeOrder {
+                user_id: order.user_id,
+                trading_pair: &trading_pair,
+                request: &request,
+                quantity,
+                preview: Some(&preview),
+            })
+            .await?;
+
+        if preview.lock_amount > held {
+            self.lock_balance(order.user_id, &preview.lock_currency, preview.lock_amount - held).await?;
+        } else if held > preview.lock_amount {
+            self.unlock_balance(order.user_id, &preview.lock_currency, held - preview.lock_amount).await?;
+        }
+        Ok(preview)
+    }
+
+    /// Gives back what a scheduled order held once it will not be placed.
+    async fn release_scheduled_lock(&self, order: &Order) -> Result<()> {
+        let Some(held) = order.scheduled_lock else {
+            return Ok(());
+        };
+        let trading_pair = self.get_trading_pair(order.trading_pair_id).await?;
+        let currency = match order.side {
+            Some(OrderSide::Buy) => &trading_pair.quote_currency,
+            Some(OrderSide::Sell) => &trading_pair.base_currency,
+            None => return Err(CryptoTradeError::InvalidOrderType),
+        };
+        self.unlock_balance(order.user_id, currency, held).await
+    }
+
+    /// Records that `user_id` is placing this order, failing with
+    /// `DuplicateOrder` if they placed an identical one within the window.
+    /// Expired fingerprints of the user are cleared on the way.
+    async fn claim_fingerprint(
+        &self,
+        user_id: Uuid,
+        request: &CreateOrderRequest,
+        quantity: Decimal,
+        window_seconds: u64,
+    ) -> Result<String> {
+        let fingerprint = format!(
+            "{}:{:?}:{}:{}",
+            request.trading_pair_id,
+            request.side,
+            request.price.map(|price| price.normalize().to_string()).unwrap_or_default(),
+            quantity.normalize()
+        );
+
+        sqlx::query("DELETE FROM order_fingerprints WHERE user_id = $1 AND placed_at < NOW() - make_interval(secs => $2)")
+            .bind(user_id)
+            .bind(window_seconds as f64)
+            .execute(&self.db)
+            .await?;
+
+        let claimed = sqlx::query(
+            r#"
+            INSERT INTO order_fingerprints (user_id, fingerprint, placed_at)
+            VALUES ($1, $2, NOW())
+            ON CONFLICT (user_id, fingerprint) DO UPDATE SET placed_at = EXCLUDED.placed_at
+            WHERE order_fingerprints.placed_at < NOW() - make_interval(secs => $3)
+            "#,
+        )
+        .bind(user_id)
+        .bind(&fingerprint)
+        .bind(window_seconds as f64)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+
+        if claimed == 0 {
+            return Err(CryptoTradeError::DuplicateOrder { window_seconds });
+        }
+        Ok(fingerprint)
+    }
+
+    async fn release_fingerprint(&self, user_id: Uuid, fingerprint: &str) -> Result<()> {
+        sqlx::query("DELETE FROM order_fingerprints WHERE user_id = $1 AND fingerprint = $2")
+            .bind(user_id)
+            .bind(fingerprint)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Estimates what placing `request` would lock and cost in fees, without
+    /// placing it.
+    pub async fn preview_order(&self, user_id: Uuid, request: CreateOrderRequest) -> Result<OrderPreview> {
+        let (trading_pair, quantity) = self.validate_order_request(user_id, &request).await?;
+        self.check_terms(user_id, &trading_pair, &request, quantity).await?;
+        self.estimate_order(user_id, &trading_pair, &request, quantity).await
+    }
+
+    /// Runs `request` through the matching engine against a copy of the live
+    /// book: the fills it would get now, their average price and slippage
+    /// from the best price, and the taker fee on them. Nothing is placed,
+    /// locked or journaled.
+    pub async fn simulate_order(&self, user_id: Uuid, request: CreateOrderRequest) -> Result<OrderSimulation> {
+        let (trading_pair, quantity) = self.validate_order_request(user_id, &request).await?;
+        if !matches!(request.order_type, OrderType::Market | OrderType::Limit) {
+            return Err(CryptoTradeError::Validation {
+                message: "Only market and limit orders can be simulated; stop orders rest outside the book until triggered"
+                    .to_string(),
+            });
+        }
+        self.check_terms(user_id, &trading_pair, &request, quantity).await?;
+
+        // The user's own id, so self-trade prevention applies as it would
+        let simulation = self
+            .matching_service
+            .simulate(
+                trading_pair.id,
+                NewOrder {
+                    order_id: Uuid::new_v4(),
+                    user_id,
+                    side: request.side,
+                    order_type: request.order_type,
+                    price: request.price,
+                    quantity,
+                    time_in_force: request.time_in_force.unwrap_or(TimeInForce::GTC),
+                    quote_budget: None,
+                },
+            )
+            .await?;
+
+        let mut fills: Vec<SimulatedFill> = Vec::new();
+        let mut rests = true;
+        let mut rejection_reason = None;
+        for event in &simulation.events {
+            match &event.kind {
+                EngineEventKind::Matched { price, quantity, .. } => match fills.last_mut() {
+                    Some(fill) if fill.price == *price => fill.quantity += *quantity,
+                    _ => fills.push(SimulatedFill {
+                        price: *price,
+                        quantity: *quantity,
+                    }),
+                },
+                EngineEventKind::Cancelled { .. } => rests = false,
+                EngineEventKind::Rejected { reason, .. } => {
+                    rests = false;
+                    rejection_reason = Some(reason.clone());
+                }
+                _ => {}
+            }
+        }
+
+        let filled_quantity: Decimal = fills.iter().map(|fill| fill.quantity).sum();
+        let notional: Decimal = fills.iter().map(|fill| fill.price * fill.quantity).sum();
+        let average_price = (filled_quantity > Decimal::ZERO).then(|| notional / filled_quantity);
+        let slippage_percent = match (average_price, simulation.best_price) {
+            (Some(average), Some(best)) if best > Decimal::ZERO => {
+                let worse_by = match request.side {
+                    OrderSide::Buy => average - best,
+                    OrderSide::Sell => best - average,
+                };
+                Some((worse_by / best * Decimal::ONE_HUNDRED).round_dp(4))
+            }
+            _ => None,
+        };
+        let fee_rate = taker_fee_rate(&trading_pair);
+        let remaining_quantity = quantity - filled_quantity;
+
+        Ok(OrderSimulation {
+            trading_pair_id: trading_pair.id,
+            side: request.side,
+            order_type: request.order_type,
+            quantity,
+            fills,
+            filled_quantity,
+            remaining_quantity,
+            average_price,
+            best_price: simulation.best_price,
+            slippage_percent,
+            notional,
+            fee_rate,
+            fee: notional * fee_rate,
+            fee_currency: trading_pair.quote_currency.clone(),
+            rests: rests && remaining_quantity > Decimal::ZERO,
+            rejection_reason,
+        })
+    }
+
+    /// Cancels an order and places its replacement. The replacement is
+    /// validated up front so an invalid request never cancels anything; with
+    /// `StopOnFailure` the replacement is only placed once the cancel succeeds.
+    pub async fn cancel_replace(&self, user_id: Uuid, request: CancelReplaceRequest) -> Result<CancelReplaceResult> {
+        // A restricted account could cancel but never place the replacement
+        account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        let existing = sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE id = $1 AND user_id = $2")
+            .bind(request.cancel_order_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::OrderNotFound)?;
+
+        if existing.trading_pair_id != request.new_order.trading_pair_id {
+            return Err(CryptoTradeError::Validation {
+                message: "Replacement order must be on the same trading pair".to_string(),
+            });
+        }
+        let (trading_pair, quantity) = self.validate_order_request(user_id, &request.new_order).await?;
+        self.check_terms(user_id, &trading_pair, &request.new_order, quantity).await?;
+
+        let mut result = CancelReplaceResult {
+            cancel_order_id: request.cancel_order_id,
+            cancel_result: CancelReplaceStatus::Success,
+            new_order_result: CancelReplaceStatus::NotAttempted,
+            cancelled_order: None,
+            new_order: None,
+            error_code: None,
+            error: None,
+        };
+
+        match self.cancel_order(user_id, request.cancel_order_id).await {
+            Ok(order) => result.cancelled_order = Some(order),
+            Err(e) => {
+                result.cancel_result = CancelReplaceStatus::Failure;
+                result.error_code = Some(e.error_code().to_string());
+                result.error = Some(e.to_string());
+                if request.mode.unwrap_or_default() == CancelReplaceMode::StopOnFailure {
+                    return Ok(result);
+                }
+            }
+        }
+
+        match self.create_order(user_id, request.new_order).await {
+            Ok(order) => {
+                result.new_order_result = CancelReplaceStatus::Success;
+                result.new_order = Some(order);
+            }
+            Err(e) => {
+                tracing::warn!("Replacement for order {} failed after cancel: {}", request.cancel_order_id, e);
+                result.new_order_result = CancelReplaceStatus::Failure;
+                result.error_code = Some(e.error_code().to_string());
+                result.error = Some(e.to_string());
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Runs each cancel-replace independently; a failure in one entry does
+    /// not affect the others.
+    pub async fn cancel_replace_batch(&self, user_id: Uuid, request: CancelReplaceBatchRequest) -> Result<Vec<CancelReplaceResult>> {
+        request.validate().map_err(|e| CryptoTradeError::Validation {
+            message: e.to_string(),
+        })?;
+
+        let mut results = Vec::with_capacity(request.orders.len());
+        for entry in request.orders {
+            let cancel_order_id = entry.cancel_order_id;
+            let result = match self.cancel_replace(user_id, entry).await {
+                Ok(result) => result,
+                Err(e) => CancelReplaceResult {
+                    cancel_order_id,
+                    cancel_result: CancelReplaceStatus::NotAttempted,
+                    new_order_result: CancelReplaceStatus::NotAttempted,
+                    cancelled_order: None,
+                    new_order: None,
+                    error_code: Some(e.error_code().to_string()),
+                    error: Some(e.to_string()),
+                },
+            };
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
     pub async fn cancel_order(&self, user_id: Uuid, order_id: Uuid) -> Result<Order> {
         let order = sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE id = $1 AND user_id = $2")
             .bind(order_id)
@@ -93,38 +538,98 @@ impl OrderService {
                 message: "Order not found".to_string(),
             })?;
 
-        if !matches!(order.status, Some(OrderStatus::Open) | Some(OrderStatus::PartiallyFilled)) {
-            return Err(CryptoTradeError::OrderNotCancellable);
-        }
+        self.cancel(&order, Some(user_id)).await
+    }
 
-        let updated_order = sqlx::query_as::<_, Order>(
-            "UPDATE orders SET status = 'cancelled', updated_at = $1 WHERE id = $2 RETURNING *"
+    /// Cancels every open or scheduled order on the pair on behalf of
+    /// `actor_id`, releasing what they locked, and returns how many were
+    /// cancelled. Orders that fill or get cancelled concurrently are skipped.
+    pub async fn cancel_pair_orders(&self, trading_pair_id: Uuid, actor_id: Option<Uuid>) -> Result<u64> {
+        let orders = sqlx::query_as::<_, Order>(
+            "SELECT * FROM orders WHERE trading_pair_id = $1 AND status IN ('scheduled', 'open', 'partially_filled') ORDER BY created_at",
         )
-        .bind(Utc::now())
-        .bind(order_id)
-        .fetch_one(&self.db)
+        .bind(trading_pair_id)
+        .fetch_all(&self.db)
         .await?;
 
-        // Release locked balance
-        let trading_pair = self.get_trading_pair(order.trading_pair_id).await?;
-        let currency = match order.side {
-            Some(OrderSide::Buy) => &trading_pair.quote_currency,
-            Some(OrderSide::Sell) => &trading_pair.base_currency,
-            None => return Err(CryptoTradeError::InvalidOrderType),
-        };
+        let mut cancelled = 0;
+        for order in &orders {
+            match self.cancel(order, actor_id).await {
+                Ok(_) => cancelled += 1,
+                Err(CryptoTradeError::OrderNotCancellable) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(cancelled)
+    }
 
-        let remaining_quantity = order.remaining_quantity.unwrap_or(Decimal::ZERO);
-        let order_price = order.price.unwrap_or(Decimal::ZERO);
+    /// Stops continuous matching on the pair so orders accumulate for a call
+    /// auction.
+    pub async fn start_auction(&self, trading_pair_id: Uuid) -> Result<()> {
+        self.matching_service.submit(trading_pair_id, EngineCommand::StartAuction).await?;
+        Ok(())
+    }
 
-        let amount_to_release = match order.side {
-            Some(OrderSide::Buy) => remaining_quantity * order_price,
-            Some(OrderSide::Sell) => remaining_quantity,
-            None => return Err(CryptoTradeError::InvalidOrderType),
+    /// Ends the pair's call auction, settling the crossed orders at the
+    /// opening price, and returns that price with the quantity it opened
+    /// with.
+    pub async fn uncross_auction(&self, trading_pair_id: Uuid, actor_id: Option<Uuid>) -> Result<(Option<Decimal>, Decimal)> {
+        let events = self.matching_service.submit(trading_pair_id, EngineCommand::Uncross).await?;
+        self.apply_engine_events(&events, actor_id).await?;
+
+        Ok(events
+            .iter()
+            .find_map(|event| match event.kind {
+                EngineEventKind::Uncrossed { price, quantity } => Some((price, quantity)),
+                _ => None,
+            })
+            .unwrap_or((None, Decimal::ZERO)))
+    }
+
+    /// Cancels `order` for `actor_id`: its owner, an admin, or `None` for the
+    /// exchange itself.
+    async fn cancel(&self, order: &Order, actor_id: Option<Uuid>) -> Result<Order> {
+        let order_id = order.id;
+        if order.status == Some(OrderStatus::Scheduled) {
+            let cancelled = sqlx::query("UPDATE orders SET status = 'cancelled', version = version + 1, updated_at = NOW() WHERE id = $1 AND status = 'scheduled'")
+                .bind(order_id)
+                .execute(&self.db)
+                .await?;
+            // Otherwise it activated in the meantime
+            if cancelled.rows_affected() == 1 {
+                record_order_event(&self.db, order_id, OrderEventType::Cancelled, actor_id, None, None).await?;
+                self.release_scheduled_lock(order).await?;
+                return self.get_order(order_id).await;
+            }
+            return Err(CryptoTradeError::OrderNotCancellable);
+        }
+        if !matches!(order.status, Some(OrderStatus::Open) | Some(OrderStatus::PartiallyFilled)) {
+            return Err(CryptoTradeError::OrderNotCancellable);
+        }
+
+        let events = if is_matchable(order) {
+            self.matching_service
+                .submit(order.trading_pair_id, EngineCommand::CancelOrder { order_id })
+                .await?
+        } else {
+            Vec::new()
         };
 
-        self.unlock_balance(user_id, currency, amount_to_release).await?;
+        if events.iter().any(|event| matches!(event.kind, EngineEventKind::Cancelled { .. })) {
+            self.apply_engine_events(&events, actor_id).await?;
+        } else {
+            // Order is not resting in the book (e.g. an untriggered stop
+            // order), or a concurrent cancel took it off first
+            let released = self
+                .release_order_retrying(order_id, None, OrderStatus::Cancelled, actor_id, None)
+                .await?;
+            if !released {
+                return Err(CryptoTradeError::OrderNotCancellable);
+            }
+        }
 
-        Ok(updated_order)
+        self.get_order(order_id).await
     }
 
     pub async fn get_user_orders(&self, user_id: Uuid, status: Option<OrderStatus>, limit: Option<i64>) -> Result<Vec<Order>> {
@@ -152,6 +657,61 @@ impl OrderService {
         Ok(orders)
     }
 
+    pub async fn get_user_order(&self, user_id: Uuid, order_id: Uuid) -> Result<Order> {
+        sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE id = $1 AND user_id = $2")
+            .bind(order_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::OrderNotFound)
+    }
+
+    /// The trades that filled an order, oldest first, from the order owner's
+    /// point of view.
+    pub async fn get_order_fills(&self, user_id: Uuid, order_id: Uuid) -> Result<Vec<Fill>> {
+        let order = self.get_user_order(user_id, order_id).await?;
+        let trading_pair = self.get_trading_pair(order.trading_pair_id).await?;
+
+        let trades = sqlx::query_as::<_, Trade>(
+            "SELECT * FROM trades WHERE (buyer_order_id = $1 OR seller_order_id = $1) AND busted_at IS NULL ORDER BY created_at ASC"
+        )
+        .bind(order_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(trades
+            .into_iter()
+            .map(|trade| {
+                let price = trade.price.unwrap_or(Decimal::ZERO);
+                let quantity = trade.quantity.unwrap_or(Decimal::ZERO);
+
+                Fill {
+                    trade_id: trade.id,
+                    order_id,
+                    price,
+                    quantity,
+                    quote_quantity: price * quantity,
+                    fee: trade.fee(order_id),
+                    fee_currency: trading_pair.quote_currency.clone(),
+                    liquidity: trade.liquidity(order_id),
+                    created_at: trade.created_at,
+                }
+            })
+            .collect())
+    }
+
+    /// Every recorded transition of the user's order, oldest first.
+    pub async fn get_order_history(&self, user_id: Uuid, order_id: Uuid) -> Result<Vec<OrderEvent>> {
+        self.get_user_order(user_id, order_id).await?;
+
+        let events = sqlx::query_as::<_, OrderEvent>("SELECT * FROM order_events WHERE order_id = $1 ORDER BY sequence")
+            .bind(order_id)
+            .fetch_all(&self.db)
+            .await?;
+
+        Ok(events)
+    }
+
     pub async fn get_order_book(&self, trading_pair_id: Uuid, depth: Option<usize>) -> Result<OrderBook> {
         let depth = depth.unwrap_or(20).min(100);
 
@@ -199,7 +759,130 @@ impl OrderService {
         })
     }
 
-    async fn get_trading_pair(&self, trading_pair_id: Uuid) -> Result<TradingPair> {
+    /// Checks a request against its trading pair without side effects and
+    /// returns the pair, at the user's fees, with the parsed quantity.
+    async fn validate_order_request(&self, user_id: Uuid, request: &CreateOrderRequest) -> Result<(TradingPair, Decimal)> {
+        request.validate().map_err(|e| CryptoTradeError::Validation {
+            message: e.to_string(),
+        })?;
+
+        let trading_pair = self.trading_pair_for(request.trading_pair_id, user_id).await?;
+        ensure_pair_visible(&self.db, &trading_pair, user_id).await?;
+
+        let quantity = Decimal::from_f64_retain(request.quantity)
+            .ok_or(CryptoTradeError::InvalidQuantity)?;
+
+        let min_size = trading_pair.min_order_size.unwrap_or(Decimal::ZERO);
+        let max_size = trading_pair.max_order_size.unwrap_or(Decimal::from(1000000));
+
+        if quantity < min_size || quantity > max_size {
+            return Err(CryptoTradeError::InvalidQuantity);
+        }
+
+        if matches!(request.order_type, OrderType::Limit | OrderType::StopLossLimit | OrderType::TakeProfitLimit)
+            && request.price.is_none()
+        {
+            return Err(CryptoTradeError::InvalidPrice);
+        }
+
+        Ok((trading_pair, quantity))
+    }
+
+    /// The pre-trade checks that hold regardless of the user's balances and
+    /// open orders.
+    async fn check_terms(&self, user_id: Uuid, trading_pair: &TradingPair, request: &CreateOrderRequest, quantity: Decimal) -> Result<()> {
+        self.risk_service
+            .check_terms(&PreTradeOrder {
+                user_id,
+                trading_pair,
+                request,
+                quantity,
+                preview: None,
+            })
+            .await
+    }
+
+    async fn get_order(&self, order_id: Uuid) -> Result<Order> {
+        sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE id = $1")
+            .bind(order_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::OrderNotFound)
+    }
+
+    /// Releases an order, reading it afresh and trying again whenever a
+    /// concurrent update gets in first. `remaining_quantity` is what the
+    /// engine reported, if it did; otherwise whatever the order has left.
+    /// Returns false if the order had already finished, so there was
+    /// nothing left to release.
+    async fn release_order_retrying(
+        &self,
+        order_id: Uuid,
+        remaining_quantity: Option<Decimal>,
+        status: OrderStatus,
+        actor_id: Option<Uuid>,
+        reason: Option<&str>,
+    ) -> Result<bool> {
+        for _ in 0..MAX_CONFLICT_RETRIES {
+            let order = self.get_order(order_id).await?;
+            if matches!(
+                order.status,
+                Some(OrderStatus::Filled) | Some(OrderStatus::Cancelled) | Some(OrderStatus::Rejected)
+            ) {
+                return Ok(false);
+            }
+            let remaining_quantity = remaining_quantity.or(order.remaining_quantity).unwrap_or(Decimal::ZERO);
+            match self.release_order(&order, remaining_quantity, status, actor_id, reason).await {
+                Err(CryptoTradeError::Conflict { .. }) => continue,
+                result => return result.map(|_| true),
+            }
+        }
+
+        Err(order_conflict(order_id))
+    }
+
+    /// Moves an order to a terminal status, cancelled or rejected, and
+    /// unlocks the funds still held for its unfilled quantity. Fails with
+    /// `Conflict` if the order was updated since `order` was read.
+    async fn release_order(
+        &self,
+        order: &Order,
+        remaining_quantity: Decimal,
+        status: OrderStatus,
+        actor_id: Option<Uuid>,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        let released = sqlx::query("UPDATE orders SET status = $1, version = version + 1, updated_at = $2 WHERE id = $3 AND version = $4")
+            .bind(status)
+            .bind(Utc::now())
+            .bind(order.id)
+            .bind(order.version)
+            .execute(&self.db)
+            .await?;
+        if released.rows_affected() == 0 {
+            return Err(order_conflict(order.id));
+        }
+        let event_type = if status == OrderStatus::Rejected {
+            OrderEventType::Rejected
+        } else {
+            OrderEventType::Cancelled
+        };
+        record_order_event(&self.db, order.id, event_type, actor_id, None, reason).await?;
+
+        let trading_pair = self.trading_pair_for(order.trading_pair_id, order.user_id).await?;
+        let (currency, amount_to_release) = match order.side {
+            Some(OrderSide::Buy) if order.order_type == Some(OrderType::Market) => {
+                (&trading_pair.quote_currency, unspent_lock(&self.db, order.id).await?)
+            }
+            Some(OrderSide::Buy) => (&trading_pair.quote_currency, remaining_quantity * buy_lock_per_unit(order)),
+            Some(OrderSide::Sell) => (&trading_pair.base_currency, remaining_quantity),
+            None => return Err(CryptoTradeError::InvalidOrderType),
+        };
+
+        self.unlock_balance(order.user_id, currency, amount_to_release).await
+    }
+
+    pub async fn get_trading_pair(&self, trading_pair_id: Uuid) -> Result<TradingPair> {
         sqlx::query_as::<_, TradingPair>("SELECT * FROM trading_pairs WHERE id = $1")
             .bind(trading_pair_id)
             .fetch_optional(&self.db)
@@ -209,30 +892,158 @@ impl OrderService {
             })
     }
 
-    async fn get_account(&self, user_id: Uuid, currency: &str) -> Result<Account> {
-        sqlx::query_as::<_, Account>(
-            "SELECT * FROM accounts WHERE user_id = $1 AND currency = $2"
+    /// The pair at `user_id`'s fees, which their tenant may set.
+    async fn trading_pair_for(&self, trading_pair_id: Uuid, user_id: Uuid) -> Result<TradingPair> {
+        let trading_pair = self.get_trading_pair(trading_pair_id).await?;
+        with_fee_schedule(&self.db, trading_pair, user_id).await
+    }
+
+    /// Locks `amount` only if it is available, so concurrent orders can never
+    /// drive `available_balance` below zero.
+    async fn lock_balance(&self, user_id: Uuid, currency: &str, amount: Decimal) -> Result<()> {
+        let result = sqlx::query(
+            "UPDATE accounts SET available_balance = available_balance - $1, locked_balance = locked_balance + $1 WHERE user_id = $2 AND currency = $3 AND available_balance >= $1"
         )
+        .bind(amount)
         .bind(user_id)
         .bind(currency)
-        .fetch_optional(&self.db)
-        .await?
-        .ok_or(CryptoTradeError::InsufficientBalance)
+        .execute(&self.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(CryptoTradeError::InsufficientBalance {
+                currency: currency.to_string(),
+                required: amount,
+                available: self.available_balance(user_id, currency).await?,
+            });
+        }
+
+        Ok(())
     }
 
-    async fn lock_balance(&self, user_id: Uuid, currency: &str, amount: Decimal) -> Result<()> {
-        sqlx::query(
-            "UPDATE accounts SET available_balance = available_balance - $1, locked_balance = locked_balance + $1 WHERE user_id = $2 AND currency = $3"
+    /// Locks `amount` as `lock_balance` does for an order that has no row
+    /// yet, recording the lock as pending until the journal batch writes the
+    /// row. Both happen in one statement, so no lock is left unrecorded.
+    async fn lock_pending(&self, order_id: Uuid, user_id: Uuid, currency: &str, amount: Decimal) -> Result<()> {
+        let result = sqlx::query(
+            "WITH locked AS (UPDATE accounts SET available_balance = available_balance - $1, locked_balance = locked_balance + $1 WHERE user_id = $2 AND currency = $3 AND available_balance >= $1 RETURNING user_id) INSERT INTO pending_order_locks (order_id, user_id, currency, amount) SELECT $4, user_id, $3, $1 FROM locked"
         )
         .bind(amount)
         .bind(user_id)
         .bind(currency)
+        .bind(order_id)
         .execute(&self.db)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(CryptoTradeError::InsufficientBalance {
+                currency: currency.to_string(),
+                required: amount,
+                available: self.available_balance(user_id, currency).await?,
+            });
+        }
+
         Ok(())
     }
 
+    async fn available_balance(&self, user_id: Uuid, currency: &str) -> Result<Decimal> {
+        let available = sqlx::query_scalar::<_, Option<Decimal>>(
+            "SELECT available_balance FROM accounts WHERE user_id = $1 AND currency = $2"
+        )
+        .bind(user_id)
+        .bind(currency)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(available.flatten().unwrap_or(Decimal::ZERO))
+    }
+
+    /// Buys lock the quote notional plus the worst-case (taker) fee; sells
+    /// lock the base quantity, since their fee comes out of the proceeds.
+    /// Market orders are priced by walking the resting opposite side; a
+    /// market buy the book cannot fill is refused, since its lock would not
+    /// cover what it goes on to buy.
+    async fn estimate_order(
+        &self,
+        user_id: Uuid,
+        trading_pair: &TradingPair,
+        request: &CreateOrderRequest,
+        quantity: Decimal,
+    ) -> Result<OrderPreview> {
+        let estimated_price = match request.price {
+            Some(price) if request.order_type != OrderType::Market => Some(price),
+            _ => {
+                let (price, covered) = self.estimate_market_price(trading_pair.id, request.side, quantity).await?;
+                if request.order_type == OrderType::Market && request.side == OrderSide::Buy && covered < quantity {
+                    return Err(CryptoTradeError::Validation {
+                        message: format!(
+                            "The book can fill only {} of this market buy of {}; place a limit order instead",
+                            covered, quantity
+                        ),
+                    });
+                }
+                price
+            }
+        };
+
+        let estimated_notional = quantity * estimated_price.unwrap_or(Decimal::ZERO);
+        let fee_rate = taker_fee_rate(trading_pair);
+        let estimated_fee = estimated_notional * fee_rate;
+
+        let (lock_currency, lock_amount) = match request.side {
+            OrderSide::Buy => (trading_pair.quote_currency.clone(), estimated_notional + estimated_fee),
+            OrderSide::Sell => (trading_pair.base_currency.clone(), quantity),
+        };
+        let available_balance = self.available_balance(user_id, &lock_currency).await?;
+
+        Ok(OrderPreview {
+            trading_pair_id: trading_pair.id,
+            side: request.side,
+            order_type: request.order_type,
+            quantity,
+            estimated_price,
+            estimated_notional,
+            fee_rate,
+            estimated_fee,
+            fee_currency: trading_pair.quote_currency.clone(),
+            sufficient_balance: available_balance >= lock_amount,
+            lock_currency,
+            lock_amount,
+            available_balance,
+        })
+    }
+
+    /// Volume-weighted price of the liquidity a market order of `quantity`
+    /// would take, or `None` if the opposite side is empty, with how much of
+    /// `quantity` that liquidity covers.
+    async fn estimate_market_price(
+        &self,
+        trading_pair_id: Uuid,
+        side: OrderSide,
+        quantity: Decimal,
+    ) -> Result<(Option<Decimal>, Decimal)> {
+        let book = self.get_order_book(trading_pair_id, Some(100)).await?;
+        let levels = match side {
+            OrderSide::Buy => book.asks,
+            OrderSide::Sell => book.bids,
+        };
+
+        let mut remaining = quantity;
+        let mut filled = Decimal::ZERO;
+        let mut notional = Decimal::ZERO;
+        for level in levels {
+            if remaining <= Decimal::ZERO {
+                break;
+            }
+            let take = remaining.min(level.quantity);
+            filled += take;
+            notional += take * level.price;
+            remaining -= take;
+        }
+
+        Ok(((filled > Decimal::ZERO).then(|| notional / filled), filled))
+    }
+
     async fn unlock_balance(&self, user_id: Uuid, currency: &str, amount: Decimal) -> Result<()> {
         sqlx::query(
             "UPDATE accounts SET available_balance = available_balance + $1, locked_balance = locked_balance - $1 WHERE user_id = $2 AND currency = $3"
@@ -246,14 +1057,212 @@ impl OrderService {
         Ok(())
     }
 
-    async fn submit_to_matching_engine(&self, _order: &Order) -> Result<()> {
-        // In a real implementation, this would send the order to a message queue
-        // or matching engine service
-        sqlx::query("UPDATE orders SET status = 'open' WHERE id = $1")
-            .bind(_order.id)
-            .execute(&self.db)
+    /// `preview` is what the order's lock was sized by; the lock is recorded
+    /// on the order so that releasing it gives back what it locked.
+    async fn submit_to_matching_engine(&self, order: &Order, preview: &OrderPreview) -> Result<()> {
+        let accepted = sqlx::query(
+            "UPDATE orders SET status = 'open', locked_amount = $2, version = version + 1 WHERE id = $1 AND status = 'pending'",
+        )
+        .bind(order.id)
+        .bind(preview.lock_amount)
+        .execute(&self.db)
+        .await?;
+        if accepted.rows_affected() == 1 {
+            record_order_event(&self.db, order.id, OrderEventType::Accepted, None, None, None).await?;
+        }
+
+        // Stop orders rest outside the book until their trigger price is reached
+        if !is_matchable(order) {
+            return Ok(());
+        }
+
+        let new_order = NewOrder {
+            order_id: order.id,
+            user_id: order.user_id,
+            side: order.side.ok_or(CryptoTradeError::InvalidOrderType)?,
+            order_type: order.order_type.ok_or(CryptoTradeError::InvalidOrderType)?,
+            price: order.price,
+            quantity: order.quantity.unwrap_or(Decimal::ZERO),
+            time_in_force: order.time_in_force.unwrap_or(TimeInForce::GTC),
+            quote_budget: quote_budget(preview),
+        };
+
+        let events = self
+            .matching_service
+            .submit(order.trading_pair_id, EngineCommand::AddOrder(new_order))
             .await?;
+        self.apply_engine_events(&events, None).await
+    }
+
+    /// Settles engine outputs: matches become trades, cancellations and
+    /// rejections release whatever the order still had locked. Consecutive
+    /// matches, as from one taker sweeping the book, are settled together.
+    /// `actor_id` is whoever sent the command, if not the engine itself.
+    async fn apply_engine_events(&self, events: &[EngineEvent], actor_id: Option<Uuid>) -> Result<()> {
+        let mut matches = Vec::new();
+        for event in events {
+            if let EngineEventKind::Matched {
+                maker_order_id,
+                taker_order_id,
+                taker_side,
+                price,
+                quantity,
+                ..
+            } = &event.kind
+            {
+                let maker = self.get_order(*maker_order_id).await?;
+                let taker = self.get_order(*taker_order_id).await?;
+                let (buyer_order, seller_order) = match taker_side {
+                    OrderSide::Buy => (taker, maker),
+                    OrderSide::Sell => (maker, taker),
+                };
+                matches.push(MatchSettlement {
+                    settlement_key: format!("{}:{}", event.trading_pair_id, event.sequence),
+                    buyer_order,
+                    seller_order,
+                    taker_side: *taker_side,
+                    price: *price,
+                    quantity: *quantity,
+                });
+                continue;
+            }
+
+            // What follows the matches, like an IOC remainder's cancel,
+            // must see them settled
+            self.trading_service.execute_trades(&std::mem::take(&mut matches)).await?;
+            match &event.kind {
+                EngineEventKind::Cancelled { order_id, remaining_quantity } => {
+                    self.release_order_retrying(*order_id, Some(*remaining_quantity), OrderStatus::Cancelled, actor_id, None)
+                        .await?;
+                }
+                EngineEventKind::Rejected { order_id, reason } => {
+                    // Rejected cancels/amends leave the order as it was
+                    if matches!(events.first().map(|event| &event.kind), Some(EngineEventKind::OrderAdded(_))) {
+                        tracing::warn!("Matching engine rejected order {}: {}", order_id, reason);
+                        self.release_order_retrying(*order_id, None, OrderStatus::Rejected, None, Some(reason)).await?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.trading_service.execute_trades(&matches).await?;
 
         Ok(())
     }
 }
+
+/// How many times a compare-and-swap update of an order is tried against a
+/// fresh read before giving up with `Conflict`.
+pub(crate) const MAX_CONFLICT_RETRIES: usize = 3;
+
+pub(crate) fn order_conflict(order_id: Uuid) -> CryptoTradeError {
+    CryptoTradeError::Conflict {
+        message: format!("Order {} kept changing under concurrent updates", order_id),
+    }
+}
+
+/// Records a transition of an order, with the status and quantities the
+/// order was left with.
+pub(crate) async fn record_order_event<'e>(
+    executor: impl PgExecutor<'e>,
+    order_id: Uuid,
+    event_type: OrderEventType,
+    actor_id: Option<Uuid>,
+    trade_id: Option<Uuid>,
+    reason: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO order_events (order_id, event_type, status, filled_quantity, remaining_quantity, trade_id, actor_id, reason) SELECT id, $2, status, filled_quantity, remaining_quantity, $3, $4, $5 FROM orders WHERE id = $1",
+    )
+    .bind(order_id)
+    .bind(event_type)
+    .bind(trade_id)
+    .bind(actor_id)
+    .bind(reason)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// What a buy holds for each unit it has left: what it locked when placed,
+/// spread over its quantity. A market buy's lock is an estimate that is
+/// settled as a whole once the engine is done with it, so it holds none
+/// per unit.
+pub(crate) fn buy_lock_per_unit(order: &Order) -> Decimal {
+    match (order.order_type, order.locked_amount, order.quantity) {
+        (Some(OrderType::Market), _, _) => Decimal::ZERO,
+        (_, Some(locked), Some(quantity)) if quantity > Decimal::ZERO => locked / quantity,
+        _ => Decimal::ZERO,
+    }
+}
+
+/// Releases the pending locks of `order_ids` whose orders have no row, and
+/// returns how many there were. Locks the journal has already accounted
+/// for are gone, so releasing can never give back a lock twice.
+pub(crate) async fn release_pending_locks<'e>(executor: impl PgExecutor<'e>, order_ids: &[Uuid]) -> Result<u64> {
+    let released = sqlx::query(
+        r#"
+        WITH released AS (
+            DELETE FROM pending_order_locks
+            WHERE order_id = ANY($1) AND NOT EXISTS (SELECT 1 FROM orders WHERE orders.id = pending_order_locks.order_id)
+            RETURNING user_id, currency, amount
+        ),
+        totals AS (
+            SELECT user_id, currency, SUM(amount) AS amount, COUNT(*) AS locks FROM released GROUP BY user_id, currency
+        )
+        UPDATE accounts SET available_balance = available_balance + totals.amount, locked_balance = locked_balance - totals.amount
+        FROM totals
+        WHERE accounts.user_id = totals.user_id AND accounts.currency = totals.currency
+        RETURNING totals.locks
+        "#,
+    )
+    .bind(order_ids)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(released.iter().map(|row| row.get::<i64, _>("locks") as u64).sum())
+}
+
+/// What a market buy locked that its fills have not spent. Its lock is an
+/// estimate, given back as a whole once the engine is done with the order:
+/// when its last fill settles, or when it is cancelled or rejected. Busted
+/// fills count neither way; the bust took their spend off the lock.
+pub(crate) async fn unspent_lock<'e>(executor: impl PgExecutor<'e>, order_id: Uuid) -> Result<Decimal> {
+    let unspent = sqlx::query_scalar::<_, Option<Decimal>>(
+        "SELECT locked_amount - COALESCE(cumulative_quote_quantity, 0) - (SELECT COALESCE(SUM(buyer_fee), 0) FROM trades WHERE buyer_order_id = orders.id AND busted_at IS NULL) FROM orders WHERE id = $1",
+    )
+    .bind(order_id)
+    .fetch_optional(executor)
+    .await?
+    .flatten()
+    .unwrap_or(Decimal::ZERO);
+
+    if unspent < Decimal::ZERO {
+        // The engine caps a market buy at its lock, so this is a bug; there
+        // is nothing left to give back
+        tracing::error!("Market buy {} spent {} more than it locked", order_id, -unspent);
+        return Ok(Decimal::ZERO);
+    }
+    Ok(unspent)
+}
+
+/// Whether an order is handled by the matching engine rather than resting
+/// outside the book.
+fn is_matchable(order: &Order) -> bool {
+    matches!(order.order_type, Some(OrderType::Market) | Some(OrderType::Limit))
+}
+
+/// The most a market buy may spend on notional so that, with the taker fee
+/// on top, its fills stay within what it locked.
+fn quote_budget(preview: &OrderPreview) -> Option<Decimal> {
+    (preview.order_type == OrderType::Market && preview.side == OrderSide::Buy).then(|| {
+        (preview.lock_amount / (Decimal::ONE + preview.fee_rate))
+            .round_dp_with_strategy(AMOUNT_SCALE, RoundingStrategy::ToZero)
+    })
+}
+
+/// The fee rate locks are sized with; matches the fallback used at settlement.
+fn taker_fee_rate(trading_pair: &TradingPair) -> Decimal {
+    trading_pair.taker_fee.unwrap_or(Decimal::new(1, 3))
+}
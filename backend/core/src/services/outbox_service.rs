@@ -0,0 +1,252 @@
+use crate::{database::Database, error::CryptoTradeError, models::*, resilience::CircuitBreaker, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgExecutor};
+use uuid::Uuid;
+
+/// A state change other systems hear about through NATS. Each is recorded
+/// with [`enqueue_event`] in the transaction making the change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    TradeExecuted {
+        trade: Trade,
+    },
+    DepositConfirmed {
+        transaction_id: Uuid,
+        user_id: Uuid,
+        currency: String,
+        amount: Decimal,
+    },
+    WithdrawalStatusChanged {
+        withdrawal: FiatWithdrawal,
+    },
+    AccountStatusChanged {
+        change: AccountStatusChange,
+    },
+    /// A ticket was opened, answered or had its status changed; the hook
+    /// for notifying the other side
+    SupportTicketUpdated {
+        ticket: SupportTicket,
+        /// Support made the change, so the user is the one to notify
+        from_staff: bool,
+    },
+}
+
+impl DomainEvent {
+    /// The subject it is published on, under the configured prefix.
+    pub fn subject(&self) -> &'static str {
+        match self {
+            DomainEvent::TradeExecuted { .. } => "trade.executed",
+            DomainEvent::DepositConfirmed { .. } => "deposit.confirmed",
+            DomainEvent::WithdrawalStatusChanged { .. } => "withdrawal.status_changed",
+            DomainEvent::AccountStatusChanged { .. } => "account.status_changed",
+            DomainEvent::SupportTicketUpdated { .. } => "support.ticket_updated",
+        }
+    }
+}
+
+/// Records `event` in the outbox as part of the caller's transaction, so it
+/// is published if and only if the change it describes is committed.
+pub async fn enqueue_event<'e>(executor: impl PgExecutor<'e>, event: &DomainEvent) -> Result<()> {
+    sqlx::query("INSERT INTO outbox_events (subject, payload) VALUES ($1, $2)")
+        .bind(event.subject())
+        .bind(serde_json::to_value(event)?)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// What the relay hands events to.
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, subject: String, event_id: Uuid, payload: Vec<u8>) -> Result<()>;
+
+    /// Returns once everything published so far has reached the server.
+    async fn flush(&self) -> Result<()>;
+}
+
+/// Publishes to NATS with the event id as `Nats-Msg-Id`, which JetStream
+/// streams use to drop a redelivered event within their duplicate window.
+/// That makes publishing safe to retry; a batch the breaker cuts short is
+/// picked up again on the relay's next pass.
+pub struct NatsPublisher {
+    client: async_nats::Client,
+    breaker: CircuitBreaker,
+}
+
+impl NatsPublisher {
+    pub fn new(client: async_nats::Client, breaker: CircuitBreaker) -> Self {
+        Self { client, breaker }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for NatsPublisher {
+    async fn publish(&self, subject: String, event_id: Uuid, payload: Vec<u8>) -> Result<()> {
+        let mut headers = async_nats::HeaderMap::new();
+        headers.insert("Nats-Msg-Id", event_id.to_string().as_str());
+        self.breaker
+            .call(|| async {
+                self.client
+                    .publish_with_headers(subject.clone(), headers.clone(), payload.clone().into())
+                    .await
+                    .map_err(|e| nats_error("publish", e))
+            })
+            .await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.breaker
+            .call(|| async { self.client.flush().await.map_err(|e| nats_error("flush", e)) })
+            .await
+    }
+}
+
+fn nats_error(operation: &str, e: impl std::fmt::Display) -> CryptoTradeError {
+    CryptoTradeError::Io(std::io::Error::other(format!("NATS {} failed: {}", operation, e)))
+}
+
+/// The message published for an outbox event.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutboxMessage {
+    /// Stable across redeliveries; consumers dedupe on it
+    pub id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub event: DomainEvent,
+}
+
+/// Something that acts on outbox messages. Its effects are made on the
+/// connection it is handed, inside the transaction that records the message
+/// as processed, so a redelivered message is never applied twice.
+#[async_trait]
+pub trait EventConsumer: Send + Sync {
+    /// Stable name the consumer's processed events are recorded under.
+    fn name(&self) -> &'static str;
+
+    async fn handle(&self, conn: &mut PgConnection, message: &OutboxMessage) -> Result<()>;
+}
+
+/// Records `event_key` as processed by `consumer` in the caller's
+/// transaction. Returns false if it already was, in which case the caller
+/// must not apply the event again. A concurrent claim of the same key waits
+/// for the other transaction and then sees it.
+pub async fn claim_event(conn: &mut PgConnection, consumer: &str, event_key: &str) -> Result<bool> {
+    let result = sqlx::query("INSERT INTO processed_events (consumer, event_key) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(consumer)
+        .bind(event_key)
+        .execute(conn)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[derive(sqlx::FromRow)]
+struct PendingEvent {
+    id: i64,
+    event_id: Uuid,
+    subject: String,
+    payload: serde_json::Value,
+    created_at: DateTime<Utc>,
+}
+
+/// Relays the outbox (see migration 044) to a publisher, oldest first, and
+/// prunes events already published. Delivery is at least once: an event is
+/// only marked published after the publisher has flushed it.
+#[derive(Clone)]
+pub struct OutboxService {
+    db: Database,
+    subject_prefix: String,
+}
+
+impl OutboxService {
+    pub fn new(db: Database, subject_prefix: String) -> Self {
+        Self { db, subject_prefix }
+    }
+
+    /// Publishes up to `limit` pending events; returns how many went out.
+    /// Instances relaying concurrently take different events. A failed
+    /// publish is recorded on its event and stops the batch, so later
+    /// events do not overtake it.
+    pub async fn relay(&self, publisher: &dyn EventPublisher, limit: i64) -> Result<usize> {
+        let mut tx = self.db.begin().await?;
+        let events = sqlx::query_as::<_, PendingEvent>(
+            "SELECT id, event_id, subject, payload, created_at FROM outbox_events WHERE published_at IS NULL ORDER BY id LIMIT $1 FOR UPDATE SKIP LOCKED",
+        )
+        .bind(limit)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut published = Vec::with_capacity(events.len());
+        let mut failure = None;
+        for event in events {
+            let message = serde_json::to_vec(&OutboxMessage {
+                id: event.event_id,
+                occurred_at: event.created_at,
+                event: serde_json::from_value(event.payload)?,
+            })?;
+            let subject = format!("{}.{}", self.subject_prefix, event.subject);
+            match publisher.publish(subject, event.event_id, message).await {
+                Ok(()) => published.push(event.id),
+                Err(e) => {
+                    failure = Some((event.id, e));
+                    break;
+                }
+            }
+        }
+        if !published.is_empty() {
+            if let Err(e) = publisher.flush().await {
+                failure = published.first().map(|id| (*id, e));
+                published.clear();
+            }
+        }
+
+        sqlx::query("UPDATE outbox_events SET published_at = NOW(), attempts = attempts + 1 WHERE id = ANY($1)")
+            .bind(&published)
+            .execute(&mut *tx)
+            .await?;
+        if let Some((id, e)) = &failure {
+            sqlx::query("UPDATE outbox_events SET attempts = attempts + 1, last_error = $2 WHERE id = $1")
+                .bind(id)
+                .bind(e.to_string())
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        match failure {
+            Some((_, e)) => Err(e),
+            None => Ok(published.len()),
+        }
+    }
+
+    /// Applies a delivered message with `consumer` exactly once. Returns
+    /// false for a redelivery of a message it already processed.
+    pub async fn consume(&self, consumer: &dyn EventConsumer, message: &OutboxMessage) -> Result<bool> {
+        let mut tx = self.db.begin().await?;
+        if !claim_event(&mut tx, consumer.name(), &message.id.to_string()).await? {
+            return Ok(false);
+        }
+        consumer.handle(&mut tx, message).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Deletes events published before `before`.
+    pub async fn prune(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM outbox_events WHERE published_at < $1")
+            .bind(before)
+            .execute(&self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Events not yet published.
+    pub async fn backlog(&self) -> Result<i64> {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM outbox_events WHERE published_at IS NULL")
+            .fetch_one(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+}
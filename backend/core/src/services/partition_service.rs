@@ -0,0 +1,122 @@
+use crate::{
+    config::{PartitioningConfig, RetentionAction, RetentionPolicy},
+    database::Database,
+    Result,
+};
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use sqlx::Row;
+
+/// Maintains the monthly partitions of `orders` and `trades` (see migration
+/// 010): creates upcoming months ahead of time and retires months past their
+/// retention by detaching them, then archiving or dropping them.
+#[derive(Clone)]
+pub struct PartitionService {
+    db: Database,
+}
+
+#[derive(Debug, Default)]
+pub struct MaintenanceReport {
+    pub archived: Vec<String>,
+    pub dropped: Vec<String>,
+}
+
+impl PartitionService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub async fn run_maintenance(&self, config: &PartitioningConfig) -> Result<MaintenanceReport> {
+        let mut report = MaintenanceReport::default();
+
+        for (table, policy) in [("orders", &config.orders), ("trades", &config.trades)] {
+            sqlx::query("SELECT create_monthly_partitions($1, NOW(), $2)")
+                .bind(table)
+                .bind(config.months_ahead as i32)
+                .execute(&self.db)
+                .await?;
+
+            self.apply_retention(table, policy, &mut report).await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn apply_retention(&self, table: &str, policy: &RetentionPolicy, report: &mut MaintenanceReport) -> Result<()> {
+        if policy.retention_months == 0 {
+            return Ok(());
+        }
+
+        let today = Utc::now().date_naive();
+        let Some(cutoff) = NaiveDate::from_ymd_opt(today.year(), today.month(), 1)
+            .and_then(|month| month.checked_sub_months(Months::new(policy.retention_months)))
+        else {
+            return Ok(());
+        };
+
+        for partition in self.list_partitions(table).await? {
+            match partition_month(table, &partition) {
+                Some(month) if month < cutoff => {}
+                _ => continue,
+            }
+
+            // Old orders can still be resting on the book
+            if table == "orders" && self.has_open_orders(&partition).await? {
+                tracing::warn!("Keeping partition {} past retention: it still holds open orders", partition);
+                continue;
+            }
+
+            sqlx::query(&format!("ALTER TABLE {} DETACH PARTITION \"{}\"", table, partition))
+                .execute(&self.db)
+                .await?;
+
+            match policy.action {
+                RetentionAction::Archive => {
+                    sqlx::query(&format!("ALTER TABLE \"{}\" SET SCHEMA archive", partition))
+                        .execute(&self.db)
+                        .await?;
+                    report.archived.push(partition);
+                }
+                RetentionAction::Drop => {
+                    sqlx::query(&format!("DROP TABLE \"{}\"", partition))
+                        .execute(&self.db)
+                        .await?;
+                    report.dropped.push(partition);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn list_partitions(&self, table: &str) -> Result<Vec<String>> {
+        let rows = sqlx::query(
+            "SELECT child.relname FROM pg_inherits JOIN pg_class parent ON parent.oid = pg_inherits.inhparent JOIN pg_class child ON child.oid = pg_inherits.inhrelid JOIN pg_namespace ns ON ns.oid = parent.relnamespace WHERE parent.relname = $1 AND ns.nspname = current_schema() ORDER BY child.relname"
+        )
+        .bind(table)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.get("relname")).collect())
+    }
+
+    async fn has_open_orders(&self, partition: &str) -> Result<bool> {
+        sqlx::query_scalar::<_, bool>(&format!(
+            "SELECT EXISTS (SELECT 1 FROM \"{}\" WHERE status IN ('pending', 'open', 'partially_filled'))",
+            partition
+        ))
+        .fetch_one(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+}
+
+/// The month a partition named like `trades_p202406` holds. The default
+/// partition and anything not created by `create_monthly_partition` yield
+/// `None`, which also keeps untrusted names out of the DDL above.
+fn partition_month(table: &str, partition: &str) -> Option<NaiveDate> {
+    let suffix = partition.strip_prefix(table)?.strip_prefix("_p")?;
+    if suffix.len() != 6 || !suffix.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(suffix[..4].parse().ok()?, suffix[4..].parse().ok()?, 1)
+}
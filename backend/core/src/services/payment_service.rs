@@ -0,0 +1,227 @@
+use crate::{
+    config::PaymentsConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    payments::{BankTransfer, CardProcessor, PaymentEvent, PaymentProvider},
+    services::{
+        outbox_service::{enqueue_event, DomainEvent},
+        user_service::{account_status, get_or_create_account},
+        LedgerService, Posting, RiskLimitService,
+    },
+    Result,
+};
+use axum::http::HeaderMap;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Fiat deposits through payment providers. A deposit is a pending
+/// `deposit` transaction until its provider's webhook settles it, which
+/// credits the user's balance once however often the webhook is delivered.
+#[derive(Clone)]
+pub struct PaymentService {
+    db: Database,
+    ledger: LedgerService,
+    risk_limits: RiskLimitService,
+    config: PaymentsConfig,
+    providers: HashMap<PaymentMethod, Arc<dyn PaymentProvider>>,
+}
+
+impl PaymentService {
+    /// Uses the providers enabled in `config`.
+    pub fn new(db: Database, ledger: LedgerService, risk_limits: RiskLimitService, config: PaymentsConfig) -> Self {
+        let mut service = Self {
+            db,
+            ledger,
+            risk_limits,
+            config: config.clone(),
+            providers: HashMap::new(),
+        };
+        if config.card.enabled {
+            service = service.with_provider(Arc::new(CardProcessor::new(config.card)));
+        }
+        if config.bank_transfer.enabled {
+            service = service.with_provider(Arc::new(BankTransfer::new(config.bank_transfer)));
+        }
+        service
+    }
+
+    /// Adds `provider`, replacing any provider for the same method.
+    pub fn with_provider(mut self, provider: Arc<dyn PaymentProvider>) -> Self {
+        self.providers.insert(provider.method(), provider);
+        self
+    }
+
+    pub fn methods(&self) -> Vec<PaymentMethod> {
+        self.providers.keys().copied().collect()
+    }
+
+    pub async fn create_payment(&self, user_id: Uuid, request: CreatePaymentRequest) -> Result<PaymentIntent> {
+        account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        let provider = self.provider(request.method)?;
+        let amount = request.amount;
+        if amount < self.config.min_amount || amount > self.config.max_amount {
+            return Err(CryptoTradeError::Validation {
+                message: format!(
+                    "Amount must be between {} and {} {}",
+                    self.config.min_amount, self.config.max_amount, self.config.currency
+                ),
+            });
+        }
+        if amount.normalize().scale() > 2 {
+            return Err(CryptoTradeError::Validation {
+                message: "Amount must be in whole cents".to_string(),
+            });
+        }
+        // The check holds the user until the payment is recorded, so payments
+        // started together cannot each pass the limit on their own
+        let mut tx = self.db.begin().await?;
+        self.risk_limits
+            .check_daily_deposit(&mut tx, user_id, &self.config.currency, amount)
+            .await?;
+
+        let id = Uuid::new_v4();
+        let payment = provider.create_payment(id, amount, &self.config.currency).await?;
+        sqlx::query(
+            "INSERT INTO transactions (id, user_id, transaction_type, currency, amount, status, provider, external_id) VALUES ($1, $2, 'deposit', $3, $4, 'pending', $5, $6)",
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(&self.config.currency)
+        .bind(amount)
+        .bind(request.method.as_str())
+        .bind(&payment.external_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(PaymentIntent {
+            id,
+            method: request.method,
+            currency: self.config.currency.clone(),
+            amount,
+            status: TransactionStatus::Pending,
+            client_secret: payment.client_secret,
+            bank_instructions: payment.bank_instructions,
+        })
+    }
+
+    pub async fn list_payments(&self, user_id: Uuid) -> Result<Vec<Payment>> {
+        let payments = sqlx::query_as::<_, Payment>(
+            "SELECT id, provider, external_id, currency, amount, status, created_at, updated_at FROM transactions WHERE user_id = $1 AND transaction_type = 'deposit' AND provider IS NOT NULL ORDER BY created_at DESC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(payments)
+    }
+
+    /// Applies a webhook from the provider for `method`.
+    pub async fn handle_webhook(&self, method: PaymentMethod, headers: &HeaderMap, body: &[u8]) -> Result<()> {
+        let provider = self.provider(method)?;
+        match provider.parse_webhook(headers, body)? {
+            Some(PaymentEvent::Settled {
+                external_id,
+                amount,
+                currency,
+            }) => self.settle(method, &external_id, amount, &currency).await,
+            Some(PaymentEvent::Failed { external_id }) => self.fail(method, &external_id).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Confirms the deposit with the amount actually received and credits
+    /// it; a deposit that is no longer pending is left alone, so repeated
+    /// webhooks credit nothing more.
+    async fn settle(&self, method: PaymentMethod, external_id: &str, amount: Decimal, currency: &str) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+        let Some((id, user_id, expected_currency, status)) = sqlx::query_as::<_, (Uuid, Uuid, String, TransactionStatus)>(
+            "SELECT id, user_id, currency, status FROM transactions WHERE provider = $1 AND external_id = $2 FOR UPDATE",
+        )
+        .bind(method.as_str())
+        .bind(external_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            tracing::warn!("Ignoring settlement of unknown {} payment {}", method.as_str(), external_id);
+            return Ok(());
+        };
+        if status != TransactionStatus::Pending {
+            return Ok(());
+        }
+        if currency != expected_currency || amount <= Decimal::ZERO {
+            tracing::error!(
+                "Payment {} settled with {} {}, expected a positive amount of {}",
+                id, amount, currency, expected_currency
+            );
+            return Err(CryptoTradeError::Validation {
+                message: "Settlement does not match the payment".to_string(),
+            });
+        }
+
+        sqlx::query("UPDATE transactions SET status = 'confirmed', amount = $2, updated_at = NOW() WHERE id = $1")
+            .bind(id)
+            .bind(amount)
+            .execute(&mut *tx)
+            .await?;
+        get_or_create_account(&mut *tx, user_id, currency).await?;
+        let balance = sqlx::query_scalar::<_, Decimal>(
+            "UPDATE accounts SET balance = COALESCE(balance, 0) + $3, available_balance = COALESCE(available_balance, 0) + $3, updated_at = NOW() WHERE user_id = $1 AND currency = $2 RETURNING balance",
+        )
+        .bind(user_id)
+        .bind(currency)
+        .bind(amount)
+        .fetch_one(&mut *tx)
+        .await?;
+        let event = DomainEvent::DepositConfirmed {
+            transaction_id: id,
+            user_id,
+            currency: currency.to_string(),
+            amount,
+        };
+        enqueue_event(&mut *tx, &event).await?;
+        tx.commit().await?;
+
+        let postings = [
+            Posting {
+                user_id: Some(user_id),
+                currency: currency.to_string(),
+                entry_type: LedgerEntryType::Deposit,
+                amount,
+                balance_after: Some(balance),
+            },
+            Posting {
+                user_id: None,
+                currency: currency.to_string(),
+                entry_type: LedgerEntryType::Deposit,
+                amount: -amount,
+                balance_after: None,
+            },
+        ];
+        self.ledger.post(id, Some(id), &postings).await?;
+        tracing::info!("Credited {} {} to user {} for payment {}", amount, currency, user_id, id);
+
+        Ok(())
+    }
+
+    async fn fail(&self, method: PaymentMethod, external_id: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE transactions SET status = 'failed', updated_at = NOW() WHERE provider = $1 AND external_id = $2 AND status = 'pending'",
+        )
+        .bind(method.as_str())
+        .bind(external_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    fn provider(&self, method: PaymentMethod) -> Result<&Arc<dyn PaymentProvider>> {
+        self.providers.get(&method).ok_or_else(|| CryptoTradeError::NotFound {
+            message: format!("Payment method {} is not available", method.as_str()),
+        })
+    }
+}
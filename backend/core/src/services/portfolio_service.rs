@@ -1,29 +1,37 @@
 use crate::{
     database::Database,
+    error::CryptoTradeError,
     models::*,
+    repositories::{PgRepository, Repository},
+    services::order_service::buy_lock_per_unit,
     Result,
 };
 use rust_decimal::Decimal;
-use sqlx::Row;
+use std::collections::HashMap;
 use uuid::Uuid;
 
+/// Fee rate of pairs without one configured; matches settlement.
+const DEFAULT_FEE_RATE: Decimal = Decimal::from_parts(1, 0, 0, false, 3);
+
 #[derive(Clone)]
-pub struct PortfolioService {
-    db: Database,
+pub struct PortfolioService<R = PgRepository> {
+    repository: R,
 }
 
 impl PortfolioService {
     pub fn new(db: Database) -> Self {
-        Self { db }
+        Self::with_repository(PgRepository::new(db))
+    }
+}
+
+impl<R: Repository> PortfolioService<R> {
+    pub fn with_repository(repository: R) -> Self {
+        Self { repository }
     }
 
     pub async fn get_portfolio(&self, user_id: Uuid) -> Result<Portfolio> {
-        let accounts = sqlx::query_as::<_, Account>(
-            "SELECT * FROM accounts WHERE user_id = $1"
-        )
-        .bind(user_id)
-        .fetch_all(&self.db)
-        .await?;
+        let accounts = self.repository.find_accounts(user_id).await?;
+        let usd_prices = self.usd_prices().await?;
 
         let mut account_balances = Vec::new();
         let mut total_value_usd = Decimal::ZERO;
@@ -33,7 +41,7 @@ impl PortfolioService {
             let available_balance = account.available_balance.unwrap_or(Decimal::ZERO);
             let locked_balance = account.locked_balance.unwrap_or(Decimal::ZERO);
 
-            let usd_value = self.get_usd_value(&account.currency, balance).await?;
+            let usd_value = usd_value(&usd_prices, &account.currency, balance);
             total_value_usd += usd_value;
 
             account_balances.push(AccountBalance {
@@ -53,20 +61,8 @@ impl PortfolioService {
             }
         }
 
-        let open_orders_count = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM orders WHERE user_id = $1 AND status IN ('open', 'partially_filled')"
-        )
-        .bind(user_id)
-        .fetch_one(&self.db)
-        .await?;
-
-        let total_trades = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM trades WHERE buyer_user_id = $1 OR seller_user_id = $1"
-        )
-        .bind(user_id)
-        .fetch_one(&self.db)
-        .await?;
-
+        let open_orders_count = self.repository.find_open_orders(user_id).await?.len() as i64;
+        let total_trades = self.repository.count_user_trades(user_id).await?;
         let performance_24h = self.calculate_24h_performance(user_id).await?;
 
         Ok(Portfolio {
@@ -80,39 +76,100 @@ impl PortfolioService {
     }
 
     pub async fn get_portfolio_history(&self, user_id: Uuid, days: i32) -> Result<Vec<PortfolioSnapshot>> {
-        sqlx::query_as::<_, PortfolioSnapshot>(
-            "SELECT * FROM portfolio_snapshots WHERE user_id = $1 AND created_at >= NOW() - INTERVAL '$2 days' ORDER BY created_at DESC"
-        )
-        .bind(user_id)
-        .bind(days.to_string())
-        .fetch_all(&self.db)
-        .await
-        .map_err(Into::into)
+        self.repository.find_portfolio_snapshots(user_id, days).await
+    }
+
+    pub async fn get_user_stats(&self, user_id: Uuid) -> Result<UserStats> {
+        let since = chrono::Utc::now() - chrono::Duration::days(30);
+        let volumes: HashMap<Uuid, Decimal> =
+            self.repository.user_volume_by_pair(user_id, since).await?.into_iter().collect();
+        let open_orders = self.repository.find_open_orders(user_id).await?;
+        let trading_pairs = self.repository.find_trading_pairs().await?;
+        let usd_prices = self.usd_prices().await?;
+
+        let mut pairs = Vec::new();
+        let mut volume_30d_usd = Decimal::ZERO;
+
+        for trading_pair in trading_pairs.iter().filter(|pair| pair.is_active == Some(true)) {
+            let volume_30d = volumes.get(&trading_pair.id).copied().unwrap_or(Decimal::ZERO);
+            volume_30d_usd += usd_value(&usd_prices, &trading_pair.quote_currency, volume_30d);
+
+            pairs.push(UserPairStats {
+                trading_pair_id: trading_pair.id,
+                symbol: trading_pair.symbol.clone(),
+                volume_30d,
+                maker_fee: trading_pair.maker_fee.unwrap_or(DEFAULT_FEE_RATE),
+                taker_fee: trading_pair.taker_fee.unwrap_or(DEFAULT_FEE_RATE),
+                open_orders: open_orders
+                    .iter()
+                    .filter(|order| order.trading_pair_id == trading_pair.id)
+                    .count() as i64,
+            });
+        }
+
+        Ok(UserStats {
+            user_id,
+            volume_30d_usd,
+            pairs,
+            order_locks: order_locks(&open_orders, &trading_pairs, None),
+        })
+    }
+
+    /// What each open order holds of the user's locked balance, optionally
+    /// limited to one currency.
+    pub async fn get_order_locks(&self, user_id: Uuid, currency: Option<&str>) -> Result<Vec<OrderLock>> {
+        let open_orders = self.repository.find_open_orders(user_id).await?;
+        let trading_pairs = self.repository.find_trading_pairs().await?;
+
+        Ok(order_locks(&open_orders, &trading_pairs, currency))
+    }
+
+    /// Breaks an account's locked balance down into the open orders and
+    /// pending withdrawals holding it.
+    pub async fn get_account_holds(&self, user_id: Uuid, currency: &str) -> Result<AccountHolds> {
+        let account = self
+            .repository
+            .find_account(user_id, currency)
+            .await?
+            .ok_or_else(|| CryptoTradeError::NotFound {
+                message: format!("No {} account", currency),
+            })?;
+
+        let mut holds: Vec<BalanceHold> = self
+            .get_order_locks(user_id, Some(currency))
+            .await?
+            .into_iter()
+            .map(|lock| BalanceHold {
+                kind: HoldKind::Order,
+                reference_id: lock.order_id,
+                trading_pair_id: Some(lock.trading_pair_id),
+                amount: lock.amount,
+            })
+            .collect();
+
+        let withdrawals = self.repository.find_pending_withdrawals(user_id, currency).await?;
+        holds.extend(withdrawals.into_iter().map(|withdrawal| BalanceHold {
+            kind: HoldKind::Withdrawal,
+            reference_id: withdrawal.id,
+            trading_pair_id: None,
+            amount: withdrawal.amount,
+        }));
+
+        Ok(AccountHolds {
+            currency: account.currency,
+            locked_balance: account.locked_balance.unwrap_or(Decimal::ZERO),
+            total_held: holds.iter().map(|hold| hold.amount).sum(),
+            holds,
+        })
     }
 
     async fn calculate_24h_performance(&self, user_id: Uuid) -> Result<PerformanceMetrics> {
-        let now = chrono::Utc::now();
-        let yesterday = now - chrono::Duration::hours(24);
-
-        let trade_stats = sqlx::query(
-            r#"
-            SELECT
-                COALESCE(SUM(CASE WHEN buyer_user_id = $1 THEN price * quantity ELSE 0 END), 0) as buy_volume,
-                COALESCE(SUM(CASE WHEN seller_user_id = $1 THEN price * quantity ELSE 0 END), 0) as sell_volume,
-                COALESCE(SUM(CASE WHEN buyer_user_id = $1 THEN buyer_fee ELSE seller_fee END), 0) as total_fees
-            FROM trades
-            WHERE (buyer_user_id = $1 OR seller_user_id = $1)
-              AND created_at >= $2
-            "#
-        )
-        .bind(user_id)
-        .bind(yesterday)
-        .fetch_one(&self.db)
-        .await?;
-
-        let buy_volume: Decimal = trade_stats.get("buy_volume");
-        let sell_volume: Decimal = trade_stats.get("sell_volume");
-        let total_fees: Decimal = trade_stats.get("total_fees");
+        let yesterday = chrono::Utc::now() - chrono::Duration::hours(24);
+        let totals = self.repository.user_trade_totals(user_id, yesterday).await?;
+
+        let buy_volume = totals.buy_volume;
+        let sell_volume = totals.sell_volume;
+        let total_fees = totals.fees;
         let total_volume_24h = buy_volume + sell_volume;
 
         // For now, calculate PnL as sell volume minus buy volume
@@ -132,17 +189,233 @@ impl PortfolioService {
         })
     }
 
-    async fn get_usd_value(&self, currency: &str, amount: Decimal) -> Result<Decimal> {
-        if currency == "USD" || currency == "USDT" {
-            return Ok(amount);
+    /// The reference USD price of each listed currency that has one.
+    async fn usd_prices(&self) -> Result<HashMap<String, Decimal>> {
+        Ok(self
+            .repository
+            .find_currencies()
+            .await?
+            .into_iter()
+            .filter_map(|currency| Some((currency.code, currency.usd_price?)))
+            .collect())
+    }
+}
+
+/// Values `amount` at the currency's reference price; a currency without
+/// one is worth nothing.
+fn usd_value(usd_prices: &HashMap<String, Decimal>, currency: &str, amount: Decimal) -> Decimal {
+    usd_prices.get(currency).map_or(Decimal::ZERO, |price| amount * price)
+}
+
+/// Mirrors how orders release their locks: buys hold their share of what
+/// they locked for each unit left, sells the quantity. Market buys still in
+/// flight hold an estimate settled as a whole and are left out. A scheduled
+/// order holds what it locked when it was scheduled.
+fn order_locks(open_orders: &[Order], trading_pairs: &[TradingPair], currency: Option<&str>) -> Vec<OrderLock> {
+    let mut locks: Vec<OrderLock> = open_orders
+        .iter()
+        .filter_map(|order| {
+            let trading_pair = trading_pairs.iter().find(|pair| pair.id == order.trading_pair_id)?;
+            let remaining = order.remaining_quantity.unwrap_or(Decimal::ZERO);
+
+            let side = order.side?;
+            let currency = match side {
+                OrderSide::Buy => &trading_pair.quote_currency,
+                OrderSide::Sell => &trading_pair.base_currency,
+            };
+            let amount = match (order.status, order.scheduled_lock, side) {
+                (Some(OrderStatus::Scheduled), Some(held), _) => held,
+                (_, _, OrderSide::Buy) if order.order_type == Some(OrderType::Market) => return None,
+                (_, _, OrderSide::Buy) => remaining * buy_lock_per_unit(order),
+                (_, _, OrderSide::Sell) => remaining,
+            };
+
+            Some(OrderLock {
+                order_id: order.id,
+                trading_pair_id: order.trading_pair_id,
+                side,
+                currency: currency.clone(),
+                amount,
+            })
+        })
+        .filter(|lock| currency.is_none_or(|currency| lock.currency == currency))
+        .collect();
+
+    locks.sort_by(|a, b| a.currency.cmp(&b.currency).then(b.amount.cmp(&a.amount)));
+    locks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::{InMemoryRepository, PendingWithdrawal};
+    use chrono::Utc;
+
+    fn trading_pair(symbol: &str, base: &str, quote: &str, is_active: bool) -> TradingPair {
+        TradingPair {
+            id: Uuid::new_v4(),
+            symbol: symbol.to_string(),
+            base_currency: base.to_string(),
+            quote_currency: quote.to_string(),
+            is_active: Some(is_active),
+            min_order_size: None,
+            max_order_size: None,
+            price_precision: None,
+            quantity_precision: None,
+            maker_fee: Some(Decimal::new(5, 4)),
+            taker_fee: Some(Decimal::new(1, 3)),
+            created_at: None,
+            price_band_percent: None,
+            status: if is_active { TradingPairStatus::Trading } else { TradingPairStatus::Halt },
+            tenant_id: None,
+        }
+    }
+
+    fn open_order(user_id: Uuid, pair: &TradingPair, side: OrderSide, remaining: i64, price: i64) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            user_id,
+            trading_pair_id: pair.id,
+            order_type: Some(OrderType::Limit),
+            side: Some(side),
+            quantity: Some(Decimal::from(remaining)),
+            price: Some(Decimal::from(price)),
+            filled_quantity: Some(Decimal::ZERO),
+            remaining_quantity: Some(Decimal::from(remaining)),
+            cumulative_quote_quantity: Some(Decimal::ZERO),
+            average_fill_price: None,
+            status: Some(OrderStatus::Open),
+            time_in_force: Some(TimeInForce::GTC),
+            stop_price: None,
+            created_at: Some(Utc::now()),
+            updated_at: None,
+            expires_at: None,
+            activate_at: None,
+            scheduled_lock: None,
+            // What placing it locked, with the pair's taker fee on buys
+            locked_amount: Some(match side {
+                OrderSide::Buy => Decimal::from(remaining * price) * (Decimal::ONE + pair.taker_fee.unwrap()),
+                OrderSide::Sell => Decimal::from(remaining),
+            }),
+            parent_order_id: None,
+            version: 0,
+        }
+    }
+
+    fn currency(code: &str, usd_price: Option<i64>) -> Currency {
+        Currency {
+            code: code.to_string(),
+            name: code.to_string(),
+            decimals: 8,
+            is_active: true,
+            open_by_default: false,
+            usd_price: usd_price.map(Decimal::from),
+            memo_format: None,
+            deposit_address: None,
+            deposit_confirmations: 1,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
         }
+    }
 
-        // For other currencies, we would typically look up the current exchange rate
-        // For now, return a placeholder value
-        match currency {
-            "BTC" => Ok(amount * Decimal::from(50000)), // Placeholder BTC price
-            "ETH" => Ok(amount * Decimal::from(3000)),  // Placeholder ETH price
-            _ => Ok(Decimal::ZERO),
+    fn account(user_id: Uuid, currency: &str, balance: i64, locked: i64) -> Account {
+        Account {
+            id: Uuid::new_v4(),
+            user_id,
+            currency: currency.to_string(),
+            balance: Some(Decimal::from(balance)),
+            available_balance: Some(Decimal::from(balance - locked)),
+            locked_balance: Some(Decimal::from(locked)),
+            created_at: None,
+            updated_at: None,
         }
     }
+
+    #[tokio::test]
+    async fn test_account_holds_cover_orders_and_withdrawals() {
+        let user_id = Uuid::new_v4();
+        let btc_usdt = trading_pair("BTCUSDT", "BTC", "USDT", true);
+        let repository = InMemoryRepository::default();
+
+        repository.accounts.lock().unwrap().push(account(user_id, "USDT", 1000, 250));
+        repository.orders.lock().unwrap().extend([
+            open_order(user_id, &btc_usdt, OrderSide::Buy, 2, 100),
+            open_order(user_id, &btc_usdt, OrderSide::Sell, 1, 120),
+        ]);
+        repository.withdrawals.lock().unwrap().push(PendingWithdrawal {
+            id: Uuid::new_v4(),
+            user_id,
+            currency: "USDT".to_string(),
+            amount: Decimal::from(50),
+        });
+        repository.trading_pairs.lock().unwrap().push(btc_usdt);
+
+        let holds = PortfolioService::with_repository(repository)
+            .get_account_holds(user_id, "USDT")
+            .await
+            .unwrap();
+
+        // The sell order holds BTC, not USDT
+        assert_eq!(holds.holds.len(), 2);
+        assert_eq!(holds.holds[0].kind, HoldKind::Order);
+        assert_eq!(holds.holds[0].amount, Decimal::new(2002, 1));
+        assert_eq!(holds.holds[1].kind, HoldKind::Withdrawal);
+        assert_eq!(holds.total_held, Decimal::new(2502, 1));
+        assert_eq!(holds.locked_balance, Decimal::from(250));
+    }
+
+    #[tokio::test]
+    async fn test_account_holds_without_account() {
+        let service = PortfolioService::with_repository(InMemoryRepository::default());
+
+        let result = service.get_account_holds(Uuid::new_v4(), "BTC").await;
+        assert!(matches!(result, Err(CryptoTradeError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_user_stats_lists_active_pairs() {
+        let user_id = Uuid::new_v4();
+        let btc_usdt = trading_pair("BTCUSDT", "BTC", "USDT", true);
+        let eth_usdt = trading_pair("ETHUSDT", "ETH", "USDT", false);
+        let repository = InMemoryRepository::default();
+
+        repository.orders.lock().unwrap().extend([
+            open_order(user_id, &btc_usdt, OrderSide::Buy, 1, 100),
+            open_order(user_id, &btc_usdt, OrderSide::Sell, 1, 110),
+            open_order(Uuid::new_v4(), &btc_usdt, OrderSide::Sell, 1, 110),
+        ]);
+        repository.trading_pairs.lock().unwrap().extend([btc_usdt.clone(), eth_usdt]);
+
+        let stats = PortfolioService::with_repository(repository).get_user_stats(user_id).await.unwrap();
+
+        assert_eq!(stats.pairs.len(), 1);
+        assert_eq!(stats.pairs[0].trading_pair_id, btc_usdt.id);
+        assert_eq!(stats.pairs[0].open_orders, 2);
+        assert_eq!(stats.pairs[0].taker_fee, Decimal::new(1, 3));
+        assert_eq!(stats.order_locks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_portfolio_percentages() {
+        let user_id = Uuid::new_v4();
+        let repository = InMemoryRepository::default();
+        repository.accounts.lock().unwrap().extend([
+            account(user_id, "USDT", 25000, 0),
+            account(user_id, "BTC", 1, 0),
+            account(user_id, "DOGE", 1000, 0),
+        ]);
+        repository.currencies.lock().unwrap().extend([
+            currency("USDT", Some(1)),
+            currency("BTC", Some(50000)),
+            currency("DOGE", None),
+        ]);
+
+        let portfolio = PortfolioService::with_repository(repository).get_portfolio(user_id).await.unwrap();
+
+        assert_eq!(portfolio.total_value_usd, Decimal::from(75000));
+        let btc = portfolio.accounts.iter().find(|account| account.currency == "BTC").unwrap();
+        assert_eq!(btc.percentage.round_dp(2), Decimal::new(6667, 2));
+        let doge = portfolio.accounts.iter().find(|account| account.currency == "DOGE").unwrap();
+        assert_eq!(doge.usd_value, Decimal::ZERO);
+    }
 }
@@ -0,0 +1,200 @@
+use crate::{
+    config::{MarketDataConfig, MarketDataTier},
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    resilience::CircuitBreaker,
+    Result,
+};
+use chrono::{DateTime, Duration, Utc};
+use redis::aio::ConnectionManager;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// Counters outlive their window by this much, so usage of the window just
+/// ended can still be read
+const MINUTE_KEY_TTL_SECONDS: i64 = 120;
+const DAY_KEY_TTL_SECONDS: i64 = 2 * 86_400;
+
+/// Who is calling a market data endpoint.
+#[derive(Debug, Clone, Copy)]
+pub enum Caller {
+    /// Counted per address; callers whose address is unknown share a count
+    Anonymous(Option<IpAddr>),
+    ApiKey(Uuid),
+}
+
+/// Where a caller stands after a request, for the `X-RateLimit-*` headers,
+/// and the limits its tier allows.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitStatus {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_at: DateTime<Utc>,
+    pub tier: MarketDataTier,
+}
+
+/// Fixed-window request counters for the public market data endpoints,
+/// kept in Redis so every API instance shares them. Anonymous callers are
+/// counted per IP address; API keys get a larger per-minute allowance and a
+/// daily quota on top. While Redis is unavailable requests are let through
+/// uncounted rather than refused.
+#[derive(Clone)]
+pub struct RateLimitService {
+    db: Database,
+    redis: ConnectionManager,
+    breaker: CircuitBreaker,
+    config: MarketDataConfig,
+}
+
+impl RateLimitService {
+    pub fn new(db: Database, redis: ConnectionManager, breaker: CircuitBreaker, config: MarketDataConfig) -> Self {
+        Self { db, redis, breaker, config }
+    }
+
+    /// Counts a market data request, refusing it once the caller is over
+    /// its limit.
+    pub async fn check_market_data(&self, caller: Caller) -> Result<RateLimitStatus> {
+        let now = Utc::now();
+        let (tier, caller_key) = match caller {
+            Caller::Anonymous(ip) => (
+                self.config.anonymous,
+                format!("ip:{}", ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())),
+            ),
+            Caller::ApiKey(key_id) => (self.config.api_key, format!("key:{}", key_id)),
+        };
+
+        let (window, reset_at) = minute_window(now);
+        let used = self.count(&minute_key(&caller_key, window), MINUTE_KEY_TTL_SECONDS).await;
+        if used > tier.requests_per_minute {
+            return Err(CryptoTradeError::RateLimited {
+                limit: tier.requests_per_minute,
+                window: "minute",
+                retry_after_seconds: seconds_until(now, reset_at),
+            });
+        }
+
+        if let Caller::ApiKey(key_id) = caller {
+            let used_today = self.count(&day_key(key_id, now), DAY_KEY_TTL_SECONDS).await;
+            if used_today > self.config.api_key_daily_quota {
+                return Err(CryptoTradeError::RateLimited {
+                    limit: self.config.api_key_daily_quota,
+                    window: "day",
+                    retry_after_seconds: seconds_until(now, next_midnight(now)),
+                });
+            }
+        }
+
+        Ok(RateLimitStatus {
+            limit: tier.requests_per_minute,
+            remaining: tier.requests_per_minute - used,
+            reset_at,
+            tier,
+        })
+    }
+
+    /// Current market data usage of each of the user's active API keys.
+    pub async fn api_usage(&self, user_id: Uuid) -> Result<Vec<ApiKeyUsage>> {
+        let keys = sqlx::query_as::<_, (Uuid, String)>(
+            "SELECT id, label FROM api_keys WHERE user_id = $1 AND revoked_at IS NULL ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        let now = Utc::now();
+        let (window, minute_resets_at) = minute_window(now);
+        let mut usage = Vec::with_capacity(keys.len());
+        for (key_id, label) in keys {
+            usage.push(ApiKeyUsage {
+                key_id,
+                label,
+                requests_per_minute: self.config.api_key.requests_per_minute,
+                used_this_minute: self.read(&minute_key(&format!("key:{}", key_id), window)).await?,
+                minute_resets_at,
+                daily_quota: self.config.api_key_daily_quota,
+                used_today: self.read(&day_key(key_id, now)).await?,
+                day_resets_at: next_midnight(now),
+            });
+        }
+        Ok(usage)
+    }
+
+    /// Counts a request against `key`, or 0 when it cannot be counted.
+    async fn count(&self, key: &str, ttl_seconds: i64) -> u64 {
+        let mut redis = self.redis.clone();
+        let counted = self
+            .breaker
+            .call_once(
+                redis::pipe()
+                    .atomic()
+                    .incr(key, 1)
+                    .expire(key, ttl_seconds)
+                    .ignore()
+                    .query_async::<_, (u64,)>(&mut redis),
+            )
+            .await;
+        match counted {
+            Ok((count,)) => count,
+            Err(e) => {
+                tracing::warn!("Request not counted against {}: {}", key, e);
+                0
+            }
+        }
+    }
+
+    async fn read(&self, key: &str) -> Result<u64> {
+        let count: Option<u64> = self
+            .breaker
+            .call(|| {
+                let mut redis = self.redis.clone();
+                async move { redis::cmd("GET").arg(key).query_async(&mut redis).await }
+            })
+            .await?;
+        Ok(count.unwrap_or(0))
+    }
+}
+
+fn minute_key(caller_key: &str, window: i64) -> String {
+    format!("rate_limit:{}:{}", caller_key, window)
+}
+
+fn day_key(key_id: Uuid, now: DateTime<Utc>) -> String {
+    format!("api_usage:{}:{}", key_id, now.format("%Y-%m-%d"))
+}
+
+/// The number of the minute `now` falls in, and when it ends.
+fn minute_window(now: DateTime<Utc>) -> (i64, DateTime<Utc>) {
+    let window = now.timestamp().div_euclid(60);
+    let reset_at = DateTime::from_timestamp((window + 1) * 60, 0).unwrap_or(now);
+    (window, reset_at)
+}
+
+fn next_midnight(now: DateTime<Utc>) -> DateTime<Utc> {
+    (now.date_naive() + Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .map_or(now, |midnight| midnight.and_utc())
+}
+
+/// Whole seconds from `now` to `then`, at least one so clients never retry
+/// immediately.
+fn seconds_until(now: DateTime<Utc>, then: DateTime<Utc>) -> u64 {
+    let millis = (then - now).num_milliseconds().max(1);
+    (millis as u64).div_ceil(1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_end_on_the_minute_and_at_midnight() {
+        let now = DateTime::parse_from_rfc3339("2024-03-01T23:59:30.250Z").unwrap().with_timezone(&Utc);
+        let (window, reset_at) = minute_window(now);
+        assert_eq!(reset_at.to_rfc3339(), "2024-03-02T00:00:00+00:00");
+        assert_eq!(minute_window(reset_at).0, window + 1);
+        assert_eq!(next_midnight(now), reset_at);
+        assert_eq!(seconds_until(now, reset_at), 30);
+        assert_eq!(seconds_until(reset_at, reset_at), 1);
+    }
+}
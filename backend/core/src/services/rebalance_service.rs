@@ -0,0 +1,409 @@
+use crate::{
+    config::RebalanceConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    oracle::{LastTradePrices, PriceOracle},
+    services::{user_service::account_status, OrderService},
+    Result,
+};
+use chrono::{Duration, Utc};
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Decimals percentages are reported to, as stored for targets
+const PERCENT_SCALE: u32 = 4;
+/// Decimals quantities are kept to when a pair sets no precision
+const DEFAULT_QUANTITY_SCALE: u32 = 8;
+
+/// Target allocations per asset and the trades that move a portfolio back
+/// to them (see migration 053). Holdings are valued at the oracle's price
+/// in the reference currency and every trade goes through a pair with it.
+/// A plan is only placed when the user confirms it, exactly as proposed.
+#[derive(Clone)]
+pub struct RebalanceService {
+    db: Database,
+    order_service: OrderService,
+    config: RebalanceConfig,
+    oracle: Arc<dyn PriceOracle>,
+}
+
+impl RebalanceService {
+    /// Values holdings at the exchange's last trades.
+    pub fn new(db: Database, order_service: OrderService, config: RebalanceConfig) -> Self {
+        Self {
+            oracle: Arc::new(LastTradePrices::new(db.clone())),
+            db,
+            order_service,
+            config,
+        }
+    }
+
+    pub async fn targets(&self, user_id: Uuid) -> Result<Vec<AllocationTarget>> {
+        sqlx::query_as::<_, AllocationTarget>(
+            "SELECT currency, target_percent FROM allocation_targets WHERE user_id = $1 ORDER BY target_percent DESC, currency",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+
+    pub async fn set_targets(&self, user_id: Uuid, request: SetAllocationTargetsRequest) -> Result<Vec<AllocationTarget>> {
+        let targets = validate_targets(request.targets)?;
+
+        let mut tx = self.db.begin().await?;
+        sqlx::query("DELETE FROM allocation_targets WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        for target in &targets {
+            sqlx::query("INSERT INTO allocation_targets (user_id, currency, target_percent) VALUES ($1, $2, $3)")
+                .bind(user_id)
+                .bind(&target.currency)
+                .bind(target.target_percent)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        self.targets(user_id).await
+    }
+
+    /// The portfolio's drift from its targets with the trades that would
+    /// close it, recorded so the user can confirm them as they stand.
+    pub async fn plan(&self, user_id: Uuid) -> Result<RebalancePlan> {
+        let targets: BTreeMap<String, Decimal> = self
+            .targets(user_id)
+            .await?
+            .into_iter()
+            .map(|target| (target.currency, target.target_percent))
+            .collect();
+        if targets.is_empty() {
+            return Err(CryptoTradeError::Validation {
+                message: "Set allocation targets before rebalancing".to_string(),
+            });
+        }
+
+        let reference = &self.config.reference_currency;
+        let mut balances: BTreeMap<String, Decimal> = sqlx::query_as::<_, (String, Decimal)>(
+            "SELECT currency, balance FROM accounts WHERE user_id = $1 AND balance > 0",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .collect();
+        for currency in targets.keys() {
+            balances.entry(currency.clone()).or_default();
+        }
+        let mut holdings = Vec::new();
+        for (currency, balance) in balances {
+            let price = self.oracle.price(&currency, reference).await?;
+            holdings.push((currency, balance, price));
+        }
+        let (total_value, assets) = drift(&holdings, &targets);
+
+        let mut trades = Vec::new();
+        for asset in &assets {
+            let (Some(price), Some(value)) = (asset.price, asset.value) else {
+                continue;
+            };
+            let shift = asset.target_percent * total_value / Decimal::ONE_HUNDRED - value;
+            if asset.currency == *reference || shift.abs() < self.config.min_trade_value || shift.is_zero() {
+                continue;
+            }
+            let Some(pair) = self.reference_pair(&asset.currency).await? else {
+                tracing::debug!("No {} pair with {} to rebalance through", asset.currency, reference);
+                continue;
+            };
+            trades.extend(trade_for(&pair, &asset.currency, shift, price));
+        }
+        // Trades raising the reference currency go first, to pay for the rest
+        trades.sort_by_key(|trade| !raises_reference(trade, reference));
+
+        let expires_at = Utc::now() + Duration::seconds(self.config.plan_ttl_seconds.into());
+        let id = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO rebalance_plans (user_id, trades, expires_at) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(user_id)
+        .bind(serde_json::to_value(&trades)?)
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(RebalancePlan {
+            id,
+            reference_currency: reference.clone(),
+            total_value,
+            assets,
+            trades,
+            expires_at,
+        })
+    }
+
+    /// Places a plan's trades as market orders, in order, as they were
+    /// proposed. A plan is placed at most once; a trade that fails does not
+    /// stop the ones after it.
+    pub async fn execute(&self, user_id: Uuid, plan_id: Uuid) -> Result<RebalanceExecution> {
+        account_status(&self.db, user_id).await?.ensure_can_trade()?;
+        let claimed = sqlx::query_scalar::<_, serde_json::Value>(
+            r#"
+            UPDATE rebalance_plans SET executed_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND executed_at IS NULL AND expires_at > NOW()
+            RETURNING trades
+            "#,
+        )
+        .bind(plan_id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+        let Some(trades) = claimed else {
+            let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM rebalance_plans WHERE id = $1 AND user_id = $2)")
+                .bind(plan_id)
+                .bind(user_id)
+                .fetch_one(&self.db)
+                .await?;
+            return Err(match exists {
+                true => CryptoTradeError::Conflict {
+                    message: "Rebalance plan has expired or was already placed".to_string(),
+                },
+                false => CryptoTradeError::NotFound {
+                    message: "Rebalance plan not found".to_string(),
+                },
+            });
+        };
+
+        let mut results = Vec::new();
+        for trade in serde_json::from_value::<Vec<RebalanceTrade>>(trades)? {
+            let placed = match trade.quantity.to_f64() {
+                Some(quantity) => {
+                    self.order_service
+                        .create_order(
+                            user_id,
+                            CreateOrderRequest {
+                                trading_pair_id: trade.trading_pair_id,
+                                order_type: OrderType::Market,
+                                side: trade.side,
+                                quantity,
+                                price: None,
+                                time_in_force: None,
+                                stop_price: None,
+                                allow_duplicate: true,
+                                activate_at: None,
+                                lock_funds: false,
+                            },
+                        )
+                        .await
+                }
+                None => Err(CryptoTradeError::InvalidQuantity),
+            };
+            let (order_id, error) = match placed {
+                Ok(order) => (Some(order.id), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            results.push(RebalanceTradeResult { trade, order_id, error });
+        }
+        tracing::info!("User {} placed rebalance plan {}", user_id, plan_id);
+
+        Ok(RebalanceExecution {
+            plan_id,
+            trades: results,
+        })
+    }
+
+    /// An active pair between `currency` and the reference currency,
+    /// preferring the one with `currency` as base.
+    async fn reference_pair(&self, currency: &str) -> Result<Option<TradingPair>> {
+        sqlx::query_as::<_, TradingPair>(
+            r#"
+            SELECT * FROM trading_pairs
+            WHERE is_active = true AND tenant_id IS NULL
+              AND ((base_currency = $1 AND quote_currency = $2) OR (base_currency = $2 AND quote_currency = $1))
+            ORDER BY base_currency = $1 DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(currency)
+        .bind(&self.config.reference_currency)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+}
+
+/// Checks a new set of targets: known-looking currencies, each at most
+/// once, with positive shares summing to exactly 100.
+fn validate_targets(targets: Vec<AllocationTarget>) -> Result<Vec<AllocationTarget>> {
+    let invalid = |message: String| CryptoTradeError::Validation { message };
+    let mut seen = HashSet::new();
+    let mut cleaned = Vec::new();
+    for target in targets {
+        let currency = target.currency.trim().to_uppercase();
+        if currency.is_empty() || currency.len() > 10 {
+            return Err(invalid(format!("'{}' is not a currency", target.currency)));
+        }
+        if !seen.insert(currency.clone()) {
+            return Err(invalid(format!("{} has more than one target", currency)));
+        }
+        if target.target_percent <= Decimal::ZERO || target.target_percent.scale() > PERCENT_SCALE {
+            return Err(invalid(format!(
+                "{}'s target must be positive, with at most {} decimal places",
+                currency, PERCENT_SCALE
+            )));
+        }
+        cleaned.push(AllocationTarget {
+            currency,
+            target_percent: target.target_percent,
+        });
+    }
+    let total: Decimal = cleaned.iter().map(|target| target.target_percent).sum();
+    if !cleaned.is_empty() && total != Decimal::ONE_HUNDRED {
+        return Err(invalid(format!("Targets must sum to 100, not {}", total)));
+    }
+    Ok(cleaned)
+}
+
+/// Values each holding, given as `(currency, balance, price)`, and reports
+/// its drift; returns the total value too. Held assets without a target
+/// have a target of 0.
+fn drift(holdings: &[(String, Decimal, Option<Decimal>)], targets: &BTreeMap<String, Decimal>) -> (Decimal, Vec<AllocationDrift>) {
+    let total: Decimal = holdings.iter().filter_map(|(_, balance, price)| price.map(|price| balance * price)).sum();
+    let assets = holdings
+        .iter()
+        .map(|(currency, balance, price)| {
+            let value = price.map(|price| balance * price);
+            let current_percent = match value {
+                Some(value) if !total.is_zero() => (value * Decimal::ONE_HUNDRED / total).round_dp(PERCENT_SCALE),
+                _ => Decimal::ZERO,
+            };
+            let target_percent = targets.get(currency).copied().unwrap_or_default();
+            AllocationDrift {
+                currency: currency.clone(),
+                balance: *balance,
+                price: *price,
+                value,
+                current_percent,
+                target_percent,
+                drift_percent: current_percent - target_percent,
+            }
+        })
+        .collect();
+    (total, assets)
+}
+
+/// The order moving `shift` worth of `currency` (in the reference currency,
+/// positive to buy) at `price`, through `pair` between the two. Quantities
+/// round down to the pair's precision; `None` if that leaves less than the
+/// pair's minimum.
+fn trade_for(pair: &TradingPair, currency: &str, shift: Decimal, price: Decimal) -> Option<RebalanceTrade> {
+    let buying = shift > Decimal::ZERO;
+    let value = shift.abs();
+    let (side, quantity, estimated_price) = if pair.base_currency == currency {
+        let side = if buying { OrderSide::Buy } else { OrderSide::Sell };
+        (side, value / price, price)
+    } else {
+        // The reference currency is the base, so buying `currency` sells it
+        let side = if buying { OrderSide::Sell } else { OrderSide::Buy };
+        (side, value, Decimal::ONE / price)
+    };
+    let scale = pair.quantity_precision.map_or(DEFAULT_QUANTITY_SCALE, |precision| precision.max(0) as u32);
+    let quantity = quantity.round_dp_with_strategy(scale, RoundingStrategy::ToZero);
+    if quantity.is_zero() || quantity < pair.min_order_size.unwrap_or_default() {
+        return None;
+    }
+    Some(RebalanceTrade {
+        trading_pair_id: pair.id,
+        symbol: pair.symbol.clone(),
+        side,
+        quantity,
+        estimated_price,
+        value,
+    })
+}
+
+fn raises_reference(trade: &RebalanceTrade, reference: &str) -> bool {
+    let sells_base = trade.side == OrderSide::Sell;
+    match trade.symbol.split_once('-') {
+        Some((base, _)) if base == reference => !sells_base,
+        _ => sells_base,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn pair(base: &str, quote: &str) -> TradingPair {
+        TradingPair {
+            id: Uuid::new_v4(),
+            symbol: format!("{}-{}", base, quote),
+            base_currency: base.to_string(),
+            quote_currency: quote.to_string(),
+            is_active: Some(true),
+            min_order_size: Some(dec("0.001")),
+            max_order_size: None,
+            price_precision: Some(2),
+            quantity_precision: Some(4),
+            maker_fee: None,
+            taker_fee: None,
+            created_at: None,
+            price_band_percent: None,
+            status: TradingPairStatus::Trading,
+            tenant_id: None,
+        }
+    }
+
+    #[test]
+    fn test_drift_is_measured_against_the_priced_total() {
+        let holdings = vec![
+            ("BTC".to_string(), dec("0.1"), Some(dec("20000"))),
+            ("USD".to_string(), dec("6000"), Some(Decimal::ONE)),
+            ("XYZ".to_string(), dec("5"), None),
+        ];
+        let targets = BTreeMap::from([("BTC".to_string(), dec("50")), ("USD".to_string(), dec("50"))]);
+        let (total, assets) = drift(&holdings, &targets);
+
+        assert_eq!(total, dec("8000"));
+        assert_eq!((assets[0].current_percent, assets[0].drift_percent), (dec("25"), dec("-25")));
+        assert_eq!((assets[1].current_percent, assets[1].drift_percent), (dec("75"), dec("25")));
+        assert_eq!((assets[2].value, assets[2].target_percent), (None, Decimal::ZERO));
+    }
+
+    #[test]
+    fn test_trades_are_sized_through_either_side_of_the_pair() {
+        let buy = trade_for(&pair("BTC", "USD"), "BTC", dec("2000"), dec("20000")).unwrap();
+        assert_eq!((buy.side, buy.quantity, buy.estimated_price), (OrderSide::Buy, dec("0.1"), dec("20000")));
+        let sell = trade_for(&pair("BTC", "USD"), "BTC", dec("-1000"), dec("30000")).unwrap();
+        assert_eq!((sell.side, sell.quantity), (OrderSide::Sell, dec("0.0333")));
+        assert!(raises_reference(&sell, "USD"));
+
+        // Buying EUR on USD-EUR sells USD
+        let via_base = trade_for(&pair("USD", "EUR"), "EUR", dec("50"), dec("1.25")).unwrap();
+        assert_eq!((via_base.side, via_base.quantity, via_base.estimated_price), (OrderSide::Sell, dec("50"), dec("0.8")));
+        assert!(!raises_reference(&via_base, "USD"));
+
+        assert_eq!(trade_for(&pair("BTC", "USD"), "BTC", dec("1"), dec("20000")), None);
+    }
+
+    #[test]
+    fn test_targets_must_sum_to_one_hundred() {
+        let target = |currency: &str, percent: &str| AllocationTarget {
+            currency: currency.to_string(),
+            target_percent: dec(percent),
+        };
+        let cleaned = validate_targets(vec![target(" btc", "60"), target("USD", "40")]).unwrap();
+        assert_eq!(cleaned[0].currency, "BTC");
+        assert!(validate_targets(vec![target("BTC", "60"), target("USD", "30")]).is_err());
+        assert!(validate_targets(vec![target("BTC", "50"), target("btc", "50")]).is_err());
+        assert!(validate_targets(vec![target("BTC", "100.00001")]).is_err());
+        assert!(validate_targets(Vec::new()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{
+    config::{KycTiersConfig, TradingConfig},
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    oracle::{LastTradePrices, PriceOracle},
+    Result,
+};
+use rust_decimal::Decimal;
+use sqlx::PgConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Statuses of orders still resting on (or waiting for) the book
+const OPEN_STATUSES: &str = "('pending', 'open', 'partially_filled')";
+
+/// Per-user caps on resting orders: how many a user may have open in one
+/// pair, and the notional they may have open per quote currency. Defaults
+/// come from `TradingConfig`, with the notional further capped by the
+/// user's KYC tier; admins override them per user in `user_risk_limits`
+/// (see migration 014). Market orders never rest, so they neither count
+/// towards nor are checked against either limit. The KYC tier also caps
+/// daily fiat deposits and withdrawals, and crypto withdrawals valued at the
+/// oracle price.
+#[derive(Clone)]
+pub struct RiskLimitService {
+    db: Database,
+    config: TradingConfig,
+    kyc_tiers: KycTiersConfig,
+    oracle: Arc<dyn PriceOracle>,
+}
+
+#[derive(sqlx::FromRow)]
+struct LimitSettings {
+    kyc_tier: KycTier,
+    /// Whether an admin has overridden the limits
+    custom: bool,
+    max_open_orders_per_pair: Option<i32>,
+    max_open_notional: Option<Decimal>,
+}
+
+impl RiskLimitService {
+    /// Values crypto at the exchange's last trades.
+    pub fn new(db: Database, config: TradingConfig, kyc_tiers: KycTiersConfig) -> Self {
+        Self {
+            oracle: Arc::new(LastTradePrices::new(db.clone())),
+            db,
+            config,
+            kyc_tiers,
+        }
+    }
+
+    pub fn with_oracle(mut self, oracle: Arc<dyn PriceOracle>) -> Self {
+        self.oracle = oracle;
+        self
+    }
+
+    /// The limits in force for the user and how much of them is in use.
+    pub async fn get_user_limits(&self, user_id: Uuid) -> Result<UserLimits> {
+        let settings = self.find_settings(user_id).await?;
+        let (max_open_orders_per_pair, max_open_notional) = self.effective(settings.as_ref());
+
+        let pairs = sqlx::query_as::<_, PairOrderUsage>(&format!(
+            "SELECT o.trading_pair_id, tp.symbol, COUNT(*) AS open_orders FROM orders o JOIN trading_pairs tp ON o.trading_pair_id = tp.id WHERE o.user_id = $1 AND o.status IN {} AND o.order_type <> 'market' GROUP BY o.trading_pair_id, tp.symbol ORDER BY tp.symbol",
+            OPEN_STATUSES
+        ))
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        let open_notional = sqlx::query_as::<_, NotionalUsage>(&format!(
+            "SELECT tp.quote_currency AS currency, SUM(o.remaining_quantity * COALESCE(o.price, o.stop_price, 0)) AS open_notional FROM orders o JOIN trading_pairs tp ON o.trading_pair_id = tp.id WHERE o.user_id = $1 AND o.status IN {} AND o.order_type <> 'market' GROUP BY tp.quote_currency ORDER BY tp.quote_currency",
+            OPEN_STATUSES
+        ))
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(UserLimits {
+            max_open_orders_per_pair,
+            max_open_notional,
+            custom: settings.is_some_and(|settings| settings.custom),
+            pairs,
+            open_notional,
+        })
+    }
+
+    /// Overrides the user's limits; a `None` field goes back to the default.
+    pub async fn set_user_limits(&self, user_id: Uuid, request: UpdateUserLimitsRequest) -> Result<UserLimits> {
+        if request.max_open_orders_per_pair.is_some_and(|max| max < 0)
+            || request.max_open_notional.is_some_and(|max| max < Decimal::ZERO)
+        {
+            return Err(CryptoTradeError::Validation {
+                message: "Risk limits must not be negative".to_string(),
+            });
+        }
+
+        let user_exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM users WHERE id = $1)")
+            .bind(user_id)
+            .fetch_one(&self.db)
+            .await?;
+        if !user_exists {
+            return Err(CryptoTradeError::UserNotFound);
+        }
+
+        if request.max_open_orders_per_pair.is_none() && request.max_open_notional.is_none() {
+            sqlx::query("DELETE FROM user_risk_limits WHERE user_id = $1")
+                .bind(user_id)
+                .execute(&self.db)
+                .await?;
+        } else {
+            sqlx::query(
+                "INSERT INTO user_risk_limits (user_id, max_open_orders_per_pair, max_open_notional) VALUES ($1, $2, $3) ON CONFLICT (user_id) DO UPDATE SET max_open_orders_per_pair = EXCLUDED.max_open_orders_per_pair, max_open_notional = EXCLUDED.max_open_notional, updated_at = NOW()",
+            )
+            .bind(user_id)
+            .bind(request.max_open_orders_per_pair)
+            .bind(request.max_open_notional)
+            .execute(&self.db)
+            .await?;
+        }
+
+        self.get_user_limits(user_id).await
+    }
+
+    /// Fails with `RiskLimitExceeded` if resting an order of `notional` on
+    /// the pair would take the user past either limit.
+    pub async fn check_new_order(&self, user_id: Uuid, trading_pair: &TradingPair, notional: Decimal) -> Result<()> {
+        let settings = self.find_settings(user_id).await?;
+        let (max_open_orders, max_open_notional) = self.effective(settings.as_ref());
+
+        let (open_orders, open_notional) = sqlx::query_as::<_, (i64, Option<Decimal>)>(&format!(
+            "SELECT COUNT(*) FILTER (WHERE o.trading_pair_id = $2), SUM(o.remaining_quantity * COALESCE(o.price, o.stop_price, 0)) FROM orders o JOIN trading_pairs tp ON o.trading_pair_id = tp.id WHERE o.user_id = $1 AND tp.quote_currency = $3 AND o.status IN {} AND o.order_type <> 'market'",
+            OPEN_STATUSES
+        ))
+        .bind(user_id)
+        .bind(trading_pair.id)
+        .bind(&trading_pair.quote_currency)
+        .fetch_one(&self.db)
+        .await?;
+
+        if open_orders >= i64::from(max_open_orders) {
+            return Err(CryptoTradeError::RiskLimitExceeded {
+                limit: "max_open_orders_per_pair".to_string(),
+                max: Decimal::from(max_open_orders),
+                current: Decimal::from(open_orders),
+            });
+        }
+
+        let open_notional = open_notional.unwrap_or_default();
+        if open_notional + notional > max_open_notional {
+            return Err(CryptoTradeError::RiskLimitExceeded {
+                limit: "max_open_notional".to_string(),
+                max: max_open_notional,
+                current: open_notional.normalize(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Fails with `RiskLimitExceeded` if depositing `amount` of `currency`
+    /// would take the user past their KYC tier's daily deposit limit. Runs
+    /// in the caller's transaction, which records the deposit before it
+    /// commits.
+    pub async fn check_daily_deposit(&self, conn: &mut PgConnection, user_id: Uuid, currency: &str, amount: Decimal) -> Result<()> {
+        self.check_daily_transfer(conn, user_id, "deposit", currency, amount, Decimal::ONE).await
+    }
+
+    /// Likewise for withdrawals, fees excluded.
+    pub async fn check_daily_withdrawal(&self, conn: &mut PgConnection, user_id: Uuid, currency: &str, amount: Decimal) -> Result<()> {
+        self.check_daily_transfer(conn, user_id, "withdrawal", currency, amount, Decimal::ONE).await
+    }
+
+    /// Likewise for crypto withdrawals, whose amounts are valued in the
+    /// tiers' reference currency at the oracle price. A currency the oracle
+    /// cannot price is refused, since its value is unknown.
+    pub async fn check_daily_crypto_withdrawal(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        currency: &str,
+        amount: Decimal,
+    ) -> Result<()> {
+        let reference = &self.kyc_tiers.reference_currency;
+        let price = if currency == reference {
+            Decimal::ONE
+        } else {
+            self.oracle
+                .price(currency, reference)
+                .await?
+                .ok_or_else(|| CryptoTradeError::Validation {
+                    message: format!("{} withdrawals cannot be valued in {} right now; try again later", currency, reference),
+                })?
+        };
+        self.check_daily_transfer(conn, user_id, "withdrawal", currency, amount, price).await
+    }
+
+    /// `price` values `currency` in the units of the limits. Transfers that
+    /// failed or were cancelled do not count; pending ones do.
+    /// The user's row stays locked until the caller's transaction ends, so
+    /// concurrent transfers are checked one at a time, each seeing those
+    /// recorded before it.
+    async fn check_daily_transfer(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        transaction_type: &str,
+        currency: &str,
+        amount: Decimal,
+        price: Decimal,
+    ) -> Result<()> {
+        let kyc_tier = sqlx::query_scalar::<_, KycTier>("SELECT kyc_tier FROM users WHERE id = $1 FOR NO KEY UPDATE")
+            .bind(user_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .unwrap_or_default();
+        let limits = self.kyc_tiers.limits(kyc_tier);
+        let max = match transaction_type {
+            "deposit" => limits.daily_deposit,
+            _ => limits.daily_withdrawal,
+        };
+
+        let today = sqlx::query_scalar::<_, Option<Decimal>>(
+            "SELECT SUM(amount) FROM transactions WHERE user_id = $1 AND transaction_type = $2::transaction_type AND currency = $3 AND status NOT IN ('failed', 'cancelled') AND created_at >= date_trunc('day', NOW() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'",
+        )
+        .bind(user_id)
+        .bind(transaction_type)
+        .bind(currency)
+        .fetch_one(&mut *conn)
+        .await?
+        .unwrap_or_default();
+
+        if (today + amount) * price > max {
+            return Err(CryptoTradeError::RiskLimitExceeded {
+                limit: format!("daily_{}", transaction_type),
+                max,
+                current: (today * price).normalize(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The user's KYC tier and any admin override of their limits; `None`
+    /// if there is no such user.
+    async fn find_settings(&self, user_id: Uuid) -> Result<Option<LimitSettings>> {
+        Ok(sqlx::query_as::<_, LimitSettings>(
+            "SELECT u.kyc_tier, l.user_id IS NOT NULL AS custom, l.max_open_orders_per_pair, l.max_open_notional FROM users u LEFT JOIN user_risk_limits l ON l.user_id = u.id WHERE u.id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?)
+    }
+
+    /// An admin override wins; otherwise the open notional is the lower of
+    /// the default and what the user's KYC tier allows.
+    fn effective(&self, settings: Option<&LimitSettings>) -> (i32, Decimal) {
+        let kyc_tier = settings.map_or(KycTier::Basic, |settings| settings.kyc_tier);
+        let tier_notional = self.kyc_tiers.limits(kyc_tier).max_open_notional;
+        (
+            settings
+                .and_then(|s| s.max_open_orders_per_pair)
+                .unwrap_or(self.config.max_open_orders_per_pair),
+            settings
+                .and_then(|s| s.max_open_notional)
+                .unwrap_or(self.config.max_open_notional.min(tier_notional)),
+        )
+    }
+}
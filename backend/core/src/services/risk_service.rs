@@ -0,0 +1,335 @@
+use crate::{
+    auth::API_KEY_SCOPE,
+    config::{RiskCheck, RiskConfig},
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    services::{trading_pair_service::pair_in_session, RiskLimitService, RuntimeConfigService},
+    Result,
+};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Runs the configured pre-trade checks on an order before it is placed.
+/// Every check runs even after one fails, so the user sees all the reasons
+/// at once: a single failure is returned as its own error, several as
+/// `PreTradeChecksFailed`. Outcomes are counted in [`RiskMetrics`].
+#[derive(Clone)]
+pub struct RiskService {
+    db: Database,
+    risk_limits: RiskLimitService,
+    runtime: RuntimeConfigService,
+    config: RiskConfig,
+    metrics: RiskMetrics,
+}
+
+/// An order as the checks see it.
+pub struct PreTradeOrder<'a> {
+    pub user_id: Uuid,
+    pub trading_pair: &'a TradingPair,
+    pub request: &'a CreateOrderRequest,
+    pub quantity: Decimal,
+    /// Absent when only the order's terms are checked
+    pub preview: Option<&'a OrderPreview>,
+}
+
+impl RiskService {
+    pub fn new(db: Database, risk_limits: RiskLimitService, runtime: RuntimeConfigService, config: RiskConfig) -> Self {
+        Self {
+            db,
+            risk_limits,
+            runtime,
+            config,
+            metrics: RiskMetrics::default(),
+        }
+    }
+
+    pub fn metrics(&self) -> &RiskMetrics {
+        &self.metrics
+    }
+
+    /// Runs every configured check.
+    pub async fn check_order(&self, order: &PreTradeOrder<'_>) -> Result<()> {
+        self.run(order, |_| true).await
+    }
+
+    /// Runs only the checks on the order's own terms, skipping those that
+    /// depend on the user's balances and open orders. Used where those are
+    /// about to change, as when an order is being replaced.
+    pub async fn check_terms(&self, order: &PreTradeOrder<'_>) -> Result<()> {
+        self.run(order, |check| {
+            matches!(check, RiskCheck::PairPermissions | RiskCheck::Kyc | RiskCheck::PriceBand)
+        })
+        .await
+    }
+
+    async fn run(&self, order: &PreTradeOrder<'_>, include: impl Fn(RiskCheck) -> bool) -> Result<()> {
+        let started = Instant::now();
+        let mut failures = Vec::new();
+        for check in self.config.checks.iter().copied().filter(|check| include(*check)) {
+            let outcome = match check {
+                RiskCheck::PairPermissions => self.check_pair(order).await?,
+                RiskCheck::Kyc => self.check_kyc(order).await?,
+                RiskCheck::PriceBand => self.check_price_band(order).await?,
+                RiskCheck::Balance => self.check_balance(order),
+                RiskCheck::Limits => self.check_limits(order).await?,
+                RiskCheck::SelfTrade => self.check_self_trade(order).await?,
+            };
+            self.metrics.record_check(check, outcome.is_ok());
+            if let Err(failure) = outcome {
+                failures.push(failure);
+            }
+        }
+        self.metrics.record_evaluation(started.elapsed(), !failures.is_empty());
+
+        match failures.len() {
+            0 => Ok(()),
+            1 => Err(failures.remove(0)),
+            _ => Err(CryptoTradeError::PreTradeChecksFailed { failures }),
+        }
+    }
+
+    /// The pair must be active and in one of its trading sessions, and both
+    /// the user and the API key placing the order (if any) must be allowed
+    /// to spot trade it.
+    async fn check_pair(&self, order: &PreTradeOrder<'_>) -> Result<Outcome> {
+        if !order.trading_pair.is_active.unwrap_or(false) {
+            return Ok(Err(CryptoTradeError::TradingPairNotActive));
+        }
+        // The schedule halts the pair only on its next tick after closing
+        if !pair_in_session(&self.db, order.trading_pair.id, Utc::now()).await? {
+            return Ok(Err(CryptoTradeError::OutsideTradingSession));
+        }
+
+        let user_scope = sqlx::query_as::<_, PermissionScope>("SELECT permissions, trading_pair_ids FROM users WHERE id = $1")
+            .bind(order.user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .unwrap_or_default();
+        let key_scope = API_KEY_SCOPE.try_with(Clone::clone).ok();
+
+        for scope in std::iter::once(&user_scope).chain(key_scope.as_ref()) {
+            if !scope.allows(TradingPermission::SpotTrade) {
+                return Ok(Err(CryptoTradeError::PermissionDenied {
+                    permission: TradingPermission::SpotTrade,
+                }));
+            }
+            if !scope.allows_pair(order.trading_pair.id) {
+                return Ok(Err(CryptoTradeError::TradingPairNotPermitted {
+                    trading_pair_id: order.trading_pair.id,
+                }));
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    async fn check_kyc(&self, order: &PreTradeOrder<'_>) -> Result<Outcome> {
+        let status = sqlx::query_scalar::<_, Option<KycStatus>>("SELECT kyc_status FROM users WHERE id = $1")
+            .bind(order.user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .flatten();
+
+        let allowed = match status {
+            Some(KycStatus::Approved) => true,
+            Some(KycStatus::Rejected) => false,
+            _ => !self.config.require_kyc_approval,
+        };
+        Ok(if allowed { Ok(()) } else { Err(CryptoTradeError::KycRequired) })
+    }
+
+    async fn check_price_band(&self, order: &PreTradeOrder<'_>) -> Result<Outcome> {
+        let band_percent = order
+            .trading_pair
+            .price_band_percent
+            .or_else(|| self.runtime.settings().default_price_band_percent);
+        let (Some(price), Some(band_percent)) = (order.request.price, band_percent) else {
+            return Ok(Ok(()));
+        };
+
+        // A pair that has never traded has nothing to measure against
+        let last_price = sqlx::query_scalar::<_, Decimal>(
+            "SELECT price FROM trades WHERE trading_pair_id = $1 AND busted_at IS NULL ORDER BY created_at DESC LIMIT 1",
+        )
+        .bind(order.trading_pair.id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        if let Some(last_price) = last_price {
+            let (min, max) = price_band(last_price, band_percent);
+            if price < min || price > max {
+                return Ok(Err(CryptoTradeError::PriceOutOfBounds { price, min, max }));
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    /// Advisory only: the lock taken when the order is placed is what keeps
+    /// concurrent orders from overspending.
+    fn check_balance(&self, order: &PreTradeOrder<'_>) -> Outcome {
+        match order.preview {
+            Some(preview) if !preview.sufficient_balance => Err(CryptoTradeError::InsufficientBalance {
+                currency: preview.lock_currency.clone(),
+                required: preview.lock_amount,
+                available: preview.available_balance,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Market orders never rest, so they are not held to the open order
+    /// limits.
+    async fn check_limits(&self, order: &PreTradeOrder<'_>) -> Result<Outcome> {
+        let Some(preview) = order.preview else {
+            return Ok(Ok(()));
+        };
+        if order.request.order_type == OrderType::Market {
+            return Ok(Ok(()));
+        }
+
+        // Valued the way resting orders are: at the limit price, else the trigger
+        let notional = order
+            .request
+            .price
+            .or(order.request.stop_price)
+            .map_or(preview.estimated_notional, |price| price * order.quantity);
+        match self.risk_limits.check_new_order(order.user_id, order.trading_pair, notional).await {
+            Err(e @ CryptoTradeError::RiskLimitExceeded { .. }) => Ok(Err(e)),
+            other => other.map(Ok),
+        }
+    }
+
+    /// Limit orders crossing one of the user's own resting orders, and market
+    /// orders when the user's own order is at the top of the opposite side.
+    /// Untriggered stop orders are left alone.
+    async fn check_self_trade(&self, order: &PreTradeOrder<'_>) -> Result<Outcome> {
+        let request = order.request;
+        let (opposite, crosses, best) = match request.side {
+            OrderSide::Buy => ("sell", "<=", "MIN"),
+            OrderSide::Sell => ("buy", ">=", "MAX"),
+        };
+        let limit = match request.order_type {
+            OrderType::Limit => request.price,
+            OrderType::Market => None,
+            _ => return Ok(Ok(())),
+        };
+
+        // Without a limit price the order takes the best opposite level
+        let price_filter = match limit {
+            Some(_) => format!("price {} $3", crosses),
+            None => format!(
+                "price = (SELECT {}(price) FROM orders WHERE trading_pair_id = $2 AND side = '{}' AND order_type = 'limit' AND status IN ('open', 'partially_filled'))",
+                best, opposite
+            ),
+        };
+        let sql = format!(
+            "SELECT EXISTS (SELECT 1 FROM orders WHERE user_id = $1 AND trading_pair_id = $2 AND side = '{}' AND order_type = 'limit' AND status IN ('open', 'partially_filled') AND {})",
+            opposite, price_filter
+        );
+        let mut query = sqlx::query_scalar::<_, bool>(&sql).bind(order.user_id).bind(order.trading_pair.id);
+        if let Some(price) = limit {
+            query = query.bind(price);
+        }
+        let crossed = query.fetch_one(&self.db).await?;
+
+        Ok(if crossed { Err(CryptoTradeError::SelfTradePrevented) } else { Ok(()) })
+    }
+}
+
+/// A check's verdict; the outer `Result` of the async checks carries
+/// failures to run the check at all.
+type Outcome = std::result::Result<(), CryptoTradeError>;
+
+/// Lowest and highest limit price within `band_percent` of `reference`.
+fn price_band(reference: Decimal, band_percent: Decimal) -> (Decimal, Decimal) {
+    let deviation = reference * band_percent / Decimal::ONE_HUNDRED;
+    ((reference - deviation).normalize(), (reference + deviation).normalize())
+}
+
+/// Prometheus counters for the pre-trade checks.
+#[derive(Clone, Default)]
+pub struct RiskMetrics {
+    inner: Arc<Mutex<RiskCounters>>,
+}
+
+#[derive(Default)]
+struct RiskCounters {
+    /// Keyed on check and whether it passed
+    checks: HashMap<(RiskCheck, bool), u64>,
+    evaluations: u64,
+    rejections: u64,
+    duration: Duration,
+}
+
+impl RiskMetrics {
+    fn record_check(&self, check: RiskCheck, passed: bool) {
+        let mut counters = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        *counters.checks.entry((check, passed)).or_default() += 1;
+    }
+
+    fn record_evaluation(&self, elapsed: Duration, rejected: bool) {
+        let mut counters = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        counters.evaluations += 1;
+        counters.rejections += u64::from(rejected);
+        counters.duration += elapsed;
+    }
+
+    /// The metrics in Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let counters = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let mut checks: Vec<_> = counters.checks.iter().collect();
+        checks.sort_by_key(|((check, passed), _)| (check_name(*check), *passed));
+
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP risk_checks_total Pre-trade check outcomes by check");
+        let _ = writeln!(out, "# TYPE risk_checks_total counter");
+        for ((check, passed), count) in checks {
+            let result = if *passed { "pass" } else { "fail" };
+            let _ = writeln!(out, "risk_checks_total{{check=\"{}\",result=\"{}\"}} {}", check_name(*check), result, count);
+        }
+        let _ = writeln!(out, "# HELP risk_rejections_total Orders rejected by at least one pre-trade check");
+        let _ = writeln!(out, "# TYPE risk_rejections_total counter");
+        let _ = writeln!(out, "risk_rejections_total {}", counters.rejections);
+        let _ = writeln!(out, "# HELP risk_evaluation_seconds Time to run the pre-trade checks on an order");
+        let _ = writeln!(out, "# TYPE risk_evaluation_seconds summary");
+        let _ = writeln!(out, "risk_evaluation_seconds_sum {}", counters.duration.as_secs_f64());
+        let _ = writeln!(out, "risk_evaluation_seconds_count {}", counters.evaluations);
+        out
+    }
+}
+
+fn check_name(check: RiskCheck) -> &'static str {
+    match check {
+        RiskCheck::PairPermissions => "pair_permissions",
+        RiskCheck::Kyc => "kyc",
+        RiskCheck::PriceBand => "price_band",
+        RiskCheck::Balance => "balance",
+        RiskCheck::Limits => "limits",
+        RiskCheck::SelfTrade => "self_trade",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_render_outcomes_per_check() {
+        let metrics = RiskMetrics::default();
+        metrics.record_check(RiskCheck::Balance, true);
+        metrics.record_check(RiskCheck::Balance, false);
+        metrics.record_check(RiskCheck::Balance, false);
+        metrics.record_evaluation(Duration::from_millis(2), true);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("risk_checks_total{check=\"balance\",result=\"fail\"} 2"));
+        assert!(rendered.contains("risk_checks_total{check=\"balance\",result=\"pass\"} 1"));
+        assert!(rendered.contains("risk_rejections_total 1"));
+        assert!(rendered.contains("risk_evaluation_seconds_count 1"));
+    }
+}
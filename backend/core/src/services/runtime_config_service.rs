@@ -0,0 +1,181 @@
+use crate::{auth::ADMIN_ROLE, config::Config, database::Database, error::CryptoTradeError, models::*, Result};
+use rust_decimal::Decimal;
+use serde_json::{Map, Value};
+use sqlx::postgres::PgListener;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Postgres channel announcing a change to `runtime_settings`
+const CHANNEL: &str = "runtime_settings";
+
+/// Settings that change without a restart. Defaults come from the static
+/// config; admins override single fields in `runtime_settings` (see
+/// migration 018). Reads are served from memory, and every instance reloads
+/// when an update is announced over Postgres `NOTIFY`, so a change made
+/// through one instance reaches all of them.
+#[derive(Clone)]
+pub struct RuntimeConfigService {
+    db: Database,
+    defaults: RuntimeSettings,
+    current: Arc<RwLock<RuntimeConfig>>,
+}
+
+impl RuntimeSettings {
+    /// The settings with nothing overridden.
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            maintenance_mode: false,
+            maintenance_message: None,
+            default_price_band_percent: None,
+            duplicate_window_seconds: config.trading.duplicate_window_seconds,
+            ws_max_messages_per_second: config.websocket.max_messages_per_second,
+            ws_max_subscriptions: config.websocket.max_subscriptions,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |message: &str| {
+            Err(CryptoTradeError::Validation {
+                message: message.to_string(),
+            })
+        };
+        if self
+            .default_price_band_percent
+            .is_some_and(|percent| percent <= Decimal::ZERO || percent > Decimal::ONE_HUNDRED)
+        {
+            return invalid("Price band must be above 0 and at most 100 percent");
+        }
+        if self.ws_max_messages_per_second == 0 || self.ws_max_subscriptions == 0 {
+            return invalid("WebSocket limits must be positive");
+        }
+        Ok(())
+    }
+}
+
+impl RuntimeConfigService {
+    /// Loads the overrides stored so far on top of `defaults`.
+    pub async fn load(db: Database, defaults: RuntimeSettings) -> Result<Self> {
+        let current = fetch(&db, &defaults).await?;
+        Ok(Self {
+            db,
+            defaults,
+            current: Arc::new(RwLock::new(current)),
+        })
+    }
+
+    pub fn settings(&self) -> RuntimeSettings {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).settings.clone()
+    }
+
+    pub fn config(&self) -> RuntimeConfig {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Fails with `MaintenanceMode` while in maintenance, unless `role` is
+    /// the admin role.
+    pub fn ensure_open(&self, role: &str) -> Result<()> {
+        let settings = self.settings();
+        if settings.maintenance_mode && role != ADMIN_ROLE {
+            return Err(CryptoTradeError::MaintenanceMode {
+                message: settings
+                    .maintenance_message
+                    .unwrap_or_else(|| "The exchange is undergoing maintenance".to_string()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Applies `changes`, a map of setting names to new values in which
+    /// `null` restores a setting's default, and tells every instance.
+    pub async fn update(&self, admin_id: Uuid, changes: Map<String, Value>) -> Result<RuntimeConfig> {
+        // Validate the result before storing anything
+        let mut merged = serde_json::to_value(self.settings())?;
+        for (key, value) in &changes {
+            let default = serde_json::to_value(&self.defaults)?[key].clone();
+            merged[key] = if value.is_null() { default } else { value.clone() };
+        }
+        let settings: RuntimeSettings = serde_json::from_value(merged).map_err(|e| CryptoTradeError::Validation {
+            message: format!("Invalid runtime settings: {}", e),
+        })?;
+        settings.validate()?;
+
+        let mut tx = self.db.begin().await?;
+        for (key, value) in &changes {
+            if value.is_null() {
+                sqlx::query("DELETE FROM runtime_settings WHERE key = $1")
+                    .bind(key)
+                    .execute(&mut *tx)
+                    .await?;
+            } else {
+                sqlx::query(
+                    "INSERT INTO runtime_settings (key, value, updated_by) VALUES ($1, $2, $3) ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, updated_by = EXCLUDED.updated_by, updated_at = NOW()",
+                )
+                .bind(key)
+                .bind(value)
+                .bind(admin_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        sqlx::query("SELECT pg_notify($1, '')").bind(CHANNEL).execute(&mut *tx).await?;
+        tx.commit().await?;
+
+        self.reload().await?;
+        Ok(self.config())
+    }
+
+    pub async fn reload(&self) -> Result<()> {
+        let current = fetch(&self.db, &self.defaults).await?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = current;
+        Ok(())
+    }
+
+    /// Reloads whenever another instance announces a change, and after the
+    /// listening connection is re-established, since announcements made
+    /// while it was down are lost.
+    pub fn spawn_listener(&self) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = service.listen().await {
+                    tracing::error!("Runtime settings listener failed: {}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+    }
+
+    async fn listen(&self) -> Result<()> {
+        let mut listener = PgListener::connect_with(&self.db).await?;
+        listener.listen(CHANNEL).await?;
+        self.reload().await?;
+
+        loop {
+            let notification = listener.try_recv().await?;
+            if notification.is_none() {
+                tracing::warn!("Runtime settings listener reconnected; reloading");
+            }
+            self.reload().await?;
+        }
+    }
+}
+
+async fn fetch(db: &Database, defaults: &RuntimeSettings) -> Result<RuntimeConfig> {
+    let rows = sqlx::query_as::<_, (String, Value)>("SELECT key, value FROM runtime_settings ORDER BY key")
+        .fetch_all(db)
+        .await?;
+
+    let mut merged = serde_json::to_value(defaults)?;
+    let mut overridden = Vec::new();
+    for (key, value) in rows {
+        // Rows for settings that no longer exist are ignored
+        if merged.get(&key).is_some() {
+            merged[&key] = value;
+            overridden.push(key);
+        }
+    }
+    let settings = serde_json::from_value(merged)?;
+
+    Ok(RuntimeConfig { settings, overridden })
+}
@@ -0,0 +1,288 @@
+use crate::{
+    config::SandboxConfig,
+    database::Database,
+    error::CryptoTradeError,
+    matching::{QuoteLadder, RandomWalk},
+    models::*,
+    services::{LedgerService, OrderService, Posting},
+    Result,
+};
+use rust_decimal::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// The house account that quotes the simulated market. It has no usable
+/// password, so nobody can log in as it.
+const HOUSE_EMAIL: &str = "market@sandbox.invalid";
+const HOUSE_USERNAME: &str = "sandbox-market";
+
+/// Paper trading support: funds users with play money and keeps a two-sided
+/// market on every active pair around a seeded random walk.
+#[derive(Clone)]
+pub struct SandboxService {
+    db: Database,
+    ledger: LedgerService,
+    order_service: OrderService,
+    config: SandboxConfig,
+    market: Arc<Mutex<SimulatedMarket>>,
+}
+
+struct SimulatedMarket {
+    walk: RandomWalk,
+    house_id: Option<Uuid>,
+    prices: HashMap<Uuid, Decimal>,
+}
+
+impl SandboxService {
+    pub fn new(db: Database, ledger: LedgerService, order_service: OrderService, config: SandboxConfig) -> Self {
+        let market = SimulatedMarket {
+            walk: RandomWalk::new(config.seed, config.volatility_bps),
+            house_id: None,
+            prices: HashMap::new(),
+        };
+
+        Self {
+            db,
+            ledger,
+            order_service,
+            config,
+            market: Arc::new(Mutex::new(market)),
+        }
+    }
+
+    /// Gives a newly registered user the configured starting balances.
+    pub async fn fund_user(&self, user_id: Uuid) -> Result<()> {
+        let balances: BTreeMap<String, Decimal> = self.config.starting_balances.clone().into_iter().collect();
+        self.set_balances(user_id, &balances).await
+    }
+
+    /// Cancels the user's open orders and puts every starting currency back
+    /// to its starting balance.
+    pub async fn reset_user(&self, user_id: Uuid) -> Result<Vec<Account>> {
+        self.cancel_open_orders(user_id).await?;
+        self.fund_user(user_id).await?;
+
+        let accounts = sqlx::query_as::<_, Account>("SELECT * FROM accounts WHERE user_id = $1 ORDER BY currency")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await?;
+
+        Ok(accounts)
+    }
+
+    /// Advances the walk one step on every active pair and replaces the
+    /// house quotes around the new prices. Returns the number of quotes
+    /// placed.
+    pub async fn tick(&self) -> Result<usize> {
+        let mut market = self.market.lock().await;
+
+        let house_id = match market.house_id {
+            Some(house_id) => house_id,
+            None => {
+                let house_id = self.ensure_house_user().await?;
+                market.house_id = Some(house_id);
+                house_id
+            }
+        };
+        self.cancel_open_orders(house_id).await?;
+
+        // Pairs in symbol order so the same seed draws the same path
+        let pairs = sqlx::query_as::<_, TradingPair>(
+            "SELECT * FROM trading_pairs WHERE is_active = true ORDER BY symbol"
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut ladders = Vec::new();
+        let mut needed: BTreeMap<String, Decimal> = BTreeMap::new();
+        for pair in pairs {
+            let current = match market.prices.get(&pair.id) {
+                Some(price) => *price,
+                None => match self.opening_price(&pair).await? {
+                    Some(price) => price,
+                    None => continue,
+                },
+            };
+            let price = market.walk.step(current);
+            market.prices.insert(pair.id, price);
+
+            let quotes = self.ladder(&pair).quotes(price);
+            let taker_fee = pair.taker_fee.unwrap_or(Decimal::ZERO);
+            for quote in &quotes {
+                // Reserve what the order service will actually lock: the
+                // request carries the quantity as f64, and each lock is
+                // stored rounded to 8 places
+                let quantity = quote
+                    .quantity
+                    .to_f64()
+                    .and_then(Decimal::from_f64_retain)
+                    .unwrap_or(quote.quantity);
+                let (currency, amount) = match quote.side {
+                    OrderSide::Buy => (&pair.quote_currency, quote.price * quantity * (Decimal::ONE + taker_fee)),
+                    OrderSide::Sell => (&pair.base_currency, quantity),
+                };
+                *needed.entry(currency.clone()).or_default() +=
+                    amount.round_dp_with_strategy(8, RoundingStrategy::AwayFromZero);
+            }
+            ladders.push((pair.id, quotes));
+        }
+
+        // The house only ever holds what its current ladder needs, so fills
+        // against it never accumulate into an unbounded position
+        let held = sqlx::query_scalar::<_, String>("SELECT currency FROM accounts WHERE user_id = $1")
+            .bind(house_id)
+            .fetch_all(&self.db)
+            .await?;
+        for currency in held {
+            needed.entry(currency).or_default();
+        }
+        self.set_balances(house_id, &needed).await?;
+
+        let mut placed = 0;
+        for (trading_pair_id, quotes) in ladders {
+            for quote in quotes {
+                let request = CreateOrderRequest {
+                    trading_pair_id,
+                    order_type: OrderType::Limit,
+                    side: quote.side,
+                    quantity: quote.quantity.to_f64().unwrap_or_default(),
+                    price: Some(quote.price),
+                    time_in_force: Some(TimeInForce::GTC),
+                    stop_price: None,
+                    allow_duplicate: true,
+                    activate_at: None,
+                    lock_funds: false,
+                };
+                match self.order_service.create_order(house_id, request).await {
+                    Ok(_) => placed += 1,
+                    Err(e) => tracing::warn!("Sandbox quote on {} rejected: {}", trading_pair_id, e),
+                }
+            }
+        }
+
+        Ok(placed)
+    }
+
+    fn ladder(&self, pair: &TradingPair) -> QuoteLadder {
+        QuoteLadder {
+            levels: self.config.levels,
+            spread_bps: self.config.spread_bps,
+            level_step_bps: self.config.level_step_bps,
+            level_notional: self.config.level_notional,
+            price_precision: pair.price_precision.unwrap_or(8).max(0) as u32,
+            quantity_precision: pair.quantity_precision.unwrap_or(8).max(0) as u32,
+            min_quantity: pair.min_order_size.unwrap_or(Decimal::ZERO),
+        }
+    }
+
+    async fn opening_price(&self, pair: &TradingPair) -> Result<Option<Decimal>> {
+        if let Some(price) = self.config.reference_prices.get(&pair.symbol) {
+            return Ok(Some(*price));
+        }
+
+        let last_price = sqlx::query_scalar::<_, Decimal>(
+            "SELECT price FROM trades WHERE trading_pair_id = $1 ORDER BY created_at DESC LIMIT 1"
+        )
+        .bind(pair.id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(last_price)
+    }
+
+    async fn ensure_house_user(&self) -> Result<Uuid> {
+        let house_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO users (email, username, password_hash, is_verified, is_active)
+            VALUES ($1, $2, '!', true, true)
+            ON CONFLICT (email) DO UPDATE SET updated_at = NOW()
+            RETURNING id
+            "#
+        )
+        .bind(HOUSE_EMAIL)
+        .bind(HOUSE_USERNAME)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(house_id)
+    }
+
+    async fn cancel_open_orders(&self, user_id: Uuid) -> Result<()> {
+        let order_ids = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM orders WHERE user_id = $1 AND status IN ('open', 'partially_filled')"
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        for order_id in order_ids {
+            match self.order_service.cancel_order(user_id, order_id).await {
+                // Filled since it was listed
+                Ok(_) | Err(CryptoTradeError::OrderNotCancellable) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the available balance of each currency, leaving any amount still
+    /// locked by orders in place, and records the difference as an
+    /// adjustment against the exchange.
+    async fn set_balances(&self, user_id: Uuid, balances: &BTreeMap<String, Decimal>) -> Result<()> {
+        for (currency, available) in balances {
+            let previous = sqlx::query_scalar::<_, Option<Decimal>>(
+                "SELECT balance FROM accounts WHERE user_id = $1 AND currency = $2"
+            )
+            .bind(user_id)
+            .bind(currency)
+            .fetch_optional(&self.db)
+            .await?
+            .flatten()
+            .unwrap_or(Decimal::ZERO);
+
+            let balance = sqlx::query_scalar::<_, Decimal>(
+                r#"
+                INSERT INTO accounts (user_id, currency, balance, available_balance, locked_balance)
+                VALUES ($1, $2, $3, $3, 0)
+                ON CONFLICT (user_id, currency) DO UPDATE SET
+                    balance = EXCLUDED.available_balance + COALESCE(accounts.locked_balance, 0),
+                    available_balance = EXCLUDED.available_balance,
+                    updated_at = NOW()
+                RETURNING balance
+                "#
+            )
+            .bind(user_id)
+            .bind(currency)
+            .bind(available)
+            .fetch_one(&self.db)
+            .await?;
+
+            let delta = balance - previous;
+            if delta.is_zero() {
+                continue;
+            }
+            let postings = [
+                Posting {
+                    user_id: Some(user_id),
+                    currency: currency.clone(),
+                    entry_type: LedgerEntryType::Adjustment,
+                    amount: delta,
+                    balance_after: Some(balance),
+                },
+                Posting {
+                    user_id: None,
+                    currency: currency.clone(),
+                    entry_type: LedgerEntryType::Adjustment,
+                    amount: -delta,
+                    balance_after: None,
+                },
+            ];
+            self.ledger.post(Uuid::new_v4(), None, &postings).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{config::WebSocketConfig, interval::Interval, models::*, resilience::CircuitBreaker, Result};
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+const PUBLISH_QUEUE_CAPACITY: usize = 4096;
+const BROADCAST_CAPACITY: usize = 4096;
+
+/// Fans out feed messages to WebSocket connections.
+///
+/// Messages are sequenced per channel with a Redis counter and kept in a
+/// short Redis list, so a client that reconnects or falls behind can replay
+/// what it missed instead of resubscribing from scratch. A single publisher
+/// task assigns sequences, which keeps delivery order equal to sequence order.
+/// While Redis is unavailable messages cannot be sequenced and are dropped.
+#[derive(Clone)]
+pub struct StreamService {
+    redis: ConnectionManager,
+    breaker: CircuitBreaker,
+    publisher: mpsc::Sender<(String, serde_json::Value)>,
+    broadcaster: broadcast::Sender<StreamMessage>,
+}
+
+impl StreamService {
+    /// Spawns the publisher task; must be called from within a Tokio runtime.
+    pub fn new(redis: ConnectionManager, breaker: CircuitBreaker, config: &WebSocketConfig) -> Self {
+        let (publisher, receiver) = mpsc::channel(PUBLISH_QUEUE_CAPACITY);
+        let (broadcaster, _) = broadcast::channel(BROADCAST_CAPACITY);
+
+        tokio::spawn(run_publisher(
+            redis.clone(),
+            breaker.clone(),
+            receiver,
+            broadcaster.clone(),
+            config.clone(),
+        ));
+
+        Self {
+            redis,
+            breaker,
+            publisher,
+            broadcaster,
+        }
+    }
+
+    pub fn trades_channel(trading_pair_id: Uuid) -> String {
+        format!("trades:{}", trading_pair_id)
+    }
+
+    pub fn book_channel(trading_pair_id: Uuid) -> String {
+        format!("book:{}", trading_pair_id)
+    }
+
+    pub fn ticker_channel(trading_pair_id: Uuid) -> String {
+        format!("ticker:{}", trading_pair_id)
+    }
+
+    /// Live candles of one interval, e.g. `candles_5m:<uuid>`.
+    pub fn candles_channel(trading_pair_id: Uuid, interval: Interval) -> String {
+        format!("candles_{}:{}", interval, trading_pair_id)
+    }
+
+    /// Queues a message for sequencing and delivery without waiting on Redis,
+    /// so publishing never stalls the caller.
+    pub fn publish(&self, channel: String, data: serde_json::Value) {
+        if self.publisher.try_send((channel, data)).is_err() {
+            tracing::warn!("Stream publish queue is full; dropping message");
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StreamMessage> {
+        self.broadcaster.subscribe()
+    }
+
+    /// The sequence of the newest message published on `channel`, or 0.
+    pub async fn latest_sequence(&self, channel: &str) -> Result<i64> {
+        let latest: Option<i64> = self
+            .breaker
+            .call(|| {
+                let mut redis = self.redis.clone();
+                async move { redis.get(sequence_key(channel)).await }
+            })
+            .await?;
+        Ok(latest.unwrap_or(0))
+    }
+
+    /// Returns the buffered messages of `channel` from `from_sequence` on, or
+    /// `None` when the buffer no longer reaches back that far.
+    pub async fn resync(&self, channel: &str, from_sequence: i64) -> Result<Option<Vec<StreamMessage>>> {
+        if from_sequence > self.latest_sequence(channel).await? {
+            return Ok(Some(Vec::new()));
+        }
+
+        let buffered: Vec<String> = self
+            .breaker
+            .call(|| {
+                let mut redis = self.redis.clone();
+                async move { redis.lrange(buffer_key(channel), 0, -1).await }
+            })
+            .await?;
+        let mut messages = buffered
+            .iter()
+            .map(|payload| serde_json::from_str::<StreamMessage>(payload))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        messages.sort_by_key(|message| message.sequence);
+
+        match messages.first() {
+            Some(oldest) if oldest.sequence <= from_sequence => {
+                messages.retain(|message| message.sequence >= from_sequence);
+                Ok(Some(messages))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+async fn run_publisher(
+    mut redis: ConnectionManager,
+    breaker: CircuitBreaker,
+    mut receiver: mpsc::Receiver<(String, serde_json::Value)>,
+    broadcaster: broadcast::Sender<StreamMessage>,
+    config: WebSocketConfig,
+) {
+    while let Some((channel, data)) = receiver.recv().await {
+        match sequence_and_buffer(&mut redis, &breaker, &config, channel, data).await {
+            // No receivers just means nobody is connected
+            Ok(message) => {
+                let _ = broadcaster.send(message);
+            }
+            Err(e) => tracing::error!("Failed to sequence stream message: {}", e),
+        }
+    }
+}
+
+async fn sequence_and_buffer(
+    redis: &mut ConnectionManager,
+    breaker: &CircuitBreaker,
+    config: &WebSocketConfig,
+    channel: String,
+    data: serde_json::Value,
+) -> Result<StreamMessage> {
+    let sequence: i64 = breaker.call_once(redis.incr(sequence_key(&channel), 1)).await?;
+
+    let message = StreamMessage {
+        channel,
+        sequence,
+        data,
+        timestamp: Utc::now(),
+    };
+
+    let key = buffer_key(&message.channel);
+    breaker
+        .call_once(
+            redis::pipe()
+                .atomic()
+                .rpush(&key, serde_json::to_string(&message)?)
+                .ignore()
+                .ltrim(&key, -(config.replay_buffer_size as isize), -1)
+                .ignore()
+                .expire(&key, config.replay_buffer_ttl_seconds as i64)
+                .ignore()
+                .query_async::<_, ()>(redis),
+        )
+        .await?;
+
+    Ok(message)
+}
+
+fn sequence_key(channel: &str) -> String {
+    format!("ws:seq:{}", channel)
+}
+
+fn buffer_key(channel: &str) -> String {
+    format!("ws:buffer:{}", channel)
+}
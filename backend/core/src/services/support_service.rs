@@ -0,0 +1,334 @@
+use crate::{
+    config::SupportConfig,
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    services::outbox_service::{enqueue_event, DomainEvent},
+    storage::{self, ObjectStore, PresignedUrl},
+    Result,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use validator::Validate;
+
+/// File types a ticket may carry, by extension
+const ATTACHMENT_EXTENSIONS: [&str; 4] = ["pdf", "png", "jpg", "jpeg"];
+
+/// Who is acting on a ticket: its user, who only sees their own, or support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TicketActor {
+    User(Uuid),
+    Staff(Uuid),
+}
+
+impl TicketActor {
+    fn id(&self) -> Uuid {
+        match self {
+            Self::User(id) | Self::Staff(id) => *id,
+        }
+    }
+
+    fn is_staff(&self) -> bool {
+        matches!(self, Self::Staff(_))
+    }
+}
+
+/// Support tickets (see migration 050). Users open tickets and reply on
+/// them; support replies, sees the account the ticket is about, and
+/// resolves them. Every change is published as
+/// [`DomainEvent::SupportTicketUpdated`] for whatever notifies the other
+/// side. Attachments are kept in object storage.
+#[derive(Clone)]
+pub struct SupportService {
+    db: Database,
+    storage: Arc<dyn ObjectStore>,
+    presign_ttl: Duration,
+    config: SupportConfig,
+}
+
+impl SupportService {
+    pub fn new(db: Database, storage: Arc<dyn ObjectStore>, presign_ttl: Duration, config: SupportConfig) -> Self {
+        Self {
+            db,
+            storage,
+            presign_ttl,
+            config,
+        }
+    }
+
+    pub async fn open_ticket(&self, user_id: Uuid, request: CreateTicketRequest) -> Result<SupportTicketDetail> {
+        request.validate().map_err(|e| CryptoTradeError::Validation {
+            message: e.to_string(),
+        })?;
+
+        let mut tx = self.db.begin().await?;
+        let ticket = sqlx::query_as::<_, SupportTicket>(
+            "INSERT INTO support_tickets (user_id, category, subject) VALUES ($1, $2, $3) RETURNING *",
+        )
+        .bind(user_id)
+        .bind(request.category)
+        .bind(request.subject.trim())
+        .fetch_one(&mut *tx)
+        .await?;
+        let message = sqlx::query_as::<_, TicketMessage>(
+            "INSERT INTO ticket_messages (ticket_id, author_id, from_staff, body) VALUES ($1, $2, false, $3) RETURNING *",
+        )
+        .bind(ticket.id)
+        .bind(user_id)
+        .bind(request.message.trim())
+        .fetch_one(&mut *tx)
+        .await?;
+        enqueue_event(
+            &mut *tx,
+            &DomainEvent::SupportTicketUpdated {
+                ticket: ticket.clone(),
+                from_staff: false,
+            },
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(SupportTicketDetail {
+            ticket,
+            messages: vec![message],
+            attachments: Vec::new(),
+            account: None,
+        })
+    }
+
+    /// The user's tickets, newest first.
+    pub async fn list_tickets(&self, user_id: Uuid) -> Result<Vec<SupportTicket>> {
+        sqlx::query_as::<_, SupportTicket>("SELECT * FROM support_tickets WHERE user_id = $1 ORDER BY created_at DESC")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Tickets in `status` for support, longest waiting first; by default
+    /// those waiting on support.
+    pub async fn queue(&self, status: Option<TicketStatus>) -> Result<Vec<SupportTicket>> {
+        sqlx::query_as::<_, SupportTicket>("SELECT * FROM support_tickets WHERE status = $1 ORDER BY updated_at")
+            .bind(status.unwrap_or(TicketStatus::Open))
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// The ticket with its conversation, and for support the account it is
+    /// about.
+    pub async fn ticket(&self, actor: TicketActor, ticket_id: Uuid) -> Result<SupportTicketDetail> {
+        let ticket = self.find_ticket(actor, ticket_id).await?;
+        let messages = sqlx::query_as::<_, TicketMessage>(
+            "SELECT * FROM ticket_messages WHERE ticket_id = $1 ORDER BY created_at, id",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.db)
+        .await?;
+        let attachments = sqlx::query_as::<_, TicketAttachment>(
+            "SELECT * FROM ticket_attachments WHERE ticket_id = $1 ORDER BY created_at, id",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.db)
+        .await?;
+        let account = match actor {
+            TicketActor::Staff(_) => Some(
+                sqlx::query_as::<_, TicketAccountContext>(
+                    r#"
+                    SELECT u.id AS user_id, u.email, u.username, u.account_status, u.kyc_status, u.two_fa_enabled,
+                           u.country, u.tenant_id, u.created_at AS registered_at,
+                           (SELECT COUNT(*) FROM support_tickets t WHERE t.user_id = u.id AND t.id <> $2 AND t.status <> 'resolved') AS other_open_tickets
+                    FROM users u WHERE u.id = $1
+                    "#,
+                )
+                .bind(ticket.user_id)
+                .bind(ticket.id)
+                .fetch_one(&self.db)
+                .await?,
+            ),
+            TicketActor::User(_) => None,
+        };
+
+        Ok(SupportTicketDetail {
+            ticket,
+            messages,
+            attachments,
+            account,
+        })
+    }
+
+    /// Adds a message. A user's reopens the ticket for support; support's
+    /// leaves it pending on the user.
+    pub async fn reply(&self, actor: TicketActor, ticket_id: Uuid, request: TicketReplyRequest) -> Result<TicketMessage> {
+        request.validate().map_err(|e| CryptoTradeError::Validation {
+            message: e.to_string(),
+        })?;
+        self.find_ticket(actor, ticket_id).await?;
+
+        let status = if actor.is_staff() { TicketStatus::Pending } else { TicketStatus::Open };
+        let mut tx = self.db.begin().await?;
+        let message = sqlx::query_as::<_, TicketMessage>(
+            "INSERT INTO ticket_messages (ticket_id, author_id, from_staff, body) VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(ticket_id)
+        .bind(actor.id())
+        .bind(actor.is_staff())
+        .bind(request.body.trim())
+        .fetch_one(&mut *tx)
+        .await?;
+        let ticket = sqlx::query_as::<_, SupportTicket>(
+            "UPDATE support_tickets SET status = $2, resolved_at = NULL, updated_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(ticket_id)
+        .bind(status)
+        .fetch_one(&mut *tx)
+        .await?;
+        enqueue_event(
+            &mut *tx,
+            &DomainEvent::SupportTicketUpdated {
+                ticket,
+                from_staff: actor.is_staff(),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(message)
+    }
+
+    /// Moves a ticket through its lifecycle on support's say.
+    pub async fn set_status(&self, ticket_id: Uuid, status: TicketStatus) -> Result<SupportTicket> {
+        let mut tx = self.db.begin().await?;
+        let ticket = sqlx::query_as::<_, SupportTicket>(
+            r#"
+            UPDATE support_tickets SET
+                status = $2,
+                resolved_at = CASE WHEN $2 = 'resolved'::ticket_status THEN COALESCE(resolved_at, NOW()) END,
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(ticket_id)
+        .bind(status)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(ticket_not_found)?;
+        enqueue_event(
+            &mut *tx,
+            &DomainEvent::SupportTicketUpdated {
+                ticket: ticket.clone(),
+                from_staff: true,
+            },
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(ticket)
+    }
+
+    /// Stores a file on the ticket. Only PDFs and PNG or JPEG images are
+    /// taken, up to the configured size and count.
+    pub async fn attach(&self, actor: TicketActor, ticket_id: Uuid, file_name: &str, body: Vec<u8>) -> Result<TicketAttachment> {
+        self.find_ticket(actor, ticket_id).await?;
+        let file_name = file_name.trim();
+        let extension = attachment_extension(file_name).ok_or_else(|| CryptoTradeError::Validation {
+            message: format!("Attachments must be one of: {}", ATTACHMENT_EXTENSIONS.join(", ")),
+        })?;
+        if body.is_empty() || body.len() > self.config.max_attachment_bytes {
+            return Err(CryptoTradeError::Validation {
+                message: format!("Attachments must be between 1 and {} bytes", self.config.max_attachment_bytes),
+            });
+        }
+        let attached = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM ticket_attachments WHERE ticket_id = $1")
+            .bind(ticket_id)
+            .fetch_one(&self.db)
+            .await?;
+        if attached >= self.config.max_attachments_per_ticket {
+            return Err(CryptoTradeError::Validation {
+                message: format!("A ticket can carry at most {} attachments", self.config.max_attachments_per_ticket),
+            });
+        }
+
+        let attachment_id = Uuid::new_v4();
+        let key = format!("support/{}/{}.{}", ticket_id, attachment_id, extension);
+        let content_type = storage::content_type(&key);
+        let size = body.len() as i64;
+        self.storage.put(&key, body, content_type).await?;
+
+        sqlx::query_as::<_, TicketAttachment>(
+            r#"
+            INSERT INTO ticket_attachments (id, ticket_id, uploaded_by, file_name, content_type, size_bytes, storage_key)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *
+            "#,
+        )
+        .bind(attachment_id)
+        .bind(ticket_id)
+        .bind(actor.id())
+        .bind(file_name.chars().take(255).collect::<String>())
+        .bind(content_type)
+        .bind(size)
+        .bind(&key)
+        .fetch_one(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+
+    /// A short-lived download URL for an attachment.
+    pub async fn attachment_url(&self, actor: TicketActor, ticket_id: Uuid, attachment_id: Uuid) -> Result<PresignedUrl> {
+        self.find_ticket(actor, ticket_id).await?;
+        let key = sqlx::query_scalar::<_, String>(
+            "SELECT storage_key FROM ticket_attachments WHERE id = $1 AND ticket_id = $2",
+        )
+        .bind(attachment_id)
+        .bind(ticket_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| CryptoTradeError::NotFound {
+            message: "Attachment not found".to_string(),
+        })?;
+
+        Ok(storage::presign(self.storage.as_ref(), &key, self.presign_ttl))
+    }
+
+    async fn find_ticket(&self, actor: TicketActor, ticket_id: Uuid) -> Result<SupportTicket> {
+        let owner = match actor {
+            TicketActor::User(user_id) => Some(user_id),
+            TicketActor::Staff(_) => None,
+        };
+        sqlx::query_as::<_, SupportTicket>("SELECT * FROM support_tickets WHERE id = $1 AND ($2::uuid IS NULL OR user_id = $2)")
+            .bind(ticket_id)
+            .bind(owner)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(ticket_not_found)
+    }
+}
+
+/// The lower case extension of an allowed file name.
+fn attachment_extension(file_name: &str) -> Option<String> {
+    let (_, extension) = file_name.rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    ATTACHMENT_EXTENSIONS.contains(&extension.as_str()).then_some(extension)
+}
+
+fn ticket_not_found() -> CryptoTradeError {
+    CryptoTradeError::NotFound {
+        message: "Ticket not found".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_documents_and_images_can_be_attached() {
+        assert_eq!(attachment_extension("statement.PDF").as_deref(), Some("pdf"));
+        assert_eq!(attachment_extension("screen shot.final.jpeg").as_deref(), Some("jpeg"));
+        assert_eq!(attachment_extension("script.exe"), None);
+        assert_eq!(attachment_extension("pdf"), None);
+    }
+}
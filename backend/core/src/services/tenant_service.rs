@@ -0,0 +1,268 @@
+use crate::{database::Database, error::CryptoTradeError, models::*, Result};
+use rust_decimal::Decimal;
+use sqlx::PgExecutor;
+use std::collections::HashSet;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Highest fee a tenant's schedule may charge on either side
+const MAX_TENANT_FEE: Decimal = Decimal::from_parts(1, 0, 0, false, 1);
+
+/// White-label brokers (see migration 046). Isolation is enforced here and
+/// in the services that call the helpers below: a tenant's users see and
+/// trade only shared pairs and the tenant's own, pay its fee schedule, and
+/// hold API keys in its namespace.
+#[derive(Clone)]
+pub struct TenantService {
+    db: Database,
+}
+
+impl TenantService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub async fn list_tenants(&self) -> Result<Vec<Tenant>> {
+        sqlx::query_as::<_, Tenant>("SELECT * FROM tenants ORDER BY slug")
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// The tenant a white-label front end names by slug.
+    pub async fn get_by_slug(&self, slug: &str) -> Result<Tenant> {
+        tenant_by_slug(&self.db, slug).await
+    }
+
+    /// The tenant `user_id` registered through; `None` for the exchange's
+    /// own users.
+    pub async fn user_tenant_id(&self, user_id: Uuid) -> Result<Option<Uuid>> {
+        Ok(sqlx::query_scalar::<_, Option<Uuid>>("SELECT tenant_id FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .flatten())
+    }
+
+    /// Fails as if the pair did not exist when it is private to a tenant
+    /// other than `tenant_id`. Unknown pairs pass, for the caller to report.
+    pub async fn ensure_pair_visible_to(&self, trading_pair_id: Uuid, tenant_id: Option<Uuid>) -> Result<()> {
+        let pair_tenant = sqlx::query_scalar::<_, Option<Uuid>>("SELECT tenant_id FROM trading_pairs WHERE id = $1")
+            .bind(trading_pair_id)
+            .fetch_optional(&self.db)
+            .await?
+            .flatten();
+        match pair_tenant {
+            Some(pair_tenant) if Some(pair_tenant) != tenant_id => Err(CryptoTradeError::TradingPairNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    /// Pairs private to tenants other than `tenant_id`, for listings that
+    /// cannot filter in their own query.
+    pub async fn hidden_pair_ids(&self, tenant_id: Option<Uuid>) -> Result<HashSet<Uuid>> {
+        let ids = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM trading_pairs WHERE tenant_id IS NOT NULL AND tenant_id IS DISTINCT FROM $1",
+        )
+        .bind(tenant_id)
+        .fetch_all(&self.db)
+        .await?;
+        Ok(ids.into_iter().collect())
+    }
+
+    pub async fn create_tenant(&self, request: CreateTenantRequest) -> Result<Tenant> {
+        request.validate().map_err(|e| CryptoTradeError::Validation {
+            message: e.to_string(),
+        })?;
+        let slug = request.slug.trim().to_lowercase();
+        if !slug.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(CryptoTradeError::Validation {
+                message: "Tenant slug must be letters, digits and dashes only".to_string(),
+            });
+        }
+        let prefix = request.api_key_prefix.trim().to_lowercase();
+        if !prefix.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(CryptoTradeError::Validation {
+                message: "API key prefix must be letters and digits only".to_string(),
+            });
+        }
+        validate_fees(request.maker_fee, request.taker_fee)?;
+        validate_color(request.primary_color.as_deref())?;
+
+        sqlx::query_as::<_, Tenant>(
+            r#"
+            INSERT INTO tenants (slug, name, api_key_prefix, maker_fee, taker_fee, display_name, logo_url, primary_color, support_email)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT DO NOTHING
+            RETURNING *
+            "#,
+        )
+        .bind(&slug)
+        .bind(request.name.trim())
+        .bind(&prefix)
+        .bind(request.maker_fee)
+        .bind(request.taker_fee)
+        .bind(&request.display_name)
+        .bind(&request.logo_url)
+        .bind(&request.primary_color)
+        .bind(&request.support_email)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| CryptoTradeError::Validation {
+            message: format!("A tenant with slug {} or API key prefix {} already exists", slug, prefix),
+        })
+    }
+
+    pub async fn update_tenant(&self, tenant_id: Uuid, request: UpdateTenantRequest) -> Result<Tenant> {
+        request.validate().map_err(|e| CryptoTradeError::Validation {
+            message: e.to_string(),
+        })?;
+        validate_fees(request.maker_fee, request.taker_fee)?;
+        validate_color(request.primary_color.as_deref())?;
+
+        sqlx::query_as::<_, Tenant>(
+            r#"
+            UPDATE tenants SET
+                name = $2,
+                maker_fee = $3,
+                taker_fee = $4,
+                display_name = $5,
+                logo_url = $6,
+                primary_color = $7,
+                support_email = $8,
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(request.name.trim())
+        .bind(request.maker_fee)
+        .bind(request.taker_fee)
+        .bind(&request.display_name)
+        .bind(&request.logo_url)
+        .bind(&request.primary_color)
+        .bind(&request.support_email)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(tenant_not_found)
+    }
+
+    /// Makes the pair private to `tenant_id`, or shared with everyone when
+    /// `None`. Orders already resting on it are left alone.
+    pub async fn set_pair_tenant(&self, trading_pair_id: Uuid, tenant_id: Option<Uuid>) -> Result<TradingPair> {
+        if let Some(tenant_id) = tenant_id {
+            sqlx::query_scalar::<_, Uuid>("SELECT id FROM tenants WHERE id = $1")
+                .bind(tenant_id)
+                .fetch_optional(&self.db)
+                .await?
+                .ok_or_else(tenant_not_found)?;
+        }
+
+        sqlx::query_as::<_, TradingPair>("UPDATE trading_pairs SET tenant_id = $2 WHERE id = $1 RETURNING *")
+            .bind(trading_pair_id)
+            .bind(tenant_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)
+    }
+}
+
+pub(crate) async fn tenant_by_slug<'e>(executor: impl PgExecutor<'e>, slug: &str) -> Result<Tenant> {
+    sqlx::query_as::<_, Tenant>("SELECT * FROM tenants WHERE slug = $1")
+        .bind(slug.trim().to_lowercase())
+        .fetch_optional(executor)
+        .await?
+        .ok_or_else(tenant_not_found)
+}
+
+/// The tenant `user_id` belongs to, if any.
+pub(crate) async fn tenant_of<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Option<Tenant>> {
+    sqlx::query_as::<_, Tenant>("SELECT t.* FROM tenants t JOIN users u ON u.tenant_id = t.id WHERE u.id = $1")
+        .bind(user_id)
+        .fetch_optional(executor)
+        .await
+        .map_err(Into::into)
+}
+
+/// `trading_pair` as `user_id` trades it: with their tenant's fee schedule
+/// in place of the pair's own fees where it sets them.
+pub(crate) async fn with_fee_schedule<'e>(
+    executor: impl PgExecutor<'e>,
+    mut trading_pair: TradingPair,
+    user_id: Uuid,
+) -> Result<TradingPair> {
+    if let Some(tenant) = tenant_of(executor, user_id).await? {
+        trading_pair.maker_fee = tenant.maker_fee.or(trading_pair.maker_fee);
+        trading_pair.taker_fee = tenant.taker_fee.or(trading_pair.taker_fee);
+    }
+    Ok(trading_pair)
+}
+
+/// Fails as if the pair did not exist when it is private to a tenant the
+/// user does not belong to.
+pub(crate) async fn ensure_pair_visible<'e>(executor: impl PgExecutor<'e>, trading_pair: &TradingPair, user_id: Uuid) -> Result<()> {
+    let Some(pair_tenant) = trading_pair.tenant_id else {
+        return Ok(());
+    };
+    let user_tenant = sqlx::query_scalar::<_, Option<Uuid>>("SELECT tenant_id FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(executor)
+        .await?
+        .flatten();
+    if user_tenant != Some(pair_tenant) {
+        return Err(CryptoTradeError::TradingPairNotFound);
+    }
+    Ok(())
+}
+
+fn tenant_not_found() -> CryptoTradeError {
+    CryptoTradeError::NotFound {
+        message: "Tenant not found".to_string(),
+    }
+}
+
+fn validate_fees(maker_fee: Option<Decimal>, taker_fee: Option<Decimal>) -> Result<()> {
+    for fee in [maker_fee, taker_fee].into_iter().flatten() {
+        if fee.is_sign_negative() || fee > MAX_TENANT_FEE {
+            return Err(CryptoTradeError::Validation {
+                message: format!("Tenant fees must be between 0 and {}", MAX_TENANT_FEE),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn validate_color(color: Option<&str>) -> Result<()> {
+    let Some(color) = color else {
+        return Ok(());
+    };
+    let valid = color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !valid {
+        return Err(CryptoTradeError::Validation {
+            message: "primary_color must be #rrggbb".to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_fees_are_capped() {
+        assert!(validate_fees(Some(Decimal::new(5, 4)), None).is_ok());
+        assert!(validate_fees(None, Some(MAX_TENANT_FEE)).is_ok());
+        assert!(validate_fees(Some(Decimal::new(-1, 4)), None).is_err());
+        assert!(validate_fees(None, Some(Decimal::new(11, 2))).is_err());
+    }
+
+    #[test]
+    fn test_primary_color_must_be_hex() {
+        assert!(validate_color(None).is_ok());
+        assert!(validate_color(Some("#1a2B3c")).is_ok());
+        assert!(validate_color(Some("1a2b3c")).is_err());
+        assert!(validate_color(Some("#1a2b3g")).is_err());
+    }
+}
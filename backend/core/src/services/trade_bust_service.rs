@@ -0,0 +1,204 @@
+use crate::{
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    services::{
+        ledger_service::{LedgerService, Posting},
+        order_service::record_order_event,
+        StreamService,
+    },
+    Result,
+};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// Reverses erroneous trades on an admin's say-so. A bust posts the trade's
+/// ledger journal again with every amount negated, takes the fill off both
+/// orders, marks the trade busted and records who did it and why in the
+/// audit log, all in one transaction. The busted quantity is not offered
+/// again: an order the trade had filled ends cancelled.
+#[derive(Clone)]
+pub struct TradeBustService {
+    db: Database,
+    ledger: LedgerService,
+    stream: StreamService,
+}
+
+impl TradeBustService {
+    pub fn new(db: Database, stream: StreamService) -> Self {
+        Self {
+            ledger: LedgerService::new(db.clone()),
+            db,
+            stream,
+        }
+    }
+
+    pub async fn bust(&self, admin_id: Uuid, trade_id: Uuid, request: BustTradeRequest) -> Result<TradeBust> {
+        let reason = request.reason.trim();
+        if reason.is_empty() {
+            return Err(CryptoTradeError::Validation {
+                message: "A reason is required".to_string(),
+            });
+        }
+
+        let mut tx = self.db.begin().await?;
+        let trade = sqlx::query_as::<_, Trade>("SELECT * FROM trades WHERE id = $1 FOR UPDATE")
+            .bind(trade_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| CryptoTradeError::NotFound {
+                message: "Trade not found".to_string(),
+            })?;
+        if trade.busted_at.is_some() {
+            return Err(CryptoTradeError::Validation {
+                message: "Trade is already busted".to_string(),
+            });
+        }
+
+        let journal_id = Uuid::new_v4();
+        let postings = self.reverse_settlement(&mut tx, &trade).await?;
+        self.ledger.post_in(&mut tx, journal_id, Some(trade.id), &postings).await?;
+
+        let price = trade.price.unwrap_or(Decimal::ZERO);
+        let quantity = trade.quantity.unwrap_or(Decimal::ZERO);
+        for order_id in [trade.buyer_order_id, trade.seller_order_id] {
+            remove_fill(&mut tx, order_id, price, quantity).await?;
+            record_order_event(&mut *tx, order_id, OrderEventType::FillBusted, Some(admin_id), Some(trade.id), Some(reason)).await?;
+        }
+        // The reversal handed the fill's spend back as available, so a
+        // market buy's lock no longer holds it
+        sqlx::query("UPDATE orders SET locked_amount = locked_amount - $2 WHERE id = $1 AND order_type = 'market'")
+            .bind(trade.buyer_order_id)
+            .bind(price * quantity + trade.buyer_fee.unwrap_or(Decimal::ZERO))
+            .execute(&mut *tx)
+            .await?;
+
+        let trade = sqlx::query_as::<_, Trade>("UPDATE trades SET busted_at = $2 WHERE id = $1 RETURNING *")
+            .bind(trade.id)
+            .bind(Utc::now())
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO audit_log (actor_id, action, target_id, reason, details) VALUES ($1, 'trade_bust', $2, $3, $4)")
+            .bind(admin_id)
+            .bind(trade.id)
+            .bind(reason)
+            .bind(json!({
+                "trading_pair_id": trade.trading_pair_id,
+                "journal_id": journal_id,
+                "price": price,
+                "quantity": quantity,
+                "buyer_order_id": trade.buyer_order_id,
+                "seller_order_id": trade.seller_order_id,
+            }))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        tracing::info!("Admin {} busted trade {}: {}", admin_id, trade.id, reason);
+
+        self.publish(&trade, price, quantity);
+        Ok(TradeBust {
+            trade,
+            journal_id,
+            reason: reason.to_string(),
+            busted_by: admin_id,
+        })
+    }
+
+    /// Undoes each balance change of the trade's settlement journal and
+    /// returns the postings recording that. Amounts taken from locked funds
+    /// come back as available, since the orders no longer need them.
+    async fn reverse_settlement(&self, conn: &mut PgConnection, trade: &Trade) -> Result<Vec<Posting>> {
+        let entries = sqlx::query_as::<_, (Option<Uuid>, String, LedgerEntryType, Decimal)>(
+            "SELECT user_id, currency, entry_type, amount FROM ledger_entries WHERE journal_id = $1 ORDER BY sequence",
+        )
+        .bind(trade.id)
+        .fetch_all(&mut *conn)
+        .await?;
+        if entries.is_empty() {
+            return Err(CryptoTradeError::Validation {
+                message: "Trade has no settlement journal to reverse".to_string(),
+            });
+        }
+
+        let mut postings = Vec::with_capacity(entries.len());
+        for (user_id, currency, entry_type, amount) in entries {
+            let amount = -amount;
+            let balance_after = match user_id {
+                Some(user_id) => sqlx::query_scalar::<_, Option<Decimal>>(
+                    "UPDATE accounts SET balance = balance + $1, available_balance = available_balance + $1, updated_at = NOW() WHERE user_id = $2 AND currency = $3 RETURNING balance",
+                )
+                .bind(amount)
+                .bind(user_id)
+                .bind(&currency)
+                .fetch_optional(&mut *conn)
+                .await?
+                .flatten(),
+                None => Some(self.ledger.refund_fees_in(&mut *conn, &currency, -amount).await?),
+            };
+            if balance_after.is_some_and(|balance| balance < Decimal::ZERO) {
+                tracing::warn!("Busting trade {} left user {:?} with a negative {} balance", trade.id, user_id, currency);
+            }
+            postings.push(Posting {
+                user_id,
+                currency,
+                entry_type,
+                amount,
+                balance_after,
+            });
+        }
+
+        Ok(postings)
+    }
+
+    fn publish(&self, trade: &Trade, price: Decimal, quantity: Decimal) {
+        let (Some(traded_at), Some(busted_at)) = (trade.created_at, trade.busted_at) else {
+            return;
+        };
+        let bust = PublicTradeBust {
+            trading_pair_id: trade.trading_pair_id,
+            trade_id: trade.id,
+            price,
+            quantity,
+            taker_side: trade.taker_side,
+            traded_at,
+            busted_at,
+        };
+        match serde_json::to_value(&bust) {
+            Ok(data) => self.stream.publish(StreamService::trades_channel(trade.trading_pair_id), data),
+            Err(e) => tracing::error!("Failed to encode bust of trade {}: {}", trade.id, e),
+        }
+    }
+}
+
+/// Takes a busted fill off an order. An order the fill had completed is
+/// cancelled; one still on the book keeps resting with what it had left.
+async fn remove_fill(conn: &mut PgConnection, order_id: Uuid, price: Decimal, quantity: Decimal) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE orders SET
+            filled_quantity = filled_quantity - $2,
+            cumulative_quote_quantity = COALESCE(cumulative_quote_quantity, 0) - $2 * $3,
+            average_fill_price = CASE WHEN filled_quantity - $2 > 0
+                THEN (COALESCE(cumulative_quote_quantity, 0) - $2 * $3) / (filled_quantity - $2)
+            END,
+            status = CASE
+                WHEN status = 'filled' THEN 'cancelled'::order_status
+                WHEN status = 'partially_filled' AND filled_quantity - $2 <= 0 THEN 'open'::order_status
+                ELSE status
+            END,
+            version = version + 1,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(order_id)
+    .bind(quantity)
+    .bind(price)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,412 @@
+use crate::{database::Database, error::CryptoTradeError, models::*, services::OrderService, Result};
+use chrono::{DateTime, Datelike, Utc};
+use rust_decimal::Decimal;
+use sqlx::Row;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Admin-managed settings of trading pairs, their lifecycle and their
+/// trading hours.
+#[derive(Clone)]
+pub struct TradingPairService {
+    db: Database,
+    order_service: OrderService,
+}
+
+impl TradingPairService {
+    pub fn new(db: Database, order_service: OrderService) -> Self {
+        Self { db, order_service }
+    }
+
+    /// Sets how far from the last trade limit prices on the pair may be, or
+    /// removes the band with `None`.
+    pub async fn set_price_band(&self, trading_pair_id: Uuid, price_band_percent: Option<Decimal>) -> Result<TradingPair> {
+        if let Some(percent) = price_band_percent {
+            if percent <= Decimal::ZERO || percent > Decimal::ONE_HUNDRED {
+                return Err(CryptoTradeError::Validation {
+                    message: "Price band must be above 0 and at most 100 percent".to_string(),
+                });
+            }
+        }
+
+        sqlx::query_as::<_, TradingPair>("UPDATE trading_pairs SET price_band_percent = $1 WHERE id = $2 RETURNING *")
+            .bind(price_band_percent)
+            .bind(trading_pair_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)
+    }
+
+    /// Moves the pair through its lifecycle on an admin's decision.
+    ///
+    /// Entering a call auction stops matching on the book; leaving it for
+    /// trading uncrosses the accumulated orders at a single opening price.
+    /// Delisting cancels every open order on the pair, releasing what they
+    /// locked, and then completes to delisted; asking for delisted retries a
+    /// delisting that did not complete.
+    pub async fn set_status(&self, admin_id: Uuid, trading_pair_id: Uuid, to: TradingPairStatus) -> Result<TradingPairTransition> {
+        let from = self.get_trading_pair(trading_pair_id).await?.status;
+        if !from.can_become(to) {
+            return Err(CryptoTradeError::Validation {
+                message: format!("Trading pair cannot move from {:?} to {:?}", from, to),
+            });
+        }
+
+        self.transition(trading_pair_id, from, to, Some(admin_id)).await
+    }
+
+    /// The id of the pair listed as `symbol`, such as `BTC-USD`, in any
+    /// case.
+    pub async fn pair_id(&self, symbol: &str) -> Result<Uuid> {
+        sqlx::query_scalar::<_, Uuid>("SELECT id FROM trading_pairs WHERE symbol = $1")
+            .bind(symbol.to_ascii_uppercase())
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)
+    }
+
+    /// Every pair not delisted that `tenant_id` can trade (the shared ones,
+    /// plus its own), with its trading hours, by symbol.
+    pub async fn list_trading_pairs(&self, tenant_id: Option<Uuid>) -> Result<Vec<TradingPairListing>> {
+        let pairs = sqlx::query_as::<_, TradingPair>(
+            "SELECT * FROM trading_pairs WHERE status <> 'delisted' AND (tenant_id IS NULL OR tenant_id = $1) ORDER BY symbol",
+        )
+        .bind(tenant_id)
+        .fetch_all(&self.db)
+        .await?;
+        let mut sessions = self.sessions_by_pair(None).await?;
+
+        let now = Utc::now();
+        Ok(pairs
+            .into_iter()
+            .map(|trading_pair| {
+                let sessions = sessions.remove(&trading_pair.id).unwrap_or_default();
+                TradingPairListing {
+                    in_session: in_session(&sessions, now),
+                    trading_pair,
+                    sessions,
+                }
+            })
+            .collect())
+    }
+
+    /// Replaces the pair's trading sessions. Sessions on the same weekday
+    /// must not overlap.
+    pub async fn set_sessions(&self, trading_pair_id: Uuid, mut sessions: Vec<TradingSession>) -> Result<TradingPairListing> {
+        sessions.sort_by_key(|session| (session.weekday, session.opens_at));
+        validate_sessions(&sessions)?;
+
+        let mut tx = self.db.begin().await?;
+        let trading_pair = sqlx::query_as::<_, TradingPair>("SELECT * FROM trading_pairs WHERE id = $1 FOR UPDATE")
+            .bind(trading_pair_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)?;
+        sqlx::query("DELETE FROM trading_sessions WHERE trading_pair_id = $1")
+            .bind(trading_pair_id)
+            .execute(&mut *tx)
+            .await?;
+        for session in &sessions {
+            sqlx::query("INSERT INTO trading_sessions (trading_pair_id, weekday, opens_at, closes_at) VALUES ($1, $2, $3, $4)")
+                .bind(trading_pair_id)
+                .bind(session.weekday)
+                .bind(session.opens_at)
+                .bind(session.closes_at)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(TradingPairListing {
+            in_session: in_session(&sessions, Utc::now()),
+            trading_pair,
+            sessions,
+        })
+    }
+
+    /// Halts trading pairs whose session has ended, and reopens the ones the
+    /// schedule halted through a call auction once their next session
+    /// starts. Pairs an admin halted stay halted. Returns how many pairs
+    /// moved.
+    pub async fn apply_session_schedules(&self) -> Result<u32> {
+        let sessions = self.sessions_by_pair(None).await?;
+        let now = Utc::now();
+        let mut moved = 0;
+
+        for (trading_pair_id, sessions) in sessions {
+            let open = in_session(&sessions, now);
+            let status = self.get_trading_pair(trading_pair_id).await?.status;
+
+            let result = match status {
+                TradingPairStatus::Trading if !open => self
+                    .transition(trading_pair_id, status, TradingPairStatus::Halt, None)
+                    .await
+                    .map(|_| true),
+                TradingPairStatus::Halt if open && self.halted_by_schedule(trading_pair_id).await? => {
+                    let reopened = async {
+                        self.transition(trading_pair_id, status, TradingPairStatus::CallAuction, None).await?;
+                        self.transition(trading_pair_id, TradingPairStatus::CallAuction, TradingPairStatus::Trading, None)
+                            .await
+                    };
+                    reopened.await.map(|_| true)
+                }
+                _ => Ok(false),
+            };
+
+            match result {
+                Ok(true) => moved += 1,
+                Ok(false) => {}
+                // An admin moved the pair in the meantime
+                Err(CryptoTradeError::Conflict { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(moved)
+    }
+
+    /// Applies an allowed move and whatever it sets off. `changed_by` is
+    /// the admin, or `None` for the session schedule.
+    async fn transition(
+        &self,
+        trading_pair_id: Uuid,
+        from: TradingPairStatus,
+        to: TradingPairStatus,
+        changed_by: Option<Uuid>,
+    ) -> Result<TradingPairTransition> {
+        let mut changes = Vec::new();
+        let mut opening = None;
+        let mut cancelled_orders = 0;
+
+        match to {
+            TradingPairStatus::CallAuction => {
+                changes.push(self.change_status(trading_pair_id, from, to, changed_by).await?);
+                self.order_service.start_auction(trading_pair_id).await?;
+            }
+            TradingPairStatus::Trading if from == TradingPairStatus::CallAuction => {
+                // Orders placed before the uncross still rest with the auction's
+                changes.push(self.change_status(trading_pair_id, from, to, changed_by).await?);
+                opening = Some(self.order_service.uncross_auction(trading_pair_id, changed_by).await?);
+            }
+            TradingPairStatus::Delisting | TradingPairStatus::Delisted => {
+                if from != TradingPairStatus::Delisting {
+                    changes.push(self.change_status(trading_pair_id, from, TradingPairStatus::Delisting, changed_by).await?);
+                }
+                cancelled_orders = self.order_service.cancel_pair_orders(trading_pair_id, changed_by).await?;
+                changes.push(
+                    self.change_status(trading_pair_id, TradingPairStatus::Delisting, TradingPairStatus::Delisted, changed_by)
+                        .await?,
+                );
+            }
+            _ => changes.push(self.change_status(trading_pair_id, from, to, changed_by).await?),
+        }
+
+        tracing::info!(
+            "Trading pair {} moved from {:?} to {:?}{}",
+            trading_pair_id,
+            from,
+            changes.last().map_or(to, |change| change.to_status),
+            if changed_by.is_none() { " on its session schedule" } else { "" }
+        );
+        Ok(TradingPairTransition {
+            trading_pair: self.get_trading_pair(trading_pair_id).await?,
+            changes,
+            opening_price: opening.and_then(|(price, _)| price),
+            opening_quantity: opening.map(|(_, quantity)| quantity),
+            cancelled_orders,
+        })
+    }
+
+    /// Whether the pair's latest status change was made by the session
+    /// schedule rather than an admin.
+    async fn halted_by_schedule(&self, trading_pair_id: Uuid) -> Result<bool> {
+        let changed_by = sqlx::query_scalar::<_, Option<Uuid>>(
+            "SELECT changed_by FROM trading_pair_status_changes WHERE trading_pair_id = $1 ORDER BY created_at DESC LIMIT 1",
+        )
+        .bind(trading_pair_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(matches!(changed_by, Some(None)))
+    }
+
+    /// Trading sessions of `trading_pair_id`, or of every pair with any.
+    async fn sessions_by_pair(&self, trading_pair_id: Option<Uuid>) -> Result<HashMap<Uuid, Vec<TradingSession>>> {
+        sessions_by_pair(&self.db, trading_pair_id).await
+    }
+
+    async fn get_trading_pair(&self, trading_pair_id: Uuid) -> Result<TradingPair> {
+        sqlx::query_as::<_, TradingPair>("SELECT * FROM trading_pairs WHERE id = $1")
+            .bind(trading_pair_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::TradingPairNotFound)
+    }
+
+    /// Moves the pair from `from` to `to` and records the change, failing
+    /// with `Conflict` if another change got there first.
+    async fn change_status(
+        &self,
+        trading_pair_id: Uuid,
+        from: TradingPairStatus,
+        to: TradingPairStatus,
+        changed_by: Option<Uuid>,
+    ) -> Result<TradingPairStatusChange> {
+        let mut tx = self.db.begin().await?;
+        let updated = sqlx::query("UPDATE trading_pairs SET status = $3 WHERE id = $1 AND status = $2")
+            .bind(trading_pair_id)
+            .bind(from)
+            .bind(to)
+            .execute(&mut *tx)
+            .await?;
+        if updated.rows_affected() == 0 {
+            return Err(CryptoTradeError::Conflict {
+                message: format!("Trading pair {} changed status concurrently", trading_pair_id),
+            });
+        }
+
+        let change = sqlx::query_as::<_, TradingPairStatusChange>(
+            "INSERT INTO trading_pair_status_changes (trading_pair_id, from_status, to_status, changed_by) VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(trading_pair_id)
+        .bind(from)
+        .bind(to)
+        .bind(changed_by)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(change)
+    }
+}
+
+/// Whether the pair takes orders at `at` under its trading sessions; pairs
+/// without sessions always do.
+pub(crate) async fn pair_in_session(db: &Database, trading_pair_id: Uuid, at: DateTime<Utc>) -> Result<bool> {
+    let sessions = sessions_by_pair(db, Some(trading_pair_id)).await?;
+    Ok(in_session(sessions.get(&trading_pair_id).map_or(&[], Vec::as_slice), at))
+}
+
+async fn sessions_by_pair(db: &Database, trading_pair_id: Option<Uuid>) -> Result<HashMap<Uuid, Vec<TradingSession>>> {
+    let rows = sqlx::query(
+        "SELECT trading_pair_id, weekday, opens_at, closes_at FROM trading_sessions WHERE $1::UUID IS NULL OR trading_pair_id = $1 ORDER BY weekday, opens_at",
+    )
+    .bind(trading_pair_id)
+    .fetch_all(db)
+    .await?;
+
+    let mut sessions: HashMap<Uuid, Vec<TradingSession>> = HashMap::new();
+    for row in rows {
+        sessions.entry(row.get("trading_pair_id")).or_default().push(TradingSession {
+            weekday: row.get("weekday"),
+            opens_at: row.get("opens_at"),
+            closes_at: row.get("closes_at"),
+        });
+    }
+    Ok(sessions)
+}
+
+/// Whether `at` falls in one of `sessions`, or `sessions` is empty.
+pub fn in_session(sessions: &[TradingSession], at: DateTime<Utc>) -> bool {
+    let (weekday, time) = (at.weekday().number_from_monday() as i16, at.time());
+    sessions.is_empty()
+        || sessions
+            .iter()
+            .any(|session| session.weekday == weekday && session.opens_at <= time && time < session.closes_at)
+}
+
+/// Sessions sorted by weekday and opening time must be well formed and not
+/// overlap.
+fn validate_sessions(sessions: &[TradingSession]) -> Result<()> {
+    let invalid = |message: &str| {
+        Err(CryptoTradeError::Validation {
+            message: message.to_string(),
+        })
+    };
+
+    for session in sessions {
+        if !(1..=7).contains(&session.weekday) {
+            return invalid("Weekday must be 1 (Monday) to 7 (Sunday)");
+        }
+        if session.opens_at >= session.closes_at {
+            return invalid("A session must open before it closes");
+        }
+    }
+    if sessions
+        .windows(2)
+        .any(|pair| pair[0].weekday == pair[1].weekday && pair[0].closes_at > pair[1].opens_at)
+    {
+        return invalid("Sessions on the same weekday must not overlap");
+    }
+    Ok(())
+}
+
+impl TradingPairStatus {
+    /// Whether a pair may move from this status to `to`. Halted pairs reopen
+    /// through a call auction, and delisted is final.
+    pub fn can_become(self, to: TradingPairStatus) -> bool {
+        use TradingPairStatus::*;
+
+        matches!(
+            (self, to),
+            (Announced, CallAuction | Trading | Delisting)
+                | (CallAuction, Trading | Halt | Delisting)
+                | (Trading, CallAuction | Halt | Delisting)
+                | (Halt, CallAuction | Delisting)
+                | (Delisting, Delisted)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_halts_reopen_through_an_auction_and_delisting_is_final() {
+        use TradingPairStatus::*;
+
+        assert!(Announced.can_become(CallAuction) && CallAuction.can_become(Trading));
+        assert!(Trading.can_become(Halt) && Halt.can_become(CallAuction));
+        assert!(!Halt.can_become(Trading));
+        assert!(Trading.can_become(Delisting) && Delisting.can_become(Delisted));
+        assert!(!Trading.can_become(Delisted) && !Delisting.can_become(Trading));
+        assert!([Announced, CallAuction, Trading, Halt, Delisting, Delisted]
+            .into_iter()
+            .all(|to| !Delisted.can_become(to)));
+    }
+
+    #[test]
+    fn test_sessions_open_on_their_weekday_until_closing() {
+        use chrono::{NaiveTime, TimeZone};
+
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        // Monday and Tuesday, 14:30 to 21:00
+        let sessions: Vec<_> = [1, 2]
+            .map(|weekday| TradingSession {
+                weekday,
+                opens_at: time(14, 30),
+                closes_at: time(21, 0),
+            })
+            .into();
+        // 2024-01-01 was a Monday
+        let at = |day, hour, minute| Utc.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap();
+
+        assert!(in_session(&sessions, at(1, 14, 30)));
+        assert!(in_session(&sessions, at(2, 20, 59)));
+        assert!(!in_session(&sessions, at(1, 21, 0)));
+        assert!(!in_session(&sessions, at(3, 15, 0)));
+        assert!(in_session(&[], at(6, 3, 0)));
+
+        let overlapping = [
+            sessions[0].clone(),
+            TradingSession {
+                weekday: 1,
+                opens_at: time(20, 0),
+                closes_at: time(22, 0),
+            },
+        ];
+        assert!(validate_sessions(&sessions).is_ok());
+        assert!(validate_sessions(&overlapping).is_err());
+    }
+}
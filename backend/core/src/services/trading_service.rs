@@ -2,74 +2,168 @@ use crate::{
     database::Database,
     error::CryptoTradeError,
     models::*,
+    services::{
+        ledger_service::{LedgerService, Posting},
+        order_service::{buy_lock_per_unit, order_conflict, record_order_event, unspent_lock, MAX_CONFLICT_RETRIES},
+        outbox_service::{claim_event, enqueue_event, DomainEvent},
+        tenant_service::with_fee_schedule,
+        user_service::get_or_create_account,
+    },
     Result,
 };
 use chrono::Utc;
 use rust_decimal::Decimal;
-use std::str::FromStr;
+use sqlx::PgConnection;
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
 use uuid::Uuid;
 
+/// Consumer name engine matches are recorded under in `processed_events`
+const SETTLEMENT_CONSUMER: &str = "trade_settlement";
+
 #[derive(Clone)]
 pub struct TradingService {
     db: Database,
+    ledger: LedgerService,
 }
 
 impl TradingService {
     pub fn new(db: Database) -> Self {
-        Self { db }
+        Self {
+            ledger: LedgerService::new(db.clone()),
+            db,
+        }
     }
 
+    /// Settles a match: records the trade, fills both orders and moves the
+    /// balances, all in one transaction keyed by `settlement_key` (the engine
+    /// event's pair and sequence). Returns `None` without touching anything
+    /// if that match was already settled, so replaying engine events after a
+    /// crash cannot settle a trade twice.
     pub async fn execute_trade(
         &self,
+        settlement_key: &str,
         buyer_order: &Order,
         seller_order: &Order,
+        taker_side: OrderSide,
         price: Decimal,
         quantity: Decimal,
-    ) -> Result<Trade> {
-        let trade_id = Uuid::new_v4();
+    ) -> Result<Option<Trade>> {
+        let settlement = MatchSettlement {
+            settlement_key: settlement_key.to_string(),
+            buyer_order: buyer_order.clone(),
+            seller_order: seller_order.clone(),
+            taker_side,
+            price,
+            quantity,
+        };
+        Ok(self.execute_trades(&[settlement]).await?.pop().flatten())
+    }
+
+    /// Settles the matches of one engine command, all on the same pair, in
+    /// a single transaction, as `execute_trade` settles one. Balance changes
+    /// are summed per account first, so a taker sweeping many levels updates
+    /// each of its accounts once rather than once per fill. Returns each
+    /// match's trade, or `None` where it was already settled.
+    pub async fn execute_trades(&self, settlements: &[MatchSettlement]) -> Result<Vec<Option<Trade>>> {
+        let Some(first) = settlements.first() else {
+            return Ok(Vec::new());
+        };
+        let trading_pair = self.get_trading_pair(first.buyer_order.trading_pair_id).await?;
         let now = Utc::now();
 
-        let trading_pair = self.get_trading_pair(buyer_order.trading_pair_id).await?;
+        let mut tx = self.db.begin().await?;
+        // Each side pays at its own tenant's fee schedule
+        let mut fee_pairs: HashMap<Uuid, TradingPair> = HashMap::new();
+        let mut deltas = BalanceDeltas::default();
+        let mut trades = Vec::with_capacity(settlements.len());
+        let mut filled_market_buys = Vec::new();
 
-        let trade_value = price * quantity;
-        let buyer_fee = trade_value * trading_pair.taker_fee.unwrap_or(Decimal::from_str("0.001").unwrap());
-        let seller_fee = trade_value * trading_pair.maker_fee.unwrap_or(Decimal::from_str("0.001").unwrap());
+        for settlement in settlements {
+            if !claim_event(&mut tx, SETTLEMENT_CONSUMER, &settlement.settlement_key).await? {
+                tracing::info!("Match {} was already settled; skipping", settlement.settlement_key);
+                trades.push(None);
+                continue;
+            }
 
-        let trade = sqlx::query_as::<_, Trade>(
-            "INSERT INTO trades (id, trading_pair_id, buyer_order_id, seller_order_id, buyer_user_id, seller_user_id, price, quantity, buyer_fee, seller_fee, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING *"
-        )
-        .bind(trade_id)
-        .bind(buyer_order.trading_pair_id)
-        .bind(buyer_order.id)
-        .bind(seller_order.id)
-        .bind(buyer_order.user_id)
-        .bind(seller_order.user_id)
-        .bind(price)
-        .bind(quantity)
-        .bind(buyer_fee)
-        .bind(seller_fee)
-        .bind(now)
-        .fetch_one(&self.db)
-        .await?;
-
-        // Update orders
-        self.update_order_fill(buyer_order.id, quantity).await?;
-        self.update_order_fill(seller_order.id, quantity).await?;
-
-        // Update account balances
-        self.update_balances_after_trade(&trade, &trading_pair).await?;
-
-        Ok(trade)
+            let (buyer_order, seller_order) = (&settlement.buyer_order, &settlement.seller_order);
+            for user_id in [buyer_order.user_id, seller_order.user_id] {
+                if let Entry::Vacant(entry) = fee_pairs.entry(user_id) {
+                    entry.insert(with_fee_schedule(&mut *tx, trading_pair.clone(), user_id).await?);
+                }
+            }
+            let buyer_pair = &fee_pairs[&buyer_order.user_id];
+            let seller_pair = &fee_pairs[&seller_order.user_id];
+
+            let (price, quantity) = (settlement.price, settlement.quantity);
+            let trade_value = price * quantity;
+            let buyer_fee = trade_value * fee_rate(buyer_pair, settlement.taker_side == OrderSide::Buy);
+            let seller_fee = trade_value * fee_rate(seller_pair, settlement.taker_side == OrderSide::Sell);
+
+            let trade = sqlx::query_as!(
+                Trade,
+                r#"
+                INSERT INTO trades (id, trading_pair_id, buyer_order_id, seller_order_id, buyer_user_id, seller_user_id, price, quantity, buyer_fee, seller_fee, taker_side, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                RETURNING id, trading_pair_id, buyer_order_id, seller_order_id, buyer_user_id, seller_user_id, price AS "price?", quantity AS "quantity?",
+                          buyer_fee, seller_fee, taker_side AS "taker_side: OrderSide", created_at AS "created_at?", busted_at
+                "#,
+                Uuid::new_v4(),
+                buyer_order.trading_pair_id,
+                buyer_order.id,
+                seller_order.id,
+                buyer_order.user_id,
+                seller_order.user_id,
+                price,
+                quantity,
+                buyer_fee,
+                seller_fee,
+                settlement.taker_side as OrderSide,
+                now,
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            let buyer_status = self.update_order_fill(&mut tx, buyer_order.id, trade.id, price, quantity).await?;
+            self.update_order_fill(&mut tx, seller_order.id, trade.id, price, quantity).await?;
+            if buyer_order.order_type == Some(OrderType::Market) && buyer_status == OrderStatus::Filled {
+                filled_market_buys.push(buyer_order);
+            }
+
+            let surplus = fill_surplus(buyer_order, &trade);
+            deltas.add_trade(&trade, &trading_pair.base_currency, &trading_pair.quote_currency, surplus);
+            trades.push(Some(trade));
+        }
+        // A filled market buy gives back what its estimate locked beyond
+        // what it spent, with its last fill
+        for order in filled_market_buys {
+            deltas.account(order.user_id, &trading_pair.quote_currency).released += unspent_lock(&mut *tx, order.id).await?;
+        }
+
+        let mut balances = deltas.apply(&mut tx, &self.ledger).await?;
+        for trade in trades.iter().flatten() {
+            self.post_trade(&mut tx, trade, &trading_pair, &mut balances).await?;
+        }
+
+        tx.commit().await?;
+        Ok(trades)
     }
 
     pub async fn get_recent_trades(&self, trading_pair_id: Uuid, limit: Option<i64>) -> Result<Vec<Trade>> {
         let limit = limit.unwrap_or(100).min(1000);
 
-        sqlx::query_as::<_, Trade>(
-            "SELECT * FROM trades WHERE trading_pair_id = $1 ORDER BY created_at DESC LIMIT $2"
+        sqlx::query_as!(
+            Trade,
+            r#"
+            SELECT id, trading_pair_id, buyer_order_id, seller_order_id, buyer_user_id, seller_user_id, price AS "price?", quantity AS "quantity?",
+                   buyer_fee, seller_fee, taker_side AS "taker_side: OrderSide", created_at AS "created_at?", busted_at
+            FROM trades
+            WHERE trading_pair_id = $1 AND busted_at IS NULL
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            trading_pair_id,
+            limit,
         )
-        .bind(trading_pair_id)
-        .bind(limit)
         .fetch_all(&self.db)
         .await
         .map_err(Into::into)
@@ -78,89 +172,311 @@ impl TradingService {
     pub async fn get_user_trades(&self, user_id: Uuid, limit: Option<i64>) -> Result<Vec<Trade>> {
         let limit = limit.unwrap_or(100).min(1000);
 
-        sqlx::query_as::<_, Trade>(
-            "SELECT * FROM trades WHERE buyer_user_id = $1 OR seller_user_id = $1 ORDER BY created_at DESC LIMIT $2"
+        sqlx::query_as!(
+            Trade,
+            r#"
+            SELECT id, trading_pair_id, buyer_order_id, seller_order_id, buyer_user_id, seller_user_id, price AS "price?", quantity AS "quantity?",
+                   buyer_fee, seller_fee, taker_side AS "taker_side: OrderSide", created_at AS "created_at?", busted_at
+            FROM trades
+            WHERE buyer_user_id = $1 OR seller_user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit,
         )
-        .bind(user_id)
-        .bind(limit)
         .fetch_all(&self.db)
         .await
         .map_err(Into::into)
     }
 
     async fn get_trading_pair(&self, trading_pair_id: Uuid) -> Result<TradingPair> {
-        sqlx::query_as::<_, TradingPair>("SELECT * FROM trading_pairs WHERE id = $1")
-            .bind(trading_pair_id)
-            .fetch_optional(&self.db)
-            .await?
-            .ok_or(CryptoTradeError::NotFound {
+        sqlx::query_as!(
+            TradingPair,
+            r#"
+            SELECT id, symbol, base_currency, quote_currency, is_active, min_order_size AS "min_order_size?", max_order_size AS "max_order_size?",
+                   price_precision, quantity_precision, maker_fee, taker_fee, created_at, price_band_percent,
+                   status AS "status: TradingPairStatus", tenant_id
+            FROM trading_pairs
+            WHERE id = $1
+            "#,
+            trading_pair_id,
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(CryptoTradeError::NotFound {
                 message: "Trading pair not found".to_string(),
             })
     }
 
-    async fn update_order_fill(&self, order_id: Uuid, quantity: Decimal) -> Result<()> {
-        sqlx::query(
-            "UPDATE orders SET filled_quantity = filled_quantity + $1, remaining_quantity = remaining_quantity - $1, status = CASE WHEN remaining_quantity - $1 <= 0 THEN 'filled' ELSE 'partially_filled' END, updated_at = $2 WHERE id = $3"
-        )
-        .bind(quantity)
-        .bind(Utc::now())
-        .bind(order_id)
-        .execute(&self.db)
-        .await?;
+    /// Adds a fill the engine has already made to the order, as a
+    /// compare-and-swap on its version that is retried against a fresh read
+    /// if anything else updated the order in between. An order whose cancel
+    /// was settled first stays cancelled. Returns the status the order is
+    /// left with.
+    async fn update_order_fill(&self, conn: &mut PgConnection, order_id: Uuid, trade_id: Uuid, price: Decimal, quantity: Decimal) -> Result<OrderStatus> {
+        for _ in 0..MAX_CONFLICT_RETRIES {
+            let version = sqlx::query_scalar!("SELECT version FROM orders WHERE id = $1", order_id)
+                .fetch_optional(&mut *conn)
+                .await?
+                .ok_or(CryptoTradeError::OrderNotFound)?;
+
+            let status = sqlx::query_scalar!(
+                r#"
+                UPDATE orders SET
+                    filled_quantity = filled_quantity + $1,
+                    remaining_quantity = remaining_quantity - $1,
+                    cumulative_quote_quantity = COALESCE(cumulative_quote_quantity, 0) + $1 * $4,
+                    average_fill_price = (COALESCE(cumulative_quote_quantity, 0) + $1 * $4) / (COALESCE(filled_quantity, 0) + $1),
+                    status = CASE WHEN status = 'cancelled' THEN status WHEN remaining_quantity - $1 <= 0 THEN 'filled'::order_status ELSE 'partially_filled'::order_status END,
+                    version = version + 1,
+                    updated_at = $2
+                WHERE id = $3 AND version = $5
+                RETURNING status AS "status!: OrderStatus"
+                "#,
+                quantity,
+                Utc::now(),
+                order_id,
+                price,
+                version,
+            )
+            .fetch_optional(&mut *conn)
+            .await?;
+            let Some(status) = status else {
+                continue;
+            };
+
+            let event_type = if status == OrderStatus::Filled {
+                OrderEventType::Filled
+            } else {
+                OrderEventType::PartiallyFilled
+            };
+            record_order_event(&mut *conn, order_id, event_type, None, Some(trade_id), None).await?;
+            return Ok(status);
+        }
 
-        Ok(())
+        Err(order_conflict(order_id))
     }
 
-    async fn update_balances_after_trade(&self, trade: &Trade, trading_pair: &TradingPair) -> Result<()> {
+    /// Journals a trade's balance changes, already applied, and queues the
+    /// event announcing it.
+    async fn post_trade(&self, conn: &mut PgConnection, trade: &Trade, trading_pair: &TradingPair, balances: &mut RunningBalances) -> Result<()> {
         let base_currency = &trading_pair.base_currency;
         let quote_currency = &trading_pair.quote_currency;
-
-        // Handle Option types properly
-        let trade_price = trade.price.unwrap_or(Decimal::ZERO);
         let trade_quantity = trade.quantity.unwrap_or(Decimal::ZERO);
         let buyer_fee = trade.buyer_fee.unwrap_or(Decimal::ZERO);
         let seller_fee = trade.seller_fee.unwrap_or(Decimal::ZERO);
+        let notional = trade.price.unwrap_or(Decimal::ZERO) * trade_quantity;
+        let buyer = Some(trade.buyer_user_id);
+        let seller = Some(trade.seller_user_id);
+
+        // Each fee is its own entry after the trade entry, so the trade
+        // entry's running balance is the one before the fee came off
+        let postings: Vec<Posting> = [
+            (buyer, base_currency, LedgerEntryType::Trade, trade_quantity),
+            (buyer, quote_currency, LedgerEntryType::Trade, -notional),
+            (buyer, quote_currency, LedgerEntryType::Fee, -buyer_fee),
+            (seller, quote_currency, LedgerEntryType::Trade, notional),
+            (seller, quote_currency, LedgerEntryType::Fee, -seller_fee),
+            (seller, base_currency, LedgerEntryType::Trade, -trade_quantity),
+            // Both fees go to the treasury
+            (None, quote_currency, LedgerEntryType::Fee, buyer_fee + seller_fee),
+        ]
+        .into_iter()
+        .filter(|(_, _, _, amount)| !amount.is_zero())
+        .map(|(user_id, currency, entry_type, amount)| Posting {
+            user_id,
+            currency: currency.to_string(),
+            entry_type,
+            amount,
+            balance_after: balances.post(user_id, currency, amount),
+        })
+        .collect();
+
+        // The journal and the event announcing the trade commit with the
+        // balance changes
+        self.ledger.post_in(&mut *conn, trade.id, Some(trade.id), &postings).await?;
+        enqueue_event(&mut *conn, &DomainEvent::TradeExecuted { trade: trade.clone() }).await
+    }
+}
+
+/// A match the engine made, for `TradingService::execute_trades` to settle.
+#[derive(Debug, Clone)]
+pub struct MatchSettlement {
+    /// The engine event's pair and sequence
+    pub settlement_key: String,
+    pub buyer_order: Order,
+    pub seller_order: Order,
+    pub taker_side: OrderSide,
+    pub price: Decimal,
+    pub quantity: Decimal,
+}
 
-        // Buyer receives base currency, pays quote currency + fee
-        let buyer_base_amount = trade_quantity;
-        let buyer_quote_amount = trade_price * trade_quantity + buyer_fee;
+fn fee_rate(trading_pair: &TradingPair, is_taker: bool) -> Decimal {
+    let rate = if is_taker { trading_pair.taker_fee } else { trading_pair.maker_fee };
+    rate.unwrap_or(Decimal::new(1, 3))
+}
 
-        // Seller receives quote currency - fee, loses base currency
-        let seller_quote_amount = trade_price * trade_quantity - seller_fee;
-        let seller_base_amount = trade_quantity;
+/// A buy holds its share of what it locked for every unit it fills. A fill
+/// at a better price, or at the maker fee, spends less than that, and the
+/// difference goes back to the buyer's available balance.
+fn fill_surplus(buyer_order: &Order, trade: &Trade) -> Decimal {
+    let quantity = trade.quantity.unwrap_or(Decimal::ZERO);
+    let reserved = quantity * buy_lock_per_unit(buyer_order);
+    let spent = quantity * trade.price.unwrap_or(Decimal::ZERO) + trade.buyer_fee.unwrap_or(Decimal::ZERO);
+    (reserved - spent).max(Decimal::ZERO)
+}
 
-        // Update buyer balances
-        self.update_account_balance(trade.buyer_user_id, base_currency, buyer_base_amount, true).await?;
-        self.update_account_balance(trade.buyer_user_id, quote_currency, buyer_quote_amount, false).await?;
+/// What a batch of trades does to one account.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct AccountDelta {
+    /// Added to the balance and to what is available
+    credit: Decimal,
+    /// Taken out of the balance from what is locked
+    debit: Decimal,
+    /// Moved from locked back to available
+    released: Decimal,
+}
 
-        // Update seller balances
-        self.update_account_balance(trade.seller_user_id, quote_currency, seller_quote_amount, true).await?;
-        self.update_account_balance(trade.seller_user_id, base_currency, seller_base_amount, false).await?;
+/// The balance changes of a batch of trades, summed per account so each
+/// account is written once however many of the trades touched it.
+#[derive(Debug, Default)]
+struct BalanceDeltas {
+    /// Ordered, so concurrent batches lock shared accounts in the same order
+    accounts: BTreeMap<(Uuid, String), AccountDelta>,
+    treasury: BTreeMap<String, Decimal>,
+}
 
-        Ok(())
+impl BalanceDeltas {
+    fn account(&mut self, user_id: Uuid, currency: &str) -> &mut AccountDelta {
+        self.accounts.entry((user_id, currency.to_string())).or_default()
     }
 
-    async fn update_account_balance(&self, user_id: Uuid, currency: &str, amount: Decimal, is_credit: bool) -> Result<()> {
-        if is_credit {
-            sqlx::query(
-                "UPDATE accounts SET balance = balance + $1, available_balance = available_balance + $1 WHERE user_id = $2 AND currency = $3"
-            )
-            .bind(amount)
-            .bind(user_id)
-            .bind(currency)
-            .execute(&self.db)
-            .await?;
-        } else {
-            sqlx::query(
-                "UPDATE accounts SET balance = balance - $1, locked_balance = locked_balance - $1 WHERE user_id = $2 AND currency = $3"
+    /// The buyer receives the base currency and pays the quote plus their
+    /// fee out of their lock; the seller gives up the base and receives the
+    /// quote less their fee. Both fees go to the treasury.
+    fn add_trade(&mut self, trade: &Trade, base: &str, quote: &str, buyer_surplus: Decimal) {
+        let quantity = trade.quantity.unwrap_or(Decimal::ZERO);
+        let notional = trade.price.unwrap_or(Decimal::ZERO) * quantity;
+        let buyer_fee = trade.buyer_fee.unwrap_or(Decimal::ZERO);
+        let seller_fee = trade.seller_fee.unwrap_or(Decimal::ZERO);
+
+        self.account(trade.buyer_user_id, base).credit += quantity;
+        let buyer_quote = self.account(trade.buyer_user_id, quote);
+        buyer_quote.debit += notional + buyer_fee;
+        buyer_quote.released += buyer_surplus;
+        self.account(trade.seller_user_id, quote).credit += notional - seller_fee;
+        self.account(trade.seller_user_id, base).debit += quantity;
+        *self.treasury.entry(quote.to_string()).or_default() += buyer_fee + seller_fee;
+    }
+
+    /// Writes every account once and returns the balances the batch's
+    /// ledger entries run up to. A credit opens the account if the user
+    /// never held the currency; an account missing for a debit has no
+    /// running balance.
+    async fn apply(&self, conn: &mut PgConnection, ledger: &LedgerService) -> Result<RunningBalances> {
+        let mut balances = RunningBalances::default();
+        for ((user_id, currency), delta) in &self.accounts {
+            if delta.credit > Decimal::ZERO {
+                get_or_create_account(&mut *conn, *user_id, currency).await?;
+            }
+            let balance = sqlx::query_scalar!(
+                "UPDATE accounts SET balance = balance + $1 - $2, available_balance = available_balance + $1 + $3, locked_balance = locked_balance - $2 - $3 WHERE user_id = $4 AND currency = $5 RETURNING balance",
+                delta.credit,
+                delta.debit,
+                delta.released,
+                user_id,
+                currency,
             )
-            .bind(amount)
-            .bind(user_id)
-            .bind(currency)
-            .execute(&self.db)
-            .await?;
+            .fetch_optional(&mut *conn)
+            .await?
+            .flatten();
+            balances.start(Some(*user_id), currency, balance, delta.credit - delta.debit);
+        }
+        for (currency, fees) in &self.treasury {
+            if fees.is_zero() {
+                continue;
+            }
+            let balance = ledger.credit_treasury_in(&mut *conn, currency, *fees).await?;
+            balances.start(None, currency, Some(balance), *fees);
         }
+        Ok(balances)
+    }
+}
+
+/// Balances as of each ledger entry of a batch, rebuilt from the balances
+/// the batch ended with; `None` for the exchange's own account.
+#[derive(Debug, Default)]
+struct RunningBalances {
+    balances: HashMap<(Option<Uuid>, String), Option<Decimal>>,
+}
+
+impl RunningBalances {
+    /// Records the balance an account ended the batch with, after `net`
+    /// was added to it, so entries run up from what it held before.
+    fn start(&mut self, user_id: Option<Uuid>, currency: &str, after: Option<Decimal>, net: Decimal) {
+        self.balances.insert((user_id, currency.to_string()), after.map(|after| after - net));
+    }
+
+    /// Adds an entry's amount and returns the balance after it.
+    fn post(&mut self, user_id: Option<Uuid>, currency: &str, amount: Decimal) -> Option<Decimal> {
+        let balance = self.balances.get_mut(&(user_id, currency.to_string()))?.as_mut()?;
+        *balance += amount;
+        Some(*balance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(buyer: Uuid, seller: Uuid, price: i64, buyer_fee: Decimal) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            trading_pair_id: Uuid::new_v4(),
+            buyer_order_id: Uuid::new_v4(),
+            seller_order_id: Uuid::new_v4(),
+            buyer_user_id: buyer,
+            seller_user_id: seller,
+            price: Some(Decimal::from(price)),
+            quantity: Some(Decimal::ONE),
+            buyer_fee: Some(buyer_fee),
+            seller_fee: Some(Decimal::ZERO),
+            taker_side: OrderSide::Buy,
+            created_at: None,
+            busted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_a_sweep_writes_each_account_once() {
+        let (buyer, seller) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut deltas = BalanceDeltas::default();
+        deltas.add_trade(&trade(buyer, seller, 100, Decimal::new(1, 1)), "BTC", "USD", Decimal::ONE);
+        deltas.add_trade(&trade(buyer, seller, 101, Decimal::new(101, 3)), "BTC", "USD", Decimal::ZERO);
+
+        assert_eq!(deltas.accounts.len(), 4);
+        let buyer_usd = deltas.accounts[&(buyer, "USD".to_string())];
+        assert_eq!(buyer_usd.debit, Decimal::new(201_201, 3));
+        assert_eq!(buyer_usd.released, Decimal::ONE);
+        assert_eq!(deltas.accounts[&(buyer, "BTC".to_string())].credit, Decimal::from(2));
+        assert_eq!(deltas.accounts[&(seller, "USD".to_string())].credit, Decimal::from(201));
+        assert_eq!(deltas.accounts[&(seller, "BTC".to_string())].debit, Decimal::from(2));
+        assert_eq!(deltas.treasury["USD"], Decimal::new(201, 3));
+    }
+
+    #[test]
+    fn test_running_balances_rebuild_each_entry_from_the_final_balance() {
+        let user = Uuid::new_v4();
+        let mut balances = RunningBalances::default();
+        // 500 before; -100, -0.1 fee, then -101
+        balances.start(Some(user), "USD", Some(Decimal::new(2989, 1)), Decimal::new(-2011, 1));
+        balances.start(Some(user), "BTC", None, Decimal::from(-2));
 
-        Ok(())
+        assert_eq!(balances.post(Some(user), "USD", Decimal::from(-100)), Some(Decimal::from(400)));
+        assert_eq!(balances.post(Some(user), "USD", Decimal::new(-1, 1)), Some(Decimal::new(3999, 1)));
+        assert_eq!(balances.post(Some(user), "USD", Decimal::from(-101)), Some(Decimal::new(2989, 1)));
+        assert_eq!(balances.post(Some(user), "BTC", Decimal::from(-1)), None);
+        assert_eq!(balances.post(None, "USD", Decimal::ONE), None);
     }
 }
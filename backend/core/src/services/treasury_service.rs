@@ -0,0 +1,117 @@
+use crate::{
+    database::Database,
+    error::CryptoTradeError,
+    models::*,
+    services::{LedgerService, Posting},
+    Result,
+};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// The fees the exchange has collected. Trading and withdrawal fees are
+/// credited to a treasury account per currency as they are charged; an
+/// admin sweeps them out to the exchange's operating funds.
+#[derive(Clone)]
+pub struct TreasuryService {
+    db: Database,
+    ledger: LedgerService,
+}
+
+impl TreasuryService {
+    pub fn new(db: Database, ledger: LedgerService) -> Self {
+        Self { db, ledger }
+    }
+
+    pub async fn accounts(&self) -> Result<Vec<TreasuryAccount>> {
+        let accounts = sqlx::query_as::<_, TreasuryAccount>("SELECT * FROM treasury_accounts ORDER BY currency")
+            .fetch_all(&self.db)
+            .await?;
+
+        Ok(accounts)
+    }
+
+    pub async fn sweeps(&self, currency: Option<&str>) -> Result<Vec<TreasurySweep>> {
+        let sweeps = sqlx::query_as::<_, TreasurySweep>(
+            "SELECT * FROM treasury_sweeps WHERE $1::text IS NULL OR currency = $1 ORDER BY created_at DESC",
+        )
+        .bind(currency)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(sweeps)
+    }
+
+    /// Pays `amount` of collected fees out of the treasury. The ledger
+    /// records it as a fee entry taking the amount off the treasury and a
+    /// transfer entry for where it went.
+    pub async fn sweep(&self, admin_id: Uuid, currency: &str, request: SweepTreasuryRequest) -> Result<TreasurySweep> {
+        if request.amount <= Decimal::ZERO {
+            return Err(CryptoTradeError::Validation {
+                message: "Amount must be positive".to_string(),
+            });
+        }
+        let destination = request.destination.trim();
+        if destination.is_empty() {
+            return Err(CryptoTradeError::Validation {
+                message: "A destination is required".to_string(),
+            });
+        }
+        let currency = currency.to_uppercase();
+
+        let mut tx = self.db.begin().await?;
+        let available = sqlx::query_scalar::<_, Decimal>("SELECT balance FROM treasury_accounts WHERE currency = $1 FOR UPDATE")
+            .bind(&currency)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| CryptoTradeError::NotFound {
+                message: format!("No {} treasury account", currency),
+            })?;
+        if request.amount > available {
+            return Err(CryptoTradeError::InsufficientBalance {
+                currency,
+                required: request.amount,
+                available,
+            });
+        }
+
+        let balance = sqlx::query_scalar::<_, Decimal>(
+            "UPDATE treasury_accounts SET balance = balance - $2, total_swept = total_swept + $2, updated_at = NOW() WHERE currency = $1 RETURNING balance",
+        )
+        .bind(&currency)
+        .bind(request.amount)
+        .fetch_one(&mut *tx)
+        .await?;
+        let sweep = sqlx::query_as::<_, TreasurySweep>(
+            "INSERT INTO treasury_sweeps (currency, amount, destination, note, swept_by) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+        )
+        .bind(&currency)
+        .bind(request.amount)
+        .bind(destination)
+        .bind(request.note.as_deref())
+        .bind(admin_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let postings = [
+            Posting {
+                user_id: None,
+                currency: currency.clone(),
+                entry_type: LedgerEntryType::Fee,
+                amount: -sweep.amount,
+                balance_after: Some(balance),
+            },
+            Posting {
+                user_id: None,
+                currency: currency.clone(),
+                entry_type: LedgerEntryType::Transfer,
+                amount: sweep.amount,
+                balance_after: None,
+            },
+        ];
+        self.ledger.post_in(&mut tx, sweep.id, Some(sweep.id), &postings).await?;
+        tx.commit().await?;
+        tracing::info!("Admin {} swept {} {} of fees to {}", admin_id, sweep.amount, currency, sweep.destination);
+
+        Ok(sweep)
+    }
+}
@@ -1,26 +1,54 @@
 use crate::{
     auth::AuthService,
+    config::KycTiersConfig,
     database::Database,
     error::CryptoTradeError,
+    i18n::Locale,
     models::*,
+    services::{
+        device_service::{DeviceInfo, DeviceService},
+        login_risk_service::LoginRiskService,
+        outbox_service::{enqueue_event, DomainEvent},
+        tenant_service::tenant_by_slug,
+    },
     Result,
 };
 use chrono::Utc;
+use sqlx::PgExecutor;
 use uuid::Uuid;
 use validator::Validate;
 
+/// Order statuses that still hold funds
+const OPEN_ORDER_STATUSES: &str = "('pending', 'open', 'partially_filled')";
+
 #[derive(Clone)]
 pub struct UserService {
     db: Database,
     auth_service: AuthService,
+    devices: DeviceService,
+    login_risk: LoginRiskService,
+    kyc_tiers: KycTiersConfig,
 }
 
 impl UserService {
-    pub fn new(db: Database, auth_service: AuthService) -> Self {
-        Self { db, auth_service }
+    pub fn new(
+        db: Database,
+        auth_service: AuthService,
+        devices: DeviceService,
+        login_risk: LoginRiskService,
+        kyc_tiers: KycTiersConfig,
+    ) -> Self {
+        Self {
+            db,
+            auth_service,
+            devices,
+            login_risk,
+            kyc_tiers,
+        }
     }
 
-    pub async fn register(&self, request: RegisterRequest) -> Result<AuthResponse> {
+    /// Registers a user; `device` becomes their first confirmed device.
+    pub async fn register(&self, request: RegisterRequest, device: &DeviceInfo) -> Result<AuthResponse> {
         request.validate().map_err(|e| CryptoTradeError::Validation {
             message: e.to_string(),
         })?;
@@ -38,14 +66,19 @@ impl UserService {
             });
         }
 
+        let tenant_id = match request.tenant.as_deref() {
+            Some(slug) => Some(tenant_by_slug(&self.db, slug).await?.id),
+            None => None,
+        };
+
         let password_hash = self.auth_service.hash_password(&request.password)?;
         let user_id = Uuid::new_v4();
 
         // Create user
         let user = sqlx::query_as::<_, User>(
             r#"
-            INSERT INTO users (id, email, username, password_hash, first_name, last_name, is_verified, is_active, two_fa_enabled, kyc_status, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6, false, true, false, 'pending', $7, $7)
+            INSERT INTO users (id, email, username, password_hash, first_name, last_name, country, tenant_id, is_verified, is_active, two_fa_enabled, kyc_status, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, false, true, false, 'pending', $9, $9)
             RETURNING *
             "#
         )
@@ -55,15 +88,22 @@ impl UserService {
         .bind(password_hash)
         .bind(&request.first_name)
         .bind(&request.last_name)
+        .bind(request.country.as_deref().map(str::to_ascii_uppercase))
+        .bind(tenant_id)
         .bind(Utc::now())
         .fetch_one(&self.db)
         .await?;
 
-        // Create default accounts for major currencies
-        let currencies = vec!["USD", "BTC", "ETH", "USDT"];
+        let currencies = sqlx::query_scalar::<_, String>(
+            "SELECT code FROM currencies WHERE is_active AND open_by_default ORDER BY code",
+        )
+        .fetch_all(&self.db)
+        .await?;
         for currency in currencies {
-            self.create_account(user.id, currency).await?;
+            self.get_or_create_account(user.id, &currency).await?;
         }
+        self.devices.record_confirmed(user.id, device).await?;
+        self.login_risk.record_registration(user.id, device).await?;
 
         let access_token = self.auth_service.generate_jwt(&user)?;
         let refresh_token = self.auth_service.generate_refresh_token(user.id)?;
@@ -72,20 +112,14 @@ impl UserService {
             access_token,
             refresh_token,
             expires_in: 3600,
-            user: UserProfile {
-                id: user.id,
-                email: user.email,
-                username: user.username,
-                first_name: user.first_name,
-                last_name: user.last_name,
-                is_verified: user.is_verified.unwrap_or(false),
-                two_fa_enabled: user.two_fa_enabled.unwrap_or(false),
-                kyc_status: user.kyc_status.unwrap_or(KycStatus::Pending),
-            },
+            user: self.profile(user),
         })
     }
 
-    pub async fn login(&self, request: LoginRequest) -> Result<AuthResponse> {
+    /// Issues tokens once the password, any 2FA code and, for a device the
+    /// user has not confirmed or a login from improbably far away, the
+    /// emailed challenge are satisfied.
+    pub async fn login(&self, request: LoginRequest, device: &DeviceInfo) -> Result<AuthResponse> {
         request.validate().map_err(|e| CryptoTradeError::Validation {
             message: e.to_string(),
         })?;
@@ -107,6 +141,7 @@ impl UserService {
         }
 
         if !self.auth_service.verify_password(&request.password, &user.password_hash)? {
+            self.record_failed_login(user.id).await?;
             return Err(CryptoTradeError::Authentication {
                 message: "Invalid credentials".to_string(),
             });
@@ -116,6 +151,7 @@ impl UserService {
         if user.two_fa_enabled.unwrap_or(false) {
             if let Some(code) = &request.totp_code {
                 if !self.verify_2fa(user.id, code).await? {
+                    self.record_failed_login(user.id).await?;
                     return Err(CryptoTradeError::Authentication {
                         message: "Invalid 2FA code".to_string(),
                     });
@@ -125,6 +161,19 @@ impl UserService {
             }
         }
 
+        if user.failed_login_attempts > 0 {
+            sqlx::query("UPDATE users SET failed_login_attempts = 0 WHERE id = $1")
+                .bind(user.id)
+                .execute(&self.db)
+                .await?;
+        }
+
+        // A valid 2FA code confirms a new device, or improbable travel since
+        // the last login, without the emailed link
+        let two_factor_passed = user.two_fa_enabled.unwrap_or(false);
+        let login = self.login_risk.record_login(&user, device, two_factor_passed).await?;
+        self.devices.check_login(&user, device, two_factor_passed, &login).await?;
+
         let access_token = self.auth_service.generate_jwt(&user)?;
         let refresh_token = self.auth_service.generate_refresh_token(user.id)?;
 
@@ -132,19 +181,52 @@ impl UserService {
             access_token,
             refresh_token,
             expires_in: 3600,
-            user: UserProfile {
-                id: user.id,
-                email: user.email,
-                username: user.username,
-                first_name: user.first_name,
-                last_name: user.last_name,
-                is_verified: user.is_verified.unwrap_or(false),
-                two_fa_enabled: user.two_fa_enabled.unwrap_or(false),
-                kyc_status: user.kyc_status.unwrap_or(KycStatus::Pending),
-            },
+            user: self.profile(user),
         })
     }
 
+    /// Counts towards the failures after which logins need a CAPTCHA.
+    async fn record_failed_login(&self, user_id: Uuid) -> Result<()> {
+        sqlx::query(
+            "UPDATE users SET failed_login_attempts = failed_login_attempts + 1, last_failed_login_at = NOW() WHERE id = $1",
+        )
+        .bind(user_id)
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// The user as they see themselves, with what their KYC tier allows.
+    pub fn profile(&self, user: User) -> UserProfile {
+        UserProfile {
+            id: user.id,
+            email: user.email,
+            username: user.username,
+            first_name: user.first_name,
+            last_name: user.last_name,
+            is_verified: user.is_verified.unwrap_or(false),
+            two_fa_enabled: user.two_fa_enabled.unwrap_or(false),
+            kyc_status: user.kyc_status.unwrap_or(KycStatus::Pending),
+            kyc_tier: user.kyc_tier,
+            kyc_limits: self.kyc_tiers.limits(user.kyc_tier).clone(),
+            account_status: user.account_status,
+            country: user.country,
+            locale: user.locale,
+            tenant_id: user.tenant_id,
+        }
+    }
+
+    /// Moves the user to another KYC tier, with the limits that come with it.
+    pub async fn set_kyc_tier(&self, user_id: Uuid, kyc_tier: KycTier) -> Result<UserProfile> {
+        let user = sqlx::query_as::<_, User>("UPDATE users SET kyc_tier = $1, updated_at = NOW() WHERE id = $2 RETURNING *")
+            .bind(kyc_tier)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::UserNotFound)?;
+        Ok(self.profile(user))
+    }
+
     pub async fn get_user_by_id(&self, user_id: Uuid) -> Result<User> {
         sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
             .bind(user_id)
@@ -202,21 +284,158 @@ impl UserService {
         })
     }
 
-    pub async fn create_account(&self, user_id: Uuid, currency: &str) -> Result<Account> {
-        let account_id = Uuid::new_v4();
-        let now = Utc::now();
+    /// What the user may do across all their sessions and API keys.
+    pub async fn get_permissions(&self, user_id: Uuid) -> Result<PermissionScope> {
+        sqlx::query_as::<_, PermissionScope>("SELECT permissions, trading_pair_ids FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::UserNotFound)
+    }
 
-        sqlx::query_as::<_, Account>(
-            "INSERT INTO accounts (id, user_id, currency, balance, available_balance, locked_balance, created_at, updated_at) VALUES ($1, $2, $3, 0, 0, 0, $4, $5) RETURNING *"
+    /// Restricts the user to some actions or pairs; `None` lifts the
+    /// restriction on that dimension.
+    pub async fn set_permissions(&self, user_id: Uuid, scope: PermissionScope) -> Result<PermissionScope> {
+        sqlx::query_as::<_, PermissionScope>(
+            "UPDATE users SET permissions = $1, trading_pair_ids = $2, updated_at = $3 WHERE id = $4 RETURNING permissions, trading_pair_ids",
         )
-        .bind(account_id)
+        .bind(&scope.permissions)
+        .bind(&scope.trading_pair_ids)
+        .bind(Utc::now())
         .bind(user_id)
-        .bind(currency)
-        .bind(now)
-        .bind(now)
-        .fetch_one(&self.db)
-        .await
-        .map_err(Into::into)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(CryptoTradeError::UserNotFound)
+    }
+
+    pub async fn set_locale(&self, user_id: Uuid, locale: Option<Locale>) -> Result<User> {
+        sqlx::query_as::<_, User>("UPDATE users SET locale = $1, updated_at = $2 WHERE id = $3 RETURNING *")
+            .bind(locale.map(Locale::tag))
+            .bind(Utc::now())
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or(CryptoTradeError::UserNotFound)
+    }
+
+    /// The locale the user chose on their profile, if any.
+    pub async fn preferred_locale(&self, user_id: Uuid) -> Result<Option<Locale>> {
+        let tag = sqlx::query_scalar::<_, Option<String>>("SELECT locale FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .flatten();
+        Ok(tag.as_deref().and_then(Locale::from_tag))
+    }
+
+    pub async fn account_status(&self, user_id: Uuid) -> Result<AccountStatus> {
+        account_status(&self.db, user_id).await
+    }
+
+    /// Closes the caller's own account once they confirm their password.
+    pub async fn close_account(&self, user_id: Uuid, request: CloseAccountRequest) -> Result<AccountStatusChange> {
+        let user = self.get_user_by_id(user_id).await?;
+        if !self.auth_service.verify_password(&request.password, &user.password_hash)? {
+            return Err(CryptoTradeError::Authentication {
+                message: "Invalid credentials".to_string(),
+            });
+        }
+
+        let reason = request
+            .reason
+            .filter(|reason| !reason.trim().is_empty())
+            .unwrap_or_else(|| "Closed by the account holder".to_string());
+        self.change_account_status(user_id, AccountStatus::Closed, &reason, None).await
+    }
+
+    /// Restricts, reinstates or closes an account on an admin's decision.
+    pub async fn set_account_status(&self, admin_id: Uuid, user_id: Uuid, request: UpdateAccountStatusRequest) -> Result<AccountStatusChange> {
+        if request.reason.trim().is_empty() {
+            return Err(CryptoTradeError::Validation {
+                message: "A reason is required".to_string(),
+            });
+        }
+        self.change_account_status(user_id, request.status, request.reason.trim(), Some(admin_id)).await
+    }
+
+    /// Moves the account to `to` and records the change. Only an account
+    /// holding no funds and no open orders can be closed; closing revokes
+    /// its API keys.
+    pub(crate) async fn change_account_status(&self, user_id: Uuid, to: AccountStatus, reason: &str, changed_by: Option<Uuid>) -> Result<AccountStatusChange> {
+        let mut tx = self.db.begin().await?;
+        let from = sqlx::query_scalar::<_, AccountStatus>("SELECT account_status FROM users WHERE id = $1 FOR UPDATE")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(CryptoTradeError::UserNotFound)?;
+        if !from.can_become(to) {
+            let message = if from == AccountStatus::Closed {
+                "Closed accounts cannot be reopened".to_string()
+            } else {
+                format!("Account is already {:?}", to).to_lowercase()
+            };
+            return Err(CryptoTradeError::Validation { message });
+        }
+
+        if to == AccountStatus::Closed {
+            let open_orders = sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT COUNT(*) FROM orders WHERE user_id = $1 AND status IN {}",
+                OPEN_ORDER_STATUSES
+            ))
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if open_orders > 0 {
+                return Err(CryptoTradeError::Validation {
+                    message: "Cancel open orders before closing the account".to_string(),
+                });
+            }
+            let funded = sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS (SELECT 1 FROM accounts WHERE user_id = $1 AND (COALESCE(balance, 0) <> 0 OR COALESCE(locked_balance, 0) <> 0))",
+            )
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if funded {
+                return Err(CryptoTradeError::Validation {
+                    message: "Withdraw all funds before closing the account".to_string(),
+                });
+            }
+
+            sqlx::query("UPDATE api_keys SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query(
+            "UPDATE users SET account_status = $2, status_reason = $3, closed_at = CASE WHEN $2 = 'closed'::account_status THEN NOW() END, updated_at = NOW() WHERE id = $1",
+        )
+        .bind(user_id)
+        .bind(to)
+        .bind(reason)
+        .execute(&mut *tx)
+        .await?;
+        let change = sqlx::query_as::<_, AccountStatusChange>(
+            "INSERT INTO account_status_changes (user_id, from_status, to_status, reason, changed_by) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+        )
+        .bind(user_id)
+        .bind(from)
+        .bind(to)
+        .bind(reason)
+        .bind(changed_by)
+        .fetch_one(&mut *tx)
+        .await?;
+        let event = DomainEvent::AccountStatusChanged { change: change.clone() };
+        enqueue_event(&mut *tx, &event).await?;
+        tx.commit().await?;
+
+        tracing::info!("Account {} changed from {:?} to {:?}: {}", user_id, from, to, reason);
+        Ok(change)
+    }
+
+    pub async fn get_or_create_account(&self, user_id: Uuid, currency: &str) -> Result<Account> {
+        get_or_create_account(&self.db, user_id, currency).await
     }
 
     pub async fn get_user_accounts(&self, user_id: Uuid) -> Result<Vec<Account>> {
@@ -239,3 +458,75 @@ impl UserService {
         }
     }
 }
+
+impl AccountStatus {
+    /// Whether an account may move from this status to `to`. Closing is final.
+    pub fn can_become(self, to: AccountStatus) -> bool {
+        self != AccountStatus::Closed && self != to
+    }
+
+    /// Placing orders and funding the account take an active account.
+    pub fn ensure_can_trade(self) -> Result<()> {
+        match self {
+            AccountStatus::Active => Ok(()),
+            AccountStatus::Restricted => Err(CryptoTradeError::AccountRestricted),
+            AccountStatus::Closed => Err(CryptoTradeError::AccountClosed),
+        }
+    }
+
+    /// Moving funds out, which a restricted account may still do to wind down.
+    pub fn ensure_can_withdraw(self) -> Result<()> {
+        match self {
+            AccountStatus::Closed => Err(CryptoTradeError::AccountClosed),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The user's account status, for services that enforce it.
+pub async fn account_status(db: &Database, user_id: Uuid) -> Result<AccountStatus> {
+    sqlx::query_scalar::<_, AccountStatus>("SELECT account_status FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(CryptoTradeError::UserNotFound)
+}
+
+/// The user's account in `currency`, opened with a zero balance if they
+/// never held it. Concurrent callers all get the same row: the insert
+/// yields to the `(user_id, currency)` unique key, and its no-op update
+/// returns the row that won.
+pub async fn get_or_create_account<'e>(executor: impl PgExecutor<'e>, user_id: Uuid, currency: &str) -> Result<Account> {
+    sqlx::query_as::<_, Account>(
+        r#"
+        INSERT INTO accounts (user_id, currency, balance, available_balance, locked_balance)
+        VALUES ($1, $2, 0, 0, 0)
+        ON CONFLICT (user_id, currency) DO UPDATE SET currency = EXCLUDED.currency
+        RETURNING *
+        "#,
+    )
+    .bind(user_id)
+    .bind(currency)
+    .fetch_one(executor)
+    .await
+    .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closing_is_final_and_restriction_allows_winding_down() {
+        use AccountStatus::*;
+
+        assert!(Active.can_become(Restricted) && Restricted.can_become(Active));
+        assert!(Active.can_become(Closed) && Restricted.can_become(Closed));
+        assert!(!Closed.can_become(Active) && !Closed.can_become(Restricted));
+        assert!(!Active.can_become(Active));
+
+        assert!(Restricted.ensure_can_withdraw().is_ok());
+        assert!(matches!(Restricted.ensure_can_trade(), Err(CryptoTradeError::AccountRestricted)));
+        assert!(matches!(Closed.ensure_can_withdraw(), Err(CryptoTradeError::AccountClosed)));
+    }
+}
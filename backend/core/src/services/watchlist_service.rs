@@ -0,0 +1,154 @@
+use crate::{database::Database, error::CryptoTradeError, models::*, Result};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Most pairs one watchlist holds
+const MAX_WATCHLIST_ENTRIES: usize = 200;
+
+/// The pairs each user follows, in the order they chose.
+#[derive(Clone)]
+pub struct WatchlistService {
+    db: Database,
+}
+
+impl WatchlistService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub async fn get_watchlist(&self, user_id: Uuid) -> Result<Vec<WatchlistEntry>> {
+        sqlx::query_as::<_, WatchlistEntry>(
+            r#"
+            SELECT w.trading_pair_id, tp.symbol, w.position, w.created_at
+            FROM watchlist_entries w
+            JOIN trading_pairs tp ON tp.id = w.trading_pair_id
+            WHERE w.user_id = $1
+            ORDER BY w.position
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(Into::into)
+    }
+
+    /// The watched pairs' ids, in the user's order.
+    pub async fn watched_pair_ids(&self, user_id: Uuid) -> Result<Vec<Uuid>> {
+        sqlx::query_scalar::<_, Uuid>("SELECT trading_pair_id FROM watchlist_entries WHERE user_id = $1 ORDER BY position")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Appends a pair; a pair already watched keeps its place.
+    pub async fn add_entry(&self, user_id: Uuid, request: AddWatchlistEntryRequest) -> Result<Vec<WatchlistEntry>> {
+        self.ensure_pairs_exist(&[request.trading_pair_id]).await?;
+
+        let mut tx = self.db.begin().await?;
+        lock_watchlist(&mut tx, user_id).await?;
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM watchlist_entries WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if count as usize >= MAX_WATCHLIST_ENTRIES {
+            return Err(CryptoTradeError::Validation {
+                message: format!("A watchlist holds at most {} pairs", MAX_WATCHLIST_ENTRIES),
+            });
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO watchlist_entries (user_id, trading_pair_id, position)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, trading_pair_id) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(request.trading_pair_id)
+        .bind(count as i32)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.get_watchlist(user_id).await
+    }
+
+    /// Replaces the whole list, which is how it is reordered.
+    pub async fn set_watchlist(&self, user_id: Uuid, request: SetWatchlistRequest) -> Result<Vec<WatchlistEntry>> {
+        let ids = request.trading_pair_ids;
+        if ids.len() > MAX_WATCHLIST_ENTRIES {
+            return Err(CryptoTradeError::Validation {
+                message: format!("A watchlist holds at most {} pairs", MAX_WATCHLIST_ENTRIES),
+            });
+        }
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = ids.iter().find(|id| !seen.insert(**id)) {
+            return Err(CryptoTradeError::Validation {
+                message: format!("Trading pair {} is listed twice", duplicate),
+            });
+        }
+        self.ensure_pairs_exist(&ids).await?;
+
+        let mut tx = self.db.begin().await?;
+        lock_watchlist(&mut tx, user_id).await?;
+        sqlx::query("DELETE FROM watchlist_entries WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        for (position, trading_pair_id) in ids.iter().enumerate() {
+            sqlx::query("INSERT INTO watchlist_entries (user_id, trading_pair_id, position) VALUES ($1, $2, $3)")
+                .bind(user_id)
+                .bind(trading_pair_id)
+                .bind(position as i32)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        self.get_watchlist(user_id).await
+    }
+
+    /// Drops a pair and closes the gap it leaves in the positions.
+    pub async fn remove_entry(&self, user_id: Uuid, trading_pair_id: Uuid) -> Result<Vec<WatchlistEntry>> {
+        let mut tx = self.db.begin().await?;
+        lock_watchlist(&mut tx, user_id).await?;
+        let position = sqlx::query_scalar::<_, i32>(
+            "DELETE FROM watchlist_entries WHERE user_id = $1 AND trading_pair_id = $2 RETURNING position",
+        )
+        .bind(user_id)
+        .bind(trading_pair_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| CryptoTradeError::NotFound {
+            message: "Trading pair is not on the watchlist".to_string(),
+        })?;
+        sqlx::query("UPDATE watchlist_entries SET position = position - 1 WHERE user_id = $1 AND position > $2")
+            .bind(user_id)
+            .bind(position)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        self.get_watchlist(user_id).await
+    }
+
+    async fn ensure_pairs_exist(&self, ids: &[Uuid]) -> Result<()> {
+        let found = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM trading_pairs WHERE id = ANY($1)")
+            .bind(ids)
+            .fetch_one(&self.db)
+            .await?;
+        if found as usize != ids.len() {
+            return Err(CryptoTradeError::TradingPairNotFound);
+        }
+        Ok(())
+    }
+}
+
+/// Serializes edits of one user's list, so positions stay contiguous.
+async fn lock_watchlist(conn: &mut sqlx::PgConnection, user_id: Uuid) -> Result<()> {
+    sqlx::query("SELECT id FROM users WHERE id = $1 FOR UPDATE")
+        .bind(user_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
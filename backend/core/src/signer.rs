@@ -0,0 +1,246 @@
+//! Hot wallet transaction signing. Withdrawals hand the signer the digest
+//! of the transaction to sign and never see the key: with the `aws_kms`
+//! backend it stays inside the KMS HSMs and only signatures come back. The
+//! `local` backend holds `blockchain.private_key` in process memory, for
+//! development; production refuses it (see [`crate::config::Config::problems`]).
+
+use crate::config::{BlockchainConfig, SignerBackend, SignerConfig};
+use crate::error::CryptoTradeError;
+use crate::secrets::sigv4_authorization;
+use crate::Result;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use std::sync::Arc;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// How long a KMS request may take
+const KMS_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[async_trait]
+pub trait TransactionSigner: Send + Sync {
+    fn backend(&self) -> &'static str;
+    /// The public key signatures verify against, DER encoded for KMS keys
+    fn public_key(&self) -> &[u8];
+    /// Signs the 32 byte digest of a transaction.
+    async fn sign(&self, digest: &[u8; 32]) -> Result<Vec<u8>>;
+}
+
+/// The signer `config` selects. KMS keys are looked up once here, so a
+/// missing key or permission fails at startup rather than at the first
+/// withdrawal.
+pub async fn signer(config: &BlockchainConfig) -> Result<Arc<dyn TransactionSigner>> {
+    let signer: Arc<dyn TransactionSigner> = match config.signer.backend {
+        SignerBackend::Local => Arc::new(LocalSigner::from_hex(&config.private_key)?),
+        SignerBackend::AwsKms => Arc::new(AwsKmsSigner::connect(&config.signer).await?),
+    };
+    tracing::info!(
+        "Signing hot wallet transactions with the {} signer, public key {}",
+        signer.backend(),
+        hex::encode(signer.public_key())
+    );
+    Ok(signer)
+}
+
+/// An Ed25519 key held in memory.
+pub struct LocalSigner {
+    key_pair: Ed25519KeyPair,
+}
+
+impl LocalSigner {
+    /// From a 32 byte seed in hex, with or without a `0x` prefix. The
+    /// decoded copy is wiped once the key pair is built.
+    pub fn from_hex(seed: &str) -> Result<Self> {
+        let seed = Zeroizing::new(
+            hex::decode(seed.trim().trim_start_matches("0x"))
+                .map_err(|_| signer_error("the private key is not hex"))?,
+        );
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed)
+            .map_err(|_| signer_error("the private key must be a 32 byte seed"))?;
+        Ok(Self { key_pair })
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for LocalSigner {
+    fn backend(&self) -> &'static str {
+        "local"
+    }
+
+    fn public_key(&self) -> &[u8] {
+        self.key_pair.public_key().as_ref()
+    }
+
+    async fn sign(&self, digest: &[u8; 32]) -> Result<Vec<u8>> {
+        Ok(self.key_pair.sign(digest).as_ref().to_vec())
+    }
+}
+
+/// An asymmetric AWS KMS key, used through the KMS `Sign` API with the
+/// credentials in the standard `AWS_*` variables. KMS returns ECDSA
+/// signatures DER encoded.
+pub struct AwsKmsSigner {
+    client: reqwest::Client,
+    key_id: String,
+    region: String,
+    signing_algorithm: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    public_key: Vec<u8>,
+}
+
+impl AwsKmsSigner {
+    /// Fetches the key's public half, which also checks the key exists and
+    /// may be used.
+    pub async fn connect(config: &SignerConfig) -> Result<Self> {
+        let env = |name: &str| {
+            std::env::var(name).map_err(|_| signer_error(&format!("{} must be set for the aws_kms signer", name)))
+        };
+        let mut signer = Self {
+            client: reqwest::Client::builder()
+                .timeout(KMS_TIMEOUT)
+                .build()
+                .map_err(|e| signer_error(&e.to_string()))?,
+            key_id: config.kms_key_id.clone(),
+            region: config.kms_region.clone(),
+            signing_algorithm: config.kms_signing_algorithm.clone(),
+            access_key_id: env("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: env("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            public_key: Vec::new(),
+        };
+        let response = signer
+            .call("GetPublicKey", serde_json::json!({ "KeyId": signer.key_id }))
+            .await?;
+        signer.public_key = decode_blob(&response, "PublicKey")?;
+        Ok(signer)
+    }
+
+    /// Posts a KMS API action, signed with AWS Signature Version 4.
+    async fn call(&self, action: &str, body: serde_json::Value) -> Result<serde_json::Value> {
+        let host = format!("kms.{}.amazonaws.com", self.region);
+        let body = body.to_string();
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.push(("x-amz-target", format!("TrentService.{}", action)));
+        let authorization = sigv4_authorization(
+            "POST",
+            "/",
+            &self.access_key_id,
+            &self.secret_access_key,
+            &self.region,
+            "kms",
+            &amz_date,
+            &headers,
+            body.as_bytes(),
+        );
+
+        let mut request = self.client.post(format!("https://{}/", host)).body(body);
+        for (header, value) in headers.iter().filter(|(header, _)| *header != "host") {
+            request = request.header(*header, value);
+        }
+        let response = request
+            .header("authorization", authorization)
+            .send()
+            .await
+            .map_err(|e| signer_error(&format!("KMS {} failed: {}", action, e)))?;
+        let status = response.status();
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| signer_error(&format!("KMS {} returned an invalid response: {}", action, e)))?;
+        if !status.is_success() {
+            return Err(signer_error(&format!(
+                "KMS {} failed with {}: {}",
+                action,
+                status,
+                body["message"]
+                    .as_str()
+                    .or(body["Message"].as_str())
+                    .unwrap_or("no message")
+            )));
+        }
+        Ok(body)
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for AwsKmsSigner {
+    fn backend(&self) -> &'static str {
+        "aws_kms"
+    }
+
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    async fn sign(&self, digest: &[u8; 32]) -> Result<Vec<u8>> {
+        let response = self
+            .call(
+                "Sign",
+                serde_json::json!({
+                    "KeyId": self.key_id,
+                    "Message": STANDARD.encode(digest),
+                    "MessageType": "DIGEST",
+                    "SigningAlgorithm": self.signing_algorithm,
+                }),
+            )
+            .await?;
+        decode_blob(&response, "Signature")
+    }
+}
+
+/// A base64 field of a KMS response.
+fn decode_blob(response: &serde_json::Value, field: &str) -> Result<Vec<u8>> {
+    response[field]
+        .as_str()
+        .and_then(|blob| STANDARD.decode(blob).ok())
+        .ok_or_else(|| signer_error(&format!("KMS response has no valid {}", field)))
+}
+
+fn signer_error(message: &str) -> CryptoTradeError {
+    CryptoTradeError::Signer {
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ED25519};
+
+    #[tokio::test]
+    async fn test_local_signatures_verify_against_the_public_key() {
+        let signer = LocalSigner::from_hex(&format!("0x{}", "11".repeat(32))).unwrap();
+        let digest = [7u8; 32];
+
+        let signature = signer.sign(&digest).await.unwrap();
+        assert_eq!(signature.len(), 64);
+        assert!(UnparsedPublicKey::new(&ED25519, signer.public_key())
+            .verify(&digest, &signature)
+            .is_ok());
+        assert!(UnparsedPublicKey::new(&ED25519, signer.public_key())
+            .verify(&[8u8; 32], &signature)
+            .is_err());
+
+        assert!(matches!(
+            LocalSigner::from_hex("0x1234"),
+            Err(CryptoTradeError::Signer { .. })
+        ));
+        assert!(matches!(
+            LocalSigner::from_hex("not hex"),
+            Err(CryptoTradeError::Signer { .. })
+        ));
+    }
+}
@@ -0,0 +1,352 @@
+//! Object storage for files the exchange keeps outside the database:
+//! generated documents, export files and archived ledger entries. Objects
+//! are written once under a key such as `documents/<user>/<id>.pdf`;
+//! writing the same key again replaces it, so a job interrupted after
+//! writing can simply run again. Clients download through presigned URLs,
+//! which stop working once they expire.
+
+use crate::{
+    config::{StorageBackend, StorageConfig},
+    error::CryptoTradeError,
+    secrets::{hmac, sigv4_authorization, signing_key},
+    Result,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Route the API serves local objects from, followed by the key
+pub const LOCAL_DOWNLOAD_PATH: &str = "/api/v1/storage/";
+
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    async fn put(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<()>;
+
+    /// The object's bytes, or `None` if there is no such key.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// A URL anyone holding it can download the object from until
+    /// `expires_at`.
+    fn presigned_url(&self, key: &str, expires_at: DateTime<Utc>) -> String;
+
+    /// Whether `signature` is this store's signature of a download URL for
+    /// `key` expiring at `expires` (Unix seconds). Only stores the API
+    /// serves itself sign their own URLs.
+    fn verify_signature(&self, _key: &str, _expires: i64, _signature: &str) -> bool {
+        false
+    }
+}
+
+/// A presigned URL and when it stops working.
+pub struct PresignedUrl {
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Builds the store selected by `storage.backend`. Local download URLs are
+/// signed with `signing_secret`.
+pub fn object_store(config: &StorageConfig, signing_secret: &str) -> Result<Arc<dyn ObjectStore>> {
+    Ok(match config.backend {
+        StorageBackend::Local => Arc::new(LocalStorage {
+            dir: PathBuf::from(&config.dir),
+            signing_secret: signing_secret.as_bytes().to_vec(),
+        }),
+        StorageBackend::S3 => Arc::new(S3Storage {
+            client: reqwest::Client::new(),
+            endpoint: config.endpoint.trim_end_matches('/').to_string(),
+            bucket: config.bucket.clone(),
+            region: config.region.clone(),
+            access_key_id: required_env("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: required_env("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        }),
+    })
+}
+
+/// Presigns `key` for `ttl` from now.
+pub fn presign(store: &dyn ObjectStore, key: &str, ttl: Duration) -> PresignedUrl {
+    let expires_at = Utc::now() + chrono::Duration::from_std(ttl).unwrap_or_else(|_| chrono::Duration::minutes(15));
+    PresignedUrl {
+        url: store.presigned_url(key, expires_at),
+        expires_at,
+    }
+}
+
+/// The content type to serve a key with, from its extension.
+pub fn content_type(key: &str) -> &'static str {
+    match key.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("pdf") => "application/pdf",
+        Some("ndjson") => "application/x-ndjson",
+        Some("csv") => "text/csv",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Files under a directory, for development and single-host deployments.
+/// The API serves them itself at [`LOCAL_DOWNLOAD_PATH`].
+pub struct LocalStorage {
+    pub dir: PathBuf,
+    pub signing_secret: Vec<u8>,
+}
+
+impl LocalStorage {
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        // Keys come from the exchange, but the download route takes them
+        // from the URL: nothing may climb out of the directory
+        if key.is_empty() || key.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+            return Err(CryptoTradeError::NotFound {
+                message: "Object not found".to_string(),
+            });
+        }
+        Ok(self.dir.join(key))
+    }
+
+    fn signature(&self, key: &str, expires: i64) -> Vec<u8> {
+        hmac(&self.signing_secret, format!("{}\n{}", key, expires).as_bytes())
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LocalStorage {
+    async fn put(&self, key: &str, body: Vec<u8>, _content_type: &str) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Written aside and renamed, so a crash never leaves half an object
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, body).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn presigned_url(&self, key: &str, expires_at: DateTime<Utc>) -> String {
+        let expires = expires_at.timestamp();
+        format!(
+            "{}{}?expires={}&signature={}",
+            LOCAL_DOWNLOAD_PATH,
+            uri_encode_path(key),
+            expires,
+            hex::encode(self.signature(key, expires))
+        )
+    }
+
+    fn verify_signature(&self, key: &str, expires: i64, signature: &str) -> bool {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        // verify_slice compares in constant time
+        Hmac::<Sha256>::new_from_slice(&self.signing_secret)
+            .expect("HMAC accepts keys of any length")
+            .chain_update(format!("{}\n{}", key, expires).as_bytes())
+            .verify_slice(&signature)
+            .is_ok()
+    }
+}
+
+/// A bucket on any S3-compatible service, addressed path-style so MinIO
+/// and other self-hosted services work without DNS per bucket.
+pub struct S3Storage {
+    client: reqwest::Client,
+    endpoint: String,
+    bucket: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Storage {
+    fn host(&self) -> String {
+        self.endpoint
+            .split_once("://")
+            .map_or(self.endpoint.as_str(), |(_, host)| host)
+            .to_string()
+    }
+
+    fn path(&self, key: &str) -> String {
+        format!("/{}/{}", self.bucket, uri_encode_path(key))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for S3Storage {
+    async fn put(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<()> {
+        let path = self.path(key);
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut headers = vec![
+            ("content-type", content_type.to_string()),
+            ("host", self.host()),
+            ("x-amz-content-sha256", hex::encode(Sha256::digest(&body))),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let authorization = sigv4_authorization(
+            "PUT",
+            &path,
+            &self.access_key_id,
+            &self.secret_access_key,
+            &self.region,
+            "s3",
+            &amz_date,
+            &headers,
+            &body,
+        );
+
+        let mut request = self.client.put(format!("{}{}", self.endpoint, path)).body(body);
+        for (header, value) in headers.iter().filter(|(header, _)| *header != "host") {
+            request = request.header(*header, value);
+        }
+        request
+            .header("authorization", authorization)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| storage_error(key, e))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let url = self.presigned_url(key, Utc::now() + chrono::Duration::minutes(1));
+        let response = self.client.get(url).send().await.map_err(|e| storage_error(key, e))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = response
+            .error_for_status()
+            .map_err(|e| storage_error(key, e))?
+            .bytes()
+            .await
+            .map_err(|e| storage_error(key, e))?;
+        Ok(Some(body.to_vec()))
+    }
+
+    /// A SigV4 query-string presigned GET.
+    fn presigned_url(&self, key: &str, expires_at: DateTime<Utc>) -> String {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let expires_in = (expires_at - now).num_seconds().clamp(1, 604_800);
+
+        // Already in the sorted order SigV4 signs them in
+        let mut query = vec![
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+            ("X-Amz-Credential", format!("{}/{}", self.access_key_id, scope)),
+            ("X-Amz-Date", amz_date.clone()),
+            ("X-Amz-Expires", expires_in.to_string()),
+        ];
+        if let Some(token) = &self.session_token {
+            query.push(("X-Amz-Security-Token", token.clone()));
+        }
+        query.push(("X-Amz-SignedHeaders", "host".to_string()));
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, uri_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let path = self.path(key);
+        let canonical_request = format!("GET\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD", path, query, self.host());
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signature = hmac(
+            &signing_key(&self.secret_access_key, date, &self.region, "s3"),
+            string_to_sign.as_bytes(),
+        );
+
+        format!("{}{}?{}&X-Amz-Signature={}", self.endpoint, path, query, hex::encode(signature))
+    }
+}
+
+fn storage_error(key: &str, e: reqwest::Error) -> CryptoTradeError {
+    CryptoTradeError::Io(std::io::Error::other(format!("object storage request for {} failed: {}", key, e)))
+}
+
+/// Percent-encodes a key as SigV4 expects, keeping the `/` separators.
+fn uri_encode_path(key: &str) -> String {
+    key.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+}
+
+fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn required_env(name: &str) -> Result<String> {
+    std::env::var(name).map_err(|_| {
+        CryptoTradeError::Config(config::ConfigError::Message(format!(
+            "{} must be set for s3 object storage",
+            name
+        )))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local() -> LocalStorage {
+        LocalStorage {
+            dir: std::env::temp_dir().join(format!("cryptotrade-storage-{}", uuid::Uuid::new_v4())),
+            signing_secret: b"secret".to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_uri_encode_path_keeps_separators() {
+        assert_eq!(uri_encode_path("ledger/2024-05/a b+c.ndjson"), "ledger/2024-05/a%20b%2Bc.ndjson");
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_round_trips_and_refuses_escaping_keys() {
+        let storage = local();
+
+        storage.put("ledger/2024-05/1.ndjson", b"{}\n".to_vec(), "application/x-ndjson").await.unwrap();
+
+        assert_eq!(storage.get("ledger/2024-05/1.ndjson").await.unwrap().unwrap(), b"{}\n");
+        assert!(storage.get("ledger/2024-05/2.ndjson").await.unwrap().is_none());
+        assert!(storage.get("../etc/passwd").await.is_err());
+        std::fs::remove_dir_all(&storage.dir).unwrap();
+    }
+
+    #[test]
+    fn test_local_presigned_urls_verify_only_for_their_key_and_expiry() {
+        let storage = local();
+        let url = storage.presigned_url("documents/a.pdf", DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+        let signature = url.rsplit_once("signature=").unwrap().1;
+
+        assert!(url.starts_with("/api/v1/storage/documents/a.pdf?expires=1700000000&"));
+        assert!(storage.verify_signature("documents/a.pdf", 1_700_000_000, signature));
+        assert!(!storage.verify_signature("documents/b.pdf", 1_700_000_000, signature));
+        assert!(!storage.verify_signature("documents/a.pdf", 1_700_000_001, signature));
+    }
+}
@@ -0,0 +1,19 @@
+-- Append-only matching engine journal. Every input (order added, cancel/amend
+-- requested) and output (matched, cancelled, amended, rejected) is recorded
+-- with a per-pair sequence number so book state can be rebuilt by replay.
+CREATE TABLE engine_events (
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    sequence BIGINT NOT NULL,
+    event_type VARCHAR(32) NOT NULL,
+    order_id UUID,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (trading_pair_id, sequence)
+);
+
+CREATE INDEX idx_engine_events_order_id ON engine_events(order_id);
+CREATE INDEX idx_engine_events_created_at ON engine_events(created_at);
+
+-- Journal rows are never updated or removed
+CREATE RULE engine_events_no_update AS ON UPDATE TO engine_events DO INSTEAD NOTHING;
+CREATE RULE engine_events_no_delete AS ON DELETE TO engine_events DO INSTEAD NOTHING;
@@ -0,0 +1,9 @@
+-- Periodic order book snapshots; recovery loads the latest one and replays
+-- engine_events with a higher sequence on top of it.
+CREATE TABLE order_book_snapshots (
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    sequence BIGINT NOT NULL,
+    orders JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (trading_pair_id, sequence)
+);
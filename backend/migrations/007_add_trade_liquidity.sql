@@ -0,0 +1,10 @@
+-- Record which side took liquidity so fees and fills can tell maker from taker.
+ALTER TABLE trades ADD COLUMN taker_side order_side;
+
+-- Existing trades: the order placed later crossed the one resting on the book
+UPDATE trades t
+SET taker_side = CASE WHEN buyer.created_at > seller.created_at THEN 'buy'::order_side ELSE 'sell'::order_side END
+FROM orders buyer, orders seller
+WHERE buyer.id = t.buyer_order_id AND seller.id = t.seller_order_id;
+
+ALTER TABLE trades ALTER COLUMN taker_side SET NOT NULL;
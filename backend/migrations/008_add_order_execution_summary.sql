@@ -0,0 +1,17 @@
+-- Running execution summary, maintained on every fill.
+ALTER TABLE orders ADD COLUMN cumulative_quote_quantity DECIMAL(20, 8) DEFAULT 0;
+ALTER TABLE orders ADD COLUMN average_fill_price DECIMAL(20, 8);
+
+UPDATE orders o
+SET cumulative_quote_quantity = fills.quote_quantity,
+    average_fill_price = fills.quote_quantity / fills.quantity
+FROM (
+    SELECT order_id, SUM(price * quantity) AS quote_quantity, SUM(quantity) AS quantity
+    FROM (
+        SELECT buyer_order_id AS order_id, price, quantity FROM trades
+        UNION ALL
+        SELECT seller_order_id AS order_id, price, quantity FROM trades
+    ) matched
+    GROUP BY order_id
+) fills
+WHERE o.id = fills.order_id AND fills.quantity > 0;
@@ -0,0 +1,33 @@
+-- Double-entry ledger: every balance change is posted as a journal whose
+-- entries sum to zero per currency. Entries without a user belong to the
+-- exchange itself (collected fees, and the external side of deposits and
+-- withdrawals).
+CREATE TYPE ledger_entry_type AS ENUM ('trade', 'fee', 'deposit', 'withdrawal', 'transfer', 'adjustment');
+
+CREATE TABLE ledger_entries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    sequence BIGSERIAL NOT NULL,
+    journal_id UUID NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    currency VARCHAR(10) NOT NULL,
+    entry_type ledger_entry_type NOT NULL,
+    amount DECIMAL(20, 8) NOT NULL,
+    balance_after DECIMAL(20, 8),
+    reference_id UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_ledger_entries_user_currency ON ledger_entries(user_id, currency, sequence);
+CREATE INDEX idx_ledger_entries_journal_id ON ledger_entries(journal_id);
+CREATE INDEX idx_ledger_entries_created_at ON ledger_entries(created_at);
+
+-- Open the ledger with each existing balance so running balances reconcile
+INSERT INTO ledger_entries (journal_id, user_id, currency, entry_type, amount, balance_after, reference_id)
+SELECT id, user_id, currency, 'adjustment', balance, balance, id
+FROM accounts
+WHERE balance <> 0;
+
+INSERT INTO ledger_entries (journal_id, user_id, currency, entry_type, amount, reference_id)
+SELECT id, NULL, currency, 'adjustment', -balance, id
+FROM accounts
+WHERE balance <> 0;
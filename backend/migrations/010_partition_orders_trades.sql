@@ -0,0 +1,90 @@
+-- Monthly range partitions on created_at for orders and trades. Partitions
+-- are created ahead of time by the API's partition maintenance job, which
+-- also applies the configured retention; the default partitions only catch
+-- rows the job has not made room for yet.
+
+-- Creates the partition of `parent` holding `month` and returns its name,
+-- e.g. trades_p202406. Does nothing if it already exists.
+CREATE OR REPLACE FUNCTION create_monthly_partition(parent TEXT, month DATE) RETURNS TEXT AS $$
+DECLARE
+    start_date DATE := date_trunc('month', month)::date;
+    partition_name TEXT := parent || '_p' || to_char(start_date, 'YYYYMM');
+BEGIN
+    IF to_regclass(quote_ident(partition_name)) IS NULL THEN
+        EXECUTE format(
+            'CREATE TABLE %I PARTITION OF %I FOR VALUES FROM (%L) TO (%L)',
+            partition_name, parent, start_date, (start_date + INTERVAL '1 month')::date
+        );
+    END IF;
+    RETURN partition_name;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Creates partitions from the month of `since` through `months_ahead` months
+-- after the current one.
+CREATE OR REPLACE FUNCTION create_monthly_partitions(parent TEXT, since TIMESTAMPTZ, months_ahead INTEGER) RETURNS VOID AS $$
+DECLARE
+    month DATE := date_trunc('month', COALESCE(since, NOW()))::date;
+BEGIN
+    WHILE month <= date_trunc('month', NOW() + make_interval(months => months_ahead))::date LOOP
+        PERFORM create_monthly_partition(parent, month);
+        month := (month + INTERVAL '1 month')::date;
+    END LOOP;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Detached partitions are moved here when retention archives them
+CREATE SCHEMA IF NOT EXISTS archive;
+
+-- A foreign key to a partitioned table must cover its whole primary key,
+-- which now includes created_at, so trades lose theirs to orders
+ALTER TABLE trades DROP CONSTRAINT trades_buyer_order_id_fkey;
+ALTER TABLE trades DROP CONSTRAINT trades_seller_order_id_fkey;
+
+-- Orders
+UPDATE orders SET created_at = NOW() WHERE created_at IS NULL;
+ALTER TABLE orders RENAME TO orders_unpartitioned;
+ALTER TABLE orders_unpartitioned RENAME CONSTRAINT orders_pkey TO orders_unpartitioned_pkey;
+
+CREATE TABLE orders (LIKE orders_unpartitioned INCLUDING DEFAULTS) PARTITION BY RANGE (created_at);
+ALTER TABLE orders ALTER COLUMN created_at SET NOT NULL;
+ALTER TABLE orders ADD PRIMARY KEY (id, created_at);
+ALTER TABLE orders ADD FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE orders ADD FOREIGN KEY (trading_pair_id) REFERENCES trading_pairs(id);
+CREATE TABLE orders_default PARTITION OF orders DEFAULT;
+
+SELECT create_monthly_partitions('orders', (SELECT MIN(created_at) FROM orders_unpartitioned), 3);
+INSERT INTO orders SELECT * FROM orders_unpartitioned;
+DROP TABLE orders_unpartitioned;
+
+CREATE INDEX idx_orders_id ON orders(id);
+CREATE INDEX idx_orders_user_id ON orders(user_id);
+CREATE INDEX idx_orders_trading_pair_id ON orders(trading_pair_id);
+CREATE INDEX idx_orders_status ON orders(status);
+CREATE INDEX idx_orders_side_price ON orders(side, price) WHERE status = 'open';
+CREATE INDEX idx_orders_created_at ON orders(created_at);
+
+-- Trades
+UPDATE trades SET created_at = NOW() WHERE created_at IS NULL;
+ALTER TABLE trades RENAME TO trades_unpartitioned;
+ALTER TABLE trades_unpartitioned RENAME CONSTRAINT trades_pkey TO trades_unpartitioned_pkey;
+
+CREATE TABLE trades (LIKE trades_unpartitioned INCLUDING DEFAULTS) PARTITION BY RANGE (created_at);
+ALTER TABLE trades ALTER COLUMN created_at SET NOT NULL;
+ALTER TABLE trades ADD PRIMARY KEY (id, created_at);
+ALTER TABLE trades ADD FOREIGN KEY (trading_pair_id) REFERENCES trading_pairs(id);
+ALTER TABLE trades ADD FOREIGN KEY (buyer_user_id) REFERENCES users(id);
+ALTER TABLE trades ADD FOREIGN KEY (seller_user_id) REFERENCES users(id);
+CREATE TABLE trades_default PARTITION OF trades DEFAULT;
+
+SELECT create_monthly_partitions('trades', (SELECT MIN(created_at) FROM trades_unpartitioned), 3);
+INSERT INTO trades SELECT * FROM trades_unpartitioned;
+DROP TABLE trades_unpartitioned;
+
+CREATE INDEX idx_trades_id ON trades(id);
+CREATE INDEX idx_trades_trading_pair_id ON trades(trading_pair_id);
+CREATE INDEX idx_trades_buyer_user_id ON trades(buyer_user_id);
+CREATE INDEX idx_trades_seller_user_id ON trades(seller_user_id);
+CREATE INDEX idx_trades_buyer_order_id ON trades(buyer_order_id);
+CREATE INDEX idx_trades_seller_order_id ON trades(seller_order_id);
+CREATE INDEX idx_trades_created_at ON trades(created_at);
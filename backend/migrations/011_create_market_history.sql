@@ -0,0 +1,32 @@
+-- External market history loaded by the importer so new pairs launch with
+-- chart history. Kept apart from trades, which belong to real orders and
+-- users; the candlestick query reads both.
+
+CREATE TABLE imported_trades (
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    source TEXT NOT NULL,
+    -- The dump's trade id, or one derived from the row when it has none
+    external_id TEXT NOT NULL,
+    price DECIMAL(20, 8) NOT NULL,
+    quantity DECIMAL(20, 8) NOT NULL,
+    taker_side order_side,
+    traded_at TIMESTAMPTZ NOT NULL,
+    imported_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (trading_pair_id, source, external_id)
+);
+
+CREATE INDEX idx_imported_trades_pair_time ON imported_trades(trading_pair_id, traded_at);
+
+CREATE TABLE candlesticks (
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    interval_minutes INTEGER NOT NULL,
+    open_time TIMESTAMPTZ NOT NULL,
+    open DECIMAL(20, 8) NOT NULL,
+    high DECIMAL(20, 8) NOT NULL,
+    low DECIMAL(20, 8) NOT NULL,
+    close DECIMAL(20, 8) NOT NULL,
+    volume DECIMAL(28, 8) NOT NULL,
+    source TEXT NOT NULL,
+    imported_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (trading_pair_id, interval_minutes, open_time)
+);
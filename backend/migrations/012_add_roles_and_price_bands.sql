@@ -0,0 +1,8 @@
+-- Roles gate the admin endpoints. There is no API to grant one; promote the
+-- first admin with: UPDATE users SET role = 'admin' WHERE email = '...';
+ALTER TABLE users ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'user';
+
+-- Limit prices further than this from the pair's last trade are rejected;
+-- NULL leaves the pair unbanded
+ALTER TABLE trading_pairs ADD COLUMN price_band_percent DECIMAL(5, 2)
+    CHECK (price_band_percent > 0);
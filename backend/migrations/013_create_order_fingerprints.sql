@@ -0,0 +1,10 @@
+-- The most recent placement of each distinct order (pair, side, price,
+-- quantity) per user, for rejecting accidental repeats. Claiming a
+-- fingerprint is a single upsert, so concurrent double submits cannot both
+-- pass; rows past the window are cleared as the user places new orders.
+CREATE TABLE order_fingerprints (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    fingerprint TEXT NOT NULL,
+    placed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, fingerprint)
+);
@@ -0,0 +1,8 @@
+-- Per-user overrides of the configured risk limits; a NULL column falls
+-- back to the configured default
+CREATE TABLE user_risk_limits (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    max_open_orders_per_pair INTEGER CHECK (max_open_orders_per_pair >= 0),
+    max_open_notional DECIMAL(30, 8) CHECK (max_open_notional >= 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,25 @@
+-- Least-privilege access for bots and integrations. Permissions and pairs
+-- can be narrowed per API key, and by admins for a whole user; NULL means
+-- unrestricted.
+CREATE TYPE trading_permission AS ENUM ('read', 'spot_trade', 'margin', 'withdraw');
+
+ALTER TABLE users
+    ADD COLUMN permissions trading_permission[],
+    ADD COLUMN trading_pair_ids UUID[];
+
+CREATE TABLE api_keys (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    label VARCHAR(100) NOT NULL,
+    -- Public half, sent with every request
+    key VARCHAR(64) NOT NULL UNIQUE,
+    -- SHA-256 of the secret, which is shown only once
+    secret_hash VARCHAR(64) NOT NULL,
+    permissions trading_permission[] NOT NULL,
+    trading_pair_ids UUID[],
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_api_keys_user ON api_keys(user_id);
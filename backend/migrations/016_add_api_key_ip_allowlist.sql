@@ -0,0 +1,3 @@
+-- Networks (CIDR, or single addresses) an API key may be used from; NULL
+-- allows any address
+ALTER TABLE api_keys ADD COLUMN ip_allowlist TEXT[];
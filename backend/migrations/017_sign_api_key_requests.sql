@@ -0,0 +1,8 @@
+-- Requests made with an API key are now HMAC-signed, which needs the secret
+-- itself rather than its hash. Keys issued before this cannot sign and are
+-- revoked; their owners create new ones.
+ALTER TABLE api_keys ADD COLUMN secret VARCHAR(64);
+
+UPDATE api_keys SET revoked_at = NOW() WHERE revoked_at IS NULL;
+
+ALTER TABLE api_keys DROP COLUMN secret_hash;
@@ -0,0 +1,10 @@
+-- Settings admins change while the API runs. Each row overrides one field of
+-- the defaults derived from the static config; deleting it restores the
+-- default. Changes are announced on the runtime_settings channel so every
+-- instance reloads.
+CREATE TABLE runtime_settings (
+    key VARCHAR(64) PRIMARY KEY,
+    value JSONB NOT NULL,
+    updated_by UUID REFERENCES users(id),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,14 @@
+-- Admin overrides of the feature flags the code declares. A flag without a
+-- row keeps its built-in default; deleting a row restores it. Changes are
+-- announced on the feature_flags channel so every instance reloads.
+CREATE TABLE feature_flags (
+    key VARCHAR(64) PRIMARY KEY,
+    enabled BOOLEAN NOT NULL,
+    -- Environments the flag applies in; empty means all of them
+    environments TEXT[] NOT NULL DEFAULT '{}',
+    -- Users who get the flag regardless of enabled and rollout_percent
+    user_ids UUID[] NOT NULL DEFAULT '{}',
+    rollout_percent SMALLINT NOT NULL DEFAULT 100 CHECK (rollout_percent BETWEEN 0 AND 100),
+    updated_by UUID REFERENCES users(id),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
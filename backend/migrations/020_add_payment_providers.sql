@@ -0,0 +1,8 @@
+-- Fiat deposits through payment providers are recorded as deposit
+-- transactions; provider names the provider and external_id is its id for
+-- the payment, so each provider notification settles at most one deposit.
+ALTER TABLE transactions ADD COLUMN provider VARCHAR(32);
+
+CREATE UNIQUE INDEX idx_transactions_provider_external_id
+    ON transactions(provider, external_id)
+    WHERE provider IS NOT NULL;
@@ -0,0 +1,37 @@
+-- Bank accounts users withdraw fiat to. An account is usable once the user
+-- confirms the two small deposits sent to it.
+CREATE TYPE bank_rail AS ENUM ('sepa', 'ach');
+CREATE TYPE bank_account_status AS ENUM ('pending_verification', 'verified', 'verification_failed', 'removed');
+
+CREATE TABLE bank_accounts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    rail bank_rail NOT NULL,
+    holder_name VARCHAR(140) NOT NULL,
+    -- IBAN for SEPA, account number for ACH
+    account_number VARCHAR(34) NOT NULL,
+    -- BIC for SEPA, routing number for ACH
+    routing_code VARCHAR(11) NOT NULL,
+    currency VARCHAR(10) NOT NULL,
+    status bank_account_status NOT NULL DEFAULT 'pending_verification',
+    micro_deposits DECIMAL(20, 8)[] NOT NULL,
+    verification_attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    verified_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_bank_accounts_user_id ON bank_accounts(user_id);
+CREATE INDEX idx_bank_accounts_pending ON bank_accounts(created_at) WHERE status = 'pending_verification';
+
+-- Fiat withdrawals are withdrawal transactions to a bank account. They wait
+-- in 'pending' for an admin, stay 'approved' while the transfer is sent and
+-- end 'confirmed', or 'cancelled'/'failed' with the funds released.
+ALTER TYPE transaction_status ADD VALUE 'approved' AFTER 'pending';
+
+ALTER TABLE transactions
+    ADD COLUMN bank_account_id UUID REFERENCES bank_accounts(id),
+    ADD COLUMN reviewed_by UUID REFERENCES users(id),
+    ADD COLUMN note TEXT;
+
+CREATE INDEX idx_transactions_fiat_withdrawals ON transactions(status, created_at)
+    WHERE bank_account_id IS NOT NULL;
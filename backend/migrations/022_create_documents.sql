@@ -0,0 +1,22 @@
+-- PDF documents users download: receipts for deposits and withdrawals,
+-- trade confirmations and monthly statements. A request is queued as
+-- pending and rendered in the background. subject is what the document is
+-- about (a transaction or trade id, or a statement's month as YYYY-MM), so
+-- asking again for the same document returns the one already made.
+CREATE TYPE document_kind AS ENUM ('receipt', 'trade_confirmation', 'statement');
+CREATE TYPE document_status AS ENUM ('pending', 'ready', 'failed');
+
+CREATE TABLE documents (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind document_kind NOT NULL,
+    subject VARCHAR(64) NOT NULL,
+    status document_status NOT NULL DEFAULT 'pending',
+    content BYTEA,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+    UNIQUE (user_id, kind, subject)
+);
+
+CREATE INDEX idx_documents_pending ON documents(created_at) WHERE status = 'pending';
@@ -0,0 +1,25 @@
+-- Account lifecycle: active -> restricted -> closed. Accounts are never
+-- deleted. A restricted account can only wind down, by cancelling orders
+-- and withdrawing; a closed one keeps its history readable but can no
+-- longer transact, and closing is final. is_active still decides whether
+-- the account may log in at all.
+CREATE TYPE account_status AS ENUM ('active', 'restricted', 'closed');
+
+ALTER TABLE users
+    ADD COLUMN account_status account_status NOT NULL DEFAULT 'active',
+    ADD COLUMN status_reason TEXT,
+    ADD COLUMN closed_at TIMESTAMPTZ;
+
+-- Every status change, and who made it; changed_by is NULL when users close
+-- their own account
+CREATE TABLE account_status_changes (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    from_status account_status NOT NULL,
+    to_status account_status NOT NULL,
+    reason TEXT NOT NULL,
+    changed_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_account_status_changes_user ON account_status_changes(user_id, created_at);
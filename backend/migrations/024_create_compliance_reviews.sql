@@ -0,0 +1,24 @@
+-- Country of residence as declared at registration, or where the request
+-- came from when none was declared (ISO 3166-1 alpha-2)
+ALTER TABLE users ADD COLUMN country CHAR(2);
+
+CREATE TYPE compliance_review_status AS ENUM ('pending', 'cleared', 'confirmed');
+
+-- Possible sanctions list matches awaiting a compliance officer. A user
+-- with a pending review is restricted until every review is cleared.
+CREATE TABLE compliance_reviews (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    screened_name TEXT NOT NULL,
+    listed_name TEXT NOT NULL,
+    list_name TEXT NOT NULL,
+    score DECIMAL(5, 4) NOT NULL,
+    status compliance_review_status NOT NULL DEFAULT 'pending',
+    note TEXT,
+    reviewed_by UUID REFERENCES users(id),
+    reviewed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_compliance_reviews_status ON compliance_reviews(status, created_at);
+CREATE INDEX idx_compliance_reviews_user ON compliance_reviews(user_id);
@@ -0,0 +1,22 @@
+-- Travel rule: withdrawals at or above a per-currency threshold must carry
+-- originator and beneficiary information, passed on to the receiving
+-- institution, before an admin may release them.
+ALTER TABLE transactions ADD COLUMN travel_rule_required BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TYPE travel_rule_status AS ENUM ('pending', 'transmitted', 'failed');
+
+CREATE TABLE travel_rule_records (
+    withdrawal_id UUID PRIMARY KEY REFERENCES transactions(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- The payload as sent to the provider
+    payload JSONB NOT NULL,
+    provider VARCHAR(20) NOT NULL,
+    status travel_rule_status NOT NULL DEFAULT 'pending',
+    -- The provider's id for the transfer once transmitted
+    reference TEXT,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    transmitted_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_travel_rule_records_user ON travel_rule_records(user_id);
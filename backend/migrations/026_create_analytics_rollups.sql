@@ -0,0 +1,30 @@
+-- Daily rollups behind the admin dashboards, refreshed by a background
+-- task so dashboard queries never scan trades or the ledger. Days are UTC.
+CREATE TABLE analytics_daily (
+    day DATE PRIMARY KEY,
+    -- Users who placed an order or took part in a trade
+    active_traders INTEGER NOT NULL,
+    new_registrations INTEGER NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE analytics_daily_pairs (
+    day DATE NOT NULL,
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id) ON DELETE CASCADE,
+    trade_count INTEGER NOT NULL,
+    base_volume DECIMAL(28, 8) NOT NULL,
+    quote_volume DECIMAL(28, 8) NOT NULL,
+    PRIMARY KEY (day, trading_pair_id)
+);
+
+-- Fees the exchange collected and money users moved in and out, by currency
+CREATE TABLE analytics_daily_currencies (
+    day DATE NOT NULL,
+    currency VARCHAR(10) NOT NULL,
+    fee_revenue DECIMAL(28, 8) NOT NULL,
+    deposits DECIMAL(28, 8) NOT NULL,
+    deposit_count INTEGER NOT NULL,
+    withdrawals DECIMAL(28, 8) NOT NULL,
+    withdrawal_count INTEGER NOT NULL,
+    PRIMARY KEY (day, currency)
+);
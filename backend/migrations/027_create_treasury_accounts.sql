@@ -0,0 +1,31 @@
+-- The exchange's treasury: one account per currency holding the trading and
+-- withdrawal fees collected. Ledger entries without a user of type 'fee'
+-- move it; a sweep pays part of it out to the exchange's operating funds.
+CREATE TABLE treasury_accounts (
+    currency VARCHAR(10) PRIMARY KEY,
+    balance DECIMAL(28, 8) NOT NULL DEFAULT 0 CHECK (balance >= 0),
+    total_collected DECIMAL(28, 8) NOT NULL DEFAULT 0,
+    total_swept DECIMAL(28, 8) NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE treasury_sweeps (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    currency VARCHAR(10) NOT NULL REFERENCES treasury_accounts(currency),
+    amount DECIMAL(28, 8) NOT NULL CHECK (amount > 0),
+    -- Where the funds went, e.g. the operating bank account
+    destination VARCHAR(140) NOT NULL,
+    note TEXT,
+    swept_by UUID NOT NULL REFERENCES users(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_treasury_sweeps_currency ON treasury_sweeps(currency, created_at);
+
+-- Fees collected before the treasury existed
+INSERT INTO treasury_accounts (currency, balance, total_collected)
+SELECT currency, SUM(amount), SUM(amount)
+FROM ledger_entries
+WHERE user_id IS NULL AND entry_type = 'fee'
+GROUP BY currency
+HAVING SUM(amount) > 0;
@@ -0,0 +1,24 @@
+-- Small leftover balances a user swept into one currency. Each item is one
+-- source balance, converted at the oracle price with the fee taken from the
+-- converted amount.
+CREATE TABLE dust_conversions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    target_currency VARCHAR(10) NOT NULL,
+    -- Credited to the user, after fees
+    amount DECIMAL(28, 8) NOT NULL,
+    fee DECIMAL(28, 8) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE dust_conversion_items (
+    conversion_id UUID NOT NULL REFERENCES dust_conversions(id) ON DELETE CASCADE,
+    currency VARCHAR(10) NOT NULL,
+    amount DECIMAL(28, 8) NOT NULL,
+    price DECIMAL(28, 12) NOT NULL,
+    converted DECIMAL(28, 8) NOT NULL,
+    fee DECIMAL(28, 8) NOT NULL,
+    PRIMARY KEY (conversion_id, currency)
+);
+
+CREATE INDEX idx_dust_conversions_user_id ON dust_conversions(user_id, created_at);
@@ -0,0 +1,11 @@
+-- Orders placed ahead of time. A scheduled order stays out of the book
+-- until activate_at, when the scheduler runs the pre-trade checks and
+-- submits it; it holds funds meanwhile only if the user asked it to.
+ALTER TYPE order_status ADD VALUE 'scheduled' BEFORE 'pending';
+
+ALTER TABLE orders ADD COLUMN activate_at TIMESTAMPTZ;
+-- What the order locked when it was scheduled, in the currency it will lock
+-- once active; NULL when nothing is held
+ALTER TABLE orders ADD COLUMN scheduled_lock DECIMAL(20, 8);
+
+CREATE INDEX idx_orders_activate_at ON orders(activate_at) WHERE activate_at IS NOT NULL;
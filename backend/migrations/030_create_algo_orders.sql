@@ -0,0 +1,38 @@
+-- Parent orders worked by the algo order service. A TWAP order is split
+-- into equal slices spread over its duration; a POV order trades a share of
+-- the pair's market volume until it is done or its duration runs out. Each
+-- slice is an ordinary child order pointing back at its parent.
+CREATE TYPE algo_strategy AS ENUM ('twap', 'pov');
+CREATE TYPE algo_order_status AS ENUM ('active', 'completed', 'cancelled', 'failed');
+
+CREATE TABLE algo_orders (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    side order_side NOT NULL,
+    strategy algo_strategy NOT NULL,
+    quantity DECIMAL(20, 8) NOT NULL CHECK (quantity > 0),
+    -- Children are limit IOC orders at this price, or market orders without it
+    limit_price DECIMAL(20, 8),
+    duration_seconds INTEGER NOT NULL CHECK (duration_seconds > 0),
+    -- TWAP only
+    slice_count INTEGER,
+    -- POV only: share of market volume to trade, e.g. 0.1 for 10%
+    participation_rate DECIMAL(5, 4),
+    executed_quantity DECIMAL(20, 8) NOT NULL DEFAULT 0,
+    slices_sent INTEGER NOT NULL DEFAULT 0,
+    status algo_order_status NOT NULL DEFAULT 'active',
+    error TEXT,
+    next_slice_at TIMESTAMPTZ NOT NULL,
+    -- POV: market volume after this point has not been participated in yet
+    volume_since TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_algo_orders_user_id ON algo_orders(user_id, created_at);
+CREATE INDEX idx_algo_orders_due ON algo_orders(next_slice_at) WHERE status = 'active';
+
+ALTER TABLE orders ADD COLUMN parent_order_id UUID;
+CREATE INDEX idx_orders_parent_order_id ON orders(parent_order_id) WHERE parent_order_id IS NOT NULL;
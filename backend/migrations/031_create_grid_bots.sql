@@ -0,0 +1,44 @@
+-- Grid trading bots. A bot keeps a ladder of limit orders at evenly spaced
+-- prices between lower_price and upper_price: buys below the market, sells
+-- above it. Each filled order is replaced by the opposite order one level
+-- away, so every buy-then-sell (or sell-then-buy) earns one grid step.
+CREATE TYPE grid_bot_status AS ENUM ('running', 'stopped', 'failed');
+
+CREATE TABLE grid_bots (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    lower_price DECIMAL(20, 8) NOT NULL CHECK (lower_price > 0),
+    upper_price DECIMAL(20, 8) NOT NULL,
+    grid_count INTEGER NOT NULL CHECK (grid_count >= 2),
+    -- Quote currency the ladder is sized for
+    investment DECIMAL(28, 8) NOT NULL CHECK (investment > 0),
+    quantity_per_grid DECIMAL(20, 8) NOT NULL,
+    status grid_bot_status NOT NULL DEFAULT 'running',
+    -- Grid steps earned by completed round trips, before fees
+    realized_pnl DECIMAL(28, 8) NOT NULL DEFAULT 0,
+    completed_cycles INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    stopped_at TIMESTAMPTZ,
+    CHECK (upper_price > lower_price)
+);
+
+CREATE INDEX idx_grid_bots_user_id ON grid_bots(user_id, created_at);
+
+-- Every order a bot has placed and the grid level it sits at
+CREATE TABLE grid_bot_orders (
+    order_id UUID PRIMARY KEY,
+    bot_id UUID NOT NULL REFERENCES grid_bots(id) ON DELETE CASCADE,
+    level INTEGER NOT NULL,
+    side order_side NOT NULL,
+    -- The filled order this one replaced; null for the initial ladder
+    counter_to UUID,
+    -- Set once the order has filled and its replacement was placed, or
+    -- when a restart laid a new ladder
+    replaced BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_grid_bot_orders_bot_id ON grid_bot_orders(bot_id) WHERE NOT replaced;
@@ -0,0 +1,74 @@
+-- Copy trading. A user publishes their account as a lead trader; followers
+-- subscribe with a budget and the lead's fills are mirrored into their
+-- accounts in proportion to that budget. Leads earn a performance fee on
+-- the profit they make their followers.
+CREATE TABLE lead_traders (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    display_name VARCHAR(50) NOT NULL,
+    -- Share of a follower's realized profit above its high-water mark
+    performance_fee_rate DECIMAL(5, 4) NOT NULL DEFAULT 0 CHECK (performance_fee_rate >= 0 AND performance_fee_rate < 1),
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TYPE copy_subscription_status AS ENUM ('active', 'stopped', 'closed');
+
+CREATE TABLE copy_subscriptions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    follower_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    lead_id UUID NOT NULL REFERENCES lead_traders(user_id) ON DELETE CASCADE,
+    -- Only the lead's fills on pairs quoted in this currency are mirrored
+    budget_currency VARCHAR(10) NOT NULL,
+    budget DECIMAL(28, 8) NOT NULL CHECK (budget > 0),
+    -- Follower risk limits: the largest mirrored order, and the loss at
+    -- which mirroring stops
+    max_trade_notional DECIMAL(28, 8),
+    stop_loss DECIMAL(28, 8),
+    -- The lead's rate when the subscription was taken out
+    performance_fee_rate DECIMAL(5, 4) NOT NULL,
+    status copy_subscription_status NOT NULL DEFAULT 'active',
+    stop_reason TEXT,
+    realized_pnl DECIMAL(28, 8) NOT NULL DEFAULT 0,
+    high_water_mark DECIMAL(28, 8) NOT NULL DEFAULT 0,
+    fees_paid DECIMAL(28, 8) NOT NULL DEFAULT 0,
+    -- Lead fills before this point have been mirrored or skipped
+    mirrored_until TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    closed_at TIMESTAMPTZ,
+    CHECK (follower_id <> lead_id)
+);
+
+CREATE UNIQUE INDEX idx_copy_subscriptions_open ON copy_subscriptions(follower_id, lead_id) WHERE status <> 'closed';
+CREATE INDEX idx_copy_subscriptions_lead_id ON copy_subscriptions(lead_id) WHERE status = 'active';
+
+-- What a subscription holds in each pair, at cost, so that it only ever
+-- sells what it bought
+CREATE TABLE copy_positions (
+    subscription_id UUID NOT NULL REFERENCES copy_subscriptions(id) ON DELETE CASCADE,
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    quantity DECIMAL(20, 8) NOT NULL DEFAULT 0,
+    cost DECIMAL(28, 8) NOT NULL DEFAULT 0,
+    PRIMARY KEY (subscription_id, trading_pair_id)
+);
+
+CREATE TYPE copy_trade_status AS ENUM ('filled', 'skipped', 'failed');
+
+-- One row per lead fill a subscription saw, mirrored or not
+CREATE TABLE copy_trades (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    subscription_id UUID NOT NULL REFERENCES copy_subscriptions(id) ON DELETE CASCADE,
+    lead_trade_id UUID NOT NULL,
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    side order_side NOT NULL,
+    lead_quantity DECIMAL(20, 8) NOT NULL,
+    quantity DECIMAL(20, 8) NOT NULL DEFAULT 0,
+    quote_quantity DECIMAL(28, 8) NOT NULL DEFAULT 0,
+    order_id UUID,
+    status copy_trade_status NOT NULL,
+    note TEXT,
+    performance_fee DECIMAL(28, 8) NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (subscription_id, lead_trade_id)
+);
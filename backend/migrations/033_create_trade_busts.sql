@@ -0,0 +1,19 @@
+-- Busting an erroneous trade reverses its settlement: its ledger journal is
+-- posted again with every amount negated and both orders lose the fill.
+-- The trade row stays, marked busted, and drops out of market data.
+ALTER TABLE trades ADD COLUMN busted_at TIMESTAMPTZ;
+
+-- Admin actions that change money or orders, with who took them and why
+CREATE TABLE audit_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    actor_id UUID NOT NULL REFERENCES users(id),
+    action VARCHAR(50) NOT NULL,
+    -- What the action was taken on, e.g. the busted trade
+    target_id UUID NOT NULL,
+    reason TEXT NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_log_target ON audit_log(target_id, created_at);
+CREATE INDEX idx_audit_log_created_at ON audit_log(created_at);
@@ -0,0 +1,29 @@
+-- Every state transition of an order, with who caused it, for dispute
+-- resolution. actor_id is the user or admin behind the transition, and
+-- NULL for the matching engine and background jobs. Each event carries the
+-- order's status and quantities right after it. Orders placed before this
+-- migration have no history.
+--
+-- 'amended' and 'expired' have no emitter yet; they are declared now since
+-- adding enum values cannot be done inside a migration's transaction.
+CREATE TYPE order_event_type AS ENUM (
+    'created', 'accepted', 'partially_filled', 'filled', 'amended', 'cancelled', 'rejected', 'expired', 'fill_busted'
+);
+
+-- No foreign key to orders: it is partitioned, and its key includes created_at
+CREATE TABLE order_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    sequence BIGSERIAL NOT NULL,
+    order_id UUID NOT NULL,
+    event_type order_event_type NOT NULL,
+    status order_status NOT NULL,
+    filled_quantity DECIMAL(20, 8),
+    remaining_quantity DECIMAL(20, 8),
+    -- The trade behind a fill or a busted fill
+    trade_id UUID,
+    actor_id UUID REFERENCES users(id),
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_order_events_order ON order_events(order_id, sequence);
@@ -0,0 +1,5 @@
+-- Every update of an order bumps its version. Updates that decide an
+-- order's fate from what was read (cancelling, rejecting, filling) only
+-- apply while the version is still the one read, so a concurrent cancel
+-- and fill can never overwrite each other or release funds twice.
+ALTER TABLE orders ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
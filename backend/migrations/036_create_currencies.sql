@@ -0,0 +1,22 @@
+-- The currencies the exchange lists, so a new asset is an admin API call
+-- rather than a deploy. Registration opens an account in every active
+-- currency marked open_by_default; any other currency gets its account on
+-- its first credit. Portfolios are valued in USD at usd_price.
+CREATE TABLE currencies (
+    code VARCHAR(10) PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    decimals INTEGER NOT NULL DEFAULT 8,
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    open_by_default BOOLEAN NOT NULL DEFAULT false,
+    -- Reference price for portfolio valuation; NULL values balances at zero
+    usd_price DECIMAL(20, 8),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- What registration and valuation had hardcoded until now
+INSERT INTO currencies (code, name, decimals, open_by_default, usd_price) VALUES
+    ('USD', 'US Dollar', 2, true, 1),
+    ('BTC', 'Bitcoin', 8, true, 50000),
+    ('ETH', 'Ether', 8, true, 3000),
+    ('USDT', 'Tether', 6, true, 1);
@@ -0,0 +1,32 @@
+-- Trading pair lifecycle: announced -> call_auction -> trading, with halts
+-- that reopen through another call auction, and delisting -> delisted at
+-- the end. During a call auction orders rest without matching until the
+-- book uncrosses at a single opening price. Delisting cancels every open
+-- order on the pair and releases its funds; delisted is final.
+CREATE TYPE trading_pair_status AS ENUM ('announced', 'call_auction', 'trading', 'halt', 'delisting', 'delisted');
+
+ALTER TABLE trading_pairs ADD COLUMN status trading_pair_status NOT NULL DEFAULT 'trading';
+UPDATE trading_pairs SET status = 'halt' WHERE is_active IS NOT TRUE;
+
+-- is_active now follows the status: orders are accepted during the call
+-- auction and in continuous trading only
+DROP INDEX idx_trading_pairs_active;
+ALTER TABLE trading_pairs DROP COLUMN is_active;
+ALTER TABLE trading_pairs
+    ADD COLUMN is_active BOOLEAN GENERATED ALWAYS AS (status IN ('call_auction', 'trading')) STORED;
+CREATE INDEX idx_trading_pairs_active ON trading_pairs(is_active);
+
+CREATE TABLE trading_pair_status_changes (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    from_status trading_pair_status NOT NULL,
+    to_status trading_pair_status NOT NULL,
+    changed_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_trading_pair_status_changes_pair ON trading_pair_status_changes(trading_pair_id, created_at);
+
+-- Books collecting a call auction must come back from a snapshot still
+-- collecting
+ALTER TABLE order_book_snapshots ADD COLUMN in_auction BOOLEAN NOT NULL DEFAULT FALSE;
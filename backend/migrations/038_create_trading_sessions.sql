@@ -0,0 +1,14 @@
+-- Optional trading hours per pair, e.g. for tokenized equities. A pair with
+-- sessions only takes orders inside one of them and is halted outside
+-- them; a pair without sessions trades around the clock. Times are UTC and
+-- weekdays ISO (1 = Monday).
+CREATE TABLE trading_sessions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    weekday SMALLINT NOT NULL CHECK (weekday BETWEEN 1 AND 7),
+    opens_at TIME NOT NULL,
+    closes_at TIME NOT NULL,
+    CHECK (opens_at < closes_at)
+);
+
+CREATE INDEX idx_trading_sessions_pair ON trading_sessions(trading_pair_id, weekday);
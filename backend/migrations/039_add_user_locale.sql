@@ -0,0 +1,3 @@
+-- Preferred language for error messages and notifications. NULL follows
+-- the request's Accept-Language header.
+ALTER TABLE users ADD COLUMN locale VARCHAR(10);
@@ -0,0 +1,8 @@
+-- Trading pairs a user follows, in the order they arranged them
+CREATE TABLE watchlist_entries (
+    user_id UUID NOT NULL REFERENCES users(id),
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    position INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, trading_pair_id)
+);
@@ -0,0 +1,4 @@
+-- Balances at a point in time are the sum of a user's entries up to it;
+-- carrying currency and amount in the index lets that sum be read from the
+-- index alone.
+CREATE INDEX idx_ledger_entries_user_created_at ON ledger_entries(user_id, created_at) INCLUDE (currency, amount);
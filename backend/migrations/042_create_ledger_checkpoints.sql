@@ -0,0 +1,19 @@
+-- Ledger compaction rolls entries older than the retention into one row per
+-- (user, currency) and month: the running balance at period_end, exclusive.
+-- A balance at any later moment is the latest checkpoint before it plus the
+-- raw entries from its period_end on. The entries themselves are archived
+-- to cold storage and deleted. user_id is NULL for the exchange's own side.
+CREATE TABLE ledger_checkpoints (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    currency VARCHAR(10) NOT NULL,
+    period_end TIMESTAMPTZ NOT NULL,
+    balance DECIMAL(20, 8) NOT NULL,
+    -- Entries rolled into this checkpoint, for reconciling against the archive
+    entry_count BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX idx_ledger_checkpoints_account_period
+    ON ledger_checkpoints(COALESCE(user_id, '00000000-0000-0000-0000-000000000000'::uuid), currency, period_end);
+CREATE INDEX idx_ledger_checkpoints_user_period ON ledger_checkpoints(user_id, period_end) INCLUDE (currency, balance);
@@ -0,0 +1,3 @@
+-- Rendered documents are kept in object storage under storage_key; content
+-- only holds the PDFs rendered before that.
+ALTER TABLE documents ADD COLUMN storage_key VARCHAR(255);
@@ -0,0 +1,18 @@
+-- Transactional outbox: domain events are inserted in the same transaction
+-- as the state change they describe, and a relay publishes them to NATS
+-- and sets published_at. A crash between publishing and marking means the
+-- event goes out again, so consumers dedupe on event_id.
+CREATE TABLE outbox_events (
+    id BIGSERIAL PRIMARY KEY,
+    event_id UUID NOT NULL UNIQUE DEFAULT uuid_generate_v4(),
+    -- Relative to the configured subject prefix, e.g. trade.executed
+    subject VARCHAR(100) NOT NULL,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    published_at TIMESTAMPTZ,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT
+);
+
+CREATE INDEX idx_outbox_events_pending ON outbox_events(id) WHERE published_at IS NULL;
+CREATE INDEX idx_outbox_events_published_at ON outbox_events(published_at) WHERE published_at IS NOT NULL;
@@ -0,0 +1,11 @@
+-- Events a consumer has applied, recorded in the same transaction as its
+-- effects. Redelivered outbox messages and re-settled engine matches find
+-- their key already here and are skipped, so a balance is never credited
+-- twice for the same event.
+CREATE TABLE processed_events (
+    consumer VARCHAR(100) NOT NULL,
+    -- The outbox event id, or trading_pair_id:sequence for engine matches
+    event_key VARCHAR(255) NOT NULL,
+    processed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (consumer, event_key)
+);
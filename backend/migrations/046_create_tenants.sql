@@ -0,0 +1,24 @@
+-- White-label brokers sharing the deployment. Users with no tenant belong
+-- to the exchange itself. A trading pair with a tenant is private to it;
+-- one without is shared by everyone, tenants included, on one order book.
+CREATE TABLE tenants (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    slug VARCHAR(50) NOT NULL UNIQUE,
+    name VARCHAR(100) NOT NULL,
+    -- Namespaces the tenant's API keys: ctk_<prefix>_...
+    api_key_prefix VARCHAR(12) NOT NULL UNIQUE,
+    -- Fee schedule for the tenant's users; unset falls back to the pair's
+    maker_fee DECIMAL(5, 4),
+    taker_fee DECIMAL(5, 4),
+    display_name VARCHAR(100),
+    logo_url VARCHAR(500),
+    primary_color VARCHAR(7),
+    support_email VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE users ADD COLUMN tenant_id UUID REFERENCES tenants(id);
+ALTER TABLE trading_pairs ADD COLUMN tenant_id UUID REFERENCES tenants(id);
+
+CREATE INDEX idx_users_tenant_id ON users(tenant_id) WHERE tenant_id IS NOT NULL;
@@ -0,0 +1,29 @@
+-- Devices each user has logged in from, keyed by a fingerprint of the
+-- client. A login from a device not confirmed here is held until the user
+-- follows the link emailed to them, or passes 2FA in the same request.
+CREATE TABLE user_devices (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- SHA-256 hex of the client's fingerprint
+    fingerprint VARCHAR(64) NOT NULL,
+    user_agent VARCHAR(500),
+    ip_address VARCHAR(45),
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    confirmed_at TIMESTAMPTZ,
+    -- A revoked device must be confirmed again on its next login
+    revoked_at TIMESTAMPTZ,
+    UNIQUE (user_id, fingerprint)
+);
+
+CREATE TABLE device_challenges (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    device_id UUID NOT NULL REFERENCES user_devices(id) ON DELETE CASCADE,
+    -- SHA-256 hex of the token in the emailed link; the token is not stored
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_device_challenges_device_id ON device_challenges(device_id);
@@ -0,0 +1,4 @@
+-- Failed logins in a row, cleared by a successful one. Past the configured
+-- threshold the account's logins need a solved CAPTCHA.
+ALTER TABLE users ADD COLUMN failed_login_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN last_failed_login_at TIMESTAMPTZ;
@@ -0,0 +1,21 @@
+-- Maker-checker for large fiat withdrawals: one admin initiates the
+-- approval, a different admin confirms it.
+ALTER TABLE transactions ADD COLUMN dual_approval_required BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE transactions ADD COLUMN approval_initiated_by UUID REFERENCES users(id);
+
+CREATE TYPE withdrawal_action AS ENUM ('approve', 'reject', 'complete', 'fail');
+
+-- Every admin action on a fiat withdrawal, including the approval an
+-- initiating admin records before a second one confirms it. Never updated.
+CREATE TABLE withdrawal_reviews (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    withdrawal_id UUID NOT NULL REFERENCES transactions(id),
+    admin_id UUID NOT NULL REFERENCES users(id),
+    action withdrawal_action NOT NULL,
+    status_before transaction_status NOT NULL,
+    status_after transaction_status NOT NULL,
+    note TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_withdrawal_reviews_withdrawal_id ON withdrawal_reviews(withdrawal_id, created_at);
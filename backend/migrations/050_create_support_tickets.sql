@@ -0,0 +1,44 @@
+-- Support tickets. A ticket is open while support owes a reply, pending
+-- while the user does, and resolved once support closes it; a reply from
+-- the user reopens it.
+CREATE TYPE ticket_status AS ENUM ('open', 'pending', 'resolved');
+CREATE TYPE ticket_category AS ENUM ('account', 'deposit', 'withdrawal', 'trading', 'verification', 'other');
+
+CREATE TABLE support_tickets (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id),
+    category ticket_category NOT NULL,
+    subject VARCHAR(200) NOT NULL,
+    status ticket_status NOT NULL DEFAULT 'open',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_support_tickets_user_id ON support_tickets(user_id, created_at DESC);
+CREATE INDEX idx_support_tickets_status ON support_tickets(status, updated_at);
+
+CREATE TABLE ticket_messages (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    ticket_id UUID NOT NULL REFERENCES support_tickets(id) ON DELETE CASCADE,
+    author_id UUID NOT NULL REFERENCES users(id),
+    from_staff BOOLEAN NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_ticket_messages_ticket_id ON ticket_messages(ticket_id, created_at);
+
+-- Files kept in object storage under support/<ticket>/<attachment>.<ext>
+CREATE TABLE ticket_attachments (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    ticket_id UUID NOT NULL REFERENCES support_tickets(id) ON DELETE CASCADE,
+    uploaded_by UUID NOT NULL REFERENCES users(id),
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(100) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    storage_key VARCHAR(500) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_ticket_attachments_ticket_id ON ticket_attachments(ticket_id);
@@ -0,0 +1,43 @@
+-- Order templates fired by TradingView-style alert webhooks. Each template
+-- has its own secret: an alert either carries it as `passphrase` in the
+-- JSON body, since TradingView cannot sign, or signs the raw body with it
+-- (hex HMAC-SHA256 in X-Alert-Signature). The secret is kept in the clear
+-- to check signatures, as for API keys.
+CREATE TABLE alert_templates (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id),
+    name VARCHAR(100) NOT NULL,
+    trading_pair_id UUID NOT NULL REFERENCES trading_pairs(id),
+    order_type order_type NOT NULL CHECK (order_type IN ('market', 'limit')),
+    -- NULL takes the side from the alert's action
+    side order_side,
+    -- Used when the alert does not name a quantity
+    quantity DECIMAL(20, 8) NOT NULL CHECK (quantity > 0),
+    -- Risk limits: no alert may order more than max_quantity, or more than
+    -- max_notional in the quote currency, and at most max_orders_per_day
+    -- alerts are executed per UTC day
+    max_quantity DECIMAL(20, 8) NOT NULL CHECK (max_quantity >= quantity),
+    max_notional DECIMAL(20, 8) CHECK (max_notional > 0),
+    max_orders_per_day INTEGER NOT NULL CHECK (max_orders_per_day > 0),
+    secret VARCHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    disabled_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX idx_alert_templates_name ON alert_templates(user_id, name) WHERE disabled_at IS NULL;
+
+CREATE TYPE alert_execution_status AS ENUM ('executed', 'rejected');
+
+-- Every authenticated alert, with the order it placed or why it did not
+CREATE TABLE alert_executions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    template_id UUID NOT NULL REFERENCES alert_templates(id),
+    payload JSONB NOT NULL,
+    status alert_execution_status NOT NULL,
+    -- orders is partitioned, so this cannot be a foreign key
+    order_id UUID,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_alert_executions_template ON alert_executions(template_id, created_at DESC);
@@ -0,0 +1,32 @@
+-- Bulk limit orders uploaded as CSV. Rows are parsed on upload and kept
+-- here with the order each asks for; small files are placed before the
+-- upload returns, larger ones by the background job a batch at a time. A
+-- dry run only validates each row.
+CREATE TYPE order_import_status AS ENUM ('pending', 'completed');
+CREATE TYPE order_import_row_status AS ENUM ('pending', 'valid', 'placed', 'rejected');
+
+CREATE TABLE order_imports (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id),
+    dry_run BOOLEAN NOT NULL,
+    status order_import_status NOT NULL DEFAULT 'pending',
+    total_rows INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_order_imports_user ON order_imports(user_id, created_at DESC);
+CREATE INDEX idx_order_imports_pending ON order_imports(created_at) WHERE status = 'pending';
+
+CREATE TABLE order_import_rows (
+    import_id UUID NOT NULL REFERENCES order_imports(id),
+    -- Line in the file, counting the header as line 1
+    line INTEGER NOT NULL,
+    -- The order to place; NULL when the row could not be parsed
+    request JSONB,
+    status order_import_row_status NOT NULL DEFAULT 'pending',
+    -- orders is partitioned, so this cannot be a foreign key
+    order_id UUID,
+    reason TEXT,
+    PRIMARY KEY (import_id, line)
+);
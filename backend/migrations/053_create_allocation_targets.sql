@@ -0,0 +1,22 @@
+-- Target allocations: the share of a user's portfolio value each asset
+-- should make up. A user's targets always sum to 100%.
+CREATE TABLE allocation_targets (
+    user_id UUID NOT NULL REFERENCES users(id),
+    currency VARCHAR(10) NOT NULL,
+    target_percent DECIMAL(7, 4) NOT NULL CHECK (target_percent > 0 AND target_percent <= 100),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, currency)
+);
+
+-- Rebalance trades proposed to a user. A plan is placed exactly as
+-- proposed, once, if the user confirms it before it expires.
+CREATE TABLE rebalance_plans (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id),
+    trades JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    executed_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_rebalance_plans_user ON rebalance_plans(user_id, created_at DESC);
@@ -0,0 +1,10 @@
+-- KYC tiers set each user's daily deposit and withdrawal limits and how
+-- much they may have resting on the books. Users whose KYC was already
+-- approved start out verified.
+CREATE TYPE kyc_tier AS ENUM ('basic', 'verified', 'enhanced');
+
+ALTER TABLE users ADD COLUMN kyc_tier kyc_tier NOT NULL DEFAULT 'basic';
+UPDATE users SET kyc_tier = 'verified' WHERE kyc_status = 'approved';
+
+-- Today's deposits and withdrawals of a user, summed against the limits
+CREATE INDEX idx_transactions_user_type_created ON transactions(user_id, transaction_type, created_at);
@@ -0,0 +1,26 @@
+-- Logins that passed the password check, geolocated where the CDN allows,
+-- so each can be compared with the user's last trusted one. Improbable
+-- travel between the two holds the login until it is confirmed.
+CREATE TYPE login_event_status AS ENUM ('allowed', 'challenged', 'confirmed');
+
+CREATE TABLE login_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ip_address VARCHAR(45),
+    country CHAR(2),
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    distance_km INTEGER,
+    speed_kmh INTEGER,
+    risk_score INTEGER NOT NULL CHECK (risk_score BETWEEN 0 AND 100),
+    improbable_travel BOOLEAN NOT NULL,
+    status login_event_status NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- When the user confirmed a challenged login, making it the latest trusted one
+    confirmed_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_login_events_user_created ON login_events(user_id, created_at DESC);
+
+-- The login a challenge holds, confirmed along with the device
+ALTER TABLE device_challenges ADD COLUMN login_event_id UUID REFERENCES login_events(id) ON DELETE SET NULL;
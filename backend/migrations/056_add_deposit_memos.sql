@@ -0,0 +1,35 @@
+-- Chains such as XRP and Stellar pay every user's deposits to one shared
+-- address and tell them apart by a memo, a destination tag on XRP. A
+-- currency with a shared deposit_address hands each user a memo on it;
+-- deposits whose memo matches no user wait in unmatched_deposits.
+CREATE TYPE memo_format AS ENUM ('tag', 'text');
+
+ALTER TABLE currencies
+    -- How the chain's memos are written; NULL for chains without memos
+    ADD COLUMN memo_format memo_format,
+    ADD COLUMN deposit_address VARCHAR(255),
+    ADD COLUMN deposit_confirmations INTEGER NOT NULL DEFAULT 1 CHECK (deposit_confirmations > 0),
+    ADD CONSTRAINT currencies_shared_address_memo CHECK (deposit_address IS NULL OR memo_format IS NOT NULL);
+
+-- A memo belongs to one user on its address
+CREATE UNIQUE INDEX idx_deposit_addresses_memo ON deposit_addresses(currency, address, tag) WHERE tag IS NOT NULL;
+
+-- The memo a crypto deposit arrived with or a withdrawal is sent with
+ALTER TABLE transactions ADD COLUMN memo VARCHAR(100);
+
+CREATE TABLE unmatched_deposits (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    currency VARCHAR(10) NOT NULL,
+    address VARCHAR(255) NOT NULL,
+    memo VARCHAR(100),
+    tx_id VARCHAR(255) NOT NULL,
+    amount DECIMAL(20, 8) NOT NULL,
+    -- Set once an admin credits the deposit to its sender
+    credited_to UUID REFERENCES users(id),
+    credited_by UUID REFERENCES users(id),
+    credited_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (currency, tx_id)
+);
+
+CREATE INDEX idx_unmatched_deposits_open ON unmatched_deposits(created_at) WHERE credited_to IS NULL;
@@ -0,0 +1,9 @@
+-- What an order locked when it was placed. Releases are sized from it, so
+-- an order gives back what it locked even after how locks are sized, or
+-- the fees they include, change. Live orders were placed when buys locked
+-- their limit notional without the fee.
+ALTER TABLE orders ADD COLUMN locked_amount DECIMAL(20, 8);
+
+UPDATE orders
+SET locked_amount = CASE WHEN side = 'buy' THEN quantity * COALESCE(price, 0) ELSE quantity END
+WHERE status IN ('pending', 'open', 'partially_filled');
@@ -0,0 +1,11 @@
+-- Funds locked for an order on its way to the matching engine, whose row is
+-- only written with the journal batch that records it. The batch deletes
+-- the entry; one left behind by a crash, or a batch that never committed,
+-- belongs to an order that was never placed, and recovery releases it.
+CREATE TABLE pending_order_locks (
+    order_id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id),
+    currency VARCHAR(10) NOT NULL,
+    amount DECIMAL(20, 8) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
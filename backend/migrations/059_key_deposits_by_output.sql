@@ -0,0 +1,13 @@
+-- One chain transaction can pay several deposits, to different users or
+-- memos, as separate outputs (UTXO chains) or transfer logs (token
+-- contracts). A deposit is identified by its currency, transaction hash and
+-- output index, so each output is credited or held on its own.
+ALTER TABLE unmatched_deposits
+    ADD COLUMN output_index INTEGER NOT NULL DEFAULT 0 CHECK (output_index >= 0),
+    DROP CONSTRAINT unmatched_deposits_currency_tx_id_key,
+    ADD CONSTRAINT unmatched_deposits_currency_tx_id_output_index_key UNIQUE (currency, tx_id, output_index);
+
+-- Chain deposits were keyed by the bare hash; they become output 0 of it
+UPDATE transactions
+SET external_id = currency || ':' || external_id || ':0'
+WHERE provider = 'chain' AND transaction_type = 'deposit';
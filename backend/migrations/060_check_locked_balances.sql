@@ -0,0 +1,6 @@
+-- Settlement takes an order's fills out of what it locked, so a locked
+-- balance below zero means an order spent more than it held. Refuse the
+-- write instead. Available balances are left unchecked: busting a trade
+-- can take back funds the user has already moved on.
+ALTER TABLE accounts
+    ADD CONSTRAINT accounts_locked_balance_non_negative CHECK (locked_balance >= 0);
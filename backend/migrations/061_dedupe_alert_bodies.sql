@@ -0,0 +1,8 @@
+-- Alerts are turned away when their exact body already reached the
+-- template in the past day, so a captured alert cannot be replayed.
+-- Strategies that repeat a signal should vary the body, e.g. with
+-- TradingView's {{timenow}} placeholder. Executions from before this
+-- migration have no digest and never match.
+ALTER TABLE alert_executions ADD COLUMN body_digest BYTEA;
+
+CREATE INDEX idx_alert_executions_body_digest ON alert_executions(template_id, body_digest);
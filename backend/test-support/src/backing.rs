@@ -0,0 +1,88 @@
+use anyhow::Context;
+use sqlx::{Connection, PgConnection};
+use testcontainers_modules::{
+    postgres::Postgres,
+    redis::{Redis, REDIS_PORT},
+    testcontainers::{runners::AsyncRunner, ContainerAsync, ImageExt},
+};
+use uuid::Uuid;
+
+/// Matches the version the services run against in CI and docker-compose;
+/// the module's default image is too old for `gen_random_uuid()`.
+const POSTGRES_TAG: &str = "15-alpine";
+const POSTGRES_PORT: u16 = 5432;
+
+/// Postgres and Redis for one test. With `TEST_DATABASE_URL` set, a fresh
+/// database is created on that server instead of starting a container, and
+/// `TEST_REDIS_URL` likewise points at an existing Redis. Containers are
+/// removed when this is dropped; databases created on a shared server are
+/// left behind for inspection.
+pub(crate) struct Backing {
+    pub database_url: String,
+    pub redis_url: String,
+    _postgres: Option<ContainerAsync<Postgres>>,
+    _redis: Option<ContainerAsync<Redis>>,
+}
+
+impl Backing {
+    pub async fn start() -> anyhow::Result<Self> {
+        let (database_url, postgres) = match std::env::var("TEST_DATABASE_URL") {
+            Ok(server_url) => (create_database(&server_url).await?, None),
+            Err(_) => {
+                let container = Postgres::default()
+                    .with_tag(POSTGRES_TAG)
+                    .start()
+                    .await
+                    .context("starting Postgres container (is Docker running?)")?;
+                let url = format!(
+                    "postgresql://postgres:postgres@{}:{}/postgres",
+                    container.get_host().await?,
+                    container.get_host_port_ipv4(POSTGRES_PORT).await?
+                );
+                (url, Some(container))
+            }
+        };
+
+        let (redis_url, redis) = match std::env::var("TEST_REDIS_URL") {
+            Ok(url) => (url, None),
+            Err(_) => {
+                let container = Redis::default()
+                    .start()
+                    .await
+                    .context("starting Redis container (is Docker running?)")?;
+                let url = format!(
+                    "redis://{}:{}",
+                    container.get_host().await?,
+                    container.get_host_port_ipv4(REDIS_PORT).await?
+                );
+                (url, Some(container))
+            }
+        };
+
+        Ok(Self {
+            database_url,
+            redis_url,
+            _postgres: postgres,
+            _redis: redis,
+        })
+    }
+}
+
+/// Creates `test_<uuid>` on the server and returns a URL pointing at it, so
+/// tests sharing one server never see each other's rows.
+async fn create_database(server_url: &str) -> anyhow::Result<String> {
+    let name = format!("test_{}", Uuid::new_v4().simple());
+
+    let mut connection = PgConnection::connect(server_url)
+        .await
+        .context("connecting to TEST_DATABASE_URL")?;
+    sqlx::query(&format!("CREATE DATABASE \"{}\"", name))
+        .execute(&mut connection)
+        .await?;
+    connection.close().await?;
+
+    let (server, _) = server_url
+        .rsplit_once('/')
+        .context("TEST_DATABASE_URL must include a database name")?;
+    Ok(format!("{}/{}", server, name))
+}
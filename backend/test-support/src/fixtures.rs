@@ -0,0 +1,154 @@
+use cryptotrade_core::{
+    AuthResponse, CreateOrderRequest, LedgerEntryType, OrderSide, OrderType, Posting, TradingPair, ADMIN_ROLE,
+};
+use rust_decimal::Decimal;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::TestApp;
+
+pub const TEST_PASSWORD: &str = "correct-horse-battery";
+
+/// A registered user and a valid access token for them.
+#[derive(Debug, Clone)]
+pub struct TestUser {
+    pub id: Uuid,
+    pub email: String,
+    pub username: String,
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+/// A limit order for `quantity` at `price`. Tests place identical orders
+/// freely, so the duplicate check is off; anything else is set with struct
+/// update syntax:
+///
+/// ```ignore
+/// CreateOrderRequest { time_in_force: Some(TimeInForce::IOC), ..limit_order(pair.id, OrderSide::Buy, 20_000, 0.1) }
+/// ```
+pub fn limit_order(trading_pair_id: Uuid, side: OrderSide, price: impl Into<Decimal>, quantity: f64) -> CreateOrderRequest {
+    CreateOrderRequest {
+        trading_pair_id,
+        order_type: OrderType::Limit,
+        side,
+        quantity,
+        price: Some(price.into()),
+        time_in_force: None,
+        stop_price: None,
+        allow_duplicate: true,
+        activate_at: None,
+        lock_funds: false,
+    }
+}
+
+impl TestApp {
+    /// Registers `username` through the API, so the token is exactly what a
+    /// client would hold.
+    pub async fn seed_user(&self, username: &str) -> TestUser {
+        let email = format!("{}@example.com", username);
+        let response = self
+            .server
+            .post("/api/v1/auth/register")
+            .json(&json!({
+                "email": email,
+                "username": username,
+                "password": TEST_PASSWORD,
+            }))
+            .await;
+        response.assert_status_ok();
+        let auth: AuthResponse = response.json();
+
+        TestUser {
+            id: auth.user.id,
+            email,
+            username: username.to_string(),
+            access_token: auth.access_token,
+            refresh_token: auth.refresh_token,
+        }
+    }
+
+    /// Registers `username` with the admin role. The role is granted in the
+    /// database, as for a real deployment, and the user then logs in again
+    /// so the token carries it.
+    pub async fn seed_admin(&self, username: &str) -> TestUser {
+        let user = self.seed_user(username).await;
+        sqlx::query("UPDATE users SET role = $1 WHERE id = $2")
+            .bind(ADMIN_ROLE)
+            .bind(user.id)
+            .execute(&self.db)
+            .await
+            .expect("failed to grant admin role");
+
+        let response = self
+            .server
+            .post("/api/v1/auth/login")
+            .json(&json!({ "email": user.email, "password": TEST_PASSWORD }))
+            .await;
+        response.assert_status_ok();
+        let auth: AuthResponse = response.json();
+
+        TestUser {
+            access_token: auth.access_token,
+            refresh_token: auth.refresh_token,
+            ..user
+        }
+    }
+
+    /// Adds an active pair; `symbol` is `BASE-QUOTE`.
+    pub async fn seed_trading_pair(&self, symbol: &str) -> TradingPair {
+        let (base, quote) = symbol.split_once('-').expect("symbol must be BASE-QUOTE");
+        sqlx::query_as::<_, TradingPair>(
+            "INSERT INTO trading_pairs (symbol, base_currency, quote_currency) VALUES ($1, $2, $3) RETURNING *"
+        )
+        .bind(symbol)
+        .bind(base)
+        .bind(quote)
+        .fetch_one(&self.db)
+        .await
+        .expect("failed to seed trading pair")
+    }
+
+    /// Credits `amount` to the user's available balance, with a balancing
+    /// adjustment journal so ledger and account stay reconciled.
+    pub async fn seed_balance(&self, user_id: Uuid, currency: &str, amount: Decimal) {
+        let balance: Decimal = sqlx::query_scalar(
+            r#"
+            INSERT INTO accounts (user_id, currency, balance, available_balance)
+            VALUES ($1, $2, $3, $3)
+            ON CONFLICT (user_id, currency) DO UPDATE SET
+                balance = accounts.balance + EXCLUDED.balance,
+                available_balance = accounts.available_balance + EXCLUDED.available_balance,
+                updated_at = NOW()
+            RETURNING balance
+            "#
+        )
+        .bind(user_id)
+        .bind(currency)
+        .bind(amount)
+        .fetch_one(&self.db)
+        .await
+        .expect("failed to seed balance");
+
+        let postings = [
+            Posting {
+                user_id: Some(user_id),
+                currency: currency.to_string(),
+                entry_type: LedgerEntryType::Adjustment,
+                amount,
+                balance_after: Some(balance),
+            },
+            Posting {
+                user_id: None,
+                currency: currency.to_string(),
+                entry_type: LedgerEntryType::Adjustment,
+                amount: -amount,
+                balance_after: None,
+            },
+        ];
+        self.state
+            .ledger_service
+            .post(Uuid::new_v4(), None, &postings)
+            .await
+            .expect("failed to post seed journal");
+    }
+}
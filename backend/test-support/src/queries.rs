@@ -0,0 +1,36 @@
+//! Counting the statements a piece of code sends to the database, to pin
+//! down how many queries a code path issues.
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tracing::{instrument::WithSubscriber, Event, Subscriber};
+use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+/// Runs `future` and counts the statements it executes. Only the future's
+/// own polls are observed, so background tasks querying meanwhile are not
+/// counted.
+pub async fn count_queries<F: Future>(future: F) -> (F::Output, usize) {
+    let counter = QueryCounter::default();
+    let subscriber = tracing_subscriber::registry().with(counter.clone());
+    let output = future.with_subscriber(subscriber).await;
+    (output, counter.count.load(Ordering::SeqCst))
+}
+
+/// Counts sqlx's per-statement log events.
+#[derive(Clone, Default)]
+struct QueryCounter {
+    count: Arc<AtomicUsize>,
+}
+
+impl<S: Subscriber> Layer<S> for QueryCounter {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        if event.metadata().target() == "sqlx::query" {
+            self.count.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Backfills a trading pair's market history from external CSV dumps, so a
+//! newly listed pair has chart history from its first day. Connects to the
+//! database configured for the API (`DATABASE_URL` etc.).
+
+use clap::{Parser, Subcommand};
+use cryptotrade_core::{
+    database,
+    services::import_service::{parse_candles, parse_trades},
+    Config, ImportReport, ImportService, Interval,
+};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+/// Rejected rows listed in the report before the rest are summarised
+const REJECTED_SHOWN: usize = 20;
+
+#[derive(Parser)]
+#[command(name = "importer", about = "Historical market data importer")]
+struct Cli {
+    /// Pair symbol, e.g. BTC-USD
+    #[arg(long)]
+    pair: String,
+    /// Where the dump comes from; trade ids are unique per source
+    #[arg(long)]
+    source: String,
+    /// Validate the file and report without writing anything
+    #[arg(long)]
+    dry_run: bool,
+    /// Import the valid rows even if others are rejected
+    #[arg(long)]
+    skip_invalid: bool,
+    #[command(subcommand)]
+    kind: Kind,
+}
+
+#[derive(Subcommand)]
+enum Kind {
+    /// OHLCV rows: timestamp,open,high,low,close,volume
+    Candles {
+        /// Candle interval of the dump, from 1m to 1w, or 1M for months
+        #[arg(long)]
+        interval: String,
+        file: PathBuf,
+    },
+    /// Trade rows: timestamp,price,quantity and optionally side and id
+    Trades { file: PathBuf },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let cli = Cli::parse();
+
+    let config = Config::from_env()?;
+    let db = database::connect(&config.database).await?;
+    let service = ImportService::new(db);
+    let pair = service.find_trading_pair(&cli.pair).await?;
+
+    let report = match &cli.kind {
+        Kind::Candles { interval, file } => {
+            let interval: Interval = interval.parse()?;
+            let (candles, mut report) = parse_candles(BufReader::new(File::open(file)?), interval)?;
+            if should_store(&cli, &report)? {
+                let stored = service.store_candles(pair.id, interval, &cli.source, &candles).await?;
+                report.record_stored(candles.len(), stored);
+            }
+            report
+        }
+        Kind::Trades { file } => {
+            let (trades, mut report) = parse_trades(BufReader::new(File::open(file)?))?;
+            if should_store(&cli, &report)? {
+                let stored = service.store_trades(pair.id, &cli.source, &trades).await?;
+                report.record_stored(trades.len(), stored);
+            }
+            report
+        }
+    };
+
+    print_report(&cli.pair, &report, cli.dry_run);
+    Ok(())
+}
+
+fn should_store(cli: &Cli, report: &ImportReport) -> anyhow::Result<bool> {
+    if !report.rejected.is_empty() && !cli.skip_invalid && !cli.dry_run {
+        print_rejected(report);
+        anyhow::bail!(
+            "{} of {} rows rejected; nothing imported (pass --skip-invalid to import the rest)",
+            report.rejected.len(),
+            report.rows_read
+        );
+    }
+    Ok(!cli.dry_run)
+}
+
+fn print_report(pair: &str, report: &ImportReport, dry_run: bool) {
+    print_rejected(report);
+    println!("{}: {} rows read", pair, report.rows_read);
+    if dry_run {
+        println!("  dry run, nothing written");
+    } else {
+        println!("  imported:   {}", report.imported);
+    }
+    println!("  duplicates: {}", report.duplicates);
+    println!("  rejected:   {}", report.rejected.len());
+}
+
+fn print_rejected(report: &ImportReport) {
+    for row in report.rejected.iter().take(REJECTED_SHOWN) {
+        eprintln!("line {}: {}", row.line, row.reason);
+    }
+    if report.rejected.len() > REJECTED_SHOWN {
+        eprintln!("... and {} more rejected rows", report.rejected.len() - REJECTED_SHOWN);
+    }
+}
@@ -0,0 +1,38 @@
+use crate::report::Report;
+use chrono::Utc;
+use cryptotrade_core::{EngineCommand, LimitOrderBook, SyntheticOrderFlow};
+use rust_decimal::Decimal;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Interleaves flows for `pairs` books on the current thread, timing each
+/// command end to end through the book.
+pub fn run(orders: usize, pairs: usize, cancel_ratio: f64, seed: u64) -> Report {
+    let pairs = pairs.max(1);
+    let mut books: Vec<LimitOrderBook> = (0..pairs).map(|_| LimitOrderBook::new(Uuid::new_v4())).collect();
+    let mut flows: Vec<SyntheticOrderFlow> = (0..pairs)
+        .map(|index| {
+            SyntheticOrderFlow::new(seed + index as u64, Decimal::from(30_000)).with_cancel_ratio(cancel_ratio)
+        })
+        .collect();
+
+    let commands: Vec<(usize, EngineCommand)> = (0..orders)
+        .map(|index| {
+            let pair = index % pairs;
+            (pair, flows[pair].next_command())
+        })
+        .collect();
+
+    let mut report = Report::default();
+    let started = Instant::now();
+
+    for (pair, command) in &commands {
+        let submitted = Instant::now();
+        let events = books[*pair].process(command, Utc::now());
+        let outcome = events.last().map(|event| event.kind.event_type()).unwrap_or("none");
+        report.record(submitted.elapsed(), outcome);
+    }
+
+    report.elapsed = started.elapsed();
+    report
+}
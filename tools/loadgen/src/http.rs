@@ -0,0 +1,108 @@
+use crate::report::Report;
+use cryptotrade_core::{CreateOrderRequest, EngineCommand, Order, OrderType, SyntheticOrderFlow};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::collections::HashMap;
+use std::time::Instant;
+use uuid::Uuid;
+
+pub struct HttpOptions {
+    pub url: String,
+    pub token: String,
+    pub trading_pair_id: Uuid,
+    pub mid_price: Decimal,
+    pub orders: usize,
+    pub concurrency: usize,
+    pub cancel_ratio: f64,
+    pub seed: u64,
+}
+
+/// Runs `concurrency` workers, each replaying its own seeded flow and
+/// translating synthetic order IDs to the IDs assigned by the server.
+pub async fn run(options: HttpOptions) -> anyhow::Result<Report> {
+    let client = reqwest::Client::new();
+    let workers = options.concurrency.max(1);
+    let started = Instant::now();
+
+    let mut handles = Vec::with_capacity(workers);
+    for worker in 0..workers {
+        let orders = options.orders / workers + usize::from(worker < options.orders % workers);
+        let flow = SyntheticOrderFlow::new(options.seed + worker as u64, options.mid_price)
+            .with_cancel_ratio(options.cancel_ratio);
+        let client = client.clone();
+        let url = options.url.clone();
+        let token = options.token.clone();
+        let trading_pair_id = options.trading_pair_id;
+
+        handles.push(tokio::spawn(async move {
+            run_worker(client, url, token, trading_pair_id, flow, orders).await
+        }));
+    }
+
+    let mut report = Report::default();
+    for handle in handles {
+        report.merge(handle.await??);
+    }
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
+
+async fn run_worker(
+    client: reqwest::Client,
+    url: String,
+    token: String,
+    trading_pair_id: Uuid,
+    mut flow: SyntheticOrderFlow,
+    orders: usize,
+) -> anyhow::Result<Report> {
+    let mut report = Report::default();
+    let mut server_ids: HashMap<Uuid, Uuid> = HashMap::new();
+
+    for _ in 0..orders {
+        let submitted = Instant::now();
+
+        let response = match flow.next_command() {
+            EngineCommand::AddOrder(order) => {
+                let request = CreateOrderRequest {
+                    trading_pair_id,
+                    order_type: OrderType::Limit,
+                    side: order.side,
+                    quantity: order.quantity.to_f64().unwrap_or_default(),
+                    price: order.price,
+                    time_in_force: None,
+                    stop_price: None,
+                    allow_duplicate: true,
+                    activate_at: None,
+                    lock_funds: false,
+                };
+                let response = client
+                    .post(format!("{}/api/v1/orders", url))
+                    .bearer_auth(&token)
+                    .json(&request)
+                    .send()
+                    .await?;
+                let status = response.status();
+                if status.is_success() {
+                    let created: Order = response.json().await?;
+                    server_ids.insert(order.order_id, created.id);
+                }
+                status
+            }
+            EngineCommand::CancelOrder { order_id } => {
+                let Some(server_id) = server_ids.remove(&order_id) else {
+                    continue;
+                };
+                client
+                    .delete(format!("{}/api/v1/orders/{}", url, server_id))
+                    .bearer_auth(&token)
+                    .send()
+                    .await?
+                    .status()
+            }
+            EngineCommand::AmendOrder { .. } | EngineCommand::StartAuction | EngineCommand::Uncross => continue,
+        };
+
+        report.record(submitted.elapsed(), format!("HTTP {}", response.as_u16()));
+    }
+
+    Ok(report)
+}
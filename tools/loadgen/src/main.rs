@@ -0,0 +1,89 @@
+//! Load generator replaying synthetic order flow against the matching engine
+//! (in-process) or a running API instance, reporting throughput and latency.
+
+mod engine;
+mod http;
+mod report;
+
+use clap::{Parser, Subcommand};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+#[derive(Parser)]
+#[command(name = "loadgen", about = "Synthetic order flow load generator")]
+struct Cli {
+    #[command(subcommand)]
+    target: Target,
+}
+
+#[derive(Subcommand)]
+enum Target {
+    /// Drive in-memory books directly, without a database
+    Engine {
+        #[arg(long, default_value_t = 100_000)]
+        orders: usize,
+        #[arg(long, default_value_t = 4)]
+        pairs: usize,
+        #[arg(long, default_value_t = 0.2)]
+        cancel_ratio: f64,
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
+    /// Place and cancel orders through the REST API
+    Http {
+        #[arg(long, default_value = "http://localhost:8080")]
+        url: String,
+        /// Bearer token of the account placing orders
+        #[arg(long, env = "LOADGEN_TOKEN")]
+        token: String,
+        #[arg(long)]
+        pair: Uuid,
+        #[arg(long)]
+        mid_price: Decimal,
+        #[arg(long, default_value_t = 1_000)]
+        orders: usize,
+        #[arg(long, default_value_t = 8)]
+        concurrency: usize,
+        #[arg(long, default_value_t = 0.2)]
+        cancel_ratio: f64,
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let report = match Cli::parse().target {
+        Target::Engine {
+            orders,
+            pairs,
+            cancel_ratio,
+            seed,
+        } => engine::run(orders, pairs, cancel_ratio, seed),
+        Target::Http {
+            url,
+            token,
+            pair,
+            mid_price,
+            orders,
+            concurrency,
+            cancel_ratio,
+            seed,
+        } => {
+            let options = http::HttpOptions {
+                url,
+                token,
+                trading_pair_id: pair,
+                mid_price,
+                orders,
+                concurrency,
+                cancel_ratio,
+                seed,
+            };
+            http::run(options).await?
+        }
+    };
+
+    report.print();
+    Ok(())
+}
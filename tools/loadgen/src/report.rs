@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Default)]
+pub struct Report {
+    pub latencies: Vec<Duration>,
+    pub elapsed: Duration,
+    /// Outcome label (event type or HTTP status) to count
+    pub outcomes: BTreeMap<String, usize>,
+}
+
+impl Report {
+    pub fn record(&mut self, latency: Duration, outcome: impl Into<String>) {
+        self.latencies.push(latency);
+        *self.outcomes.entry(outcome.into()).or_default() += 1;
+    }
+
+    pub fn merge(&mut self, other: Report) {
+        self.latencies.extend(other.latencies);
+        for (outcome, count) in other.outcomes {
+            *self.outcomes.entry(outcome).or_default() += count;
+        }
+    }
+
+    pub fn print(mut self) {
+        self.latencies.sort();
+        let requests = self.latencies.len();
+        let throughput = requests as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON);
+
+        println!("requests:    {}", requests);
+        println!("elapsed:     {:.3}s", self.elapsed.as_secs_f64());
+        println!("throughput:  {:.0} orders/sec", throughput);
+        println!("latency p50: {:?}", percentile(&self.latencies, 0.50));
+        println!("latency p99: {:?}", percentile(&self.latencies, 0.99));
+        println!("latency max: {:?}", self.latencies.last().copied().unwrap_or_default());
+        for (outcome, count) in &self.outcomes {
+            println!("  {:<24} {}", outcome, count);
+        }
+    }
+}
+
+fn percentile(sorted: &[Duration], quantile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((sorted.len() as f64 * quantile).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
@@ -0,0 +1,68 @@
+use cryptotrade_core::{CreateOrderRequest, MarketData, Order};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+/// The REST calls the bot needs, authenticated as the quoting account.
+pub struct ApiClient {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+impl ApiClient {
+    pub fn new(url: String, token: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            token,
+        }
+    }
+
+    pub async fn market_data(&self) -> anyhow::Result<Vec<MarketData>> {
+        self.send(self.client.get(format!("{}/api/v1/market-data", self.url))).await
+    }
+
+    pub async fn open_orders(&self) -> anyhow::Result<Vec<Order>> {
+        let request = self
+            .client
+            .get(format!("{}/api/v1/orders", self.url))
+            .query(&[("status", "Open"), ("limit", "1000")]);
+        self.send(request).await
+    }
+
+    pub async fn place(&self, request: &CreateOrderRequest) -> anyhow::Result<Order> {
+        self.send(self.client.post(format!("{}/api/v1/orders", self.url)).json(request)).await
+    }
+
+    /// Cancels an order; one that has filled or is already gone is not an
+    /// error.
+    pub async fn cancel(&self, order_id: Uuid) -> anyhow::Result<()> {
+        let response = self
+            .client
+            .delete(format!("{}/api/v1/orders/{}", self.url, order_id))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND => Ok(()),
+            _ => Err(error(response).await),
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> anyhow::Result<T> {
+        let response = request.bearer_auth(&self.token).send().await?;
+        if !response.status().is_success() {
+            return Err(error(response).await);
+        }
+        Ok(response.json().await?)
+    }
+}
+
+async fn error(response: Response) -> anyhow::Error {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    anyhow::anyhow!("HTTP {}: {}", status.as_u16(), body)
+}
@@ -0,0 +1,51 @@
+use cryptotrade_core::RandomWalk;
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::str::FromStr;
+
+/// Where the reference price for each requote comes from.
+pub enum PriceFeed {
+    /// Seeded random walk from the previous reference price
+    Walk(Box<RandomWalk>),
+    /// An external spot price endpoint returning JSON
+    Http {
+        client: reqwest::Client,
+        url_template: String,
+        pointer: String,
+    },
+}
+
+impl PriceFeed {
+    pub fn walk(seed: u64, volatility_bps: u32) -> Self {
+        Self::Walk(Box::new(RandomWalk::new(seed, volatility_bps)))
+    }
+
+    pub fn http(url_template: String, pointer: String) -> Self {
+        Self::Http {
+            client: reqwest::Client::new(),
+            url_template,
+            pointer,
+        }
+    }
+
+    pub async fn next_price(&mut self, symbol: &str, current: Decimal) -> anyhow::Result<Decimal> {
+        match self {
+            Self::Walk(walk) => Ok(walk.step(current)),
+            Self::Http {
+                client,
+                url_template,
+                pointer,
+            } => {
+                let url = url_template.replace("{symbol}", symbol);
+                let body: Value = client.get(&url).send().await?.error_for_status()?.json().await?;
+                let price = match body.pointer(pointer) {
+                    Some(Value::String(price)) => Decimal::from_str(price)?,
+                    Some(Value::Number(price)) => Decimal::from_str(&price.to_string())?,
+                    _ => anyhow::bail!("No price at {} in the response from {}", pointer, url),
+                };
+                anyhow::ensure!(price > Decimal::ZERO, "Feed returned a non-positive price for {}", symbol);
+                Ok(price)
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Market-making bot that keeps two-sided quote ladders on configured pairs
+//! through the public API, following a reference price feed. Pointed at a
+//! sandbox deployment it supplies realistic liquidity for demos and load
+//! tests; it needs an account funded in both currencies of every pair.
+
+mod api;
+mod feed;
+mod stream;
+
+use api::ApiClient;
+use clap::Parser;
+use cryptotrade_core::{CreateOrderRequest, OrderType, QuoteLadder, TimeInForce};
+use feed::PriceFeed;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+#[derive(Parser)]
+#[command(name = "market-maker", about = "Two-sided quoting bot for the exchange API")]
+struct Cli {
+    #[arg(long, default_value = "http://localhost:8080")]
+    url: String,
+    /// Bearer token of the quoting account
+    #[arg(long, env = "MARKET_MAKER_TOKEN")]
+    token: String,
+    /// Pair symbol to quote, e.g. BTC-USD; repeat for several pairs
+    #[arg(long = "pair", required = true)]
+    pairs: Vec<String>,
+    /// Starting reference price as SYMBOL=PRICE; defaults to the pair's last
+    /// traded price
+    #[arg(long = "price", value_parser = parse_price)]
+    prices: Vec<(String, Decimal)>,
+    /// Spot price URL with `{symbol}` standing for the pair symbol; without
+    /// one the reference price follows a seeded random walk
+    #[arg(long)]
+    feed_url: Option<String>,
+    /// JSON pointer to the price in the feed response
+    #[arg(long, default_value = "/data/amount")]
+    feed_pointer: String,
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+    /// Largest random walk move per requote, in basis points
+    #[arg(long, default_value_t = 20)]
+    volatility_bps: u32,
+    #[arg(long, default_value_t = 5)]
+    levels: u32,
+    #[arg(long, default_value_t = 10)]
+    spread_bps: u32,
+    #[arg(long, default_value_t = 10)]
+    level_step_bps: u32,
+    /// Quote currency value of each level
+    #[arg(long, default_value = "1000")]
+    level_notional: Decimal,
+    #[arg(long, default_value_t = 2)]
+    price_decimals: u32,
+    #[arg(long, default_value_t = 6)]
+    quantity_decimals: u32,
+    /// Seconds between requotes; a trade on a quoted pair triggers one early
+    #[arg(long, default_value_t = 5)]
+    interval_seconds: u64,
+}
+
+struct QuotedPair {
+    id: Uuid,
+    symbol: String,
+    reference_price: Decimal,
+    resting: Vec<Uuid>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt().with_env_filter("market_maker=info").init();
+
+    let cli = Cli::parse();
+    let client = ApiClient::new(cli.url.clone(), cli.token.clone());
+    let ladder = QuoteLadder {
+        levels: cli.levels,
+        spread_bps: cli.spread_bps,
+        level_step_bps: cli.level_step_bps,
+        level_notional: cli.level_notional,
+        price_precision: cli.price_decimals,
+        quantity_precision: cli.quantity_decimals,
+        min_quantity: Decimal::ZERO,
+    };
+    let mut feed = match &cli.feed_url {
+        Some(url) => PriceFeed::http(url.clone(), cli.feed_pointer.clone()),
+        None => PriceFeed::walk(cli.seed, cli.volatility_bps),
+    };
+
+    let market_data = client.market_data().await?;
+    let mut pairs = Vec::new();
+    for symbol in &cli.pairs {
+        let Some(market) = market_data.iter().find(|market| &market.symbol == symbol) else {
+            anyhow::bail!("Unknown trading pair {}", symbol);
+        };
+        let reference_price = cli
+            .prices
+            .iter()
+            .find(|(priced, _)| priced == symbol)
+            .map(|(_, price)| *price)
+            .unwrap_or(market.last_price);
+        if reference_price <= Decimal::ZERO && cli.feed_url.is_none() {
+            anyhow::bail!("{} has never traded; pass --price {}=<price>", symbol, symbol);
+        }
+        pairs.push(QuotedPair {
+            id: market.trading_pair_id,
+            symbol: symbol.clone(),
+            reference_price,
+            resting: Vec::new(),
+        });
+    }
+
+    // Quotes left behind by an earlier run would otherwise stay in the book
+    let pair_ids: Vec<Uuid> = pairs.iter().map(|pair| pair.id).collect();
+    for order in client.open_orders().await? {
+        if pair_ids.contains(&order.trading_pair_id) {
+            client.cancel(order.id).await?;
+        }
+    }
+
+    let (trades_tx, mut trades_rx) = mpsc::channel(256);
+    stream::spawn(&cli.url, cli.token.clone(), pair_ids, trades_tx);
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(cli.interval_seconds));
+    loop {
+        let due: HashSet<Uuid> = tokio::select! {
+            _ = ticker.tick() => pairs.iter().map(|pair| pair.id).collect(),
+            Some(pair_id) = trades_rx.recv() => {
+                let mut due = HashSet::from([pair_id]);
+                while let Ok(pair_id) = trades_rx.try_recv() {
+                    due.insert(pair_id);
+                }
+                due
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        for pair in pairs.iter_mut().filter(|pair| due.contains(&pair.id)) {
+            if let Err(e) = requote(&client, &mut feed, &ladder, pair).await {
+                tracing::warn!("Requoting {} failed: {}", pair.symbol, e);
+            }
+        }
+    }
+
+    tracing::info!("Shutting down, cancelling quotes");
+    for pair in &mut pairs {
+        for order_id in pair.resting.drain(..) {
+            client.cancel(order_id).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn requote(
+    client: &ApiClient,
+    feed: &mut PriceFeed,
+    ladder: &QuoteLadder,
+    pair: &mut QuotedPair,
+) -> anyhow::Result<()> {
+    pair.reference_price = feed.next_price(&pair.symbol, pair.reference_price).await?;
+
+    for order_id in pair.resting.drain(..) {
+        client.cancel(order_id).await?;
+    }
+
+    for quote in ladder.quotes(pair.reference_price) {
+        let request = CreateOrderRequest {
+            trading_pair_id: pair.id,
+            order_type: OrderType::Limit,
+            side: quote.side,
+            quantity: quote.quantity.to_f64().unwrap_or_default(),
+            price: Some(quote.price),
+            time_in_force: Some(TimeInForce::GTC),
+            stop_price: None,
+            allow_duplicate: true,
+            activate_at: None,
+            lock_funds: false,
+        };
+        let order = client.place(&request).await?;
+        pair.resting.push(order.id);
+    }
+
+    tracing::info!(
+        "{} quoted around {} ({} orders)",
+        pair.symbol,
+        pair.reference_price.round_dp(ladder.price_precision),
+        pair.resting.len()
+    );
+    Ok(())
+}
+
+fn parse_price(value: &str) -> Result<(String, Decimal), String> {
+    let (symbol, price) = value.split_once('=').ok_or("expected SYMBOL=PRICE")?;
+    let price = price.parse::<Decimal>().map_err(|e| e.to_string())?;
+    Ok((symbol.to_string(), price))
+}
@@ -0,0 +1,61 @@
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, http::header, Message},
+};
+use uuid::Uuid;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Watches the `trades:<pair id>` feeds and sends the pair id whenever a
+/// trade prints, reconnecting for as long as the receiver is alive.
+pub fn spawn(api_url: &str, token: String, pair_ids: Vec<Uuid>, trades: mpsc::Sender<Uuid>) {
+    let ws_url = format!("{}/ws", api_url.replacen("http", "ws", 1));
+
+    tokio::spawn(async move {
+        while !trades.is_closed() {
+            match watch(&ws_url, &token, &pair_ids, &trades).await {
+                Ok(()) => tracing::warn!("Trade feed closed, reconnecting"),
+                Err(e) => tracing::warn!("Trade feed failed: {}, reconnecting", e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+async fn watch(ws_url: &str, token: &str, pair_ids: &[Uuid], trades: &mpsc::Sender<Uuid>) -> anyhow::Result<()> {
+    let mut request = ws_url.into_client_request()?;
+    request
+        .headers_mut()
+        .insert(header::AUTHORIZATION, format!("Bearer {}", token).parse()?);
+    let (mut socket, _) = connect_async(request).await?;
+
+    let channels: Vec<String> = pair_ids.iter().map(|id| format!("trades:{}", id)).collect();
+    socket
+        .send(Message::Text(json!({ "op": "subscribe", "channels": channels }).to_string()))
+        .await?;
+
+    while let Some(message) = socket.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let message: Value = serde_json::from_str(&text)?;
+        if message["type"] != "update" {
+            continue;
+        }
+        let pair_id = message["channel"]
+            .as_str()
+            .and_then(|channel| channel.strip_prefix("trades:"))
+            .and_then(|id| id.parse::<Uuid>().ok());
+        if let Some(pair_id) = pair_id {
+            trades.send(pair_id).await?;
+        }
+    }
+
+    Ok(())
+}